@@ -0,0 +1,250 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use passivbot_rust::closes::calc_closes_long;
+use passivbot_rust::entries::{
+    calc_blended_entries_long, calc_blended_entries_long_with_precomputed, calc_entries_long,
+    calc_entries_long_with_precomputed, calc_grid_entry_long, calc_next_entry_long,
+    calc_next_entry_long_with_precomputed, PrecomputedState,
+};
+use passivbot_rust::fixtures;
+use passivbot_rust::orders::{calc_ideal_orders, calc_ideal_orders_batch};
+use passivbot_rust::runner::{backtest, calc_ema_alphas, EMAs};
+use passivbot_rust::types::{BotParams, Position, TrailingPriceBundle};
+
+fn bench_entries(c: &mut Criterion) {
+    let exchange_params = fixtures::exchange_params();
+    let bot_params = fixtures::bot_params();
+    let state_params = fixtures::state_params(60000.0);
+    let position = fixtures::position_long(60000.0);
+    let trailing_price_bundle = TrailingPriceBundle::default();
+    c.bench_function("calc_next_entry_long", |b| {
+        b.iter(|| {
+            calc_next_entry_long(
+                black_box(&exchange_params),
+                black_box(&state_params),
+                black_box(&bot_params),
+                black_box(&position),
+                black_box(&trailing_price_bundle),
+            )
+        })
+    });
+    c.bench_function("calc_grid_entry_long", |b| {
+        b.iter(|| {
+            calc_grid_entry_long(
+                black_box(&exchange_params),
+                black_box(&state_params),
+                black_box(&bot_params),
+                black_box(&position),
+            )
+        })
+    });
+}
+
+/// The grid first entry below entry_trailing_grid_ratio runs against a scaled exposure limit.
+/// "override" passes it down as calc_next_entry_long does; "clone" is the former way, a
+/// BotParams copy with the limit replaced, whose Vec fields allocate on every call.
+fn bench_exposure_limit_override(c: &mut Criterion) {
+    let exchange_params = fixtures::exchange_params();
+    let bot_params = BotParams {
+        entry_grid_qty_schedule: vec![1.0, 1.5, 2.0, 2.5, 3.0],
+        entry_blackout_windows: vec![(1_700_000_000_000, 1_700_003_600_000)],
+        ..fixtures::bot_params()
+    };
+    // grid first: ratio -0.28169, position at half of wallet_exposure_limit
+    let state_params = fixtures::state_params(60000.0);
+    let position = fixtures::position_long(60000.0);
+    let trailing_price_bundle = TrailingPriceBundle::default();
+    let clone_with_limit = |bot_params: &BotParams| {
+        let mut bot_params_modified = bot_params.clone();
+        bot_params_modified.wallet_exposure_limit =
+            bot_params.wallet_exposure_limit * (1.0 + bot_params.entry_trailing_grid_ratio) * 1.01;
+        calc_grid_entry_long(
+            &exchange_params,
+            &state_params,
+            &bot_params_modified,
+            &position,
+        )
+    };
+    assert_eq!(
+        calc_next_entry_long(
+            &exchange_params,
+            &state_params,
+            &bot_params,
+            &position,
+            &trailing_price_bundle,
+        ),
+        clone_with_limit(&bot_params)
+    );
+    let mut group = c.benchmark_group("hybrid_grid_entry_exposure_limit");
+    group.bench_function("override", |b| {
+        b.iter(|| {
+            calc_next_entry_long(
+                black_box(&exchange_params),
+                black_box(&state_params),
+                black_box(&bot_params),
+                black_box(&position),
+                black_box(&trailing_price_bundle),
+            )
+        })
+    });
+    group.bench_function("clone", |b| {
+        b.iter(|| clone_with_limit(black_box(&bot_params)))
+    });
+    group.finish();
+}
+
+/// The entry calculators of one tick over the long entry matrix: "legacy" lets each derive its
+/// PrecomputedState, "precomputed" derives it once per symbol as the backtester does.
+fn bench_precomputed_tick(c: &mut Criterion) {
+    let exchange_params = fixtures::exchange_params();
+    let bot_params = fixtures::bot_params();
+    let cases = fixtures::long_entry_matrix();
+    let trailing_price_bundle = TrailingPriceBundle::default();
+    let mut group = c.benchmark_group("entry_calculators_per_tick");
+    group.bench_function("legacy", |b| {
+        b.iter(|| {
+            for (state_params, position) in &cases {
+                black_box(calc_next_entry_long(
+                    &exchange_params,
+                    state_params,
+                    &bot_params,
+                    position,
+                    &trailing_price_bundle,
+                ));
+                black_box(calc_entries_long(
+                    &exchange_params,
+                    state_params,
+                    &bot_params,
+                    position,
+                    &trailing_price_bundle,
+                ));
+                black_box(calc_blended_entries_long(
+                    &exchange_params,
+                    state_params,
+                    &bot_params,
+                    position,
+                    &trailing_price_bundle,
+                ));
+            }
+        })
+    });
+    group.bench_function("precomputed", |b| {
+        b.iter(|| {
+            for (state_params, position) in &cases {
+                let precomputed =
+                    PrecomputedState::long(&exchange_params, state_params, &bot_params);
+                black_box(calc_next_entry_long_with_precomputed(
+                    &exchange_params,
+                    state_params,
+                    &bot_params,
+                    position,
+                    &trailing_price_bundle,
+                    &precomputed,
+                ));
+                black_box(calc_entries_long_with_precomputed(
+                    &exchange_params,
+                    state_params,
+                    &bot_params,
+                    position,
+                    &trailing_price_bundle,
+                    &precomputed,
+                ));
+                black_box(calc_blended_entries_long_with_precomputed(
+                    &exchange_params,
+                    state_params,
+                    &bot_params,
+                    position,
+                    &trailing_price_bundle,
+                    &precomputed,
+                ));
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_closes(c: &mut Criterion) {
+    let exchange_params = fixtures::exchange_params();
+    let bot_params = fixtures::bot_params();
+    let state_params = fixtures::state_params(60000.0);
+    let position = Position {
+        price: 59000.0,
+        ..fixtures::position_long(60000.0)
+    };
+    let trailing_price_bundle = TrailingPriceBundle::default();
+    c.bench_function("calc_closes_long", |b| {
+        b.iter(|| {
+            calc_closes_long(
+                black_box(&exchange_params),
+                black_box(&state_params),
+                black_box(&bot_params),
+                black_box(&position),
+                black_box(&trailing_price_bundle),
+            )
+        })
+    });
+}
+
+fn bench_ideal_orders_batch(c: &mut Criterion) {
+    let inputs = fixtures::symbol_inputs(500, 0);
+    c.bench_function("calc_ideal_orders_batch_500_symbols", |b| {
+        b.iter(|| calc_ideal_orders_batch(black_box(&inputs)))
+    });
+    // one batch call against the per-symbol loop it replaces
+    let inputs = fixtures::symbol_inputs(100, 0);
+    let mut group = c.benchmark_group("ideal_orders_100_symbols");
+    group.bench_function("batch", |b| {
+        b.iter(|| calc_ideal_orders_batch(black_box(&inputs)))
+    });
+    group.bench_function("single_calls", |b| {
+        b.iter(|| {
+            black_box(&inputs)
+                .iter()
+                .map(calc_ideal_orders)
+                .collect::<Vec<_>>()
+        })
+    });
+    group.finish();
+}
+
+fn bench_emas(c: &mut Criterion) {
+    let ema_alphas = calc_ema_alphas(&fixtures::bot_params_pair());
+    let mut emas = EMAs {
+        long: [60000.0; 3],
+        short: [60000.0; 3],
+    };
+    c.bench_function("EMAs::update", |b| {
+        b.iter(|| emas.update(black_box(60010.0), black_box(&ema_alphas)))
+    });
+}
+
+fn bench_backtest(c: &mut Criterion) {
+    let candles = fixtures::candles(100_000, 0);
+    let bot_params_pair = fixtures::bot_params_pair();
+    let exchange_params = fixtures::exchange_params();
+    let backtest_params = fixtures::backtest_params();
+    let mut group = c.benchmark_group("backtest");
+    group.sample_size(10);
+    group.bench_function("single_symbol_100k_candles", |b| {
+        b.iter(|| {
+            backtest(
+                black_box(&candles),
+                &bot_params_pair,
+                &exchange_params,
+                &backtest_params,
+            )
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_entries,
+    bench_exposure_limit_override,
+    bench_precomputed_tick,
+    bench_closes,
+    bench_ideal_orders_batch,
+    bench_emas,
+    bench_backtest
+);
+criterion_main!(benches);
@@ -0,0 +1,192 @@
+#ifndef PASSIVBOT_H
+#define PASSIVBOT_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define PB_OK 0
+
+#define PB_ERR_NULL_POINTER -1
+
+#define PB_ERR_INVALID_PARAMS -2
+
+#define PB_ERR_PANIC -3
+
+#define PB_ENTRY_TRAILING_MODE_THRESHOLD_DRAWDOWN 0
+
+#define PB_ENTRY_TRAILING_MODE_TRAILING_STOP 1
+
+#define PB_ENTRY_TRAILING_GRID_MODE_SWITCH 0
+
+#define PB_ENTRY_TRAILING_GRID_MODE_BLENDED 1
+
+#define PB_EMA_WARMUP_SUPPRESS_ENTRIES 0
+
+#define PB_EMA_WARMUP_FALLBACK_TO_ORDER_BOOK 1
+
+#define PB_ENTRY_SIZING_BALANCE 0
+
+#define PB_ENTRY_SIZING_EQUITY 1
+
+#define PB_MAX_QTY_SCHEDULE_LEN 16
+
+#define PB_MAX_BLACKOUT_WINDOWS 16
+
+// Binance's client order id limit, the shortest among supported exchanges (Bybit allows 45).
+#define CUSTOM_ID_MAX_LEN 36
+
+typedef struct ExchangeParamsC {
+  double qty_step;
+  double price_step;
+  double min_qty;
+  double min_cost;
+  double c_mult;
+  double fee_rate;
+  double max_position_size;
+  double price_band_pct;
+} ExchangeParamsC;
+
+typedef struct StateParamsC {
+  double balance;
+  double bid;
+  double ask;
+  double ema_band_upper;
+  double ema_band_lower;
+  uint64_t timestamp;
+  double last_entry_fill_price;
+  uint64_t last_entry_fill_timestamp;
+  uint64_t trailing_add_count;
+  double equity;
+  double volatility;
+} StateParamsC;
+
+typedef struct BotParamsC {
+  uint8_t auto_adjust_markup;
+  double balance_reserve_pct;
+  double bracket_stop_pct;
+  double close_grid_gap_pct;
+  double close_grid_markup_range;
+  double close_grid_min_markup;
+  double close_min_net_markup;
+  double close_grid_qty_pct;
+  double close_trailing_retracement_pct;
+  double close_trailing_grid_ratio;
+  double close_trailing_qty_pct;
+  double close_trailing_threshold_pct;
+  uint8_t coalesce_grid_orders;
+  double entry_grid_double_down_factor;
+  double entry_grid_spacing_weight;
+  double entry_grid_spacing_pct;
+  uint8_t entry_grid_ema_floor;
+  double entry_initial_ema_dist;
+  double entry_initial_qty_pct;
+  double entry_initial_timeout_sec;
+  double entry_reentry_ema_dist;
+  double entry_trailing_drawdown_pct;
+  int32_t entry_trailing_mode;
+  double entry_trailing_retracement_pct;
+  double entry_trailing_grid_ratio;
+  double entry_trailing_threshold_pct;
+  uint64_t filter_rolling_window;
+  double filter_relative_volume_clip_pct;
+  double ema_span_0;
+  double ema_span_1;
+  uint64_t n_positions;
+  double total_wallet_exposure_limit;
+  double wallet_exposure_limit;
+  double unstuck_close_pct;
+  double unstuck_ema_dist;
+  double unstuck_loss_allowance_pct;
+  double unstuck_threshold;
+  uint64_t entry_trailing_max_adds;
+  int32_t entry_trailing_grid_mode;
+  int32_t ema_warmup_behavior;
+  double close_trailing_min_profit_pct;
+  double entry_grid_qty_schedule[PB_MAX_QTY_SCHEDULE_LEN];
+  uint64_t entry_grid_qty_schedule_len;
+  uint64_t entry_blackout_windows[PB_MAX_BLACKOUT_WINDOWS][2];
+  uint64_t entry_blackout_windows_len;
+  double close_grid_max_markup;
+  int32_t entry_sizing_basis;
+  double close_trailing_drawdown_floor_pct;
+  double entry_grid_spacing_vol_weight;
+  double close_grid_min_entry_distance_ratio;
+} BotParamsC;
+
+typedef struct PositionC {
+  double size;
+  double price;
+} PositionC;
+
+typedef struct OrderC {
+  double qty;
+  double price;
+  uint8_t order_type;
+} OrderC;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Next long entry. min_since_open and max_since_min are the trailing prices since the
+// position last changed.
+//
+// # Safety
+// Every pointer must be null or valid for the duration of the call.
+int32_t pb_calc_next_entry_long(const struct ExchangeParamsC *exchange_params,
+                                const struct StateParamsC *state_params,
+                                const struct BotParamsC *bot_params,
+                                const struct PositionC *position,
+                                double min_since_open,
+                                double max_since_min,
+                                struct OrderC *out);
+
+// Next short entry. max_since_open and min_since_max are the trailing prices since the
+// position last changed.
+//
+// # Safety
+// Every pointer must be null or valid for the duration of the call.
+int32_t pb_calc_next_entry_short(const struct ExchangeParamsC *exchange_params,
+                                 const struct StateParamsC *state_params,
+                                 const struct BotParamsC *bot_params,
+                                 const struct PositionC *position,
+                                 double max_since_open,
+                                 double min_since_max,
+                                 struct OrderC *out);
+
+// Next long close. max_since_open and min_since_max are the trailing prices since the
+// position last changed.
+//
+// # Safety
+// Every pointer must be null or valid for the duration of the call.
+int32_t pb_calc_next_close_long(const struct ExchangeParamsC *exchange_params,
+                                const struct StateParamsC *state_params,
+                                const struct BotParamsC *bot_params,
+                                const struct PositionC *position,
+                                double max_since_open,
+                                double min_since_max,
+                                struct OrderC *out);
+
+// Next short close. min_since_open and max_since_min are the trailing prices since the
+// position last changed.
+//
+// # Safety
+// Every pointer must be null or valid for the duration of the call.
+int32_t pb_calc_next_close_short(const struct ExchangeParamsC *exchange_params,
+                                 const struct StateParamsC *state_params,
+                                 const struct BotParamsC *bot_params,
+                                 const struct PositionC *position,
+                                 double min_since_open,
+                                 double max_since_min,
+                                 struct OrderC *out);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* PASSIVBOT_H */
@@ -0,0 +1,200 @@
+# Type stubs for the passivbot_rust extension module, for IDEs and type checkers.
+# Covers the classes and the bindings returning them; the remaining functions are typed Any.
+# OrderType members follow the OrderType enum in src/types.rs.
+
+from typing import Any, Callable, ClassVar, Optional, Union
+
+import numpy as np
+
+class OrderType:
+    EntryInitialNormalLong: ClassVar[OrderType]
+    EntryInitialPartialLong: ClassVar[OrderType]
+    EntryTrailingNormalLong: ClassVar[OrderType]
+    EntryTrailingCroppedLong: ClassVar[OrderType]
+    EntryGridNormalLong: ClassVar[OrderType]
+    EntryGridCroppedLong: ClassVar[OrderType]
+    EntryGridInflatedLong: ClassVar[OrderType]
+    CloseGridLong: ClassVar[OrderType]
+    CloseTrailingLong: ClassVar[OrderType]
+    CloseUnstuckLong: ClassVar[OrderType]
+    CloseStopLong: ClassVar[OrderType]
+    EntryInitialNormalShort: ClassVar[OrderType]
+    EntryInitialPartialShort: ClassVar[OrderType]
+    EntryTrailingNormalShort: ClassVar[OrderType]
+    EntryTrailingCroppedShort: ClassVar[OrderType]
+    EntryGridNormalShort: ClassVar[OrderType]
+    EntryGridCroppedShort: ClassVar[OrderType]
+    EntryGridInflatedShort: ClassVar[OrderType]
+    CloseGridShort: ClassVar[OrderType]
+    CloseTrailingShort: ClassVar[OrderType]
+    CloseUnstuckShort: ClassVar[OrderType]
+    CloseStopShort: ClassVar[OrderType]
+    Empty: ClassVar[OrderType]
+    @property
+    def name(self) -> str: ...
+    @property
+    def value(self) -> int: ...
+    @property
+    def is_entry(self) -> bool: ...
+    @property
+    def is_close(self) -> bool: ...
+    @property
+    def is_long(self) -> bool: ...
+    @property
+    def is_short(self) -> bool: ...
+    @property
+    def side(self) -> Optional[Side]: ...
+    @staticmethod
+    def from_name(name: str) -> OrderType: ...
+    @staticmethod
+    def from_value(value: int) -> OrderType: ...
+    @staticmethod
+    def members() -> list[OrderType]: ...
+    def __int__(self) -> int: ...
+    def __hash__(self) -> int: ...
+
+class Side:
+    Long: ClassVar[Side]
+    Short: ClassVar[Side]
+    @property
+    def name(self) -> str: ...
+    @property
+    def value(self) -> int: ...
+    @staticmethod
+    def from_name(name: str) -> Side: ...
+    @staticmethod
+    def from_value(value: int) -> Side: ...
+    @staticmethod
+    def members() -> list[Side]: ...
+    def __int__(self) -> int: ...
+    def __hash__(self) -> int: ...
+
+class EmptyReason:
+    NotEmpty: ClassVar[EmptyReason]
+    ZeroExposureLimit: ClassVar[EmptyReason]
+    ZeroBalance: ClassVar[EmptyReason]
+    PriceBelowStep: ClassVar[EmptyReason]
+    ExposureLimitReached: ClassVar[EmptyReason]
+    TrailingNotTriggered: ClassVar[EmptyReason]
+    TrailingMaxAddsReached: ClassVar[EmptyReason]
+    MaxPositionSizeReached: ClassVar[EmptyReason]
+    QtyRoundedToZero: ClassVar[EmptyReason]
+    SameTickFill: ClassVar[EmptyReason]
+    EntryBlackout: ClassVar[EmptyReason]
+    WrongSidePosition: ClassVar[EmptyReason]
+    @property
+    def name(self) -> str: ...
+    @property
+    def is_empty(self) -> bool: ...
+    @staticmethod
+    def members() -> list[EmptyReason]: ...
+    def __int__(self) -> int: ...
+    def __hash__(self) -> int: ...
+
+class Order:
+    def __init__(self, qty: float, price: float, order_type: OrderType) -> None: ...
+    @property
+    def qty(self) -> float: ...
+    @property
+    def price(self) -> float: ...
+    @property
+    def order_type(self) -> OrderType: ...
+    def custom_id(self, symbol: str, exchange_params: dict[str, Any]) -> str: ...
+    @staticmethod
+    def from_custom_id(
+        id: str, symbol: str, exchange_params: dict[str, Any]
+    ) -> Optional[Order]: ...
+    def to_dict(self) -> dict[str, Any]: ...
+    def to_order_request(
+        self, symbol: str, hedge_mode: bool = True
+    ) -> Optional[dict[str, Any]]: ...
+    def to_binance_futures_params(
+        self, symbol: str, exchange_params: dict[str, Any], hedge_mode: bool = True
+    ) -> Optional[dict[str, Any]]: ...
+    def to_bybit_v5_params(
+        self, symbol: str, exchange_params: dict[str, Any], hedge_mode: bool = True
+    ) -> Optional[dict[str, Any]]: ...
+
+class Position:
+    def __init__(self, size: float = 0.0, price: float = 0.0) -> None: ...
+    @property
+    def size(self) -> float: ...
+    @property
+    def price(self) -> float: ...
+    def project_fill(self, order: Order, qty_step: float) -> Position: ...
+    def to_dict(self) -> dict[str, float]: ...
+
+class Metrics:
+    @property
+    def adg(self) -> float: ...
+    @property
+    def mdg(self) -> float: ...
+    @property
+    def sharpe_ratio(self) -> float: ...
+    @property
+    def max_drawdown(self) -> float: ...
+    @property
+    def time_stuck_fraction(self) -> float: ...
+    @property
+    def fills_per_day(self) -> float: ...
+    @property
+    def pruned(self) -> bool: ...
+    def to_dict(self) -> dict[str, Union[float, bool]]: ...
+
+class BacktestResult:
+    @property
+    def fills(self) -> np.recarray: ...
+    @property
+    def equities(self) -> np.ndarray: ...
+    @property
+    def final_balance(self) -> float: ...
+    @property
+    def n_trades(self) -> int: ...
+    @property
+    def metrics(self) -> Metrics: ...
+    def to_dict(self) -> dict[str, Any]: ...
+
+def check_bot_params_pair_py(config: dict[str, Any]) -> list[str]: ...
+def calc_next_entry_long_dict_py(
+    exchange_params: dict[str, Any], bot_params: dict[str, Any], state: dict[str, Any]
+) -> Order: ...
+def calc_next_entry_long_explained_dict_py(
+    exchange_params: dict[str, Any], bot_params: dict[str, Any], state: dict[str, Any]
+) -> tuple[Order, EmptyReason]: ...
+def calc_next_entry_short_dict_py(
+    exchange_params: dict[str, Any], bot_params: dict[str, Any], state: dict[str, Any]
+) -> Order: ...
+def calc_blended_entries_long_dict_py(
+    exchange_params: dict[str, Any], bot_params: dict[str, Any], state: dict[str, Any]
+) -> list[Order]: ...
+def calc_blended_entries_short_dict_py(
+    exchange_params: dict[str, Any], bot_params: dict[str, Any], state: dict[str, Any]
+) -> list[Order]: ...
+def calc_closes_long_dict_py(
+    exchange_params: dict[str, Any], bot_params: dict[str, Any], state: dict[str, Any]
+) -> list[Order]: ...
+def calc_closes_short_dict_py(
+    exchange_params: dict[str, Any], bot_params: dict[str, Any], state: dict[str, Any]
+) -> list[Order]: ...
+def calc_next_grid_trigger_price_long_dict_py(
+    exchange_params: dict[str, Any], bot_params: dict[str, Any], state: dict[str, Any]
+) -> Optional[float]: ...
+def calc_next_grid_trigger_price_short_dict_py(
+    exchange_params: dict[str, Any], bot_params: dict[str, Any], state: dict[str, Any]
+) -> Optional[float]: ...
+def run_backtest_py(
+    candles: Union[np.ndarray, list[np.ndarray]],
+    bot_params_pair: dict[str, Any],
+    exchange_params: Union[dict[str, Any], list[dict[str, Any]]],
+    backtest_params: dict[str, Any],
+) -> Union[BacktestResult, list[BacktestResult]]: ...
+def run_optimize_py(
+    candles: np.ndarray,
+    exchange_params: dict[str, Any],
+    backtest_params: dict[str, Any],
+    bounds: dict[str, Any],
+    ga_config: dict[str, Any],
+    progress_callback: Optional[Callable[[int, int, float], Any]] = None,
+    progress_interval: int = 100,
+) -> dict[str, Any]: ...
+def __getattr__(name: str) -> Any: ...
@@ -0,0 +1,393 @@
+pub use crate::backtest::calc_daily_returns;
+use crate::backtest::calc_drawdowns;
+use crate::runner::BacktestResult;
+use crate::types::{ExchangeParams, Position};
+use crate::utils::{calc_pnl_long, calc_pnl_short, calc_wallet_exposure, qty_to_cost};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Metrics {
+    pub adg: f64,
+    pub mdg: f64,
+    pub sharpe_ratio: f64,
+    pub max_drawdown: f64,
+    pub time_stuck_fraction: f64, // longest stretch without fills over backtest length
+    pub fills_per_day: f64,
+    #[serde(default)]
+    pub pruned: bool, // run aborted early; other fields are meaningless
+}
+
+impl Metrics {
+    /// Placeholder for a run stopped by a pruner. Scorer gives it PRUNED_SCORE.
+    pub fn pruned() -> Self {
+        Metrics {
+            pruned: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn from_result(result: &BacktestResult) -> Self {
+        let n_candles = result.equities.len();
+        if n_candles == 0 {
+            return Metrics::default();
+        }
+        let daily_returns = calc_daily_returns(&result.equities);
+        let n_days = daily_returns.len().max(1) as f64;
+        let adg = daily_returns.iter().sum::<f64>() / n_days;
+        let mut sorted_returns = daily_returns.clone();
+        let mdg = if sorted_returns.is_empty() {
+            0.0
+        } else {
+            sorted_returns.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+            percentile_sorted(&sorted_returns, 0.5)
+        };
+        let std_dev = (daily_returns
+            .iter()
+            .map(|&x| (x - adg).powi(2))
+            .sum::<f64>()
+            / n_days)
+            .sqrt();
+        let mut last_fill_index = 0;
+        let mut longest_stuck = 0;
+        for fill in &result.fills {
+            longest_stuck = longest_stuck.max(fill.index - last_fill_index);
+            last_fill_index = fill.index;
+        }
+        longest_stuck = longest_stuck.max(n_candles - 1 - last_fill_index.min(n_candles - 1));
+        Metrics {
+            adg,
+            mdg,
+            sharpe_ratio: if std_dev > 0.0 { adg / std_dev } else { 0.0 },
+            max_drawdown: result.max_drawdown,
+            time_stuck_fraction: longest_stuck as f64 / n_candles as f64,
+            fills_per_day: result.fills.len() as f64 / (n_candles as f64 / 1440.0),
+            pruned: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Percentiles {
+    pub p05: f64,
+    pub p50: f64,
+    pub p95: f64,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct MonteCarloResult {
+    pub n_samples: usize,
+    pub final_equity: Percentiles, // relative to starting equity of 1.0
+    pub max_drawdown: Percentiles,
+    pub adg: Percentiles,
+}
+
+/// Block bootstrap of a daily return series.
+/// Blocks of consecutive days are drawn (wrapping around the end) to preserve autocorrelation.
+/// Same seed gives same result.
+pub fn monte_carlo_analysis(
+    daily_returns: &[f64],
+    n_samples: usize,
+    block_size: usize,
+    seed: u64,
+) -> MonteCarloResult {
+    let n_days = daily_returns.len();
+    if n_days == 0 || n_samples == 0 || block_size == 0 {
+        return MonteCarloResult::default();
+    }
+    let block_size = block_size.min(n_days);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut final_equities = Vec::with_capacity(n_samples);
+    let mut max_drawdowns = Vec::with_capacity(n_samples);
+    let mut adgs = Vec::with_capacity(n_samples);
+    let mut resampled = Vec::with_capacity(n_days);
+    let mut equities = Vec::with_capacity(n_days + 1);
+    for _ in 0..n_samples {
+        resampled.clear();
+        while resampled.len() < n_days {
+            let start = rng.gen_range(0..n_days);
+            for i in 0..block_size.min(n_days - resampled.len()) {
+                resampled.push(daily_returns[(start + i) % n_days]);
+            }
+        }
+        equities.clear();
+        equities.push(1.0);
+        for &daily_return in &resampled {
+            equities.push(equities.last().unwrap() * (1.0 + daily_return));
+        }
+        final_equities.push(*equities.last().unwrap());
+        max_drawdowns.push(
+            calc_drawdowns(&equities)
+                .iter()
+                .fold(0.0, |a, &b| f64::max(a, b.abs())),
+        );
+        adgs.push(resampled.iter().sum::<f64>() / n_days as f64);
+    }
+    MonteCarloResult {
+        n_samples,
+        final_equity: calc_percentiles(&mut final_equities),
+        max_drawdown: calc_percentiles(&mut max_drawdowns),
+        adg: calc_percentiles(&mut adgs),
+    }
+}
+
+fn calc_percentiles(values: &mut [f64]) -> Percentiles {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    Percentiles {
+        p05: percentile_sorted(values, 0.05),
+        p50: percentile_sorted(values, 0.5),
+        p95: percentile_sorted(values, 0.95),
+    }
+}
+
+fn percentile_sorted(sorted: &[f64], q: f64) -> f64 {
+    // linear interpolation between closest ranks
+    let rank = q * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SymbolExposure {
+    pub symbol: String,
+    pub size: f64, // negative for short
+    pub wallet_exposure: f64,
+    pub notional: f64, // at mark price
+    pub unrealized_pnl: f64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExposureReport {
+    pub symbols: Vec<SymbolExposure>,
+    pub total_wallet_exposure: f64,
+    pub unrealized_pnl: f64,
+    pub notional_long: f64,
+    pub notional_short: f64,
+    pub largest: Option<SymbolExposure>, // None if no position is open
+}
+
+/// Risk summary over (symbol, position, exchange params, mark price) entries; in hedge mode a
+/// symbol may appear once per side. Wallet exposure is at position price, as everywhere else,
+/// while notional and unrealized pnl are at mark price. Flat positions are skipped.
+pub fn calc_exposure_report(
+    balance: f64,
+    positions: &[(String, Position, ExchangeParams, f64)],
+) -> ExposureReport {
+    let mut report = ExposureReport::default();
+    for (symbol, position, exchange_params, mark_price) in positions {
+        if position.size == 0.0 {
+            continue;
+        }
+        let c_mult = exchange_params.c_mult;
+        let exposure = SymbolExposure {
+            symbol: symbol.clone(),
+            size: position.size,
+            wallet_exposure: calc_wallet_exposure(c_mult, balance, position.size, position.price),
+            notional: qty_to_cost(position.size, *mark_price, c_mult),
+            unrealized_pnl: if position.size > 0.0 {
+                calc_pnl_long(position.price, *mark_price, position.size, c_mult)
+            } else {
+                calc_pnl_short(position.price, *mark_price, position.size, c_mult)
+            },
+        };
+        report.total_wallet_exposure += exposure.wallet_exposure;
+        report.unrealized_pnl += exposure.unrealized_pnl;
+        if position.size > 0.0 {
+            report.notional_long += exposure.notional;
+        } else {
+            report.notional_short += exposure.notional;
+        }
+        if report
+            .largest
+            .as_ref()
+            .is_none_or(|largest| exposure.wallet_exposure > largest.wallet_exposure)
+        {
+            report.largest = Some(exposure.clone());
+        }
+        report.symbols.push(exposure);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monte_carlo_same_seed_same_result() {
+        let daily_returns: Vec<f64> = (0..60)
+            .map(|i| ((i * 7 % 11) as f64 - 5.0) * 0.002)
+            .collect();
+        let a = monte_carlo_analysis(&daily_returns, 200, 5, 42);
+        let b = monte_carlo_analysis(&daily_returns, 200, 5, 42);
+        let c = monte_carlo_analysis(&daily_returns, 200, 5, 43);
+        assert_eq!(a.n_samples, 200);
+        assert_eq!(a.final_equity, b.final_equity);
+        assert_eq!(a.max_drawdown, b.max_drawdown);
+        assert_eq!(a.adg, b.adg);
+        assert_ne!(a.final_equity, c.final_equity);
+        assert!(
+            a.final_equity.p05 <= a.final_equity.p50 && a.final_equity.p50 <= a.final_equity.p95
+        );
+    }
+
+    #[test]
+    fn monte_carlo_full_length_blocks_are_rotations() {
+        // one block covering every day wraps into a rotation of the series: same sum and product
+        let daily_returns = [0.01, -0.02, 0.03, 0.0, -0.01];
+        let result = monte_carlo_analysis(&daily_returns, 50, 100, 7);
+        let final_equity: f64 = daily_returns.iter().map(|r| 1.0 + r).product();
+        let adg = daily_returns.iter().sum::<f64>() / 5.0;
+        for p in [
+            result.final_equity.p05,
+            result.final_equity.p50,
+            result.final_equity.p95,
+        ] {
+            assert!((p - final_equity).abs() < 1e-12);
+        }
+        for p in [result.adg.p05, result.adg.p95] {
+            assert!((p - adg).abs() < 1e-12);
+        }
+        assert!(result.max_drawdown.p05 > 0.0);
+    }
+
+    #[test]
+    fn monte_carlo_degenerate_inputs() {
+        let flat = monte_carlo_analysis(&[0.01; 10], 20, 3, 1);
+        assert!((flat.final_equity.p50 - 1.01f64.powi(10)).abs() < 1e-12);
+        assert_eq!(flat.max_drawdown.p95, 0.0);
+        assert_eq!(monte_carlo_analysis(&[], 20, 3, 1).n_samples, 0);
+        assert_eq!(monte_carlo_analysis(&[0.01], 0, 3, 1).n_samples, 0);
+        assert_eq!(monte_carlo_analysis(&[0.01], 20, 0, 1).n_samples, 0);
+    }
+
+    #[test]
+    fn metrics_from_result() {
+        use crate::types::{Fill, OrderType};
+        let fill = |index| Fill {
+            index,
+            symbol: String::new(),
+            pnl: 0.0,
+            fee_paid: 0.0,
+            balance: 0.0,
+            fill_qty: 0.001,
+            fill_price: 60000.0,
+            position_size: 0.001,
+            position_price: 60000.0,
+            order_type: OrderType::EntryGridNormalLong,
+        };
+        // flat days at 100, 110, 121 and 121: daily returns 0.1, 0.1 and 0
+        let equities: Vec<f64> = (0..5760)
+            .map(|i| [100.0, 110.0, 121.0, 121.0][i / 1440])
+            .collect();
+        let result = BacktestResult {
+            fills: vec![fill(100), fill(3000)],
+            equities,
+            max_drawdown: 0.05,
+            ..Default::default()
+        };
+        let metrics = Metrics::from_result(&result);
+        assert!((metrics.adg - 0.2 / 3.0).abs() < 1e-12);
+        assert!((metrics.mdg - 0.1).abs() < 1e-12);
+        assert!((metrics.sharpe_ratio - 2f64.sqrt()).abs() < 1e-9);
+        assert_eq!(metrics.max_drawdown, 0.05);
+        // longest gap is between the two fills
+        assert_eq!(metrics.time_stuck_fraction, 2900.0 / 5760.0);
+        assert_eq!(metrics.fills_per_day, 0.5);
+        assert!(!metrics.pruned);
+        assert_eq!(
+            Metrics::from_result(&BacktestResult::default()),
+            Metrics::default()
+        );
+    }
+
+    fn exposure_inputs() -> Vec<(String, Position, ExchangeParams, f64)> {
+        let exchange_params = |c_mult| ExchangeParams {
+            c_mult,
+            ..Default::default()
+        };
+        let position = |size, price| Position { size, price };
+        vec![
+            (
+                "BTC".into(),
+                position(0.1, 60000.0),
+                exchange_params(1.0),
+                61000.0,
+            ),
+            (
+                "ETH".into(),
+                position(-2.0, 3000.0),
+                exchange_params(1.0),
+                3100.0,
+            ),
+            (
+                "1000PEPE".into(),
+                position(100.0, 0.01),
+                exchange_params(1000.0),
+                0.012,
+            ),
+            (
+                "SOL".into(),
+                Position::default(),
+                exchange_params(1.0),
+                150.0,
+            ),
+            (
+                "XMR".into(),
+                position(-5.0, 200.0),
+                exchange_params(10.0),
+                190.0,
+            ),
+        ]
+    }
+
+    #[test]
+    fn exposure_report_totals_mixed_sides_and_c_mult() {
+        let report = calc_exposure_report(10000.0, &exposure_inputs());
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        // flat SOL is skipped
+        let symbols: Vec<&str> = report.symbols.iter().map(|s| s.symbol.as_str()).collect();
+        assert_eq!(symbols, ["BTC", "ETH", "1000PEPE", "XMR"]);
+        let expected = [
+            // (wallet exposure, notional, unrealized pnl)
+            (0.6, 6100.0, 100.0),
+            (0.6, 6200.0, -200.0),
+            (0.1, 1200.0, 200.0),
+            (1.0, 9500.0, 500.0),
+        ];
+        for (exposure, (wallet_exposure, notional, unrealized_pnl)) in
+            report.symbols.iter().zip(expected)
+        {
+            assert!(close(exposure.wallet_exposure, wallet_exposure));
+            assert!(close(exposure.notional, notional));
+            assert!(close(exposure.unrealized_pnl, unrealized_pnl));
+        }
+        assert!(close(report.total_wallet_exposure, 2.3));
+        assert!(close(report.unrealized_pnl, 600.0));
+        assert!(close(report.notional_long, 7300.0));
+        assert!(close(report.notional_short, 15700.0));
+        assert_eq!(report.largest.as_ref().unwrap().symbol, "XMR");
+        assert_eq!(report.largest.as_ref().unwrap().size, -5.0);
+        // ties keep the first
+        let report = calc_exposure_report(10000.0, &exposure_inputs()[..2]);
+        assert_eq!(report.largest.unwrap().symbol, "BTC");
+        assert_eq!(
+            calc_exposure_report(10000.0, &[]),
+            ExposureReport::default()
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn exposure_report_json_round_trip() {
+        let report = calc_exposure_report(10000.0, &exposure_inputs());
+        let json = serde_json::to_string(&report).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ExposureReport>(&json).unwrap(),
+            report
+        );
+    }
+}
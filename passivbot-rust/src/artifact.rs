@@ -0,0 +1,308 @@
+use crate::analysis::Metrics;
+use crate::runner::{BacktestResult, PnlStats};
+use crate::types::{BacktestParams, BotParamsPair, ExchangeParams, Fill, OrderType};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+pub const SCHEMA_VERSION: u32 = 1;
+pub const EQUITY_SAMPLE_INTERVAL: usize = 60; // one equity per hour of 1m candles
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArtifactFormat {
+    Json,        // pretty printed
+    MessagePack, // compact binary
+}
+
+impl ArtifactFormat {
+    /// Guesses the format from the file extension, defaulting to JSON.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("msgpack") | Some("mpk") => ArtifactFormat::MessagePack,
+            _ => ArtifactFormat::Json,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ArtifactError {
+    Io(std::io::Error),
+    Serialization(String),
+    FeatureDisabled(ArtifactFormat),
+    UnsupportedSchemaVersion(u32),
+}
+
+impl fmt::Display for ArtifactError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArtifactError::Io(err) => write!(f, "io error: {}", err),
+            ArtifactError::Serialization(msg) => write!(f, "serialization error: {}", msg),
+            ArtifactError::FeatureDisabled(format) => {
+                write!(f, "{:?} support not enabled in this build", format)
+            }
+            ArtifactError::UnsupportedSchemaVersion(version) => write!(
+                f,
+                "schema_version {} is newer than supported version {}",
+                version, SCHEMA_VERSION
+            ),
+        }
+    }
+}
+
+impl From<std::io::Error> for ArtifactError {
+    fn from(err: std::io::Error) -> Self {
+        ArtifactError::Io(err)
+    }
+}
+
+/// Self-contained backtest output: config echo, metrics, fills and sampled equity curve.
+/// Unknown keys are ignored on load so newer writers stay readable.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BacktestArtifact {
+    pub schema_version: u32,
+    pub bot_params_pair: BotParamsPair,
+    pub exchange_params: ExchangeParams,
+    pub backtest_params: BacktestParams,
+    pub metrics: Metrics,
+    pub final_balance: f64,
+    pub max_drawdown: f64,
+    pub n_trades: usize,
+    pub pnl_by_order_type: HashMap<OrderType, PnlStats>,
+    pub fills: Vec<Fill>,
+    pub equity_sample_interval: usize,
+    pub equities: Vec<f64>, // every equity_sample_interval-th equity, plus the last one
+}
+
+impl BacktestArtifact {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ArtifactError> {
+        let path = path.as_ref();
+        let artifact: BacktestArtifact = read_file(path)?;
+        if artifact.schema_version > SCHEMA_VERSION {
+            return Err(ArtifactError::UnsupportedSchemaVersion(
+                artifact.schema_version,
+            ));
+        }
+        Ok(artifact)
+    }
+
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+        format: ArtifactFormat,
+    ) -> Result<(), ArtifactError> {
+        write_file(self, path, format)
+    }
+}
+
+impl BacktestResult {
+    pub fn to_artifact(&self) -> BacktestArtifact {
+        let mut equities: Vec<f64> = self
+            .equities
+            .iter()
+            .step_by(EQUITY_SAMPLE_INTERVAL)
+            .cloned()
+            .collect();
+        if let Some(&last) = self.equities.last() {
+            if !(self.equities.len() - 1).is_multiple_of(EQUITY_SAMPLE_INTERVAL) {
+                equities.push(last);
+            }
+        }
+        BacktestArtifact {
+            schema_version: SCHEMA_VERSION,
+            bot_params_pair: self.bot_params_pair.clone(),
+            exchange_params: self.exchange_params.clone(),
+            backtest_params: self.backtest_params.clone(),
+            metrics: Metrics::from_result(self),
+            final_balance: self.final_balance,
+            max_drawdown: self.max_drawdown,
+            n_trades: self.n_trades,
+            pnl_by_order_type: self.pnl_by_order_type.clone(),
+            fills: self.fills.clone(),
+            equity_sample_interval: EQUITY_SAMPLE_INTERVAL,
+            equities,
+        }
+    }
+
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+        format: ArtifactFormat,
+    ) -> Result<(), ArtifactError> {
+        self.to_artifact().save(path, format)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<BacktestArtifact, ArtifactError> {
+        BacktestArtifact::load(path)
+    }
+}
+
+/// Serializes value to path in the given format.
+pub(crate) fn write_file<T: Serialize>(
+    value: &T,
+    path: impl AsRef<Path>,
+    format: ArtifactFormat,
+) -> Result<(), ArtifactError> {
+    let bytes = match format {
+        ArtifactFormat::Json => to_json(value)?,
+        ArtifactFormat::MessagePack => to_msgpack(value)?,
+    };
+    fs::write(path, bytes)?;
+    Ok(())
+}
+
+/// Deserializes path, guessing the format from its extension.
+pub(crate) fn read_file<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T, ArtifactError> {
+    let path = path.as_ref();
+    let bytes = fs::read(path)?;
+    match ArtifactFormat::from_path(path) {
+        ArtifactFormat::Json => from_json(&bytes),
+        ArtifactFormat::MessagePack => from_msgpack(&bytes),
+    }
+}
+
+#[cfg(feature = "json")]
+fn to_json<T: Serialize>(value: &T) -> Result<Vec<u8>, ArtifactError> {
+    serde_json::to_vec_pretty(value).map_err(|err| ArtifactError::Serialization(err.to_string()))
+}
+
+#[cfg(not(feature = "json"))]
+fn to_json<T: Serialize>(_: &T) -> Result<Vec<u8>, ArtifactError> {
+    Err(ArtifactError::FeatureDisabled(ArtifactFormat::Json))
+}
+
+#[cfg(feature = "json")]
+fn from_json<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, ArtifactError> {
+    serde_json::from_slice(bytes).map_err(|err| ArtifactError::Serialization(err.to_string()))
+}
+
+#[cfg(not(feature = "json"))]
+fn from_json<T: DeserializeOwned>(_: &[u8]) -> Result<T, ArtifactError> {
+    Err(ArtifactError::FeatureDisabled(ArtifactFormat::Json))
+}
+
+#[cfg(feature = "msgpack")]
+fn to_msgpack<T: Serialize>(value: &T) -> Result<Vec<u8>, ArtifactError> {
+    // named fields so that readers can skip unknown keys
+    rmp_serde::to_vec_named(value).map_err(|err| ArtifactError::Serialization(err.to_string()))
+}
+
+#[cfg(not(feature = "msgpack"))]
+fn to_msgpack<T: Serialize>(_: &T) -> Result<Vec<u8>, ArtifactError> {
+    Err(ArtifactError::FeatureDisabled(ArtifactFormat::MessagePack))
+}
+
+#[cfg(feature = "msgpack")]
+fn from_msgpack<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, ArtifactError> {
+    rmp_serde::from_slice(bytes).map_err(|err| ArtifactError::Serialization(err.to_string()))
+}
+
+#[cfg(not(feature = "msgpack"))]
+fn from_msgpack<T: DeserializeOwned>(_: &[u8]) -> Result<T, ArtifactError> {
+    Err(ArtifactError::FeatureDisabled(ArtifactFormat::MessagePack))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::runner::backtest;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("pb_artifact_{}_{}", std::process::id(), name))
+    }
+
+    fn backtest_result() -> BacktestResult {
+        backtest(
+            &fixtures::candles(3000, 5),
+            &fixtures::bot_params_pair(),
+            &fixtures::exchange_params(),
+            &fixtures::backtest_params(),
+        )
+    }
+
+    fn sorted_pnl(artifact: &BacktestArtifact) -> Vec<(&OrderType, &PnlStats)> {
+        let mut pnl: Vec<_> = artifact.pnl_by_order_type.iter().collect();
+        pnl.sort_by_key(|(order_type, _)| order_type.code());
+        pnl
+    }
+
+    fn assert_round_trip(result: &BacktestResult, path: &Path, format: ArtifactFormat) {
+        result.save(path, format).unwrap();
+        let loaded = BacktestResult::load(path).unwrap();
+        fs::remove_file(path).unwrap();
+        let artifact = result.to_artifact();
+        assert_eq!(loaded.schema_version, SCHEMA_VERSION);
+        assert_eq!(loaded.fills, artifact.fills);
+        assert_eq!(loaded.equities, artifact.equities);
+        assert_eq!(loaded.metrics, artifact.metrics);
+        assert_eq!(
+            format!("{:?}", sorted_pnl(&loaded)),
+            format!("{:?}", sorted_pnl(&artifact))
+        );
+        assert_eq!(loaded.final_balance, result.final_balance);
+        assert_eq!(
+            format!("{:?}", loaded.bot_params_pair),
+            format!("{:?}", result.bot_params_pair)
+        );
+    }
+
+    #[test]
+    fn equities_sampled_hourly_plus_last() {
+        let result = backtest_result();
+        let artifact = result.to_artifact();
+        assert_eq!(artifact.equities.len(), 51);
+        assert_eq!(artifact.equities[1], result.equities[60]);
+        assert_eq!(artifact.equities.last(), result.equities.last());
+        assert!(!artifact.fills.is_empty());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_round_trip() {
+        assert_round_trip(
+            &backtest_result(),
+            &temp_path("round_trip.json"),
+            ArtifactFormat::Json,
+        );
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn msgpack_round_trip() {
+        assert_round_trip(
+            &backtest_result(),
+            &temp_path("round_trip.msgpack"),
+            ArtifactFormat::MessagePack,
+        );
+    }
+
+    #[cfg(not(feature = "msgpack"))]
+    #[test]
+    fn msgpack_without_feature_is_an_error() {
+        let path = temp_path("disabled.msgpack");
+        assert!(matches!(
+            backtest_result().save(&path, ArtifactFormat::MessagePack),
+            Err(ArtifactError::FeatureDisabled(ArtifactFormat::MessagePack))
+        ));
+        assert!(!path.exists());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn newer_schema_version_is_rejected() {
+        let path = temp_path("newer.json");
+        let mut artifact = backtest_result().to_artifact();
+        artifact.schema_version = SCHEMA_VERSION + 1;
+        artifact.save(&path, ArtifactFormat::Json).unwrap();
+        let loaded = BacktestArtifact::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            loaded,
+            Err(ArtifactError::UnsupportedSchemaVersion(version)) if version == SCHEMA_VERSION + 1
+        ));
+    }
+}
@@ -3,12 +3,16 @@ use crate::closes::{
 };
 use crate::constants::{CLOSE, HIGH, LONG, LOW, NO_POS, SHORT, VOLUME};
 use crate::entries::{
-    calc_entries_long, calc_entries_short, calc_min_entry_qty, calc_next_entry_long,
-    calc_next_entry_short,
+    calc_blended_entries_long_with_precomputed, calc_blended_entries_short_with_precomputed,
+    calc_entries_long_with_precomputed, calc_entries_short_with_precomputed, calc_min_entry_qty,
+    calc_next_entry_long, calc_next_entry_long_with_precomputed, calc_next_entry_short,
+    calc_next_entry_short_with_precomputed, PrecomputedState,
 };
+use crate::error::PassivbotError;
 use crate::types::{
-    Analysis, BacktestParams, BotParams, BotParamsPair, EMABands, ExchangeParams, Fill, Order,
-    OrderBook, OrderType, Position, Positions, StateParams, TrailingPriceBundle,
+    Analysis, BacktestParams, BotParamsPair, EMABands, EntrySizingBasis, EntryTrailingGridMode,
+    ExchangeParams, Fill, LastFill, Order, OrderBook, OrderKind, OrderType, Position, Positions,
+    StateParams, TrailingPriceBundle,
 };
 use crate::utils::{
     calc_auto_unstuck_allowance, calc_new_psize_pprice, calc_pnl_long, calc_pnl_short,
@@ -67,6 +71,16 @@ impl EMAs {
         };
         EMABands { upper, lower }
     }
+
+    #[inline]
+    pub fn update(&mut self, close_price: f64, ema_alphas: &EmaAlphas) {
+        for z in 0..3 {
+            self.long[z] = close_price * ema_alphas.long.alphas[z]
+                + self.long[z] * ema_alphas.long.alphas_inv[z];
+            self.short[z] = close_price * ema_alphas.short.alphas[z]
+                + self.short[z] * ema_alphas.short.alphas_inv[z];
+        }
+    }
 }
 
 #[derive(Debug, Default)]
@@ -99,6 +113,18 @@ pub struct TrailingPrices {
     pub short: HashMap<usize, TrailingPriceBundle>,
 }
 
+#[derive(Default, Debug)]
+pub struct LastEntryFills {
+    pub long: HashMap<usize, LastFill>,
+    pub short: HashMap<usize, LastFill>,
+}
+
+#[derive(Default, Debug)]
+pub struct TrailingAddCounts {
+    pub long: HashMap<usize, usize>,
+    pub short: HashMap<usize, usize>,
+}
+
 pub struct TrailingEnabled {
     long: bool,
     short: bool,
@@ -128,6 +154,8 @@ pub struct Backtest<'a> {
     positions: Positions,
     open_orders: OpenOrdersNew,
     trailing_prices: TrailingPrices,
+    last_entry_fills: LastEntryFills,
+    trailing_add_counts: TrailingAddCounts,
     actives: Actives,
     pnl_cumsum_running: f64,
     pnl_cumsum_max: f64,
@@ -151,9 +179,23 @@ impl<'a> Backtest<'a> {
         bot_params_pair: BotParamsPair,
         exchange_params_list: Vec<ExchangeParams>,
         backtest_params: &BacktestParams,
-    ) -> Self {
+    ) -> Result<Self, PassivbotError> {
         let n_timesteps = hlcvs.shape()[0];
         let n_coins = hlcvs.shape()[1];
+        if n_timesteps == 0 || hlcvs.shape()[2] <= VOLUME {
+            return Err(PassivbotError::InvalidParams(format!(
+                "hlcvs shape {:?} needs at least one timestep and {} columns",
+                hlcvs.shape(),
+                VOLUME + 1
+            )));
+        }
+        if exchange_params_list.len() != n_coins {
+            return Err(PassivbotError::InvalidParams(format!(
+                "{} exchange_params for {} coins",
+                exchange_params_list.len(),
+                n_coins
+            )));
+        }
         let initial_emas = (0..n_coins)
             .map(|i| {
                 let close_price = hlcvs[[0, i, CLOSE]];
@@ -176,7 +218,7 @@ impl<'a> Backtest<'a> {
             (n_coins as f64 * (1.0 - bot_params_pair.short.filter_relative_volume_clip_pct)).round()
                 as usize,
         );
-        Backtest {
+        Ok(Backtest {
             hlcvs,
             bot_params_pair: bot_params_pair_cloned,
             exchange_params_list,
@@ -188,6 +230,8 @@ impl<'a> Backtest<'a> {
             positions: Positions::default(),
             open_orders: OpenOrdersNew::default(),
             trailing_prices: TrailingPrices::default(),
+            last_entry_fills: LastEntryFills::default(),
+            trailing_add_counts: TrailingAddCounts::default(),
             actives: Actives::default(),
             pnl_cumsum_running: 0.0,
             pnl_cumsum_max: 0.0,
@@ -218,7 +262,7 @@ impl<'a> Backtest<'a> {
                 prev_k_short: 0,
             },
             volume_indices_buffer: Some(vec![(0.0, 0); n_coins]), // Initialize here
-        }
+        })
     }
 
     pub fn calc_preferred_coins(&mut self, k: usize, pside: usize) -> Vec<usize> {
@@ -350,15 +394,79 @@ impl<'a> Backtest<'a> {
         (self.fills.clone(), self.equities.clone())
     }
 
+    fn sizing_balance(&self) -> f64 {
+        self.backtest_params
+            .balance_mode
+            .sizing_balance(self.balance, self.backtest_params.starting_balance)
+    }
+
+    /// Timestamp of candle k in ms. hlcvs holds 1m candles from first_timestamp_ms on.
+    fn timestamp_ms(&self, k: usize) -> u64 {
+        self.backtest_params.first_timestamp_ms + k as u64 * 60_000
+    }
+
     fn create_state_params(&self, k: usize, idx: usize, pside: usize) -> StateParams {
         let close_price = self.hlcvs[[k, idx, CLOSE]];
+        let bot_params = if pside == LONG {
+            &self.bot_params_pair.long
+        } else {
+            &self.bot_params_pair.short
+        };
         StateParams {
-            balance: self.balance,
+            balance: self.sizing_balance(),
+            // calc_equity walks every open position; only equity sizing reads it
+            equity: match bot_params.entry_sizing_basis {
+                EntrySizingBasis::Balance => None,
+                EntrySizingBasis::Equity => Some(
+                    self.backtest_params
+                        .balance_mode
+                        .sizing_balance(self.calc_equity(k), self.backtest_params.starting_balance),
+                ),
+            },
             order_book: OrderBook {
                 bid: close_price,
                 ask: close_price,
             },
             ema_bands: self.emas[idx].compute_bands(pside),
+            timestamp: self.timestamp_ms(k),
+            last_entry_fill: if pside == LONG {
+                self.last_entry_fills.long.get(&idx).copied()
+            } else {
+                self.last_entry_fills.short.get(&idx).copied()
+            },
+            trailing_add_count: if pside == LONG {
+                self.trailing_add_counts.long.get(&idx).copied()
+            } else {
+                self.trailing_add_counts.short.get(&idx).copied()
+            }
+            .unwrap_or(0),
+            volatility: self.calc_volatility(k, idx, pside),
+        }
+    }
+
+    /// Mean (high - low) / close of idx over the filter_rolling_window candles before k, the
+    /// per candle noisiness that calc_preferred_coins ranks by. Unlisted candles are skipped.
+    /// Zero while entry_grid_spacing_vol_weight is, as nothing reads it then.
+    fn calc_volatility(&self, k: usize, idx: usize, pside: usize) -> f64 {
+        let bot_params = match pside {
+            LONG => &self.bot_params_pair.long,
+            _ => &self.bot_params_pair.short,
+        };
+        if bot_params.entry_grid_spacing_vol_weight == 0.0 {
+            return 0.0;
+        }
+        let start_k = k.saturating_sub(bot_params.filter_rolling_window);
+        let (sum, n) = self
+            .hlcvs
+            .slice(s![start_k..k, idx, ..])
+            .axis_iter(Axis(0))
+            .map(|row| (row[HIGH] - row[LOW]) / row[CLOSE])
+            .filter(|range| !range.is_nan())
+            .fold((0.0, 0usize), |(sum, n), range| (sum + range, n + 1));
+        if n == 0 {
+            0.0
+        } else {
+            sum / n as f64
         }
     }
 
@@ -371,6 +479,11 @@ impl<'a> Backtest<'a> {
     }
 
     fn update_equities(&mut self, k: usize) {
+        self.equities.push(self.calc_equity(k));
+    }
+
+    /// Balance plus unrealized PnL of all positions, marked at the close of candle k.
+    fn calc_equity(&self, k: usize) -> f64 {
         let mut equity = self.balance;
         // Calculate unrealized PnL for long positions
         for (&idx, position) in &self.positions.long {
@@ -394,7 +507,7 @@ impl<'a> Backtest<'a> {
             );
             equity += upnl;
         }
-        self.equities.push(equity);
+        equity
     }
 
     fn update_actives(&mut self, k: usize, pside: usize) -> Vec<usize> {
@@ -541,7 +654,7 @@ impl<'a> Backtest<'a> {
                 if self.positions.long.contains_key(&idx) {
                     let wallet_exposure = calc_wallet_exposure(
                         self.exchange_params_list[idx].c_mult,
-                        self.balance,
+                        self.sizing_balance(),
                         self.positions.long[&idx].size,
                         self.positions.long[&idx].price,
                     );
@@ -560,7 +673,7 @@ impl<'a> Backtest<'a> {
                 if self.positions.short.contains_key(&idx) {
                     let wallet_exposure = calc_wallet_exposure(
                         self.exchange_params_list[idx].c_mult,
-                        self.balance,
+                        self.sizing_balance(),
                         self.positions.short[&idx].size.abs(),
                         self.positions.short[&idx].price,
                     );
@@ -611,6 +724,7 @@ impl<'a> Backtest<'a> {
         let current_pprice = self.positions.long[&idx].price;
         if new_psize == 0.0 {
             self.positions.long.remove(&idx);
+            self.trailing_add_counts.long.remove(&idx);
         } else {
             self.positions.long.get_mut(&idx).unwrap().size = new_psize;
         }
@@ -660,6 +774,7 @@ impl<'a> Backtest<'a> {
         let current_pprice = self.positions.short[&idx].price;
         if new_psize == 0.0 {
             self.positions.short.remove(&idx);
+            self.trailing_add_counts.short.remove(&idx);
         } else {
             self.positions.short.get_mut(&idx).unwrap().size = new_psize;
         }
@@ -699,6 +814,16 @@ impl<'a> Backtest<'a> {
         );
         self.positions.long.get_mut(&idx).unwrap().size = new_psize;
         self.positions.long.get_mut(&idx).unwrap().price = new_pprice;
+        self.last_entry_fills.long.insert(
+            idx,
+            LastFill {
+                price: order.price,
+                timestamp: self.timestamp_ms(k),
+            },
+        );
+        if order.order_type.kind() == OrderKind::EntryTrailing {
+            *self.trailing_add_counts.long.entry(idx).or_insert(0) += 1;
+        }
         self.fills.push(Fill {
             index: k,                                          // index minute
             symbol: self.backtest_params.symbols[idx].clone(), // symbol
@@ -735,6 +860,16 @@ impl<'a> Backtest<'a> {
         );
         self.positions.short.get_mut(&idx).unwrap().size = new_psize;
         self.positions.short.get_mut(&idx).unwrap().price = new_pprice;
+        self.last_entry_fills.short.insert(
+            idx,
+            LastFill {
+                price: order.price,
+                timestamp: self.timestamp_ms(k),
+            },
+        );
+        if order.order_type.kind() == OrderKind::EntryTrailing {
+            *self.trailing_add_counts.short.entry(idx).or_insert(0) += 1;
+        }
         self.fills.push(Fill {
             index: k,                                          // index minute
             symbol: self.backtest_params.symbols[idx].clone(), // symbol
@@ -816,22 +951,11 @@ impl<'a> Backtest<'a> {
         } else {
             self.trailing_prices.short.entry(idx).or_default()
         };
-        if self.hlcvs[[k, idx, LOW]] < trailing_price_bundle.min_since_open {
-            trailing_price_bundle.min_since_open = self.hlcvs[[k, idx, LOW]];
-            trailing_price_bundle.max_since_min = self.hlcvs[[k, idx, CLOSE]];
-        } else {
-            trailing_price_bundle.max_since_min = trailing_price_bundle
-                .max_since_min
-                .max(self.hlcvs[[k, idx, HIGH]]);
-        }
-        if self.hlcvs[[k, idx, HIGH]] > trailing_price_bundle.max_since_open {
-            trailing_price_bundle.max_since_open = self.hlcvs[[k, idx, HIGH]];
-            trailing_price_bundle.min_since_max = self.hlcvs[[k, idx, CLOSE]];
-        } else {
-            trailing_price_bundle.min_since_max = trailing_price_bundle
-                .min_since_max
-                .min(self.hlcvs[[k, idx, LOW]]);
-        }
+        trailing_price_bundle.update(
+            self.hlcvs[[k, idx, HIGH]],
+            self.hlcvs[[k, idx, LOW]],
+            self.hlcvs[[k, idx, CLOSE]],
+        );
     }
 
     fn has_next_grid_order(&mut self, order: &Order, pside: usize) -> bool {
@@ -890,24 +1014,45 @@ impl<'a> Backtest<'a> {
                 return;
             }
         }
-        let next_entry_order = calc_next_entry_long(
+        // shared by the entry calculators below
+        let precomputed = PrecomputedState::long(
+            &self.exchange_params_list[idx],
+            &state_params,
+            &self.bot_params_pair.long,
+        );
+        let next_entry_order = calc_next_entry_long_with_precomputed(
             &self.exchange_params_list[idx],
             &state_params,
             &self.bot_params_pair.long,
             &position,
             &self.trailing_prices.long[&idx],
+            &precomputed,
         );
         // if initial entry or grid, peek next candle to see if order will fill
         if self.order_filled(k + 1, idx, &next_entry_order)
             && self.has_next_grid_order(&next_entry_order, LONG)
         {
-            self.open_orders.long.entry(idx).or_default().entries = calc_entries_long(
-                &self.exchange_params_list[idx],
-                &state_params,
-                &self.bot_params_pair.long,
-                &position,
-                &self.trailing_prices.long[&idx],
-            );
+            self.open_orders.long.entry(idx).or_default().entries =
+                calc_entries_long_with_precomputed(
+                    &self.exchange_params_list[idx],
+                    &state_params,
+                    &self.bot_params_pair.long,
+                    &position,
+                    &self.trailing_prices.long[&idx],
+                    &precomputed,
+                );
+        } else if self.bot_params_pair.long.entry_trailing_grid_mode
+            == EntryTrailingGridMode::Blended
+        {
+            self.open_orders.long.entry(idx).or_default().entries =
+                calc_blended_entries_long_with_precomputed(
+                    &self.exchange_params_list[idx],
+                    &state_params,
+                    &self.bot_params_pair.long,
+                    &position,
+                    &self.trailing_prices.long[&idx],
+                    &precomputed,
+                );
         } else {
             self.open_orders.long.entry(idx).or_default().entries = [next_entry_order].to_vec();
         }
@@ -955,31 +1100,52 @@ impl<'a> Backtest<'a> {
                         ),
                         self.exchange_params_list[idx].price_step,
                     ),
-                    order_type: OrderType::CloseUnstuckLong,
+                    order_type: OrderType::CloseUnstuckShort,
                 }]
                 .to_vec();
                 self.open_orders.short.entry(idx).or_default().entries = Vec::new();
                 return;
             }
         }
-        let next_entry_order = calc_next_entry_short(
+        // shared by the entry calculators below
+        let precomputed = PrecomputedState::short(
+            &self.exchange_params_list[idx],
+            &state_params,
+            &self.bot_params_pair.short,
+        );
+        let next_entry_order = calc_next_entry_short_with_precomputed(
             &self.exchange_params_list[idx],
             &state_params,
             &self.bot_params_pair.short,
             &position,
             &self.trailing_prices.short[&idx],
+            &precomputed,
         );
         // if initial entry or grid, peek next candle to see if order will fill
         if self.order_filled(k + 1, idx, &next_entry_order)
             && self.has_next_grid_order(&next_entry_order, SHORT)
         {
-            self.open_orders.short.entry(idx).or_default().entries = calc_entries_short(
-                &self.exchange_params_list[idx],
-                &state_params,
-                &self.bot_params_pair.short,
-                &position,
-                &self.trailing_prices.short[&idx],
-            );
+            self.open_orders.short.entry(idx).or_default().entries =
+                calc_entries_short_with_precomputed(
+                    &self.exchange_params_list[idx],
+                    &state_params,
+                    &self.bot_params_pair.short,
+                    &position,
+                    &self.trailing_prices.short[&idx],
+                    &precomputed,
+                );
+        } else if self.bot_params_pair.short.entry_trailing_grid_mode
+            == EntryTrailingGridMode::Blended
+        {
+            self.open_orders.short.entry(idx).or_default().entries =
+                calc_blended_entries_short_with_precomputed(
+                    &self.exchange_params_list[idx],
+                    &state_params,
+                    &self.bot_params_pair.short,
+                    &position,
+                    &self.trailing_prices.short[&idx],
+                    &precomputed,
+                );
         } else {
             self.open_orders.short.entry(idx).or_default().entries = [next_entry_order].to_vec();
         }
@@ -1108,7 +1274,7 @@ impl<'a> Backtest<'a> {
                                 min_entry_qty,
                                 round_dn(
                                     cost_to_qty(
-                                        self.balance
+                                        self.sizing_balance()
                                             * self.bot_params_pair.long.wallet_exposure_limit
                                             * self.bot_params_pair.long.unstuck_close_pct,
                                         close_price,
@@ -1174,7 +1340,7 @@ impl<'a> Backtest<'a> {
                                 min_entry_qty,
                                 round_dn(
                                     cost_to_qty(
-                                        self.balance
+                                        self.sizing_balance()
                                             * self.bot_params_pair.short.wallet_exposure_limit
                                             * self.bot_params_pair.short.unstuck_close_pct,
                                         close_price,
@@ -1396,24 +1562,12 @@ impl<'a> Backtest<'a> {
     #[inline]
     fn update_emas(&mut self, k: usize) {
         for i in 0..self.n_coins {
-            let close_price = self.hlcvs[[k, i, CLOSE]];
-
-            let long_alphas = &self.ema_alphas.long.alphas;
-            let long_alphas_inv = &self.ema_alphas.long.alphas_inv;
-            let short_alphas = &self.ema_alphas.short.alphas;
-            let short_alphas_inv = &self.ema_alphas.short.alphas_inv;
-
-            let emas = &mut self.emas[i];
-
-            for z in 0..3 {
-                emas.long[z] = close_price * long_alphas[z] + emas.long[z] * long_alphas_inv[z];
-                emas.short[z] = close_price * short_alphas[z] + emas.short[z] * short_alphas_inv[z];
-            }
+            self.emas[i].update(self.hlcvs[[k, i, CLOSE]], &self.ema_alphas);
         }
     }
 }
 
-fn calc_ema_alphas(bot_params_pair: &BotParamsPair) -> EmaAlphas {
+pub fn calc_ema_alphas(bot_params_pair: &BotParamsPair) -> EmaAlphas {
     let mut ema_spans_long = [
         bot_params_pair.long.ema_span_0,
         bot_params_pair.long.ema_span_1,
@@ -1447,30 +1601,7 @@ fn calc_ema_alphas(bot_params_pair: &BotParamsPair) -> EmaAlphas {
 }
 
 pub fn analyze_backtest(fills: &[Fill], equities: &Vec<f64>) -> Analysis {
-    // Calculate daily equities
-    let mut daily_eqs = Vec::new();
-    let mut current_day = 0;
-    let mut sum = 0.0;
-    let mut count = 0;
-    for (i, &equity) in equities.iter().enumerate() {
-        let day = i / 1440;
-        if day > current_day {
-            daily_eqs.push(sum / count as f64);
-            current_day = day;
-            sum = equity;
-            count = 1;
-        } else {
-            sum += equity;
-            count += 1;
-        }
-    }
-    if count > 0 {
-        daily_eqs.push(sum / count as f64);
-    }
-
-    // Calculate daily percentage changes
-    let daily_eqs_pct_change: Vec<f64> =
-        daily_eqs.windows(2).map(|w| (w[1] - w[0]) / w[0]).collect();
+    let daily_eqs_pct_change = calc_daily_returns(equities);
 
     // Calculate ADG and Sharpe ratio
     let adg = daily_eqs_pct_change.iter().sum::<f64>() / daily_eqs_pct_change.len() as f64;
@@ -1549,7 +1680,33 @@ pub fn analyze_backtest(fills: &[Fill], equities: &Vec<f64>) -> Analysis {
     }
 }
 
-fn calc_drawdowns(equity_series: &[f64]) -> Vec<f64> {
+pub fn calc_daily_returns(equities: &[f64]) -> Vec<f64> {
+    // Calculate daily equities
+    let mut daily_eqs = Vec::new();
+    let mut current_day = 0;
+    let mut sum = 0.0;
+    let mut count = 0;
+    for (i, &equity) in equities.iter().enumerate() {
+        let day = i / 1440;
+        if day > current_day {
+            daily_eqs.push(sum / count as f64);
+            current_day = day;
+            sum = equity;
+            count = 1;
+        } else {
+            sum += equity;
+            count += 1;
+        }
+    }
+    if count > 0 {
+        daily_eqs.push(sum / count as f64);
+    }
+
+    // Calculate daily percentage changes
+    daily_eqs.windows(2).map(|w| (w[1] - w[0]) / w[0]).collect()
+}
+
+pub fn calc_drawdowns(equity_series: &[f64]) -> Vec<f64> {
     let mut cumulative_returns = vec![1.0];
     let mut cumulative_max = vec![1.0];
 
@@ -1566,3 +1723,158 @@ fn calc_drawdowns(equity_series: &[f64]) -> Vec<f64> {
         .map(|(&ret, &max)| (ret - max) / max)
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    /// One symbol of 1m candles oscillating +-5% around 60000, as sine_candles in
+    /// tests/runner.rs.
+    fn sine_hlcvs(n_candles: usize, period: usize) -> Array3<f64> {
+        Array3::from_shape_fn((n_candles, 1, 4), |(k, _, column)| {
+            let phase = k as f64 / period as f64 * std::f64::consts::TAU;
+            let close = (60000.0 * (1.0 + 0.05 * phase.sin()) * 10.0).round() / 10.0;
+            match column {
+                HIGH => close * 1.0005,
+                LOW => close * 0.9995,
+                CLOSE => close,
+                _ => 100.0,
+            }
+        })
+    }
+
+    fn entry_indices(
+        hlcvs: &Array3<f64>,
+        entry_blackout_windows: Vec<(u64, u64)>,
+        first_timestamp_ms: u64,
+    ) -> Vec<usize> {
+        let mut bot_params_pair = fixtures::bot_params_pair();
+        bot_params_pair.short.wallet_exposure_limit = 0.0;
+        bot_params_pair.long.entry_blackout_windows = entry_blackout_windows;
+        let backtest_params = BacktestParams {
+            symbols: vec!["BTCUSDT".to_string()],
+            first_timestamp_ms,
+            ..fixtures::backtest_params()
+        };
+        let hlcvs = hlcvs.view();
+        let mut backtest = Backtest::new(
+            &hlcvs,
+            bot_params_pair,
+            vec![fixtures::exchange_params()],
+            &backtest_params,
+        )
+        .unwrap();
+        let (fills, _) = backtest.run();
+        fills
+            .iter()
+            .filter(|fill| fill.order_type.is_entry())
+            .map(|fill| fill.index)
+            .collect()
+    }
+
+    #[test]
+    fn no_entries_in_blackout_window_from_first_timestamp() {
+        let hlcvs = sine_hlcvs(20_000, 2_000);
+        let base_ms = 1_700_000_000_000;
+        let window = (base_ms + 6_000 * 60_000, base_ms + 12_000 * 60_000);
+        let in_window = |k: &usize| (6_000..12_000).contains(k);
+        assert!(entry_indices(&hlcvs, vec![], base_ms).iter().any(in_window));
+        let blacked_out = entry_indices(&hlcvs, vec![window], base_ms);
+        assert!(!blacked_out.iter().any(in_window));
+        assert!(blacked_out.iter().any(|&k| k >= 12_000));
+        // candles dated from epoch 0 never reach the window
+        assert!(entry_indices(&hlcvs, vec![window], 0).iter().any(in_window));
+    }
+
+    #[test]
+    fn equity_sizing_follows_unrealized_pnl() {
+        // symbol 0 at 66000, symbol 1 at 60000
+        let hlcvs = Array3::from_shape_fn((10, 2, 4), |(_, idx, column)| match column {
+            VOLUME => 100.0,
+            _ if idx == 0 => 66000.0,
+            _ => 60000.0,
+        });
+        let hlcvs = hlcvs.view();
+        let exchange_params = fixtures::exchange_params();
+        // initial entry of symbol 1 while symbol 0 holds position_0
+        let initial_entry = |entry_sizing_basis: EntrySizingBasis, position_0: Position| {
+            let mut bot_params_pair = fixtures::bot_params_pair();
+            bot_params_pair.long.entry_sizing_basis = entry_sizing_basis;
+            bot_params_pair.long.wallet_exposure_limit = 1.0;
+            bot_params_pair.long.entry_initial_qty_pct = 0.5;
+            let bot_params = bot_params_pair.long.clone();
+            let backtest_params = BacktestParams {
+                symbols: vec!["BTCUSDT".to_string(), "ETHUSDT".to_string()],
+                ..fixtures::backtest_params()
+            };
+            let mut backtest = Backtest::new(
+                &hlcvs,
+                bot_params_pair,
+                vec![exchange_params.clone(); 2],
+                &backtest_params,
+            )
+            .unwrap();
+            backtest.positions.long.insert(0, position_0);
+            let state_params = backtest.create_state_params(9, 1, LONG);
+            let entry = calc_next_entry_long(
+                &exchange_params,
+                &state_params,
+                &bot_params,
+                &Position::default(),
+                &TrailingPriceBundle::default(),
+            );
+            (state_params.equity, entry.qty)
+        };
+        let at = |price: f64| Position { size: 0.1, price };
+        // 0.1 bought at 60000 is 600 up at 66000, bought at 72000 600 down
+        let (flat_equity, flat_qty) = initial_entry(EntrySizingBasis::Equity, at(66000.0));
+        let (up_equity, up_qty) = initial_entry(EntrySizingBasis::Equity, at(60000.0));
+        let (down_equity, down_qty) = initial_entry(EntrySizingBasis::Equity, at(72000.0));
+        assert_eq!(
+            (flat_equity, up_equity, down_equity),
+            (Some(10000.0), Some(10600.0), Some(9400.0))
+        );
+        assert!(down_qty < flat_qty && flat_qty < up_qty);
+        for position_price in [60000.0, 66000.0, 72000.0] {
+            assert_eq!(
+                initial_entry(EntrySizingBasis::Balance, at(position_price)),
+                (None, flat_qty)
+            );
+        }
+    }
+
+    #[test]
+    fn volatility_is_the_mean_candle_range_before_the_current_one() {
+        // ranges (high - low) / close of 0.01 * (k + 1), unlisted at k 3
+        let hlcvs = Array3::from_shape_fn((8, 1, 4), |(k, _, column)| match column {
+            _ if k == 3 && column != VOLUME => f64::NAN,
+            HIGH => 100.0 + 0.5 * (k + 1) as f64,
+            LOW => 100.0 - 0.5 * (k + 1) as f64,
+            CLOSE => 100.0,
+            _ => 10.0,
+        });
+        let hlcvs = hlcvs.view();
+        let mut bot_params_pair = fixtures::bot_params_pair();
+        bot_params_pair.long.filter_rolling_window = 4;
+        bot_params_pair.long.entry_grid_spacing_vol_weight = 10.0;
+        let backtest_params = BacktestParams {
+            symbols: vec!["BTCUSDT".to_string()],
+            ..fixtures::backtest_params()
+        };
+        let backtest = Backtest::new(
+            &hlcvs,
+            bot_params_pair,
+            vec![fixtures::exchange_params()],
+            &backtest_params,
+        )
+        .unwrap();
+        let volatility = |k: usize| backtest.calc_volatility(k, 0, LONG);
+        assert_eq!(volatility(0), 0.0);
+        assert!((volatility(2) - 0.015).abs() < 1e-12);
+        // k 2..6 with k 3 skipped: (0.03 + 0.05 + 0.06) / 3
+        assert!((volatility(6) - 0.14 / 3.0).abs() < 1e-12);
+        // nothing reads it without a weight
+        assert_eq!(backtest.calc_volatility(6, 0, SHORT), 0.0);
+    }
+}
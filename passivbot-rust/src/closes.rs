@@ -1,14 +1,83 @@
+//! Close calculators. See Order for what zero qty and Empty orders mean.
+
+use crate::constants::{LONG, SHORT};
 use crate::entries::calc_min_entry_qty;
+use crate::sync::{apply_price_band, coalesce_orders};
 use crate::types::{
-    BotParams, BotParamsPair, EMABands, ExchangeParams, Order, OrderType, Position, Positions,
-    StateParams, TrailingPriceBundle,
+    check_side, debug_assert_signs, BotParams, BotParamsPair, EMABands, ExchangeParams, Order,
+    OrderBook, OrderType, Position, Positions, Side, StateParams, TrailingPriceBundle,
 };
 use crate::utils::{
-    calc_pprice_diff_int, calc_wallet_exposure, cost_to_qty, round_, round_dn, round_up,
+    calc_pnl_long, calc_pnl_short, calc_pprice_diff_int, calc_wallet_exposure, cost_to_qty, round_,
+    round_dn, round_up,
 };
 use ndarray::{Array1, Array2};
 use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CloseLevelsError {
+    LengthMismatch {
+        n_levels: usize,
+        n_qtys_pct: usize,
+    },
+    InvalidQtyPct(f64),
+    QtyPctSumAboveOne(f64),
+    InvalidStopPrice {
+        stop_price: f64,
+        position_price: f64,
+    },
+    InvalidRMultiple(f64),
+}
 
+impl fmt::Display for CloseLevelsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CloseLevelsError::LengthMismatch {
+                n_levels,
+                n_qtys_pct,
+            } => write!(f, "{} levels but {} qty fractions", n_levels, n_qtys_pct),
+            CloseLevelsError::InvalidQtyPct(qty_pct) => {
+                write!(
+                    f,
+                    "qty fraction {} is not a number of at least 0.0",
+                    qty_pct
+                )
+            }
+            CloseLevelsError::QtyPctSumAboveOne(sum) => {
+                write!(f, "qty fractions sum to {}, more than 1.0", sum)
+            }
+            CloseLevelsError::InvalidStopPrice {
+                stop_price,
+                position_price,
+            } => write!(
+                f,
+                "stop price {} is not on the loss side of position price {}",
+                stop_price, position_price
+            ),
+            CloseLevelsError::InvalidRMultiple(r) => write!(
+                f,
+                "r multiple {} must be above 0.0 and give a target price above 0.0",
+                r
+            ),
+        }
+    }
+}
+
+/// Qty closing the whole position, on the qty_step grid. A size off the grid by float error
+/// only closes in full; a size genuinely off the grid is rounded down instead, so that a
+/// reduce-only order cannot be rejected for closing more than is held.
+fn calc_full_close_qty(position_size: f64, qty_step: f64) -> f64 {
+    let position_size_abs = position_size.abs();
+    let close_qty = round_(position_size_abs, qty_step);
+    if close_qty > position_size_abs * (1.0 + 1e-9) {
+        round_dn(position_size_abs, qty_step)
+    } else {
+        close_qty
+    }
+}
+
+#[must_use]
 pub fn calc_close_qty(
     exchange_params: &ExchangeParams,
     bot_params: &BotParams,
@@ -25,8 +94,9 @@ pub fn calc_close_qty(
     let position_size_abs = position.size.abs();
     let leftover = f64::max(0.0, position_size_abs - full_psize);
     let min_entry_qty = calc_min_entry_qty(close_price, &exchange_params);
+    let full_close_qty = calc_full_close_qty(position_size_abs, exchange_params.qty_step);
     let close_qty = f64::min(
-        round_(position_size_abs, exchange_params.qty_step),
+        full_close_qty,
         f64::max(
             min_entry_qty,
             round_up(
@@ -36,34 +106,84 @@ pub fn calc_close_qty(
         ),
     );
     if close_qty > 0.0
-        && close_qty < position_size_abs
+        && close_qty < full_close_qty
         && position_size_abs - close_qty < min_entry_qty
     {
-        position_size_abs
+        full_close_qty
     } else {
         close_qty
     }
 }
 
+#[must_use]
+pub fn calc_close_grid_min_markup(exchange_params: &ExchangeParams, bot_params: &BotParams) -> f64 {
+    // raise the whole close grid so its lowest slice is profitable after entry and close fees
+    if exchange_params.fee_rate > 0.0 || bot_params.close_min_net_markup > 0.0 {
+        f64::max(
+            bot_params.close_grid_min_markup,
+            2.0 * exchange_params.fee_rate + bot_params.close_min_net_markup,
+        )
+    } else {
+        bot_params.close_grid_min_markup
+    }
+}
+
+/// close_grid_markup_range, narrowed so the top of the close grid stays within
+/// close_grid_max_markup, if set. Zero if the min markup is at or above the cap already.
+#[must_use]
+pub fn calc_close_grid_markup_range(close_grid_min_markup: f64, bot_params: &BotParams) -> f64 {
+    if bot_params.close_grid_max_markup > 0.0 {
+        f64::max(
+            0.0,
+            f64::min(
+                bot_params.close_grid_markup_range,
+                bot_params.close_grid_max_markup - close_grid_min_markup,
+            ),
+        )
+    } else {
+        bot_params.close_grid_markup_range
+    }
+}
+
+/// close_trailing_retracement_pct, narrowed one point per point of profit_pct down to
+/// close_trailing_drawdown_floor_pct, if set, so trailing closes lock in more of a large move.
+/// profit_pct is the best move in the position's favor since open, relative to its price.
+#[must_use]
+pub fn calc_close_trailing_retracement_pct(bot_params: &BotParams, profit_pct: f64) -> f64 {
+    let retracement_pct = bot_params.close_trailing_retracement_pct;
+    let floor_pct = bot_params.close_trailing_drawdown_floor_pct;
+    if floor_pct > 0.0 && floor_pct < retracement_pct {
+        (retracement_pct - f64::max(0.0, profit_pct)).clamp(floor_pct, retracement_pct)
+    } else {
+        retracement_pct
+    }
+}
+
+#[must_use]
 pub fn calc_grid_close_long(
     exchange_params: &ExchangeParams,
     state_params: &StateParams,
     bot_params: &BotParams,
     position: &Position,
 ) -> Order {
+    if !check_side(position, Side::Long) {
+        return Order::default();
+    }
     if position.size <= 0.0 {
         return Order::default();
     }
-    if bot_params.close_grid_markup_range <= 0.0
+    let close_grid_min_markup = calc_close_grid_min_markup(exchange_params, bot_params);
+    let close_grid_markup_range = calc_close_grid_markup_range(close_grid_min_markup, bot_params);
+    if close_grid_markup_range <= 0.0
         || bot_params.close_grid_qty_pct < 0.0
         || bot_params.close_grid_qty_pct >= 1.0
     {
         return Order {
-            qty: -round_(position.size, exchange_params.qty_step),
+            qty: -calc_full_close_qty(position.size, exchange_params.qty_step),
             price: f64::max(
                 state_params.order_book.ask,
                 round_up(
-                    position.price * (1.0 + bot_params.close_grid_min_markup),
+                    position.price * (1.0 + close_grid_min_markup),
                     exchange_params.price_step,
                 ),
             ),
@@ -71,17 +191,22 @@ pub fn calc_grid_close_long(
         };
     }
     let close_prices_start = round_up(
-        position.price * (1.0 + bot_params.close_grid_min_markup),
+        position.price * (1.0 + close_grid_min_markup),
         exchange_params.price_step,
     );
     let close_prices_end = round_up(
-        position.price
-            * (1.0 + bot_params.close_grid_min_markup + bot_params.close_grid_markup_range),
+        position.price * (1.0 + close_grid_min_markup + close_grid_markup_range),
         exchange_params.price_step,
     );
+    if bot_params.close_grid_gap_pct > 0.0
+        && state_params.order_book.bid > close_prices_end * (1.0 + bot_params.close_grid_gap_pct)
+    {
+        // price gapped through the whole ladder: no stale limits far below the market
+        return Order::default();
+    }
     if close_prices_start == close_prices_end {
         return Order {
-            qty: -round_(position.size, exchange_params.qty_step),
+            qty: -calc_full_close_qty(position.size, exchange_params.qty_step),
             price: f64::max(state_params.order_book.ask, close_prices_start),
             order_type: OrderType::CloseGridLong,
         };
@@ -99,8 +224,8 @@ pub fn calc_grid_close_long(
         round_up(
             position.price
                 * (1.0
-                    + bot_params.close_grid_min_markup
-                    + bot_params.close_grid_markup_range * (1.0 - wallet_exposure_ratio)),
+                    + close_grid_min_markup
+                    + close_grid_markup_range * (1.0 - wallet_exposure_ratio)),
             exchange_params.price_step,
         ),
         state_params.order_book.ask,
@@ -120,6 +245,7 @@ pub fn calc_grid_close_long(
     }
 }
 
+#[must_use]
 pub fn calc_trailing_close_long(
     exchange_params: &ExchangeParams,
     state_params: &StateParams,
@@ -127,15 +253,33 @@ pub fn calc_trailing_close_long(
     position: &Position,
     trailing_price_bundle: &TrailingPriceBundle,
 ) -> Order {
+    if !check_side(position, Side::Long) {
+        return Order::default();
+    }
     if position.size == 0.0 {
         return Order::default();
     }
+    if bot_params.close_trailing_retracement_pct > 0.0
+        && bot_params.close_trailing_min_profit_pct > 0.0
+        && trailing_price_bundle.max_since_open
+            < position.price * (1.0 + bot_params.close_trailing_min_profit_pct)
+    {
+        // profit lock not reached, retracements don't count yet
+        return Order {
+            qty: 0.0,
+            price: 0.0,
+            order_type: OrderType::CloseTrailingLong,
+        };
+    }
+    let retracement_pct = calc_close_trailing_retracement_pct(
+        bot_params,
+        trailing_price_bundle.max_since_open / position.price - 1.0,
+    );
     if bot_params.close_trailing_threshold_pct <= 0.0 {
         // means trailing close immediately from pos open
         if bot_params.close_trailing_retracement_pct > 0.0
             && trailing_price_bundle.min_since_max
-                < trailing_price_bundle.max_since_open
-                    * (1.0 - bot_params.close_trailing_retracement_pct)
+                < trailing_price_bundle.max_since_open * (1.0 - retracement_pct)
         {
             Order {
                 qty: -calc_close_qty(
@@ -184,15 +328,13 @@ pub fn calc_trailing_close_long(
             if trailing_price_bundle.max_since_open
                 > position.price * (1.0 + bot_params.close_trailing_threshold_pct)
                 && trailing_price_bundle.min_since_max
-                    < trailing_price_bundle.max_since_open
-                        * (1.0 - bot_params.close_trailing_retracement_pct)
+                    < trailing_price_bundle.max_since_open * (1.0 - retracement_pct)
             {
                 let close_price = f64::max(
                     state_params.order_book.ask,
                     round_up(
                         position.price
-                            * (1.0 + bot_params.close_trailing_threshold_pct
-                                - bot_params.close_trailing_retracement_pct),
+                            * (1.0 + bot_params.close_trailing_threshold_pct - retracement_pct),
                         exchange_params.price_step,
                     ),
                 );
@@ -219,6 +361,118 @@ pub fn calc_trailing_close_long(
     }
 }
 
+/// Trailing close plus a protective stop for the full position at
+/// position.price * (1 - bracket_stop_pct), armed once the highest price since position open
+/// has exceeded the trailing close threshold. No stop if bracket_stop_pct is zero.
+#[must_use]
+pub fn calc_bracket_close_long(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    trailing_price_bundle: &TrailingPriceBundle,
+) -> (Order, Option<Order>) {
+    if !check_side(position, Side::Long) {
+        return (Order::default(), None);
+    }
+    let trailing_close = calc_trailing_close_long(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+    );
+    if position.size == 0.0
+        || bot_params.bracket_stop_pct <= 0.0
+        || trailing_price_bundle.max_since_open
+            <= position.price * (1.0 + bot_params.close_trailing_threshold_pct)
+    {
+        return (trailing_close, None);
+    }
+    let stop = Order {
+        qty: -position.size,
+        price: round_dn(
+            position.price * (1.0 - bot_params.bracket_stop_pct),
+            exchange_params.price_step,
+        ),
+        order_type: OrderType::CloseStopLong,
+    };
+    (trailing_close, Some(stop))
+}
+
+/// Close grid from explicit markups: one order at position.price * (1 + level) per level,
+/// closing the matching fraction of the position. Fractions may sum to less than 1.0,
+/// leaving the rest open.
+#[must_use = "the close orders are only computed, not placed"]
+pub fn calc_grid_close_long_at_levels(
+    position: &Position,
+    levels: &[f64],
+    qtys_pct: &[f64],
+    exchange_params: &ExchangeParams,
+) -> Result<Vec<Order>, CloseLevelsError> {
+    if !check_side(position, Side::Long) {
+        return Ok(Vec::new());
+    }
+    validate_close_levels(levels, qtys_pct)?;
+    if position.size <= 0.0 {
+        return Ok(Vec::new());
+    }
+    let close_prices: Vec<f64> = levels
+        .iter()
+        .map(|level| round_up(position.price * (1.0 + level), exchange_params.price_step))
+        .collect();
+    Ok(
+        split_close_qtys(position.size, &close_prices, qtys_pct, exchange_params)
+            .into_iter()
+            .map(|(qty, price)| Order::new(-qty, price, OrderType::CloseGridLong))
+            .collect(),
+    )
+}
+
+/// Scale out at R-multiples, R being the distance from position.price down to stop_price:
+/// one order at position.price + r * R per target, closing the matching fraction of the
+/// position. Fractions may sum to less than 1.0, leaving the rest open.
+#[must_use = "the close orders are only computed, not placed"]
+pub fn calc_r_multiple_closes_long(
+    position: &Position,
+    stop_price: f64,
+    r_targets: &[f64],
+    qtys_pct: &[f64],
+    exchange_params: &ExchangeParams,
+) -> Result<Vec<Order>, CloseLevelsError> {
+    if !check_side(position, Side::Long) {
+        return Ok(Vec::new());
+    }
+    validate_close_levels(r_targets, qtys_pct)?;
+    if position.size <= 0.0 {
+        return Ok(Vec::new());
+    }
+    if !(stop_price > 0.0 && stop_price < position.price) {
+        return Err(CloseLevelsError::InvalidStopPrice {
+            stop_price,
+            position_price: position.price,
+        });
+    }
+    let risk = position.price - stop_price;
+    let mut close_prices = Vec::with_capacity(r_targets.len());
+    for &r in r_targets {
+        if !(r > 0.0 && r.is_finite()) {
+            return Err(CloseLevelsError::InvalidRMultiple(r));
+        }
+        close_prices.push(round_up(
+            position.price + r * risk,
+            exchange_params.price_step,
+        ));
+    }
+    Ok(
+        split_close_qtys(position.size, &close_prices, qtys_pct, exchange_params)
+            .into_iter()
+            .map(|(qty, price)| Order::new(-qty, price, OrderType::CloseGridLong))
+            .collect(),
+    )
+}
+
+#[must_use]
 pub fn calc_next_close_long(
     exchange_params: &ExchangeParams,
     state_params: &StateParams,
@@ -226,6 +480,9 @@ pub fn calc_next_close_long(
     position: &Position,
     trailing_price_bundle: &TrailingPriceBundle,
 ) -> Order {
+    if !check_side(position, Side::Long) {
+        return Order::default();
+    }
     if position.size == 0.0 {
         // no position
         return Order::default();
@@ -319,26 +576,32 @@ pub fn calc_next_close_long(
     }
 }
 
+#[must_use]
 pub fn calc_grid_close_short(
     exchange_params: &ExchangeParams,
     state_params: &StateParams,
     bot_params: &BotParams,
     position: &Position,
 ) -> Order {
+    if !check_side(position, Side::Short) {
+        return Order::default();
+    }
     let position_size_abs = position.size.abs();
     if position_size_abs == 0.0 {
         return Order::default();
     }
-    if bot_params.close_grid_markup_range <= 0.0
+    let close_grid_min_markup = calc_close_grid_min_markup(exchange_params, bot_params);
+    let close_grid_markup_range = calc_close_grid_markup_range(close_grid_min_markup, bot_params);
+    if close_grid_markup_range <= 0.0
         || bot_params.close_grid_qty_pct < 0.0
         || bot_params.close_grid_qty_pct >= 1.0
     {
         return Order {
-            qty: round_(position_size_abs, exchange_params.qty_step),
+            qty: calc_full_close_qty(position_size_abs, exchange_params.qty_step),
             price: f64::min(
                 state_params.order_book.bid,
                 round_dn(
-                    position.price * (1.0 - bot_params.close_grid_min_markup),
+                    position.price * (1.0 - close_grid_min_markup),
                     exchange_params.price_step,
                 ),
             ),
@@ -346,17 +609,22 @@ pub fn calc_grid_close_short(
         };
     }
     let close_prices_start = round_dn(
-        position.price * (1.0 - bot_params.close_grid_min_markup),
+        position.price * (1.0 - close_grid_min_markup),
         exchange_params.price_step,
     );
     let close_prices_end = round_dn(
-        position.price
-            * (1.0 - bot_params.close_grid_min_markup - bot_params.close_grid_markup_range),
+        position.price * (1.0 - close_grid_min_markup - close_grid_markup_range),
         exchange_params.price_step,
     );
+    if bot_params.close_grid_gap_pct > 0.0
+        && state_params.order_book.ask < close_prices_end * (1.0 - bot_params.close_grid_gap_pct)
+    {
+        // price gapped through the whole ladder: no stale limits far above the market
+        return Order::default();
+    }
     if close_prices_start == close_prices_end {
         return Order {
-            qty: round_(position_size_abs, exchange_params.qty_step),
+            qty: calc_full_close_qty(position_size_abs, exchange_params.qty_step),
             price: f64::min(state_params.order_book.bid, close_prices_start),
             order_type: OrderType::CloseGridShort,
         };
@@ -374,8 +642,8 @@ pub fn calc_grid_close_short(
         round_dn(
             position.price
                 * (1.0
-                    - bot_params.close_grid_min_markup
-                    - bot_params.close_grid_markup_range * (1.0 - wallet_exposure_ratio)),
+                    - close_grid_min_markup
+                    - close_grid_markup_range * (1.0 - wallet_exposure_ratio)),
             exchange_params.price_step,
         ),
         state_params.order_book.bid,
@@ -395,6 +663,7 @@ pub fn calc_grid_close_short(
     }
 }
 
+#[must_use]
 pub fn calc_trailing_close_short(
     exchange_params: &ExchangeParams,
     state_params: &StateParams,
@@ -402,16 +671,34 @@ pub fn calc_trailing_close_short(
     position: &Position,
     trailing_price_bundle: &TrailingPriceBundle,
 ) -> Order {
+    if !check_side(position, Side::Short) {
+        return Order::default();
+    }
     let position_size_abs = position.size.abs();
     if position_size_abs == 0.0 {
         return Order::default();
     }
+    if bot_params.close_trailing_retracement_pct > 0.0
+        && bot_params.close_trailing_min_profit_pct > 0.0
+        && trailing_price_bundle.min_since_open
+            > position.price * (1.0 - bot_params.close_trailing_min_profit_pct)
+    {
+        // profit lock not reached, retracements don't count yet
+        return Order {
+            qty: 0.0,
+            price: 0.0,
+            order_type: OrderType::CloseTrailingShort,
+        };
+    }
+    let retracement_pct = calc_close_trailing_retracement_pct(
+        bot_params,
+        1.0 - trailing_price_bundle.min_since_open / position.price,
+    );
     if bot_params.close_trailing_threshold_pct <= 0.0 {
         // means trailing stop immediately from pos open
         if bot_params.close_trailing_retracement_pct > 0.0
             && trailing_price_bundle.max_since_min
-                > trailing_price_bundle.min_since_open
-                    * (1.0 + bot_params.close_trailing_retracement_pct)
+                > trailing_price_bundle.min_since_open * (1.0 + retracement_pct)
         {
             Order {
                 qty: calc_close_qty(
@@ -459,15 +746,13 @@ pub fn calc_trailing_close_short(
             if trailing_price_bundle.min_since_open
                 < position.price * (1.0 - bot_params.close_trailing_threshold_pct)
                 && trailing_price_bundle.max_since_min
-                    > trailing_price_bundle.min_since_open
-                        * (1.0 + bot_params.close_trailing_retracement_pct)
+                    > trailing_price_bundle.min_since_open * (1.0 + retracement_pct)
             {
                 let close_price = f64::min(
                     state_params.order_book.bid,
                     round_dn(
                         position.price
-                            * (1.0 - bot_params.close_trailing_threshold_pct
-                                + bot_params.close_trailing_retracement_pct),
+                            * (1.0 - bot_params.close_trailing_threshold_pct + retracement_pct),
                         exchange_params.price_step,
                     ),
                 );
@@ -494,6 +779,177 @@ pub fn calc_trailing_close_short(
     }
 }
 
+/// Trailing close plus a protective stop for the full position at
+/// position.price * (1 + bracket_stop_pct), armed once the lowest price since position open
+/// has fallen below the trailing close threshold. No stop if bracket_stop_pct is zero.
+#[must_use]
+pub fn calc_bracket_close_short(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    trailing_price_bundle: &TrailingPriceBundle,
+) -> (Order, Option<Order>) {
+    if !check_side(position, Side::Short) {
+        return (Order::default(), None);
+    }
+    let trailing_close = calc_trailing_close_short(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+    );
+    if position.size == 0.0
+        || bot_params.bracket_stop_pct <= 0.0
+        || trailing_price_bundle.min_since_open
+            >= position.price * (1.0 - bot_params.close_trailing_threshold_pct)
+    {
+        return (trailing_close, None);
+    }
+    let stop = Order {
+        qty: position.size.abs(),
+        price: round_up(
+            position.price * (1.0 + bot_params.bracket_stop_pct),
+            exchange_params.price_step,
+        ),
+        order_type: OrderType::CloseStopShort,
+    };
+    (trailing_close, Some(stop))
+}
+
+/// Close grid from explicit markups: one order at position.price * (1 - level) per level,
+/// closing the matching fraction of the position. Fractions may sum to less than 1.0,
+/// leaving the rest open.
+#[must_use = "the close orders are only computed, not placed"]
+pub fn calc_grid_close_short_at_levels(
+    position: &Position,
+    levels: &[f64],
+    qtys_pct: &[f64],
+    exchange_params: &ExchangeParams,
+) -> Result<Vec<Order>, CloseLevelsError> {
+    if !check_side(position, Side::Short) {
+        return Ok(Vec::new());
+    }
+    validate_close_levels(levels, qtys_pct)?;
+    if position.size >= 0.0 {
+        return Ok(Vec::new());
+    }
+    let close_prices: Vec<f64> = levels
+        .iter()
+        .map(|level| round_dn(position.price * (1.0 - level), exchange_params.price_step))
+        .collect();
+    Ok(split_close_qtys(
+        position.size.abs(),
+        &close_prices,
+        qtys_pct,
+        exchange_params,
+    )
+    .into_iter()
+    .map(|(qty, price)| Order::new(qty, price, OrderType::CloseGridShort))
+    .collect())
+}
+
+/// Scale out at R-multiples, R being the distance from position.price up to stop_price:
+/// one order at position.price - r * R per target, closing the matching fraction of the
+/// position. Fractions may sum to less than 1.0, leaving the rest open.
+#[must_use = "the close orders are only computed, not placed"]
+pub fn calc_r_multiple_closes_short(
+    position: &Position,
+    stop_price: f64,
+    r_targets: &[f64],
+    qtys_pct: &[f64],
+    exchange_params: &ExchangeParams,
+) -> Result<Vec<Order>, CloseLevelsError> {
+    if !check_side(position, Side::Short) {
+        return Ok(Vec::new());
+    }
+    validate_close_levels(r_targets, qtys_pct)?;
+    if position.size >= 0.0 {
+        return Ok(Vec::new());
+    }
+    if !(stop_price > position.price && stop_price.is_finite()) {
+        return Err(CloseLevelsError::InvalidStopPrice {
+            stop_price,
+            position_price: position.price,
+        });
+    }
+    let risk = stop_price - position.price;
+    let mut close_prices = Vec::with_capacity(r_targets.len());
+    for &r in r_targets {
+        let close_price = round_dn(position.price - r * risk, exchange_params.price_step);
+        if !(r > 0.0 && close_price > 0.0) {
+            return Err(CloseLevelsError::InvalidRMultiple(r));
+        }
+        close_prices.push(close_price);
+    }
+    Ok(split_close_qtys(
+        position.size.abs(),
+        &close_prices,
+        qtys_pct,
+        exchange_params,
+    )
+    .into_iter()
+    .map(|(qty, price)| Order::new(qty, price, OrderType::CloseGridShort))
+    .collect())
+}
+
+fn validate_close_levels(levels: &[f64], qtys_pct: &[f64]) -> Result<(), CloseLevelsError> {
+    if levels.len() != qtys_pct.len() {
+        return Err(CloseLevelsError::LengthMismatch {
+            n_levels: levels.len(),
+            n_qtys_pct: qtys_pct.len(),
+        });
+    }
+    if let Some(&qty_pct) = qtys_pct
+        .iter()
+        .find(|&&qty_pct| qty_pct.is_nan() || qty_pct < 0.0)
+    {
+        return Err(CloseLevelsError::InvalidQtyPct(qty_pct));
+    }
+    let sum: f64 = qtys_pct.iter().sum();
+    // tolerance for fractions like [0.1; 10] not summing to exactly 1.0
+    if sum > 1.0 + 1e-9 {
+        return Err(CloseLevelsError::QtyPctSumAboveOne(sum));
+    }
+    Ok(())
+}
+
+fn split_close_qtys(
+    position_size_abs: f64,
+    close_prices: &[f64],
+    qtys_pct: &[f64],
+    exchange_params: &ExchangeParams,
+) -> Vec<(f64, f64)> {
+    // returns (abs qty, price) per level with nonzero qty
+    let mut remaining = calc_full_close_qty(position_size_abs, exchange_params.qty_step);
+    let mut closes = Vec::with_capacity(close_prices.len());
+    for (&close_price, &qty_pct) in close_prices.iter().zip(qtys_pct) {
+        if remaining <= 0.0 {
+            break;
+        }
+        if qty_pct == 0.0 {
+            continue;
+        }
+        let min_entry_qty = calc_min_entry_qty(close_price, exchange_params);
+        let mut close_qty = f64::min(
+            remaining,
+            f64::max(
+                min_entry_qty,
+                round_(position_size_abs * qty_pct, exchange_params.qty_step),
+            ),
+        );
+        if remaining - close_qty < min_entry_qty {
+            // don't leave a remainder too small to close
+            close_qty = remaining;
+        }
+        remaining = round_(remaining - close_qty, exchange_params.qty_step);
+        closes.push((close_qty, close_price));
+    }
+    closes
+}
+
+#[must_use]
 pub fn calc_next_close_short(
     exchange_params: &ExchangeParams,
     state_params: &StateParams,
@@ -501,6 +957,9 @@ pub fn calc_next_close_short(
     position: &Position,
     trailing_price_bundle: &TrailingPriceBundle,
 ) -> Order {
+    if !check_side(position, Side::Short) {
+        return Order::default();
+    }
     let position_size_abs = position.size.abs();
     if position_size_abs == 0.0 {
         // no position
@@ -599,6 +1058,7 @@ pub fn calc_next_close_short(
     }
 }
 
+#[must_use]
 pub fn calc_closes_long(
     exchange_params: &ExchangeParams,
     state_params: &StateParams,
@@ -606,6 +1066,9 @@ pub fn calc_closes_long(
     position: &Position,
     trailing_price_bundle: &TrailingPriceBundle,
 ) -> Vec<Order> {
+    if !check_side(position, Side::Long) {
+        return Vec::new();
+    }
     let mut closes = Vec::<Order>::new();
     let mut psize = position.size;
     let mut ask = state_params.order_book.ask;
@@ -634,23 +1097,25 @@ pub fn calc_closes_long(
             }
             if closes[closes.len() - 1].price == close.price {
                 let previous_close = closes.pop();
-                let merged_close = Order {
-                    qty: round_(
-                        previous_close.unwrap().qty + close.qty,
-                        exchange_params.qty_step,
-                    ),
-                    price: close.price,
-                    order_type: close.order_type,
-                };
+                let merged_close = close.with_qty(round_(
+                    previous_close.unwrap().qty + close.qty,
+                    exchange_params.qty_step,
+                ));
                 closes.push(merged_close);
                 continue;
             }
         }
         closes.push(close);
     }
+    if bot_params.coalesce_grid_orders {
+        closes = coalesce_orders(&closes, exchange_params);
+    }
+    closes = apply_price_band(&closes, &state_params.order_book, exchange_params);
+    debug_assert_signs(&closes);
     closes
 }
 
+#[must_use]
 pub fn calc_closes_short(
     exchange_params: &ExchangeParams,
     state_params: &StateParams,
@@ -658,6 +1123,9 @@ pub fn calc_closes_short(
     position: &Position,
     trailing_price_bundle: &TrailingPriceBundle,
 ) -> Vec<Order> {
+    if !check_side(position, Side::Short) {
+        return Vec::new();
+    }
     let mut closes = Vec::<Order>::new();
     let mut psize = position.size;
     let mut bid = state_params.order_book.bid;
@@ -686,19 +1154,1296 @@ pub fn calc_closes_short(
             }
             if closes[closes.len() - 1].price == close.price {
                 let previous_close = closes.pop();
-                let merged_close = Order {
-                    qty: round_(
-                        previous_close.unwrap().qty + close.qty,
-                        exchange_params.qty_step,
-                    ),
-                    price: close.price,
-                    order_type: close.order_type,
-                };
+                let merged_close = close.with_qty(round_(
+                    previous_close.unwrap().qty + close.qty,
+                    exchange_params.qty_step,
+                ));
                 closes.push(merged_close);
                 continue;
             }
         }
         closes.push(close);
     }
+    if bot_params.coalesce_grid_orders {
+        closes = coalesce_orders(&closes, exchange_params);
+    }
+    closes = apply_price_band(&closes, &state_params.order_book, exchange_params);
+    debug_assert_signs(&closes);
     closes
 }
+
+/// Unstuck close at the upper EMA band shifted by unstuck_ema_dist, or the ask if higher,
+/// sized at unstuck_close_pct of the full position and shrunk so the loss it realizes stays
+/// within unstuck_allowance. Does not check whether the position is stuck.
+#[must_use]
+pub fn calc_unstuck_close_long(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    unstuck_allowance: f64,
+) -> Order {
+    if !check_side(position, Side::Long) {
+        return Order::default();
+    }
+    if position.size <= 0.0 || unstuck_allowance <= 0.0 {
+        return Order::default();
+    }
+    let close_price = f64::max(
+        state_params.order_book.ask,
+        round_up(
+            state_params.ema_bands.upper * (1.0 + bot_params.unstuck_ema_dist),
+            exchange_params.price_step,
+        ),
+    );
+    let close_qty = calc_unstuck_close_qty(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        close_price,
+        unstuck_allowance,
+        calc_pnl_long,
+    );
+    Order::new(-close_qty, close_price, OrderType::CloseUnstuckLong)
+}
+
+/// Unstuck close at the lower EMA band shifted by unstuck_ema_dist, or the bid if lower,
+/// sized at unstuck_close_pct of the full position and shrunk so the loss it realizes stays
+/// within unstuck_allowance. Does not check whether the position is stuck.
+#[must_use]
+pub fn calc_unstuck_close_short(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    unstuck_allowance: f64,
+) -> Order {
+    if !check_side(position, Side::Short) {
+        return Order::default();
+    }
+    if position.size >= 0.0 || unstuck_allowance <= 0.0 {
+        return Order::default();
+    }
+    let close_price = f64::min(
+        state_params.order_book.bid,
+        round_dn(
+            state_params.ema_bands.lower * (1.0 - bot_params.unstuck_ema_dist),
+            exchange_params.price_step,
+        ),
+    );
+    let close_qty = calc_unstuck_close_qty(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        close_price,
+        unstuck_allowance,
+        calc_pnl_short,
+    );
+    Order::new(close_qty, close_price, OrderType::CloseUnstuckShort)
+}
+
+fn calc_unstuck_close_qty(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    close_price: f64,
+    unstuck_allowance: f64,
+    calc_pnl: fn(f64, f64, f64, f64) -> f64,
+) -> f64 {
+    // abs qty, same sizing as the backtester's unstucking close
+    let position_size_abs = position.size.abs();
+    let min_entry_qty = calc_min_entry_qty(close_price, exchange_params);
+    let close_qty = f64::min(
+        position_size_abs,
+        f64::max(
+            min_entry_qty,
+            round_dn(
+                cost_to_qty(
+                    state_params.balance
+                        * bot_params.wallet_exposure_limit
+                        * bot_params.unstuck_close_pct,
+                    close_price,
+                    exchange_params.c_mult,
+                ),
+                exchange_params.qty_step,
+            ),
+        ),
+    );
+    let pnl_if_closed = calc_pnl(
+        position.price,
+        close_price,
+        close_qty,
+        exchange_params.c_mult,
+    );
+    if pnl_if_closed < 0.0 && pnl_if_closed.abs() > unstuck_allowance {
+        // shrink to fit the allowance
+        f64::min(
+            position_size_abs,
+            f64::max(
+                min_entry_qty,
+                round_dn(
+                    close_qty * (unstuck_allowance / pnl_if_closed.abs()),
+                    exchange_params.qty_step,
+                ),
+            ),
+        )
+    } else {
+        close_qty
+    }
+}
+
+/// One open position considered for unstucking, with the market state to evaluate it at.
+#[derive(Debug, Clone)]
+pub struct UnstuckCandidate {
+    pub side: Side,
+    pub position: Position,
+    pub exchange_params: ExchangeParams,
+    pub state_params: StateParams,
+}
+
+/// Picks at most one position across the portfolio to unstuck and returns its index with the
+/// unstuck close. Positions qualify when wallet_exposure / wallet_exposure_limit exceeds
+/// unstuck_threshold; the one whose unstuck close price is relatively nearest its position
+/// price wins, ties going to the lowest index. bot_params is indexed like candidates.
+/// unstuck_allowance is the loss allowed across the portfolio, e.g. from
+/// calc_auto_unstuck_allowance; None if it is used up.
+pub fn select_unstuck_candidate(
+    candidates: &[UnstuckCandidate],
+    bot_params: &[BotParams],
+    unstuck_allowance: f64,
+) -> Option<(usize, Order)> {
+    if unstuck_allowance <= 0.0 {
+        return None;
+    }
+    let mut stuck: Vec<(usize, Order, f64)> = Vec::new();
+    for (idx, (candidate, bot_params)) in candidates.iter().zip(bot_params).enumerate() {
+        let position = &candidate.position;
+        if position.size == 0.0 || bot_params.wallet_exposure_limit <= 0.0 {
+            continue;
+        }
+        let wallet_exposure = calc_wallet_exposure(
+            candidate.exchange_params.c_mult,
+            candidate.state_params.balance,
+            position.size,
+            position.price,
+        );
+        if wallet_exposure / bot_params.wallet_exposure_limit <= bot_params.unstuck_threshold {
+            continue;
+        }
+        let (pside, close) = match candidate.side {
+            Side::Long => (
+                LONG,
+                calc_unstuck_close_long(
+                    &candidate.exchange_params,
+                    &candidate.state_params,
+                    bot_params,
+                    position,
+                    unstuck_allowance,
+                ),
+            ),
+            Side::Short => (
+                SHORT,
+                calc_unstuck_close_short(
+                    &candidate.exchange_params,
+                    &candidate.state_params,
+                    bot_params,
+                    position,
+                    unstuck_allowance,
+                ),
+            ),
+        };
+        if close.qty != 0.0 {
+            let distance = calc_pprice_diff_int(pside, position.price, close.price);
+            stuck.push((idx, close, distance));
+        }
+    }
+    // stable sort keeps index order among equal distances
+    stuck.sort_by(|a, b| a.2.total_cmp(&b.2));
+    stuck.into_iter().next().map(|(idx, close, _)| (idx, close))
+}
+
+/// Kill switch intent: cancel every resting order, then send closes as immediate-or-cancel.
+#[derive(Debug, Clone, Default)]
+pub struct PanicOrders {
+    pub closes: Vec<(String, Order)>, // symbol and its full close
+    pub cancel_all: bool,
+}
+
+/// Reduce-only closes for every open position, priced ticks_through price steps past the
+/// opposite side of the book so they execute at once; the live layer sends them as IOC.
+/// Positions below min_qty are closed too, as exchanges accept reduce-only dust. Uses the
+/// CloseStop order types. In hedge mode a symbol may appear once per side.
+pub fn calc_panic_orders(
+    positions: &[(String, Position, OrderBook, ExchangeParams)],
+    ticks_through: u32,
+) -> PanicOrders {
+    let mut closes = Vec::new();
+    for (symbol, position, order_book, exchange_params) in positions {
+        let close_qty = calc_full_close_qty(position.size, exchange_params.qty_step);
+        if close_qty == 0.0 {
+            continue;
+        }
+        let offset = ticks_through as f64 * exchange_params.price_step;
+        let close = if position.size > 0.0 {
+            Order::new(
+                -close_qty,
+                f64::max(
+                    exchange_params.price_step,
+                    round_dn(order_book.bid - offset, exchange_params.price_step),
+                ),
+                OrderType::CloseStopLong,
+            )
+        } else {
+            Order::new(
+                close_qty,
+                round_up(order_book.ask + offset, exchange_params.price_step),
+                OrderType::CloseStopShort,
+            )
+        };
+        closes.push((symbol.clone(), close));
+    }
+    PanicOrders {
+        closes,
+        cancel_all: true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::utils::qty_to_cost;
+
+    #[test]
+    fn full_close_qty_is_on_qty_step() {
+        // off the grid by float error only: the whole position, rounded
+        assert_eq!(calc_full_close_qty(0.1 + 0.2, 0.1), 0.3);
+        assert_eq!(calc_full_close_qty(-0.5, 0.001), 0.5);
+        // genuinely off the grid: rounded down rather than over-closing
+        assert_eq!(calc_full_close_qty(0.0126, 0.001), 0.012);
+        assert_eq!(calc_full_close_qty(-0.0124, 0.001), 0.012);
+        assert_eq!(calc_full_close_qty(0.0004, 0.001), 0.0);
+    }
+
+    #[test]
+    fn full_grid_close_never_over_closes() {
+        let exchange_params = fixtures::exchange_params();
+        let bot_params = BotParams {
+            close_grid_qty_pct: 1.0,
+            ..fixtures::bot_params()
+        };
+        let state_params = fixtures::state_params(60000.0);
+        for (size, expected) in [(0.1 + 0.2, 0.3), (0.0126, 0.012)] {
+            let long = Position {
+                size,
+                price: 60000.0,
+            };
+            let close = calc_grid_close_long(&exchange_params, &state_params, &bot_params, &long);
+            assert_eq!(close.qty, -expected);
+            let short = Position {
+                size: -size,
+                price: 60000.0,
+            };
+            let close = calc_grid_close_short(&exchange_params, &state_params, &bot_params, &short);
+            assert_eq!(close.qty, expected);
+        }
+    }
+
+    #[test]
+    fn close_qty_leaving_dust_closes_in_full() {
+        // min entry qty at 60000 is 0.002
+        let exchange_params = ExchangeParams {
+            min_cost: 100.0,
+            ..fixtures::exchange_params()
+        };
+        let bot_params = fixtures::bot_params();
+        // 0.016 off by float error; a 90% close of full_psize (0.016) leaves 0.001
+        let position = Position {
+            size: 0.1 + 0.2 - 0.284,
+            price: 60000.0,
+        };
+        let close_qty = calc_close_qty(
+            &exchange_params,
+            &bot_params,
+            &position,
+            0.9,
+            10000.0,
+            60000.0,
+        );
+        assert_eq!(close_qty, 0.016);
+    }
+
+    #[test]
+    fn close_grid_raised_above_fee_adjusted_breakeven() {
+        let bot_params = BotParams {
+            close_grid_min_markup: 0.0001,
+            close_grid_markup_range: 0.0,
+            close_min_net_markup: 0.001,
+            ..fixtures::bot_params()
+        };
+        let no_fees = ExchangeParams {
+            fee_rate: 0.0,
+            ..fixtures::exchange_params()
+        };
+        // without fees or a net markup the configured markup stands
+        let plain = BotParams {
+            close_min_net_markup: 0.0,
+            ..bot_params.clone()
+        };
+        assert_eq!(calc_close_grid_min_markup(&no_fees, &plain), 0.0001);
+        // 2 * 0.0002 fees + 0.001 net markup
+        let exchange_params = fixtures::exchange_params();
+        let min_markup = calc_close_grid_min_markup(&exchange_params, &bot_params);
+        assert!((min_markup - 0.0014).abs() < 1e-12);
+        // a configured markup above breakeven is kept
+        let wide = BotParams {
+            close_grid_min_markup: 0.01,
+            ..bot_params.clone()
+        };
+        assert_eq!(calc_close_grid_min_markup(&exchange_params, &wide), 0.01);
+
+        let state_params = fixtures::state_params(60000.0);
+        let long = Position {
+            size: 0.01,
+            price: 60000.0,
+        };
+        let close = calc_grid_close_long(&exchange_params, &state_params, &bot_params, &long);
+        assert_eq!(close.price, 60084.0);
+        let short = Position {
+            size: -0.01,
+            price: 60000.0,
+        };
+        let close = calc_grid_close_short(&exchange_params, &state_params, &bot_params, &short);
+        assert_eq!(close.price, 59916.0);
+    }
+
+    #[test]
+    fn close_grid_dropped_when_price_gaps_through_it() {
+        let exchange_params = ExchangeParams {
+            fee_rate: 0.0,
+            ..fixtures::exchange_params()
+        };
+        // ladder from 60600 to 61800 long, 59400 to 58200 short
+        let bot_params = BotParams {
+            close_grid_min_markup: 0.01,
+            close_grid_markup_range: 0.02,
+            close_grid_qty_pct: 0.5,
+            close_grid_gap_pct: 0.01,
+            ..fixtures::bot_params()
+        };
+        let long = Position {
+            size: 0.01,
+            price: 60000.0,
+        };
+        let short = Position {
+            size: -0.01,
+            price: 60000.0,
+        };
+        let trailing_price_bundle = TrailingPriceBundle::default();
+        // long gap threshold 61800 * 1.01 = 62418
+        for (bid, gapped) in [(62400.0, false), (62500.0, true)] {
+            let state_params = fixtures::state_params(bid);
+            let close = calc_grid_close_long(&exchange_params, &state_params, &bot_params, &long);
+            let closes = calc_closes_long(
+                &exchange_params,
+                &state_params,
+                &bot_params,
+                &long,
+                &trailing_price_bundle,
+            );
+            if gapped {
+                assert_eq!(close, Order::default());
+                assert!(closes.is_empty());
+            } else {
+                assert_eq!(close.price, bid);
+                assert!(close.qty < 0.0);
+                assert!(!closes.is_empty());
+            }
+        }
+        // short gap threshold 58200 * 0.99 = 57618
+        for (ask, gapped) in [(57700.0, false), (57600.0, true)] {
+            let state_params = fixtures::state_params(ask);
+            let close = calc_grid_close_short(&exchange_params, &state_params, &bot_params, &short);
+            let closes = calc_closes_short(
+                &exchange_params,
+                &state_params,
+                &bot_params,
+                &short,
+                &trailing_price_bundle,
+            );
+            if gapped {
+                assert_eq!(close, Order::default());
+                assert!(closes.is_empty());
+            } else {
+                assert_eq!(close.price, ask);
+                assert!(close.qty > 0.0);
+                assert!(!closes.is_empty());
+            }
+        }
+        // no gap handling when disabled
+        let disabled = BotParams {
+            close_grid_gap_pct: 0.0,
+            ..bot_params
+        };
+        let state_params = fixtures::state_params(70000.0);
+        assert!(calc_grid_close_long(&exchange_params, &state_params, &disabled, &long).qty < 0.0);
+    }
+
+    #[test]
+    fn bracket_stop_armed_past_trailing_threshold() {
+        let exchange_params = fixtures::exchange_params();
+        let state_params = fixtures::state_params(60000.0);
+        let bot_params = BotParams {
+            close_trailing_threshold_pct: 0.01,
+            close_trailing_retracement_pct: 0.005,
+            bracket_stop_pct: 0.02,
+            ..fixtures::bot_params()
+        };
+        let long = Position {
+            size: 0.01,
+            price: 60001.0,
+        };
+        let short = Position {
+            size: -0.01,
+            ..long
+        };
+        let stops = |bot_params: &BotParams, max_since_open: f64, min_since_open: f64| {
+            let trailing_price_bundle = TrailingPriceBundle {
+                max_since_open,
+                min_since_open,
+                ..Default::default()
+            };
+            (
+                calc_bracket_close_long(
+                    &exchange_params,
+                    &state_params,
+                    bot_params,
+                    &long,
+                    &trailing_price_bundle,
+                )
+                .1,
+                calc_bracket_close_short(
+                    &exchange_params,
+                    &state_params,
+                    bot_params,
+                    &short,
+                    &trailing_price_bundle,
+                )
+                .1,
+            )
+        };
+        // thresholds at 60601.01 and 59400.99
+        assert_eq!(stops(&bot_params, 60601.0, 59401.0), (None, None));
+        // 60001 * 0.98 = 58800.98 and 60001 * 1.02 = 61201.02, rounded away from the market
+        assert_eq!(
+            stops(&bot_params, 60602.0, 59400.0),
+            (
+                Some(Order {
+                    qty: -0.01,
+                    price: 58800.9,
+                    order_type: OrderType::CloseStopLong,
+                }),
+                Some(Order {
+                    qty: 0.01,
+                    price: 61201.1,
+                    order_type: OrderType::CloseStopShort,
+                })
+            )
+        );
+        let no_stop = BotParams {
+            bracket_stop_pct: 0.0,
+            ..bot_params.clone()
+        };
+        assert_eq!(stops(&no_stop, 60602.0, 59400.0), (None, None));
+    }
+
+    #[test]
+    fn close_qty_scales_with_c_mult() {
+        let bot_params = BotParams {
+            wallet_exposure_limit: 1.0,
+            close_grid_min_markup: 0.01,
+            close_grid_markup_range: 0.01,
+            close_grid_qty_pct: 0.25,
+            ..Default::default()
+        };
+        let state_params = StateParams {
+            balance: 10000.0,
+            order_book: OrderBook {
+                bid: 100.0,
+                ask: 100.0,
+            },
+            ..Default::default()
+        };
+        // (c_mult, qty at full exposure of 10000.0 at price 100.0)
+        for (c_mult, full_qty) in [(0.001, 100000.0), (10.0, 10.0)] {
+            let exchange_params = ExchangeParams {
+                qty_step: 0.001,
+                price_step: 0.01,
+                min_qty: 0.001,
+                min_cost: 5.0,
+                c_mult,
+                ..Default::default()
+            };
+            let position = Position {
+                size: full_qty,
+                price: 100.0,
+            };
+            // a quarter of the full position, at the low end of the markup range when full
+            let close =
+                calc_grid_close_long(&exchange_params, &state_params, &bot_params, &position);
+            assert_eq!((close.qty, close.price), (-full_qty * 0.25, 101.0));
+            assert!((qty_to_cost(-close.qty, close.price, c_mult) - 2525.0).abs() < 1e-9);
+            let short = Position {
+                size: -full_qty,
+                price: 100.0,
+            };
+            let close = calc_grid_close_short(&exchange_params, &state_params, &bot_params, &short);
+            assert_eq!((close.qty, close.price), (full_qty * 0.25, 99.0));
+            // 20% over the limit: the excess closes on top of the quarter
+            let over = Position {
+                size: full_qty * 1.2,
+                price: 100.0,
+            };
+            assert_eq!(
+                calc_close_qty(&exchange_params, &bot_params, &over, 0.25, 10000.0, 101.0),
+                full_qty * 0.45
+            );
+        }
+    }
+
+    #[test]
+    fn close_ladder_at_custom_levels() {
+        let exchange_params = fixtures::exchange_params();
+        let long = Position {
+            size: 0.1,
+            price: 60000.0,
+        };
+        let short = Position { size: -0.1, ..long };
+        let levels = [0.01, 0.02, 0.03];
+        assert_eq!(
+            calc_grid_close_long_at_levels(&long, &levels, &[0.3, 0.3, 0.4], &exchange_params),
+            Ok(vec![
+                Order::new(-0.03, 60600.0, OrderType::CloseGridLong),
+                Order::new(-0.03, 61200.0, OrderType::CloseGridLong),
+                Order::new(-0.04, 61800.0, OrderType::CloseGridLong),
+            ])
+        );
+        // fractions below 1.0 leave the rest of the position open; zero fractions are skipped
+        assert_eq!(
+            calc_grid_close_short_at_levels(&short, &levels, &[0.25, 0.0, 0.25], &exchange_params),
+            Ok(vec![
+                Order::new(0.025, 59400.0, OrderType::CloseGridShort),
+                Order::new(0.025, 58200.0, OrderType::CloseGridShort),
+            ])
+        );
+        // fractions that sum to 1.0 only up to float error are accepted
+        assert_eq!(
+            calc_grid_close_long_at_levels(&long, &[0.01; 10], &[0.1; 10], &exchange_params)
+                .unwrap()
+                .len(),
+            10
+        );
+        assert_eq!(
+            calc_grid_close_long_at_levels(
+                &Position::default(),
+                &levels,
+                &[0.3; 3],
+                &exchange_params
+            ),
+            Ok(Vec::new())
+        );
+    }
+
+    #[test]
+    fn close_ladder_rejects_invalid_fractions() {
+        let exchange_params = fixtures::exchange_params();
+        let long = Position {
+            size: 0.1,
+            price: 60000.0,
+        };
+        let short = Position { size: -0.1, ..long };
+        let cases = [
+            (
+                vec![0.3, 0.3],
+                CloseLevelsError::LengthMismatch {
+                    n_levels: 3,
+                    n_qtys_pct: 2,
+                },
+            ),
+            (vec![0.3, -0.1, 0.3], CloseLevelsError::InvalidQtyPct(-0.1)),
+            (
+                vec![0.5, 0.3, 0.3],
+                CloseLevelsError::QtyPctSumAboveOne(1.1),
+            ),
+        ];
+        for (qtys_pct, error) in cases {
+            assert_eq!(
+                calc_grid_close_long_at_levels(
+                    &long,
+                    &[0.01, 0.02, 0.03],
+                    &qtys_pct,
+                    &exchange_params
+                ),
+                Err(error.clone())
+            );
+            assert_eq!(
+                calc_grid_close_short_at_levels(
+                    &short,
+                    &[0.01, 0.02, 0.03],
+                    &qtys_pct,
+                    &exchange_params
+                ),
+                Err(error)
+            );
+        }
+        assert!(matches!(
+            calc_grid_close_long_at_levels(&long, &[0.01], &[f64::NAN], &exchange_params),
+            Err(CloseLevelsError::InvalidQtyPct(qty_pct)) if qty_pct.is_nan()
+        ));
+    }
+
+    #[test]
+    fn r_multiple_ladder_from_stop_distance() {
+        let exchange_params = fixtures::exchange_params();
+        let long = Position {
+            size: 0.1,
+            price: 60000.0,
+        };
+        let short = Position { size: -0.1, ..long };
+        // R is 1000.0 either way
+        assert_eq!(
+            calc_r_multiple_closes_long(
+                &long,
+                59000.0,
+                &[1.0, 2.0, 3.0],
+                &[0.5, 0.25, 0.25],
+                &exchange_params
+            ),
+            Ok(vec![
+                Order::new(-0.05, 61000.0, OrderType::CloseGridLong),
+                Order::new(-0.025, 62000.0, OrderType::CloseGridLong),
+                Order::new(-0.025, 63000.0, OrderType::CloseGridLong),
+            ])
+        );
+        // a third left open for a runner
+        assert_eq!(
+            calc_r_multiple_closes_short(
+                &short,
+                61000.0,
+                &[1.0, 2.0, 3.0],
+                &[0.4, 0.3, 0.0],
+                &exchange_params
+            ),
+            Ok(vec![
+                Order::new(0.04, 59000.0, OrderType::CloseGridShort),
+                Order::new(0.03, 58000.0, OrderType::CloseGridShort),
+            ])
+        );
+    }
+
+    #[test]
+    fn r_multiple_ladder_rejects_bad_stops_and_targets() {
+        let exchange_params = fixtures::exchange_params();
+        let long = Position {
+            size: 0.1,
+            price: 60000.0,
+        };
+        let short = Position { size: -0.1, ..long };
+        for stop_price in [60000.0, 61000.0, 0.0, f64::NAN] {
+            assert!(matches!(
+                calc_r_multiple_closes_long(&long, stop_price, &[1.0], &[1.0], &exchange_params),
+                Err(CloseLevelsError::InvalidStopPrice { position_price, .. })
+                    if position_price == 60000.0
+            ));
+        }
+        for stop_price in [60000.0, 59000.0, f64::INFINITY, f64::NAN] {
+            assert!(matches!(
+                calc_r_multiple_closes_short(&short, stop_price, &[1.0], &[1.0], &exchange_params),
+                Err(CloseLevelsError::InvalidStopPrice { .. })
+            ));
+        }
+        for r in [0.0, -1.0, f64::INFINITY] {
+            assert_eq!(
+                calc_r_multiple_closes_long(
+                    &long,
+                    59000.0,
+                    &[1.0, r],
+                    &[0.5, 0.5],
+                    &exchange_params
+                ),
+                Err(CloseLevelsError::InvalidRMultiple(r))
+            );
+        }
+        // 60 R below a short position is a negative price
+        assert_eq!(
+            calc_r_multiple_closes_short(&short, 61000.0, &[60.0], &[1.0], &exchange_params),
+            Err(CloseLevelsError::InvalidRMultiple(60.0))
+        );
+        // fractions are checked as for custom levels
+        assert_eq!(
+            calc_r_multiple_closes_long(&long, 59000.0, &[1.0, 2.0], &[0.6, 0.6], &exchange_params),
+            Err(CloseLevelsError::QtyPctSumAboveOne(1.2))
+        );
+        assert_eq!(
+            calc_r_multiple_closes_short(&short, 61000.0, &[1.0], &[0.5, 0.5], &exchange_params),
+            Err(CloseLevelsError::LengthMismatch {
+                n_levels: 1,
+                n_qtys_pct: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn full_trailing_close_never_over_closes() {
+        let exchange_params = fixtures::exchange_params();
+        // close everything at 1% profit
+        let bot_params = BotParams {
+            wallet_exposure_limit: 1.0,
+            close_trailing_qty_pct: 1.0,
+            close_trailing_threshold_pct: 0.01,
+            close_trailing_retracement_pct: 0.0,
+            ..fixtures::bot_params()
+        };
+        let state_params = fixtures::state_params(60000.0);
+        let trailing = TrailingPriceBundle::default();
+        for (size, expected) in [(0.1 + 0.2, 0.3), (0.0126, 0.012)] {
+            let long = Position {
+                size,
+                price: 60000.0,
+            };
+            let close = calc_trailing_close_long(
+                &exchange_params,
+                &state_params,
+                &bot_params,
+                &long,
+                &trailing,
+            );
+            assert_eq!((close.qty, close.price), (-expected, 60600.0));
+            let short = Position {
+                size: -size,
+                price: 60000.0,
+            };
+            let close = calc_trailing_close_short(
+                &exchange_params,
+                &state_params,
+                &bot_params,
+                &short,
+                &trailing,
+            );
+            assert_eq!((close.qty, close.price), (expected, 59400.0));
+        }
+    }
+
+    fn unstuck_candidate(side: Side, size: f64, price: f64) -> UnstuckCandidate {
+        UnstuckCandidate {
+            side,
+            position: Position { size, price },
+            exchange_params: fixtures::exchange_params(),
+            state_params: fixtures::state_params(60000.0),
+        }
+    }
+
+    #[test]
+    fn unstuck_selects_the_stuck_position_nearest_its_close() {
+        let bot_params = BotParams {
+            wallet_exposure_limit: 1.0,
+            unstuck_threshold: 0.5,
+            unstuck_ema_dist: 0.0,
+            unstuck_close_pct: 0.1,
+            ..fixtures::bot_params()
+        };
+        // long closes at 60120.0, short at 59880.0
+        let candidates = [
+            unstuck_candidate(Side::Long, 0.1, 66000.0), // 8.9% away
+            unstuck_candidate(Side::Long, 0.1, 63000.0), // 4.6% away
+            unstuck_candidate(Side::Short, -0.1, 57000.0), // 5.1% away
+            // nearest, but at 0.06 exposure not stuck
+            unstuck_candidate(Side::Long, 0.01, 60500.0),
+        ];
+        let all_bot_params = vec![bot_params.clone(); 4];
+        let expected = calc_unstuck_close_long(
+            &candidates[1].exchange_params,
+            &candidates[1].state_params,
+            &bot_params,
+            &candidates[1].position,
+            1000.0,
+        );
+        assert_eq!(
+            expected,
+            Order::new(-0.016, 60120.0, OrderType::CloseUnstuckLong)
+        );
+        assert_eq!(
+            select_unstuck_candidate(&candidates, &all_bot_params, 1000.0),
+            Some((1, expected))
+        );
+        // thresholds are per symbol: the runner up is the short
+        let mut per_symbol = all_bot_params.clone();
+        per_symbol[1].unstuck_threshold = 0.7;
+        let (idx, close) = select_unstuck_candidate(&candidates, &per_symbol, 1000.0).unwrap();
+        assert_eq!((idx, close.order_type), (2, OrderType::CloseUnstuckShort));
+        // equal distances go to the lowest index
+        let tied = [candidates[1].clone(), candidates[1].clone()];
+        assert_eq!(
+            select_unstuck_candidate(&tied, &all_bot_params[..2], 1000.0)
+                .unwrap()
+                .0,
+            0
+        );
+        // an exhausted allowance unsticks nothing
+        assert_eq!(
+            select_unstuck_candidate(&candidates, &all_bot_params, 0.0),
+            None
+        );
+        assert_eq!(
+            select_unstuck_candidate(&candidates, &all_bot_params, -10.0),
+            None
+        );
+    }
+
+    #[test]
+    fn panic_orders_close_both_sides_through_the_book() {
+        let exchange_params = ExchangeParams {
+            price_step: 0.5,
+            min_qty: 0.01,
+            ..fixtures::exchange_params()
+        };
+        let order_book = OrderBook {
+            bid: 60000.0,
+            ask: 60000.5,
+        };
+        let entry = |symbol: &str, size| {
+            (
+                symbol.to_string(),
+                Position {
+                    size,
+                    price: 59000.0,
+                },
+                order_book.clone(),
+                exchange_params.clone(),
+            )
+        };
+        let positions = [
+            entry("BTC", 0.1 + 0.2),
+            entry("ETH", -0.25),
+            entry("SOL", 0.0),
+            // below min_qty, closed anyway
+            entry("DOGE", 0.002),
+            entry("XRP", -0.002),
+            // below qty_step, nothing to close
+            entry("ADA", 0.0004),
+        ];
+        let panic_orders = calc_panic_orders(&positions, 3);
+        assert!(panic_orders.cancel_all);
+        let closes: Vec<(&str, Order)> = panic_orders
+            .closes
+            .iter()
+            .map(|(symbol, close)| (symbol.as_str(), *close))
+            .collect();
+        assert_eq!(
+            closes,
+            [
+                ("BTC", Order::new(-0.3, 59998.5, OrderType::CloseStopLong)),
+                ("ETH", Order::new(0.25, 60002.0, OrderType::CloseStopShort)),
+                (
+                    "DOGE",
+                    Order::new(-0.002, 59998.5, OrderType::CloseStopLong)
+                ),
+                ("XRP", Order::new(0.002, 60002.0, OrderType::CloseStopShort)),
+            ]
+        );
+        // at the book without ticks through; never priced at or below zero
+        let closes = calc_panic_orders(&positions[..2], 0).closes;
+        assert_eq!((closes[0].1.price, closes[1].1.price), (60000.0, 60000.5));
+        let near_zero = OrderBook { bid: 1.0, ask: 1.5 };
+        let closes = calc_panic_orders(
+            &[(
+                "PEPE".to_string(),
+                Position {
+                    size: 10.0,
+                    price: 2.0,
+                },
+                near_zero,
+                exchange_params,
+            )],
+            5,
+        )
+        .closes;
+        assert_eq!(closes[0].1.price, 0.5);
+        // still cancels resting orders when flat
+        let flat = calc_panic_orders(&positions[2..3], 3);
+        assert!(flat.closes.is_empty() && flat.cancel_all);
+    }
+
+    #[test]
+    fn profit_lock_defers_trailing_closes_until_reached() {
+        let exchange_params = fixtures::exchange_params();
+        // trail 1% from position open once 2% profit is locked in
+        let bot_params = BotParams {
+            wallet_exposure_limit: 1.0,
+            close_trailing_qty_pct: 1.0,
+            close_trailing_threshold_pct: 0.0,
+            close_trailing_retracement_pct: 0.01,
+            close_trailing_min_profit_pct: 0.02,
+            ..fixtures::bot_params()
+        };
+        let without_lock = BotParams {
+            close_trailing_min_profit_pct: 0.0,
+            ..bot_params.clone()
+        };
+        let long = Position {
+            size: 0.01,
+            price: 60000.0,
+        };
+        let state_params = fixtures::state_params(60500.0);
+        // lock at 61200.0; every case retraced more than 1% from its high
+        for (max_since_open, expected_qty) in [(61199.9, 0.0), (61200.0, -0.01), (61200.1, -0.01)] {
+            let trailing = TrailingPriceBundle {
+                max_since_open,
+                min_since_max: 60500.0,
+                ..Default::default()
+            };
+            let close = calc_trailing_close_long(
+                &exchange_params,
+                &state_params,
+                &bot_params,
+                &long,
+                &trailing,
+            );
+            assert_eq!(close.qty, expected_qty, "max since open {}", max_since_open);
+            let close = calc_trailing_close_long(
+                &exchange_params,
+                &state_params,
+                &without_lock,
+                &long,
+                &trailing,
+            );
+            assert_eq!((close.qty, close.price), (-0.01, 60500.0));
+        }
+        let short = Position {
+            size: -0.01,
+            price: 60000.0,
+        };
+        let state_params = fixtures::state_params(59500.0);
+        // lock at 58800.0
+        for (min_since_open, expected_qty) in [(58800.1, 0.0), (58800.0, 0.01), (58799.9, 0.01)] {
+            let trailing = TrailingPriceBundle {
+                min_since_open,
+                max_since_min: 59500.0,
+                ..Default::default()
+            };
+            let close = calc_trailing_close_short(
+                &exchange_params,
+                &state_params,
+                &bot_params,
+                &short,
+                &trailing,
+            );
+            assert_eq!(close.qty, expected_qty, "min since open {}", min_since_open);
+            let close = calc_trailing_close_short(
+                &exchange_params,
+                &state_params,
+                &without_lock,
+                &short,
+                &trailing,
+            );
+            assert_eq!((close.qty, close.price), (0.01, 59500.0));
+        }
+    }
+
+    #[test]
+    fn close_grid_top_capped_at_max_markup() {
+        let exchange_params = ExchangeParams {
+            fee_rate: 0.0,
+            ..fixtures::exchange_params()
+        };
+        let bot_params = |close_grid_max_markup: f64| BotParams {
+            close_grid_min_markup: 0.01,
+            close_grid_markup_range: 0.02,
+            close_grid_max_markup,
+            close_grid_qty_pct: 0.5,
+            close_trailing_grid_ratio: 0.0,
+            ..fixtures::bot_params()
+        };
+        let range = |close_grid_max_markup: f64| {
+            calc_close_grid_markup_range(0.01, &bot_params(close_grid_max_markup))
+        };
+        assert_eq!(range(0.0), 0.02);
+        assert_eq!(range(0.04), 0.02);
+        assert!((range(0.025) - 0.015).abs() < 1e-12);
+        assert_eq!(range(0.01), 0.0);
+        assert_eq!(range(0.005), 0.0);
+
+        let state_params = fixtures::state_params(60000.0);
+        let long = Position {
+            size: 0.01,
+            price: 60000.0,
+        };
+        let short = Position {
+            size: -0.01,
+            price: 60000.0,
+        };
+        let closes = |close_grid_max_markup: f64| {
+            let bot_params = bot_params(close_grid_max_markup);
+            let trailing_price_bundle = TrailingPriceBundle::default();
+            (
+                calc_closes_long(
+                    &exchange_params,
+                    &state_params,
+                    &bot_params,
+                    &long,
+                    &trailing_price_bundle,
+                ),
+                calc_closes_short(
+                    &exchange_params,
+                    &state_params,
+                    &bot_params,
+                    &short,
+                    &trailing_price_bundle,
+                ),
+            )
+        };
+        // cap inside the range: the ladder tops out at 60000 * 1.025 instead of 61800
+        let (uncapped_long, uncapped_short) = closes(0.0);
+        let (capped_long, capped_short) = closes(0.025);
+        assert!(uncapped_long.iter().any(|close| close.price > 61500.0));
+        assert!(uncapped_short.iter().any(|close| close.price < 58500.0));
+        assert!(capped_long.len() > 1 && capped_short.len() > 1);
+        assert!(capped_long
+            .iter()
+            .all(|close| (60600.0..=61500.0).contains(&close.price)));
+        assert!(capped_short
+            .iter()
+            .all(|close| (58500.0..=59400.0).contains(&close.price)));
+        assert_eq!(
+            capped_long.iter().map(|close| close.qty).sum::<f64>(),
+            -0.01
+        );
+        // cap below the min markup: the whole position in one close at the min markup
+        assert_eq!(
+            closes(0.005),
+            (
+                vec![Order::new(-0.01, 60600.0, OrderType::CloseGridLong)],
+                vec![Order::new(0.01, 59400.0, OrderType::CloseGridShort)],
+            )
+        );
+    }
+
+    #[test]
+    fn trailing_retracement_tightens_with_profit_down_to_the_floor() {
+        let bot_params = |close_trailing_drawdown_floor_pct: f64| BotParams {
+            close_trailing_retracement_pct: 0.02,
+            close_trailing_drawdown_floor_pct,
+            close_trailing_threshold_pct: 0.0,
+            close_trailing_min_profit_pct: 0.0,
+            ..fixtures::bot_params()
+        };
+        let tightened = bot_params(0.005);
+        for (profit_pct, expected) in [
+            (0.0, 0.02),
+            (0.01, 0.01),
+            // floor reached, and held beyond
+            (0.015, 0.005),
+            (0.05, 0.005),
+            // a position in loss keeps the configured retracement
+            (-0.03, 0.02),
+        ] {
+            let retracement_pct = calc_close_trailing_retracement_pct(&tightened, profit_pct);
+            assert!((retracement_pct - expected).abs() < 1e-12);
+        }
+        // off, or a floor at or above the retracement: disabled
+        for floor_pct in [0.0, 0.02, 0.03] {
+            for profit_pct in [-0.03, 0.01, 0.05] {
+                assert_eq!(
+                    calc_close_trailing_retracement_pct(&bot_params(floor_pct), profit_pct),
+                    0.02
+                );
+            }
+        }
+
+        // 1.5% up, then 0.51% back: only the tightened trailing close fires
+        let exchange_params = fixtures::exchange_params();
+        let long = Position {
+            size: 0.01,
+            price: 60000.0,
+        };
+        let short = Position {
+            size: -0.01,
+            price: 60000.0,
+        };
+        let bundle_long = TrailingPriceBundle {
+            max_since_open: 60900.0,
+            min_since_max: 60590.0,
+            ..Default::default()
+        };
+        let bundle_short = TrailingPriceBundle {
+            min_since_open: 59100.0,
+            max_since_min: 59410.0,
+            ..Default::default()
+        };
+        let state_long = fixtures::state_params(60590.0);
+        let state_short = fixtures::state_params(59410.0);
+        for (bot_params, fires) in [(tightened, true), (bot_params(0.0), false)] {
+            let close_long = calc_trailing_close_long(
+                &exchange_params,
+                &state_long,
+                &bot_params,
+                &long,
+                &bundle_long,
+            );
+            let close_short = calc_trailing_close_short(
+                &exchange_params,
+                &state_short,
+                &bot_params,
+                &short,
+                &bundle_short,
+            );
+            assert_eq!(close_long.qty < 0.0, fires);
+            assert_eq!(close_short.qty > 0.0, fires);
+        }
+    }
+
+    /// A position of the other side: size -0.01 for long calculators, 0.01 for short ones.
+    fn wrong_side(side: Side) -> Position {
+        Position {
+            size: if side == Side::Long { -0.01 } else { 0.01 },
+            price: 60000.0,
+        }
+    }
+
+    // One test per calculator given a position of the other side: check_side panics in debug
+    // builds, release builds get no orders.
+    macro_rules! wrong_side_tests {
+        ($($name:ident: $side:expr, |$position:ident| $calc:expr => $empty:expr;)*) => {
+            $(
+                #[test]
+                #[cfg_attr(debug_assertions, should_panic(expected = "with the wrong sign"))]
+                fn $name() {
+                    let $position = &wrong_side($side);
+                    assert_eq!($calc, $empty);
+                }
+            )*
+        };
+    }
+
+    wrong_side_tests! {
+        wrong_side_grid_close_long: Side::Long, |position| calc_grid_close_long(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+        ) => Order::default();
+        wrong_side_trailing_close_long: Side::Long, |position| calc_trailing_close_long(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+            &TrailingPriceBundle::default(),
+        ) => Order::default();
+        wrong_side_next_close_long: Side::Long, |position| calc_next_close_long(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+            &TrailingPriceBundle::default(),
+        ) => Order::default();
+        wrong_side_bracket_close_long: Side::Long, |position| calc_bracket_close_long(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+            &TrailingPriceBundle::default(),
+        ) => (Order::default(), None);
+        wrong_side_closes_long: Side::Long, |position| calc_closes_long(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+            &TrailingPriceBundle::default(),
+        ) => Vec::<Order>::new();
+        wrong_side_unstuck_close_long: Side::Long, |position| calc_unstuck_close_long(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+            100.0,
+        ) => Order::default();
+        wrong_side_grid_close_long_at_levels: Side::Long, |position| {
+            calc_grid_close_long_at_levels(
+                position,
+                &[0.01, 0.02],
+                &[0.5, 0.5],
+                &fixtures::exchange_params(),
+            )
+        } => Ok(Vec::new());
+        wrong_side_r_multiple_closes_long: Side::Long, |position| {
+            calc_r_multiple_closes_long(
+                position,
+                59000.0,
+                &[1.0, 2.0],
+                &[0.5, 0.5],
+                &fixtures::exchange_params(),
+            )
+        } => Ok(Vec::new());
+        wrong_side_grid_close_short: Side::Short, |position| calc_grid_close_short(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+        ) => Order::default();
+        wrong_side_trailing_close_short: Side::Short, |position| calc_trailing_close_short(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+            &TrailingPriceBundle::default(),
+        ) => Order::default();
+        wrong_side_next_close_short: Side::Short, |position| calc_next_close_short(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+            &TrailingPriceBundle::default(),
+        ) => Order::default();
+        wrong_side_bracket_close_short: Side::Short, |position| calc_bracket_close_short(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+            &TrailingPriceBundle::default(),
+        ) => (Order::default(), None);
+        wrong_side_closes_short: Side::Short, |position| calc_closes_short(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+            &TrailingPriceBundle::default(),
+        ) => Vec::<Order>::new();
+        wrong_side_unstuck_close_short: Side::Short, |position| calc_unstuck_close_short(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+            100.0,
+        ) => Order::default();
+        wrong_side_grid_close_short_at_levels: Side::Short, |position| {
+            calc_grid_close_short_at_levels(
+                position,
+                &[0.01, 0.02],
+                &[0.5, 0.5],
+                &fixtures::exchange_params(),
+            )
+        } => Ok(Vec::new());
+        wrong_side_r_multiple_closes_short: Side::Short, |position| {
+            calc_r_multiple_closes_short(
+                position,
+                61000.0,
+                &[1.0, 2.0],
+                &[0.5, 0.5],
+                &fixtures::exchange_params(),
+            )
+        } => Ok(Vec::new());
+    }
+}
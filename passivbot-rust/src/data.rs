@@ -0,0 +1,1201 @@
+use crate::types::Candle;
+use crate::utils::round_;
+#[cfg(feature = "mmap")]
+use memmap::{Mmap, MmapOptions};
+use std::fmt;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::ops::Range;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CandleError {
+    NonMonotonicTimestamp {
+        index: usize,
+        previous: u64,
+        timestamp: u64,
+    },
+}
+
+impl fmt::Display for CandleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CandleError::NonMonotonicTimestamp {
+                index,
+                previous,
+                timestamp,
+            } => write!(
+                f,
+                "candle {}: timestamp {} not after previous timestamp {}",
+                index, timestamp, previous
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Gap {
+    pub start_index: usize,
+    pub n_missing: usize,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct GapStats {
+    pub interval_ms: u64,
+    pub gaps: Vec<Gap>,
+    pub n_missing: usize,
+    pub longest_gap: usize,
+    pub leading_nans: usize,  // symbol not yet listed
+    pub trailing_nans: usize, // symbol delisted
+}
+
+/// Preflight check of candle data before a backtest.
+/// A gap is either a run of NaN closes or a jump in timestamps larger than the candle interval.
+pub fn validate_candles(candles: &[Candle]) -> Result<GapStats, Vec<CandleError>> {
+    let errors: Vec<CandleError> = candles
+        .windows(2)
+        .enumerate()
+        .filter(|(_, w)| w[1].timestamp <= w[0].timestamp)
+        .map(|(i, w)| CandleError::NonMonotonicTimestamp {
+            index: i + 1,
+            previous: w[0].timestamp,
+            timestamp: w[1].timestamp,
+        })
+        .collect();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let mut stats = GapStats {
+        interval_ms: candles
+            .windows(2)
+            .map(|w| w[1].timestamp - w[0].timestamp)
+            .min()
+            .unwrap_or(0),
+        ..Default::default()
+    };
+    let first_valid = match candles.iter().position(|c| !c.close.is_nan()) {
+        Some(i) => i,
+        None => {
+            stats.leading_nans = candles.len();
+            return Ok(stats);
+        }
+    };
+    let last_valid = candles.iter().rposition(|c| !c.close.is_nan()).unwrap();
+    stats.leading_nans = first_valid;
+    stats.trailing_nans = candles.len() - 1 - last_valid;
+
+    let mut i = first_valid + 1;
+    while i <= last_valid {
+        if candles[i].close.is_nan() {
+            let start_index = i;
+            while candles[i].close.is_nan() {
+                i += 1;
+            }
+            stats.gaps.push(Gap {
+                start_index,
+                n_missing: i - start_index,
+            });
+        } else {
+            let elapsed = candles[i].timestamp - candles[i - 1].timestamp;
+            if stats.interval_ms > 0 && elapsed >= 2 * stats.interval_ms {
+                stats.gaps.push(Gap {
+                    start_index: i,
+                    n_missing: (elapsed / stats.interval_ms) as usize - 1,
+                });
+            }
+            i += 1;
+        }
+    }
+    stats.n_missing = stats.gaps.iter().map(|gap| gap.n_missing).sum();
+    stats.longest_gap = stats
+        .gaps
+        .iter()
+        .map(|gap| gap.n_missing)
+        .max()
+        .unwrap_or(0);
+    Ok(stats)
+}
+
+/// Zero based column indices of a candle CSV. The open price is not read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvSchema {
+    pub timestamp: usize,
+    pub high: usize,
+    pub low: usize,
+    pub close: usize,
+    pub volume: Option<usize>, // None gives zero volume
+}
+
+impl CsvSchema {
+    /// open_time,open,high,low,close,volume,... as in Binance kline exports, which is also the
+    /// generic ts,o,h,l,c,v layout.
+    pub const OHLCV: CsvSchema = CsvSchema {
+        timestamp: 0,
+        high: 2,
+        low: 3,
+        close: 4,
+        volume: Some(5),
+    };
+
+    /// Columns named in a header row, case insensitive, e.g. "timestamp,high,low,close,volume"
+    /// or "ts,o,h,l,c,v". None unless timestamp, high, low and close are all found.
+    pub fn from_header(fields: &[&str]) -> Option<CsvSchema> {
+        let find = |names: &[&str]| {
+            fields
+                .iter()
+                .position(|field| names.contains(&field.to_ascii_lowercase().as_str()))
+        };
+        Some(CsvSchema {
+            timestamp: find(&["timestamp", "ts", "time", "open_time"])?,
+            high: find(&["high", "h"])?,
+            low: find(&["low", "l"])?,
+            close: find(&["close", "c"])?,
+            volume: find(&["volume", "v", "vol"]),
+        })
+    }
+
+    fn n_columns(&self) -> usize {
+        [self.timestamp, self.high, self.low, self.close]
+            .into_iter()
+            .chain(self.volume)
+            .max()
+            .unwrap()
+            + 1
+    }
+}
+
+#[derive(Debug)]
+pub enum DataError {
+    Io(std::io::Error),
+    GzipDisabled, // gzipped file, built without the gzip feature
+    UnknownLayout {
+        line: usize,
+    },
+    Parse {
+        line: usize,
+        message: String,
+    },
+    NonMonotonicTimestamp {
+        line: usize,
+        previous: u64,
+        timestamp: u64,
+    },
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DataError::Io(err) => write!(f, "io error: {}", err),
+            DataError::GzipDisabled => write!(f, "gzip support not enabled in this build"),
+            DataError::UnknownLayout { line } => {
+                write!(f, "line {}: unknown column layout, pass a CsvSchema", line)
+            }
+            DataError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            DataError::NonMonotonicTimestamp {
+                line,
+                previous,
+                timestamp,
+            } => write!(
+                f,
+                "line {}: timestamp {} not after previous timestamp {}",
+                line, timestamp, previous
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DataError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DataError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for DataError {
+    fn from(err: std::io::Error) -> Self {
+        DataError::Io(err)
+    }
+}
+
+/// Candles from a CSV file, gzipped or not; gzip needs the gzip feature. See
+/// parse_candles_csv for the format.
+pub fn load_candles_csv(
+    path: impl AsRef<Path>,
+    schema: Option<CsvSchema>,
+) -> Result<Vec<Candle>, DataError> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    if reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        #[cfg(feature = "gzip")]
+        return parse_candles_csv(
+            BufReader::new(flate2::bufread::MultiGzDecoder::new(reader)),
+            schema,
+        );
+        #[cfg(not(feature = "gzip"))]
+        return Err(DataError::GzipDisabled);
+    }
+    parse_candles_csv(reader, schema)
+}
+
+/// Candles from comma separated rows. Leading rows whose timestamp is not a number are taken
+/// as headers and skipped; without a schema the first one names the columns, see
+/// CsvSchema::from_header, and files without a header must be in the CsvSchema::OHLCV layout.
+/// Timestamps may be in seconds, milliseconds or microseconds, told apart by magnitude, and
+/// are returned in milliseconds. They must increase. Errors give the 1 based line of the first
+/// offending row.
+pub fn parse_candles_csv<R: BufRead>(
+    reader: R,
+    schema: Option<CsvSchema>,
+) -> Result<Vec<Candle>, DataError> {
+    let mut schema = schema;
+    let mut in_header = true;
+    let mut candles: Vec<Candle> = Vec::new();
+    for (i, row) in reader.lines().enumerate() {
+        let line = i + 1;
+        let row = row?;
+        let row = row.trim();
+        if row.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = row.split(',').map(str::trim).collect();
+        if in_header {
+            let is_header = fields
+                .get(schema.map_or(0, |schema| schema.timestamp))
+                .is_some_and(|field| field.parse::<f64>().is_err());
+            if is_header {
+                if schema.is_none() {
+                    schema = Some(
+                        CsvSchema::from_header(&fields).ok_or(DataError::UnknownLayout { line })?,
+                    );
+                }
+                continue;
+            }
+            in_header = false;
+            if schema.is_none() {
+                if fields.len() < CsvSchema::OHLCV.n_columns() {
+                    return Err(DataError::UnknownLayout { line });
+                }
+                schema = Some(CsvSchema::OHLCV);
+            }
+        }
+        let schema = schema.unwrap();
+        if fields.len() < schema.n_columns() {
+            return Err(DataError::Parse {
+                line,
+                message: format!(
+                    "{} columns, expected at least {}",
+                    fields.len(),
+                    schema.n_columns()
+                ),
+            });
+        }
+        let parse = |column: usize| {
+            fields[column].parse::<f64>().map_err(|_| DataError::Parse {
+                line,
+                message: format!("column {}: {:?} is not a number", column, fields[column]),
+            })
+        };
+        let timestamp = parse(schema.timestamp)?;
+        if !(timestamp >= 0.0 && timestamp.is_finite()) {
+            return Err(DataError::Parse {
+                line,
+                message: format!("invalid timestamp {}", timestamp),
+            });
+        }
+        let candle = Candle {
+            timestamp: timestamp_to_ms(timestamp),
+            high: parse(schema.high)?,
+            low: parse(schema.low)?,
+            close: parse(schema.close)?,
+            volume: schema.volume.map_or(Ok(0.0), parse)?,
+        };
+        if let Some(previous) = candles.last() {
+            if candle.timestamp <= previous.timestamp {
+                return Err(DataError::NonMonotonicTimestamp {
+                    line,
+                    previous: previous.timestamp,
+                    timestamp: candle.timestamp,
+                });
+            }
+        }
+        candles.push(candle);
+    }
+    Ok(candles)
+}
+
+/// Seconds below 1e11 (year 5138), microseconds from 1e14 on (milliseconds of year 5138).
+fn timestamp_to_ms(timestamp: f64) -> u64 {
+    if timestamp < 1e11 {
+        (timestamp * 1000.0).round() as u64
+    } else if timestamp >= 1e14 {
+        (timestamp / 1000.0).round() as u64
+    } else {
+        timestamp.round() as u64
+    }
+}
+
+const CANDLE_STORE_MAGIC: &[u8; 8] = b"PBCANDLE";
+const CANDLE_STORE_VERSION: u32 = 1;
+const CANDLE_STORE_HEADER_LEN: usize = 32;
+const N_FLOAT_COLUMNS: usize = 4; // high, low, close, volume
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CandlePrecision {
+    #[default]
+    F64 = 0, // discriminants are the precision codes of the file header
+    F32 = 1, // half the memory; prices come back rounded to the symbol's price_step
+}
+
+impl CandlePrecision {
+    fn width(&self) -> usize {
+        match self {
+            CandlePrecision::F64 => 8,
+            CandlePrecision::F32 => 4,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CandleStoreError {
+    Io(std::io::Error),
+    InvalidFile(String),
+    MmapDisabled, // built without the mmap feature
+    PriceStepsMismatch {
+        n_symbols: usize,
+        n_price_steps: usize,
+    },
+    LengthMismatch {
+        symbol: usize,
+        n_candles: usize,
+        n_timesteps: usize,
+    },
+    TimestampMismatch {
+        symbol: usize,
+        index: usize,
+    },
+}
+
+impl fmt::Display for CandleStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CandleStoreError::Io(err) => write!(f, "io error: {}", err),
+            CandleStoreError::InvalidFile(msg) => write!(f, "invalid candle store file: {}", msg),
+            CandleStoreError::MmapDisabled => write!(f, "mmap support not enabled in this build"),
+            CandleStoreError::PriceStepsMismatch {
+                n_symbols,
+                n_price_steps,
+            } => write!(
+                f,
+                "{} price steps given for {} symbols",
+                n_price_steps, n_symbols
+            ),
+            CandleStoreError::LengthMismatch {
+                symbol,
+                n_candles,
+                n_timesteps,
+            } => write!(
+                f,
+                "symbol {}: {} candles, expected {}",
+                symbol, n_candles, n_timesteps
+            ),
+            CandleStoreError::TimestampMismatch { symbol, index } => write!(
+                f,
+                "symbol {}: candle {} timestamp differs from symbol 0",
+                symbol, index
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CandleStoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CandleStoreError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CandleStoreError {
+    fn from(err: std::io::Error) -> Self {
+        CandleStoreError::Io(err)
+    }
+}
+
+enum Backing {
+    Owned(Vec<u8>),
+    #[cfg(feature = "mmap")]
+    Mapped(Mmap),
+}
+
+impl Backing {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Backing::Owned(bytes) => bytes,
+            #[cfg(feature = "mmap")]
+            Backing::Mapped(mmap) => mmap,
+        }
+    }
+}
+
+/// Candles of many symbols on a shared timeline, stored column by column: timestamps once,
+/// then high, low, close and volume per symbol. Symbols not trading at a timestamp hold NaN,
+/// as in the hlcvs arrays of the multi symbol backtest.
+///
+/// The in memory layout is the cache file format, so load reads a file as is and
+/// open_mmap maps it, paging candles in as they are read. Multi-byte values are little endian:
+/// - header: b"PBCANDLE", version u32, precision u32 (0 f64, 1 f32), n_symbols u64,
+///   n_timesteps u64
+/// - price_step f64 per symbol, then timestamp u64 per timestep
+/// - per symbol: high, low, close and volume columns of n_timesteps values each
+///
+/// With CandlePrecision::F32 prices are read back rounded to price_step, so prices already
+/// rounded to it come back unchanged while price / price_step stays below 2^23. Volume keeps
+/// f32 precision.
+pub struct CandleStore {
+    precision: CandlePrecision,
+    n_symbols: usize,
+    n_timesteps: usize,
+    price_steps: Vec<f64>,
+    backing: Backing,
+}
+
+impl fmt::Debug for CandleStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CandleStore")
+            .field("precision", &self.precision)
+            .field("n_symbols", &self.n_symbols)
+            .field("n_timesteps", &self.n_timesteps)
+            .finish()
+    }
+}
+
+impl CandleStore {
+    /// One candle slice per symbol, all with the timestamps of the first, and the price_step of
+    /// each symbol.
+    pub fn from_candles(
+        symbols: &[&[Candle]],
+        price_steps: &[f64],
+        precision: CandlePrecision,
+    ) -> Result<Self, CandleStoreError> {
+        if price_steps.len() != symbols.len() {
+            return Err(CandleStoreError::PriceStepsMismatch {
+                n_symbols: symbols.len(),
+                n_price_steps: price_steps.len(),
+            });
+        }
+        let n_timesteps = symbols.first().map_or(0, |candles| candles.len());
+        for (symbol, candles) in symbols.iter().enumerate() {
+            if candles.len() != n_timesteps {
+                return Err(CandleStoreError::LengthMismatch {
+                    symbol,
+                    n_candles: candles.len(),
+                    n_timesteps,
+                });
+            }
+            if let Some(index) =
+                (0..n_timesteps).position(|k| candles[k].timestamp != symbols[0][k].timestamp)
+            {
+                return Err(CandleStoreError::TimestampMismatch { symbol, index });
+            }
+        }
+        let mut bytes = Vec::with_capacity(
+            store_len(precision, symbols.len(), n_timesteps).unwrap_or_default(),
+        );
+        bytes.extend_from_slice(CANDLE_STORE_MAGIC);
+        bytes.extend_from_slice(&CANDLE_STORE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(precision as u32).to_le_bytes());
+        bytes.extend_from_slice(&(symbols.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&(n_timesteps as u64).to_le_bytes());
+        for price_step in price_steps {
+            bytes.extend_from_slice(&price_step.to_le_bytes());
+        }
+        for candle in symbols.first().map_or(&[][..], |candles| candles) {
+            bytes.extend_from_slice(&candle.timestamp.to_le_bytes());
+        }
+        let columns: [fn(&Candle) -> f64; N_FLOAT_COLUMNS] = [
+            |candle| candle.high,
+            |candle| candle.low,
+            |candle| candle.close,
+            |candle| candle.volume,
+        ];
+        for candles in symbols {
+            for column in columns {
+                for candle in candles.iter() {
+                    match precision {
+                        CandlePrecision::F64 => {
+                            bytes.extend_from_slice(&column(candle).to_le_bytes())
+                        }
+                        CandlePrecision::F32 => {
+                            bytes.extend_from_slice(&(column(candle) as f32).to_le_bytes())
+                        }
+                    }
+                }
+            }
+        }
+        Self::from_backing(Backing::Owned(bytes))
+    }
+
+    /// Reads a cache file written by save into memory.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CandleStoreError> {
+        Self::from_backing(Backing::Owned(fs::read(path)?))
+    }
+
+    /// Maps a cache file written by save; candles are read from disk on first access. The file
+    /// must not change while the store is alive. Needs the mmap feature.
+    pub fn open_mmap(path: impl AsRef<Path>) -> Result<Self, CandleStoreError> {
+        #[cfg(feature = "mmap")]
+        {
+            let file = fs::File::open(path)?;
+            let mmap = unsafe { MmapOptions::new().map(&file)? };
+            Self::from_backing(Backing::Mapped(mmap))
+        }
+        #[cfg(not(feature = "mmap"))]
+        {
+            let _ = path;
+            Err(CandleStoreError::MmapDisabled)
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), CandleStoreError> {
+        Ok(fs::write(path, self.backing.bytes())?)
+    }
+
+    fn from_backing(backing: Backing) -> Result<Self, CandleStoreError> {
+        let bytes = backing.bytes();
+        let invalid = |msg: &str| Err(CandleStoreError::InvalidFile(msg.to_string()));
+        if bytes.len() < CANDLE_STORE_HEADER_LEN || &bytes[..8] != CANDLE_STORE_MAGIC {
+            return invalid("missing header");
+        }
+        if read_u32(bytes, 8) != CANDLE_STORE_VERSION {
+            return invalid("unsupported version");
+        }
+        let precision = match read_u32(bytes, 12) {
+            0 => CandlePrecision::F64,
+            1 => CandlePrecision::F32,
+            _ => return invalid("unknown precision"),
+        };
+        let n_symbols = read_u64(bytes, 16) as usize;
+        let n_timesteps = read_u64(bytes, 24) as usize;
+        let expected_len = store_len(precision, n_symbols, n_timesteps);
+        if expected_len != Some(bytes.len()) {
+            return invalid("length does not match header");
+        }
+        let price_steps = (0..n_symbols)
+            .map(|i| f64::from_bits(read_u64(bytes, CANDLE_STORE_HEADER_LEN + 8 * i)))
+            .collect();
+        Ok(CandleStore {
+            precision,
+            n_symbols,
+            n_timesteps,
+            price_steps,
+            backing,
+        })
+    }
+
+    pub fn precision(&self) -> CandlePrecision {
+        self.precision
+    }
+
+    pub fn n_symbols(&self) -> usize {
+        self.n_symbols
+    }
+
+    pub fn n_timesteps(&self) -> usize {
+        self.n_timesteps
+    }
+
+    pub fn price_step(&self, symbol: usize) -> f64 {
+        self.price_steps[symbol]
+    }
+
+    /// All candles of symbol. Panics if symbol is out of range.
+    pub fn symbol(&self, symbol: usize) -> CandleView<'_> {
+        assert!(symbol < self.n_symbols, "symbol {} out of range", symbol);
+        CandleView {
+            store: self,
+            symbol,
+            start: 0,
+            len: self.n_timesteps,
+        }
+    }
+
+    fn timestamp(&self, k: usize) -> u64 {
+        read_u64(
+            self.backing.bytes(),
+            CANDLE_STORE_HEADER_LEN + 8 * (self.n_symbols + k),
+        )
+    }
+
+    fn value(&self, symbol: usize, column: usize, k: usize) -> f64 {
+        let width = self.precision.width();
+        let offset = CANDLE_STORE_HEADER_LEN
+            + 8 * (self.n_symbols + self.n_timesteps)
+            + width * ((symbol * N_FLOAT_COLUMNS + column) * self.n_timesteps + k);
+        let bytes = self.backing.bytes();
+        match self.precision {
+            CandlePrecision::F64 => f64::from_bits(read_u64(bytes, offset)),
+            CandlePrecision::F32 => f32::from_bits(read_u32(bytes, offset)) as f64,
+        }
+    }
+
+    fn candle(&self, symbol: usize, k: usize) -> Candle {
+        let price = |column| {
+            let value = self.value(symbol, column, k);
+            match self.precision {
+                CandlePrecision::F64 => value,
+                CandlePrecision::F32 => round_(value, self.price_steps[symbol]),
+            }
+        };
+        Candle {
+            timestamp: self.timestamp(k),
+            high: price(0),
+            low: price(1),
+            close: price(2),
+            volume: self.value(symbol, 3, k),
+        }
+    }
+}
+
+/// Length in bytes of a store file, None if it would not fit in memory.
+fn store_len(precision: CandlePrecision, n_symbols: usize, n_timesteps: usize) -> Option<usize> {
+    let n_values = n_symbols
+        .checked_mul(n_timesteps)?
+        .checked_mul(N_FLOAT_COLUMNS)?;
+    let n_index_bytes = n_symbols.checked_add(n_timesteps)?.checked_mul(8)?;
+    n_values
+        .checked_mul(precision.width())?
+        .checked_add(n_index_bytes)?
+        .checked_add(CANDLE_STORE_HEADER_LEN)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// Candles of one symbol of a CandleStore, or a range of them. Decodes candles on access.
+#[derive(Debug, Clone, Copy)]
+pub struct CandleView<'a> {
+    store: &'a CandleStore,
+    symbol: usize,
+    start: usize,
+    len: usize,
+}
+
+impl<'a> CandleView<'a> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Panics if k is out of range.
+    pub fn get(&self, k: usize) -> Candle {
+        assert!(k < self.len, "candle {} out of range", k);
+        self.store.candle(self.symbol, self.start + k)
+    }
+
+    /// Sub-view of range, relative to this view, e.g. one chunk of a streamed backtest. Panics
+    /// if range is out of bounds.
+    pub fn slice(&self, range: Range<usize>) -> CandleView<'a> {
+        assert!(
+            range.start <= range.end && range.end <= self.len,
+            "range {:?} out of bounds",
+            range
+        );
+        CandleView {
+            start: self.start + range.start,
+            len: range.end - range.start,
+            ..*self
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Candle> + 'a {
+        let view = *self;
+        (0..view.len).map(move |k| view.get(k))
+    }
+
+    pub fn to_vec(&self) -> Vec<Candle> {
+        self.iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candle(k: u64, close: f64) -> Candle {
+        Candle {
+            timestamp: k * 60_000,
+            high: close + 1.0,
+            low: close - 1.0,
+            close,
+            volume: 10.0,
+        }
+    }
+
+    #[test]
+    fn nan_runs_are_gaps_and_edges_are_listing_and_delisting() {
+        let closes = [
+            f64::NAN,
+            f64::NAN,
+            100.0,
+            f64::NAN,
+            101.0,
+            f64::NAN,
+            f64::NAN,
+            f64::NAN,
+            102.0,
+            f64::NAN,
+        ];
+        let candles: Vec<Candle> = (0..)
+            .zip(closes)
+            .map(|(k, close)| candle(k, close))
+            .collect();
+        let stats = validate_candles(&candles).unwrap();
+        assert_eq!(
+            stats,
+            GapStats {
+                interval_ms: 60_000,
+                gaps: vec![
+                    Gap {
+                        start_index: 3,
+                        n_missing: 1
+                    },
+                    Gap {
+                        start_index: 5,
+                        n_missing: 3
+                    },
+                ],
+                n_missing: 4,
+                longest_gap: 3,
+                leading_nans: 2,
+                trailing_nans: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn timestamp_jumps_are_gaps() {
+        let candles = [candle(0, 100.0), candle(1, 100.0), candle(5, 100.0)];
+        let stats = validate_candles(&candles).unwrap();
+        assert_eq!(
+            stats.gaps,
+            vec![Gap {
+                start_index: 2,
+                n_missing: 3
+            }]
+        );
+        assert_eq!(stats.leading_nans + stats.trailing_nans, 0);
+    }
+
+    #[test]
+    fn all_nan_and_non_monotonic_candles() {
+        let all_nan = [candle(0, f64::NAN), candle(1, f64::NAN)];
+        let stats = validate_candles(&all_nan).unwrap();
+        assert_eq!(stats.leading_nans, 2);
+        assert!(stats.gaps.is_empty());
+
+        let candles = [
+            candle(0, 100.0),
+            candle(2, 100.0),
+            candle(1, 100.0),
+            candle(1, 100.0),
+        ];
+        assert_eq!(
+            validate_candles(&candles),
+            Err(vec![
+                CandleError::NonMonotonicTimestamp {
+                    index: 2,
+                    previous: 120_000,
+                    timestamp: 60_000
+                },
+                CandleError::NonMonotonicTimestamp {
+                    index: 3,
+                    previous: 60_000,
+                    timestamp: 60_000
+                },
+            ])
+        );
+    }
+
+    /// fixtures::candles with prices rounded to price_step 0.1, as exchanges report them.
+    fn stepped_candles(n_candles: usize, seed: u64) -> Vec<Candle> {
+        crate::fixtures::candles(n_candles, seed)
+            .into_iter()
+            .map(|candle| Candle {
+                high: round_(candle.high, 0.1),
+                low: round_(candle.low, 0.1),
+                ..candle
+            })
+            .collect()
+    }
+
+    /// Bitwise, so that NaN gaps compare equal.
+    fn assert_same_candles(actual: &[Candle], expected: &[Candle]) {
+        let bits = |candles: &[Candle]| -> Vec<[u64; 5]> {
+            candles
+                .iter()
+                .map(|candle| {
+                    [
+                        candle.timestamp,
+                        candle.high.to_bits(),
+                        candle.low.to_bits(),
+                        candle.close.to_bits(),
+                        candle.volume.to_bits(),
+                    ]
+                })
+                .collect()
+        };
+        assert_eq!(bits(actual), bits(expected));
+    }
+
+    #[test]
+    fn candle_store_round_trips_stepped_prices_at_f32() {
+        let btc = stepped_candles(500, 1);
+        // a cheaper symbol on a finer step, unlisted for its first 100 candles
+        let alt: Vec<Candle> = btc
+            .iter()
+            .enumerate()
+            .map(|(k, candle)| {
+                let price = |p: f64| {
+                    if k < 100 {
+                        f64::NAN
+                    } else {
+                        round_(p / 600.0, 0.001)
+                    }
+                };
+                Candle {
+                    high: price(candle.high),
+                    low: price(candle.low),
+                    close: price(candle.close),
+                    ..*candle
+                }
+            })
+            .collect();
+        let price_steps = [0.1, 0.001];
+        for precision in [CandlePrecision::F64, CandlePrecision::F32] {
+            let store = CandleStore::from_candles(&[&btc, &alt], &price_steps, precision).unwrap();
+            assert_eq!(store.precision(), precision);
+            assert_eq!((store.n_symbols(), store.n_timesteps()), (2, 500));
+            for (symbol, candles) in [&btc, &alt].into_iter().enumerate() {
+                let expected: Vec<Candle> = match precision {
+                    CandlePrecision::F64 => candles.clone(),
+                    // prices exact, volume at f32 precision
+                    CandlePrecision::F32 => candles
+                        .iter()
+                        .map(|candle| Candle {
+                            volume: candle.volume as f32 as f64,
+                            ..*candle
+                        })
+                        .collect(),
+                };
+                let view = store.symbol(symbol);
+                assert_same_candles(&view.to_vec(), &expected);
+                assert_same_candles(&view.slice(100..250).to_vec(), &expected[100..250]);
+                assert_same_candles(&[view.slice(100..250).get(3)], &expected[103..104]);
+            }
+
+            let path = std::env::temp_dir().join(format!(
+                "passivbot_candle_store_{:?}_{}.bin",
+                precision,
+                std::process::id()
+            ));
+            store.save(&path).unwrap();
+            let loaded = CandleStore::load(&path).unwrap();
+            assert_eq!(loaded.precision(), precision);
+            assert_eq!((loaded.price_step(0), loaded.price_step(1)), (0.1, 0.001));
+            for symbol in 0..2 {
+                assert_same_candles(
+                    &loaded.symbol(symbol).to_vec(),
+                    &store.symbol(symbol).to_vec(),
+                );
+            }
+            #[cfg(not(feature = "mmap"))]
+            assert!(matches!(
+                CandleStore::open_mmap(&path),
+                Err(CandleStoreError::MmapDisabled)
+            ));
+            #[cfg(feature = "mmap")]
+            assert_same_candles(
+                &CandleStore::open_mmap(&path).unwrap().symbol(1).to_vec(),
+                &store.symbol(1).to_vec(),
+            );
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn f32_store_backtests_like_the_candles() {
+        let candles = stepped_candles(5_000, 7);
+        let bot_params_pair = crate::fixtures::bot_params_pair();
+        let exchange_params = crate::fixtures::exchange_params();
+        let backtest_params = crate::fixtures::backtest_params();
+        let expected = crate::runner::backtest(
+            &candles,
+            &bot_params_pair,
+            &exchange_params,
+            &backtest_params,
+        );
+        assert!(expected.n_trades > 0);
+        for precision in [CandlePrecision::F64, CandlePrecision::F32] {
+            let store = CandleStore::from_candles(&[&candles], &[0.1], precision).unwrap();
+            let result = crate::runner::backtest_view(
+                &store.symbol(0),
+                &bot_params_pair,
+                &exchange_params,
+                &backtest_params,
+            );
+            assert_eq!(result.fills, expected.fills);
+            assert_eq!(result.equities, expected.equities);
+        }
+    }
+
+    #[test]
+    fn candle_store_rejects_mismatched_inputs_and_bad_files() {
+        let candles = stepped_candles(10, 1);
+        let shifted: Vec<Candle> = candles
+            .iter()
+            .map(|candle| Candle {
+                timestamp: candle.timestamp + 1,
+                ..*candle
+            })
+            .collect();
+        let from_candles = |symbols: &[&[Candle]], price_steps: &[f64]| {
+            CandleStore::from_candles(symbols, price_steps, CandlePrecision::F32).unwrap_err()
+        };
+        assert!(matches!(
+            from_candles(&[&candles, &candles], &[0.1]),
+            CandleStoreError::PriceStepsMismatch {
+                n_symbols: 2,
+                n_price_steps: 1
+            }
+        ));
+        assert!(matches!(
+            from_candles(&[&candles, &candles[..9]], &[0.1, 0.1]),
+            CandleStoreError::LengthMismatch {
+                symbol: 1,
+                n_candles: 9,
+                n_timesteps: 10
+            }
+        ));
+        assert!(matches!(
+            from_candles(&[&candles, &shifted], &[0.1, 0.1]),
+            CandleStoreError::TimestampMismatch {
+                symbol: 1,
+                index: 0
+            }
+        ));
+
+        let store = CandleStore::from_candles(&[&candles], &[0.1], CandlePrecision::F32).unwrap();
+        let path = std::env::temp_dir().join(format!(
+            "passivbot_candle_store_bad_{}.bin",
+            std::process::id()
+        ));
+        store.save(&path).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        let load = |bytes: &[u8]| {
+            fs::write(&path, bytes).unwrap();
+            match CandleStore::load(&path) {
+                Err(CandleStoreError::InvalidFile(msg)) => msg,
+                other => panic!("{:?}", other),
+            }
+        };
+        assert_eq!(
+            load(&bytes[..bytes.len() - 1]),
+            "length does not match header"
+        );
+        bytes[12] = 2;
+        assert_eq!(load(&bytes), "unknown precision");
+        bytes[8] = 99;
+        assert_eq!(load(&bytes), "unsupported version");
+        assert_eq!(load(b"PBCANDL"), "missing header");
+        fs::remove_file(&path).unwrap();
+    }
+
+    fn parse(csv: &str, schema: Option<CsvSchema>) -> Result<Vec<Candle>, DataError> {
+        parse_candles_csv(csv.as_bytes(), schema)
+    }
+
+    #[test]
+    fn csv_schema_from_header_names_and_aliases() {
+        assert_eq!(
+            CsvSchema::from_header(&["open_time", "open", "high", "low", "close", "volume"]),
+            Some(CsvSchema::OHLCV)
+        );
+        assert_eq!(
+            CsvSchema::from_header(&["TS", "O", "H", "L", "C", "Vol"]),
+            Some(CsvSchema::OHLCV)
+        );
+        assert_eq!(
+            CsvSchema::from_header(&["close", "low", "high", "time"]),
+            Some(CsvSchema {
+                timestamp: 3,
+                high: 2,
+                low: 1,
+                close: 0,
+                volume: None,
+            })
+        );
+        assert_eq!(
+            CsvSchema::from_header(&["timestamp", "high", "low", "volume"]),
+            None
+        );
+        assert_eq!(
+            CsvSchema::from_header(&["high", "low", "close", "volume"]),
+            None
+        );
+    }
+
+    #[test]
+    fn csv_with_header_headerless_or_explicit_schema() {
+        let expected = vec![
+            Candle {
+                timestamp: 1_700_000_000_000,
+                high: 11.0,
+                low: 9.0,
+                close: 10.0,
+                volume: 5.0,
+            },
+            Candle {
+                timestamp: 1_700_000_060_000,
+                high: 12.0,
+                low: 10.0,
+                close: 11.0,
+                volume: 6.0,
+            },
+        ];
+        let rows = "1700000000000,10,11,9,10,5\n1700000060000,10,12,10,11,6\n";
+        assert_same_candles(&parse(rows, None).unwrap(), &expected);
+        let header = "open_time,open,high,low,close,volume\n";
+        assert_same_candles(
+            &parse(&format!("{header}\n{rows}\n"), None).unwrap(),
+            &expected,
+        );
+
+        let reordered =
+            "Close,Volume,Low,High,Time\n10,5,9,11,1700000000000\n11,6,10,12,1700000060000\n";
+        assert_same_candles(&parse(reordered, None).unwrap(), &expected);
+
+        let schema = CsvSchema {
+            timestamp: 4,
+            high: 3,
+            low: 2,
+            close: 0,
+            volume: None,
+        };
+        let no_volume: Vec<Candle> = expected
+            .iter()
+            .map(|candle| Candle {
+                volume: 0.0,
+                ..*candle
+            })
+            .collect();
+        let unnamed = "a,b,c,d,e\n10,5,9,11,1700000000000\n11,6,10,12,1700000060000\n";
+        assert_same_candles(&parse(unnamed, Some(schema)).unwrap(), &no_volume);
+        assert!(matches!(
+            parse(unnamed, None),
+            Err(DataError::UnknownLayout { line: 1 })
+        ));
+        assert!(matches!(
+            parse("1700000000000,10,11,9\n", None),
+            Err(DataError::UnknownLayout { line: 1 })
+        ));
+    }
+
+    #[test]
+    fn csv_timestamps_in_seconds_milliseconds_or_microseconds() {
+        let timestamps = |csv: &str| -> Vec<u64> {
+            parse(csv, None)
+                .unwrap()
+                .iter()
+                .map(|candle| candle.timestamp)
+                .collect()
+        };
+        assert_eq!(
+            timestamps("1700000000,1,1,1,1,1\n1700000060.5,1,1,1,1,1\n"),
+            vec![1_700_000_000_000, 1_700_000_060_500]
+        );
+        assert_eq!(
+            timestamps("1700000000000,1,1,1,1,1\n1700000060000,1,1,1,1,1\n"),
+            vec![1_700_000_000_000, 1_700_000_060_000]
+        );
+        assert_eq!(
+            timestamps("1700000000000000,1,1,1,1,1\n1700000060000000,1,1,1,1,1\n"),
+            vec![1_700_000_000_000, 1_700_000_060_000]
+        );
+    }
+
+    #[test]
+    fn csv_row_errors_give_the_line() {
+        let header = "ts,o,h,l,c,v\n";
+        match parse(
+            &format!("{header}1700000000000,1,1,1,1,1\n\n1700000000000,1,1,1,1,1\n"),
+            None,
+        ) {
+            Err(DataError::NonMonotonicTimestamp {
+                line: 4,
+                previous: 1_700_000_000_000,
+                timestamp: 1_700_000_000_000,
+            }) => {}
+            other => panic!("{:?}", other),
+        }
+        match parse(&format!("{header}1700000000000,1,x,1,1,1\n"), None) {
+            Err(DataError::Parse { line: 2, message }) => {
+                assert_eq!(message, "column 2: \"x\" is not a number")
+            }
+            other => panic!("{:?}", other),
+        }
+        match parse(
+            &format!("{header}1700000000000,1,1,1,1,1\n1700000060000,1,1,1,1\n"),
+            None,
+        ) {
+            Err(DataError::Parse { line: 3, message }) => {
+                assert_eq!(message, "5 columns, expected at least 6")
+            }
+            other => panic!("{:?}", other),
+        }
+        let err = parse(&format!("{header}-1700000000000,1,1,1,1,1\n"), None).unwrap_err();
+        assert_eq!(err.to_string(), "line 2: invalid timestamp -1700000000000");
+    }
+
+    #[test]
+    fn gzipped_csv_needs_the_gzip_feature() {
+        let path =
+            std::env::temp_dir().join(format!("passivbot_candles_{}.csv.gz", std::process::id()));
+        let csv = "ts,o,h,l,c,v\n60000,10,11,9,10,5\n";
+        #[cfg(feature = "gzip")]
+        {
+            use std::io::Write;
+            let mut encoder =
+                flate2::write::GzEncoder::new(fs::File::create(&path).unwrap(), Default::default());
+            encoder.write_all(csv.as_bytes()).unwrap();
+            encoder.finish().unwrap();
+            assert_same_candles(
+                &load_candles_csv(&path, None).unwrap(),
+                &parse(csv, None).unwrap(),
+            );
+        }
+        #[cfg(not(feature = "gzip"))]
+        {
+            fs::write(&path, [0x1f, 0x8b, 0x08, 0x00]).unwrap();
+            assert!(matches!(
+                load_candles_csv(&path, None),
+                Err(DataError::GzipDisabled)
+            ));
+        }
+        fs::write(&path, csv).unwrap();
+        assert_same_candles(
+            &load_candles_csv(&path, None).unwrap(),
+            &parse(csv, None).unwrap(),
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,452 @@
+use crate::backtest::{calc_ema_alphas, EMAs, EmaAlphas};
+use crate::runner::order_calculators;
+use crate::types::{
+    BotParams, BotParamsPair, Candle, EMABands, ExchangeParams, LastFill, Order, OrderBook,
+    OrderKind, Position, Side, StateParams, TrailingPriceBundle,
+};
+use crate::utils::round_;
+
+const MINUTE_MS: u64 = 60_000;
+
+/// Market data bookkeeping for one symbol, fed by websocket trades, book updates and candles.
+/// EMAs update once per interval, closing it at its last price; EMA spans count intervals,
+/// so MINUTE_MS matches the backtest. Trailing extrema update on every trade and candle for
+/// both sides; the caller resets a side's with reset_trailing when its position changes.
+#[derive(Debug)]
+pub struct MarketState {
+    interval_ms: u64,
+    ema_alphas: EmaAlphas,
+    emas: Option<EMAs>,
+    interval: u64, // index of the current interval, timestamp / interval_ms
+    last_price: f64,
+    trailing_prices: [TrailingPriceBundle; 2],
+    order_book: OrderBook,
+    timestamp: u64,
+}
+
+impl MarketState {
+    pub fn new(bot_params_pair: &BotParamsPair, interval_ms: u64) -> Self {
+        MarketState {
+            interval_ms,
+            ema_alphas: calc_ema_alphas(bot_params_pair),
+            emas: None,
+            interval: 0,
+            last_price: 0.0,
+            trailing_prices: Default::default(),
+            order_book: OrderBook::default(),
+            timestamp: 0,
+        }
+    }
+
+    /// ts in milliseconds. EMAs start at the first price seen.
+    pub fn on_trade(&mut self, price: f64, ts: u64) {
+        self.advance(ts);
+        self.init_emas(price);
+        self.last_price = price;
+        for trailing_prices in &mut self.trailing_prices {
+            trailing_prices.update(price, price, price);
+        }
+    }
+
+    pub fn on_book(&mut self, bid: f64, ask: f64, ts: u64) {
+        self.advance(ts);
+        self.order_book = OrderBook { bid, ask };
+    }
+
+    /// A closed candle, timestamped at its open like in the backtest. Its interval is closed
+    /// at once with the candle's close rather than on the next update.
+    pub fn on_candle(&mut self, candle: &Candle) {
+        self.advance(candle.timestamp);
+        self.init_emas(candle.close);
+        self.last_price = candle.close;
+        for trailing_prices in &mut self.trailing_prices {
+            trailing_prices.update(candle.high, candle.low, candle.close);
+        }
+        self.roll_emas(candle.timestamp / self.interval_ms + 1);
+    }
+
+    pub fn reset_trailing(&mut self, side: Side) {
+        self.trailing_prices[side.index()] = TrailingPriceBundle::default();
+    }
+
+    pub fn trailing_prices(&self, side: Side) -> &TrailingPriceBundle {
+        &self.trailing_prices[side.index()]
+    }
+
+    /// None before the first trade or candle.
+    pub fn ema_bands(&self, side: Side) -> Option<EMABands> {
+        self.emas
+            .as_ref()
+            .map(|emas| emas.compute_bands(side.index()))
+    }
+
+    pub fn order_book(&self) -> &OrderBook {
+        &self.order_book
+    }
+
+    /// State for the calculators of one side, timestamped at the latest update. Fill derived
+    /// fields, last_entry_fill and trailing_add_count, are left at their defaults for the
+    /// caller to set. EMA bands are zero until the EMAs start, see ema_warmup_behavior.
+    pub fn state_params(&self, side: Side, balance: f64) -> StateParams {
+        StateParams {
+            balance,
+            order_book: self.order_book.clone(),
+            ema_bands: self.ema_bands(side).unwrap_or_default(),
+            timestamp: self.timestamp,
+            ..Default::default()
+        }
+    }
+
+    fn init_emas(&mut self, price: f64) {
+        if self.emas.is_none() {
+            self.emas = Some(EMAs {
+                long: [price; 3],
+                short: [price; 3],
+            });
+        }
+    }
+
+    fn advance(&mut self, ts: u64) {
+        self.roll_emas(ts / self.interval_ms);
+        self.timestamp = self.timestamp.max(ts);
+    }
+
+    /// One update per interval elapsed before interval, each closing at the last price seen.
+    fn roll_emas(&mut self, interval: u64) {
+        if let Some(emas) = self.emas.as_mut() {
+            for _ in self.interval..interval {
+                emas.update(self.last_price, &self.ema_alphas);
+            }
+        }
+        self.interval = self.interval.max(interval);
+    }
+}
+
+/// Stateful order calculator for one symbol and side, fed order book updates as they arrive.
+/// Keeps the state the free functions expect callers to track: a MarketState fed the mid
+/// price as trades, with trailing extrema since the position last changed, and the last
+/// entry fill.
+pub struct GridEngine {
+    side: Side,
+    bot_params: BotParams,
+    exchange_params: ExchangeParams,
+    market: MarketState,
+    position: Position,
+    last_entry_fill: Option<LastFill>,
+    trailing_add_count: usize,
+    last_entries: Vec<Order>,
+}
+
+impl GridEngine {
+    pub fn new(side: Side, bot_params: BotParams, exchange_params: ExchangeParams) -> Self {
+        // market state is kept for both sides; only this side's is used
+        let market = MarketState::new(
+            &BotParamsPair {
+                long: bot_params.clone(),
+                short: bot_params.clone(),
+            },
+            MINUTE_MS,
+        );
+        GridEngine {
+            side,
+            bot_params,
+            exchange_params,
+            market,
+            position: Position::default(),
+            last_entry_fill: None,
+            trailing_add_count: 0,
+            last_entries: Vec::new(),
+        }
+    }
+
+    pub fn trailing_prices(&self) -> &TrailingPriceBundle {
+        self.market.trailing_prices(self.side)
+    }
+
+    /// None before the first tick.
+    pub fn ema_bands(&self) -> Option<EMABands> {
+        self.market.ema_bands(self.side)
+    }
+
+    /// Desired entries then closes at this tick; now is in milliseconds.
+    /// A position differing from the previous tick's is taken as a fill: trailing extrema
+    /// restart and, if the position grew, its implied fill price becomes the last entry fill.
+    #[must_use]
+    pub fn on_tick(
+        &mut self,
+        order_book: &OrderBook,
+        position: &Position,
+        balance: f64,
+        now: u64,
+    ) -> Vec<Order> {
+        self.market.on_book(order_book.bid, order_book.ask, now);
+        self.market
+            .on_trade((order_book.bid + order_book.ask) / 2.0, now);
+        if position.size != self.position.size || position.price != self.position.price {
+            self.apply_position_change(position, now);
+        } else if position.size == 0.0 {
+            // extrema count from position open
+            self.market.reset_trailing(self.side);
+        }
+        let state_params = StateParams {
+            last_entry_fill: self.last_entry_fill,
+            trailing_add_count: self.trailing_add_count,
+            ..self.market.state_params(self.side, balance)
+        };
+        let (calc_entries, calc_closes) = order_calculators(self.side.index(), &self.bot_params);
+        let entries = calc_entries(
+            &self.exchange_params,
+            &state_params,
+            &self.bot_params,
+            &self.position,
+            self.market.trailing_prices(self.side),
+        );
+        let closes = calc_closes(
+            &self.exchange_params,
+            &state_params,
+            &self.bot_params,
+            &self.position,
+            self.market.trailing_prices(self.side),
+        );
+        self.last_entries = entries.clone();
+        entries.into_iter().chain(closes).collect()
+    }
+
+    fn apply_position_change(&mut self, position: &Position, now: u64) {
+        let previous = self.position;
+        self.position = *position;
+        self.market.reset_trailing(self.side);
+        if position.size == 0.0 {
+            self.trailing_add_count = 0;
+            return;
+        }
+        if position.size.abs() <= previous.size.abs() {
+            return;
+        }
+        let fill_price = round_(
+            (position.size * position.price - previous.size * previous.price)
+                / (position.size - previous.size),
+            self.exchange_params.price_step,
+        );
+        self.last_entry_fill = Some(LastFill {
+            price: fill_price,
+            timestamp: now,
+        });
+        // attribute the fill to the entry last returned nearest its price
+        let filled_entry = self.last_entries.iter().min_by(|a, b| {
+            (a.price - fill_price)
+                .abs()
+                .total_cmp(&(b.price - fill_price).abs())
+        });
+        if filled_entry.is_some_and(|entry| entry.order_type.kind() == OrderKind::EntryTrailing) {
+            self.trailing_add_count += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::types::OrderType;
+
+    fn book(price: f64) -> OrderBook {
+        OrderBook {
+            bid: price,
+            ask: price,
+        }
+    }
+
+    fn entries(orders: &[Order]) -> Vec<Order> {
+        orders
+            .iter()
+            .filter(|order| order.order_type.is_entry())
+            .copied()
+            .collect()
+    }
+
+    /// Trailing entries only: 1% below the position price, then a 1% bounce off the low.
+    fn trailing_bot_params(entry_trailing_max_adds: usize) -> BotParams {
+        BotParams {
+            wallet_exposure_limit: 1.0,
+            entry_initial_qty_pct: 0.05,
+            entry_grid_double_down_factor: 1.0,
+            entry_trailing_grid_ratio: 1.0,
+            entry_trailing_threshold_pct: 0.01,
+            entry_trailing_retracement_pct: 0.01,
+            entry_trailing_max_adds,
+            close_grid_min_markup: 0.01,
+            close_grid_qty_pct: 1.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn engine_tracks_trailing_extrema_from_position_open() {
+        let mut engine = GridEngine::new(
+            Side::Long,
+            trailing_bot_params(0),
+            fixtures::exchange_params(),
+        );
+        let flat = Position::default();
+        let orders = engine.on_tick(&book(60000.0), &flat, 10000.0, 0);
+        assert_eq!(
+            orders,
+            vec![Order::new(
+                0.008,
+                60000.0,
+                OrderType::EntryInitialNormalLong
+            )]
+        );
+        let ema_bands = engine.ema_bands().unwrap();
+        assert_eq!((ema_bands.lower, ema_bands.upper), (60000.0, 60000.0));
+        // extrema seen while flat, or on the tick reporting the fill, are dropped
+        let _ = engine.on_tick(&book(58000.0), &flat, 10000.0, 1000);
+        let long = Position {
+            size: 0.008,
+            price: 60000.0,
+        };
+        let orders = engine.on_tick(&book(60000.0), &long, 10000.0, 2000);
+        assert_eq!(engine.trailing_prices().min_since_open, f64::MAX);
+        assert_eq!(
+            orders,
+            vec![Order::new(-0.008, 60600.0, OrderType::CloseGridLong)]
+        );
+        assert!(entries(&engine.on_tick(&book(59000.0), &long, 10000.0, 3000)).is_empty());
+        let orders = engine.on_tick(&book(59700.0), &long, 10000.0, 4000);
+        let trailing = engine.trailing_prices();
+        assert_eq!(
+            (trailing.min_since_open, trailing.max_since_min),
+            (59000.0, 59700.0)
+        );
+        assert_eq!(
+            entries(&orders),
+            vec![Order::new(
+                0.008,
+                59700.0,
+                OrderType::EntryTrailingNormalLong
+            )]
+        );
+    }
+
+    #[test]
+    fn engine_counts_trailing_fills_towards_max_adds() {
+        let long = Position {
+            size: 0.008,
+            price: 60000.0,
+        };
+        // the trailing entry at 59700.0 fills, then price dips and bounces again
+        let doubled = Position {
+            size: 0.016,
+            price: 59850.0,
+        };
+        let run = |entry_trailing_max_adds| {
+            let mut engine = GridEngine::new(
+                Side::Long,
+                trailing_bot_params(entry_trailing_max_adds),
+                fixtures::exchange_params(),
+            );
+            for (price, position, now) in [
+                (60000.0, long, 0),
+                (59000.0, long, 1000),
+                (59700.0, long, 2000),
+                (59700.0, doubled, 3000),
+                (58000.0, doubled, 4000),
+            ] {
+                let _ = engine.on_tick(&book(price), &position, 10000.0, now);
+            }
+            entries(&engine.on_tick(&book(59000.0), &doubled, 10000.0, 5000))
+        };
+        assert_eq!(
+            run(0),
+            vec![Order::new(
+                0.016,
+                59000.0,
+                OrderType::EntryTrailingNormalLong
+            )]
+        );
+        assert!(run(1).is_empty());
+        assert_eq!(run(2).len(), 1);
+    }
+
+    #[test]
+    fn market_state_replays_ticks_into_state_params() {
+        // spans of 3 intervals: every EMA moves halfway to the interval's last price
+        let bot_params = BotParams {
+            ema_span_0: 3.0,
+            ema_span_1: 3.0,
+            ..Default::default()
+        };
+        let mut market = MarketState::new(
+            &BotParamsPair {
+                long: bot_params.clone(),
+                short: bot_params,
+            },
+            MINUTE_MS,
+        );
+        let bands = |market: &MarketState| {
+            let [long, short] =
+                [Side::Long, Side::Short].map(|side| market.ema_bands(side).unwrap());
+            assert_eq!((long.lower, long.upper), (short.lower, short.upper));
+            (long.lower, long.upper)
+        };
+        assert!(market.ema_bands(Side::Long).is_none());
+        market.on_book(99.0, 101.0, 0);
+        let ema_bands = market.state_params(Side::Long, 1000.0).ema_bands;
+        assert_eq!((ema_bands.lower, ema_bands.upper), (0.0, 0.0));
+        market.on_trade(100.0, 0);
+        market.on_trade(104.0, 10_000);
+        market.on_trade(96.0, 50_000);
+        // EMAs start at the first price and hold within the interval
+        assert_eq!(bands(&market), (100.0, 100.0));
+        // the next interval closes the first at its last price
+        market.on_trade(102.0, 61_000);
+        assert_eq!(bands(&market), (98.0, 98.0));
+        // a fill on the long side restarts its extrema only
+        market.reset_trailing(Side::Long);
+        for ts in (62_000..120_000).step_by(500) {
+            market.on_trade(110.0, ts);
+        }
+        assert_eq!(bands(&market), (98.0, 98.0));
+        assert_eq!(market.trailing_prices(Side::Long).min_since_open, 110.0);
+        assert_eq!(market.trailing_prices(Side::Short).min_since_open, 96.0);
+        market.on_book(108.0, 109.0, 150_000);
+        assert_eq!(bands(&market), (104.0, 104.0));
+        // intervals without trades close at the last price seen
+        market.on_book(108.0, 109.0, 330_000);
+        assert_eq!(bands(&market), (109.25, 109.25));
+        // a candle at 360_000 closes interval 5 at 110.0 and its own at once at 100.0
+        market.on_candle(&Candle {
+            timestamp: 360_000,
+            high: 112.0,
+            low: 90.0,
+            close: 100.0,
+            volume: 1.0,
+        });
+        assert_eq!(bands(&market), (104.8125, 104.8125));
+        for side in [Side::Long, Side::Short] {
+            let trailing = market.trailing_prices(side);
+            assert_eq!(
+                (
+                    trailing.min_since_open,
+                    trailing.max_since_min,
+                    trailing.max_since_open,
+                    trailing.min_since_max
+                ),
+                (90.0, 100.0, 112.0, 100.0)
+            );
+        }
+        let state_params = market.state_params(Side::Long, 1000.0);
+        assert_eq!(state_params.balance, 1000.0);
+        assert_eq!(
+            (state_params.order_book.bid, state_params.order_book.ask),
+            (108.0, 109.0)
+        );
+        assert_eq!(
+            (state_params.ema_bands.lower, state_params.ema_bands.upper),
+            (104.8125, 104.8125)
+        );
+        assert_eq!(state_params.timestamp, 360_000);
+        assert_eq!(state_params.last_entry_fill, None);
+    }
+}
@@ -1,30 +1,70 @@
+//! Entry calculators. See Order for what zero qty and Empty orders mean.
+
+use crate::sync::{apply_price_band, coalesce_orders};
 use crate::types::{
-    BotParams, ExchangeParams, Order, OrderType, Position, StateParams, TrailingPriceBundle,
+    check_side, debug_assert_signs, BotParams, EMABands, EmaWarmupBehavior, EntryTrailingGridMode,
+    EntryTrailingMode, ExchangeParams, Order, OrderBook, OrderType, Position, Side, StateParams,
+    TrailingPriceBundle,
 };
 use crate::utils::{
-    calc_ema_price_ask, calc_ema_price_bid, calc_new_psize_pprice, calc_wallet_exposure,
-    calc_wallet_exposure_if_filled, cost_to_qty, interpolate, round_, round_dn, round_up,
+    calc_new_psize_pprice, calc_wallet_exposure, calc_wallet_exposure_if_filled, cost_to_qty,
+    exposure_limit_reached, interpolate, qty_to_cost, round_, round_dn, round_up,
 };
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+
+// positions within 0.1% of wallet_exposure_limit get no further entries
+const EXPOSURE_LIMIT_TOLERANCE: f64 = 0.001;
 
+#[must_use]
 pub fn calc_initial_entry_qty(
     exchange_params: &ExchangeParams,
     bot_params: &BotParams,
     balance: f64,
     entry_price: f64,
 ) -> f64 {
-    f64::max(
+    calc_initial_entry_qty_and_cost(exchange_params, bot_params, balance, entry_price).0
+}
+
+#[must_use]
+pub fn calc_initial_entry_qty_and_cost(
+    exchange_params: &ExchangeParams,
+    bot_params: &BotParams,
+    balance: f64,
+    entry_price: f64,
+) -> (f64, f64) {
+    calc_initial_entry_qty_and_cost_with_limit(
+        exchange_params,
+        bot_params,
+        bot_params.wallet_exposure_limit,
+        balance,
+        entry_price,
+    )
+}
+
+fn calc_initial_entry_qty_and_cost_with_limit(
+    exchange_params: &ExchangeParams,
+    bot_params: &BotParams,
+    wallet_exposure_limit: f64,
+    balance: f64,
+    entry_price: f64,
+) -> (f64, f64) {
+    // returns (qty, cost)
+    let qty = f64::max(
         calc_min_entry_qty(entry_price, &exchange_params),
         round_(
             cost_to_qty(
-                balance * bot_params.wallet_exposure_limit * bot_params.entry_initial_qty_pct,
+                balance * wallet_exposure_limit * bot_params.entry_initial_qty_pct,
                 entry_price,
                 exchange_params.c_mult,
             ),
             exchange_params.qty_step,
         ),
-    )
+    );
+    (qty, qty_to_cost(qty, entry_price, exchange_params.c_mult))
 }
 
+#[must_use]
 pub fn calc_min_entry_qty(entry_price: f64, exchange_params: &ExchangeParams) -> f64 {
     f64::max(
         exchange_params.min_qty,
@@ -39,6 +79,67 @@ pub fn calc_min_entry_qty(entry_price: f64, exchange_params: &ExchangeParams) ->
     )
 }
 
+#[must_use]
+pub fn calc_exposure_headroom(
+    position: &Position,
+    balance: f64,
+    bot_params: &BotParams,
+    exchange_params: &ExchangeParams,
+) -> f64 {
+    // remaining notional which may be added before hitting wallet_exposure_limit
+    let wallet_exposure = calc_wallet_exposure(
+        exchange_params.c_mult,
+        balance,
+        position.size.abs(),
+        position.price,
+    );
+    f64::max(
+        0.0,
+        (bot_params.wallet_exposure_limit - wallet_exposure) * balance,
+    )
+}
+
+#[must_use]
+pub fn calc_exposure_overage(
+    position: &Position,
+    balance: f64,
+    bot_params: &BotParams,
+    exchange_params: &ExchangeParams,
+) -> f64 {
+    // notional by which the position exceeds wallet_exposure_limit, e.g. after the limit was lowered.
+    // Entries stop while over the limit, but nothing reduces the position automatically.
+    let wallet_exposure = calc_wallet_exposure(
+        exchange_params.c_mult,
+        balance,
+        position.size.abs(),
+        position.price,
+    );
+    f64::max(
+        0.0,
+        (wallet_exposure - bot_params.wallet_exposure_limit) * balance,
+    )
+}
+
+#[must_use]
+pub fn calc_addable_qty_at(
+    price: f64,
+    position: &Position,
+    balance: f64,
+    bot_params: &BotParams,
+    exchange_params: &ExchangeParams,
+) -> f64 {
+    // qty at given price consuming the headroom, rounded down so as not to exceed it
+    round_dn(
+        cost_to_qty(
+            calc_exposure_headroom(position, balance, bot_params, exchange_params),
+            price,
+            exchange_params.c_mult,
+        ),
+        exchange_params.qty_step,
+    )
+}
+
+#[must_use]
 pub fn calc_cropped_reentry_qty(
     exchange_params: &ExchangeParams,
     bot_params: &BotParams,
@@ -47,6 +148,26 @@ pub fn calc_cropped_reentry_qty(
     balance: f64,
     entry_qty: f64,
     entry_price: f64,
+) -> (f64, f64) {
+    calc_cropped_reentry_qty_with_limit(
+        exchange_params,
+        bot_params.wallet_exposure_limit,
+        position,
+        wallet_exposure,
+        balance,
+        entry_qty,
+        entry_price,
+    )
+}
+
+fn calc_cropped_reentry_qty_with_limit(
+    exchange_params: &ExchangeParams,
+    wallet_exposure_limit: f64,
+    position: &Position,
+    wallet_exposure: f64,
+    balance: f64,
+    entry_qty: f64,
+    entry_price: f64,
 ) -> (f64, f64) {
     let position_size_abs = position.size.abs();
     let entry_qty_abs = entry_qty.abs();
@@ -59,65 +180,334 @@ pub fn calc_cropped_reentry_qty(
         &exchange_params,
     );
     let min_entry_qty = calc_min_entry_qty(entry_price, &exchange_params);
-    if wallet_exposure_if_filled > bot_params.wallet_exposure_limit * 1.01 {
+    let entry_qty_cropped = if wallet_exposure_if_filled > wallet_exposure_limit * 1.01 {
         // reentry too big. Crop current reentry qty.
         let entry_qty_abs = interpolate(
-            bot_params.wallet_exposure_limit,
+            wallet_exposure_limit,
             &[wallet_exposure, wallet_exposure_if_filled],
             &[position_size_abs, position_size_abs + entry_qty_abs],
         ) - position_size_abs;
-        (
-            wallet_exposure_if_filled,
-            f64::max(
-                round_(entry_qty_abs, exchange_params.qty_step),
+        let exceeds_limit = |qty: f64| {
+            calc_wallet_exposure_if_filled(
+                balance,
+                position_size_abs,
+                position.price,
+                qty,
+                entry_price,
+                exchange_params,
+            ) > wallet_exposure_limit
+        };
+        // rounding may overshoot the limit; step down, checking again after each rounding,
+        // until the fill stays under it or min qty is reached
+        let mut entry_qty_cropped = round_(entry_qty_abs, exchange_params.qty_step);
+        while entry_qty_cropped > min_entry_qty && exceeds_limit(entry_qty_cropped) {
+            entry_qty_cropped = f64::max(
+                round_(
+                    entry_qty_cropped - exchange_params.qty_step,
+                    exchange_params.qty_step,
+                ),
                 min_entry_qty,
-            ),
-        )
+            );
+        }
+        f64::max(entry_qty_cropped, min_entry_qty)
     } else {
-        (
-            wallet_exposure_if_filled,
-            f64::max(entry_qty_abs, min_entry_qty),
-        )
+        f64::max(entry_qty_abs, min_entry_qty)
+    };
+    (
+        wallet_exposure_if_filled,
+        calc_position_size_capped_qty(exchange_params, position, entry_qty_cropped, entry_price),
+    )
+}
+
+#[must_use]
+pub fn calc_position_size_capped_qty(
+    exchange_params: &ExchangeParams,
+    position: &Position,
+    entry_qty: f64,
+    entry_price: f64,
+) -> f64 {
+    // crops abs entry qty so that abs position size stays within max_position_size
+    // returns 0.0 if not even min qty fits
+    if exchange_params.max_position_size <= 0.0 {
+        return entry_qty;
+    }
+    let room_raw = exchange_params.max_position_size - position.size.abs();
+    // rounded down unless off the qty grid by float error only
+    let room = if round_(room_raw, exchange_params.qty_step) > room_raw * (1.0 + 1e-9) {
+        round_dn(room_raw, exchange_params.qty_step)
+    } else {
+        round_(room_raw, exchange_params.qty_step)
+    };
+    if room < calc_min_entry_qty(entry_price, exchange_params) {
+        0.0
+    } else {
+        f64::min(entry_qty, room)
+    }
+}
+
+/// Number of reentries position is made of, for an initial entry costing initial_cost and each
+/// reentry costing initial_cost times its schedule multiplier, the last repeating. Counted in
+/// cost, which unlike qty does not depend on fill prices; a level counts once half of it is
+/// filled, absorbing rounding and partial fills. Capped at the last schedule index, as deeper
+/// levels take its multiplier too; 0 if a cost is NaN or infinite.
+fn calc_grid_level(position_cost: f64, initial_cost: f64, qty_schedule: &[f64]) -> usize {
+    if !position_cost.is_finite() || !initial_cost.is_finite() {
+        return 0;
+    }
+    let last_level = qty_schedule.len().saturating_sub(1);
+    let mut filled_cost = initial_cost;
+    for (level, multiplier) in qty_schedule[..last_level].iter().enumerate() {
+        let level_cost = initial_cost * multiplier;
+        if !level_cost.is_finite()
+            || level_cost <= 0.0
+            || position_cost < filled_cost + level_cost * 0.5
+        {
+            return level;
+        }
+        filled_cost += level_cost;
     }
+    last_level
 }
 
+/// Position size times entry_grid_double_down_factor, or, with an entry_grid_qty_schedule,
+/// the initial entry qty times the multiplier for the position's grid level. At least the
+/// initial entry qty and min entry qty either way.
+#[must_use]
 pub fn calc_reentry_qty(
     entry_price: f64,
     balance: f64,
     position_size: f64,
+    position_price: f64,
+    exchange_params: &ExchangeParams,
+    bot_params: &BotParams,
+) -> f64 {
+    calc_reentry_qty_with_limit(
+        entry_price,
+        balance,
+        position_size,
+        position_price,
+        exchange_params,
+        bot_params,
+        bot_params.wallet_exposure_limit,
+    )
+}
+
+fn calc_reentry_qty_with_limit(
+    entry_price: f64,
+    balance: f64,
+    position_size: f64,
+    position_price: f64,
     exchange_params: &ExchangeParams,
     bot_params: &BotParams,
+    wallet_exposure_limit: f64,
 ) -> f64 {
+    let initial_qty = cost_to_qty(balance, entry_price, exchange_params.c_mult)
+        * wallet_exposure_limit
+        * bot_params.entry_initial_qty_pct;
+    let qty = if bot_params.entry_grid_qty_schedule.is_empty() {
+        position_size.abs() * bot_params.entry_grid_double_down_factor
+    } else {
+        let schedule = &bot_params.entry_grid_qty_schedule;
+        let level = calc_grid_level(
+            qty_to_cost(position_size, position_price, exchange_params.c_mult),
+            balance * wallet_exposure_limit * bot_params.entry_initial_qty_pct,
+            schedule,
+        );
+        initial_qty * schedule[level.min(schedule.len() - 1)]
+    };
     f64::max(
-        calc_min_entry_qty(entry_price, &exchange_params),
-        round_(
-            f64::max(
-                position_size.abs() * bot_params.entry_grid_double_down_factor,
-                cost_to_qty(balance, entry_price, exchange_params.c_mult)
-                    * bot_params.wallet_exposure_limit
-                    * bot_params.entry_initial_qty_pct,
-            ),
-            exchange_params.qty_step,
-        ),
+        calc_min_entry_qty(entry_price, exchange_params),
+        round_(f64::max(qty, initial_qty), exchange_params.qty_step),
+    )
+}
+
+/// Initial entry price at the lower EMA band, capped at bid. While the band is zero, i.e. the
+/// EMAs are not warmed up, 0.0 (no entry) or the bid, per ema_warmup_behavior.
+fn calc_initial_entry_price_long(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+) -> f64 {
+    cap_initial_entry_price_long(
+        calc_ema_entry_price_long(exchange_params, state_params, bot_params),
+        state_params,
+        bot_params,
+    )
+}
+
+/// Initial entry price at the upper EMA band, floored at ask. While the band is zero, i.e. the
+/// EMAs are not warmed up, 0.0 (no entry) or the ask, per ema_warmup_behavior.
+fn calc_initial_entry_price_short(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+) -> f64 {
+    cap_initial_entry_price_short(
+        calc_ema_entry_price_short(exchange_params, state_params, bot_params),
+        state_params,
+        bot_params,
     )
 }
 
+/// Initial entry price at the lower EMA band before it is capped at bid, None while the band
+/// is zero.
+fn calc_ema_entry_price_long(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+) -> Option<f64> {
+    if state_params.ema_bands.lower.is_nan() || state_params.ema_bands.lower <= 0.0 {
+        return None;
+    }
+    Some(round_dn(
+        state_params.ema_bands.lower * (1.0 - bot_params.entry_initial_ema_dist),
+        exchange_params.price_step,
+    ))
+}
+
+/// Initial entry price at the upper EMA band before it is floored at ask, None while the band
+/// is zero.
+fn calc_ema_entry_price_short(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+) -> Option<f64> {
+    if state_params.ema_bands.upper.is_nan() || state_params.ema_bands.upper <= 0.0 {
+        return None;
+    }
+    Some(round_up(
+        state_params.ema_bands.upper * (1.0 + bot_params.entry_initial_ema_dist),
+        exchange_params.price_step,
+    ))
+}
+
+// ema_entry_price of calc_ema_entry_price_* capped at the book, or the warmup fallback
+fn cap_initial_entry_price_long(
+    ema_entry_price: Option<f64>,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+) -> f64 {
+    match (ema_entry_price, bot_params.ema_warmup_behavior) {
+        (Some(ema_entry_price), _) => f64::min(state_params.order_book.bid, ema_entry_price),
+        (None, EmaWarmupBehavior::SuppressEntries) => 0.0,
+        (None, EmaWarmupBehavior::FallbackToOrderBook) => state_params.order_book.bid,
+    }
+}
+
+fn cap_initial_entry_price_short(
+    ema_entry_price: Option<f64>,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+) -> f64 {
+    match (ema_entry_price, bot_params.ema_warmup_behavior) {
+        (Some(ema_entry_price), _) => f64::max(state_params.order_book.ask, ema_entry_price),
+        (None, EmaWarmupBehavior::SuppressEntries) => 0.0,
+        (None, EmaWarmupBehavior::FallbackToOrderBook) => state_params.order_book.ask,
+    }
+}
+
+/// Tick invariant inputs of one side's entry calculators, derived once from (exchange_params,
+/// state_params, bot_params) for callers evaluating several of them on the same tick. The
+/// order book may still differ between the state_params it was derived from and those it is
+/// used with, as calc_entries_long moves the bid down its grid; anything else must match.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrecomputedState {
+    pub balance: f64,                 // entry_sizing_balance
+    pub ema_entry_price: Option<f64>, // initial entry price before the book cap
+    pub min_entry_qty: f64,           // at ema_entry_price, 0.0 without one
+}
+
+impl PrecomputedState {
+    pub fn long(
+        exchange_params: &ExchangeParams,
+        state_params: &StateParams,
+        bot_params: &BotParams,
+    ) -> Self {
+        Self::new(
+            exchange_params,
+            state_params,
+            bot_params,
+            calc_ema_entry_price_long(exchange_params, state_params, bot_params),
+        )
+    }
+
+    pub fn short(
+        exchange_params: &ExchangeParams,
+        state_params: &StateParams,
+        bot_params: &BotParams,
+    ) -> Self {
+        Self::new(
+            exchange_params,
+            state_params,
+            bot_params,
+            calc_ema_entry_price_short(exchange_params, state_params, bot_params),
+        )
+    }
+
+    fn new(
+        exchange_params: &ExchangeParams,
+        state_params: &StateParams,
+        bot_params: &BotParams,
+        ema_entry_price: Option<f64>,
+    ) -> Self {
+        PrecomputedState {
+            balance: bot_params.entry_sizing_balance(state_params),
+            ema_entry_price,
+            min_entry_qty: ema_entry_price
+                .map_or(0.0, |price| calc_min_entry_qty(price, exchange_params)),
+        }
+    }
+
+    fn min_entry_qty_at(&self, entry_price: f64, exchange_params: &ExchangeParams) -> f64 {
+        if Some(entry_price) == self.ema_entry_price {
+            self.min_entry_qty
+        } else {
+            calc_min_entry_qty(entry_price, exchange_params)
+        }
+    }
+}
+
+/// entry_grid_spacing_pct widened by entry_grid_spacing_vol_weight * volatility, e.g. 1% becomes
+/// 1.5% at volatility 0.005 with a weight of 100.
+#[must_use]
+pub fn calc_entry_grid_spacing_pct(bot_params: &BotParams, volatility: f64) -> f64 {
+    if bot_params.entry_grid_spacing_vol_weight == 0.0 {
+        bot_params.entry_grid_spacing_pct
+    } else {
+        bot_params.entry_grid_spacing_pct
+            * (1.0 + bot_params.entry_grid_spacing_vol_weight * volatility)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn calc_reentry_price_bid(
     position_price: f64,
     wallet_exposure: f64,
     order_book_bid: f64,
+    ema_bands_lower: f64,
+    volatility: f64,
     exchange_params: &ExchangeParams,
     bot_params: &BotParams,
+    wallet_exposure_limit: f64,
 ) -> f64 {
     let multiplier =
-        (wallet_exposure / bot_params.wallet_exposure_limit) * bot_params.entry_grid_spacing_weight;
-    let reentry_price = f64::min(
-        round_dn(
-            position_price * (1.0 - bot_params.entry_grid_spacing_pct * (1.0 + multiplier)),
-            exchange_params.price_step,
-        ),
-        order_book_bid,
+        (wallet_exposure / wallet_exposure_limit) * bot_params.entry_grid_spacing_weight;
+    let mut reentry_price = round_dn(
+        position_price
+            * (1.0 - calc_entry_grid_spacing_pct(bot_params, volatility) * (1.0 + multiplier)),
+        exchange_params.price_step,
     );
+    if bot_params.entry_grid_ema_floor && ema_bands_lower > 0.0 {
+        // don't place re-entries below the re-entry EMA band
+        reentry_price = f64::max(
+            reentry_price,
+            round_dn(
+                ema_bands_lower * (1.0 - bot_params.reentry_ema_dist()),
+                exchange_params.price_step,
+            ),
+        );
+    }
+    let reentry_price = f64::min(reentry_price, order_book_bid);
     if reentry_price <= exchange_params.price_step {
         0.0
     } else {
@@ -125,22 +515,35 @@ fn calc_reentry_price_bid(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn calc_reentry_price_ask(
     position_price: f64,
     wallet_exposure: f64,
     order_book_ask: f64,
+    ema_bands_upper: f64,
+    volatility: f64,
     exchange_params: &ExchangeParams,
     bot_params: &BotParams,
+    wallet_exposure_limit: f64,
 ) -> f64 {
     let multiplier =
-        (wallet_exposure / bot_params.wallet_exposure_limit) * bot_params.entry_grid_spacing_weight;
-    let reentry_price = f64::max(
-        round_up(
-            position_price * (1.0 + bot_params.entry_grid_spacing_pct * (1.0 + multiplier)),
-            exchange_params.price_step,
-        ),
-        order_book_ask,
+        (wallet_exposure / wallet_exposure_limit) * bot_params.entry_grid_spacing_weight;
+    let mut reentry_price = round_up(
+        position_price
+            * (1.0 + calc_entry_grid_spacing_pct(bot_params, volatility) * (1.0 + multiplier)),
+        exchange_params.price_step,
     );
+    if bot_params.entry_grid_ema_floor && ema_bands_upper > 0.0 {
+        // don't place re-entries above the re-entry EMA band
+        reentry_price = f64::min(
+            reentry_price,
+            round_up(
+                ema_bands_upper * (1.0 + bot_params.reentry_ema_dist()),
+                exchange_params.price_step,
+            ),
+        );
+    }
+    let reentry_price = f64::max(reentry_price, order_book_ask);
     if reentry_price <= exchange_params.price_step {
         0.0
     } else {
@@ -148,54 +551,261 @@ fn calc_reentry_price_ask(
     }
 }
 
-pub fn calc_grid_entry_long(
+/// Why calc_next_entry_long_explained returned an empty order, for config tuning.
+#[cfg_attr(feature = "python", pyclass)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmptyReason {
+    NotEmpty,
+    ZeroExposureLimit,
+    ZeroBalance,            // after balance_reserve_pct
+    PriceBelowStep,         // entry price at or below price_step
+    ExposureLimitReached,   // position already at wallet_exposure_limit
+    TrailingNotTriggered,   // trailing conditions not met yet
+    TrailingMaxAddsReached, // entry_trailing_max_adds trailing entries filled already
+    MaxPositionSizeReached, // not even min qty fits under max_position_size
+    QtyRoundedToZero,       // computed qty rounds to zero at qty_step
+    SameTickFill,           // entry at this price filled on the current tick
+    EntryBlackout,          // timestamp inside one of entry_blackout_windows
+    WrongSidePosition,      // position size has the other side's sign, see Position::for_side
+}
+
+impl EmptyReason {
+    pub const ALL: [EmptyReason; 12] = [
+        EmptyReason::NotEmpty,
+        EmptyReason::ZeroExposureLimit,
+        EmptyReason::ZeroBalance,
+        EmptyReason::PriceBelowStep,
+        EmptyReason::ExposureLimitReached,
+        EmptyReason::TrailingNotTriggered,
+        EmptyReason::TrailingMaxAddsReached,
+        EmptyReason::MaxPositionSizeReached,
+        EmptyReason::QtyRoundedToZero,
+        EmptyReason::SameTickFill,
+        EmptyReason::EntryBlackout,
+        EmptyReason::WrongSidePosition,
+    ];
+}
+
+fn check_exposure_limit_and_balance(
+    wallet_exposure_limit: f64,
+    balance: f64,
+) -> Option<EmptyReason> {
+    if wallet_exposure_limit == 0.0 {
+        Some(EmptyReason::ZeroExposureLimit)
+    } else if balance <= 0.0 {
+        Some(EmptyReason::ZeroBalance)
+    } else {
+        None
+    }
+}
+
+/// (threshold_pct, retracement_pct) of trailing entries. A trailing stop is a trailing entry
+/// without threshold, retracing by entry_trailing_drawdown_pct off the extreme.
+fn entry_trailing_pcts(bot_params: &BotParams) -> (f64, f64) {
+    match bot_params.entry_trailing_mode {
+        EntryTrailingMode::ThresholdDrawdown => (
+            bot_params.entry_trailing_threshold_pct,
+            bot_params.entry_trailing_retracement_pct,
+        ),
+        EntryTrailingMode::TrailingStop => (0.0, bot_params.entry_trailing_drawdown_pct),
+    }
+}
+
+fn trailing_max_adds_reached(state_params: &StateParams, bot_params: &BotParams) -> bool {
+    bot_params.entry_trailing_max_adds > 0
+        && state_params.trailing_add_count >= bot_params.entry_trailing_max_adds
+}
+
+fn guard_same_tick_entry(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    order: Order,
+) -> Order {
+    match state_params.last_entry_fill {
+        // half a step tolerance, same as coalesce_orders
+        Some(last_fill)
+            if last_fill.timestamp == state_params.timestamp
+                && (last_fill.price - order.price).abs() < exchange_params.price_step * 0.5 =>
+        {
+            Order::default()
+        }
+        _ => order,
+    }
+}
+
+/// Keeps entries in order while their combined cost fits in headroom, cropping the first one
+/// that doesn't to what is left and dropping it if that is below min qty.
+fn fit_entries_to_headroom(
+    exchange_params: &ExchangeParams,
+    entries: [(Order, OrderType); 2], // each entry with its cropped order type
+    mut headroom: f64,
+) -> Vec<Order> {
+    let mut fitted = Vec::new();
+    for (entry, cropped_order_type) in entries {
+        if entry.qty == 0.0 {
+            continue;
+        }
+        let mut qty = entry.qty.abs();
+        let mut order_type = entry.order_type;
+        if qty_to_cost(qty, entry.price, exchange_params.c_mult) > headroom {
+            qty = round_dn(
+                cost_to_qty(headroom, entry.price, exchange_params.c_mult),
+                exchange_params.qty_step,
+            );
+            if qty < calc_min_entry_qty(entry.price, exchange_params) {
+                continue;
+            }
+            order_type = cropped_order_type;
+        }
+        headroom -= qty_to_cost(qty, entry.price, exchange_params.c_mult);
+        fitted.push(Order {
+            qty: qty * entry.qty.signum(),
+            price: entry.price,
+            order_type,
+        });
+    }
+    fitted
+}
+
+#[must_use]
+pub fn calc_initial_entry_long_with_timeout(
     exchange_params: &ExchangeParams,
     state_params: &StateParams,
     bot_params: &BotParams,
-    position: &Position,
+    seconds_pending: f64,
 ) -> Order {
-    if bot_params.wallet_exposure_limit == 0.0 || state_params.balance <= 0.0 {
+    // limit at EMA band until timeout, then market-equivalent order at ask
+    let balance = bot_params.entry_sizing_balance(state_params);
+    if bot_params.wallet_exposure_limit == 0.0 || balance <= 0.0 {
         return Order::default();
     }
-    let initial_entry_price = calc_ema_price_bid(
-        exchange_params.price_step,
-        state_params.order_book.bid,
-        state_params.ema_bands.lower,
-        bot_params.entry_initial_ema_dist,
-    );
+    let initial_entry_price = if bot_params.entry_initial_timeout_sec > 0.0
+        && seconds_pending >= bot_params.entry_initial_timeout_sec
+    {
+        state_params.order_book.ask
+    } else {
+        calc_initial_entry_price_long(exchange_params, state_params, bot_params)
+    };
     if initial_entry_price <= exchange_params.price_step {
         return Order::default();
     }
-    let initial_entry_qty = calc_initial_entry_qty(
-        exchange_params,
-        bot_params,
-        state_params.balance,
-        initial_entry_price,
-    );
-    if position.size == 0.0 {
-        return Order {
-            qty: initial_entry_qty,
-            price: initial_entry_price,
-            order_type: OrderType::EntryInitialNormalLong,
-        };
+    Order {
+        qty: calc_initial_entry_qty(exchange_params, bot_params, balance, initial_entry_price),
+        price: initial_entry_price,
+        order_type: OrderType::EntryInitialNormalLong,
+    }
+}
+
+/// Next grid entry. An order at the price of an entry filled on the current tick is dropped,
+/// so that re-evaluating right after a fill cannot place the same order again.
+#[must_use]
+pub fn calc_grid_entry_long(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+) -> Order {
+    if !check_side(position, Side::Long) {
+        return Order::default();
+    }
+    calc_grid_entry_long_explained(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        None,
+        &PrecomputedState::long(exchange_params, state_params, bot_params),
+    )
+    .0
+}
+
+/// wallet_exposure_limit_override, if set, is used in place of bot_params.wallet_exposure_limit,
+/// so that the dispatchers can crop an entry to part of the limit without cloning bot_params.
+fn calc_grid_entry_long_explained(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    wallet_exposure_limit_override: Option<f64>,
+    precomputed: &PrecomputedState,
+) -> (Order, EmptyReason) {
+    let (order, reason) = calc_grid_entry_long_unguarded(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        wallet_exposure_limit_override,
+        precomputed,
+    );
+    let guarded = guard_same_tick_entry(exchange_params, state_params, order);
+    if guarded.qty == 0.0 && order.qty != 0.0 {
+        (guarded, EmptyReason::SameTickFill)
+    } else {
+        (guarded, reason)
+    }
+}
+
+fn calc_grid_entry_long_unguarded(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    wallet_exposure_limit_override: Option<f64>,
+    precomputed: &PrecomputedState,
+) -> (Order, EmptyReason) {
+    let wallet_exposure_limit =
+        wallet_exposure_limit_override.unwrap_or(bot_params.wallet_exposure_limit);
+    let balance = precomputed.balance;
+    if let Some(reason) = check_exposure_limit_and_balance(wallet_exposure_limit, balance) {
+        return (Order::default(), reason);
+    }
+    let initial_entry_price =
+        cap_initial_entry_price_long(precomputed.ema_entry_price, state_params, bot_params);
+    if initial_entry_price <= exchange_params.price_step {
+        return (Order::default(), EmptyReason::PriceBelowStep);
+    }
+    let initial_entry_qty = calc_initial_entry_qty_and_cost_with_limit(
+        exchange_params,
+        bot_params,
+        wallet_exposure_limit,
+        balance,
+        initial_entry_price,
+    )
+    .0;
+    if position.size == 0.0 {
+        return (
+            Order {
+                qty: initial_entry_qty,
+                price: initial_entry_price,
+                order_type: OrderType::EntryInitialNormalLong,
+            },
+            EmptyReason::NotEmpty,
+        );
     } else if position.size < initial_entry_qty * 0.8 {
-        return Order {
-            qty: f64::max(
-                calc_min_entry_qty(initial_entry_price, &exchange_params),
-                round_dn(initial_entry_qty - position.size, exchange_params.qty_step),
-            ),
-            price: initial_entry_price,
-            order_type: OrderType::EntryInitialPartialLong,
-        };
+        return (
+            Order {
+                qty: f64::max(
+                    precomputed.min_entry_qty_at(initial_entry_price, exchange_params),
+                    round_dn(initial_entry_qty - position.size, exchange_params.qty_step),
+                ),
+                price: initial_entry_price,
+                order_type: OrderType::EntryInitialPartialLong,
+            },
+            EmptyReason::NotEmpty,
+        );
     }
     let wallet_exposure = calc_wallet_exposure(
         exchange_params.c_mult,
-        state_params.balance,
+        balance,
         position.size,
         position.price,
     );
-    if wallet_exposure >= bot_params.wallet_exposure_limit * 0.999 {
-        return Order::default();
+    if exposure_limit_reached(
+        wallet_exposure,
+        wallet_exposure_limit,
+        EXPOSURE_LIMIT_TOLERANCE,
+    ) {
+        return (Order::default(), EmptyReason::ExposureLimitReached);
     }
 
     // normal re-entry
@@ -203,37 +813,48 @@ pub fn calc_grid_entry_long(
         position.price,
         wallet_exposure,
         state_params.order_book.bid,
+        state_params.ema_bands.lower,
+        state_params.volatility,
         exchange_params,
         bot_params,
+        wallet_exposure_limit,
     );
     if reentry_price <= 0.0 {
-        return Order::default();
+        return (Order::default(), EmptyReason::PriceBelowStep);
     }
     let reentry_qty = f64::max(
-        calc_reentry_qty(
+        calc_reentry_qty_with_limit(
             reentry_price,
-            state_params.balance,
+            balance,
             position.size,
+            position.price,
             exchange_params,
             bot_params,
+            wallet_exposure_limit,
         ),
         initial_entry_qty,
     );
-    let (wallet_exposure_if_filled, reentry_qty_cropped) = calc_cropped_reentry_qty(
+    let (wallet_exposure_if_filled, reentry_qty_cropped) = calc_cropped_reentry_qty_with_limit(
         exchange_params,
-        bot_params,
+        wallet_exposure_limit,
         position,
         wallet_exposure,
-        state_params.balance,
+        balance,
         reentry_qty,
         reentry_price,
     );
+    if reentry_qty_cropped == 0.0 {
+        return (Order::default(), EmptyReason::MaxPositionSizeReached);
+    }
     if reentry_qty_cropped < reentry_qty {
-        return Order {
-            qty: reentry_qty_cropped,
-            price: reentry_price,
-            order_type: OrderType::EntryGridCroppedLong,
-        };
+        return (
+            Order {
+                qty: reentry_qty_cropped,
+                price: reentry_price,
+                order_type: OrderType::EntryGridCroppedLong,
+            },
+            EmptyReason::NotEmpty,
+        );
     }
     // preview next order to check if reentry qty is to be inflated
     let (psize_if_filled, pprice_if_filled) = calc_new_psize_pprice(
@@ -247,28 +868,33 @@ pub fn calc_grid_entry_long(
         pprice_if_filled,
         wallet_exposure_if_filled,
         state_params.order_book.bid,
+        state_params.ema_bands.lower,
+        state_params.volatility,
         exchange_params,
         bot_params,
+        wallet_exposure_limit,
     );
     let next_reentry_qty = f64::max(
-        calc_reentry_qty(
+        calc_reentry_qty_with_limit(
             next_reentry_price,
-            state_params.balance,
+            balance,
             psize_if_filled,
+            pprice_if_filled,
             exchange_params,
             bot_params,
+            wallet_exposure_limit,
         ),
         initial_entry_qty,
     );
-    let (next_wallet_exposure_if_filled, next_reentry_qty_cropped) = calc_cropped_reentry_qty(
+    let (_, next_reentry_qty_cropped) = calc_cropped_reentry_qty_with_limit(
         exchange_params,
-        bot_params,
+        wallet_exposure_limit,
         &Position {
             size: psize_if_filled,
             price: pprice_if_filled,
         },
         wallet_exposure_if_filled,
-        state_params.balance,
+        balance,
         next_reentry_qty,
         next_reentry_price,
     );
@@ -276,24 +902,114 @@ pub fn calc_grid_entry_long(
     if effective_double_down_factor < bot_params.entry_grid_double_down_factor * 0.25 {
         // next reentry too small. Inflate current reentry.
         let new_entry_qty = interpolate(
-            bot_params.wallet_exposure_limit,
+            wallet_exposure_limit,
             &[wallet_exposure, wallet_exposure_if_filled],
             &[position.size, position.size + reentry_qty],
         ) - position.size;
-        Order {
-            qty: round_(new_entry_qty, exchange_params.qty_step),
+        let new_entry_qty = round_(new_entry_qty, exchange_params.qty_step);
+        let new_entry_qty_capped =
+            calc_position_size_capped_qty(exchange_params, position, new_entry_qty, reentry_price);
+        let order = Order {
+            qty: new_entry_qty_capped,
             price: reentry_price,
-            order_type: OrderType::EntryGridInflatedLong,
-        }
+            order_type: if new_entry_qty_capped < new_entry_qty {
+                OrderType::EntryGridCroppedLong
+            } else {
+                OrderType::EntryGridInflatedLong
+            },
+        };
+        let reason = if new_entry_qty_capped != 0.0 {
+            EmptyReason::NotEmpty
+        } else if new_entry_qty > 0.0 {
+            EmptyReason::MaxPositionSizeReached
+        } else {
+            EmptyReason::QtyRoundedToZero
+        };
+        (order, reason)
     } else {
-        Order {
-            qty: reentry_qty,
-            price: reentry_price,
-            order_type: OrderType::EntryGridNormalLong,
-        }
+        (
+            Order {
+                qty: reentry_qty,
+                price: reentry_price,
+                order_type: OrderType::EntryGridNormalLong,
+            },
+            EmptyReason::NotEmpty,
+        )
+    }
+}
+
+/// Price at which calc_grid_entry_long's next re-entry fills, i.e. the re-entry price before
+/// it is capped at bid, so a live caller can wait for it instead of polling. None if flat, as
+/// the initial entry follows the EMA bands, or if no further entry fits under
+/// wallet_exposure_limit or max_position_size. EMA bands are not known here, so the price
+/// ignores entry_grid_ema_floor, which can only raise it. volatility is as in StateParams.
+pub fn calc_next_grid_trigger_price_long(
+    position: &Position,
+    balance: f64,
+    volatility: f64,
+    bot_params: &BotParams,
+    exchange_params: &ExchangeParams,
+) -> Option<f64> {
+    if !check_side(position, Side::Long) {
+        return None;
+    }
+    let balance = bot_params.usable_balance(balance);
+    if position.size <= 0.0
+        || check_exposure_limit_and_balance(bot_params.wallet_exposure_limit, balance).is_some()
+    {
+        return None;
+    }
+    let wallet_exposure = calc_wallet_exposure(
+        exchange_params.c_mult,
+        balance,
+        position.size,
+        position.price,
+    );
+    if exposure_limit_reached(
+        wallet_exposure,
+        bot_params.wallet_exposure_limit,
+        EXPOSURE_LIMIT_TOLERANCE,
+    ) {
+        return None;
+    }
+    let reentry_price = calc_reentry_price_bid(
+        position.price,
+        wallet_exposure,
+        f64::INFINITY,
+        0.0,
+        volatility,
+        exchange_params,
+        bot_params,
+        bot_params.wallet_exposure_limit,
+    );
+    if reentry_price <= 0.0 {
+        return None;
+    }
+    let reentry_qty = calc_reentry_qty(
+        reentry_price,
+        balance,
+        position.size,
+        position.price,
+        exchange_params,
+        bot_params,
+    );
+    let (_, reentry_qty_cropped) = calc_cropped_reentry_qty(
+        exchange_params,
+        bot_params,
+        position,
+        wallet_exposure,
+        balance,
+        reentry_qty,
+        reentry_price,
+    );
+    if reentry_qty_cropped == 0.0 {
+        None
+    } else {
+        Some(reentry_price)
     }
 }
 
+#[must_use]
 pub fn calc_next_entry_long(
     exchange_params: &ExchangeParams,
     state_params: &StateParams,
@@ -301,27 +1017,106 @@ pub fn calc_next_entry_long(
     position: &Position,
     trailing_price_bundle: &TrailingPriceBundle,
 ) -> Order {
+    calc_next_entry_long_with_precomputed(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+        &PrecomputedState::long(exchange_params, state_params, bot_params),
+    )
+}
+
+/// Same as calc_next_entry_long, with the tick invariant inputs taken from precomputed.
+#[must_use]
+pub fn calc_next_entry_long_with_precomputed(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    trailing_price_bundle: &TrailingPriceBundle,
+    precomputed: &PrecomputedState,
+) -> Order {
+    if !check_side(position, Side::Long) {
+        return Order::default();
+    }
+    calc_next_entry_long_explained_with_precomputed(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+        precomputed,
+    )
+    .0
+}
+
+/// Same as calc_next_entry_long, alongside the reason why the order is empty, if it is.
+#[must_use]
+pub fn calc_next_entry_long_explained(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    trailing_price_bundle: &TrailingPriceBundle,
+) -> (Order, EmptyReason) {
+    if !check_side(position, Side::Long) {
+        return (Order::default(), EmptyReason::WrongSidePosition);
+    }
+    calc_next_entry_long_explained_with_precomputed(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+        &PrecomputedState::long(exchange_params, state_params, bot_params),
+    )
+}
+
+fn calc_next_entry_long_explained_with_precomputed(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    trailing_price_bundle: &TrailingPriceBundle,
+    precomputed: &PrecomputedState,
+) -> (Order, EmptyReason) {
     // determines whether trailing or grid order, returns Order
-    if bot_params.wallet_exposure_limit == 0.0 || state_params.balance <= 0.0 {
+    let balance = precomputed.balance;
+    if let Some(reason) =
+        check_exposure_limit_and_balance(bot_params.wallet_exposure_limit, balance)
+    {
         // no orders
-        return Order::default();
+        return (Order::default(), reason);
+    }
+    if bot_params.in_entry_blackout(state_params.timestamp) {
+        return (Order::default(), EmptyReason::EntryBlackout);
     }
     if bot_params.entry_trailing_grid_ratio >= 1.0 || bot_params.entry_trailing_grid_ratio <= -1.0 {
         // return trailing only
-        return calc_trailing_entry_long(
-            &exchange_params,
-            &state_params,
-            &bot_params,
-            &position,
-            &trailing_price_bundle,
+        return calc_trailing_entry_long_explained(
+            exchange_params,
+            state_params,
+            bot_params,
+            position,
+            trailing_price_bundle,
+            None,
+            precomputed,
         );
     } else if bot_params.entry_trailing_grid_ratio == 0.0 {
         // return grid only
-        return calc_grid_entry_long(&exchange_params, &state_params, &bot_params, &position);
+        return calc_grid_entry_long_explained(
+            exchange_params,
+            state_params,
+            bot_params,
+            position,
+            None,
+            precomputed,
+        );
     }
     let wallet_exposure = calc_wallet_exposure(
         exchange_params.c_mult,
-        state_params.balance,
+        balance,
         position.size,
         position.price,
     );
@@ -330,149 +1125,296 @@ pub fn calc_next_entry_long(
         // trailing first
         if wallet_exposure_ratio < bot_params.entry_trailing_grid_ratio {
             // return trailing order, but crop to max bot_params.wallet_exposure_limit * bot_params.entry_trailing_grid_ratio + 1%
-            if wallet_exposure == 0.0 {
-                calc_trailing_entry_long(
-                    &exchange_params,
-                    &state_params,
-                    &bot_params,
-                    &position,
-                    &trailing_price_bundle,
-                )
-            } else {
-                let mut bot_params_modified = bot_params.clone();
-                bot_params_modified.wallet_exposure_limit =
-                    bot_params.wallet_exposure_limit * bot_params.entry_trailing_grid_ratio * 1.01;
-                calc_trailing_entry_long(
-                    &exchange_params,
-                    &state_params,
-                    &bot_params_modified,
-                    &position,
-                    &trailing_price_bundle,
-                )
-            }
+            let wallet_exposure_limit_override = (wallet_exposure != 0.0).then_some(
+                bot_params.wallet_exposure_limit * bot_params.entry_trailing_grid_ratio * 1.01,
+            );
+            calc_trailing_entry_long_explained(
+                exchange_params,
+                state_params,
+                bot_params,
+                position,
+                trailing_price_bundle,
+                wallet_exposure_limit_override,
+                precomputed,
+            )
         } else {
             // return grid order
-            calc_grid_entry_long(&exchange_params, &state_params, &bot_params, &position)
+            calc_grid_entry_long_explained(
+                exchange_params,
+                state_params,
+                bot_params,
+                position,
+                None,
+                precomputed,
+            )
         }
     } else {
         // grid first
         if wallet_exposure_ratio < 1.0 + bot_params.entry_trailing_grid_ratio {
             // return grid order, but crop to max bot_params.wallet_exposure_limit * (1.0 + bot_params.entry_trailing_grid_ratio) + 1%
-            if wallet_exposure == 0.0 {
-                calc_grid_entry_long(&exchange_params, &state_params, &bot_params, &position)
-            } else {
-                let mut bot_params_modified = bot_params.clone();
-                if wallet_exposure != 0.0 {
-                    bot_params_modified.wallet_exposure_limit = bot_params.wallet_exposure_limit
-                        * (1.0 + bot_params.entry_trailing_grid_ratio)
-                        * 1.01;
-                }
-                calc_grid_entry_long(
-                    &exchange_params,
-                    &state_params,
-                    &bot_params_modified,
-                    &position,
-                )
-            }
+            let wallet_exposure_limit_override = (wallet_exposure != 0.0).then_some(
+                bot_params.wallet_exposure_limit
+                    * (1.0 + bot_params.entry_trailing_grid_ratio)
+                    * 1.01,
+            );
+            calc_grid_entry_long_explained(
+                exchange_params,
+                state_params,
+                bot_params,
+                position,
+                wallet_exposure_limit_override,
+                precomputed,
+            )
         } else {
-            calc_trailing_entry_long(
-                &exchange_params,
-                &state_params,
-                &bot_params,
-                &position,
-                &trailing_price_bundle,
+            calc_trailing_entry_long_explained(
+                exchange_params,
+                state_params,
+                bot_params,
+                position,
+                trailing_price_bundle,
+                None,
+                precomputed,
             )
         }
     }
 }
 
-pub fn calc_trailing_entry_long(
+/// Entries of the Blended entry_trailing_grid_mode: for 0 < entry_trailing_grid_ratio < 1, a
+/// trailing entry and a grid entry side by side instead of one or the other. The trailing entry
+/// may use entry_trailing_grid_ratio of the remaining exposure and the grid entry the rest, so
+/// both filling stays within wallet_exposure_limit. Trailing entry first.
+/// In Switch mode, for other ratios and while the initial entry is unfilled, the single entry of
+/// calc_next_entry_long, or none if it is empty.
+#[must_use]
+pub fn calc_blended_entries_long(
     exchange_params: &ExchangeParams,
     state_params: &StateParams,
     bot_params: &BotParams,
     position: &Position,
     trailing_price_bundle: &TrailingPriceBundle,
-) -> Order {
-    let initial_entry_price = calc_ema_price_bid(
-        exchange_params.price_step,
-        state_params.order_book.bid,
-        state_params.ema_bands.lower,
-        bot_params.entry_initial_ema_dist,
-    );
-    if initial_entry_price <= exchange_params.price_step {
-        return Order::default();
-    }
-    let initial_entry_qty = calc_initial_entry_qty(
+) -> Vec<Order> {
+    calc_blended_entries_long_with_precomputed(
         exchange_params,
+        state_params,
         bot_params,
-        state_params.balance,
-        initial_entry_price,
-    );
-    if position.size == 0.0 {
-        // normal initial entry
-        return Order {
-            qty: initial_entry_qty,
-            price: initial_entry_price,
-            order_type: OrderType::EntryInitialNormalLong,
-        };
-    } else if position.size < initial_entry_qty * 0.8 {
-        return Order {
-            qty: f64::max(
-                calc_min_entry_qty(initial_entry_price, &exchange_params),
-                round_dn(initial_entry_qty - position.size, exchange_params.qty_step),
-            ),
-            price: initial_entry_price,
-            order_type: OrderType::EntryInitialPartialLong,
+        position,
+        trailing_price_bundle,
+        &PrecomputedState::long(exchange_params, state_params, bot_params),
+    )
+}
+
+/// Same as calc_blended_entries_long, with the tick invariant inputs taken from precomputed.
+#[must_use]
+pub fn calc_blended_entries_long_with_precomputed(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    trailing_price_bundle: &TrailingPriceBundle,
+    precomputed: &PrecomputedState,
+) -> Vec<Order> {
+    if !check_side(position, Side::Long) {
+        return Vec::new();
+    }
+    let balance = precomputed.balance;
+    let ratio = bot_params.entry_trailing_grid_ratio;
+    if bot_params.entry_trailing_grid_mode != EntryTrailingGridMode::Blended
+        || ratio <= 0.0
+        || ratio >= 1.0
+        || position.size == 0.0
+        || check_exposure_limit_and_balance(bot_params.wallet_exposure_limit, balance).is_some()
+        || bot_params.in_entry_blackout(state_params.timestamp)
+        || calc_grid_entry_long_explained(
+            exchange_params,
+            state_params,
+            bot_params,
+            position,
+            None,
+            precomputed,
+        )
+        .0
+        .order_type
+            == OrderType::EntryInitialPartialLong
+    {
+        let entry = calc_next_entry_long_with_precomputed(
+            exchange_params,
+            state_params,
+            bot_params,
+            position,
+            trailing_price_bundle,
+            precomputed,
+        );
+        return if entry.qty == 0.0 {
+            Vec::new()
+        } else {
+            vec![entry]
         };
     }
     let wallet_exposure = calc_wallet_exposure(
         exchange_params.c_mult,
-        state_params.balance,
+        balance,
         position.size,
         position.price,
     );
-    if wallet_exposure > bot_params.wallet_exposure_limit * 0.999 {
+    let remaining_exposure = bot_params.wallet_exposure_limit - wallet_exposure;
+    if remaining_exposure <= 0.0 {
+        return Vec::new();
+    }
+    fit_entries_to_headroom(
+        exchange_params,
+        [
+            (
+                calc_trailing_entry_long_explained(
+                    exchange_params,
+                    state_params,
+                    bot_params,
+                    position,
+                    trailing_price_bundle,
+                    Some(wallet_exposure + remaining_exposure * ratio),
+                    precomputed,
+                )
+                .0,
+                OrderType::EntryTrailingCroppedLong,
+            ),
+            (
+                calc_grid_entry_long_explained(
+                    exchange_params,
+                    state_params,
+                    bot_params,
+                    position,
+                    Some(wallet_exposure + remaining_exposure * (1.0 - ratio)),
+                    precomputed,
+                )
+                .0,
+                OrderType::EntryGridCroppedLong,
+            ),
+        ],
+        calc_exposure_headroom(position, balance, bot_params, exchange_params),
+    )
+}
+
+#[must_use]
+pub fn calc_trailing_entry_long(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    trailing_price_bundle: &TrailingPriceBundle,
+) -> Order {
+    if !check_side(position, Side::Long) {
         return Order::default();
     }
-    let mut entry_triggered = false;
-    let mut reentry_price = 0.0;
-    if bot_params.entry_trailing_threshold_pct <= 0.0 {
+    calc_trailing_entry_long_explained(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+        None,
+        &PrecomputedState::long(exchange_params, state_params, bot_params),
+    )
+    .0
+}
+
+fn calc_trailing_entry_long_explained(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    trailing_price_bundle: &TrailingPriceBundle,
+    wallet_exposure_limit_override: Option<f64>,
+    precomputed: &PrecomputedState,
+) -> (Order, EmptyReason) {
+    let wallet_exposure_limit =
+        wallet_exposure_limit_override.unwrap_or(bot_params.wallet_exposure_limit);
+    let balance = precomputed.balance;
+    let initial_entry_price =
+        cap_initial_entry_price_long(precomputed.ema_entry_price, state_params, bot_params);
+    if initial_entry_price <= exchange_params.price_step {
+        return (Order::default(), EmptyReason::PriceBelowStep);
+    }
+    let initial_entry_qty = calc_initial_entry_qty_and_cost_with_limit(
+        exchange_params,
+        bot_params,
+        wallet_exposure_limit,
+        balance,
+        initial_entry_price,
+    )
+    .0;
+    if position.size == 0.0 {
+        // normal initial entry
+        return (
+            Order {
+                qty: initial_entry_qty,
+                price: initial_entry_price,
+                order_type: OrderType::EntryInitialNormalLong,
+            },
+            EmptyReason::NotEmpty,
+        );
+    } else if position.size < initial_entry_qty * 0.8 {
+        return (
+            Order {
+                qty: f64::max(
+                    precomputed.min_entry_qty_at(initial_entry_price, exchange_params),
+                    round_dn(initial_entry_qty - position.size, exchange_params.qty_step),
+                ),
+                price: initial_entry_price,
+                order_type: OrderType::EntryInitialPartialLong,
+            },
+            EmptyReason::NotEmpty,
+        );
+    }
+    let wallet_exposure = calc_wallet_exposure(
+        exchange_params.c_mult,
+        balance,
+        position.size,
+        position.price,
+    );
+    if exposure_limit_reached(
+        wallet_exposure,
+        wallet_exposure_limit,
+        EXPOSURE_LIMIT_TOLERANCE,
+    ) {
+        return (Order::default(), EmptyReason::ExposureLimitReached);
+    }
+    if trailing_max_adds_reached(state_params, bot_params) {
+        return (Order::default(), EmptyReason::TrailingMaxAddsReached);
+    }
+    let mut entry_triggered = false;
+    let mut reentry_price = 0.0;
+    let (threshold_pct, retracement_pct) = entry_trailing_pcts(bot_params);
+    if threshold_pct <= 0.0 {
         // means trailing entry immediately from pos change
-        if bot_params.entry_trailing_retracement_pct > 0.0
+        if retracement_pct > 0.0
             && trailing_price_bundle.max_since_min
-                > trailing_price_bundle.min_since_open
-                    * (1.0 + bot_params.entry_trailing_retracement_pct)
+                > trailing_price_bundle.min_since_open * (1.0 + retracement_pct)
         {
             entry_triggered = true;
             reentry_price = state_params.order_book.bid;
         }
     } else {
         // means trailing entry will activate only after a threshold
-        if bot_params.entry_trailing_retracement_pct <= 0.0 {
+        if retracement_pct <= 0.0 {
             // close at threshold
             entry_triggered = true;
             reentry_price = f64::min(
                 state_params.order_book.bid,
                 round_dn(
-                    position.price * (1.0 - bot_params.entry_trailing_threshold_pct),
+                    position.price * (1.0 - threshold_pct),
                     exchange_params.price_step,
                 ),
             );
         } else {
             // enter if both conditions are met
-            if trailing_price_bundle.min_since_open
-                < position.price * (1.0 - bot_params.entry_trailing_threshold_pct)
+            if trailing_price_bundle.min_since_open < position.price * (1.0 - threshold_pct)
                 && trailing_price_bundle.max_since_min
-                    > trailing_price_bundle.min_since_open
-                        * (1.0 + bot_params.entry_trailing_retracement_pct)
+                    > trailing_price_bundle.min_since_open * (1.0 + retracement_pct)
             {
                 entry_triggered = true;
                 reentry_price = f64::min(
                     state_params.order_book.bid,
                     round_dn(
-                        position.price
-                            * (1.0 - bot_params.entry_trailing_threshold_pct
-                                + bot_params.entry_trailing_retracement_pct),
+                        position.price * (1.0 - threshold_pct + retracement_pct),
                         exchange_params.price_step,
                     ),
                 );
@@ -480,70 +1422,286 @@ pub fn calc_trailing_entry_long(
         }
     }
     if !entry_triggered {
-        return Order {
-            qty: 0.0,
-            price: 0.0,
-            order_type: OrderType::EntryTrailingNormalLong,
-        };
+        return (
+            Order {
+                qty: 0.0,
+                price: 0.0,
+                order_type: OrderType::EntryTrailingNormalLong,
+            },
+            EmptyReason::TrailingNotTriggered,
+        );
     }
     let reentry_qty = f64::max(
-        calc_reentry_qty(
+        calc_reentry_qty_with_limit(
             reentry_price,
-            state_params.balance,
+            balance,
             position.size,
-            &exchange_params,
-            &bot_params,
+            position.price,
+            exchange_params,
+            bot_params,
+            wallet_exposure_limit,
         ),
         initial_entry_qty,
     );
-    let (wallet_exposure_if_filled, reentry_qty_cropped) = calc_cropped_reentry_qty(
+    let (_, reentry_qty_cropped) = calc_cropped_reentry_qty_with_limit(
         exchange_params,
-        bot_params,
+        wallet_exposure_limit,
         position,
         wallet_exposure,
-        state_params.balance,
+        balance,
         reentry_qty,
         reentry_price,
     );
+    if reentry_qty_cropped == 0.0 {
+        return (Order::default(), EmptyReason::MaxPositionSizeReached);
+    }
     if reentry_qty_cropped < reentry_qty {
-        Order {
-            qty: reentry_qty_cropped,
-            price: reentry_price,
-            order_type: OrderType::EntryTrailingCroppedLong,
-        }
+        (
+            Order {
+                qty: reentry_qty_cropped,
+                price: reentry_price,
+                order_type: OrderType::EntryTrailingCroppedLong,
+            },
+            EmptyReason::NotEmpty,
+        )
     } else {
-        Order {
-            qty: reentry_qty,
-            price: reentry_price,
-            order_type: OrderType::EntryTrailingNormalLong,
+        (
+            Order {
+                qty: reentry_qty,
+                price: reentry_price,
+                order_type: OrderType::EntryTrailingNormalLong,
+            },
+            EmptyReason::NotEmpty,
+        )
+    }
+}
+
+#[must_use]
+pub fn calc_twap_entry_long(
+    total_qty: f64,
+    n_slices: usize,
+    slice_index: usize,
+    order_book: &OrderBook,
+    exchange_params: &ExchangeParams,
+) -> Order {
+    // splits an initial entry across time; caller decides when each slice is due
+    if n_slices == 0 || slice_index >= n_slices || total_qty <= 0.0 {
+        return Order::default();
+    }
+    let slice_qty = round_dn(total_qty / n_slices as f64, exchange_params.qty_step);
+    let qty = if slice_index == n_slices - 1 {
+        // final slice carries the rounding remainder
+        round_(
+            total_qty - slice_qty * (n_slices - 1) as f64,
+            exchange_params.qty_step,
+        )
+    } else {
+        slice_qty
+    };
+    if qty <= 0.0 {
+        return Order::default();
+    }
+    Order {
+        qty,
+        price: order_book.bid,
+        order_type: OrderType::EntryInitialPartialLong,
+    }
+}
+
+/// Initial entry qty split over n_stages orders, the first at the initial entry price and each
+/// next entry_grid_spacing_pct below the previous. Quantities sum to calc_initial_entry_qty;
+/// stages are dropped while a slice would fall below the min entry qty. For a flat position.
+#[must_use]
+pub fn calc_staged_initial_entry_long(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    n_stages: usize,
+) -> Vec<Order> {
+    let balance = bot_params.entry_sizing_balance(state_params);
+    if n_stages == 0
+        || check_exposure_limit_and_balance(bot_params.wallet_exposure_limit, balance).is_some()
+    {
+        return Vec::new();
+    }
+    let initial_entry_price =
+        calc_initial_entry_price_long(exchange_params, state_params, bot_params);
+    if initial_entry_price <= exchange_params.price_step {
+        return Vec::new();
+    }
+    let total_qty =
+        calc_initial_entry_qty(exchange_params, bot_params, balance, initial_entry_price);
+    let mut prices = vec![initial_entry_price];
+    for _ in 1..n_stages {
+        let prev = prices[prices.len() - 1];
+        // at least one tick apart, so coarse price steps do not stack stages
+        let price = f64::min(
+            round_dn(
+                prev * (1.0 - bot_params.entry_grid_spacing_pct),
+                exchange_params.price_step,
+            ),
+            round_(
+                prev - exchange_params.price_step,
+                exchange_params.price_step,
+            ),
+        );
+        if price <= exchange_params.price_step {
+            break;
+        }
+        prices.push(price);
+    }
+    let slices = split_staged_qty(total_qty, &prices, exchange_params);
+    slices
+        .into_iter()
+        .enumerate()
+        .map(|(i, (qty, price))| {
+            let order_type = if i == 0 {
+                OrderType::EntryInitialNormalLong
+            } else {
+                OrderType::EntryInitialPartialLong
+            };
+            Order::new(qty, price, order_type)
+        })
+        .collect()
+}
+
+/// Splits total_qty evenly over the leading prices, the last slice carrying the rounding
+/// remainder. Uses as many prices as keep every slice at or above its min entry qty.
+fn split_staged_qty(
+    total_qty: f64,
+    prices: &[f64],
+    exchange_params: &ExchangeParams,
+) -> Vec<(f64, f64)> {
+    let mut n = prices.len();
+    while n > 1 {
+        let slice_qty = round_dn(total_qty / n as f64, exchange_params.qty_step);
+        // min qty follows min_cost, so it differs per price
+        if prices[..n]
+            .iter()
+            .all(|&price| slice_qty >= calc_min_entry_qty(price, exchange_params))
+        {
+            break;
         }
+        n -= 1;
+    }
+    let slice_qty = round_dn(total_qty / n as f64, exchange_params.qty_step);
+    prices[..n]
+        .iter()
+        .enumerate()
+        .map(|(i, &price)| {
+            let qty = if i == n - 1 {
+                round_(
+                    total_qty - slice_qty * (n - 1) as f64,
+                    exchange_params.qty_step,
+                )
+            } else {
+                slice_qty
+            };
+            (qty, price)
+        })
+        .collect()
+}
+
+#[must_use]
+pub fn calc_initial_entry_short_with_timeout(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    seconds_pending: f64,
+) -> Order {
+    // limit at EMA band until timeout, then market-equivalent order at bid
+    let balance = bot_params.entry_sizing_balance(state_params);
+    if bot_params.wallet_exposure_limit == 0.0 || balance <= 0.0 {
+        return Order::default();
+    }
+    let initial_entry_price = if bot_params.entry_initial_timeout_sec > 0.0
+        && seconds_pending >= bot_params.entry_initial_timeout_sec
+    {
+        state_params.order_book.bid
+    } else {
+        calc_initial_entry_price_short(exchange_params, state_params, bot_params)
+    };
+    if initial_entry_price <= exchange_params.price_step {
+        return Order::default();
+    }
+    Order {
+        qty: -calc_initial_entry_qty(exchange_params, bot_params, balance, initial_entry_price),
+        price: initial_entry_price,
+        order_type: OrderType::EntryInitialNormalShort,
     }
 }
 
+/// Next grid entry. An order at the price of an entry filled on the current tick is dropped,
+/// so that re-evaluating right after a fill cannot place the same order again.
+#[must_use]
 pub fn calc_grid_entry_short(
     exchange_params: &ExchangeParams,
     state_params: &StateParams,
     bot_params: &BotParams,
     position: &Position,
 ) -> Order {
-    if bot_params.wallet_exposure_limit == 0.0 || state_params.balance <= 0.0 {
+    if !check_side(position, Side::Short) {
         return Order::default();
     }
-    let initial_entry_price = calc_ema_price_ask(
-        exchange_params.price_step,
-        state_params.order_book.ask,
-        state_params.ema_bands.upper,
-        bot_params.entry_initial_ema_dist,
-    );
+    calc_grid_entry_short_with_override(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        None,
+        &PrecomputedState::short(exchange_params, state_params, bot_params),
+    )
+}
+
+fn calc_grid_entry_short_with_override(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    wallet_exposure_limit_override: Option<f64>,
+    precomputed: &PrecomputedState,
+) -> Order {
+    guard_same_tick_entry(
+        exchange_params,
+        state_params,
+        calc_grid_entry_short_unguarded(
+            exchange_params,
+            state_params,
+            bot_params,
+            position,
+            wallet_exposure_limit_override,
+            precomputed,
+        ),
+    )
+}
+
+fn calc_grid_entry_short_unguarded(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    wallet_exposure_limit_override: Option<f64>,
+    precomputed: &PrecomputedState,
+) -> Order {
+    let wallet_exposure_limit =
+        wallet_exposure_limit_override.unwrap_or(bot_params.wallet_exposure_limit);
+    let balance = precomputed.balance;
+    if wallet_exposure_limit == 0.0 || balance <= 0.0 {
+        return Order::default();
+    }
+    let initial_entry_price =
+        cap_initial_entry_price_short(precomputed.ema_entry_price, state_params, bot_params);
     if initial_entry_price <= exchange_params.price_step {
         return Order::default();
     }
-    let initial_entry_qty = calc_initial_entry_qty(
+    let initial_entry_qty = calc_initial_entry_qty_and_cost_with_limit(
         exchange_params,
         bot_params,
-        state_params.balance,
+        wallet_exposure_limit,
+        balance,
         initial_entry_price,
-    );
+    )
+    .0;
     let position_size_abs = position.size.abs();
     if position_size_abs == 0.0 {
         return Order {
@@ -554,7 +1712,7 @@ pub fn calc_grid_entry_short(
     } else if position_size_abs < initial_entry_qty * 0.8 {
         return Order {
             qty: -f64::max(
-                calc_min_entry_qty(initial_entry_price, &exchange_params),
+                precomputed.min_entry_qty_at(initial_entry_price, exchange_params),
                 round_dn(
                     initial_entry_qty - position_size_abs,
                     exchange_params.qty_step,
@@ -566,11 +1724,15 @@ pub fn calc_grid_entry_short(
     }
     let wallet_exposure = calc_wallet_exposure(
         exchange_params.c_mult,
-        state_params.balance,
+        balance,
         position_size_abs,
         position.price,
     );
-    if wallet_exposure >= bot_params.wallet_exposure_limit * 0.999 {
+    if exposure_limit_reached(
+        wallet_exposure,
+        wallet_exposure_limit,
+        EXPOSURE_LIMIT_TOLERANCE,
+    ) {
         return Order::default();
     }
 
@@ -579,31 +1741,39 @@ pub fn calc_grid_entry_short(
         position.price,
         wallet_exposure,
         state_params.order_book.ask,
+        state_params.ema_bands.upper,
+        state_params.volatility,
         exchange_params,
         bot_params,
+        wallet_exposure_limit,
     );
     if reentry_price <= 0.0 {
         return Order::default();
     }
     let reentry_qty = f64::max(
-        calc_reentry_qty(
+        calc_reentry_qty_with_limit(
             reentry_price,
-            state_params.balance,
+            balance,
             position_size_abs,
+            position.price,
             exchange_params,
             bot_params,
+            wallet_exposure_limit,
         ),
         initial_entry_qty,
     );
-    let (wallet_exposure_if_filled, reentry_qty_cropped) = calc_cropped_reentry_qty(
+    let (wallet_exposure_if_filled, reentry_qty_cropped) = calc_cropped_reentry_qty_with_limit(
         exchange_params,
-        bot_params,
+        wallet_exposure_limit,
         position,
         wallet_exposure,
-        state_params.balance,
+        balance,
         reentry_qty,
         reentry_price,
     );
+    if reentry_qty_cropped == 0.0 {
+        return Order::default();
+    }
     if reentry_qty_cropped < reentry_qty {
         return Order {
             qty: -reentry_qty_cropped,
@@ -623,28 +1793,33 @@ pub fn calc_grid_entry_short(
         pprice_if_filled,
         wallet_exposure_if_filled,
         state_params.order_book.ask,
+        state_params.ema_bands.upper,
+        state_params.volatility,
         exchange_params,
         bot_params,
+        wallet_exposure_limit,
     );
     let next_reentry_qty = f64::max(
-        calc_reentry_qty(
+        calc_reentry_qty_with_limit(
             next_reentry_price,
-            state_params.balance,
+            balance,
             psize_if_filled,
+            pprice_if_filled,
             exchange_params,
             bot_params,
+            wallet_exposure_limit,
         ),
         initial_entry_qty,
     );
-    let (next_wallet_exposure_if_filled, next_reentry_qty_cropped) = calc_cropped_reentry_qty(
+    let (_, next_reentry_qty_cropped) = calc_cropped_reentry_qty_with_limit(
         exchange_params,
-        bot_params,
+        wallet_exposure_limit,
         &Position {
             size: psize_if_filled,
             price: pprice_if_filled,
         },
         wallet_exposure_if_filled,
-        state_params.balance,
+        balance,
         next_reentry_qty,
         next_reentry_price,
     );
@@ -652,14 +1827,21 @@ pub fn calc_grid_entry_short(
     if effective_double_down_factor < bot_params.entry_grid_double_down_factor * 0.25 {
         // next reentry too small. Inflate current reentry.
         let new_entry_qty = interpolate(
-            bot_params.wallet_exposure_limit,
+            wallet_exposure_limit,
             &[wallet_exposure, wallet_exposure_if_filled],
             &[position_size_abs, position_size_abs + reentry_qty],
         ) - position_size_abs;
+        let new_entry_qty = round_(new_entry_qty, exchange_params.qty_step);
+        let new_entry_qty_capped =
+            calc_position_size_capped_qty(exchange_params, position, new_entry_qty, reentry_price);
         Order {
-            qty: -round_(new_entry_qty, exchange_params.qty_step),
+            qty: -new_entry_qty_capped,
             price: reentry_price,
-            order_type: OrderType::EntryGridInflatedShort,
+            order_type: if new_entry_qty_capped < new_entry_qty {
+                OrderType::EntryGridCroppedShort
+            } else {
+                OrderType::EntryGridInflatedShort
+            },
         }
     } else {
         Order {
@@ -670,6 +1852,7 @@ pub fn calc_grid_entry_short(
     }
 }
 
+#[must_use]
 pub fn calc_trailing_entry_short(
     exchange_params: &ExchangeParams,
     state_params: &StateParams,
@@ -677,21 +1860,45 @@ pub fn calc_trailing_entry_short(
     position: &Position,
     trailing_price_bundle: &TrailingPriceBundle,
 ) -> Order {
-    let initial_entry_price = calc_ema_price_ask(
-        exchange_params.price_step,
-        state_params.order_book.ask,
-        state_params.ema_bands.upper,
-        bot_params.entry_initial_ema_dist,
-    );
+    if !check_side(position, Side::Short) {
+        return Order::default();
+    }
+    calc_trailing_entry_short_with_override(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+        None,
+        &PrecomputedState::short(exchange_params, state_params, bot_params),
+    )
+}
+
+fn calc_trailing_entry_short_with_override(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    trailing_price_bundle: &TrailingPriceBundle,
+    wallet_exposure_limit_override: Option<f64>,
+    precomputed: &PrecomputedState,
+) -> Order {
+    let wallet_exposure_limit =
+        wallet_exposure_limit_override.unwrap_or(bot_params.wallet_exposure_limit);
+    let balance = precomputed.balance;
+    let initial_entry_price =
+        cap_initial_entry_price_short(precomputed.ema_entry_price, state_params, bot_params);
     if initial_entry_price <= exchange_params.price_step {
         return Order::default();
     }
-    let initial_entry_qty = calc_initial_entry_qty(
+    let initial_entry_qty = calc_initial_entry_qty_and_cost_with_limit(
         exchange_params,
         bot_params,
-        state_params.balance,
+        wallet_exposure_limit,
+        balance,
         initial_entry_price,
-    );
+    )
+    .0;
     let position_size_abs = position.size.abs();
     if position_size_abs == 0.0 {
         // normal initial entry
@@ -703,7 +1910,7 @@ pub fn calc_trailing_entry_short(
     } else if position_size_abs < initial_entry_qty * 0.8 {
         return Order {
             qty: -f64::max(
-                calc_min_entry_qty(initial_entry_price, &exchange_params),
+                precomputed.min_entry_qty_at(initial_entry_price, exchange_params),
                 round_dn(
                     initial_entry_qty - position_size_abs,
                     exchange_params.qty_step,
@@ -715,52 +1922,55 @@ pub fn calc_trailing_entry_short(
     }
     let wallet_exposure = calc_wallet_exposure(
         exchange_params.c_mult,
-        state_params.balance,
+        balance,
         position_size_abs,
         position.price,
     );
-    if wallet_exposure > bot_params.wallet_exposure_limit * 0.999 {
+    if exposure_limit_reached(
+        wallet_exposure,
+        wallet_exposure_limit,
+        EXPOSURE_LIMIT_TOLERANCE,
+    ) {
+        return Order::default();
+    }
+    if trailing_max_adds_reached(state_params, bot_params) {
         return Order::default();
     }
     let mut entry_triggered = false;
     let mut reentry_price = 0.0;
-    if bot_params.entry_trailing_threshold_pct <= 0.0 {
+    let (threshold_pct, retracement_pct) = entry_trailing_pcts(bot_params);
+    if threshold_pct <= 0.0 {
         // means trailing entry immediately from pos change
-        if bot_params.entry_trailing_retracement_pct > 0.0
+        if retracement_pct > 0.0
             && trailing_price_bundle.min_since_max
-                < trailing_price_bundle.max_since_open
-                    * (1.0 - bot_params.entry_trailing_retracement_pct)
+                < trailing_price_bundle.max_since_open * (1.0 - retracement_pct)
         {
             entry_triggered = true;
             reentry_price = state_params.order_book.ask;
         }
     } else {
         // means trailing entry will activate only after a threshold
-        if bot_params.entry_trailing_retracement_pct <= 0.0 {
+        if retracement_pct <= 0.0 {
             // enter at threshold
             entry_triggered = true;
             reentry_price = f64::max(
                 state_params.order_book.ask,
                 round_up(
-                    position.price * (1.0 + bot_params.entry_trailing_threshold_pct),
+                    position.price * (1.0 + threshold_pct),
                     exchange_params.price_step,
                 ),
             );
         } else {
             // enter if both conditions are met
-            if trailing_price_bundle.max_since_open
-                > position.price * (1.0 + bot_params.entry_trailing_threshold_pct)
+            if trailing_price_bundle.max_since_open > position.price * (1.0 + threshold_pct)
                 && trailing_price_bundle.min_since_max
-                    < trailing_price_bundle.max_since_open
-                        * (1.0 - bot_params.entry_trailing_retracement_pct)
+                    < trailing_price_bundle.max_since_open * (1.0 - retracement_pct)
             {
                 entry_triggered = true;
                 reentry_price = f64::max(
                     state_params.order_book.ask,
                     round_up(
-                        position.price
-                            * (1.0 + bot_params.entry_trailing_threshold_pct
-                                - bot_params.entry_trailing_retracement_pct),
+                        position.price * (1.0 + threshold_pct - retracement_pct),
                         exchange_params.price_step,
                     ),
                 );
@@ -775,24 +1985,29 @@ pub fn calc_trailing_entry_short(
         };
     }
     let reentry_qty = f64::max(
-        calc_reentry_qty(
+        calc_reentry_qty_with_limit(
             reentry_price,
-            state_params.balance,
+            balance,
             position_size_abs,
-            &exchange_params,
-            &bot_params,
+            position.price,
+            exchange_params,
+            bot_params,
+            wallet_exposure_limit,
         ),
         initial_entry_qty,
     );
-    let (wallet_exposure_if_filled, reentry_qty_cropped) = calc_cropped_reentry_qty(
+    let (_, reentry_qty_cropped) = calc_cropped_reentry_qty_with_limit(
         exchange_params,
-        bot_params,
+        wallet_exposure_limit,
         position,
         wallet_exposure,
-        state_params.balance,
+        balance,
         reentry_qty,
         reentry_price,
     );
+    if reentry_qty_cropped == 0.0 {
+        return Order::default();
+    }
     if reentry_qty_cropped < reentry_qty {
         Order {
             qty: -reentry_qty_cropped,
@@ -808,6 +2023,78 @@ pub fn calc_trailing_entry_short(
     }
 }
 
+/// Price at which calc_grid_entry_short's next re-entry fills, i.e. the re-entry price before
+/// it is floored at ask, so a live caller can wait for it instead of polling. None if flat, as
+/// the initial entry follows the EMA bands, or if no further entry fits under
+/// wallet_exposure_limit or max_position_size. EMA bands are not known here, so the price
+/// ignores entry_grid_ema_floor, which can only lower it. volatility is as in StateParams.
+pub fn calc_next_grid_trigger_price_short(
+    position: &Position,
+    balance: f64,
+    volatility: f64,
+    bot_params: &BotParams,
+    exchange_params: &ExchangeParams,
+) -> Option<f64> {
+    if !check_side(position, Side::Short) {
+        return None;
+    }
+    let balance = bot_params.usable_balance(balance);
+    if position.size >= 0.0
+        || check_exposure_limit_and_balance(bot_params.wallet_exposure_limit, balance).is_some()
+    {
+        return None;
+    }
+    let wallet_exposure = calc_wallet_exposure(
+        exchange_params.c_mult,
+        balance,
+        position.size.abs(),
+        position.price,
+    );
+    if exposure_limit_reached(
+        wallet_exposure,
+        bot_params.wallet_exposure_limit,
+        EXPOSURE_LIMIT_TOLERANCE,
+    ) {
+        return None;
+    }
+    let reentry_price = calc_reentry_price_ask(
+        position.price,
+        wallet_exposure,
+        0.0,
+        0.0,
+        volatility,
+        exchange_params,
+        bot_params,
+        bot_params.wallet_exposure_limit,
+    );
+    if reentry_price <= 0.0 {
+        return None;
+    }
+    let reentry_qty = calc_reentry_qty(
+        reentry_price,
+        balance,
+        position.size.abs(),
+        position.price,
+        exchange_params,
+        bot_params,
+    );
+    let (_, reentry_qty_cropped) = calc_cropped_reentry_qty(
+        exchange_params,
+        bot_params,
+        position,
+        wallet_exposure,
+        balance,
+        reentry_qty,
+        reentry_price,
+    );
+    if reentry_qty_cropped == 0.0 {
+        None
+    } else {
+        Some(reentry_price)
+    }
+}
+
+#[must_use]
 pub fn calc_next_entry_short(
     exchange_params: &ExchangeParams,
     state_params: &StateParams,
@@ -815,27 +2102,63 @@ pub fn calc_next_entry_short(
     position: &Position,
     trailing_price_bundle: &TrailingPriceBundle,
 ) -> Order {
+    calc_next_entry_short_with_precomputed(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+        &PrecomputedState::short(exchange_params, state_params, bot_params),
+    )
+}
+
+/// Same as calc_next_entry_short, with the tick invariant inputs taken from precomputed.
+#[must_use]
+pub fn calc_next_entry_short_with_precomputed(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    trailing_price_bundle: &TrailingPriceBundle,
+    precomputed: &PrecomputedState,
+) -> Order {
+    if !check_side(position, Side::Short) {
+        return Order::default();
+    }
     // determines whether trailing or grid order, returns Order
-    if bot_params.wallet_exposure_limit == 0.0 || state_params.balance <= 0.0 {
+    let balance = precomputed.balance;
+    if bot_params.wallet_exposure_limit == 0.0
+        || balance <= 0.0
+        || bot_params.in_entry_blackout(state_params.timestamp)
+    {
         // no orders
         return Order::default();
     }
     if bot_params.entry_trailing_grid_ratio >= 1.0 || bot_params.entry_trailing_grid_ratio <= -1.0 {
         // return trailing only
-        return calc_trailing_entry_short(
-            &exchange_params,
-            &state_params,
-            &bot_params,
-            &position,
-            &trailing_price_bundle,
+        return calc_trailing_entry_short_with_override(
+            exchange_params,
+            state_params,
+            bot_params,
+            position,
+            trailing_price_bundle,
+            None,
+            precomputed,
         );
     } else if bot_params.entry_trailing_grid_ratio == 0.0 {
         // return grid only
-        return calc_grid_entry_short(&exchange_params, &state_params, &bot_params, &position);
+        return calc_grid_entry_short_with_override(
+            exchange_params,
+            state_params,
+            bot_params,
+            position,
+            None,
+            precomputed,
+        );
     }
     let wallet_exposure = calc_wallet_exposure(
         exchange_params.c_mult,
-        state_params.balance,
+        balance,
         position.size.abs(),
         position.price,
     );
@@ -843,63 +2166,257 @@ pub fn calc_next_entry_short(
     if bot_params.entry_trailing_grid_ratio > 0.0 {
         // trailing first
         if wallet_exposure_ratio < bot_params.entry_trailing_grid_ratio {
-            if wallet_exposure == 0.0 {
-                calc_trailing_entry_short(
-                    &exchange_params,
-                    &state_params,
-                    &bot_params,
-                    &position,
-                    &trailing_price_bundle,
-                )
-            } else {
-                // return trailing order, but crop to max bot_params.wallet_exposure_limit * bot_params.entry_trailing_grid_ratio + 1%
-                let mut bot_params_modified = bot_params.clone();
-                bot_params_modified.wallet_exposure_limit =
-                    bot_params.wallet_exposure_limit * bot_params.entry_trailing_grid_ratio * 1.01;
-                calc_trailing_entry_short(
-                    &exchange_params,
-                    &state_params,
-                    &bot_params_modified,
-                    &position,
-                    &trailing_price_bundle,
-                )
-            }
+            // return trailing order, but crop to max bot_params.wallet_exposure_limit * bot_params.entry_trailing_grid_ratio + 1%
+            let wallet_exposure_limit_override = (wallet_exposure != 0.0).then_some(
+                bot_params.wallet_exposure_limit * bot_params.entry_trailing_grid_ratio * 1.01,
+            );
+            calc_trailing_entry_short_with_override(
+                exchange_params,
+                state_params,
+                bot_params,
+                position,
+                trailing_price_bundle,
+                wallet_exposure_limit_override,
+                precomputed,
+            )
         } else {
             // return grid order
-            calc_grid_entry_short(&exchange_params, &state_params, &bot_params, &position)
+            calc_grid_entry_short_with_override(
+                exchange_params,
+                state_params,
+                bot_params,
+                position,
+                None,
+                precomputed,
+            )
         }
     } else {
         // grid first
         if wallet_exposure_ratio < 1.0 + bot_params.entry_trailing_grid_ratio {
             // return grid order, but crop to max bot_params.wallet_exposure_limit * (1.0 + bot_params.entry_trailing_grid_ratio) + 1%
-            if wallet_exposure == 0.0 {
-                calc_grid_entry_short(&exchange_params, &state_params, &bot_params, &position)
-            } else {
-                let mut bot_params_modified = bot_params.clone();
-                if wallet_exposure != 0.0 {
-                    bot_params_modified.wallet_exposure_limit = bot_params.wallet_exposure_limit
-                        * (1.0 + bot_params.entry_trailing_grid_ratio)
-                        * 1.01;
-                }
-                calc_grid_entry_short(
-                    &exchange_params,
-                    &state_params,
-                    &bot_params_modified,
-                    &position,
-                )
-            }
+            let wallet_exposure_limit_override = (wallet_exposure != 0.0).then_some(
+                bot_params.wallet_exposure_limit
+                    * (1.0 + bot_params.entry_trailing_grid_ratio)
+                    * 1.01,
+            );
+            calc_grid_entry_short_with_override(
+                exchange_params,
+                state_params,
+                bot_params,
+                position,
+                wallet_exposure_limit_override,
+                precomputed,
+            )
         } else {
-            calc_trailing_entry_short(
-                &exchange_params,
-                &state_params,
-                &bot_params,
-                &position,
-                &trailing_price_bundle,
+            calc_trailing_entry_short_with_override(
+                exchange_params,
+                state_params,
+                bot_params,
+                position,
+                trailing_price_bundle,
+                None,
+                precomputed,
             )
         }
     }
 }
 
+/// Entries of the Blended entry_trailing_grid_mode: for 0 < entry_trailing_grid_ratio < 1, a
+/// trailing entry and a grid entry side by side instead of one or the other. The trailing entry
+/// may use entry_trailing_grid_ratio of the remaining exposure and the grid entry the rest, so
+/// both filling stays within wallet_exposure_limit. Trailing entry first.
+/// In Switch mode, for other ratios and while the initial entry is unfilled, the single entry of
+/// calc_next_entry_short, or none if it is empty.
+#[must_use]
+pub fn calc_blended_entries_short(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    trailing_price_bundle: &TrailingPriceBundle,
+) -> Vec<Order> {
+    calc_blended_entries_short_with_precomputed(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+        &PrecomputedState::short(exchange_params, state_params, bot_params),
+    )
+}
+
+/// Same as calc_blended_entries_short, with the tick invariant inputs taken from precomputed.
+#[must_use]
+pub fn calc_blended_entries_short_with_precomputed(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    trailing_price_bundle: &TrailingPriceBundle,
+    precomputed: &PrecomputedState,
+) -> Vec<Order> {
+    if !check_side(position, Side::Short) {
+        return Vec::new();
+    }
+    let balance = precomputed.balance;
+    let ratio = bot_params.entry_trailing_grid_ratio;
+    if bot_params.entry_trailing_grid_mode != EntryTrailingGridMode::Blended
+        || ratio <= 0.0
+        || ratio >= 1.0
+        || position.size == 0.0
+        || check_exposure_limit_and_balance(bot_params.wallet_exposure_limit, balance).is_some()
+        || bot_params.in_entry_blackout(state_params.timestamp)
+        || calc_grid_entry_short_with_override(
+            exchange_params,
+            state_params,
+            bot_params,
+            position,
+            None,
+            precomputed,
+        )
+        .order_type
+            == OrderType::EntryInitialPartialShort
+    {
+        let entry = calc_next_entry_short_with_precomputed(
+            exchange_params,
+            state_params,
+            bot_params,
+            position,
+            trailing_price_bundle,
+            precomputed,
+        );
+        return if entry.qty == 0.0 {
+            Vec::new()
+        } else {
+            vec![entry]
+        };
+    }
+    let wallet_exposure = calc_wallet_exposure(
+        exchange_params.c_mult,
+        balance,
+        position.size.abs(),
+        position.price,
+    );
+    let remaining_exposure = bot_params.wallet_exposure_limit - wallet_exposure;
+    if remaining_exposure <= 0.0 {
+        return Vec::new();
+    }
+    fit_entries_to_headroom(
+        exchange_params,
+        [
+            (
+                calc_trailing_entry_short_with_override(
+                    exchange_params,
+                    state_params,
+                    bot_params,
+                    position,
+                    trailing_price_bundle,
+                    Some(wallet_exposure + remaining_exposure * ratio),
+                    precomputed,
+                ),
+                OrderType::EntryTrailingCroppedShort,
+            ),
+            (
+                calc_grid_entry_short_with_override(
+                    exchange_params,
+                    state_params,
+                    bot_params,
+                    position,
+                    Some(wallet_exposure + remaining_exposure * (1.0 - ratio)),
+                    precomputed,
+                ),
+                OrderType::EntryGridCroppedShort,
+            ),
+        ],
+        calc_exposure_headroom(position, balance, bot_params, exchange_params),
+    )
+}
+
+#[must_use]
+pub fn calc_twap_entry_short(
+    total_qty: f64,
+    n_slices: usize,
+    slice_index: usize,
+    order_book: &OrderBook,
+    exchange_params: &ExchangeParams,
+) -> Order {
+    // total_qty is given as a positive number; returned order qty is negative
+    if n_slices == 0 || slice_index >= n_slices || total_qty <= 0.0 {
+        return Order::default();
+    }
+    let slice_qty = round_dn(total_qty / n_slices as f64, exchange_params.qty_step);
+    let qty = if slice_index == n_slices - 1 {
+        round_(
+            total_qty - slice_qty * (n_slices - 1) as f64,
+            exchange_params.qty_step,
+        )
+    } else {
+        slice_qty
+    };
+    if qty <= 0.0 {
+        return Order::default();
+    }
+    Order {
+        qty: -qty,
+        price: order_book.ask,
+        order_type: OrderType::EntryInitialPartialShort,
+    }
+}
+
+/// Initial entry qty split over n_stages orders, the first at the initial entry price and each
+/// next entry_grid_spacing_pct above the previous. Quantities sum to calc_initial_entry_qty;
+/// stages are dropped while a slice would fall below the min entry qty. For a flat position.
+#[must_use]
+pub fn calc_staged_initial_entry_short(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    n_stages: usize,
+) -> Vec<Order> {
+    let balance = bot_params.entry_sizing_balance(state_params);
+    if n_stages == 0
+        || check_exposure_limit_and_balance(bot_params.wallet_exposure_limit, balance).is_some()
+    {
+        return Vec::new();
+    }
+    let initial_entry_price =
+        calc_initial_entry_price_short(exchange_params, state_params, bot_params);
+    if initial_entry_price <= exchange_params.price_step {
+        return Vec::new();
+    }
+    let total_qty =
+        calc_initial_entry_qty(exchange_params, bot_params, balance, initial_entry_price);
+    let mut prices = vec![initial_entry_price];
+    for _ in 1..n_stages {
+        let prev = prices[prices.len() - 1];
+        let price = f64::max(
+            round_up(
+                prev * (1.0 + bot_params.entry_grid_spacing_pct),
+                exchange_params.price_step,
+            ),
+            round_(
+                prev + exchange_params.price_step,
+                exchange_params.price_step,
+            ),
+        );
+        prices.push(price);
+    }
+    let slices = split_staged_qty(total_qty, &prices, exchange_params);
+    slices
+        .into_iter()
+        .enumerate()
+        .map(|(i, (qty, price))| {
+            let order_type = if i == 0 {
+                OrderType::EntryInitialNormalShort
+            } else {
+                OrderType::EntryInitialPartialShort
+            };
+            Order::new(-qty, price, order_type)
+        })
+        .collect()
+}
+
+#[must_use]
 pub fn calc_entries_long(
     exchange_params: &ExchangeParams,
     state_params: &StateParams,
@@ -907,6 +2424,29 @@ pub fn calc_entries_long(
     position: &Position,
     trailing_price_bundle: &TrailingPriceBundle,
 ) -> Vec<Order> {
+    calc_entries_long_with_precomputed(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+        &PrecomputedState::long(exchange_params, state_params, bot_params),
+    )
+}
+
+/// Same as calc_entries_long, with the tick invariant inputs taken from precomputed.
+#[must_use]
+pub fn calc_entries_long_with_precomputed(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    trailing_price_bundle: &TrailingPriceBundle,
+    precomputed: &PrecomputedState,
+) -> Vec<Order> {
+    if !check_side(position, Side::Long) {
+        return Vec::new();
+    }
     let mut entries = Vec::<Order>::new();
     let mut psize = position.size;
     let mut pprice = position.price;
@@ -918,12 +2458,13 @@ pub fn calc_entries_long(
         };
         let mut state_params_mod = state_params.clone();
         state_params_mod.order_book.bid = bid;
-        let entry = calc_next_entry_long(
+        let entry = calc_next_entry_long_with_precomputed(
             exchange_params,
             &state_params_mod,
             bot_params,
             &position_mod,
-            &trailing_price_bundle,
+            trailing_price_bundle,
+            precomputed,
         );
         if entry.qty == 0.0 {
             break;
@@ -948,9 +2489,15 @@ pub fn calc_entries_long(
         bid = bid.min(entry.price);
         entries.push(entry);
     }
+    if bot_params.coalesce_grid_orders {
+        entries = coalesce_orders(&entries, exchange_params);
+    }
+    entries = apply_price_band(&entries, &state_params.order_book, exchange_params);
+    debug_assert_signs(&entries);
     entries
 }
 
+#[must_use]
 pub fn calc_entries_short(
     exchange_params: &ExchangeParams,
     state_params: &StateParams,
@@ -958,6 +2505,29 @@ pub fn calc_entries_short(
     position: &Position,
     trailing_price_bundle: &TrailingPriceBundle,
 ) -> Vec<Order> {
+    calc_entries_short_with_precomputed(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+        &PrecomputedState::short(exchange_params, state_params, bot_params),
+    )
+}
+
+/// Same as calc_entries_short, with the tick invariant inputs taken from precomputed.
+#[must_use]
+pub fn calc_entries_short_with_precomputed(
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    trailing_price_bundle: &TrailingPriceBundle,
+    precomputed: &PrecomputedState,
+) -> Vec<Order> {
+    if !check_side(position, Side::Short) {
+        return Vec::new();
+    }
     let mut entries = Vec::<Order>::new();
     let mut psize = position.size;
     let mut pprice = position.price;
@@ -969,12 +2539,13 @@ pub fn calc_entries_short(
         };
         let mut state_params_mod = state_params.clone();
         state_params_mod.order_book.ask = ask;
-        let entry = calc_next_entry_short(
+        let entry = calc_next_entry_short_with_precomputed(
             exchange_params,
             &state_params_mod,
             bot_params,
             &position_mod,
-            &trailing_price_bundle,
+            trailing_price_bundle,
+            precomputed,
         );
         if entry.qty == 0.0 {
             break;
@@ -999,5 +2570,1959 @@ pub fn calc_entries_short(
         ask = ask.max(entry.price);
         entries.push(entry);
     }
+    if bot_params.coalesce_grid_orders {
+        entries = coalesce_orders(&entries, exchange_params);
+    }
+    entries = apply_price_band(&entries, &state_params.order_book, exchange_params);
+    debug_assert_signs(&entries);
     entries
 }
+
+#[must_use]
+pub fn calc_grid_depth_long(
+    exchange_params: &ExchangeParams,
+    bot_params: &BotParams,
+    balance: f64,
+    entry_price: f64,
+) -> usize {
+    // number of grid entries from flat until wallet exposure limit, with book and EMAs at entry_price
+    let bot_params_grid = BotParams {
+        entry_trailing_grid_ratio: 0.0,
+        coalesce_grid_orders: false,
+        ..bot_params.clone()
+    };
+    let state_params = StateParams {
+        balance,
+        order_book: OrderBook {
+            bid: entry_price,
+            ask: entry_price,
+        },
+        ema_bands: EMABands {
+            upper: entry_price,
+            lower: entry_price,
+        },
+        ..Default::default()
+    };
+    calc_entries_long(
+        exchange_params,
+        &state_params,
+        &bot_params_grid,
+        &Position::default(),
+        &TrailingPriceBundle::default(),
+    )
+    .len()
+}
+
+#[must_use]
+pub fn calc_balance_for_grid_depth(
+    bot_params: &BotParams,
+    exchange_params: &ExchangeParams,
+    entry_price: f64,
+    n_levels: usize,
+) -> f64 {
+    // minimum balance for which the long grid from flat has at least n_levels entries.
+    // Small balances lose levels to min qty and min cost; returns f64::INFINITY if
+    // n_levels is not reached at any balance.
+    if bot_params.wallet_exposure_limit <= 0.0 || entry_price <= 0.0 {
+        return f64::INFINITY;
+    }
+    let depth_at =
+        |balance: f64| calc_grid_depth_long(exchange_params, bot_params, balance, entry_price);
+    // below this, even a single min qty entry exceeds the wallet exposure limit
+    let min_balance = qty_to_cost(
+        calc_min_entry_qty(entry_price, exchange_params),
+        entry_price,
+        exchange_params.c_mult,
+    ) / bot_params.wallet_exposure_limit;
+    if depth_at(min_balance) >= n_levels {
+        return min_balance;
+    }
+    let mut low = min_balance;
+    let mut high = min_balance * 2.0;
+    while depth_at(high) < n_levels {
+        // far beyond min qty effects, depth no longer grows with balance
+        if high > min_balance * 1e12 {
+            return f64::INFINITY;
+        }
+        low = high;
+        high *= 2.0;
+    }
+    for _ in 0..100 {
+        if high - low <= high * 1e-6 {
+            break;
+        }
+        let mid = (low + high) * 0.5;
+        if depth_at(mid) >= n_levels {
+            high = mid;
+        } else {
+            low = mid;
+        }
+    }
+    high
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::types::{EntrySizingBasis, LastFill};
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() <= expected.abs() * 1e-12,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    fn exchange_params(c_mult: f64) -> ExchangeParams {
+        ExchangeParams {
+            qty_step: 0.001,
+            price_step: 0.01,
+            min_qty: 0.001,
+            min_cost: 5.0,
+            c_mult,
+            ..Default::default()
+        }
+    }
+
+    // (c_mult, initial entry qty, min entry qty) at price 100.0 for 10% of 1.0 * 10000.0
+    const C_MULT_CASES: [(f64, f64, f64); 3] = [
+        (0.001, 10000.0, 50.0),
+        (1.0, 10.0, 0.05),
+        (100.0, 0.1, 0.001),
+    ];
+
+    #[test]
+    fn initial_entry_qty_scales_with_c_mult() {
+        let bot_params = BotParams {
+            wallet_exposure_limit: 1.0,
+            entry_initial_qty_pct: 0.1,
+            ..Default::default()
+        };
+        for (c_mult, expected_qty, expected_min_qty) in C_MULT_CASES {
+            let exchange_params = exchange_params(c_mult);
+            let (qty, cost) =
+                calc_initial_entry_qty_and_cost(&exchange_params, &bot_params, 10000.0, 100.0);
+            assert_close(qty, expected_qty);
+            assert_close(cost, 1000.0);
+            assert_close(
+                calc_initial_entry_qty(&exchange_params, &bot_params, 10000.0, 100.0),
+                expected_qty,
+            );
+            assert_close(
+                calc_min_entry_qty(100.0, &exchange_params),
+                expected_min_qty,
+            );
+        }
+    }
+
+    #[test]
+    fn wallet_exposure_consistent_across_c_mult() {
+        for (c_mult, qty, _) in C_MULT_CASES {
+            let exchange_params = exchange_params(c_mult);
+            // cost 1000.0 against balance 10000.0
+            assert_close(calc_wallet_exposure(c_mult, 10000.0, qty, 100.0), 0.1);
+            assert_close(calc_wallet_exposure(c_mult, 10000.0, -qty, 100.0), 0.1);
+            assert_close(
+                calc_wallet_exposure_if_filled(10000.0, 0.0, 0.0, qty, 100.0, &exchange_params),
+                0.1,
+            );
+            // doubling at 110.0 gives 2 * qty at 105.0: cost 2100.0
+            assert_close(
+                calc_wallet_exposure_if_filled(10000.0, qty, 100.0, qty, 110.0, &exchange_params),
+                0.21,
+            );
+        }
+    }
+
+    #[test]
+    fn cropped_reentry_stays_within_limit_after_rounding() {
+        // coarse qty_step: cost is qty * price, so the cropped qty fills to exposure
+        // (3000.0 + qty * price) / 10000.0
+        let exchange_params = ExchangeParams {
+            qty_step: 1.0,
+            price_step: 0.01,
+            min_qty: 1.0,
+            min_cost: 5.0,
+            c_mult: 1.0,
+            ..Default::default()
+        };
+        let position = Position {
+            size: 30.0,
+            price: 100.0,
+        };
+        // (entry price, cropped qty): 28.57 rounds up to 29 over the limit, then one step down;
+        // 25 fills exactly to the limit and is kept
+        for (entry_price, expected_qty) in [(70.0, 28.0), (80.0, 25.0), (90.0, 22.0)] {
+            let (_, qty) = calc_cropped_reentry_qty_with_limit(
+                &exchange_params,
+                0.5,
+                &position,
+                0.3,
+                10000.0,
+                40.0,
+                entry_price,
+            );
+            assert_eq!(qty, expected_qty, "entry price {}", entry_price);
+            let wallet_exposure_if_filled = calc_wallet_exposure_if_filled(
+                10000.0,
+                position.size,
+                position.price,
+                qty,
+                entry_price,
+                &exchange_params,
+            );
+            assert!(wallet_exposure_if_filled <= 0.5);
+            assert!(
+                calc_wallet_exposure_if_filled(
+                    10000.0,
+                    position.size,
+                    position.price,
+                    qty + exchange_params.qty_step,
+                    entry_price,
+                    &exchange_params,
+                ) > 0.5
+            );
+        }
+        // min qty over the limit is kept rather than cropped to zero
+        let (_, qty) = calc_cropped_reentry_qty_with_limit(
+            &exchange_params,
+            0.301,
+            &position,
+            0.3,
+            10000.0,
+            40.0,
+            70.0,
+        );
+        assert_eq!(qty, 1.0);
+    }
+
+    #[test]
+    fn trailing_entry_tops_up_half_initial_entry_on_both_sides() {
+        let exchange_params = fixtures::exchange_params();
+        let state_params = fixtures::state_params(60000.0);
+        // trailing entries only, with an initial entry of several qty steps
+        let mut bot_params = fixtures::bot_params();
+        bot_params.entry_trailing_grid_ratio = 1.0;
+        bot_params.entry_initial_qty_pct = 0.5;
+        let trailing_price_bundle = TrailingPriceBundle::default();
+        type Calculator =
+            fn(&ExchangeParams, &StateParams, &BotParams, &Position, &TrailingPriceBundle) -> Order;
+        let cases: [(Calculator, Calculator, f64, OrderType, OrderType); 2] = [
+            (
+                calc_trailing_entry_long,
+                calc_next_entry_long,
+                1.0,
+                OrderType::EntryInitialNormalLong,
+                OrderType::EntryInitialPartialLong,
+            ),
+            (
+                calc_trailing_entry_short,
+                calc_next_entry_short,
+                -1.0,
+                OrderType::EntryInitialNormalShort,
+                OrderType::EntryInitialPartialShort,
+            ),
+        ];
+        for (calc_trailing_entry, calc_next_entry, sign, normal_type, partial_type) in cases {
+            let initial = calc_trailing_entry(
+                &exchange_params,
+                &state_params,
+                &bot_params,
+                &Position::default(),
+                &trailing_price_bundle,
+            );
+            assert_eq!(initial.order_type, normal_type);
+            assert_eq!(initial.qty.signum(), sign);
+            assert!(initial.qty.abs() >= 4.0 * exchange_params.qty_step);
+            let half = Position {
+                size: round_(initial.qty * 0.5, exchange_params.qty_step),
+                price: initial.price,
+            };
+            let expected = Order {
+                qty: sign
+                    * round_dn(
+                        initial.qty.abs() - half.size.abs(),
+                        exchange_params.qty_step,
+                    ),
+                price: initial.price,
+                order_type: partial_type,
+            };
+            for calculator in [calc_trailing_entry, calc_next_entry] {
+                let top_up = calculator(
+                    &exchange_params,
+                    &state_params,
+                    &bot_params,
+                    &half,
+                    &trailing_price_bundle,
+                );
+                assert_eq!(top_up, expected);
+            }
+        }
+    }
+
+    /// Wallet exposure limit 1.0 and flat grid parameters, for hand computed grid prices.
+    fn plain_grid_bot_params() -> BotParams {
+        BotParams {
+            wallet_exposure_limit: 1.0,
+            entry_initial_qty_pct: 0.05,
+            entry_grid_spacing_pct: 0.05,
+            entry_grid_double_down_factor: 1.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn ema_floor_raises_grid_reentry_to_ema_band() {
+        let exchange_params = fixtures::exchange_params();
+        let mut bot_params = plain_grid_bot_params();
+        let long = Position {
+            size: 0.01,
+            price: 60000.0,
+        };
+        let short = Position {
+            size: -0.01,
+            price: 60000.0,
+        };
+        // bid 58000 with the lower band at 57884; ask 62000 with the upper band at 62124
+        let state_long = fixtures::state_params(58000.0);
+        let state_short = fixtures::state_params(62000.0);
+        let reentry_prices = |bot_params: &BotParams| {
+            (
+                calc_grid_entry_long(&exchange_params, &state_long, bot_params, &long).price,
+                calc_grid_entry_short(&exchange_params, &state_short, bot_params, &short).price,
+            )
+        };
+        // 5% from the position price
+        assert_eq!(reentry_prices(&bot_params), (57000.0, 63000.0));
+        bot_params.entry_grid_ema_floor = true;
+        assert_eq!(reentry_prices(&bot_params), (57884.0, 62124.0));
+        // the floor never moves a re-entry through the book
+        bot_params.entry_initial_ema_dist = -0.01;
+        assert_eq!(reentry_prices(&bot_params), (58000.0, 62000.0));
+    }
+
+    #[test]
+    fn twap_slices_sum_to_total_with_remainder_in_last() {
+        let exchange_params = fixtures::exchange_params();
+        let order_book = OrderBook {
+            bid: 59999.9,
+            ask: 60000.0,
+        };
+        // 0.01 over 3 slices: 0.003, 0.003 and the remaining 0.004
+        let long: Vec<Order> = (0..3)
+            .map(|i| calc_twap_entry_long(0.01, 3, i, &order_book, &exchange_params))
+            .collect();
+        let short: Vec<Order> = (0..3)
+            .map(|i| calc_twap_entry_short(0.01, 3, i, &order_book, &exchange_params))
+            .collect();
+        for (i, qty) in [0.003, 0.003, 0.004].into_iter().enumerate() {
+            assert_eq!(
+                long[i],
+                Order::new(qty, 59999.9, OrderType::EntryInitialPartialLong)
+            );
+            assert_eq!(
+                short[i],
+                Order::new(-qty, 60000.0, OrderType::EntryInitialPartialShort)
+            );
+        }
+        // out of range slices, no slices and slices rounding to zero
+        for (total_qty, n_slices, slice_index) in [(0.01, 3, 3), (0.01, 0, 0), (0.002, 3, 0)] {
+            assert_eq!(
+                calc_twap_entry_long(
+                    total_qty,
+                    n_slices,
+                    slice_index,
+                    &order_book,
+                    &exchange_params
+                ),
+                Order::default()
+            );
+            assert_eq!(
+                calc_twap_entry_short(
+                    total_qty,
+                    n_slices,
+                    slice_index,
+                    &order_book,
+                    &exchange_params
+                ),
+                Order::default()
+            );
+        }
+    }
+
+    #[test]
+    fn exposure_headroom_and_addable_qty() {
+        let exchange_params = fixtures::exchange_params();
+        let bot_params = BotParams {
+            wallet_exposure_limit: 0.5,
+            ..Default::default()
+        };
+        // cost 3000.0 of 5000.0 allowed on a balance of 10000.0
+        for size in [0.05, -0.05] {
+            let position = Position {
+                size,
+                price: 60000.0,
+            };
+            let headroom =
+                calc_exposure_headroom(&position, 10000.0, &bot_params, &exchange_params);
+            assert_close(headroom, 2000.0);
+            // 2000.0 / 30000.0 = 0.0667 rounds down
+            assert_eq!(
+                calc_addable_qty_at(30000.0, &position, 10000.0, &bot_params, &exchange_params),
+                0.066
+            );
+        }
+        let full = Position {
+            size: 0.1,
+            price: 60000.0,
+        };
+        assert_eq!(
+            calc_exposure_headroom(&full, 10000.0, &bot_params, &exchange_params),
+            0.0
+        );
+        assert_eq!(
+            calc_addable_qty_at(30000.0, &full, 10000.0, &bot_params, &exchange_params),
+            0.0
+        );
+    }
+
+    #[test]
+    fn initial_entry_crosses_the_book_after_timeout() {
+        let exchange_params = fixtures::exchange_params();
+        let state_params = StateParams {
+            order_book: OrderBook {
+                bid: 59990.0,
+                ask: 60010.0,
+            },
+            ..fixtures::state_params(60000.0)
+        };
+        let mut bot_params = plain_grid_bot_params();
+        bot_params.entry_initial_timeout_sec = 30.0;
+        let prices = |bot_params: &BotParams, seconds_pending: f64| {
+            let long = calc_initial_entry_long_with_timeout(
+                &exchange_params,
+                &state_params,
+                bot_params,
+                seconds_pending,
+            );
+            let short = calc_initial_entry_short_with_timeout(
+                &exchange_params,
+                &state_params,
+                bot_params,
+                seconds_pending,
+            );
+            assert_eq!(long.order_type, OrderType::EntryInitialNormalLong);
+            assert_eq!(short.order_type, OrderType::EntryInitialNormalShort);
+            assert_eq!(
+                long.qty,
+                calc_initial_entry_qty(&exchange_params, bot_params, 10000.0, long.price)
+            );
+            assert_eq!(
+                short.qty,
+                -calc_initial_entry_qty(&exchange_params, bot_params, 10000.0, short.price)
+            );
+            (long.price, short.price)
+        };
+        // limit orders at the EMA bands until the timeout, then at the far side of the book
+        assert_eq!(prices(&bot_params, 29.9), (59880.0, 60120.0));
+        assert_eq!(prices(&bot_params, 30.0), (60010.0, 59990.0));
+        // no timeout configured
+        bot_params.entry_initial_timeout_sec = 0.0;
+        assert_eq!(prices(&bot_params, 3600.0), (59880.0, 60120.0));
+    }
+
+    #[test]
+    fn initial_entry_qty_and_cost() {
+        let exchange_params = fixtures::exchange_params();
+        let bot_params = plain_grid_bot_params();
+        // 10000 * 1.0 * 0.05 = 500 worth, rounded to 0.008
+        assert_eq!(
+            calc_initial_entry_qty_and_cost(&exchange_params, &bot_params, 10000.0, 60000.0),
+            (0.008, 480.0)
+        );
+        assert_eq!(
+            calc_initial_entry_qty(&exchange_params, &bot_params, 10000.0, 60000.0),
+            0.008
+        );
+        // raised to min qty
+        assert_eq!(
+            calc_initial_entry_qty_and_cost(&exchange_params, &bot_params, 100.0, 60000.0),
+            (0.001, 60.0)
+        );
+    }
+
+    #[test]
+    fn reentries_capped_at_max_position_size() {
+        let bot_params = plain_grid_bot_params();
+        let long = Position {
+            size: 0.01,
+            price: 60000.0,
+        };
+        let short = Position {
+            size: -0.01,
+            price: 60000.0,
+        };
+        let state_params = fixtures::state_params(60000.0);
+        let entries = |max_position_size| {
+            let exchange_params = ExchangeParams {
+                max_position_size,
+                ..fixtures::exchange_params()
+            };
+            (
+                calc_grid_entry_long(&exchange_params, &state_params, &bot_params, &long),
+                calc_grid_entry_short(&exchange_params, &state_params, &bot_params, &short),
+            )
+        };
+        // double down 1.0: re-entry doubles the position
+        let (long_entry, short_entry) = entries(0.0);
+        assert_eq!(
+            long_entry,
+            Order::new(0.01, 57000.0, OrderType::EntryGridNormalLong)
+        );
+        assert_eq!(
+            short_entry,
+            Order::new(-0.01, 63000.0, OrderType::EntryGridNormalShort)
+        );
+        let (long_entry, short_entry) = entries(0.015);
+        assert_eq!(
+            long_entry,
+            Order::new(0.005, 57000.0, OrderType::EntryGridCroppedLong)
+        );
+        assert_eq!(
+            short_entry,
+            Order::new(-0.005, 63000.0, OrderType::EntryGridCroppedShort)
+        );
+        // less than min qty of room
+        assert_eq!(entries(0.0105), (Order::default(), Order::default()));
+        assert_eq!(
+            calc_position_size_capped_qty(
+                &ExchangeParams {
+                    max_position_size: 0.0105,
+                    ..fixtures::exchange_params()
+                },
+                &long,
+                0.01,
+                57000.0
+            ),
+            0.0
+        );
+    }
+
+    #[test]
+    fn trailing_stop_entry_fires_after_bounce_off_extreme() {
+        let exchange_params = fixtures::exchange_params();
+        let bot_params = BotParams {
+            entry_trailing_mode: EntryTrailingMode::TrailingStop,
+            entry_trailing_drawdown_pct: 0.01,
+            // ignored in trailing stop mode
+            entry_trailing_threshold_pct: 0.05,
+            entry_trailing_retracement_pct: 0.002,
+            ..plain_grid_bot_params()
+        };
+        // the same entry as a trailing entry without threshold
+        let threshold_drawdown = BotParams {
+            entry_trailing_mode: EntryTrailingMode::ThresholdDrawdown,
+            entry_trailing_threshold_pct: 0.0,
+            entry_trailing_retracement_pct: 0.01,
+            ..bot_params.clone()
+        };
+        let long = Position {
+            size: 0.01,
+            price: 60000.0,
+        };
+        let short = Position {
+            size: -0.01,
+            price: 60000.0,
+        };
+        let state_long = fixtures::state_params(57600.0);
+        let state_short = fixtures::state_params(62400.0);
+        for (bounce, triggered) in [(0.0099, false), (0.0101, true)] {
+            let bundle_long = TrailingPriceBundle {
+                min_since_open: 57000.0,
+                max_since_min: 57000.0 * (1.0 + bounce),
+                ..Default::default()
+            };
+            let bundle_short = TrailingPriceBundle {
+                max_since_open: 63000.0,
+                min_since_max: 63000.0 * (1.0 - bounce),
+                ..Default::default()
+            };
+            let entry_long = |bot_params: &BotParams| {
+                calc_trailing_entry_long(
+                    &exchange_params,
+                    &state_long,
+                    bot_params,
+                    &long,
+                    &bundle_long,
+                )
+            };
+            let entry_short = |bot_params: &BotParams| {
+                calc_trailing_entry_short(
+                    &exchange_params,
+                    &state_short,
+                    bot_params,
+                    &short,
+                    &bundle_short,
+                )
+            };
+            let (long_entry, short_entry) = (entry_long(&bot_params), entry_short(&bot_params));
+            assert_eq!(long_entry, entry_long(&threshold_drawdown));
+            assert_eq!(short_entry, entry_short(&threshold_drawdown));
+            if triggered {
+                assert!(long_entry.qty > 0.0);
+                assert_eq!(long_entry.price, 57600.0);
+                assert!(short_entry.qty < 0.0);
+                assert_eq!(short_entry.price, 62400.0);
+            } else {
+                assert_eq!(long_entry.qty, 0.0);
+                assert_eq!(short_entry.qty, 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn balance_for_grid_depth_is_the_minimum_reaching_n_levels() {
+        let exchange_params = fixtures::exchange_params();
+        let bot_params = plain_grid_bot_params();
+        for n_levels in [1, 3, 6] {
+            let balance =
+                calc_balance_for_grid_depth(&bot_params, &exchange_params, 60000.0, n_levels);
+            assert!(balance.is_finite());
+            assert!(
+                calc_grid_depth_long(&exchange_params, &bot_params, balance, 60000.0) >= n_levels
+            );
+            if n_levels > 1 {
+                assert!(
+                    calc_grid_depth_long(&exchange_params, &bot_params, balance * 0.99, 60000.0)
+                        < n_levels
+                );
+            }
+        }
+        let no_exposure = BotParams {
+            wallet_exposure_limit: 0.0,
+            ..bot_params.clone()
+        };
+        assert_eq!(
+            calc_balance_for_grid_depth(&no_exposure, &exchange_params, 60000.0, 1),
+            f64::INFINITY
+        );
+        assert_eq!(
+            calc_balance_for_grid_depth(&bot_params, &exchange_params, 0.0, 1),
+            f64::INFINITY
+        );
+    }
+
+    #[test]
+    fn lowered_exposure_limit_leaves_overage_and_stops_entries() {
+        let exchange_params = fixtures::exchange_params();
+        let mut bot_params = plain_grid_bot_params();
+        // cost 6000.0 on a balance of 10000.0
+        let position = Position {
+            size: 0.1,
+            price: 60000.0,
+        };
+        assert_eq!(
+            calc_exposure_overage(&position, 10000.0, &bot_params, &exchange_params),
+            0.0
+        );
+        bot_params.wallet_exposure_limit = 0.4;
+        assert_close(
+            calc_exposure_overage(&position, 10000.0, &bot_params, &exchange_params),
+            2000.0,
+        );
+        let short = Position {
+            size: -0.1,
+            ..position
+        };
+        assert_close(
+            calc_exposure_overage(&short, 10000.0, &bot_params, &exchange_params),
+            2000.0,
+        );
+        let entries = calc_entries_long(
+            &exchange_params,
+            &fixtures::state_params(60000.0),
+            &bot_params,
+            &position,
+            &TrailingPriceBundle::default(),
+        );
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn reentries_anchor_to_their_own_ema_dist() {
+        let exchange_params = fixtures::exchange_params();
+        let bot_params = BotParams {
+            entry_initial_ema_dist: 0.001,
+            entry_reentry_ema_dist: Some(0.01),
+            entry_grid_ema_floor: true,
+            ..plain_grid_bot_params()
+        };
+        // lower band 57884, upper band 62124
+        let state_long = fixtures::state_params(58000.0);
+        let state_short = fixtures::state_params(62000.0);
+        let prices = |bot_params: &BotParams, long: &Position, short: &Position| {
+            (
+                calc_grid_entry_long(&exchange_params, &state_long, bot_params, long).price,
+                calc_grid_entry_short(&exchange_params, &state_short, bot_params, short).price,
+            )
+        };
+        let flat = Position::default();
+        // 57884 * 0.999 and 62124 * 1.001
+        assert_eq!(prices(&bot_params, &flat, &flat), (57826.1, 62186.2));
+        let long = Position {
+            size: 0.01,
+            price: 60000.0,
+        };
+        let short = Position {
+            size: -0.01,
+            price: 60000.0,
+        };
+        // 57884 * 0.99 and 62124 * 1.01
+        assert_eq!(prices(&bot_params, &long, &short), (57305.1, 62745.3));
+        // unset falls back to the initial entry distance
+        let fallback = BotParams {
+            entry_reentry_ema_dist: None,
+            ..bot_params.clone()
+        };
+        assert_eq!(prices(&fallback, &long, &short), (57826.1, 62186.2));
+    }
+
+    #[test]
+    fn same_tick_fill_suppresses_duplicate_entry() {
+        let exchange_params = fixtures::exchange_params();
+        let bot_params = plain_grid_bot_params();
+        let state_params = StateParams {
+            timestamp: 60_000,
+            ..fixtures::state_params(60000.0)
+        };
+        let initial_long = calc_grid_entry_long(
+            &exchange_params,
+            &state_params,
+            &bot_params,
+            &Position::default(),
+        );
+        let initial_short = calc_grid_entry_short(
+            &exchange_params,
+            &state_params,
+            &bot_params,
+            &Position::default(),
+        );
+        // a partial fill of the initial entry leaves a top-up at the same price
+        let long = Position {
+            size: 0.001,
+            price: initial_long.price,
+        };
+        let short = Position {
+            size: -0.001,
+            price: initial_short.price,
+        };
+        let entries_after_fill = |fill_price: f64, timestamp: u64| {
+            let state_params = StateParams {
+                timestamp,
+                last_entry_fill: Some(LastFill {
+                    price: fill_price,
+                    timestamp: 60_000,
+                }),
+                ..state_params.clone()
+            };
+            (
+                calc_grid_entry_long(&exchange_params, &state_params, &bot_params, &long),
+                calc_grid_entry_short(&exchange_params, &state_params, &bot_params, &short),
+            )
+        };
+        let (long_entry, _) = entries_after_fill(initial_long.price, 60_000);
+        assert_eq!(long_entry, Order::default());
+        let (_, short_entry) = entries_after_fill(initial_short.price, 60_000);
+        assert_eq!(short_entry, Order::default());
+        // next tick, or a fill at another price, leaves the top-up in place
+        for (fill_price, timestamp) in [(initial_long.price, 120_000), (50000.0, 60_000)] {
+            let (long_entry, _) = entries_after_fill(fill_price, timestamp);
+            assert_eq!(long_entry.price, initial_long.price);
+            assert_eq!(long_entry.qty, initial_long.qty - 0.001);
+        }
+        let (long_entry, reason) = calc_next_entry_long_explained(
+            &exchange_params,
+            &StateParams {
+                last_entry_fill: Some(LastFill {
+                    price: initial_long.price,
+                    timestamp: 60_000,
+                }),
+                ..state_params.clone()
+            },
+            &bot_params,
+            &long,
+            &TrailingPriceBundle::default(),
+        );
+        assert_eq!(
+            (long_entry, reason),
+            (Order::default(), EmptyReason::SameTickFill)
+        );
+    }
+
+    #[test]
+    fn grid_and_trailing_reentries_scale_with_c_mult() {
+        // book and EMA bands at 100.0; double down 2.0 makes re-entries twice the position
+        let bot_params = BotParams {
+            entry_grid_double_down_factor: 2.0,
+            entry_trailing_threshold_pct: 0.01,
+            entry_trailing_retracement_pct: 0.01,
+            ..plain_grid_bot_params()
+        };
+        let state_params = |price: f64| StateParams {
+            balance: 10000.0,
+            order_book: OrderBook {
+                bid: price,
+                ask: price,
+            },
+            ema_bands: EMABands {
+                upper: price,
+                lower: price,
+            },
+            ..Default::default()
+        };
+        // (c_mult, initial qty for cost 500.0, re-entry qty)
+        for (c_mult, initial_qty, reentry_qty) in [(0.001, 5000.0, 10000.0), (10.0, 0.5, 1.0)] {
+            let exchange_params = exchange_params(c_mult);
+            let initial = calc_grid_entry_long(
+                &exchange_params,
+                &state_params(100.0),
+                &bot_params,
+                &Position::default(),
+            );
+            assert_eq!((initial.qty, initial.price), (initial_qty, 100.0));
+            assert_close(qty_to_cost(initial.qty, initial.price, c_mult), 500.0);
+            let position = Position {
+                size: initial_qty,
+                price: 100.0,
+            };
+            // 5% below the position price
+            let grid = calc_grid_entry_long(
+                &exchange_params,
+                &state_params(96.0),
+                &bot_params,
+                &position,
+            );
+            assert_eq!((grid.qty, grid.price), (reentry_qty, 95.0));
+            assert_close(qty_to_cost(grid.qty, grid.price, c_mult), 950.0);
+            // low of 97.0 is 3% below the position price, and the bounce to 98.0 is over 1%
+            let trailing = calc_trailing_entry_long(
+                &exchange_params,
+                &state_params(98.0),
+                &bot_params,
+                &position,
+                &TrailingPriceBundle {
+                    min_since_open: 97.0,
+                    max_since_min: 98.0,
+                    ..Default::default()
+                },
+            );
+            assert_eq!((trailing.qty, trailing.price), (reentry_qty, 98.0));
+            assert_close(qty_to_cost(trailing.qty, trailing.price, c_mult), 980.0);
+            assert_close(
+                calc_wallet_exposure_if_filled(
+                    10000.0,
+                    position.size,
+                    position.price,
+                    trailing.qty,
+                    trailing.price,
+                    &exchange_params,
+                ),
+                0.148,
+            );
+        }
+    }
+
+    #[test]
+    fn balance_reserve_shrinks_entry_sizing_and_exposure_room() {
+        let exchange_params = fixtures::exchange_params();
+        let bot_params = plain_grid_bot_params();
+        let reserved = BotParams {
+            balance_reserve_pct: 0.2,
+            ..bot_params.clone()
+        };
+        assert_eq!(reserved.usable_balance(10000.0), 8000.0);
+        // 5% of 10000.0 or of 8000.0 at 59880.0, the lower EMA band
+        let initial_qty = |bot_params: &BotParams| {
+            calc_grid_entry_long(
+                &exchange_params,
+                &fixtures::state_params(60000.0),
+                bot_params,
+                &Position::default(),
+            )
+            .qty
+        };
+        assert_eq!(initial_qty(&bot_params), 0.008);
+        assert_eq!(initial_qty(&reserved), 0.007);
+        // cost 6000.0: re-entry of 0.1 at 57000.0 is cropped to the room left under the limit,
+        // 4000.0 against the full balance and 2000.0 against the usable balance
+        let position = Position {
+            size: 0.1,
+            price: 60000.0,
+        };
+        let state_params = fixtures::state_params(58000.0);
+        let reentry = |bot_params: &BotParams| {
+            let order =
+                calc_grid_entry_long(&exchange_params, &state_params, bot_params, &position);
+            assert_eq!(order.price, 57000.0);
+            order.qty
+        };
+        assert_eq!(reentry(&bot_params), 0.07);
+        assert_eq!(reentry(&reserved), 0.035);
+        // at 0.8 exposure of the full balance, nothing is left of the usable one
+        let position = Position {
+            size: 0.8 / 6.0,
+            price: 60000.0,
+        };
+        assert!(
+            calc_grid_entry_long(&exchange_params, &state_params, &bot_params, &position).qty > 0.0
+        );
+        assert_eq!(
+            calc_grid_entry_long(&exchange_params, &state_params, &reserved, &position),
+            Order::default()
+        );
+    }
+
+    #[test]
+    fn empty_entries_explain_why() {
+        let exchange_params = fixtures::exchange_params();
+        let bot_params = plain_grid_bot_params();
+        let trailing = BotParams {
+            entry_trailing_grid_ratio: 1.0,
+            entry_trailing_threshold_pct: 0.01,
+            entry_trailing_retracement_pct: 0.01,
+            ..bot_params.clone()
+        };
+        let state_params = fixtures::state_params(58000.0);
+        let position = Position {
+            size: 0.01,
+            price: 60000.0,
+        };
+        let explain = |exchange_params: &ExchangeParams,
+                       state_params: &StateParams,
+                       bot_params: &BotParams,
+                       position: &Position| {
+            calc_next_entry_long_explained(
+                exchange_params,
+                state_params,
+                bot_params,
+                position,
+                &TrailingPriceBundle::default(),
+            )
+            .1
+        };
+        let reason = |bot_params: &BotParams| {
+            explain(&exchange_params, &state_params, bot_params, &position)
+        };
+        assert_eq!(reason(&bot_params), EmptyReason::NotEmpty);
+        assert_eq!(
+            reason(&BotParams {
+                wallet_exposure_limit: 0.0,
+                ..bot_params.clone()
+            }),
+            EmptyReason::ZeroExposureLimit
+        );
+        assert_eq!(
+            reason(&BotParams {
+                balance_reserve_pct: 1.0,
+                ..bot_params.clone()
+            }),
+            EmptyReason::ZeroBalance
+        );
+        assert_eq!(
+            reason(&BotParams {
+                entry_blackout_windows: vec![(0, 60_000)],
+                ..bot_params.clone()
+            }),
+            EmptyReason::EntryBlackout
+        );
+        // no bounce off the low yet
+        assert_eq!(reason(&trailing), EmptyReason::TrailingNotTriggered);
+        let state_params_after_add = StateParams {
+            trailing_add_count: 1,
+            ..state_params.clone()
+        };
+        assert_eq!(
+            explain(
+                &exchange_params,
+                &state_params_after_add,
+                &BotParams {
+                    entry_trailing_max_adds: 1,
+                    ..trailing.clone()
+                },
+                &position,
+            ),
+            EmptyReason::TrailingMaxAddsReached
+        );
+        // book below one price step
+        assert_eq!(
+            explain(
+                &exchange_params,
+                &fixtures::state_params(0.1),
+                &bot_params,
+                &Position::default(),
+            ),
+            EmptyReason::PriceBelowStep
+        );
+        // 12000.0 worth against a limit of 10000.0
+        let full = Position {
+            size: 0.2,
+            price: 60000.0,
+        };
+        assert_eq!(
+            explain(&exchange_params, &state_params, &bot_params, &full),
+            EmptyReason::ExposureLimitReached
+        );
+        let capped = ExchangeParams {
+            max_position_size: 0.0105,
+            ..exchange_params.clone()
+        };
+        assert_eq!(
+            explain(&capped, &state_params, &bot_params, &position),
+            EmptyReason::MaxPositionSizeReached
+        );
+    }
+
+    #[test]
+    fn trailing_entries_stop_at_max_adds() {
+        let exchange_params = fixtures::exchange_params();
+        let bot_params = BotParams {
+            entry_trailing_grid_ratio: 1.0,
+            entry_trailing_threshold_pct: 0.01,
+            entry_trailing_retracement_pct: 0.01,
+            entry_trailing_max_adds: 2,
+            ..plain_grid_bot_params()
+        };
+        // low of 59000.0 then a bounce to 59700.0; high of 61000.0 then a dip to 60300.0
+        let trailing_price_bundle = TrailingPriceBundle {
+            min_since_open: 59000.0,
+            max_since_min: 59700.0,
+            max_since_open: 61000.0,
+            min_since_max: 60300.0,
+        };
+        let long = Position {
+            size: 0.01,
+            price: 60000.0,
+        };
+        let short = Position {
+            size: -0.01,
+            price: 60000.0,
+        };
+        let entries = |bot_params: &BotParams, trailing_add_count: usize| {
+            let state_params = |price: f64| StateParams {
+                trailing_add_count,
+                ..fixtures::state_params(price)
+            };
+            (
+                calc_trailing_entry_long(
+                    &exchange_params,
+                    &state_params(59700.0),
+                    bot_params,
+                    &long,
+                    &trailing_price_bundle,
+                ),
+                calc_trailing_entry_short(
+                    &exchange_params,
+                    &state_params(60300.0),
+                    bot_params,
+                    &short,
+                    &trailing_price_bundle,
+                ),
+            )
+        };
+        for trailing_add_count in [0, 1] {
+            let (long_entry, short_entry) = entries(&bot_params, trailing_add_count);
+            assert_eq!((long_entry.qty, long_entry.price), (0.01, 59700.0));
+            assert_eq!((short_entry.qty, short_entry.price), (-0.01, 60300.0));
+        }
+        // plenty of exposure left, but no more adds
+        assert_eq!(
+            entries(&bot_params, 2),
+            (Order::default(), Order::default())
+        );
+        let unlimited = BotParams {
+            entry_trailing_max_adds: 0,
+            ..bot_params.clone()
+        };
+        let (long_entry, short_entry) = entries(&unlimited, 10);
+        assert_eq!((long_entry.qty, short_entry.qty), (0.01, -0.01));
+    }
+
+    #[test]
+    fn blended_entries_split_remaining_exposure() {
+        let exchange_params = fixtures::exchange_params();
+        let bot_params = BotParams {
+            entry_trailing_grid_mode: EntryTrailingGridMode::Blended,
+            entry_trailing_grid_ratio: 0.5,
+            entry_trailing_threshold_pct: 0.01,
+            entry_trailing_retracement_pct: 0.01,
+            ..plain_grid_bot_params()
+        };
+        // bounced off the low and the high, see trailing_entries_stop_at_max_adds
+        let trailing_price_bundle = TrailingPriceBundle {
+            min_since_open: 59000.0,
+            max_since_min: 59700.0,
+            max_since_open: 61000.0,
+            min_since_max: 60300.0,
+        };
+        // 0.6 exposure: each entry may take half of the remaining 4000.0
+        let long = Position {
+            size: 0.1,
+            price: 60000.0,
+        };
+        let entries = calc_blended_entries_long(
+            &exchange_params,
+            &fixtures::state_params(59700.0),
+            &bot_params,
+            &long,
+            &trailing_price_bundle,
+        );
+        assert_eq!(
+            entries,
+            vec![
+                Order::new(0.033, 59700.0, OrderType::EntryTrailingCroppedLong),
+                Order::new(0.035, 57000.0, OrderType::EntryGridCroppedLong),
+            ]
+        );
+        let short = Position {
+            size: -0.1,
+            price: 60000.0,
+        };
+        let entries_short = calc_blended_entries_short(
+            &exchange_params,
+            &fixtures::state_params(60300.0),
+            &bot_params,
+            &short,
+            &trailing_price_bundle,
+        );
+        assert_eq!(entries_short.len(), 2);
+        assert_eq!(
+            entries_short[0].order_type,
+            OrderType::EntryTrailingCroppedShort
+        );
+        assert_eq!(
+            entries_short[1].order_type,
+            OrderType::EntryGridCroppedShort
+        );
+        // both filling stays within the limit
+        for (position, entries) in [(long, entries), (short, entries_short)] {
+            let filled = entries.iter().fold(position, |position, entry| {
+                position.project_fill(entry, exchange_params.qty_step)
+            });
+            let wallet_exposure =
+                calc_wallet_exposure(1.0, 10000.0, filled.size.abs(), filled.price);
+            assert!(wallet_exposure > 0.99 && wallet_exposure <= 1.0);
+        }
+        // Switch mode places one or the other
+        let switch = BotParams {
+            entry_trailing_grid_mode: EntryTrailingGridMode::Switch,
+            ..bot_params.clone()
+        };
+        assert_eq!(
+            calc_blended_entries_long(
+                &exchange_params,
+                &fixtures::state_params(59700.0),
+                &switch,
+                &long,
+                &trailing_price_bundle,
+            )
+            .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn zero_ema_bands_follow_ema_warmup_behavior() {
+        let exchange_params = fixtures::exchange_params();
+        let state_params = StateParams {
+            ema_bands: EMABands::default(),
+            ..fixtures::state_params(60000.0)
+        };
+        let trailing = TrailingPriceBundle::default();
+        let flat = Position::default();
+        let mut bot_params = plain_grid_bot_params();
+        assert_eq!(
+            bot_params.ema_warmup_behavior,
+            EmaWarmupBehavior::SuppressEntries
+        );
+        let long_entries = |bot_params: &BotParams| {
+            [
+                calc_grid_entry_long(&exchange_params, &state_params, bot_params, &flat),
+                calc_trailing_entry_long(
+                    &exchange_params,
+                    &state_params,
+                    bot_params,
+                    &flat,
+                    &trailing,
+                ),
+            ]
+        };
+        let short_entries = |bot_params: &BotParams| {
+            [
+                calc_grid_entry_short(&exchange_params, &state_params, bot_params, &flat),
+                calc_trailing_entry_short(
+                    &exchange_params,
+                    &state_params,
+                    bot_params,
+                    &flat,
+                    &trailing,
+                ),
+            ]
+        };
+        for order in long_entries(&bot_params)
+            .into_iter()
+            .chain(short_entries(&bot_params))
+        {
+            assert!(order.is_empty());
+        }
+        bot_params.ema_warmup_behavior = EmaWarmupBehavior::FallbackToOrderBook;
+        for order in long_entries(&bot_params) {
+            assert_eq!(
+                (order.qty, order.price, order.order_type),
+                (0.008, 60000.0, OrderType::EntryInitialNormalLong)
+            );
+        }
+        for order in short_entries(&bot_params) {
+            assert_eq!(
+                (order.qty, order.price, order.order_type),
+                (-0.008, 60000.0, OrderType::EntryInitialNormalShort)
+            );
+        }
+        // reentries are sized off the initial entry, so they follow the same behavior; the EMA
+        // floor is skipped while the bands are zero
+        bot_params.entry_grid_ema_floor = true;
+        let long = Position {
+            size: 0.008,
+            price: 60000.0,
+        };
+        let short = Position {
+            size: -0.008,
+            price: 60000.0,
+        };
+        let reentries = |bot_params: &BotParams| {
+            [
+                calc_grid_entry_long(&exchange_params, &state_params, bot_params, &long),
+                calc_grid_entry_short(&exchange_params, &state_params, bot_params, &short),
+            ]
+        };
+        let [long_reentry, short_reentry] = reentries(&bot_params);
+        assert_eq!((long_reentry.qty, long_reentry.price), (0.009, 57000.0));
+        assert_eq!((short_reentry.qty, short_reentry.price), (-0.008, 63000.0));
+        bot_params.ema_warmup_behavior = EmaWarmupBehavior::SuppressEntries;
+        assert!(reentries(&bot_params).iter().all(Order::is_empty));
+    }
+
+    #[test]
+    fn cropped_grid_reentries_stay_within_limit_on_both_sides() {
+        let exchange_params = ExchangeParams {
+            qty_step: 1.0,
+            min_qty: 1.0,
+            ..exchange_params(1.0)
+        };
+        // doubling down from 0.3 would fill to 0.84, cropped to the 0.5 limit instead
+        let bot_params = BotParams {
+            wallet_exposure_limit: 0.5,
+            entry_initial_qty_pct: 0.1,
+            entry_grid_spacing_pct: 0.1,
+            entry_grid_double_down_factor: 2.0,
+            ..Default::default()
+        };
+        let sides = [
+            (
+                30.0,
+                fixtures::state_params(90.0),
+                (22.0, 90.0),
+                OrderType::EntryGridCroppedLong,
+            ),
+            // 100.0 * 1.1 is a hair above 110.0, rounded up a step
+            (
+                -30.0,
+                fixtures::state_params(110.0),
+                (-18.0, 110.01),
+                OrderType::EntryGridCroppedShort,
+            ),
+        ];
+        for (size, state_params, (expected_qty, expected_price), expected_type) in sides {
+            let position = Position { size, price: 100.0 };
+            let entry = if size > 0.0 {
+                calc_grid_entry_long(&exchange_params, &state_params, &bot_params, &position)
+            } else {
+                calc_grid_entry_short(&exchange_params, &state_params, &bot_params, &position)
+            };
+            assert_eq!(
+                (entry.qty, entry.price, entry.order_type),
+                (expected_qty, expected_price, expected_type)
+            );
+            let wallet_exposure_if_filled = |qty: f64| {
+                calc_wallet_exposure_if_filled(
+                    10000.0,
+                    size,
+                    position.price,
+                    qty,
+                    entry.price,
+                    &exchange_params,
+                )
+            };
+            assert!(wallet_exposure_if_filled(entry.qty) <= 0.5);
+            assert!(wallet_exposure_if_filled(entry.qty.abs() + 1.0) > 0.5);
+        }
+    }
+
+    #[test]
+    fn staged_initial_entry_sums_to_initial_entry_qty() {
+        let exchange_params = exchange_params(1.0);
+        let bot_params = plain_grid_bot_params();
+        let state_params = fixtures::state_params(100.0);
+        let long = calc_staged_initial_entry_long(&exchange_params, &state_params, &bot_params, 4);
+        let short =
+            calc_staged_initial_entry_short(&exchange_params, &state_params, &bot_params, 4);
+        for (stages, initial_entry_price) in [(&long, 99.8), (&short, 100.2)] {
+            assert_eq!(stages.len(), 4);
+            let total_qty =
+                calc_initial_entry_qty(&exchange_params, &bot_params, 10000.0, initial_entry_price);
+            let sum: f64 = stages.iter().map(|order| order.qty.abs()).sum();
+            assert_close(sum, total_qty);
+            assert_eq!(stages[0].price, initial_entry_price);
+        }
+        assert_eq!(long[0].order_type, OrderType::EntryInitialNormalLong);
+        assert_eq!(short[0].order_type, OrderType::EntryInitialNormalShort);
+        for pair in long.windows(2) {
+            assert!(pair[1].price < pair[0].price && pair[1].qty > 0.0);
+            assert_eq!(pair[1].order_type, OrderType::EntryInitialPartialLong);
+            // about entry_grid_spacing_pct apart
+            assert!((1.0 - pair[1].price / pair[0].price - 0.05).abs() < 0.001);
+        }
+        for pair in short.windows(2) {
+            assert!(pair[1].price > pair[0].price && pair[1].qty < 0.0);
+            assert_eq!(pair[1].order_type, OrderType::EntryInitialPartialShort);
+            assert!((pair[1].price / pair[0].price - 1.0 - 0.05).abs() < 0.001);
+        }
+        // one stage is the plain initial entry; zero stages place nothing
+        assert_eq!(
+            calc_staged_initial_entry_long(&exchange_params, &state_params, &bot_params, 1),
+            [calc_grid_entry_long(
+                &exchange_params,
+                &state_params,
+                &bot_params,
+                &Position::default()
+            )]
+        );
+        assert!(
+            calc_staged_initial_entry_long(&exchange_params, &state_params, &bot_params, 0)
+                .is_empty()
+        );
+        // min qty of about 2.0 leaves room for two slices of 5.01
+        let coarse = ExchangeParams {
+            min_cost: 200.0,
+            ..exchange_params
+        };
+        let stages = calc_staged_initial_entry_long(&coarse, &state_params, &bot_params, 4);
+        assert_eq!(stages.len(), 2);
+        assert_close(stages.iter().map(|order| order.qty).sum(), 5.01);
+    }
+
+    #[test]
+    fn deep_grid_reentry_clamped_to_price_band() {
+        let bot_params = plain_grid_bot_params();
+        let long = Position {
+            size: 0.008,
+            price: 60000.0,
+        };
+        let state_params = fixtures::state_params(60000.0);
+        let trailing = TrailingPriceBundle::default();
+        let entries = |exchange_params: &ExchangeParams| {
+            calc_entries_long(
+                exchange_params,
+                &state_params,
+                &bot_params,
+                &long,
+                &trailing,
+            )
+        };
+        let unbanded = entries(&fixtures::exchange_params());
+        assert_eq!((unbanded[0].qty, unbanded[0].price), (0.009, 57000.0));
+        // the band floor at 58200.0 is above every re-entry: the first is raised to it, with
+        // qty cut to keep its cost, and the deeper ones are dropped until price comes closer
+        let banded = entries(&ExchangeParams {
+            price_band_pct: 0.03,
+            ..fixtures::exchange_params()
+        });
+        assert_eq!(
+            banded,
+            [Order::new(0.008, 58200.0, OrderType::EntryGridNormalLong)]
+        );
+    }
+
+    #[test]
+    fn grid_level_capped_at_schedule_end() {
+        let schedule = [1.0, 2.0, 4.0];
+        // initial entry costs 100.0, then 100.0, 200.0 and 400.0 per level
+        for (position_cost, expected_level) in [
+            (100.0, 0),
+            (149.0, 0),
+            (150.0, 1),
+            (299.0, 1),
+            (300.0, 2),
+            (400.0, 2),
+            (1e12, 2),
+        ] {
+            assert_eq!(
+                calc_grid_level(position_cost, 100.0, &schedule),
+                expected_level,
+                "position cost {}",
+                position_cost
+            );
+        }
+        assert_eq!(calc_grid_level(1e12, 100.0, &[3.0]), 0);
+        // non-finite inputs return instead of looping forever
+        for cost in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert_eq!(calc_grid_level(cost, 100.0, &schedule), 0);
+            assert_eq!(calc_grid_level(1000.0, cost, &schedule), 0);
+        }
+        for multiplier in [f64::NAN, f64::INFINITY] {
+            assert_eq!(calc_grid_level(1e12, 100.0, &[1.0, multiplier, 4.0]), 1);
+        }
+    }
+
+    #[test]
+    fn reentry_qty_follows_per_level_schedule() {
+        let exchange_params = exchange_params(1.0);
+        // initial entry qty 1.0 at 100.0
+        let bot_params = BotParams {
+            wallet_exposure_limit: 1.0,
+            entry_initial_qty_pct: 0.01,
+            entry_grid_double_down_factor: 10.0,
+            entry_grid_qty_schedule: vec![1.0, 2.0, 4.0],
+            ..Default::default()
+        };
+        let reentry_qty = |position_size, position_price| {
+            calc_reentry_qty(
+                100.0,
+                10000.0,
+                position_size,
+                position_price,
+                &exchange_params,
+                &bot_params,
+            )
+        };
+        // ladder 1, 1, 2, 4, then 4 for every deeper level
+        for (position_size, expected_qty) in [(1.0, 1.0), (2.0, 2.0), (4.0, 4.0), (8.0, 4.0)] {
+            assert_eq!(reentry_qty(position_size, 100.0), expected_qty);
+        }
+        assert_eq!(reentry_qty(1000.0, 100.0), 4.0);
+        // a non-finite position cost falls back to the first level
+        assert_eq!(reentry_qty(2.0, f64::NAN), 1.0);
+        assert_eq!(reentry_qty(2.0, f64::INFINITY), 1.0);
+        // an empty schedule doubles down
+        let double_down = BotParams {
+            entry_grid_qty_schedule: Vec::new(),
+            ..bot_params.clone()
+        };
+        assert_eq!(
+            calc_reentry_qty(100.0, 10000.0, 2.0, 100.0, &exchange_params, &double_down),
+            20.0
+        );
+    }
+
+    #[test]
+    fn next_grid_trigger_price_is_where_the_reentry_reaches_the_book() {
+        let exchange_params = fixtures::exchange_params();
+        let bot_params = plain_grid_bot_params();
+        let long = Position {
+            size: 0.01,
+            price: 60000.0,
+        };
+        let short = Position {
+            size: -0.01,
+            price: 60000.0,
+        };
+        let trigger_long =
+            calc_next_grid_trigger_price_long(&long, 10000.0, 0.0, &bot_params, &exchange_params)
+                .unwrap();
+        let trigger_short =
+            calc_next_grid_trigger_price_short(&short, 10000.0, 0.0, &bot_params, &exchange_params)
+                .unwrap();
+        // at the trigger the re-entry is at the book, a step away it rests behind it
+        let at_long = calc_grid_entry_long(
+            &exchange_params,
+            &fixtures::state_params(trigger_long),
+            &bot_params,
+            &long,
+        );
+        assert!(at_long.qty > 0.0);
+        assert_eq!(at_long.price, trigger_long);
+        let above_long = calc_grid_entry_long(
+            &exchange_params,
+            &fixtures::state_params(trigger_long + exchange_params.price_step),
+            &bot_params,
+            &long,
+        );
+        assert_eq!(
+            (above_long.qty, above_long.price),
+            (at_long.qty, trigger_long)
+        );
+        let at_short = calc_grid_entry_short(
+            &exchange_params,
+            &fixtures::state_params(trigger_short),
+            &bot_params,
+            &short,
+        );
+        assert!(at_short.qty < 0.0);
+        assert_eq!(at_short.price, trigger_short);
+        let below_short = calc_grid_entry_short(
+            &exchange_params,
+            &fixtures::state_params(trigger_short - exchange_params.price_step),
+            &bot_params,
+            &short,
+        );
+        assert_eq!(
+            (below_short.qty, below_short.price),
+            (at_short.qty, trigger_short)
+        );
+        assert!(trigger_long < 60000.0 && trigger_short > 60000.0);
+    }
+
+    #[test]
+    fn no_next_grid_trigger_price_when_flat_or_full() {
+        let exchange_params = fixtures::exchange_params();
+        let bot_params = plain_grid_bot_params();
+        let trigger = |position: &Position| {
+            (
+                calc_next_grid_trigger_price_long(
+                    position,
+                    10000.0,
+                    0.0,
+                    &bot_params,
+                    &exchange_params,
+                ),
+                calc_next_grid_trigger_price_short(
+                    &Position {
+                        size: -position.size,
+                        price: position.price,
+                    },
+                    10000.0,
+                    0.0,
+                    &bot_params,
+                    &exchange_params,
+                ),
+            )
+        };
+        assert_eq!(trigger(&Position::default()), (None, None));
+        // wallet_exposure_limit 1.0 of 10000.0 at 60000.0
+        let full = Position {
+            size: 0.167,
+            price: 60000.0,
+        };
+        assert_eq!(trigger(&full), (None, None));
+    }
+
+    #[test]
+    fn every_entry_path_stops_at_the_exposure_limit_tolerance() {
+        let exchange_params = ExchangeParams {
+            min_cost: 1.0,
+            ..exchange_params(1.0)
+        };
+        let bot_params = BotParams {
+            entry_trailing_threshold_pct: 0.0,
+            entry_trailing_retracement_pct: 0.01,
+            ..plain_grid_bot_params()
+        };
+        let balance = 1000.0;
+        let state = |price: f64| StateParams {
+            balance,
+            ..fixtures::state_params(price)
+        };
+        let (state_long, state_short) = (state(960.0), state(1040.0));
+        let bundle_long = TrailingPriceBundle {
+            min_since_open: 950.0,
+            max_since_min: 960.0,
+            ..Default::default()
+        };
+        let bundle_short = TrailingPriceBundle {
+            max_since_open: 1050.0,
+            min_since_max: 1035.0,
+            ..Default::default()
+        };
+        // (position price, at the limit): exposure of size 1.0 is position price / balance
+        for (position_price, at_limit) in [(999.0, true), (998.0, false)] {
+            let long = Position {
+                size: 1.0,
+                price: position_price,
+            };
+            let short = Position {
+                size: -1.0,
+                price: position_price,
+            };
+            let wallet_exposure = calc_wallet_exposure(1.0, balance, 1.0, position_price);
+            assert_eq!(
+                wallet_exposure
+                    == bot_params.wallet_exposure_limit * (1.0 - EXPOSURE_LIMIT_TOLERANCE),
+                at_limit
+            );
+            let entry_qtys = [
+                calc_grid_entry_long(&exchange_params, &state_long, &bot_params, &long).qty,
+                calc_trailing_entry_long(
+                    &exchange_params,
+                    &state_long,
+                    &bot_params,
+                    &long,
+                    &bundle_long,
+                )
+                .qty,
+                calc_grid_entry_short(&exchange_params, &state_short, &bot_params, &short).qty,
+                calc_trailing_entry_short(
+                    &exchange_params,
+                    &state_short,
+                    &bot_params,
+                    &short,
+                    &bundle_short,
+                )
+                .qty,
+            ];
+            let triggers = [
+                calc_next_grid_trigger_price_long(
+                    &long,
+                    balance,
+                    0.0,
+                    &bot_params,
+                    &exchange_params,
+                ),
+                calc_next_grid_trigger_price_short(
+                    &short,
+                    balance,
+                    0.0,
+                    &bot_params,
+                    &exchange_params,
+                ),
+            ];
+            if at_limit {
+                assert_eq!(entry_qtys, [0.0; 4]);
+                assert_eq!(triggers, [None, None]);
+            } else {
+                assert!(entry_qtys.iter().all(|&qty| qty != 0.0), "{:?}", entry_qtys);
+                assert!(triggers.iter().all(Option::is_some));
+            }
+        }
+    }
+
+    #[test]
+    fn shared_precomputed_state_gives_identical_orders() {
+        let exchange_params = fixtures::exchange_params();
+        let trailing_price_bundle = TrailingPriceBundle {
+            min_since_open: 57000.0,
+            max_since_min: 58000.0,
+            max_since_open: 63000.0,
+            min_since_max: 62000.0,
+        };
+        let mut bot_params_variants = Vec::new();
+        for entry_trailing_grid_ratio in [-0.5, 0.0, 0.5, 1.0] {
+            for entry_trailing_grid_mode in [
+                EntryTrailingGridMode::Switch,
+                EntryTrailingGridMode::Blended,
+            ] {
+                bot_params_variants.push(BotParams {
+                    entry_trailing_grid_ratio,
+                    entry_trailing_grid_mode,
+                    entry_sizing_basis: EntrySizingBasis::Equity,
+                    ..fixtures::bot_params()
+                });
+            }
+        }
+        for bot_params in &bot_params_variants {
+            for (state_params, position) in fixtures::long_entry_matrix() {
+                let state_params = StateParams {
+                    equity: Some(10500.0),
+                    ..state_params
+                };
+                let short = Position {
+                    size: -position.size,
+                    price: position.price,
+                };
+                // derived once with a book 1% off, as the grid walk moves it
+                let derived_from = StateParams {
+                    order_book: OrderBook {
+                        bid: state_params.order_book.bid * 0.99,
+                        ask: state_params.order_book.ask * 1.01,
+                    },
+                    ..state_params.clone()
+                };
+                let long_precomputed =
+                    PrecomputedState::long(&exchange_params, &derived_from, bot_params);
+                let short_precomputed =
+                    PrecomputedState::short(&exchange_params, &derived_from, bot_params);
+                let args = (&exchange_params, &state_params, bot_params);
+                assert_eq!(
+                    calc_next_entry_long_with_precomputed(
+                        args.0,
+                        args.1,
+                        args.2,
+                        &position,
+                        &trailing_price_bundle,
+                        &long_precomputed,
+                    ),
+                    calc_next_entry_long(args.0, args.1, args.2, &position, &trailing_price_bundle)
+                );
+                assert_eq!(
+                    calc_entries_long_with_precomputed(
+                        args.0,
+                        args.1,
+                        args.2,
+                        &position,
+                        &trailing_price_bundle,
+                        &long_precomputed,
+                    ),
+                    calc_entries_long(args.0, args.1, args.2, &position, &trailing_price_bundle)
+                );
+                assert_eq!(
+                    calc_blended_entries_long_with_precomputed(
+                        args.0,
+                        args.1,
+                        args.2,
+                        &position,
+                        &trailing_price_bundle,
+                        &long_precomputed,
+                    ),
+                    calc_blended_entries_long(
+                        args.0,
+                        args.1,
+                        args.2,
+                        &position,
+                        &trailing_price_bundle
+                    )
+                );
+                assert_eq!(
+                    calc_next_entry_short_with_precomputed(
+                        args.0,
+                        args.1,
+                        args.2,
+                        &short,
+                        &trailing_price_bundle,
+                        &short_precomputed,
+                    ),
+                    calc_next_entry_short(args.0, args.1, args.2, &short, &trailing_price_bundle)
+                );
+                assert_eq!(
+                    calc_entries_short_with_precomputed(
+                        args.0,
+                        args.1,
+                        args.2,
+                        &short,
+                        &trailing_price_bundle,
+                        &short_precomputed,
+                    ),
+                    calc_entries_short(args.0, args.1, args.2, &short, &trailing_price_bundle)
+                );
+                assert_eq!(
+                    calc_blended_entries_short_with_precomputed(
+                        args.0,
+                        args.1,
+                        args.2,
+                        &short,
+                        &trailing_price_bundle,
+                        &short_precomputed,
+                    ),
+                    calc_blended_entries_short(
+                        args.0,
+                        args.1,
+                        args.2,
+                        &short,
+                        &trailing_price_bundle
+                    )
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn grid_spacing_widens_with_volatility() {
+        let exchange_params = fixtures::exchange_params();
+        let bot_params = plain_grid_bot_params();
+        let weighted = BotParams {
+            entry_grid_spacing_vol_weight: 100.0,
+            ..bot_params.clone()
+        };
+        // the doc example: 1% becomes 1.5% at volatility 0.005 with a weight of 100
+        let one_pct = BotParams {
+            entry_grid_spacing_pct: 0.01,
+            ..weighted.clone()
+        };
+        assert_close(calc_entry_grid_spacing_pct(&one_pct, 0.005), 0.015);
+        assert_eq!(calc_entry_grid_spacing_pct(&weighted, 0.0), 0.05);
+        assert_eq!(calc_entry_grid_spacing_pct(&bot_params, 0.005), 0.05);
+
+        let long = Position {
+            size: 0.01,
+            price: 60000.0,
+        };
+        let short = Position {
+            size: -0.01,
+            price: 60000.0,
+        };
+        // (bot_params, volatility, long re-entry price, short re-entry price); spacing 5%
+        // widened to 7.5% by the weight only
+        for (bot_params, volatility, long_price, short_price) in [
+            (&bot_params, 0.005, 57000.0, 63000.0),
+            (&weighted, 0.0, 57000.0, 63000.0),
+            (&weighted, 0.005, 55500.0, 64500.0),
+        ] {
+            let state_long = StateParams {
+                volatility,
+                ..fixtures::state_params(long_price)
+            };
+            let state_short = StateParams {
+                volatility,
+                ..fixtures::state_params(short_price)
+            };
+            let entry_long = calc_grid_entry_long(&exchange_params, &state_long, bot_params, &long);
+            let entry_short =
+                calc_grid_entry_short(&exchange_params, &state_short, bot_params, &short);
+            assert_eq!(
+                (entry_long.price, entry_short.price),
+                (long_price, short_price)
+            );
+            assert_eq!(
+                calc_next_grid_trigger_price_long(
+                    &long,
+                    10000.0,
+                    volatility,
+                    bot_params,
+                    &exchange_params
+                ),
+                Some(long_price)
+            );
+            assert_eq!(
+                calc_next_grid_trigger_price_short(
+                    &short,
+                    10000.0,
+                    volatility,
+                    bot_params,
+                    &exchange_params
+                ),
+                Some(short_price)
+            );
+        }
+    }
+
+    /// A position of the other side: size -0.01 for long calculators, 0.01 for short ones.
+    fn wrong_side(side: Side) -> Position {
+        Position {
+            size: if side == Side::Long { -0.01 } else { 0.01 },
+            price: 60000.0,
+        }
+    }
+
+    // One test per calculator given a position of the other side: check_side panics in debug
+    // builds, release builds get no orders.
+    macro_rules! wrong_side_tests {
+        ($($name:ident: $side:expr, |$position:ident| $calc:expr => $empty:expr;)*) => {
+            $(
+                #[test]
+                #[cfg_attr(debug_assertions, should_panic(expected = "with the wrong sign"))]
+                fn $name() {
+                    let $position = &wrong_side($side);
+                    assert_eq!($calc, $empty);
+                }
+            )*
+        };
+    }
+
+    wrong_side_tests! {
+        wrong_side_grid_entry_long: Side::Long, |position| calc_grid_entry_long(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+        ) => Order::default();
+        wrong_side_grid_entry_short: Side::Short, |position| calc_grid_entry_short(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+        ) => Order::default();
+        wrong_side_trailing_entry_long: Side::Long, |position| calc_trailing_entry_long(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+            &TrailingPriceBundle::default(),
+        ) => Order::default();
+        wrong_side_trailing_entry_short: Side::Short, |position| calc_trailing_entry_short(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+            &TrailingPriceBundle::default(),
+        ) => Order::default();
+        wrong_side_next_entry_long: Side::Long, |position| calc_next_entry_long(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+            &TrailingPriceBundle::default(),
+        ) => Order::default();
+        wrong_side_next_entry_short: Side::Short, |position| calc_next_entry_short(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+            &TrailingPriceBundle::default(),
+        ) => Order::default();
+        wrong_side_next_entry_long_explained: Side::Long, |position| {
+            calc_next_entry_long_explained(
+                &fixtures::exchange_params(),
+                &fixtures::state_params(60000.0),
+                &fixtures::bot_params(),
+                position,
+                &TrailingPriceBundle::default(),
+            )
+        } => (Order::default(), EmptyReason::WrongSidePosition);
+        wrong_side_blended_entries_long: Side::Long, |position| calc_blended_entries_long(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+            &TrailingPriceBundle::default(),
+        ) => Vec::<Order>::new();
+        wrong_side_blended_entries_short: Side::Short, |position| calc_blended_entries_short(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+            &TrailingPriceBundle::default(),
+        ) => Vec::<Order>::new();
+        wrong_side_entries_long: Side::Long, |position| calc_entries_long(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+            &TrailingPriceBundle::default(),
+        ) => Vec::<Order>::new();
+        wrong_side_entries_short: Side::Short, |position| calc_entries_short(
+            &fixtures::exchange_params(),
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            position,
+            &TrailingPriceBundle::default(),
+        ) => Vec::<Order>::new();
+        wrong_side_next_grid_trigger_price_long: Side::Long, |position| {
+            calc_next_grid_trigger_price_long(
+                position,
+                10000.0,
+                0.0,
+                &fixtures::bot_params(),
+                &fixtures::exchange_params(),
+            )
+        } => None;
+        wrong_side_next_grid_trigger_price_short: Side::Short, |position| {
+            calc_next_grid_trigger_price_short(
+                position,
+                10000.0,
+                0.0,
+                &fixtures::bot_params(),
+                &fixtures::exchange_params(),
+            )
+        } => None;
+    }
+}
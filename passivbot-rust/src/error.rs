@@ -0,0 +1,32 @@
+use std::fmt;
+
+/// Errors raised across the Python bindings. Bad inputs become ValueError, io failures OSError
+/// and broken internal invariants, including caught panics, RuntimeError.
+#[derive(Debug)]
+pub enum PassivbotError {
+    InvalidParams(String),
+    Io {
+        context: String,
+        source: std::io::Error,
+    },
+    Internal(String),
+}
+
+impl fmt::Display for PassivbotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PassivbotError::InvalidParams(msg) => write!(f, "invalid params: {}", msg),
+            PassivbotError::Io { context, source } => write!(f, "{}: {}", context, source),
+            PassivbotError::Internal(msg) => write!(f, "internal error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for PassivbotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PassivbotError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
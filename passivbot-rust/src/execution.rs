@@ -0,0 +1,341 @@
+//! Exchange request payloads for orders. Builds the parameters only; sending them, signing
+//! and rate limiting are left to the live layer.
+
+use crate::types::{ExchangeParams, Order, OrderKind, OrderType, PositionMode, Side};
+use crate::utils::round_;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeInForce {
+    Gtc,
+    Ioc,
+    PostOnly,
+}
+
+/// Exchange neutral limit order request, for clients of exchanges without a builder below.
+/// qty is unsigned; side gives the direction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderRequest {
+    pub symbol: String,
+    pub side: OrderSide,
+    pub position_side: Option<Side>, // None in one-way mode
+    pub order_type: OrderType,       // the intent, e.g. for logging; the request is a limit order
+    pub price: f64,
+    pub qty: f64,
+    pub reduce_only: bool,
+    pub time_in_force: TimeInForce,
+}
+
+impl Order {
+    /// None for empty orders, which are not to be placed.
+    pub fn to_order_request(
+        &self,
+        symbol: &str,
+        position_mode: PositionMode,
+    ) -> Option<OrderRequest> {
+        if self.is_empty() {
+            return None;
+        }
+        let side = self.order_type.side()?;
+        Some(OrderRequest {
+            symbol: symbol.to_string(),
+            side: if self.qty > 0.0 {
+                OrderSide::Buy
+            } else {
+                OrderSide::Sell
+            },
+            position_side: match position_mode {
+                PositionMode::Hedge => Some(side),
+                PositionMode::OneWay => None,
+            },
+            order_type: self.order_type,
+            price: self.price,
+            qty: self.qty.abs(),
+            reduce_only: self.order_type.is_close(),
+            time_in_force: time_in_force(self.order_type),
+        })
+    }
+}
+
+/// Parameters of a Binance USDⓈ-M futures new order request (POST /fapi/v1/order).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BinanceOrderRequest {
+    pub symbol: String,
+    pub side: String,          // BUY or SELL
+    pub position_side: String, // LONG or SHORT in hedge mode, BOTH in one-way mode
+    #[serde(rename = "type")]
+    pub order_type: String,
+    pub time_in_force: String,
+    pub quantity: String,
+    pub price: String,
+    // rejected by Binance in hedge mode, where positionSide implies it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reduce_only: Option<String>,
+    pub new_client_order_id: String,
+}
+
+/// Parameters of a Bybit v5 linear perpetual create order request (POST /v5/order/create).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitOrderRequest {
+    pub category: String,
+    pub symbol: String,
+    pub side: String, // Buy or Sell
+    pub order_type: String,
+    pub qty: String,
+    pub price: String,
+    pub time_in_force: String,
+    pub position_idx: u8, // 0 in one-way mode, 1 long and 2 short in hedge mode
+    pub reduce_only: bool,
+    pub order_link_id: String,
+}
+
+/// value rounded to step, printed with exactly as many decimals as step, e.g. 0.1 + 0.2 with
+/// step 0.01 as "0.30". Avoids the float noise and exponent notation of plain formatting.
+pub fn format_to_step(value: f64, step: f64) -> String {
+    // f64 Display never uses exponent notation, so the decimals of step can be read off it
+    let decimals = format!("{}", step)
+        .split_once('.')
+        .map_or(0, |(_, fraction)| fraction.len());
+    format!("{:.*}", decimals, round_(value, step))
+}
+
+/// Post-only for grid orders, which rest in the book by design; IOC for kill switch closes,
+/// which must execute at once or not at all; GTC for the rest, e.g. an initial entry moved to
+/// the touch after entry_initial_timeout_sec.
+fn time_in_force(order_type: OrderType) -> TimeInForce {
+    match order_type.kind() {
+        OrderKind::EntryGrid | OrderKind::CloseGrid => TimeInForce::PostOnly,
+        OrderKind::CloseStop => TimeInForce::Ioc,
+        _ => TimeInForce::Gtc,
+    }
+}
+
+/// Limit order request for order, with its custom id as client order id. None for empty
+/// orders, which are not to be placed.
+pub fn to_binance_futures_params(
+    order: &Order,
+    symbol: &str,
+    exchange_params: &ExchangeParams,
+    position_mode: PositionMode,
+) -> Option<BinanceOrderRequest> {
+    let request = order.to_order_request(symbol, position_mode)?;
+    Some(BinanceOrderRequest {
+        symbol: request.symbol,
+        side: match request.side {
+            OrderSide::Buy => "BUY",
+            OrderSide::Sell => "SELL",
+        }
+        .to_string(),
+        position_side: match request.position_side {
+            Some(Side::Long) => "LONG",
+            Some(Side::Short) => "SHORT",
+            None => "BOTH",
+        }
+        .to_string(),
+        order_type: "LIMIT".to_string(),
+        time_in_force: match request.time_in_force {
+            TimeInForce::Gtc => "GTC",
+            TimeInForce::Ioc => "IOC",
+            TimeInForce::PostOnly => "GTX",
+        }
+        .to_string(),
+        quantity: format_to_step(request.qty, exchange_params.qty_step),
+        price: format_to_step(request.price, exchange_params.price_step),
+        reduce_only: request
+            .position_side
+            .is_none()
+            .then(|| request.reduce_only.to_string()),
+        new_client_order_id: order.custom_id(symbol, exchange_params),
+    })
+}
+
+/// Limit order request for order, with its custom id as order link id. None for empty
+/// orders, which are not to be placed.
+pub fn to_bybit_v5_params(
+    order: &Order,
+    symbol: &str,
+    exchange_params: &ExchangeParams,
+    position_mode: PositionMode,
+) -> Option<BybitOrderRequest> {
+    let request = order.to_order_request(symbol, position_mode)?;
+    Some(BybitOrderRequest {
+        category: "linear".to_string(),
+        symbol: request.symbol,
+        side: match request.side {
+            OrderSide::Buy => "Buy",
+            OrderSide::Sell => "Sell",
+        }
+        .to_string(),
+        order_type: "Limit".to_string(),
+        qty: format_to_step(request.qty, exchange_params.qty_step),
+        price: format_to_step(request.price, exchange_params.price_step),
+        time_in_force: match request.time_in_force {
+            TimeInForce::Gtc => "GTC",
+            TimeInForce::Ioc => "IOC",
+            TimeInForce::PostOnly => "PostOnly",
+        }
+        .to_string(),
+        position_idx: match request.position_side {
+            Some(Side::Long) => 1,
+            Some(Side::Short) => 2,
+            None => 0,
+        },
+        reduce_only: request.reduce_only,
+        order_link_id: order.custom_id(symbol, exchange_params),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    #[cfg(feature = "json")]
+    use serde_json::json;
+
+    #[test]
+    fn format_to_step_prints_step_decimals() {
+        assert_eq!(format_to_step(0.1 + 0.2, 0.01), "0.30");
+        assert_eq!(format_to_step(60000.06, 0.1), "60000.1");
+        assert_eq!(format_to_step(0.00000123, 1e-8), "0.00000123");
+        assert_eq!(format_to_step(2.6, 0.5), "2.5");
+        assert_eq!(format_to_step(1234.4, 1.0), "1234");
+        assert_eq!(format_to_step(1e21, 1.0), "1000000000000000000000");
+    }
+
+    #[cfg(feature = "json")]
+    fn custom_id(order_type: OrderType, price_ticks: u64, qty_steps: u64) -> String {
+        // 50d4 tags BTCUSDT
+        format!(
+            "{}_{}_{}_50d4",
+            order_type.short_code(),
+            price_ticks,
+            qty_steps
+        )
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn binance_request_bodies() {
+        let exchange_params = fixtures::exchange_params();
+        let entry = Order::new(0.1 + 0.2, 60000.1, OrderType::EntryGridNormalLong);
+        let request =
+            to_binance_futures_params(&entry, "BTCUSDT", &exchange_params, PositionMode::Hedge);
+        assert_eq!(
+            serde_json::to_value(request.unwrap()).unwrap(),
+            json!({
+                "symbol": "BTCUSDT",
+                "side": "BUY",
+                "positionSide": "LONG",
+                "type": "LIMIT",
+                "timeInForce": "GTX",
+                "quantity": "0.300",
+                "price": "60000.1",
+                "newClientOrderId": "egnl_600001_300_50d4",
+            })
+        );
+        // one-way mode: positionSide BOTH, and reduceOnly instead
+        let close = Order::new(-0.3, 61000.0, OrderType::CloseGridLong);
+        let request =
+            to_binance_futures_params(&close, "BTCUSDT", &exchange_params, PositionMode::OneWay);
+        assert_eq!(
+            serde_json::to_value(request.unwrap()).unwrap(),
+            json!({
+                "symbol": "BTCUSDT",
+                "side": "SELL",
+                "positionSide": "BOTH",
+                "type": "LIMIT",
+                "timeInForce": "GTX",
+                "quantity": "0.300",
+                "price": "61000.0",
+                "reduceOnly": "true",
+                "newClientOrderId": custom_id(OrderType::CloseGridLong, 610000, 300),
+            })
+        );
+        let stop = Order::new(0.3, 59000.0, OrderType::CloseStopShort);
+        let request =
+            to_binance_futures_params(&stop, "BTCUSDT", &exchange_params, PositionMode::Hedge)
+                .unwrap();
+        assert_eq!(
+            (request.side.as_str(), request.position_side.as_str()),
+            ("BUY", "SHORT")
+        );
+        assert_eq!(
+            (request.time_in_force.as_str(), request.reduce_only),
+            ("IOC", None)
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn bybit_request_bodies() {
+        let exchange_params = fixtures::exchange_params();
+        let entry = Order::new(-0.3, 60000.0, OrderType::EntryInitialNormalShort);
+        let request = to_bybit_v5_params(&entry, "BTCUSDT", &exchange_params, PositionMode::Hedge);
+        assert_eq!(
+            serde_json::to_value(request.unwrap()).unwrap(),
+            json!({
+                "category": "linear",
+                "symbol": "BTCUSDT",
+                "side": "Sell",
+                "orderType": "Limit",
+                "qty": "0.300",
+                "price": "60000.0",
+                "timeInForce": "GTC",
+                "positionIdx": 2,
+                "reduceOnly": false,
+                "orderLinkId": custom_id(OrderType::EntryInitialNormalShort, 600000, 300),
+            })
+        );
+        let stop = Order::new(0.3, 60000.3, OrderType::CloseStopShort);
+        let request = to_bybit_v5_params(&stop, "BTCUSDT", &exchange_params, PositionMode::OneWay);
+        assert_eq!(
+            serde_json::to_value(request.unwrap()).unwrap(),
+            json!({
+                "category": "linear",
+                "symbol": "BTCUSDT",
+                "side": "Buy",
+                "orderType": "Limit",
+                "qty": "0.300",
+                "price": "60000.3",
+                "timeInForce": "IOC",
+                "positionIdx": 0,
+                "reduceOnly": true,
+                "orderLinkId": custom_id(OrderType::CloseStopShort, 600003, 300),
+            })
+        );
+        let close = Order::new(-0.3, 61000.0, OrderType::CloseGridLong);
+        let request =
+            to_bybit_v5_params(&close, "BTCUSDT", &exchange_params, PositionMode::Hedge).unwrap();
+        assert_eq!(
+            (request.position_idx, request.time_in_force.as_str()),
+            (1, "PostOnly")
+        );
+    }
+
+    #[test]
+    fn empty_orders_build_no_request() {
+        let exchange_params = fixtures::exchange_params();
+        for position_mode in [PositionMode::Hedge, PositionMode::OneWay] {
+            let empty = Order::default();
+            assert_eq!(empty.to_order_request("BTCUSDT", position_mode), None);
+            assert_eq!(
+                to_binance_futures_params(&empty, "BTCUSDT", &exchange_params, position_mode),
+                None
+            );
+            assert_eq!(
+                to_bybit_v5_params(&empty, "BTCUSDT", &exchange_params, position_mode),
+                None
+            );
+        }
+    }
+}
@@ -0,0 +1,449 @@
+//! C ABI for non-Python consumers, built with the ffi feature.
+//! include/passivbot.h is generated from this module by cbindgen, see cbindgen.toml.
+//!
+//! The structs mirror their Rust counterparts field for field: bools are 0 or 1, optional
+//! values are NaN for None and enums are integer codes. Every function returns PB_OK or one of
+//! the negative PB_ERR codes, and writes to out only on PB_OK. Panics are caught and reported as
+//! PB_ERR_PANIC, never unwound into the caller. Field order and codes are part of the ABI:
+//! append new fields and codes at the end only.
+
+use crate::closes::{calc_next_close_long, calc_next_close_short};
+use crate::entries::{calc_next_entry_long, calc_next_entry_short};
+use crate::types::{
+    BotParams, EMABands, EmaWarmupBehavior, EntrySizingBasis, EntryTrailingGridMode,
+    EntryTrailingMode, ExchangeParams, LastFill, Order, OrderBook, Position, StateParams,
+    TrailingPriceBundle,
+};
+use std::panic::{self, AssertUnwindSafe};
+
+pub const PB_OK: i32 = 0;
+pub const PB_ERR_NULL_POINTER: i32 = -1;
+pub const PB_ERR_INVALID_PARAMS: i32 = -2; // e.g. an unknown entry_trailing_mode
+pub const PB_ERR_PANIC: i32 = -3;
+
+pub const PB_ENTRY_TRAILING_MODE_THRESHOLD_DRAWDOWN: i32 = 0;
+pub const PB_ENTRY_TRAILING_MODE_TRAILING_STOP: i32 = 1;
+
+pub const PB_ENTRY_TRAILING_GRID_MODE_SWITCH: i32 = 0;
+pub const PB_ENTRY_TRAILING_GRID_MODE_BLENDED: i32 = 1;
+pub const PB_EMA_WARMUP_SUPPRESS_ENTRIES: i32 = 0;
+pub const PB_EMA_WARMUP_FALLBACK_TO_ORDER_BOOK: i32 = 1;
+pub const PB_ENTRY_SIZING_BALANCE: i32 = 0;
+pub const PB_ENTRY_SIZING_EQUITY: i32 = 1;
+
+pub const PB_MAX_QTY_SCHEDULE_LEN: usize = 16;
+pub const PB_MAX_BLACKOUT_WINDOWS: usize = 16;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ExchangeParamsC {
+    pub qty_step: f64,
+    pub price_step: f64,
+    pub min_qty: f64,
+    pub min_cost: f64,
+    pub c_mult: f64,
+    pub fee_rate: f64,
+    pub max_position_size: f64, // 0 means unlimited
+    pub price_band_pct: f64,    // 0 means no band
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct StateParamsC {
+    pub balance: f64,
+    pub bid: f64,
+    pub ask: f64,
+    pub ema_band_upper: f64,
+    pub ema_band_lower: f64,
+    pub timestamp: u64,
+    pub last_entry_fill_price: f64, // NaN if no entry filled yet
+    pub last_entry_fill_timestamp: u64,
+    pub trailing_add_count: u64,
+    pub equity: f64, // NaN if unknown, sizing then falls back to balance
+    pub volatility: f64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct BotParamsC {
+    pub auto_adjust_markup: u8,
+    pub balance_reserve_pct: f64,
+    pub bracket_stop_pct: f64,
+    pub close_grid_gap_pct: f64,
+    pub close_grid_markup_range: f64,
+    pub close_grid_min_markup: f64,
+    pub close_min_net_markup: f64,
+    pub close_grid_qty_pct: f64,
+    pub close_trailing_retracement_pct: f64,
+    pub close_trailing_grid_ratio: f64,
+    pub close_trailing_qty_pct: f64,
+    pub close_trailing_threshold_pct: f64,
+    pub coalesce_grid_orders: u8,
+    pub entry_grid_double_down_factor: f64,
+    pub entry_grid_spacing_weight: f64,
+    pub entry_grid_spacing_pct: f64,
+    pub entry_grid_ema_floor: u8,
+    pub entry_initial_ema_dist: f64,
+    pub entry_initial_qty_pct: f64,
+    pub entry_initial_timeout_sec: f64,
+    pub entry_reentry_ema_dist: f64, // NaN falls back to entry_initial_ema_dist
+    pub entry_trailing_drawdown_pct: f64,
+    pub entry_trailing_mode: i32, // PB_ENTRY_TRAILING_MODE_*
+    pub entry_trailing_retracement_pct: f64,
+    pub entry_trailing_grid_ratio: f64,
+    pub entry_trailing_threshold_pct: f64,
+    pub filter_rolling_window: u64,
+    pub filter_relative_volume_clip_pct: f64,
+    pub ema_span_0: f64,
+    pub ema_span_1: f64,
+    pub n_positions: u64,
+    pub total_wallet_exposure_limit: f64,
+    pub wallet_exposure_limit: f64,
+    pub unstuck_close_pct: f64,
+    pub unstuck_ema_dist: f64,
+    pub unstuck_loss_allowance_pct: f64,
+    pub unstuck_threshold: f64,
+    pub entry_trailing_max_adds: u64,  // 0 means unlimited
+    pub entry_trailing_grid_mode: i32, // PB_ENTRY_TRAILING_GRID_MODE_*
+    pub ema_warmup_behavior: i32,      // PB_EMA_WARMUP_*
+    pub close_trailing_min_profit_pct: f64,
+    pub entry_grid_qty_schedule: [f64; PB_MAX_QTY_SCHEDULE_LEN],
+    pub entry_grid_qty_schedule_len: u64, // at most PB_MAX_QTY_SCHEDULE_LEN; 0 uses double down
+    pub entry_blackout_windows: [[u64; 2]; PB_MAX_BLACKOUT_WINDOWS], // [start_ms, end_ms)
+    pub entry_blackout_windows_len: u64,  // at most PB_MAX_BLACKOUT_WINDOWS
+    pub close_grid_max_markup: f64,
+    pub entry_sizing_basis: i32, // PB_ENTRY_SIZING_*
+    pub close_trailing_drawdown_floor_pct: f64,
+    pub entry_grid_spacing_vol_weight: f64,
+    pub close_grid_min_entry_distance_ratio: f64, // 0.0 is off
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PositionC {
+    pub size: f64, // negative for shorts
+    pub price: f64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct OrderC {
+    pub qty: f64,
+    pub price: f64,
+    pub order_type: u8, // OrderType code; 22 is empty, with qty 0
+}
+
+impl From<&ExchangeParamsC> for ExchangeParams {
+    fn from(params: &ExchangeParamsC) -> Self {
+        ExchangeParams {
+            qty_step: params.qty_step,
+            price_step: params.price_step,
+            min_qty: params.min_qty,
+            min_cost: params.min_cost,
+            c_mult: params.c_mult,
+            fee_rate: params.fee_rate,
+            max_position_size: params.max_position_size,
+            price_band_pct: params.price_band_pct,
+        }
+    }
+}
+
+impl From<&StateParamsC> for StateParams {
+    fn from(params: &StateParamsC) -> Self {
+        StateParams {
+            balance: params.balance,
+            equity: if params.equity.is_nan() {
+                None
+            } else {
+                Some(params.equity)
+            },
+            order_book: OrderBook {
+                bid: params.bid,
+                ask: params.ask,
+            },
+            ema_bands: EMABands {
+                upper: params.ema_band_upper,
+                lower: params.ema_band_lower,
+            },
+            timestamp: params.timestamp,
+            last_entry_fill: if params.last_entry_fill_price.is_nan() {
+                None
+            } else {
+                Some(LastFill {
+                    price: params.last_entry_fill_price,
+                    timestamp: params.last_entry_fill_timestamp,
+                })
+            },
+            trailing_add_count: params.trailing_add_count as usize,
+            volatility: params.volatility,
+        }
+    }
+}
+
+impl TryFrom<&BotParamsC> for BotParams {
+    type Error = i32;
+
+    fn try_from(params: &BotParamsC) -> Result<Self, Self::Error> {
+        let qty_schedule = params
+            .entry_grid_qty_schedule
+            .get(..params.entry_grid_qty_schedule_len as usize)
+            .ok_or(PB_ERR_INVALID_PARAMS)?;
+        let blackout_windows = params
+            .entry_blackout_windows
+            .get(..params.entry_blackout_windows_len as usize)
+            .ok_or(PB_ERR_INVALID_PARAMS)?;
+        let mut bot_params = BotParams {
+            auto_adjust_markup: params.auto_adjust_markup != 0,
+            balance_reserve_pct: params.balance_reserve_pct,
+            bracket_stop_pct: params.bracket_stop_pct,
+            close_grid_gap_pct: params.close_grid_gap_pct,
+            close_grid_markup_range: params.close_grid_markup_range,
+            close_grid_min_markup: params.close_grid_min_markup,
+            close_grid_max_markup: params.close_grid_max_markup,
+            close_grid_min_entry_distance_ratio: params.close_grid_min_entry_distance_ratio,
+            close_min_net_markup: params.close_min_net_markup,
+            close_grid_qty_pct: params.close_grid_qty_pct,
+            close_trailing_retracement_pct: params.close_trailing_retracement_pct,
+            close_trailing_drawdown_floor_pct: params.close_trailing_drawdown_floor_pct,
+            close_trailing_grid_ratio: params.close_trailing_grid_ratio,
+            close_trailing_qty_pct: params.close_trailing_qty_pct,
+            close_trailing_threshold_pct: params.close_trailing_threshold_pct,
+            close_trailing_min_profit_pct: params.close_trailing_min_profit_pct,
+            coalesce_grid_orders: params.coalesce_grid_orders != 0,
+            entry_grid_double_down_factor: params.entry_grid_double_down_factor,
+            entry_grid_qty_schedule: qty_schedule.to_vec(),
+            entry_grid_spacing_weight: params.entry_grid_spacing_weight,
+            entry_grid_spacing_pct: params.entry_grid_spacing_pct,
+            entry_grid_spacing_vol_weight: params.entry_grid_spacing_vol_weight,
+            entry_grid_ema_floor: params.entry_grid_ema_floor != 0,
+            entry_blackout_windows: blackout_windows
+                .iter()
+                .map(|&[start, end]| (start, end))
+                .collect(),
+            entry_initial_ema_dist: params.entry_initial_ema_dist,
+            entry_initial_qty_pct: params.entry_initial_qty_pct,
+            entry_initial_timeout_sec: params.entry_initial_timeout_sec,
+            entry_reentry_ema_dist: if params.entry_reentry_ema_dist.is_nan() {
+                None
+            } else {
+                Some(params.entry_reentry_ema_dist)
+            },
+            entry_sizing_basis: match params.entry_sizing_basis {
+                PB_ENTRY_SIZING_BALANCE => EntrySizingBasis::Balance,
+                PB_ENTRY_SIZING_EQUITY => EntrySizingBasis::Equity,
+                _ => return Err(PB_ERR_INVALID_PARAMS),
+            },
+            entry_trailing_drawdown_pct: params.entry_trailing_drawdown_pct,
+            entry_trailing_max_adds: params.entry_trailing_max_adds as usize,
+            entry_trailing_mode: match params.entry_trailing_mode {
+                PB_ENTRY_TRAILING_MODE_THRESHOLD_DRAWDOWN => EntryTrailingMode::ThresholdDrawdown,
+                PB_ENTRY_TRAILING_MODE_TRAILING_STOP => EntryTrailingMode::TrailingStop,
+                _ => return Err(PB_ERR_INVALID_PARAMS),
+            },
+            entry_trailing_retracement_pct: params.entry_trailing_retracement_pct,
+            entry_trailing_grid_mode: match params.entry_trailing_grid_mode {
+                PB_ENTRY_TRAILING_GRID_MODE_SWITCH => EntryTrailingGridMode::Switch,
+                PB_ENTRY_TRAILING_GRID_MODE_BLENDED => EntryTrailingGridMode::Blended,
+                _ => return Err(PB_ERR_INVALID_PARAMS),
+            },
+            entry_trailing_grid_ratio: params.entry_trailing_grid_ratio,
+            entry_trailing_threshold_pct: params.entry_trailing_threshold_pct,
+            filter_rolling_window: params.filter_rolling_window as usize,
+            filter_relative_volume_clip_pct: params.filter_relative_volume_clip_pct,
+            ema_span_0: params.ema_span_0,
+            ema_span_1: params.ema_span_1,
+            ema_warmup_behavior: match params.ema_warmup_behavior {
+                PB_EMA_WARMUP_SUPPRESS_ENTRIES => EmaWarmupBehavior::SuppressEntries,
+                PB_EMA_WARMUP_FALLBACK_TO_ORDER_BOOK => EmaWarmupBehavior::FallbackToOrderBook,
+                _ => return Err(PB_ERR_INVALID_PARAMS),
+            },
+            n_positions: params.n_positions as usize,
+            total_wallet_exposure_limit: params.total_wallet_exposure_limit,
+            wallet_exposure_limit: params.wallet_exposure_limit,
+            unstuck_close_pct: params.unstuck_close_pct,
+            unstuck_ema_dist: params.unstuck_ema_dist,
+            unstuck_loss_allowance_pct: params.unstuck_loss_allowance_pct,
+            unstuck_threshold: params.unstuck_threshold,
+        };
+        bot_params.adjust_markup();
+        Ok(bot_params)
+    }
+}
+
+impl From<Order> for OrderC {
+    fn from(order: Order) -> Self {
+        OrderC {
+            qty: order.qty,
+            price: order.price,
+            order_type: order.order_type.code(),
+        }
+    }
+}
+
+type Calculator =
+    fn(&ExchangeParams, &StateParams, &BotParams, &Position, &TrailingPriceBundle) -> Order;
+
+/// Converts the inputs, runs calculator and writes its order to out.
+unsafe fn run_calculator(
+    exchange_params: *const ExchangeParamsC,
+    state_params: *const StateParamsC,
+    bot_params: *const BotParamsC,
+    position: *const PositionC,
+    trailing_price_bundle: TrailingPriceBundle,
+    out: *mut OrderC,
+    calculator: Calculator,
+) -> i32 {
+    if exchange_params.is_null()
+        || state_params.is_null()
+        || bot_params.is_null()
+        || position.is_null()
+        || out.is_null()
+    {
+        return PB_ERR_NULL_POINTER;
+    }
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let bot_params = BotParams::try_from(&*bot_params)?;
+        Ok(calculator(
+            &ExchangeParams::from(&*exchange_params),
+            &StateParams::from(&*state_params),
+            &bot_params,
+            &Position {
+                size: (*position).size,
+                price: (*position).price,
+            },
+            &trailing_price_bundle,
+        ))
+    }));
+    match result {
+        Ok(Ok(order)) => {
+            *out = OrderC::from(order);
+            PB_OK
+        }
+        Ok(Err(code)) => code,
+        Err(_) => PB_ERR_PANIC,
+    }
+}
+
+/// Next long entry. min_since_open and max_since_min are the trailing prices since the
+/// position last changed.
+///
+/// # Safety
+/// Every pointer must be null or valid for the duration of the call.
+#[no_mangle]
+pub unsafe extern "C" fn pb_calc_next_entry_long(
+    exchange_params: *const ExchangeParamsC,
+    state_params: *const StateParamsC,
+    bot_params: *const BotParamsC,
+    position: *const PositionC,
+    min_since_open: f64,
+    max_since_min: f64,
+    out: *mut OrderC,
+) -> i32 {
+    let trailing_price_bundle = TrailingPriceBundle {
+        min_since_open,
+        max_since_min,
+        ..Default::default()
+    };
+    run_calculator(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+        out,
+        calc_next_entry_long,
+    )
+}
+
+/// Next short entry. max_since_open and min_since_max are the trailing prices since the
+/// position last changed.
+///
+/// # Safety
+/// Every pointer must be null or valid for the duration of the call.
+#[no_mangle]
+pub unsafe extern "C" fn pb_calc_next_entry_short(
+    exchange_params: *const ExchangeParamsC,
+    state_params: *const StateParamsC,
+    bot_params: *const BotParamsC,
+    position: *const PositionC,
+    max_since_open: f64,
+    min_since_max: f64,
+    out: *mut OrderC,
+) -> i32 {
+    let trailing_price_bundle = TrailingPriceBundle {
+        max_since_open,
+        min_since_max,
+        ..Default::default()
+    };
+    run_calculator(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+        out,
+        calc_next_entry_short,
+    )
+}
+
+/// Next long close. max_since_open and min_since_max are the trailing prices since the
+/// position last changed.
+///
+/// # Safety
+/// Every pointer must be null or valid for the duration of the call.
+#[no_mangle]
+pub unsafe extern "C" fn pb_calc_next_close_long(
+    exchange_params: *const ExchangeParamsC,
+    state_params: *const StateParamsC,
+    bot_params: *const BotParamsC,
+    position: *const PositionC,
+    max_since_open: f64,
+    min_since_max: f64,
+    out: *mut OrderC,
+) -> i32 {
+    let trailing_price_bundle = TrailingPriceBundle {
+        max_since_open,
+        min_since_max,
+        ..Default::default()
+    };
+    run_calculator(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+        out,
+        calc_next_close_long,
+    )
+}
+
+/// Next short close. min_since_open and max_since_min are the trailing prices since the
+/// position last changed.
+///
+/// # Safety
+/// Every pointer must be null or valid for the duration of the call.
+#[no_mangle]
+pub unsafe extern "C" fn pb_calc_next_close_short(
+    exchange_params: *const ExchangeParamsC,
+    state_params: *const StateParamsC,
+    bot_params: *const BotParamsC,
+    position: *const PositionC,
+    min_since_open: f64,
+    max_since_min: f64,
+    out: *mut OrderC,
+) -> i32 {
+    let trailing_price_bundle = TrailingPriceBundle {
+        min_since_open,
+        max_since_min,
+        ..Default::default()
+    };
+    run_calculator(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+        out,
+        calc_next_close_short,
+    )
+}
@@ -0,0 +1,224 @@
+//! Representative inputs shared by benchmarks and integration tests.
+//! Values are taken from configs/template.json and a BTC-like perpetual.
+
+use crate::analysis::Metrics;
+use crate::optimize::{ParamBounds, ParamBoundsPair};
+use crate::orders::SymbolInput;
+use crate::types::{
+    BacktestParams, BotParams, BotParamsPair, Candle, EMABands, ExchangeParams, OrderBook,
+    Position, StateParams,
+};
+use crate::utils::round_;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+pub fn exchange_params() -> ExchangeParams {
+    ExchangeParams {
+        qty_step: 0.001,
+        price_step: 0.1,
+        min_qty: 0.001,
+        min_cost: 5.0,
+        c_mult: 1.0,
+        fee_rate: 0.0002,
+        max_position_size: 0.0,
+        price_band_pct: 0.0,
+    }
+}
+
+pub fn bot_params() -> BotParams {
+    let n_positions = 10;
+    let total_wallet_exposure_limit = 0.95859;
+    BotParams {
+        close_grid_markup_range: 0.0016219,
+        close_grid_min_markup: 0.012842,
+        close_grid_qty_pct: 0.65242,
+        close_trailing_grid_ratio: 0.021638,
+        close_trailing_qty_pct: 0.88439,
+        close_trailing_retracement_pct: 0.028672,
+        close_trailing_threshold_pct: 0.065293,
+        ema_span_0: 465.26,
+        ema_span_1: 1120.5,
+        entry_grid_double_down_factor: 2.3744,
+        entry_grid_spacing_pct: 0.052341,
+        entry_grid_spacing_weight: 0.070271,
+        entry_initial_ema_dist: -0.0059754,
+        entry_initial_qty_pct: 0.029454,
+        entry_trailing_grid_ratio: -0.28169,
+        entry_trailing_retracement_pct: 0.0024748,
+        entry_trailing_threshold_pct: -0.051708,
+        filter_relative_volume_clip_pct: 0.51416,
+        filter_rolling_window: 60,
+        n_positions,
+        total_wallet_exposure_limit,
+        wallet_exposure_limit: total_wallet_exposure_limit / n_positions as f64,
+        unstuck_close_pct: 0.071741,
+        unstuck_ema_dist: -0.053527,
+        unstuck_loss_allowance_pct: 0.033558,
+        unstuck_threshold: 0.49002,
+        ..Default::default()
+    }
+}
+
+pub fn bot_params_pair() -> BotParamsPair {
+    BotParamsPair {
+        long: bot_params(),
+        short: bot_params(),
+    }
+}
+
+/// The optimize bounds of configs/template.json, same for both sides.
+pub fn param_bounds_pair() -> ParamBoundsPair {
+    let bounds = ParamBounds {
+        close_grid_markup_range: (0.0, 0.03),
+        close_grid_min_markup: (0.001, 0.03),
+        close_grid_qty_pct: (0.05, 1.0),
+        close_trailing_grid_ratio: (-1.0, 1.0),
+        close_trailing_qty_pct: (0.05, 1.0),
+        close_trailing_retracement_pct: (0.0, 0.1),
+        close_trailing_threshold_pct: (-0.1, 0.1),
+        ema_span_0: (200.0, 1440.0),
+        ema_span_1: (200.0, 1440.0),
+        entry_grid_double_down_factor: (0.1, 3.0),
+        entry_grid_spacing_pct: (0.001, 0.12),
+        entry_grid_spacing_weight: (0.0, 10.0),
+        entry_initial_ema_dist: (-0.1, 0.003),
+        entry_initial_qty_pct: (0.005, 0.1),
+        entry_trailing_grid_ratio: (-1.0, 1.0),
+        entry_trailing_retracement_pct: (0.0, 0.1),
+        entry_trailing_threshold_pct: (-0.1, 0.1),
+        filter_relative_volume_clip_pct: (0.0, 1.0),
+        filter_rolling_window: (10.0, 360.0),
+        n_positions: (1.0, 20.0),
+        total_wallet_exposure_limit: (0.0, 5.0),
+        unstuck_close_pct: (0.001, 0.1),
+        unstuck_ema_dist: (-0.1, 0.01),
+        unstuck_loss_allowance_pct: (0.0, 0.05),
+        unstuck_threshold: (0.4, 0.95),
+    };
+    ParamBoundsPair {
+        long: bounds.clone(),
+        short: bounds,
+    }
+}
+
+/// Cheap deterministic stand-in for a backtest when testing optimizers. adg peaks at zero
+/// for long ema_span_0 800 and entry_grid_spacing_pct 0.05.
+pub fn toy_metrics(bot_params_pair: &BotParamsPair) -> Metrics {
+    let long = &bot_params_pair.long;
+    Metrics {
+        adg: -((long.ema_span_0 - 800.0) / 1240.0).powi(2)
+            - ((long.entry_grid_spacing_pct - 0.05) / 0.12).powi(2),
+        ..Default::default()
+    }
+}
+
+pub fn backtest_params() -> BacktestParams {
+    BacktestParams {
+        starting_balance: 10000.0,
+        maker_fee: 0.0002,
+        ..Default::default()
+    }
+}
+
+/// Book and EMA bands pinned at `price`.
+pub fn state_params(price: f64) -> StateParams {
+    StateParams {
+        balance: 10000.0,
+        order_book: OrderBook {
+            bid: price,
+            ask: price,
+        },
+        ema_bands: EMABands {
+            upper: price * 1.002,
+            lower: price * 0.998,
+        },
+        ..Default::default()
+    }
+}
+
+/// Long position worth about half the wallet exposure limit of state_params(price).
+pub fn position_long(price: f64) -> Position {
+    Position {
+        size: round_(
+            10000.0 * bot_params().wallet_exposure_limit * 0.5 / price,
+            exchange_params().qty_step,
+        ),
+        price: price * 1.03,
+    }
+}
+
+/// Seeded random walk of 1m candles starting at 60000.
+pub fn candles(n_candles: usize, seed: u64) -> Vec<Candle> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut close: f64 = 60000.0;
+    (0..n_candles)
+        .map(|k| {
+            let open = close;
+            close = round_(open * (1.0 + rng.gen_range(-0.0015..0.0015)), 0.1);
+            Candle {
+                timestamp: k as u64 * 60_000,
+                high: open.max(close) * (1.0 + rng.gen_range(0.0..0.0008)),
+                low: open.min(close) * (1.0 - rng.gen_range(0.0..0.0008)),
+                close,
+                volume: rng.gen_range(5.0..50.0),
+            }
+        })
+        .collect()
+}
+
+/// Input matrix for long entry calculations: flat and at increasing wallet exposure,
+/// each against a book below, at and above position price.
+pub fn long_entry_matrix() -> Vec<(StateParams, Position)> {
+    let exchange_params = exchange_params();
+    let full_psize = 10000.0 * bot_params().wallet_exposure_limit / 60000.0;
+    let mut cases = Vec::new();
+    for exposure_fraction in [0.0, 0.1, 0.25, 0.5, 0.9, 1.0] {
+        for price_ratio in [0.9, 0.97, 1.0, 1.03] {
+            cases.push((
+                state_params(60000.0 * price_ratio),
+                Position {
+                    size: round_(full_psize * exposure_fraction, exchange_params.qty_step),
+                    price: if exposure_fraction == 0.0 {
+                        0.0
+                    } else {
+                        60000.0
+                    },
+                },
+            ));
+        }
+    }
+    cases
+}
+
+/// Seeded symbols around 60000 for batch calculations, cycling through flat, long only, short
+/// only and both sides in position.
+pub fn symbol_inputs(n_symbols: usize, seed: u64) -> Vec<SymbolInput> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..n_symbols)
+        .map(|i| {
+            let price = round_(60000.0 * rng.gen_range(0.9..1.1), 0.1);
+            let position_long = position_long(price);
+            let position_short = Position {
+                size: -position_long.size,
+                price: price * 0.97,
+            };
+            SymbolInput {
+                exchange_params: exchange_params(),
+                state_params_long: state_params(price),
+                state_params_short: state_params(price),
+                bot_params_pair: bot_params_pair(),
+                position_long: if i % 2 == 1 {
+                    position_long
+                } else {
+                    Position::default()
+                },
+                position_short: if i % 4 >= 2 {
+                    position_short
+                } else {
+                    Position::default()
+                },
+                ..Default::default()
+            }
+        })
+        .collect()
+}
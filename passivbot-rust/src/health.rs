@@ -0,0 +1,194 @@
+use crate::types::{BotParams, ExchangeParams, OrderBook, Position};
+use crate::utils::{calc_pnl_long, calc_pnl_short, calc_wallet_exposure};
+use serde::{Deserialize, Serialize};
+
+/// Relative weights of the position health components. Only their ratios matter; they are
+/// normalized by their sum. Defaults weigh liquidation distance highest, as it is the one
+/// component whose limit is final.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthWeights {
+    pub exposure: f64,
+    pub drawdown: f64,
+    pub liquidation: f64,
+}
+
+impl Default for HealthWeights {
+    fn default() -> Self {
+        HealthWeights {
+            exposure: 0.3,
+            drawdown: 0.3,
+            liquidation: 0.4,
+        }
+    }
+}
+
+/// Price at which the position's loss equals the balance, i.e. liquidation with the whole
+/// balance as margin and no maintenance margin. 0.0 for a long that cannot be wiped out,
+/// infinite for a flat position.
+pub fn calc_bankruptcy_price(position: &Position, balance: f64, c_mult: f64) -> f64 {
+    let loss_per_price = position.size.abs() * c_mult;
+    if position.size > 0.0 {
+        f64::max(0.0, position.price - balance / loss_per_price)
+    } else {
+        position.price + balance / loss_per_price
+    }
+}
+
+/// One number per position from 1.0 (flat, or at no risk) to 0.0 (at the limit), computed as
+/// 1 minus the weighted mean of three penalties, each clipped to [0, 1]:
+/// - exposure: wallet exposure / wallet_exposure_limit
+/// - drawdown: unrealized loss / balance, marked at bid for long and ask for short
+/// - liquidation: 1 - distance from mark to calc_bankruptcy_price, as a fraction of mark
+///
+/// Zero weights or a non-positive balance with a position open give 0.0.
+pub fn calc_position_health(
+    position: &Position,
+    balance: f64,
+    order_book: &OrderBook,
+    bot_params: &BotParams,
+    exchange_params: &ExchangeParams,
+    weights: &HealthWeights,
+) -> f64 {
+    if position.size == 0.0 {
+        return 1.0;
+    }
+    let weight_sum = weights.exposure + weights.drawdown + weights.liquidation;
+    if balance <= 0.0 || weight_sum <= 0.0 {
+        return 0.0;
+    }
+    let c_mult = exchange_params.c_mult;
+    let wallet_exposure = calc_wallet_exposure(c_mult, balance, position.size, position.price);
+    let exposure = if bot_params.wallet_exposure_limit > 0.0 {
+        wallet_exposure / bot_params.wallet_exposure_limit
+    } else {
+        1.0
+    };
+    let (mark_price, upnl) = if position.size > 0.0 {
+        let mark_price = order_book.bid;
+        let upnl = calc_pnl_long(position.price, mark_price, position.size, c_mult);
+        (mark_price, upnl)
+    } else {
+        let mark_price = order_book.ask;
+        let upnl = calc_pnl_short(position.price, mark_price, position.size, c_mult);
+        (mark_price, upnl)
+    };
+    let drawdown = f64::max(0.0, -upnl) / balance;
+    let liquidation = if mark_price > 0.0 {
+        // negative once mark is past the bankruptcy price
+        let distance = (mark_price - calc_bankruptcy_price(position, balance, c_mult))
+            * position.size.signum()
+            / mark_price;
+        1.0 - distance
+    } else {
+        1.0
+    };
+    let penalty = weights.exposure * exposure.clamp(0.0, 1.0)
+        + weights.drawdown * drawdown.clamp(0.0, 1.0)
+        + weights.liquidation * liquidation.clamp(0.0, 1.0);
+    1.0 - penalty / weight_sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn health(position: Position, bid: f64, ask: f64, weights: &HealthWeights) -> f64 {
+        let bot_params = BotParams {
+            wallet_exposure_limit: 1.0,
+            ..Default::default()
+        };
+        calc_position_health(
+            &position,
+            10000.0,
+            &OrderBook { bid, ask },
+            &bot_params,
+            &fixtures::exchange_params(),
+            weights,
+        )
+    }
+
+    #[test]
+    fn healthy_and_near_liquidation_positions() {
+        let weights = HealthWeights::default();
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        // 0.06 exposure in profit, nowhere near bankruptcy
+        let healthy = Position {
+            size: 0.01,
+            price: 60000.0,
+        };
+        assert!(close(
+            health(healthy, 61000.0, 61000.1, &weights),
+            1.0 - 0.3 * 0.06
+        ));
+        // 3x exposure, 95% of the balance lost, 1000.0 above the 40000.0 bankruptcy price
+        let long = Position {
+            size: 0.5,
+            price: 60000.0,
+        };
+        assert_eq!(calc_bankruptcy_price(&long, 10000.0, 1.0), 40000.0);
+        let near_liquidation = 1.0 - (0.3 + 0.3 * 0.95 + 0.4 * (1.0 - 1000.0 / 41000.0));
+        assert!(close(
+            health(long, 41000.0, 41000.1, &weights),
+            near_liquidation
+        ));
+        assert!(near_liquidation < 0.03);
+        let short = Position {
+            size: -0.5,
+            price: 60000.0,
+        };
+        assert_eq!(calc_bankruptcy_price(&short, 10000.0, 1.0), 80000.0);
+        let near_liquidation = 1.0 - (0.3 + 0.3 * 0.95 + 0.4 * (1.0 - 1000.0 / 79000.0));
+        assert!(close(
+            health(short, 78999.9, 79000.0, &weights),
+            near_liquidation
+        ));
+        // past bankruptcy every penalty is maxed out
+        assert_eq!(health(short, 80999.9, 81000.0, &weights), 0.0);
+        assert_eq!(health(long, 38999.9, 39000.0, &weights), 0.0);
+    }
+
+    #[test]
+    fn health_weights_and_degenerate_inputs() {
+        let healthy = Position {
+            size: 0.01,
+            price: 60000.0,
+        };
+        let exposure_only = HealthWeights {
+            exposure: 1.0,
+            drawdown: 0.0,
+            liquidation: 0.0,
+        };
+        assert!((health(healthy, 61000.0, 61000.1, &exposure_only) - 0.94).abs() < 1e-9);
+        // only ratios matter
+        let scaled = HealthWeights {
+            exposure: 3.0,
+            drawdown: 3.0,
+            liquidation: 4.0,
+        };
+        assert!(
+            (health(healthy, 59000.0, 59000.1, &scaled)
+                - health(healthy, 59000.0, 59000.1, &HealthWeights::default()))
+            .abs()
+                < 1e-12
+        );
+        let zero = HealthWeights {
+            exposure: 0.0,
+            drawdown: 0.0,
+            liquidation: 0.0,
+        };
+        assert_eq!(health(healthy, 61000.0, 61000.1, &zero), 0.0);
+        assert_eq!(
+            health(
+                Position::default(),
+                61000.0,
+                61000.1,
+                &HealthWeights::default()
+            ),
+            1.0
+        );
+        // a long whose loss can never reach the balance
+        assert_eq!(calc_bankruptcy_price(&healthy, 10000.0, 1.0), 0.0);
+    }
+}
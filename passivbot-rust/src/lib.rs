@@ -1,22 +1,53 @@
+pub mod analysis;
+pub mod artifact;
 mod backtest;
-mod closes;
+pub mod closes;
 mod constants;
-mod entries;
+pub mod data;
+pub mod engine;
+pub mod entries;
+pub mod error;
+pub mod execution;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fixtures;
+pub mod health;
+pub mod optimize;
+pub mod orders;
+pub mod params;
+pub mod pruning;
+#[cfg(feature = "python")]
 mod python;
-mod types;
+pub mod runner;
+pub mod scoring;
+pub mod sync;
+pub mod types;
 mod utils;
+pub mod walk_forward;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
-use backtest::*;
-use closes::*;
-use entries::*;
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
-use pyo3::wrap_pyfunction;
-use python::*;
-use utils::*;
 
 /// A Python module implemented in Rust.
+#[cfg(feature = "python")]
 #[pymodule]
 fn passivbot_rust(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    use backtest::*;
+    use closes::*;
+    use entries::*;
+    use pyo3::wrap_pyfunction;
+    use python::*;
+    use utils::*;
+
+    m.add_class::<types::OrderType>()?;
+    m.add_class::<types::Side>()?;
+    m.add_class::<entries::EmptyReason>()?;
+    m.add_class::<PyOrder>()?;
+    m.add_class::<PyPosition>()?;
+    m.add_class::<PyMetrics>()?;
+    m.add_class::<PyBacktestResult>()?;
     m.add_function(wrap_pyfunction!(round_, m)?)?;
     m.add_function(wrap_pyfunction!(round_up, m)?)?;
     m.add_function(wrap_pyfunction!(round_dn, m)?)?;
@@ -33,6 +64,18 @@ fn passivbot_rust(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(calc_grid_entry_long_py, m)?)?;
     m.add_function(wrap_pyfunction!(calc_trailing_entry_long_py, m)?)?;
     m.add_function(wrap_pyfunction!(calc_next_entry_long_py, m)?)?;
+    m.add_function(wrap_pyfunction!(calc_exposure_headroom_py, m)?)?;
+    m.add_function(wrap_pyfunction!(calc_addable_qty_at_py, m)?)?;
+    m.add_function(wrap_pyfunction!(
+        calc_initial_entry_long_with_timeout_py,
+        m
+    )?)?;
+    m.add_function(wrap_pyfunction!(
+        calc_initial_entry_short_with_timeout_py,
+        m
+    )?)?;
+    m.add_function(wrap_pyfunction!(calc_twap_entry_long_py, m)?)?;
+    m.add_function(wrap_pyfunction!(calc_twap_entry_short_py, m)?)?;
     m.add_function(wrap_pyfunction!(calc_trailing_close_long_py, m)?)?;
     m.add_function(wrap_pyfunction!(calc_grid_close_long_py, m)?)?;
     m.add_function(wrap_pyfunction!(calc_next_close_long_py, m)?)?;
@@ -42,7 +85,28 @@ fn passivbot_rust(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(calc_entries_short_py, m)?)?;
     m.add_function(wrap_pyfunction!(calc_closes_long_py, m)?)?;
     m.add_function(wrap_pyfunction!(calc_closes_short_py, m)?)?;
+    m.add_function(wrap_pyfunction!(calc_min_entry_qty_py, m)?)?;
+    m.add_function(wrap_pyfunction!(check_bot_params_pair_py, m)?)?;
+    m.add_function(wrap_pyfunction!(calc_next_entry_long_dict_py, m)?)?;
+    m.add_function(wrap_pyfunction!(calc_next_entry_long_explained_dict_py, m)?)?;
+    m.add_function(wrap_pyfunction!(calc_next_entry_short_dict_py, m)?)?;
+    m.add_function(wrap_pyfunction!(calc_blended_entries_long_dict_py, m)?)?;
+    m.add_function(wrap_pyfunction!(calc_blended_entries_short_dict_py, m)?)?;
+    m.add_function(wrap_pyfunction!(calc_closes_long_dict_py, m)?)?;
+    m.add_function(wrap_pyfunction!(calc_closes_short_dict_py, m)?)?;
+    m.add_function(wrap_pyfunction!(
+        calc_next_grid_trigger_price_long_dict_py,
+        m
+    )?)?;
+    m.add_function(wrap_pyfunction!(
+        calc_next_grid_trigger_price_short_dict_py,
+        m
+    )?)?;
+    m.add_function(wrap_pyfunction!(calc_entries_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(order_type_from_code, m)?)?;
     m.add_function(wrap_pyfunction!(run_backtest, m)?)?;
+    m.add_function(wrap_pyfunction!(run_backtest_py, m)?)?;
+    m.add_function(wrap_pyfunction!(run_optimize_py, m)?)?;
     m.add_function(wrap_pyfunction!(calc_auto_unstuck_allowance, m)?)?;
     Ok(())
 }
@@ -0,0 +1,514 @@
+use crate::analysis::Metrics;
+use crate::artifact::ArtifactError;
+use crate::scoring::Scorer;
+use crate::types::{BotParams, BotParamsPair};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::thread;
+
+pub mod cache;
+pub mod genetic;
+pub mod pareto;
+pub mod pso;
+pub mod session;
+pub mod sweep;
+
+use cache::EvalCache;
+use session::OptimizeSession;
+
+// (low, high); low == high means fixed. Integer fields are rounded, so their bounds should be whole
+pub type Bound = (f64, f64);
+
+trait ParamValue: Copy {
+    fn to_f64(self) -> f64;
+    fn from_f64(value: f64) -> Self;
+}
+
+impl ParamValue for f64 {
+    fn to_f64(self) -> f64 {
+        self
+    }
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+}
+
+impl ParamValue for usize {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+    fn from_f64(value: f64) -> Self {
+        value.round().max(0.0) as usize
+    }
+}
+
+macro_rules! param_bounds {
+    ($($field:ident: $ty:ty),* $(,)?) => {
+        /// (low, high) range for every tunable BotParams field of one side.
+        #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+        #[serde(default)]
+        pub struct ParamBounds {
+            $(pub $field: Bound,)*
+        }
+
+        impl ParamBounds {
+            pub const FIELD_NAMES: &'static [&'static str] = &[$(stringify!($field),)*];
+
+            /// Bounds in the same order as to_vec.
+            pub fn bounds(&self) -> Vec<Bound> {
+                vec![$(self.$field,)*]
+            }
+
+            /// Flattens the tunable fields of bot_params in FIELD_NAMES order.
+            pub fn to_vec(&self, bot_params: &BotParams) -> Vec<f64> {
+                vec![$(ParamValue::to_f64(bot_params.$field),)*]
+            }
+
+            fn set_values(&self, bot_params: &mut BotParams, values: &[f64]) {
+                assert_eq!(values.len(), Self::FIELD_NAMES.len(), "wrong number of values");
+                let mut values = values.iter();
+                $(bot_params.$field = <$ty as ParamValue>::from_f64(*values.next().unwrap());)*
+                bot_params.wallet_exposure_limit = if bot_params.n_positions > 0 {
+                    bot_params.total_wallet_exposure_limit / bot_params.n_positions as f64
+                } else {
+                    0.0
+                };
+            }
+
+            fn to_flat(&self, flat: &mut HashMap<String, Bound>, prefix: &str) {
+                $(flat.insert(format!("{}{}", prefix, stringify!($field)), self.$field);)*
+            }
+
+            fn from_flat(
+                flat: &HashMap<String, Bound>,
+                prefix: &str,
+            ) -> Result<Self, String> {
+                let get = |name: &str| {
+                    flat.get(&format!("{}{}", prefix, name))
+                        .copied()
+                        .ok_or_else(|| format!("missing bound {}{}", prefix, name))
+                };
+                Ok(ParamBounds {
+                    $($field: get(stringify!($field))?,)*
+                })
+            }
+        }
+    };
+}
+
+param_bounds!(
+    close_grid_markup_range: f64,
+    close_grid_min_markup: f64,
+    close_grid_qty_pct: f64,
+    close_trailing_grid_ratio: f64,
+    close_trailing_qty_pct: f64,
+    close_trailing_retracement_pct: f64,
+    close_trailing_threshold_pct: f64,
+    ema_span_0: f64,
+    ema_span_1: f64,
+    entry_grid_double_down_factor: f64,
+    entry_grid_spacing_pct: f64,
+    entry_grid_spacing_weight: f64,
+    entry_initial_ema_dist: f64,
+    entry_initial_qty_pct: f64,
+    entry_trailing_grid_ratio: f64,
+    entry_trailing_retracement_pct: f64,
+    entry_trailing_threshold_pct: f64,
+    filter_relative_volume_clip_pct: f64,
+    filter_rolling_window: usize,
+    n_positions: usize,
+    total_wallet_exposure_limit: f64,
+    unstuck_close_pct: f64,
+    unstuck_ema_dist: f64,
+    unstuck_loss_allowance_pct: f64,
+    unstuck_threshold: f64,
+);
+
+impl ParamBounds {
+    /// Uniform sample within bounds. Fixed fields take their value.
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> BotParams {
+        let values: Vec<f64> = self
+            .bounds()
+            .iter()
+            .map(|&bound| sample_uniform(rng, bound))
+            .collect();
+        self.from_vec(&values)
+    }
+
+    /// Copy of bot_params with every tunable field moved onto the nearest bound.
+    /// Fields which are not tunable are left as they are.
+    pub fn clamp(&self, bot_params: &BotParams) -> BotParams {
+        let values: Vec<f64> = self
+            .to_vec(bot_params)
+            .iter()
+            .zip(self.bounds())
+            .map(|(&value, (low, high))| clamp_value(value, low, high))
+            .collect();
+        let mut clamped = bot_params.clone();
+        self.set_values(&mut clamped, &values);
+        clamped
+    }
+
+    /// Inverse of to_vec. Fields which are not tunable take their defaults,
+    /// wallet_exposure_limit is derived from total_wallet_exposure_limit / n_positions.
+    pub fn from_vec(&self, values: &[f64]) -> BotParams {
+        let mut bot_params = BotParams::default();
+        self.set_values(&mut bot_params, values);
+        bot_params
+    }
+}
+
+pub(crate) fn clamp_value(value: f64, low: f64, high: f64) -> f64 {
+    if low >= high {
+        low
+    } else {
+        value.max(low).min(high)
+    }
+}
+
+/// Long and short bounds, loaded from the flat format of config["optimize"]["bounds"],
+/// e.g. {"long_ema_span_0": [200.0, 1440.0], ...}. Unknown keys are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "HashMap<String, Bound>", into = "HashMap<String, Bound>")]
+pub struct ParamBoundsPair {
+    pub long: ParamBounds,
+    pub short: ParamBounds,
+}
+
+impl ParamBoundsPair {
+    /// Long bounds followed by short bounds, in the same order as to_vec.
+    pub fn bounds(&self) -> Vec<Bound> {
+        let mut bounds = self.long.bounds();
+        bounds.extend(self.short.bounds());
+        bounds
+    }
+
+    /// Flat field names, e.g. "long_ema_span_0", in the same order as to_vec.
+    pub fn field_names() -> Vec<String> {
+        ["long_", "short_"]
+            .iter()
+            .flat_map(|prefix| {
+                ParamBounds::FIELD_NAMES
+                    .iter()
+                    .map(move |name| format!("{}{}", prefix, name))
+            })
+            .collect()
+    }
+
+    pub fn to_vec(&self, bot_params_pair: &BotParamsPair) -> Vec<f64> {
+        let mut values = self.long.to_vec(&bot_params_pair.long);
+        values.extend(self.short.to_vec(&bot_params_pair.short));
+        values
+    }
+
+    pub fn from_vec(&self, values: &[f64]) -> BotParamsPair {
+        let (long, short) = values.split_at(ParamBounds::FIELD_NAMES.len());
+        BotParamsPair {
+            long: self.long.from_vec(long),
+            short: self.short.from_vec(short),
+        }
+    }
+}
+
+impl TryFrom<HashMap<String, Bound>> for ParamBoundsPair {
+    type Error = String;
+
+    fn try_from(flat: HashMap<String, Bound>) -> Result<Self, Self::Error> {
+        Ok(ParamBoundsPair {
+            long: ParamBounds::from_flat(&flat, "long_")?,
+            short: ParamBounds::from_flat(&flat, "short_")?,
+        })
+    }
+}
+
+impl From<ParamBoundsPair> for HashMap<String, Bound> {
+    fn from(bounds: ParamBoundsPair) -> Self {
+        let mut flat = HashMap::new();
+        bounds.long.to_flat(&mut flat, "long_");
+        bounds.short.to_flat(&mut flat, "short_");
+        flat
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoredConfig {
+    pub bot_params_pair: BotParamsPair,
+    pub metrics: Metrics,
+    pub score: f64,
+    pub genome: Vec<f64>, // flattened in ParamBoundsPair::to_vec order
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OptimizeSummary {
+    pub n_steps: usize,
+    pub best_score: f64,
+    pub cache_hits: usize,
+    pub cache_misses: usize, // equals the number of backtests run
+}
+
+/// Common interface of the optimization backends, so that a driver can step either one.
+pub trait Optimizer {
+    /// Runs one generation or iteration.
+    fn step(&mut self);
+
+    /// Best config found so far.
+    fn best(&self) -> &ScoredConfig;
+
+    /// Generations or iterations run so far, including those before a resume.
+    fn n_steps(&self) -> usize;
+
+    /// Snapshot of the full optimizer state, from which the run can be resumed.
+    fn session(&self) -> OptimizeSession;
+
+    fn cache(&self) -> &EvalCache;
+
+    fn summary(&self) -> OptimizeSummary {
+        OptimizeSummary {
+            n_steps: self.n_steps(),
+            best_score: self.best().score,
+            cache_hits: self.cache().hits(),
+            cache_misses: self.cache().misses(),
+        }
+    }
+}
+
+/// Steps the optimizer until it has run n_steps in total, calling on_step with the step index and
+/// best config so far. on_step is also called once with index 0 before the first step, unless the
+/// optimizer was resumed from a session.
+pub fn drive<O: Optimizer>(
+    optimizer: &mut O,
+    n_steps: usize,
+    mut on_step: impl FnMut(usize, &ScoredConfig),
+) {
+    drive_while(optimizer, n_steps, |step, best| {
+        on_step(step, best);
+        true
+    });
+}
+
+/// Same as drive, stopping early once on_step returns false. The optimizer keeps the state of
+/// the last completed step. Returns whether all n_steps were run.
+pub fn drive_while<O: Optimizer>(
+    optimizer: &mut O,
+    n_steps: usize,
+    mut on_step: impl FnMut(usize, &ScoredConfig) -> bool,
+) -> bool {
+    if optimizer.n_steps() == 0 && !on_step(0, optimizer.best()) {
+        return false;
+    }
+    while optimizer.n_steps() < n_steps {
+        optimizer.step();
+        if !on_step(optimizer.n_steps(), optimizer.best()) {
+            return optimizer.n_steps() >= n_steps;
+        }
+    }
+    true
+}
+
+/// Same as drive, additionally saving the session to path every checkpoint_interval steps
+/// and after the last one.
+pub fn drive_with_checkpoints<O: Optimizer>(
+    optimizer: &mut O,
+    n_steps: usize,
+    checkpoint_interval: usize,
+    path: impl AsRef<Path>,
+    mut on_step: impl FnMut(usize, &ScoredConfig),
+) -> Result<(), ArtifactError> {
+    let path = path.as_ref();
+    if optimizer.n_steps() == 0 {
+        on_step(0, optimizer.best());
+    }
+    while optimizer.n_steps() < n_steps {
+        optimizer.step();
+        on_step(optimizer.n_steps(), optimizer.best());
+        if optimizer
+            .n_steps()
+            .is_multiple_of(checkpoint_interval.max(1))
+            || optimizer.n_steps() == n_steps
+        {
+            optimizer.session().save(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Scores genomes, evaluating in parallel only those not already in the cache.
+/// Genomes sharing a cache key with an earlier one in the same batch count as hits.
+/// Results are in the same order as genomes.
+pub(crate) fn evaluate_genomes<E>(
+    genomes: &[Vec<f64>],
+    bounds: &ParamBoundsPair,
+    evaluator: &E,
+    scorer: &Scorer,
+    cache: &EvalCache,
+) -> Vec<ScoredConfig>
+where
+    E: Fn(&BotParamsPair) -> Metrics + Sync,
+{
+    let keys: Vec<u64> = genomes.iter().map(|genome| cache.key(genome)).collect();
+    let mut pending = HashSet::new();
+    let misses: Vec<(u64, &Vec<f64>)> = keys
+        .iter()
+        .zip(genomes)
+        .filter(|(&key, _)| cache.get_key(key).is_none() && pending.insert(key))
+        .map(|(&key, genome)| (key, genome))
+        .collect();
+    let miss_genomes: Vec<Vec<f64>> = misses.iter().map(|(_, genome)| genome.to_vec()).collect();
+    let evaluated = parallel_map(&miss_genomes, |genome| evaluator(&bounds.from_vec(genome)));
+    for ((key, _), metrics) in misses.iter().zip(evaluated) {
+        cache.insert_key(*key, metrics);
+    }
+    cache.record(genomes.len() - misses.len(), misses.len());
+    genomes
+        .iter()
+        .zip(keys)
+        .map(|(genome, key)| {
+            let metrics = cache.get_key(key).unwrap();
+            ScoredConfig {
+                bot_params_pair: bounds.from_vec(genome),
+                metrics,
+                score: scorer.score(&metrics),
+                genome: genome.clone(),
+            }
+        })
+        .collect()
+}
+
+/// Maps genomes across all available threads. Results are in the same order as genomes.
+pub(crate) fn parallel_map<T, F>(genomes: &[Vec<f64>], f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&Vec<f64>) -> T + Sync,
+{
+    let n_threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = genomes.len().div_ceil(n_threads).max(1);
+    thread::scope(|scope| {
+        let handles: Vec<_> = genomes
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| chunk.iter().map(&f).collect::<Vec<_>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+/// Best first; NaN scores sort last.
+pub(crate) fn compare_scores(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (false, false) => b.partial_cmp(&a).unwrap_or(Ordering::Equal),
+        (a_nan, b_nan) => a_nan.cmp(&b_nan),
+    }
+}
+
+pub(crate) fn sample_uniform<R: Rng>(rng: &mut R, (low, high): Bound) -> f64 {
+    if low < high {
+        rng.gen_range(low..=high)
+    } else {
+        low
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn samples_stay_within_bounds() {
+        let bounds = fixtures::param_bounds_pair();
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..100 {
+            let bot_params_pair = BotParamsPair {
+                long: bounds.long.sample(&mut rng),
+                short: bounds.short.sample(&mut rng),
+            };
+            let values = bounds.to_vec(&bot_params_pair);
+            for (&value, (low, high)) in values.iter().zip(bounds.bounds()) {
+                assert!(low <= value && value <= high);
+            }
+            let long = &bot_params_pair.long;
+            // integer fields are whole, wallet exposure limit is derived
+            let n_positions_index = ParamBounds::FIELD_NAMES
+                .iter()
+                .position(|&name| name == "n_positions")
+                .unwrap();
+            assert_eq!(long.n_positions as f64, values[n_positions_index]);
+            assert_eq!(
+                long.wallet_exposure_limit,
+                long.total_wallet_exposure_limit / long.n_positions as f64
+            );
+        }
+        // fixed fields take their value
+        let fixed = ParamBounds {
+            ema_span_0: (500.0, 500.0),
+            ..bounds.long.clone()
+        };
+        assert_eq!(fixed.sample(&mut rng).ema_span_0, 500.0);
+    }
+
+    #[test]
+    fn same_seed_same_samples() {
+        let bounds = fixtures::param_bounds_pair().long;
+        let samples = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            bounds.to_vec(&bounds.sample(&mut rng))
+        };
+        assert_eq!(samples(3), samples(3));
+        assert_ne!(samples(3), samples(4));
+    }
+
+    #[test]
+    fn clamp_moves_tunable_fields_onto_bounds() {
+        let bounds = fixtures::param_bounds_pair().long;
+        let bot_params = BotParams {
+            ema_span_0: 10.0,
+            entry_grid_spacing_pct: 0.5,
+            n_positions: 50,
+            entry_initial_timeout_sec: 30.0,
+            ..fixtures::bot_params()
+        };
+        let clamped = bounds.clamp(&bot_params);
+        assert_eq!(clamped.ema_span_0, 200.0);
+        assert_eq!(clamped.entry_grid_spacing_pct, 0.12);
+        assert_eq!(clamped.n_positions, 20);
+        assert_eq!(
+            clamped.wallet_exposure_limit,
+            bot_params.total_wallet_exposure_limit / 20.0
+        );
+        // in bounds and untunable fields stay
+        assert_eq!(clamped.ema_span_1, bot_params.ema_span_1);
+        assert_eq!(clamped.entry_initial_timeout_sec, 30.0);
+        assert_eq!(clamp_value(5.0, 3.0, 3.0), 3.0);
+    }
+
+    #[test]
+    fn flat_bounds_and_vectors_round_trip() {
+        let bounds = fixtures::param_bounds_pair();
+        let flat: HashMap<String, Bound> = bounds.clone().into();
+        assert_eq!(flat.len(), ParamBoundsPair::field_names().len());
+        assert_eq!(flat["long_ema_span_0"], (200.0, 1440.0));
+        assert_eq!(ParamBoundsPair::try_from(flat.clone()), Ok(bounds.clone()));
+        let mut missing = flat;
+        missing.remove("short_unstuck_threshold");
+        assert_eq!(
+            ParamBoundsPair::try_from(missing),
+            Err("missing bound short_unstuck_threshold".to_string())
+        );
+
+        let bot_params_pair = fixtures::bot_params_pair();
+        let values = bounds.to_vec(&bot_params_pair);
+        assert_eq!(values.len(), 2 * ParamBounds::FIELD_NAMES.len());
+        assert_eq!(bounds.to_vec(&bounds.from_vec(&values)), values);
+        assert_eq!(
+            bounds.from_vec(&values).long.wallet_exposure_limit,
+            bot_params_pair.long.wallet_exposure_limit
+        );
+    }
+}
@@ -0,0 +1,228 @@
+use crate::analysis::Metrics;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+pub const DEFAULT_SIGNIFICANT_DIGITS: u32 = 10;
+
+/// Metrics of every genome evaluated so far, shared between threads.
+/// Genomes are rounded to significant_digits before hashing, so configs differing only below
+/// that precision share one evaluation. significant_digits 0 disables rounding.
+#[derive(Debug)]
+pub struct EvalCache {
+    significant_digits: u32,
+    entries: Mutex<HashMap<u64, Metrics>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl EvalCache {
+    pub fn new(significant_digits: u32) -> Self {
+        EvalCache {
+            significant_digits,
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    /// Cached metrics of genome, counting a hit or a miss.
+    pub fn get(&self, genome: &[f64]) -> Option<Metrics> {
+        let metrics = self.get_key(self.key(genome));
+        let counter = if metrics.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        metrics
+    }
+
+    pub fn insert(&self, genome: &[f64], metrics: Metrics) {
+        self.insert_key(self.key(genome), metrics);
+    }
+
+    pub fn significant_digits(&self) -> u32 {
+        self.significant_digits
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// FNV-1a hash of the rounded genome's bits. Stable across runs and platforms,
+    /// unlike DefaultHasher, so keys survive a session round trip.
+    pub(crate) fn key(&self, genome: &[f64]) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for &gene in genome {
+            let gene = round_significant(gene, self.significant_digits);
+            for byte in gene.to_bits().to_le_bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        }
+        hash
+    }
+
+    pub(crate) fn get_key(&self, key: u64) -> Option<Metrics> {
+        self.entries.lock().unwrap().get(&key).copied()
+    }
+
+    pub(crate) fn insert_key(&self, key: u64, metrics: Metrics) {
+        self.entries.lock().unwrap().insert(key, metrics);
+    }
+
+    pub(crate) fn record(&self, hits: usize, misses: usize) {
+        self.hits.fetch_add(hits, Ordering::Relaxed);
+        self.misses.fetch_add(misses, Ordering::Relaxed);
+    }
+}
+
+impl Default for EvalCache {
+    fn default() -> Self {
+        EvalCache::new(DEFAULT_SIGNIFICANT_DIGITS)
+    }
+}
+
+impl Clone for EvalCache {
+    fn clone(&self) -> Self {
+        EvalCache {
+            significant_digits: self.significant_digits,
+            entries: Mutex::new(self.entries.lock().unwrap().clone()),
+            hits: AtomicUsize::new(self.hits()),
+            misses: AtomicUsize::new(self.misses()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct EvalCacheState {
+    significant_digits: u32,
+    entries: HashMap<u64, Metrics>,
+    hits: usize,
+    misses: usize,
+}
+
+impl Serialize for EvalCache {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        EvalCacheState {
+            significant_digits: self.significant_digits,
+            entries: self.entries.lock().unwrap().clone(),
+            hits: self.hits(),
+            misses: self.misses(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for EvalCache {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let state = EvalCacheState::deserialize(deserializer)?;
+        Ok(EvalCache {
+            significant_digits: state.significant_digits,
+            entries: Mutex::new(state.entries),
+            hits: AtomicUsize::new(state.hits),
+            misses: AtomicUsize::new(state.misses),
+        })
+    }
+}
+
+fn round_significant(value: f64, significant_digits: u32) -> f64 {
+    if value == 0.0 {
+        return 0.0; // folds -0.0 into 0.0
+    }
+    if significant_digits == 0 || !value.is_finite() {
+        return value;
+    }
+    let magnitude = value.abs().log10().floor() as i32;
+    let scale = 10f64.powi(significant_digits as i32 - 1 - magnitude);
+    if !scale.is_finite() || scale == 0.0 {
+        return value;
+    }
+    (value * scale).round() / scale
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::optimize::evaluate_genomes;
+    use crate::scoring::Scorer;
+
+    #[test]
+    fn keys_are_pinned_fnv_hashes_of_rounded_genomes() {
+        let cache = EvalCache::default();
+        // stored in saved sessions, so must never change
+        assert_eq!(cache.key(&[]), 0xcbf29ce484222325);
+        assert_eq!(cache.key(&[1.0, 0.5]), 0x2c194bea19d690b5);
+        assert_eq!(
+            cache.key(&[1.0, 0.5]),
+            cache.key(&[1.0 + 1e-12, 0.5 - 1e-13])
+        );
+        assert_ne!(cache.key(&[1.0, 0.5]), cache.key(&[1.0 + 1e-8, 0.5]));
+        assert_ne!(cache.key(&[1.0, 0.5]), cache.key(&[0.5, 1.0]));
+        assert_eq!(cache.key(&[-0.0]), cache.key(&[0.0]));
+        let exact = EvalCache::new(0);
+        assert_ne!(exact.key(&[1.0]), exact.key(&[1.0 + 1e-12]));
+        assert_eq!(round_significant(123456.789, 3), 123000.0);
+        assert_eq!(round_significant(-0.00123456, 2), -0.0012);
+        assert!(round_significant(f64::NAN, 3).is_nan());
+    }
+
+    #[test]
+    fn repeated_genomes_are_evaluated_once() {
+        let bounds = fixtures::param_bounds_pair();
+        let genome = bounds.to_vec(&fixtures::bot_params_pair());
+        let mut nudged = genome.clone();
+        nudged[0] *= 1.0 + 1e-13;
+        let n_evaluations = AtomicUsize::new(0);
+        let evaluator = |bot_params_pair: &_| {
+            n_evaluations.fetch_add(1, Ordering::SeqCst);
+            fixtures::toy_metrics(bot_params_pair)
+        };
+        let cache = EvalCache::default();
+        let scored = evaluate_genomes(
+            &[genome.clone(), nudged],
+            &bounds,
+            &evaluator,
+            &Scorer::default(),
+            &cache,
+        );
+        assert_eq!(scored[0].metrics.adg, scored[1].metrics.adg);
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (1, 1, 1));
+        evaluate_genomes(&[genome], &bounds, &evaluator, &Scorer::default(), &cache);
+        assert_eq!((cache.hits(), cache.misses()), (2, 1));
+        assert_eq!(n_evaluations.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_round_trip_keeps_entries_and_counters() {
+        let cache = EvalCache::new(6);
+        let metrics = Metrics {
+            adg: 0.001,
+            ..Default::default()
+        };
+        cache.insert(&[1.0, 2.0], metrics);
+        assert!(cache.get(&[1.0, 2.0]).is_some());
+        assert!(cache.get(&[2.0, 1.0]).is_none());
+        let restored: EvalCache =
+            serde_json::from_str(&serde_json::to_string(&cache).unwrap()).unwrap();
+        assert_eq!(restored.significant_digits(), 6);
+        assert_eq!((restored.hits(), restored.misses()), (1, 1));
+        assert_eq!(restored.get(&[1.0, 2.0000001]).unwrap().adg, 0.001);
+    }
+}
@@ -0,0 +1,350 @@
+use crate::analysis::Metrics;
+use crate::optimize::cache::{EvalCache, DEFAULT_SIGNIFICANT_DIGITS};
+use crate::optimize::session::{OptimizeSession, OptimizerState};
+use crate::optimize::{
+    compare_scores, drive, evaluate_genomes, sample_uniform, Bound, Optimizer, ParamBoundsPair,
+    ScoredConfig,
+};
+use crate::scoring::Scorer;
+use crate::types::BotParamsPair;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GaConfig {
+    pub population_size: usize,
+    pub n_generations: usize,
+    pub n_elites: usize, // best individuals copied unchanged into the next generation
+    pub tournament_size: usize,
+    pub crossover_prob: f64,
+    pub mutation_prob: f64,         // per gene
+    pub uniform_mutation_prob: f64, // share of mutations resampling the whole bound; the rest are gaussian
+    pub mutation_sigma: f64,        // gaussian std dev as a fraction of bound width
+    pub seed: u64,
+    pub cache_significant_digits: u32, // see EvalCache
+    pub scorer: Scorer,
+}
+
+impl Default for GaConfig {
+    fn default() -> Self {
+        GaConfig {
+            population_size: 100,
+            n_generations: 50,
+            n_elites: 2,
+            tournament_size: 3,
+            crossover_prob: 0.7,
+            mutation_prob: 0.02,
+            uniform_mutation_prob: 0.1,
+            mutation_sigma: 0.05,
+            seed: 0,
+            cache_significant_digits: DEFAULT_SIGNIFICANT_DIGITS,
+            scorer: Scorer::default(),
+        }
+    }
+}
+
+pub struct GeneticOptimizer<E> {
+    bounds: ParamBoundsPair,
+    evaluator: E,
+    ga_config: GaConfig,
+    gene_bounds: Vec<Bound>,
+    rng: ChaCha12Rng,
+    population: Vec<ScoredConfig>, // best first
+    generation: usize,
+    cache: EvalCache,
+}
+
+impl<E> GeneticOptimizer<E>
+where
+    E: Fn(&BotParamsPair) -> Metrics + Sync,
+{
+    /// Samples and evaluates the initial population.
+    pub fn new(bounds: &ParamBoundsPair, evaluator: E, ga_config: &GaConfig) -> Self {
+        let gene_bounds = bounds.bounds();
+        let mut rng = ChaCha12Rng::seed_from_u64(ga_config.seed);
+        let genomes: Vec<Vec<f64>> = (0..ga_config.population_size.max(1))
+            .map(|_| {
+                gene_bounds
+                    .iter()
+                    .map(|&bound| sample_uniform(&mut rng, bound))
+                    .collect()
+            })
+            .collect();
+        let cache = EvalCache::new(ga_config.cache_significant_digits);
+        let mut population =
+            evaluate_genomes(&genomes, bounds, &evaluator, &ga_config.scorer, &cache);
+        population.sort_by(|a, b| compare_scores(a.score, b.score));
+        GeneticOptimizer {
+            bounds: bounds.clone(),
+            evaluator,
+            ga_config: ga_config.clone(),
+            gene_bounds,
+            rng,
+            population,
+            generation: 0,
+            cache,
+        }
+    }
+
+    /// Continues a saved run. None if the session holds another optimizer's state.
+    pub fn from_session(session: OptimizeSession, evaluator: E) -> Option<Self> {
+        match session.state {
+            OptimizerState::Genetic {
+                ga_config,
+                rng,
+                population,
+                generation,
+            } => Some(GeneticOptimizer {
+                gene_bounds: session.bounds.bounds(),
+                bounds: session.bounds,
+                evaluator,
+                ga_config,
+                rng,
+                population,
+                generation,
+                cache: session.cache,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn population(&self) -> &[ScoredConfig] {
+        &self.population
+    }
+
+    pub fn into_population(self) -> Vec<ScoredConfig> {
+        self.population
+    }
+}
+
+impl<E> Optimizer for GeneticOptimizer<E>
+where
+    E: Fn(&BotParamsPair) -> Metrics + Sync,
+{
+    /// One generation: elitism, tournament selection, crossover and mutation.
+    fn step(&mut self) {
+        let population_size = self.population.len();
+        let n_elites = self.ga_config.n_elites.min(population_size);
+        let mut children = Vec::with_capacity(population_size - n_elites);
+        while n_elites + children.len() < population_size {
+            // population is sorted best first, so the lowest drawn index wins
+            let parent_0 = tournament(&mut self.rng, population_size, &self.ga_config, |a, b| {
+                a < b
+            });
+            let parent_1 = tournament(&mut self.rng, population_size, &self.ga_config, |a, b| {
+                a < b
+            });
+            children.push(breed(
+                &mut self.rng,
+                &self.population[parent_0].genome,
+                &self.population[parent_1].genome,
+                &self.gene_bounds,
+                &self.ga_config,
+            ));
+        }
+        self.population.truncate(n_elites);
+        self.population.extend(evaluate_genomes(
+            &children,
+            &self.bounds,
+            &self.evaluator,
+            &self.ga_config.scorer,
+            &self.cache,
+        ));
+        self.population
+            .sort_by(|a, b| compare_scores(a.score, b.score));
+        self.generation += 1;
+    }
+
+    fn best(&self) -> &ScoredConfig {
+        &self.population[0]
+    }
+
+    fn n_steps(&self) -> usize {
+        self.generation
+    }
+
+    fn session(&self) -> OptimizeSession {
+        OptimizeSession {
+            bounds: self.bounds.clone(),
+            state: OptimizerState::Genetic {
+                ga_config: self.ga_config.clone(),
+                rng: self.rng.clone(),
+                population: self.population.clone(),
+                generation: self.generation,
+            },
+            cache: self.cache.clone(),
+        }
+    }
+
+    fn cache(&self) -> &EvalCache {
+        &self.cache
+    }
+}
+
+/// Evolves bot params within bounds, maximizing ga_config.scorer applied to the evaluator's metrics.
+/// Each generation is evaluated in parallel; on_generation is called with the generation index and
+/// the best config so far. Returns the final population, best first.
+/// Same seed gives same result as long as the evaluator is deterministic.
+pub fn run<E, P>(
+    bounds: &ParamBoundsPair,
+    evaluator: E,
+    ga_config: &GaConfig,
+    on_generation: P,
+) -> Vec<ScoredConfig>
+where
+    E: Fn(&BotParamsPair) -> Metrics + Sync,
+    P: FnMut(usize, &ScoredConfig),
+{
+    let mut optimizer = GeneticOptimizer::new(bounds, evaluator, ga_config);
+    drive(&mut optimizer, ga_config.n_generations, on_generation);
+    optimizer.into_population()
+}
+
+/// Index of the tournament winner among tournament_size random draws from 0..population_size.
+pub(crate) fn tournament<R, F>(
+    rng: &mut R,
+    population_size: usize,
+    ga_config: &GaConfig,
+    is_better: F,
+) -> usize
+where
+    R: Rng,
+    F: Fn(usize, usize) -> bool,
+{
+    let mut winner = rng.gen_range(0..population_size);
+    for _ in 1..ga_config.tournament_size.max(1) {
+        let contender = rng.gen_range(0..population_size);
+        if is_better(contender, winner) {
+            winner = contender;
+        }
+    }
+    winner
+}
+
+/// Child of two parents: single point crossover with crossover_prob, then mutation.
+pub(crate) fn breed<R: Rng>(
+    rng: &mut R,
+    genome_0: &[f64],
+    genome_1: &[f64],
+    gene_bounds: &[Bound],
+    ga_config: &GaConfig,
+) -> Vec<f64> {
+    let mut child = if genome_0.len() >= 2 && rng.gen::<f64>() < ga_config.crossover_prob {
+        let point = rng.gen_range(1..genome_0.len());
+        genome_0[..point]
+            .iter()
+            .chain(&genome_1[point..])
+            .cloned()
+            .collect()
+    } else {
+        genome_0.to_vec()
+    };
+    mutate(rng, &mut child, gene_bounds, ga_config);
+    child
+}
+
+fn mutate<R: Rng>(rng: &mut R, genome: &mut [f64], gene_bounds: &[Bound], ga_config: &GaConfig) {
+    for (gene, &(low, high)) in genome.iter_mut().zip(gene_bounds) {
+        if low >= high {
+            *gene = low;
+            continue;
+        }
+        if rng.gen::<f64>() >= ga_config.mutation_prob {
+            continue;
+        }
+        *gene = if rng.gen::<f64>() < ga_config.uniform_mutation_prob {
+            sample_uniform(rng, (low, high))
+        } else {
+            (*gene + sample_standard_normal(rng) * ga_config.mutation_sigma * (high - low))
+                .max(low)
+                .min(high)
+        };
+    }
+}
+
+fn sample_standard_normal<R: Rng>(rng: &mut R) -> f64 {
+    // Box-Muller
+    let u0: f64 = 1.0 - rng.gen::<f64>(); // (0, 1], keeps ln finite
+    let u1: f64 = rng.gen();
+    (-2.0 * u0.ln()).sqrt() * (2.0 * PI * u1).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn ga_config(seed: u64) -> GaConfig {
+        GaConfig {
+            population_size: 20,
+            n_generations: 15,
+            mutation_prob: 0.2,
+            seed,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn same_seed_same_population() {
+        let bounds = fixtures::param_bounds_pair();
+        let genomes = |seed| -> Vec<Vec<f64>> {
+            run(&bounds, fixtures::toy_metrics, &ga_config(seed), |_, _| {})
+                .into_iter()
+                .map(|scored| scored.genome)
+                .collect()
+        };
+        let population = genomes(1);
+        assert_eq!(population.len(), 20);
+        assert_eq!(population, genomes(1));
+        assert_ne!(population, genomes(2));
+    }
+
+    #[test]
+    fn elitism_keeps_the_best_score_and_genomes_in_bounds() {
+        let bounds = fixtures::param_bounds_pair();
+        let mut best_scores = Vec::new();
+        let population = run(
+            &bounds,
+            fixtures::toy_metrics,
+            &ga_config(3),
+            |generation, best| {
+                best_scores.push((generation, best.score));
+            },
+        );
+        assert_eq!(
+            best_scores.iter().map(|&(g, _)| g).collect::<Vec<_>>(),
+            (0..=15).collect::<Vec<_>>()
+        );
+        assert!(best_scores.windows(2).all(|w| w[1].1 >= w[0].1));
+        assert!(best_scores[15].1 > best_scores[0].1);
+        // best first
+        assert!(population.windows(2).all(|w| w[0].score >= w[1].score));
+        for scored in &population {
+            for (&gene, (low, high)) in scored.genome.iter().zip(bounds.bounds()) {
+                assert!(low <= gene && gene <= high);
+            }
+            assert_eq!(
+                format!("{:?}", bounds.from_vec(&scored.genome)),
+                format!("{:?}", scored.bot_params_pair)
+            );
+        }
+    }
+
+    #[test]
+    fn mutation_respects_fixed_and_ranged_bounds() {
+        let ga_config = GaConfig {
+            mutation_prob: 1.0,
+            ..Default::default()
+        };
+        let gene_bounds = [(1.0, 1.0), (0.0, 1.0)];
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        for _ in 0..100 {
+            let child = breed(&mut rng, &[5.0, 0.5], &[5.0, 0.9], &gene_bounds, &ga_config);
+            assert_eq!(child[0], 1.0);
+            assert!((0.0..=1.0).contains(&child[1]));
+        }
+    }
+}
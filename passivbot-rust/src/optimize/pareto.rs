@@ -0,0 +1,339 @@
+use crate::analysis::Metrics;
+use crate::optimize::genetic::{breed, tournament, GaConfig};
+use crate::optimize::{parallel_map, sample_uniform, ParamBoundsPair};
+use crate::types::BotParamsPair;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone)]
+pub struct ParetoMember {
+    pub bot_params_pair: BotParamsPair,
+    pub metrics: Metrics,
+    pub objectives: Vec<f64>, // all maximized
+    pub genome: Vec<f64>,     // flattened in ParamBoundsPair::to_vec order
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct OptimizeResult {
+    pub front: Vec<ParetoMember>, // sorted by first objective, best first
+}
+
+/// True if a is at least as good as b in every objective and better in at least one.
+/// Objectives are maximized.
+pub fn dominates(a: &[f64], b: &[f64]) -> bool {
+    a.iter().zip(b).all(|(x, y)| x >= y) && a.iter().zip(b).any(|(x, y)| x > y)
+}
+
+/// Splits indices into successive non-dominated fronts, best front first.
+pub fn non_dominated_sort(objectives: &[Vec<f64>]) -> Vec<Vec<usize>> {
+    let n = objectives.len();
+    let mut dominated_by = vec![Vec::new(); n]; // indices each member dominates
+    let mut n_dominating = vec![0usize; n];
+    for i in 0..n {
+        for j in i + 1..n {
+            if dominates(&objectives[i], &objectives[j]) {
+                dominated_by[i].push(j);
+                n_dominating[j] += 1;
+            } else if dominates(&objectives[j], &objectives[i]) {
+                dominated_by[j].push(i);
+                n_dominating[i] += 1;
+            }
+        }
+    }
+    let mut fronts = Vec::new();
+    let mut front: Vec<usize> = (0..n).filter(|&i| n_dominating[i] == 0).collect();
+    while !front.is_empty() {
+        let mut next_front = Vec::new();
+        for &i in &front {
+            for &j in &dominated_by[i] {
+                n_dominating[j] -= 1;
+                if n_dominating[j] == 0 {
+                    next_front.push(j);
+                }
+            }
+        }
+        fronts.push(front);
+        front = next_front;
+    }
+    fronts
+}
+
+/// NSGA-II crowding distance of each member of front, in front order.
+/// Boundary members get infinite distance so that extremes are kept.
+pub fn crowding_distances(objectives: &[Vec<f64>], front: &[usize]) -> Vec<f64> {
+    let mut distances = vec![0.0; front.len()];
+    if front.len() <= 2 {
+        distances.fill(f64::INFINITY);
+        return distances;
+    }
+    let n_objectives = objectives[front[0]].len();
+    let mut order: Vec<usize> = (0..front.len()).collect();
+    // per objective, its value for each front member in front order
+    let columns = (0..n_objectives).map(|m| front.iter().map(move |&i| objectives[i][m]));
+    for values in columns {
+        let values: Vec<f64> = values.collect();
+        order.sort_by(|&a, &b| values[a].partial_cmp(&values[b]).unwrap_or(Ordering::Equal));
+        let min = values[order[0]];
+        let max = values[order[front.len() - 1]];
+        distances[order[0]] = f64::INFINITY;
+        distances[order[front.len() - 1]] = f64::INFINITY;
+        if max <= min {
+            continue;
+        }
+        for k in 1..front.len() - 1 {
+            distances[order[k]] += (values[order[k + 1]] - values[order[k - 1]]) / (max - min);
+        }
+    }
+    distances
+}
+
+/// Non-dominated members seen so far. Not size limited, so a continuous front keeps growing.
+#[derive(Debug, Default, Clone)]
+pub struct ParetoArchive {
+    members: Vec<ParetoMember>,
+}
+
+impl ParetoArchive {
+    /// Adds member unless it is dominated by, or ties with, an existing member.
+    /// Members it dominates are dropped. Returns whether it was added.
+    pub fn insert(&mut self, member: ParetoMember) -> bool {
+        if self.members.iter().any(|existing| {
+            existing.objectives == member.objectives
+                || dominates(&existing.objectives, &member.objectives)
+        }) {
+            return false;
+        }
+        self.members
+            .retain(|existing| !dominates(&member.objectives, &existing.objectives));
+        self.members.push(member);
+        true
+    }
+
+    pub fn members(&self) -> &[ParetoMember] {
+        &self.members
+    }
+
+    pub fn into_result(self) -> OptimizeResult {
+        let mut front = self.members;
+        front.sort_by(|a, b| {
+            b.objectives[0]
+                .partial_cmp(&a.objectives[0])
+                .unwrap_or(Ordering::Equal)
+        });
+        OptimizeResult { front }
+    }
+}
+
+/// NSGA-II over the flattened bounds. The evaluator returns metrics plus the objective vector,
+/// e.g. [adg, -max_drawdown]; all objectives are maximized and NaN counts as worst.
+/// Uses ga_config for population, operators and seed; its scorer is ignored.
+/// on_generation is called with the generation index and the archive so far.
+pub fn run_nsga2<E, P>(
+    bounds: &ParamBoundsPair,
+    evaluator: E,
+    ga_config: &GaConfig,
+    mut on_generation: P,
+) -> OptimizeResult
+where
+    E: Fn(&BotParamsPair) -> (Metrics, Vec<f64>) + Sync,
+    P: FnMut(usize, &ParetoArchive),
+{
+    let gene_bounds = bounds.bounds();
+    let population_size = ga_config.population_size.max(1);
+    let mut rng = StdRng::seed_from_u64(ga_config.seed);
+    let evaluate = |genomes: &[Vec<f64>]| {
+        parallel_map(genomes, |genome| {
+            let bot_params_pair = bounds.from_vec(genome);
+            let (metrics, objectives) = evaluator(&bot_params_pair);
+            ParetoMember {
+                bot_params_pair,
+                metrics,
+                objectives: objectives
+                    .into_iter()
+                    .map(|x| if x.is_nan() { f64::NEG_INFINITY } else { x })
+                    .collect(),
+                genome: genome.clone(),
+            }
+        })
+    };
+    let genomes: Vec<Vec<f64>> = (0..population_size)
+        .map(|_| {
+            gene_bounds
+                .iter()
+                .map(|&bound| sample_uniform(&mut rng, bound))
+                .collect()
+        })
+        .collect();
+    let mut archive = ParetoArchive::default();
+    let mut population = evaluate(&genomes);
+    for member in &population {
+        archive.insert(member.clone());
+    }
+    let (mut ranks, mut crowding) = select_survivors(&mut population, population_size);
+    on_generation(0, &archive);
+    for generation in 1..=ga_config.n_generations {
+        // crowded comparison: lower rank first, then larger crowding distance
+        let is_better = |a: usize, b: usize| {
+            ranks[a] < ranks[b] || (ranks[a] == ranks[b] && crowding[a] > crowding[b])
+        };
+        let children: Vec<Vec<f64>> = (0..population_size)
+            .map(|_| {
+                let parent_0 = tournament(&mut rng, population.len(), ga_config, is_better);
+                let parent_1 = tournament(&mut rng, population.len(), ga_config, is_better);
+                breed(
+                    &mut rng,
+                    &population[parent_0].genome,
+                    &population[parent_1].genome,
+                    &gene_bounds,
+                    ga_config,
+                )
+            })
+            .collect();
+        for child in evaluate(&children) {
+            archive.insert(child.clone());
+            population.push(child);
+        }
+        (ranks, crowding) = select_survivors(&mut population, population_size);
+        on_generation(generation, &archive);
+    }
+    archive.into_result()
+}
+
+/// Keeps the best population_size members by front, breaking the last front by crowding distance.
+/// Returns rank and crowding distance of each survivor.
+fn select_survivors(
+    population: &mut Vec<ParetoMember>,
+    population_size: usize,
+) -> (Vec<usize>, Vec<f64>) {
+    let objectives: Vec<Vec<f64>> = population
+        .iter()
+        .map(|member| member.objectives.clone())
+        .collect();
+    let mut selected = Vec::with_capacity(population_size);
+    for (rank, front) in non_dominated_sort(&objectives).into_iter().enumerate() {
+        if selected.len() >= population_size {
+            break;
+        }
+        let distances = crowding_distances(&objectives, &front);
+        let mut by_crowding: Vec<(usize, f64)> = front.into_iter().zip(distances).collect();
+        by_crowding.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        by_crowding.truncate(population_size - selected.len());
+        selected.extend(
+            by_crowding
+                .into_iter()
+                .map(|(i, distance)| (i, rank, distance)),
+        );
+    }
+    let mut members: Vec<Option<ParetoMember>> = population.drain(..).map(Some).collect();
+    let mut ranks = Vec::with_capacity(selected.len());
+    let mut crowding = Vec::with_capacity(selected.len());
+    for (i, rank, distance) in selected {
+        population.push(members[i].take().unwrap());
+        ranks.push(rank);
+        crowding.push(distance);
+    }
+    (ranks, crowding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn member(objectives: &[f64]) -> ParetoMember {
+        ParetoMember {
+            bot_params_pair: BotParamsPair::default(),
+            metrics: Metrics::default(),
+            objectives: objectives.to_vec(),
+            genome: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn dominance_needs_one_strictly_better_objective() {
+        assert!(dominates(&[2.0, 1.0], &[1.0, 1.0]));
+        assert!(!dominates(&[1.0, 1.0], &[1.0, 1.0]));
+        assert!(!dominates(&[2.0, 0.0], &[1.0, 1.0]));
+        assert!(!dominates(&[1.0, 1.0], &[2.0, 1.0]));
+    }
+
+    #[test]
+    fn fronts_and_crowding() {
+        let objectives = vec![
+            vec![1.0, 4.0],
+            vec![2.0, 3.0],
+            vec![3.0, 1.0],
+            vec![1.0, 2.0], // dominated by 1
+            vec![0.0, 0.0], // dominated by all
+            vec![4.0, 0.0],
+        ];
+        assert_eq!(
+            non_dominated_sort(&objectives),
+            vec![vec![0, 1, 2, 5], vec![3], vec![4]]
+        );
+        let distances = crowding_distances(&objectives, &[0, 1, 2, 5]);
+        // extremes kept; inner members by normalized neighbour gaps
+        assert_eq!(distances[0], f64::INFINITY);
+        assert_eq!(distances[3], f64::INFINITY);
+        assert!((distances[1] - (2.0 / 3.0 + 3.0 / 4.0)).abs() < 1e-12);
+        assert!((distances[2] - (2.0 / 3.0 + 3.0 / 4.0)).abs() < 1e-12);
+        assert_eq!(
+            crowding_distances(&objectives, &[3, 4]),
+            vec![f64::INFINITY; 2]
+        );
+    }
+
+    #[test]
+    fn archive_keeps_only_non_dominated_members() {
+        let mut archive = ParetoArchive::default();
+        assert!(archive.insert(member(&[1.0, 1.0])));
+        assert!(archive.insert(member(&[2.0, 0.0])));
+        assert!(!archive.insert(member(&[1.0, 1.0])));
+        assert!(!archive.insert(member(&[0.5, 0.5])));
+        // dominates the first member
+        assert!(archive.insert(member(&[1.5, 1.0])));
+        let front: Vec<Vec<f64>> = archive
+            .into_result()
+            .front
+            .into_iter()
+            .map(|member| member.objectives)
+            .collect();
+        assert_eq!(front, vec![vec![2.0, 0.0], vec![1.5, 1.0]]);
+    }
+
+    #[test]
+    fn nsga2_front_is_non_dominated_and_seeded() {
+        let bounds = fixtures::param_bounds_pair();
+        // competing objectives: ema_span_0 towards 800 vs towards 200
+        let evaluator = |bot_params_pair: &BotParamsPair| {
+            let ema_span_0 = bot_params_pair.long.ema_span_0;
+            (
+                Metrics::default(),
+                vec![-(ema_span_0 - 800.0).abs(), -(ema_span_0 - 200.0).abs()],
+            )
+        };
+        let ga_config = GaConfig {
+            population_size: 16,
+            n_generations: 5,
+            seed: 5,
+            ..Default::default()
+        };
+        let result = run_nsga2(&bounds, evaluator, &ga_config, |_, _| {});
+        assert!(!result.front.is_empty());
+        for a in &result.front {
+            for b in &result.front {
+                assert!(!dominates(&a.objectives, &b.objectives));
+            }
+        }
+        assert!(result
+            .front
+            .windows(2)
+            .all(|w| w[0].objectives[0] >= w[1].objectives[0]));
+        let again = run_nsga2(&bounds, evaluator, &ga_config, |_, _| {});
+        assert_eq!(
+            result.front.iter().map(|m| &m.genome).collect::<Vec<_>>(),
+            again.front.iter().map(|m| &m.genome).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,315 @@
+use crate::analysis::Metrics;
+use crate::optimize::cache::{EvalCache, DEFAULT_SIGNIFICANT_DIGITS};
+use crate::optimize::session::{OptimizeSession, OptimizerState};
+use crate::optimize::{
+    clamp_value, compare_scores, drive, evaluate_genomes, sample_uniform, Bound, Optimizer,
+    ParamBoundsPair, ScoredConfig,
+};
+use crate::scoring::Scorer;
+use crate::types::BotParamsPair;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PsoConfig {
+    pub n_particles: usize,
+    pub n_iterations: usize,
+    pub inertia: f64,
+    pub cognitive: f64,             // pull towards the particle's own best
+    pub social: f64,                // pull towards the swarm's best
+    pub max_velocity_fraction: f64, // velocity clamp as a fraction of bound width
+    pub seed: u64,
+    pub cache_significant_digits: u32, // see EvalCache
+    pub scorer: Scorer,
+}
+
+impl Default for PsoConfig {
+    fn default() -> Self {
+        // constriction coefficients from Clerc and Kennedy
+        PsoConfig {
+            n_particles: 100,
+            n_iterations: 50,
+            inertia: 0.7298,
+            cognitive: 1.49618,
+            social: 1.49618,
+            max_velocity_fraction: 0.2,
+            seed: 0,
+            cache_significant_digits: DEFAULT_SIGNIFICANT_DIGITS,
+            scorer: Scorer::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Particle {
+    pub position: Vec<f64>,
+    pub velocity: Vec<f64>,
+    pub best: ScoredConfig,
+}
+
+pub struct SwarmOptimizer<E> {
+    bounds: ParamBoundsPair,
+    evaluator: E,
+    pso_config: PsoConfig,
+    gene_bounds: Vec<Bound>,
+    rng: ChaCha12Rng,
+    particles: Vec<Particle>,
+    best_index: usize, // particle holding the swarm's best
+    iteration: usize,
+    cache: EvalCache,
+}
+
+impl<E> SwarmOptimizer<E>
+where
+    E: Fn(&BotParamsPair) -> Metrics + Sync,
+{
+    /// Places particles uniformly within bounds with random velocities and evaluates them.
+    pub fn new(bounds: &ParamBoundsPair, evaluator: E, pso_config: &PsoConfig) -> Self {
+        let gene_bounds = bounds.bounds();
+        let mut rng = ChaCha12Rng::seed_from_u64(pso_config.seed);
+        let mut positions = Vec::with_capacity(pso_config.n_particles.max(1));
+        let mut velocities = Vec::with_capacity(pso_config.n_particles.max(1));
+        for _ in 0..pso_config.n_particles.max(1) {
+            positions.push(
+                gene_bounds
+                    .iter()
+                    .map(|&bound| sample_uniform(&mut rng, bound))
+                    .collect::<Vec<f64>>(),
+            );
+            velocities.push(
+                gene_bounds
+                    .iter()
+                    .map(|&(low, high)| {
+                        let max_velocity = (high - low) * pso_config.max_velocity_fraction;
+                        sample_uniform(&mut rng, (-max_velocity, max_velocity))
+                    })
+                    .collect::<Vec<f64>>(),
+            );
+        }
+        let cache = EvalCache::new(pso_config.cache_significant_digits);
+        let scored = evaluate_genomes(&positions, bounds, &evaluator, &pso_config.scorer, &cache);
+        let particles: Vec<Particle> = positions
+            .into_iter()
+            .zip(velocities)
+            .zip(scored)
+            .map(|((position, velocity), best)| Particle {
+                position,
+                velocity,
+                best,
+            })
+            .collect();
+        let mut optimizer = SwarmOptimizer {
+            bounds: bounds.clone(),
+            evaluator,
+            pso_config: pso_config.clone(),
+            gene_bounds,
+            rng,
+            particles,
+            best_index: 0,
+            iteration: 0,
+            cache,
+        };
+        optimizer.update_best_index();
+        optimizer
+    }
+
+    /// Continues a saved run. None if the session holds another optimizer's state.
+    pub fn from_session(session: OptimizeSession, evaluator: E) -> Option<Self> {
+        match session.state {
+            OptimizerState::Swarm {
+                pso_config,
+                rng,
+                particles,
+                best_index,
+                iteration,
+            } => Some(SwarmOptimizer {
+                gene_bounds: session.bounds.bounds(),
+                bounds: session.bounds,
+                evaluator,
+                pso_config,
+                rng,
+                particles,
+                best_index,
+                iteration,
+                cache: session.cache,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Each particle's best config, best first.
+    pub fn into_bests(self) -> Vec<ScoredConfig> {
+        let mut bests: Vec<ScoredConfig> = self
+            .particles
+            .into_iter()
+            .map(|particle| particle.best)
+            .collect();
+        bests.sort_by(|a, b| compare_scores(a.score, b.score));
+        bests
+    }
+
+    fn update_best_index(&mut self) {
+        // strict comparison, so the first particle wins ties
+        for i in 0..self.particles.len() {
+            if compare_scores(
+                self.particles[i].best.score,
+                self.particles[self.best_index].best.score,
+            )
+            .is_lt()
+            {
+                self.best_index = i;
+            }
+        }
+    }
+}
+
+impl<E> Optimizer for SwarmOptimizer<E>
+where
+    E: Fn(&BotParamsPair) -> Metrics + Sync,
+{
+    /// One iteration: move every particle, then evaluate all new positions.
+    fn step(&mut self) {
+        let swarm_best = self.particles[self.best_index].best.genome.clone();
+        let pso_config = &self.pso_config;
+        for particle in self.particles.iter_mut() {
+            for (j, &(low, high)) in self.gene_bounds.iter().enumerate() {
+                if low >= high {
+                    particle.position[j] = low;
+                    particle.velocity[j] = 0.0;
+                    continue;
+                }
+                let max_velocity = (high - low) * pso_config.max_velocity_fraction;
+                let velocity = pso_config.inertia * particle.velocity[j]
+                    + pso_config.cognitive
+                        * self.rng.gen::<f64>()
+                        * (particle.best.genome[j] - particle.position[j])
+                    + pso_config.social
+                        * self.rng.gen::<f64>()
+                        * (swarm_best[j] - particle.position[j]);
+                particle.velocity[j] = clamp_value(velocity, -max_velocity, max_velocity);
+                particle.position[j] =
+                    clamp_value(particle.position[j] + particle.velocity[j], low, high);
+            }
+        }
+        let positions: Vec<Vec<f64>> = self
+            .particles
+            .iter()
+            .map(|particle| particle.position.clone())
+            .collect();
+        let scored = evaluate_genomes(
+            &positions,
+            &self.bounds,
+            &self.evaluator,
+            &self.pso_config.scorer,
+            &self.cache,
+        );
+        for (particle, scored_config) in self.particles.iter_mut().zip(scored) {
+            if compare_scores(scored_config.score, particle.best.score).is_lt() {
+                particle.best = scored_config;
+            }
+        }
+        self.update_best_index();
+        self.iteration += 1;
+    }
+
+    fn best(&self) -> &ScoredConfig {
+        &self.particles[self.best_index].best
+    }
+
+    fn n_steps(&self) -> usize {
+        self.iteration
+    }
+
+    fn session(&self) -> OptimizeSession {
+        OptimizeSession {
+            bounds: self.bounds.clone(),
+            state: OptimizerState::Swarm {
+                pso_config: self.pso_config.clone(),
+                rng: self.rng.clone(),
+                particles: self.particles.clone(),
+                best_index: self.best_index,
+                iteration: self.iteration,
+            },
+            cache: self.cache.clone(),
+        }
+    }
+
+    fn cache(&self) -> &EvalCache {
+        &self.cache
+    }
+}
+
+/// Particle swarm optimization of bot params within bounds, maximizing pso_config.scorer applied
+/// to the evaluator's metrics. on_iteration is called with the iteration index and the best config
+/// so far. Returns each particle's best config, best first.
+/// Same seed gives same result as long as the evaluator is deterministic.
+pub fn run<E, P>(
+    bounds: &ParamBoundsPair,
+    evaluator: E,
+    pso_config: &PsoConfig,
+    on_iteration: P,
+) -> Vec<ScoredConfig>
+where
+    E: Fn(&BotParamsPair) -> Metrics + Sync,
+    P: FnMut(usize, &ScoredConfig),
+{
+    let mut optimizer = SwarmOptimizer::new(bounds, evaluator, pso_config);
+    drive(&mut optimizer, pso_config.n_iterations, on_iteration);
+    optimizer.into_bests()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn pso_config(seed: u64) -> PsoConfig {
+        PsoConfig {
+            n_particles: 20,
+            n_iterations: 15,
+            seed,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn same_seed_same_swarm() {
+        let bounds = fixtures::param_bounds_pair();
+        let bests = |seed| -> Vec<(Vec<f64>, f64)> {
+            run(&bounds, fixtures::toy_metrics, &pso_config(seed), |_, _| {})
+                .into_iter()
+                .map(|scored| (scored.genome, scored.score))
+                .collect()
+        };
+        let swarm = bests(1);
+        assert_eq!(swarm.len(), 20);
+        assert_eq!(swarm, bests(1));
+        assert_ne!(swarm, bests(2));
+    }
+
+    #[test]
+    fn swarm_best_improves_within_bounds() {
+        let mut bounds = fixtures::param_bounds_pair();
+        bounds.long.ema_span_1 = (600.0, 600.0);
+        let mut best_scores = Vec::new();
+        let mut optimizer = SwarmOptimizer::new(&bounds, fixtures::toy_metrics, &pso_config(3));
+        drive(&mut optimizer, 15, |iteration, best| {
+            best_scores.push((iteration, best.score))
+        });
+        assert_eq!(optimizer.n_steps(), 15);
+        assert_eq!(best_scores.len(), 16);
+        assert!(best_scores.windows(2).all(|w| w[1].1 >= w[0].1));
+        assert!(best_scores[15].1 > best_scores[0].1);
+        for particle in &optimizer.particles {
+            for (j, &(low, high)) in optimizer.gene_bounds.iter().enumerate() {
+                assert!(low <= particle.position[j] && particle.position[j] <= high);
+                let max_velocity = (high - low) * optimizer.pso_config.max_velocity_fraction;
+                assert!(particle.velocity[j].abs() <= max_velocity);
+            }
+            assert_eq!(particle.best.bot_params_pair.long.ema_span_1, 600.0);
+            assert!(particle.best.score <= optimizer.best().score);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use crate::artifact::{read_file, write_file, ArtifactError, ArtifactFormat};
+use crate::optimize::cache::EvalCache;
+use crate::optimize::genetic::GaConfig;
+use crate::optimize::pso::{Particle, PsoConfig};
+use crate::optimize::{ParamBoundsPair, ScoredConfig};
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Everything needed to continue an optimization run where it stopped.
+/// Resuming with the same evaluator gives the same trajectory as an uninterrupted run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OptimizeSession {
+    pub bounds: ParamBoundsPair,
+    pub state: OptimizerState,
+    pub cache: EvalCache,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum OptimizerState {
+    Genetic {
+        ga_config: GaConfig,
+        rng: ChaCha12Rng,
+        population: Vec<ScoredConfig>, // best first
+        generation: usize,
+    },
+    Swarm {
+        pso_config: PsoConfig,
+        rng: ChaCha12Rng,
+        particles: Vec<Particle>,
+        best_index: usize,
+        iteration: usize,
+    },
+}
+
+impl OptimizeSession {
+    /// Writes the session to path, as MessagePack if the extension is .msgpack or .mpk
+    /// and as JSON otherwise.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ArtifactError> {
+        let path = path.as_ref();
+        write_file(self, path, ArtifactFormat::from_path(path))
+    }
+
+    pub fn resume(path: impl AsRef<Path>) -> Result<Self, ArtifactError> {
+        read_file(path)
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::optimize::genetic::GeneticOptimizer;
+    use crate::optimize::pso::SwarmOptimizer;
+    use crate::optimize::{drive, drive_with_checkpoints, Optimizer};
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("pb_session_{}_{}", std::process::id(), name))
+    }
+
+    fn genomes_and_scores(scored: &[ScoredConfig]) -> Vec<(Vec<f64>, f64)> {
+        scored
+            .iter()
+            .map(|scored| (scored.genome.clone(), scored.score))
+            .collect()
+    }
+
+    fn ga_config() -> GaConfig {
+        GaConfig {
+            population_size: 12,
+            mutation_prob: 0.2,
+            seed: 4,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn genetic_resume_continues_the_same_trajectory() {
+        let bounds = fixtures::param_bounds_pair();
+        let mut straight = GeneticOptimizer::new(&bounds, fixtures::toy_metrics, &ga_config());
+        drive(&mut straight, 10, |_, _| {});
+
+        let path = temp_path("genetic.json");
+        let mut first = GeneticOptimizer::new(&bounds, fixtures::toy_metrics, &ga_config());
+        drive_with_checkpoints(&mut first, 5, 5, &path, |_, _| {}).unwrap();
+        drop(first);
+        let session = OptimizeSession::resume(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(SwarmOptimizer::from_session(session.clone(), fixtures::toy_metrics).is_none());
+        let mut resumed = GeneticOptimizer::from_session(session, fixtures::toy_metrics).unwrap();
+        assert_eq!(resumed.n_steps(), 5);
+        let mut steps = Vec::new();
+        drive(&mut resumed, 10, |step, _| steps.push(step));
+        assert_eq!(steps, (6..=10).collect::<Vec<_>>());
+        assert_eq!(
+            genomes_and_scores(resumed.population()),
+            genomes_and_scores(straight.population())
+        );
+        assert_eq!(resumed.summary(), straight.summary());
+    }
+
+    #[test]
+    fn swarm_resume_continues_the_same_trajectory() {
+        let bounds = fixtures::param_bounds_pair();
+        let pso_config = PsoConfig {
+            n_particles: 10,
+            seed: 4,
+            ..Default::default()
+        };
+        let mut straight = SwarmOptimizer::new(&bounds, fixtures::toy_metrics, &pso_config);
+        drive(&mut straight, 10, |_, _| {});
+
+        let path = temp_path("swarm.json");
+        let mut first = SwarmOptimizer::new(&bounds, fixtures::toy_metrics, &pso_config);
+        drive(&mut first, 5, |_, _| {});
+        first.session().save(&path).unwrap();
+        let session = OptimizeSession::resume(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(GeneticOptimizer::from_session(session.clone(), fixtures::toy_metrics).is_none());
+        let mut resumed = SwarmOptimizer::from_session(session, fixtures::toy_metrics).unwrap();
+        drive(&mut resumed, 10, |_, _| {});
+        assert_eq!(
+            genomes_and_scores(&resumed.into_bests()),
+            genomes_and_scores(&straight.into_bests())
+        );
+    }
+}
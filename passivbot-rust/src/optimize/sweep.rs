@@ -0,0 +1,356 @@
+use crate::analysis::Metrics;
+use crate::optimize::cache::EvalCache;
+use crate::optimize::{compare_scores, evaluate_genomes, Bound, ParamBoundsPair, ScoredConfig};
+use crate::scoring::Scorer;
+use crate::types::BotParamsPair;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SweepConfig {
+    pub max_evaluations: usize, // sweeps needing more backtests are rejected up front
+    pub seed: u64,              // latin hypercube only
+    pub scorer: Scorer,
+}
+
+impl Default for SweepConfig {
+    fn default() -> Self {
+        SweepConfig {
+            max_evaluations: 10_000,
+            seed: 0,
+            scorer: Scorer::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SweepError {
+    TooManyEvaluations {
+        n_evaluations: Option<usize>, // None if the count overflows usize
+        max_evaluations: usize,
+    },
+    UnknownField(String),
+}
+
+impl fmt::Display for SweepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SweepError::TooManyEvaluations {
+                n_evaluations: Some(n_evaluations),
+                max_evaluations,
+            } => write!(
+                f,
+                "sweep needs {} evaluations, more than max_evaluations {}",
+                n_evaluations, max_evaluations
+            ),
+            SweepError::TooManyEvaluations {
+                n_evaluations: None,
+                max_evaluations,
+            } => write!(
+                f,
+                "sweep needs more evaluations than fit in usize, max_evaluations is {}",
+                max_evaluations
+            ),
+            SweepError::UnknownField(name) => write!(f, "unknown field {}", name),
+        }
+    }
+}
+
+/// Exhaustive grid over the fields in steps, keyed by flat name, e.g. {"long_ema_span_0": 5}.
+/// Each listed field takes its step count of evenly spaced values from low to high inclusive.
+/// Unlisted fields, fixed fields and fields with fewer than 2 steps are held at their midpoint.
+/// Returns all configs, best first.
+pub fn grid_search<E>(
+    bounds: &ParamBoundsPair,
+    evaluator: E,
+    steps: &HashMap<String, usize>,
+    sweep_config: &SweepConfig,
+) -> Result<Vec<ScoredConfig>, SweepError>
+where
+    E: Fn(&BotParamsPair) -> Metrics + Sync,
+{
+    let field_names = ParamBoundsPair::field_names();
+    if let Some(name) = steps.keys().find(|name| !field_names.contains(name)) {
+        return Err(SweepError::UnknownField(name.clone()));
+    }
+    let axes: Vec<Vec<f64>> = field_names
+        .iter()
+        .zip(bounds.bounds())
+        .map(|(name, bound)| grid_axis(bound, steps.get(name).copied().unwrap_or(1)))
+        .collect();
+    let n_evaluations = axes
+        .iter()
+        .try_fold(1usize, |n, axis| n.checked_mul(axis.len()));
+    match n_evaluations {
+        Some(n) if n <= sweep_config.max_evaluations => {}
+        _ => {
+            return Err(SweepError::TooManyEvaluations {
+                n_evaluations,
+                max_evaluations: sweep_config.max_evaluations,
+            })
+        }
+    }
+    // cartesian product, last field varying fastest
+    let mut genomes = vec![Vec::with_capacity(axes.len())];
+    for axis in &axes {
+        genomes = genomes
+            .into_iter()
+            .flat_map(|genome| {
+                axis.iter().map(move |&value| {
+                    let mut genome = genome.clone();
+                    genome.push(value);
+                    genome
+                })
+            })
+            .collect();
+    }
+    Ok(evaluate_sorted(
+        &genomes,
+        bounds,
+        &evaluator,
+        &sweep_config.scorer,
+    ))
+}
+
+/// n_samples configs spread by latin hypercube sampling over all non-fixed fields.
+/// Returns all configs, best first.
+pub fn latin_hypercube<E>(
+    bounds: &ParamBoundsPair,
+    evaluator: E,
+    n_samples: usize,
+    sweep_config: &SweepConfig,
+) -> Result<Vec<ScoredConfig>, SweepError>
+where
+    E: Fn(&BotParamsPair) -> Metrics + Sync,
+{
+    if n_samples > sweep_config.max_evaluations {
+        return Err(SweepError::TooManyEvaluations {
+            n_evaluations: Some(n_samples),
+            max_evaluations: sweep_config.max_evaluations,
+        });
+    }
+    let mut rng = ChaCha12Rng::seed_from_u64(sweep_config.seed);
+    let genomes = latin_hypercube_genomes(&mut rng, &bounds.bounds(), n_samples);
+    Ok(evaluate_sorted(
+        &genomes,
+        bounds,
+        &evaluator,
+        &sweep_config.scorer,
+    ))
+}
+
+/// Splits every bound into n_samples equal strata and draws one value per stratum,
+/// pairing strata across dimensions by independent random permutations.
+/// Fixed bounds take their value.
+pub fn latin_hypercube_genomes<R: Rng>(
+    rng: &mut R,
+    gene_bounds: &[Bound],
+    n_samples: usize,
+) -> Vec<Vec<f64>> {
+    let mut genomes = vec![Vec::with_capacity(gene_bounds.len()); n_samples];
+    let mut strata: Vec<usize> = (0..n_samples).collect();
+    for &(low, high) in gene_bounds {
+        strata.shuffle(rng);
+        for (genome, &stratum) in genomes.iter_mut().zip(&strata) {
+            genome.push(if low < high {
+                let value =
+                    low + (high - low) * (stratum as f64 + rng.gen::<f64>()) / n_samples as f64;
+                // float error may push the top stratum past high
+                value.min(high)
+            } else {
+                low
+            });
+        }
+    }
+    genomes
+}
+
+fn grid_axis((low, high): Bound, n_steps: usize) -> Vec<f64> {
+    if low >= high {
+        vec![low]
+    } else if n_steps < 2 {
+        vec![(low + high) / 2.0]
+    } else {
+        (0..n_steps)
+            .map(|i| low + (high - low) * i as f64 / (n_steps - 1) as f64)
+            .collect()
+    }
+}
+
+fn evaluate_sorted<E>(
+    genomes: &[Vec<f64>],
+    bounds: &ParamBoundsPair,
+    evaluator: &E,
+    scorer: &Scorer,
+) -> Vec<ScoredConfig>
+where
+    E: Fn(&BotParamsPair) -> Metrics + Sync,
+{
+    let mut scored = evaluate_genomes(genomes, bounds, evaluator, scorer, &EvalCache::default());
+    scored.sort_by(|a, b| compare_scores(a.score, b.score));
+    scored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn field_index(name: &str) -> usize {
+        ParamBoundsPair::field_names()
+            .iter()
+            .position(|field_name| field_name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn latin_hypercube_has_one_sample_per_stratum() {
+        let gene_bounds = vec![(0.0, 1.0), (-10.0, 30.0), (5.0, 5.0)];
+        let n_samples = 8;
+        let genomes =
+            latin_hypercube_genomes(&mut ChaCha12Rng::seed_from_u64(1), &gene_bounds, n_samples);
+        assert_eq!(genomes.len(), n_samples);
+        for (i, &(low, high)) in gene_bounds.iter().enumerate() {
+            if low == high {
+                assert!(genomes.iter().all(|genome| genome[i] == low));
+                continue;
+            }
+            let mut strata: Vec<usize> = genomes
+                .iter()
+                .map(|genome| ((genome[i] - low) / (high - low) * n_samples as f64) as usize)
+                .collect();
+            strata.sort();
+            assert_eq!(strata, (0..n_samples).collect::<Vec<_>>());
+        }
+        assert_eq!(
+            genomes,
+            latin_hypercube_genomes(&mut ChaCha12Rng::seed_from_u64(1), &gene_bounds, n_samples)
+        );
+        assert_ne!(
+            genomes,
+            latin_hypercube_genomes(&mut ChaCha12Rng::seed_from_u64(2), &gene_bounds, n_samples)
+        );
+    }
+
+    #[test]
+    fn latin_hypercube_is_seeded_and_capped() {
+        let bounds = fixtures::param_bounds_pair();
+        let sweep_config = SweepConfig {
+            max_evaluations: 16,
+            seed: 3,
+            ..Default::default()
+        };
+        let genomes = |sweep_config: &SweepConfig| -> Vec<Vec<f64>> {
+            latin_hypercube(&bounds, fixtures::toy_metrics, 16, sweep_config)
+                .unwrap()
+                .into_iter()
+                .map(|scored| scored.genome)
+                .collect()
+        };
+        assert_eq!(genomes(&sweep_config), genomes(&sweep_config));
+        assert_eq!(
+            latin_hypercube(&bounds, fixtures::toy_metrics, 17, &sweep_config).unwrap_err(),
+            SweepError::TooManyEvaluations {
+                n_evaluations: Some(17),
+                max_evaluations: 16,
+            }
+        );
+    }
+
+    #[test]
+    fn grid_search_covers_the_grid_best_first() {
+        let bounds = fixtures::param_bounds_pair();
+        let steps = HashMap::from([
+            ("long_ema_span_0".to_string(), 3),
+            ("long_entry_grid_spacing_pct".to_string(), 2),
+        ]);
+        let scored = grid_search(
+            &bounds,
+            fixtures::toy_metrics,
+            &steps,
+            &SweepConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(scored.len(), 6);
+        let ema_span_0 = field_index("long_ema_span_0");
+        let spacing = field_index("long_entry_grid_spacing_pct");
+        let mut grid: Vec<(f64, f64)> = scored
+            .iter()
+            .map(|scored| (scored.genome[ema_span_0], scored.genome[spacing]))
+            .collect();
+        // closest to the toy optimum of 800 and 0.05
+        assert_eq!(grid[0], (820.0, 0.001));
+        grid.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(
+            grid,
+            vec![
+                (200.0, 0.001),
+                (200.0, 0.12),
+                (820.0, 0.001),
+                (820.0, 0.12),
+                (1440.0, 0.001),
+                (1440.0, 0.12),
+            ]
+        );
+        // unlisted fields at their midpoint
+        let ema_span_1 = field_index("long_ema_span_1");
+        assert!(scored
+            .iter()
+            .all(|scored| scored.genome[ema_span_1] == 820.0));
+    }
+
+    #[test]
+    fn grid_search_rejects_unknown_fields_and_oversized_grids() {
+        let bounds = fixtures::param_bounds_pair();
+        let unknown = HashMap::from([("long_ema_span_9".to_string(), 3)]);
+        assert_eq!(
+            grid_search(
+                &bounds,
+                fixtures::toy_metrics,
+                &unknown,
+                &SweepConfig::default()
+            )
+            .unwrap_err(),
+            SweepError::UnknownField("long_ema_span_9".to_string())
+        );
+        let steps: HashMap<String, usize> = ParamBoundsPair::field_names()
+            .into_iter()
+            .map(|name| (name, 1000))
+            .collect();
+        assert_eq!(
+            grid_search(
+                &bounds,
+                fixtures::toy_metrics,
+                &steps,
+                &SweepConfig::default()
+            )
+            .unwrap_err(),
+            SweepError::TooManyEvaluations {
+                n_evaluations: None,
+                max_evaluations: 10_000,
+            }
+        );
+        let steps = HashMap::from([
+            ("long_ema_span_0".to_string(), 101),
+            ("short_ema_span_0".to_string(), 100),
+        ]);
+        assert_eq!(
+            grid_search(
+                &bounds,
+                fixtures::toy_metrics,
+                &steps,
+                &SweepConfig::default()
+            )
+            .unwrap_err(),
+            SweepError::TooManyEvaluations {
+                n_evaluations: Some(10_100),
+                max_evaluations: 10_000,
+            }
+        );
+    }
+}
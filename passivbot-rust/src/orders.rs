@@ -0,0 +1,258 @@
+use crate::closes::{calc_closes_long, calc_closes_short, calc_panic_orders};
+use crate::entries::{calc_entries_long, calc_entries_short};
+use crate::types::{
+    BotParamsPair, ExchangeParams, Order, Position, PositionMode, StateParams, TradingMode,
+    TrailingPriceBundle,
+};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+// panic closes are priced at the touch; IOC execution is left to the live layer
+const PANIC_TICKS_THROUGH: u32 = 0;
+
+/// Computes long and short orders together.
+/// In one-way mode, entries on a side are suppressed while the opposite side holds a position,
+/// so the existing position must be closed before the other side can open.
+/// Orders the trading mode disallows are dropped; in Panic mode only the kill switch closes
+/// from calc_panic_orders are returned.
+#[must_use]
+pub fn calc_orders(input: &SymbolInput) -> Vec<Order> {
+    let SymbolInput {
+        exchange_params,
+        state_params_long,
+        state_params_short,
+        bot_params_pair,
+        position_long,
+        position_short,
+        trailing_price_bundle_long,
+        trailing_price_bundle_short,
+        position_mode,
+        trading_mode,
+    } = input;
+    if *trading_mode == TradingMode::Panic {
+        let positions = [
+            (position_long, state_params_long),
+            (position_short, state_params_short),
+        ]
+        .map(|(position, state_params)| {
+            (
+                String::new(),
+                *position,
+                state_params.order_book.clone(),
+                exchange_params.clone(),
+            )
+        });
+        return calc_panic_orders(&positions, PANIC_TICKS_THROUGH)
+            .closes
+            .into_iter()
+            .map(|(_, order)| order)
+            .collect();
+    }
+    let one_way = *position_mode == PositionMode::OneWay;
+    // graceful stop opens nothing on a flat side, grid entries included
+    let graceful_stop = *trading_mode == TradingMode::GracefulStop;
+    let mut orders = Vec::<Order>::new();
+    if bot_params_pair.long.wallet_exposure_limit > 0.0 || position_long.size != 0.0 {
+        let entries_blocked =
+            (one_way && position_short.size != 0.0) || (graceful_stop && position_long.size == 0.0);
+        if !entries_blocked {
+            orders.extend(calc_entries_long(
+                exchange_params,
+                state_params_long,
+                &bot_params_pair.long,
+                position_long,
+                trailing_price_bundle_long,
+            ));
+        }
+        orders.extend(calc_closes_long(
+            exchange_params,
+            state_params_long,
+            &bot_params_pair.long,
+            position_long,
+            trailing_price_bundle_long,
+        ));
+    }
+    if bot_params_pair.short.wallet_exposure_limit > 0.0 || position_short.size != 0.0 {
+        let entries_blocked =
+            (one_way && position_long.size != 0.0) || (graceful_stop && position_short.size == 0.0);
+        if !entries_blocked {
+            orders.extend(calc_entries_short(
+                exchange_params,
+                state_params_short,
+                &bot_params_pair.short,
+                position_short,
+                trailing_price_bundle_short,
+            ));
+        }
+        orders.extend(calc_closes_short(
+            exchange_params,
+            state_params_short,
+            &bot_params_pair.short,
+            position_short,
+            trailing_price_bundle_short,
+        ));
+    }
+    orders.retain(|order| trading_mode.allows(order.order_type));
+    orders
+}
+
+/// Everything calc_orders needs for one symbol.
+#[derive(Debug, Default, Clone)]
+pub struct SymbolInput {
+    pub exchange_params: ExchangeParams,
+    pub state_params_long: StateParams,
+    pub state_params_short: StateParams,
+    pub bot_params_pair: BotParamsPair,
+    pub position_long: Position,
+    pub position_short: Position,
+    pub trailing_price_bundle_long: TrailingPriceBundle,
+    pub trailing_price_bundle_short: TrailingPriceBundle,
+    pub position_mode: PositionMode,
+    pub trading_mode: TradingMode,
+}
+
+/// calc_orders output for one symbol, split by intent. Each keeps calc_orders' order.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct IdealOrders {
+    pub entries: Vec<Order>,
+    pub closes: Vec<Order>,
+}
+
+#[must_use]
+pub fn calc_ideal_orders(input: &SymbolInput) -> IdealOrders {
+    let (closes, entries) = calc_orders(input)
+        .into_iter()
+        .partition(|order| order.order_type.is_close());
+    IdealOrders { entries, closes }
+}
+
+/// calc_ideal_orders for many symbols, e.g. every candidate of a forager scan. Result i is for
+/// inputs[i]. Symbols are spread over rayon's global pool when the rayon feature is on; each
+/// is computed on its own, so results are the same for any thread count.
+#[must_use]
+pub fn calc_ideal_orders_batch(inputs: &[SymbolInput]) -> Vec<IdealOrders> {
+    #[cfg(feature = "rayon")]
+    let iter = inputs.par_iter();
+    #[cfg(not(feature = "rayon"))]
+    let iter = inputs.iter();
+    iter.map(calc_ideal_orders).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn batch_matches_per_symbol_orders() {
+        let inputs = fixtures::symbol_inputs(64, 7);
+        let batch = calc_ideal_orders_batch(&inputs);
+        assert_eq!(batch.len(), inputs.len());
+        for (input, ideal_orders) in inputs.iter().zip(&batch) {
+            assert_eq!(*ideal_orders, calc_ideal_orders(input));
+        }
+        // every side of the fixture cycle produces orders
+        assert!(batch
+            .iter()
+            .all(|ideal_orders| !ideal_orders.entries.is_empty()));
+        assert!(batch
+            .iter()
+            .any(|ideal_orders| !ideal_orders.closes.is_empty()));
+    }
+
+    #[test]
+    fn one_way_mode_blocks_entries_opposite_a_position() {
+        use crate::constants::{LONG, SHORT};
+        // long position, flat short
+        let hedge = fixtures::symbol_inputs(2, 7).remove(1);
+        assert!(hedge.position_long.size > 0.0 && hedge.position_short.size == 0.0);
+        let one_way = SymbolInput {
+            position_mode: PositionMode::OneWay,
+            ..hedge.clone()
+        };
+        let entries = |orders: &[Order], pside| {
+            orders
+                .iter()
+                .filter(|order| order.order_type.is_entry() && order.order_type.pside() == pside)
+                .count()
+        };
+        let (hedge_orders, one_way_orders) = (calc_orders(&hedge), calc_orders(&one_way));
+        assert!(entries(&hedge_orders, SHORT) > 0);
+        assert_eq!(entries(&one_way_orders, SHORT), 0);
+        // the held side is unaffected
+        let long_orders = |orders: &[Order]| -> Vec<Order> {
+            orders
+                .iter()
+                .filter(|order| order.order_type.pside() == LONG)
+                .cloned()
+                .collect()
+        };
+        assert!(entries(&one_way_orders, LONG) > 0);
+        assert_eq!(long_orders(&one_way_orders), long_orders(&hedge_orders));
+        // flat on both sides, one-way opens either side
+        let flat = SymbolInput {
+            position_long: Position::default(),
+            ..one_way
+        };
+        let flat_orders = calc_orders(&flat);
+        assert!(entries(&flat_orders, LONG) > 0 && entries(&flat_orders, SHORT) > 0);
+    }
+
+    #[test]
+    fn trading_mode_limits_order_kinds() {
+        use crate::types::OrderKind;
+        // flat, long only, short only, both sides in position
+        let inputs = fixtures::symbol_inputs(4, 7);
+        let kinds = |trading_mode| -> Vec<Vec<OrderKind>> {
+            inputs
+                .iter()
+                .map(|input| {
+                    let input = SymbolInput {
+                        trading_mode,
+                        ..input.clone()
+                    };
+                    calc_orders(&input)
+                        .iter()
+                        .map(|order| order.order_type.kind())
+                        .collect()
+                })
+                .collect()
+        };
+        let is_entry = |kind: &OrderKind| {
+            matches!(
+                kind,
+                OrderKind::EntryInitial | OrderKind::EntryGrid | OrderKind::EntryTrailing
+            )
+        };
+        let normal = kinds(TradingMode::Normal);
+        assert!(normal[0].contains(&OrderKind::EntryInitial));
+        assert!(normal[0].iter().all(is_entry));
+        let graceful_stop = kinds(TradingMode::GracefulStop);
+        // nothing opens on a flat side; positions keep re-entering and closing
+        assert!(graceful_stop[0].is_empty());
+        for symbol_kinds in &graceful_stop[1..] {
+            assert!(!symbol_kinds.contains(&OrderKind::EntryInitial));
+            assert!(symbol_kinds.iter().any(is_entry));
+            assert!(symbol_kinds.contains(&OrderKind::CloseGrid));
+        }
+        let tp_only = kinds(TradingMode::TpOnly);
+        assert!(tp_only[0].is_empty());
+        for (symbol_kinds, normal_kinds) in tp_only[1..].iter().zip(&normal[1..]) {
+            let normal_closes: Vec<OrderKind> = normal_kinds
+                .iter()
+                .filter(|kind| !is_entry(kind))
+                .copied()
+                .collect();
+            assert_eq!(*symbol_kinds, normal_closes);
+        }
+        assert!(kinds(TradingMode::Manual).iter().all(Vec::is_empty));
+        // one kill switch close per open side
+        let panic = kinds(TradingMode::Panic);
+        let n_closes: Vec<usize> = panic.iter().map(Vec::len).collect();
+        assert_eq!(n_closes, [0, 1, 1, 2]);
+        assert!(panic
+            .iter()
+            .flatten()
+            .all(|kind| *kind == OrderKind::CloseStop));
+    }
+}
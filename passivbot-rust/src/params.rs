@@ -0,0 +1,390 @@
+use crate::types::{
+    BotParams, BotParamsError, BotParamsPair, EmaWarmupBehavior, EntrySizingBasis,
+    EntryTrailingGridMode, EntryTrailingMode, Side,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+/// A validate() finding on one side of a pair.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamError {
+    pub side: Side,
+    pub error: BotParamsError,
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.side, self.error)
+    }
+}
+
+/// One changed field, e.g. "long.ema_span_0", with values in their Debug format for logging.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParamsChange {
+    pub new_symbol: bool, // no params were set before; changes is then empty
+    pub changes: Vec<FieldChange>, // in field declaration order, long first
+    pub warnings: Vec<ParamError>, // non fatal validate() findings of the applied params
+}
+
+macro_rules! bot_params_fields {
+    ($($field:ident: $ty:ty),* $(,)?) => {
+        fn diff_bot_params(
+            prefix: &str,
+            old: &BotParams,
+            new: &BotParams,
+            changes: &mut Vec<FieldChange>,
+        ) {
+            // exhaustive, so that a new BotParams field fails to compile until listed
+            let BotParams { $($field: _,)* } = old;
+            $(
+                let old_value = format!("{:?}", old.$field);
+                let new_value = format!("{:?}", new.$field);
+                if old_value != new_value {
+                    changes.push(FieldChange {
+                        field: format!("{}{}", prefix, stringify!($field)),
+                        old: old_value,
+                        new: new_value,
+                    });
+                }
+            )*
+        }
+
+        /// Per symbol tweaks to a shared BotParams, e.g. from a symbol's config section. Set
+        /// fields replace the base value, unset ones keep it. Since None means unset,
+        /// entry_reentry_ema_dist can be set to a value but not back to None.
+        #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+        #[serde(default)]
+        pub struct BotParamsOverride {
+            $(pub $field: Option<$ty>,)*
+        }
+
+        impl BotParams {
+            /// Copy of self with ov's set fields replaced, then auto_adjust_markup applied.
+            /// Fields are taken as given: overriding total_wallet_exposure_limit or
+            /// n_positions does not recompute wallet_exposure_limit.
+            pub fn apply_override(&self, ov: &BotParamsOverride) -> BotParams {
+                let mut bot_params = BotParams {
+                    $($field: ov.$field.clone().unwrap_or_else(|| self.$field.clone()),)*
+                };
+                bot_params.adjust_markup();
+                bot_params
+            }
+        }
+    };
+}
+
+bot_params_fields!(
+    auto_adjust_markup: bool,
+    balance_reserve_pct: f64,
+    bracket_stop_pct: f64,
+    close_grid_gap_pct: f64,
+    close_grid_markup_range: f64,
+    close_grid_min_markup: f64,
+    close_grid_max_markup: f64,
+    close_grid_min_entry_distance_ratio: f64,
+    close_min_net_markup: f64,
+    close_grid_qty_pct: f64,
+    close_trailing_retracement_pct: f64,
+    close_trailing_drawdown_floor_pct: f64,
+    close_trailing_grid_ratio: f64,
+    close_trailing_qty_pct: f64,
+    close_trailing_threshold_pct: f64,
+    close_trailing_min_profit_pct: f64,
+    coalesce_grid_orders: bool,
+    entry_grid_double_down_factor: f64,
+    entry_grid_qty_schedule: Vec<f64>,
+    entry_grid_spacing_weight: f64,
+    entry_grid_spacing_pct: f64,
+    entry_grid_spacing_vol_weight: f64,
+    entry_grid_ema_floor: bool,
+    entry_blackout_windows: Vec<(u64, u64)>,
+    entry_initial_ema_dist: f64,
+    entry_initial_qty_pct: f64,
+    entry_initial_timeout_sec: f64,
+    entry_reentry_ema_dist: Option<f64>,
+    entry_sizing_basis: EntrySizingBasis,
+    entry_trailing_drawdown_pct: f64,
+    entry_trailing_max_adds: usize,
+    entry_trailing_mode: EntryTrailingMode,
+    entry_trailing_retracement_pct: f64,
+    entry_trailing_grid_mode: EntryTrailingGridMode,
+    entry_trailing_grid_ratio: f64,
+    entry_trailing_threshold_pct: f64,
+    filter_rolling_window: usize,
+    filter_relative_volume_clip_pct: f64,
+    ema_span_0: f64,
+    ema_span_1: f64,
+    ema_warmup_behavior: EmaWarmupBehavior,
+    n_positions: usize,
+    total_wallet_exposure_limit: f64,
+    wallet_exposure_limit: f64,
+    unstuck_close_pct: f64,
+    unstuck_ema_dist: f64,
+    unstuck_loss_allowance_pct: f64,
+    unstuck_threshold: f64,
+);
+
+/// Field level differences between two pairs.
+pub fn diff_bot_params_pair(old: &BotParamsPair, new: &BotParamsPair) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+    diff_bot_params("long.", &old.long, &new.long, &mut changes);
+    diff_bot_params("short.", &old.short, &new.short, &mut changes);
+    changes
+}
+
+/// Current bot params per symbol, replaceable while calculators run. Readers get an Arc
+/// snapshot, so a calculation started before an update finishes on the old params and no
+/// reader ever sees a partly written pair.
+#[derive(Debug, Default)]
+pub struct ParamsManager {
+    params: RwLock<HashMap<String, Arc<BotParamsPair>>>,
+}
+
+impl ParamsManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<Arc<BotParamsPair>> {
+        self.params.read().unwrap().get(symbol).cloned()
+    }
+
+    /// Validates new and, unless a value is out of range, swaps it in for symbol. On error
+    /// the current params stay active. new is taken as is; apply auto_adjust_markup first,
+    /// e.g. by building it with from_py_dict.
+    pub fn apply_update(
+        &self,
+        symbol: &str,
+        new: BotParamsPair,
+    ) -> Result<ParamsChange, Vec<ParamError>> {
+        let mut fatal = Vec::new();
+        let mut warnings = Vec::new();
+        for (side, bot_params) in [(Side::Long, &new.long), (Side::Short, &new.short)] {
+            for error in bot_params.validate().err().unwrap_or_default() {
+                let error = ParamError { side, error };
+                if matches!(error.error, BotParamsError::OutOfRange { .. }) {
+                    fatal.push(error);
+                } else {
+                    warnings.push(error);
+                }
+            }
+        }
+        if !fatal.is_empty() {
+            return Err(fatal);
+        }
+        let new = Arc::new(new);
+        // diff under the write lock so that concurrent updates report against their predecessor
+        let mut params = self.params.write().unwrap();
+        let change = match params.insert(symbol.to_string(), Arc::clone(&new)) {
+            Some(old) => ParamsChange {
+                new_symbol: false,
+                changes: diff_bot_params_pair(&old, &new),
+                warnings,
+            },
+            None => ParamsChange {
+                new_symbol: true,
+                changes: Vec::new(),
+                warnings,
+            },
+        };
+        Ok(change)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn apply_update_reports_field_changes() {
+        let manager = ParamsManager::new();
+        assert!(manager.get("BTCUSDT").is_none());
+        let change = manager
+            .apply_update("BTCUSDT", fixtures::bot_params_pair())
+            .unwrap();
+        assert_eq!(
+            change,
+            ParamsChange {
+                new_symbol: true,
+                ..Default::default()
+            }
+        );
+        let mut new = fixtures::bot_params_pair();
+        new.long.ema_span_0 = 500.0;
+        new.short.entry_trailing_mode = EntryTrailingMode::TrailingStop;
+        let change = manager.apply_update("BTCUSDT", new.clone()).unwrap();
+        let field_change = |field: &str, old: &str, new: &str| FieldChange {
+            field: field.to_string(),
+            old: old.to_string(),
+            new: new.to_string(),
+        };
+        assert_eq!(
+            change.changes,
+            [
+                field_change("long.ema_span_0", "465.26", "500.0"),
+                field_change(
+                    "short.entry_trailing_mode",
+                    "ThresholdDrawdown",
+                    "TrailingStop"
+                ),
+            ]
+        );
+        assert!(!change.new_symbol && change.warnings.is_empty());
+        assert_eq!(manager.get("BTCUSDT").unwrap().long.ema_span_0, 500.0);
+        // other symbols are independent
+        assert!(manager.get("ETHUSDT").is_none());
+        let unchanged = manager.apply_update("BTCUSDT", new).unwrap();
+        assert!(unchanged.changes.is_empty());
+    }
+
+    #[test]
+    fn invalid_update_keeps_current_params() {
+        let manager = ParamsManager::new();
+        manager
+            .apply_update("BTCUSDT", fixtures::bot_params_pair())
+            .unwrap();
+        let mut invalid = fixtures::bot_params_pair();
+        invalid.long.ema_span_1 = 600.0;
+        invalid.short.close_grid_qty_pct = 1.5;
+        let errors = manager.apply_update("BTCUSDT", invalid).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].side, Side::Short);
+        assert!(matches!(
+            errors[0].error,
+            BotParamsError::OutOfRange {
+                field: "close_grid_qty_pct",
+                ..
+            }
+        ));
+        let current = manager.get("BTCUSDT").unwrap();
+        assert_eq!(
+            (current.long.ema_span_1, current.short.close_grid_qty_pct),
+            (1120.5, 0.65242)
+        );
+        // a close grid inside the entry grid is applied with a warning
+        let mut inside = fixtures::bot_params_pair();
+        inside.long.close_grid_min_entry_distance_ratio = 1.0;
+        let change = manager.apply_update("BTCUSDT", inside).unwrap();
+        assert_eq!(change.warnings.len(), 1);
+        assert_eq!(change.warnings[0].side, Side::Long);
+        assert_eq!(
+            manager
+                .get("BTCUSDT")
+                .unwrap()
+                .long
+                .close_grid_min_entry_distance_ratio,
+            1.0
+        );
+    }
+
+    #[test]
+    fn readers_never_see_a_torn_pair() {
+        let manager = ParamsManager::new();
+        // every pair written has the same value in all four spans
+        let pair = |span: f64| {
+            let mut pair = fixtures::bot_params_pair();
+            for bot_params in [&mut pair.long, &mut pair.short] {
+                bot_params.ema_span_0 = span;
+                bot_params.ema_span_1 = span;
+            }
+            pair
+        };
+        manager.apply_update("BTCUSDT", pair(200.0)).unwrap();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..2000 {
+                        let current = manager.get("BTCUSDT").unwrap();
+                        let span = current.long.ema_span_0;
+                        assert_eq!(
+                            [
+                                current.long.ema_span_1,
+                                current.short.ema_span_0,
+                                current.short.ema_span_1
+                            ],
+                            [span; 3]
+                        );
+                    }
+                });
+            }
+            scope.spawn(|| {
+                for span in 201..1200 {
+                    let change = manager.apply_update("BTCUSDT", pair(span as f64)).unwrap();
+                    // each update is diffed against its predecessor
+                    assert_eq!(change.changes.len(), 4);
+                }
+            });
+        });
+        assert_eq!(manager.get("BTCUSDT").unwrap().short.ema_span_1, 1199.0);
+    }
+
+    #[test]
+    fn apply_override_changes_only_the_set_fields() {
+        let base = fixtures::bot_params();
+        let changed_fields = |ov: &BotParamsOverride| {
+            let mut changes = Vec::new();
+            diff_bot_params("", &base, &base.apply_override(ov), &mut changes);
+            changes
+                .into_iter()
+                .map(|change| (change.field, change.new))
+                .collect::<Vec<_>>()
+        };
+        assert!(changed_fields(&BotParamsOverride::default()).is_empty());
+        let ov = BotParamsOverride {
+            ema_span_0: Some(500.0),
+            entry_grid_qty_schedule: Some(vec![1.0, 2.0]),
+            entry_reentry_ema_dist: Some(Some(0.01)),
+            entry_trailing_mode: Some(EntryTrailingMode::TrailingStop),
+            // set to the base value: no change
+            n_positions: Some(base.n_positions),
+            ..Default::default()
+        };
+        assert_eq!(
+            changed_fields(&ov),
+            [
+                (
+                    "entry_grid_qty_schedule".to_string(),
+                    "[1.0, 2.0]".to_string()
+                ),
+                (
+                    "entry_reentry_ema_dist".to_string(),
+                    "Some(0.01)".to_string()
+                ),
+                (
+                    "entry_trailing_mode".to_string(),
+                    "TrailingStop".to_string()
+                ),
+                ("ema_span_0".to_string(), "500.0".to_string()),
+            ]
+        );
+        // the merged params are adjusted as a whole: markup widened to the overridden spacing
+        let ov = BotParamsOverride {
+            auto_adjust_markup: Some(true),
+            entry_grid_spacing_pct: Some(0.05),
+            ..Default::default()
+        };
+        let merged = base.apply_override(&ov);
+        assert_eq!(merged.close_grid_min_markup, 0.05);
+        assert_eq!(
+            changed_fields(&ov)
+                .iter()
+                .map(|(field, _)| field.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "auto_adjust_markup",
+                "close_grid_min_markup",
+                "entry_grid_spacing_pct"
+            ]
+        );
+    }
+}
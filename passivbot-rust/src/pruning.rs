@@ -0,0 +1,122 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Backtest state at a pruning checkpoint.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct InterimStats {
+    pub k: usize, // candles processed
+    pub equity: f64,
+    pub max_drawdown: f64,
+}
+
+/// Consulted by backtest_with_pruner every interval candles. Shared across parallel runs.
+pub trait Pruner: Sync {
+    fn interval(&self) -> usize;
+
+    /// Whether to abort the run at the given checkpoint (1 for the first).
+    fn should_prune(&self, checkpoint: usize, stats: &InterimStats) -> bool;
+
+    /// Called with the checkpoint stats of every run which was not pruned.
+    fn report_completed(&self, checkpoints: &[InterimStats]);
+}
+
+/// Prunes a run whose interim equity is below the median interim equity of completed runs at the
+/// same checkpoint. All runs are expected to share starting balance and data.
+#[derive(Debug)]
+pub struct MedianPruner {
+    pub interval: usize,
+    pub n_startup_runs: usize, // completed runs required before pruning starts
+    pub n_warmup_checkpoints: usize, // checkpoints at the start of each run never pruned
+    completed: Mutex<Vec<Vec<f64>>>, // equities of completed runs, per checkpoint
+    n_completed: AtomicUsize,
+}
+
+impl MedianPruner {
+    pub fn new(interval: usize, n_startup_runs: usize, n_warmup_checkpoints: usize) -> Self {
+        MedianPruner {
+            interval,
+            n_startup_runs,
+            n_warmup_checkpoints,
+            completed: Mutex::new(Vec::new()),
+            n_completed: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Pruner for MedianPruner {
+    fn interval(&self) -> usize {
+        self.interval
+    }
+
+    fn should_prune(&self, checkpoint: usize, stats: &InterimStats) -> bool {
+        if checkpoint <= self.n_warmup_checkpoints
+            || self.n_completed.load(Ordering::SeqCst) < self.n_startup_runs
+        {
+            return false;
+        }
+        let completed = self.completed.lock().unwrap();
+        match completed.get(checkpoint - 1) {
+            Some(equities) if !equities.is_empty() => {
+                let mut sorted = equities.clone();
+                sorted.sort_by(|a, b| a.total_cmp(b));
+                let mid = sorted.len() / 2;
+                let median = if sorted.len() % 2 == 0 {
+                    (sorted[mid - 1] + sorted[mid]) * 0.5
+                } else {
+                    sorted[mid]
+                };
+                stats.equity < median
+            }
+            _ => false,
+        }
+    }
+
+    fn report_completed(&self, checkpoints: &[InterimStats]) {
+        let mut completed = self.completed.lock().unwrap();
+        if completed.len() < checkpoints.len() {
+            completed.resize(checkpoints.len(), Vec::new());
+        }
+        for (equities, stats) in completed.iter_mut().zip(checkpoints) {
+            equities.push(stats.equity);
+        }
+        self.n_completed.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(equity: f64) -> InterimStats {
+        InterimStats {
+            equity,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn median_pruner_waits_for_startup_runs_and_warmup_checkpoints() {
+        let pruner = MedianPruner::new(100, 2, 1);
+        pruner.report_completed(&[stats(100.0), stats(100.0)]);
+        assert!(!pruner.should_prune(2, &stats(1.0)));
+        pruner.report_completed(&[stats(100.0), stats(100.0)]);
+        assert!(pruner.should_prune(2, &stats(1.0)));
+        assert!(!pruner.should_prune(1, &stats(1.0)));
+        // no completed run got this far
+        assert!(!pruner.should_prune(3, &stats(1.0)));
+    }
+
+    #[test]
+    fn median_pruner_prunes_strictly_below_median() {
+        let pruner = MedianPruner::new(100, 0, 0);
+        for equity in [90.0, 110.0, 100.0] {
+            pruner.report_completed(&[stats(equity)]);
+        }
+        assert!(pruner.should_prune(1, &stats(99.9)));
+        assert!(!pruner.should_prune(1, &stats(100.0)));
+        // even count: mean of the middle two
+        pruner.report_completed(&[stats(120.0)]);
+        assert!(pruner.should_prune(1, &stats(104.9)));
+        assert!(!pruner.should_prune(1, &stats(105.0)));
+    }
+}
@@ -1,61 +1,151 @@
+use crate::analysis::Metrics;
 use crate::backtest::{analyze_backtest, Backtest};
 use crate::closes::{
     calc_closes_long, calc_closes_short, calc_grid_close_long, calc_next_close_long,
     calc_next_close_short, calc_trailing_close_long,
 };
+use crate::constants::{LONG, SHORT};
 use crate::entries::{
-    calc_entries_long, calc_entries_short, calc_grid_entry_long, calc_next_entry_long,
-    calc_next_entry_short, calc_trailing_entry_long,
+    calc_addable_qty_at, calc_blended_entries_long, calc_blended_entries_short, calc_entries_long,
+    calc_entries_short, calc_exposure_headroom, calc_grid_entry_long,
+    calc_initial_entry_long_with_timeout, calc_initial_entry_short_with_timeout,
+    calc_min_entry_qty, calc_next_entry_long, calc_next_entry_long_explained,
+    calc_next_entry_short, calc_next_grid_trigger_price_long, calc_next_grid_trigger_price_short,
+    calc_trailing_entry_long, calc_twap_entry_long, calc_twap_entry_short, EmptyReason,
 };
+use crate::error::PassivbotError;
+use crate::execution::{to_binance_futures_params, to_bybit_v5_params, OrderSide, TimeInForce};
+use crate::optimize::genetic::{GaConfig, GeneticOptimizer};
+use crate::optimize::{drive_while, Optimizer, ParamBoundsPair};
+use crate::runner::{backtest_from_fn, BacktestResult};
+use crate::scoring::Scorer;
 use crate::types::{
-    Analysis, BacktestParams, BotParams, BotParamsPair, EMABands, ExchangeParams, Order, OrderBook,
-    Position, StateParams, TrailingPriceBundle,
+    Analysis, BacktestParams, BalanceMode, BotParams, BotParamsError, BotParamsPair, Candle,
+    EMABands, EmaWarmupBehavior, EntrySizingBasis, EntryTrailingGridMode, EntryTrailingMode,
+    ExchangeParams, Fill, Order, OrderBook, OrderIdInfo, OrderType, Position, PositionMode, Side,
+    StateParams, TrailingPriceBundle, ORDER_TYPES,
 };
 use memmap::MmapOptions;
-use ndarray::{Array1, Array2, Array3, Array4, ArrayBase, ArrayD, ArrayView, ShapeBuilder};
+use ndarray::{
+    Array1, Array2, Array3, Array4, ArrayBase, ArrayD, ArrayView, ArrayView2, ShapeBuilder,
+};
 use numpy::{
-    IntoPyArray, PyArray1, PyArray2, PyArray3, PyArray4, PyReadonlyArray2, PyReadonlyArray3,
-    PyReadonlyArray4,
+    IntoPyArray, PyArray1, PyArray2, PyArray3, PyArray4, PyReadonlyArray1, PyReadonlyArray2,
+    PyReadonlyArray3, PyReadonlyArray4,
 };
-use pyo3::exceptions::PyValueError;
+use pyo3::exceptions::{PyKeyboardInterrupt, PyOSError, PyRuntimeError, PyValueError};
 use pyo3::prelude::*;
 use pyo3::types::{PyDict, PyList};
 use pyo3::wrap_pyfunction;
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
 use std::{fs::File, slice};
 
+impl From<PassivbotError> for PyErr {
+    fn from(err: PassivbotError) -> PyErr {
+        match err {
+            PassivbotError::InvalidParams(_) => PyValueError::new_err(err.to_string()),
+            PassivbotError::Io { .. } => PyOSError::new_err(err.to_string()),
+            PassivbotError::Internal(_) => PyRuntimeError::new_err(err.to_string()),
+        }
+    }
+}
+
+/// Runs a pyfunction body, turning a panic into RuntimeError instead of unwinding into Python.
+fn guard<T>(f: impl FnOnce() -> T) -> PyResult<T> {
+    guard_result(|| Ok(f()))
+}
+
+fn guard_result<T>(f: impl FnOnce() -> PyResult<T>) -> PyResult<T> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let msg = if let Some(msg) = payload.downcast_ref::<&str>() {
+            msg.to_string()
+        } else if let Some(msg) = payload.downcast_ref::<String>() {
+            msg.clone()
+        } else {
+            "panic".to_string()
+        };
+        Err(PassivbotError::Internal(msg).into())
+    })
+}
+
+/// fills, equities and analysis of run_backtest.
+type BacktestOutput = (Py<PyArray2<PyObject>>, Py<PyArray1<f64>>, Py<PyDict>);
+
 #[pyfunction]
 pub fn run_backtest(
     shared_memory_file: &str,
     hlcvs_shape: (usize, usize, usize),
     hlcvs_dtype: &str,
-    bot_params_pair_dict: &PyDict,
-    exchange_params_list: &PyAny,
-    backtest_params_dict: &PyDict,
-) -> PyResult<(Py<PyArray2<PyObject>>, Py<PyArray1<f64>>, Py<PyDict>)> {
+    bot_params_pair_dict: &Bound<'_, PyDict>,
+    exchange_params_list: &Bound<'_, PyAny>,
+    backtest_params_dict: &Bound<'_, PyDict>,
+) -> PyResult<BacktestOutput> {
+    guard_result(|| {
+        run_backtest_impl(
+            shared_memory_file,
+            hlcvs_shape,
+            hlcvs_dtype,
+            bot_params_pair_dict,
+            exchange_params_list,
+            backtest_params_dict,
+        )
+    })
+}
+
+fn run_backtest_impl(
+    shared_memory_file: &str,
+    hlcvs_shape: (usize, usize, usize),
+    hlcvs_dtype: &str,
+    bot_params_pair_dict: &Bound<'_, PyDict>,
+    exchange_params_list: &Bound<'_, PyAny>,
+    backtest_params_dict: &Bound<'_, PyDict>,
+) -> PyResult<BacktestOutput> {
     // Open the memory-mapped file
-    let file = File::open(shared_memory_file)
-        .map_err(|e| PyValueError::new_err(format!("Unable to open shared memory file: {}", e)))?;
+    let file = File::open(shared_memory_file).map_err(|source| PassivbotError::Io {
+        context: "Unable to open shared memory file".to_string(),
+        source,
+    })?;
 
     let mmap = unsafe {
         MmapOptions::new()
             .map(&file)
-            .map_err(|e| PyValueError::new_err(format!("Unable to map file: {}", e)))?
+            .map_err(|source| PassivbotError::Io {
+                context: "Unable to map file".to_string(),
+                source,
+            })?
     };
 
-    let hlcvs_rust = unsafe {
-        match hlcvs_dtype {
-            "<f8" => ArrayView::from_shape_ptr(hlcvs_shape, mmap.as_ptr() as *const f64),
-            _ => return Err(PyValueError::new_err("Unsupported dtype for HLCV data")),
-        }
-    };
+    if hlcvs_dtype != "<f8" {
+        return Err(PassivbotError::InvalidParams(format!(
+            "Unsupported dtype {} for HLCV data",
+            hlcvs_dtype
+        ))
+        .into());
+    }
+    // a view past the end of the mapping would read out of bounds
+    let (n_timesteps, n_coins, n_columns) = hlcvs_shape;
+    let n_bytes = n_timesteps
+        .checked_mul(n_coins)
+        .and_then(|n| n.checked_mul(n_columns))
+        .and_then(|n| n.checked_mul(std::mem::size_of::<f64>()));
+    if n_bytes.is_none_or(|n_bytes| n_bytes > mmap.len()) {
+        return Err(PassivbotError::InvalidParams(format!(
+            "hlcvs_shape {:?} does not fit shared memory file of {} bytes",
+            hlcvs_shape,
+            mmap.len()
+        ))
+        .into());
+    }
+    let hlcvs_rust = unsafe { ArrayView::from_shape_ptr(hlcvs_shape, mmap.as_ptr() as *const f64) };
 
-    let bot_params_pair = bot_params_pair_from_dict(bot_params_pair_dict)?;
+    let bot_params_pair = BotParamsPair::from_py_dict(bot_params_pair_dict)?.0;
     let exchange_params = {
         let mut params_vec = Vec::new();
         if let Ok(py_list) = exchange_params_list.downcast::<PyList>() {
             for py_dict in py_list.iter() {
                 if let Ok(dict) = py_dict.downcast::<PyDict>() {
-                    let params = exchange_params_from_dict(dict)?;
+                    let params = ExchangeParams::from_py_dict(dict)?.0;
                     params_vec.push(params);
                 } else {
                     return Err(PyValueError::new_err(
@@ -72,18 +162,28 @@ pub fn run_backtest(
     };
 
     let backtest_params = backtest_params_from_dict(backtest_params_dict)?;
+    // hlcvs carries no timestamps, so blackout windows would be matched against epoch 0
+    let has_blackout_windows = !bot_params_pair.long.entry_blackout_windows.is_empty()
+        || !bot_params_pair.short.entry_blackout_windows.is_empty();
+    if has_blackout_windows
+        && extract_value::<u64>(backtest_params_dict, "first_timestamp_ms").is_err()
+    {
+        return Err(PyValueError::new_err(
+            "entry_blackout_windows require first_timestamp_ms in backtest params",
+        ));
+    }
     let mut backtest = Backtest::new(
         &hlcvs_rust,
         bot_params_pair,
         exchange_params,
         &backtest_params,
-    );
+    )?;
 
     // Run the backtest and get fills and equities
     Python::with_gil(|py| {
         let (fills, equities) = backtest.run();
         let analysis = analyze_backtest(&fills, &equities);
-        let py_analysis = PyDict::new(py);
+        let py_analysis = PyDict::new_bound(py);
         py_analysis.set_item("adg", analysis.adg)?;
         py_analysis.set_item("mdg", analysis.mdg)?;
         py_analysis.set_item("sharpe_ratio", analysis.sharpe_ratio)?;
@@ -114,85 +214,381 @@ pub fn run_backtest(
         let py_equities = Array1::from_vec(equities);
 
         Ok((
-            py_fills.into_pyarray(py).to_owned(),
-            py_equities.into_pyarray(py).to_owned(),
+            py_fills.into_pyarray_bound(py).unbind(),
+            py_equities.into_pyarray_bound(py).unbind(),
             py_analysis.into(),
         ))
     })
 }
 
-fn backtest_params_from_dict(dict: &PyDict) -> PyResult<BacktestParams> {
+fn backtest_params_from_dict(dict: &Bound<'_, PyDict>) -> PyResult<BacktestParams> {
     Ok(BacktestParams {
         starting_balance: extract_value(dict, "starting_balance").unwrap_or_default(),
         maker_fee: extract_value(dict, "maker_fee").unwrap_or_default(),
         symbols: extract_value(dict, "symbols").unwrap_or_default(),
+        balance_mode: balance_mode_from_dict(dict)?,
+        latency_candles: 0, // not supported by the multi symbol backtest
+        max_fill_volume_fraction: 0.0,
+        strict_order_validation: false,
+        first_timestamp_ms: extract_value(dict, "first_timestamp_ms").unwrap_or_default(),
     })
 }
 
-fn exchange_params_from_dict(dict: &PyDict) -> PyResult<ExchangeParams> {
-    Ok(ExchangeParams {
-        qty_step: extract_value(dict, "qty_step").unwrap_or_default(),
-        price_step: extract_value(dict, "price_step").unwrap_or_default(),
-        min_qty: extract_value(dict, "min_qty").unwrap_or_default(),
-        min_cost: extract_value(dict, "min_cost").unwrap_or_default(),
-        c_mult: extract_value(dict, "c_mult").unwrap_or_default(),
-    })
+fn balance_mode_from_dict(dict: &Bound<'_, PyDict>) -> PyResult<BalanceMode> {
+    let balance_mode: String =
+        extract_value(dict, "balance_mode").unwrap_or_else(|_| "compounding".to_string());
+    match balance_mode.as_str() {
+        "compounding" => Ok(BalanceMode::Compounding),
+        "fixed" => Ok(BalanceMode::Fixed),
+        "capped" => Ok(BalanceMode::CappedAt(extract_value(dict, "balance_cap")?)),
+        _ => Err(PyValueError::new_err(format!(
+            "Invalid balance_mode '{}'",
+            balance_mode
+        ))),
+    }
 }
 
-fn bot_params_pair_from_dict(dict: &PyDict) -> PyResult<BotParamsPair> {
-    Ok(BotParamsPair {
-        long: bot_params_from_dict(extract_value(dict, "long")?)?,
-        short: bot_params_from_dict(extract_value(dict, "short")?)?,
-    })
+/// Typed reads from a config dict. Remembers the keys read so that the remaining ones can be
+/// reported as unknown, and names the offending key, prefixed by path, in every error.
+struct ConfigDict<'py> {
+    dict: Bound<'py, PyDict>,
+    path: String,
+    read: Vec<&'static str>,
+}
+
+impl<'py> ConfigDict<'py> {
+    fn new(dict: &Bound<'py, PyDict>, path: &str) -> Self {
+        ConfigDict {
+            dict: dict.clone(),
+            path: path.to_string(),
+            read: Vec::new(),
+        }
+    }
+
+    fn key_path(&self, key: &str) -> String {
+        if self.path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", self.path, key)
+        }
+    }
+
+    fn required<T: FromPyObject<'py>>(&mut self, key: &'static str) -> PyResult<T> {
+        self.optional(key)?
+            .ok_or_else(|| PyValueError::new_err(format!("missing key {}", self.key_path(key))))
+    }
+
+    /// None if the key is missing or its value is None.
+    fn optional<T: FromPyObject<'py>>(&mut self, key: &'static str) -> PyResult<Option<T>> {
+        self.read.push(key);
+        match self.dict.get_item(key)? {
+            Some(value) if !value.is_none() => value.extract().map(Some).map_err(|err| {
+                PyValueError::new_err(format!("invalid {}: {}", self.key_path(key), err))
+            }),
+            _ => Ok(None),
+        }
+    }
+
+    /// Config entries are often floats even where an integer is meant, e.g. n_positions.
+    fn required_count(&mut self, key: &'static str) -> PyResult<usize> {
+        self.optional_count(key)?
+            .ok_or_else(|| PyValueError::new_err(format!("missing key {}", self.key_path(key))))
+    }
+
+    fn optional_count(&mut self, key: &'static str) -> PyResult<Option<usize>> {
+        match self.optional::<f64>(key)? {
+            Some(value) if value >= 0.0 => Ok(Some(value.round() as usize)),
+            Some(value) => Err(PyValueError::new_err(format!(
+                "invalid {}: expected a non-negative number, got {}",
+                self.key_path(key),
+                value
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    fn unknown_keys(&self) -> Vec<String> {
+        self.dict
+            .keys()
+            .iter()
+            .map(|key| key.to_string())
+            .filter(|key| !self.read.contains(&key.as_str()))
+            .map(|key| format!("unknown key {}", self.key_path(&key)))
+            .collect()
+    }
+}
+
+impl ExchangeParams {
+    /// Missing keys take their defaults. Returns warnings about unknown keys alongside.
+    pub fn from_py_dict(dict: &Bound<'_, PyDict>) -> PyResult<(Self, Vec<String>)> {
+        let mut config = ConfigDict::new(dict, "");
+        let default = ExchangeParams::default();
+        let exchange_params = ExchangeParams {
+            qty_step: config.optional("qty_step")?.unwrap_or(default.qty_step),
+            price_step: config.optional("price_step")?.unwrap_or(default.price_step),
+            min_qty: config.optional("min_qty")?.unwrap_or(default.min_qty),
+            min_cost: config.optional("min_cost")?.unwrap_or(default.min_cost),
+            c_mult: config.optional("c_mult")?.unwrap_or(default.c_mult),
+            fee_rate: config.optional("fee_rate")?.unwrap_or(default.fee_rate),
+            max_position_size: config
+                .optional("max_position_size")?
+                .unwrap_or(default.max_position_size),
+            price_band_pct: config
+                .optional("price_band_pct")?
+                .unwrap_or(default.price_band_pct),
+        };
+        Ok((exchange_params, config.unknown_keys()))
+    }
+}
+
+impl BotParamsPair {
+    /// Takes either {"long": {...}, "short": {...}} or a whole live config, whose "bot" entry
+    /// is used and whose other sections are ignored.
+    pub fn from_py_dict(dict: &Bound<'_, PyDict>) -> PyResult<(Self, Vec<String>)> {
+        let (dict, path) = match dict.get_item("bot")? {
+            Some(bot) => (
+                bot.downcast_into::<PyDict>()
+                    .map_err(|_| PyValueError::new_err("invalid bot: expected a dict"))?,
+                "bot",
+            ),
+            None => (dict.clone(), ""),
+        };
+        let mut config = ConfigDict::new(&dict, path);
+        let mut warnings = Vec::new();
+        let mut side = |config: &mut ConfigDict, key: &'static str| -> PyResult<BotParams> {
+            let side_dict: Bound<'_, PyDict> = config.required(key)?;
+            let (bot_params, side_warnings) =
+                bot_params_from_config(ConfigDict::new(&side_dict, &config.key_path(key)))?;
+            warnings.extend(side_warnings);
+            Ok(bot_params)
+        };
+        let bot_params_pair = BotParamsPair {
+            long: side(&mut config, "long")?,
+            short: side(&mut config, "short")?,
+        };
+        warnings.extend(config.unknown_keys());
+        Ok((bot_params_pair, warnings))
+    }
+}
+
+impl BotParams {
+    /// Keys with a documented default may be omitted, all others must be given.
+    /// Raises on out of range values found by validate(). Returns warnings about unknown keys
+    /// and about other validate() findings alongside.
+    pub fn from_py_dict(dict: &Bound<'_, PyDict>) -> PyResult<(Self, Vec<String>)> {
+        bot_params_from_config(ConfigDict::new(dict, ""))
+    }
+}
+
+fn bot_params_from_config(mut config: ConfigDict) -> PyResult<(BotParams, Vec<String>)> {
+    let mut bot_params = BotParams {
+        auto_adjust_markup: config.optional("auto_adjust_markup")?.unwrap_or_default(),
+        balance_reserve_pct: config.optional("balance_reserve_pct")?.unwrap_or_default(),
+        bracket_stop_pct: config.optional("bracket_stop_pct")?.unwrap_or_default(),
+        close_grid_gap_pct: config.optional("close_grid_gap_pct")?.unwrap_or_default(),
+        close_grid_markup_range: config.required("close_grid_markup_range")?,
+        close_grid_min_markup: config.required("close_grid_min_markup")?,
+        close_grid_max_markup: config
+            .optional("close_grid_max_markup")?
+            .unwrap_or_default(),
+        close_grid_min_entry_distance_ratio: config
+            .optional("close_grid_min_entry_distance_ratio")?
+            .unwrap_or_default(),
+        close_min_net_markup: config.optional("close_min_net_markup")?.unwrap_or_default(),
+        close_grid_qty_pct: config.required("close_grid_qty_pct")?,
+        close_trailing_retracement_pct: config.required("close_trailing_retracement_pct")?,
+        close_trailing_drawdown_floor_pct: config
+            .optional("close_trailing_drawdown_floor_pct")?
+            .unwrap_or_default(),
+        close_trailing_grid_ratio: config.required("close_trailing_grid_ratio")?,
+        close_trailing_qty_pct: config.required("close_trailing_qty_pct")?,
+        close_trailing_threshold_pct: config.required("close_trailing_threshold_pct")?,
+        close_trailing_min_profit_pct: config
+            .optional("close_trailing_min_profit_pct")?
+            .unwrap_or_default(),
+        coalesce_grid_orders: config.optional("coalesce_grid_orders")?.unwrap_or_default(),
+        entry_grid_double_down_factor: config.required("entry_grid_double_down_factor")?,
+        entry_grid_qty_schedule: config
+            .optional("entry_grid_qty_schedule")?
+            .unwrap_or_default(),
+        entry_grid_spacing_weight: config.required("entry_grid_spacing_weight")?,
+        entry_grid_spacing_pct: config.required("entry_grid_spacing_pct")?,
+        entry_grid_spacing_vol_weight: config
+            .optional("entry_grid_spacing_vol_weight")?
+            .unwrap_or_default(),
+        entry_grid_ema_floor: config.optional("entry_grid_ema_floor")?.unwrap_or_default(),
+        // [[start_ms, end_ms], ...], possibly as floats
+        entry_blackout_windows: config
+            .optional::<Vec<[f64; 2]>>("entry_blackout_windows")?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|[start, end]| (start as u64, end as u64))
+            .collect(),
+        entry_initial_ema_dist: config.required("entry_initial_ema_dist")?,
+        entry_initial_qty_pct: config.required("entry_initial_qty_pct")?,
+        entry_initial_timeout_sec: config
+            .optional("entry_initial_timeout_sec")?
+            .unwrap_or_default(),
+        entry_reentry_ema_dist: config.optional("entry_reentry_ema_dist")?,
+        entry_sizing_basis: entry_sizing_basis_from_config(&mut config)?,
+        entry_trailing_drawdown_pct: config
+            .optional("entry_trailing_drawdown_pct")?
+            .unwrap_or_default(),
+        entry_trailing_max_adds: config
+            .optional_count("entry_trailing_max_adds")?
+            .unwrap_or_default(),
+        entry_trailing_mode: entry_trailing_mode_from_config(&mut config)?,
+        entry_trailing_retracement_pct: config.required("entry_trailing_retracement_pct")?,
+        entry_trailing_grid_mode: entry_trailing_grid_mode_from_config(&mut config)?,
+        entry_trailing_grid_ratio: config.required("entry_trailing_grid_ratio")?,
+        entry_trailing_threshold_pct: config.required("entry_trailing_threshold_pct")?,
+        filter_rolling_window: config.required_count("filter_rolling_window")?,
+        filter_relative_volume_clip_pct: config.required("filter_relative_volume_clip_pct")?,
+        ema_span_0: config.required("ema_span_0")?,
+        ema_span_1: config.required("ema_span_1")?,
+        ema_warmup_behavior: ema_warmup_behavior_from_config(&mut config)?,
+        n_positions: config.required_count("n_positions")?,
+        total_wallet_exposure_limit: config.required("total_wallet_exposure_limit")?,
+        wallet_exposure_limit: config.required("wallet_exposure_limit")?,
+        unstuck_close_pct: config.required("unstuck_close_pct")?,
+        unstuck_ema_dist: config.required("unstuck_ema_dist")?,
+        unstuck_loss_allowance_pct: config.required("unstuck_loss_allowance_pct")?,
+        unstuck_threshold: config.required("unstuck_threshold")?,
+    };
+    bot_params.adjust_markup();
+    let mut warnings = config.unknown_keys();
+    if let Err(errors) = bot_params.validate() {
+        let mut fatal = Vec::new();
+        for error in errors {
+            let message = config.key_path(&error.to_string());
+            match error {
+                BotParamsError::OutOfRange { .. } => fatal.push(message),
+                _ => warnings.push(message),
+            }
+        }
+        if !fatal.is_empty() {
+            return Err(PyValueError::new_err(fatal.join("; ")));
+        }
+    }
+    Ok((bot_params, warnings))
+}
+
+fn entry_trailing_mode_from_config(config: &mut ConfigDict) -> PyResult<EntryTrailingMode> {
+    let entry_trailing_mode: Option<String> = config.optional("entry_trailing_mode")?;
+    match entry_trailing_mode.as_deref() {
+        None | Some("threshold_drawdown") => Ok(EntryTrailingMode::ThresholdDrawdown),
+        Some("trailing_stop") => Ok(EntryTrailingMode::TrailingStop),
+        Some(other) => Err(PyValueError::new_err(format!(
+            "invalid {}: expected threshold_drawdown or trailing_stop, got '{}'",
+            config.key_path("entry_trailing_mode"),
+            other
+        ))),
+    }
+}
+
+fn entry_trailing_grid_mode_from_config(
+    config: &mut ConfigDict,
+) -> PyResult<EntryTrailingGridMode> {
+    let entry_trailing_grid_mode: Option<String> = config.optional("entry_trailing_grid_mode")?;
+    match entry_trailing_grid_mode.as_deref() {
+        None | Some("switch") => Ok(EntryTrailingGridMode::Switch),
+        Some("blended") => Ok(EntryTrailingGridMode::Blended),
+        Some(other) => Err(PyValueError::new_err(format!(
+            "invalid {}: expected switch or blended, got '{}'",
+            config.key_path("entry_trailing_grid_mode"),
+            other
+        ))),
+    }
+}
+
+fn entry_sizing_basis_from_config(config: &mut ConfigDict) -> PyResult<EntrySizingBasis> {
+    let entry_sizing_basis: Option<String> = config.optional("entry_sizing_basis")?;
+    match entry_sizing_basis.as_deref() {
+        None | Some("balance") => Ok(EntrySizingBasis::Balance),
+        Some("equity") => Ok(EntrySizingBasis::Equity),
+        Some(other) => Err(PyValueError::new_err(format!(
+            "invalid {}: expected balance or equity, got '{}'",
+            config.key_path("entry_sizing_basis"),
+            other
+        ))),
+    }
+}
+
+fn ema_warmup_behavior_from_config(config: &mut ConfigDict) -> PyResult<EmaWarmupBehavior> {
+    let ema_warmup_behavior: Option<String> = config.optional("ema_warmup_behavior")?;
+    match ema_warmup_behavior.as_deref() {
+        None | Some("suppress_entries") => Ok(EmaWarmupBehavior::SuppressEntries),
+        Some("fallback_to_order_book") => Ok(EmaWarmupBehavior::FallbackToOrderBook),
+        Some(other) => Err(PyValueError::new_err(format!(
+            "invalid {}: expected suppress_entries or fallback_to_order_book, got '{}'",
+            config.key_path("ema_warmup_behavior"),
+            other
+        ))),
+    }
+}
+
+/// Parses bot params the way run_backtest_py does, from {"long": {...}, "short": {...}} or a
+/// whole live config. Raises ValueError on invalid params, returns the warnings otherwise.
+#[pyfunction]
+pub fn check_bot_params_pair_py(config: &Bound<'_, PyDict>) -> PyResult<Vec<String>> {
+    guard_result(|| Ok(BotParamsPair::from_py_dict(config)?.1))
 }
 
-fn bot_params_from_dict(dict: &PyDict) -> PyResult<BotParams> {
-    Ok(BotParams {
-        close_grid_markup_range: extract_value(dict, "close_grid_markup_range")?,
-        close_grid_min_markup: extract_value(dict, "close_grid_min_markup")?,
-        close_grid_qty_pct: extract_value(dict, "close_grid_qty_pct")?,
-        close_trailing_retracement_pct: extract_value(dict, "close_trailing_retracement_pct")?,
-        close_trailing_grid_ratio: extract_value(dict, "close_trailing_grid_ratio")?,
-        close_trailing_qty_pct: extract_value(dict, "close_trailing_qty_pct")?,
-        close_trailing_threshold_pct: extract_value(dict, "close_trailing_threshold_pct")?,
-        entry_grid_double_down_factor: extract_value(dict, "entry_grid_double_down_factor")?,
-        entry_grid_spacing_weight: extract_value(dict, "entry_grid_spacing_weight")?,
-        entry_grid_spacing_pct: extract_value(dict, "entry_grid_spacing_pct")?,
-        entry_initial_ema_dist: extract_value(dict, "entry_initial_ema_dist")?,
-        entry_initial_qty_pct: extract_value(dict, "entry_initial_qty_pct")?,
-        entry_trailing_retracement_pct: extract_value(dict, "entry_trailing_retracement_pct")?,
-        entry_trailing_grid_ratio: extract_value(dict, "entry_trailing_grid_ratio")?,
-        entry_trailing_threshold_pct: extract_value(dict, "entry_trailing_threshold_pct")?,
-        filter_rolling_window: {
-            let filter_rolling_window_float: f64 = extract_value(dict, "filter_rolling_window")?;
-            filter_rolling_window_float.round() as usize
+/// Balance, order book, EMA bands, trailing add count, position and trailing prices from one
+/// flat dict.
+/// Missing keys take their defaults.
+fn state_from_dict(dict: &Bound<'_, PyDict>) -> (StateParams, Position, TrailingPriceBundle) {
+    let default_trailing = TrailingPriceBundle::default();
+    (
+        StateParams {
+            balance: extract_value(dict, "balance").unwrap_or_default(),
+            equity: extract_value(dict, "equity").ok(),
+            order_book: OrderBook {
+                bid: extract_value(dict, "order_book_bid").unwrap_or_default(),
+                ask: extract_value(dict, "order_book_ask").unwrap_or_default(),
+            },
+            ema_bands: EMABands {
+                upper: extract_value(dict, "ema_bands_upper").unwrap_or_default(),
+                lower: extract_value(dict, "ema_bands_lower").unwrap_or_default(),
+            },
+            trailing_add_count: extract_value(dict, "trailing_add_count").unwrap_or_default(),
+            volatility: extract_value(dict, "volatility").unwrap_or_default(),
+            ..Default::default()
         },
-        filter_relative_volume_clip_pct: extract_value(dict, "filter_relative_volume_clip_pct")?,
-        ema_span_0: extract_value(dict, "ema_span_0")?,
-        ema_span_1: extract_value(dict, "ema_span_1")?,
-        n_positions: {
-            let n_positions_float: f64 = extract_value(dict, "n_positions")?;
-            n_positions_float.round() as usize
+        Position {
+            size: extract_value(dict, "position_size").unwrap_or_default(),
+            price: extract_value(dict, "position_price").unwrap_or_default(),
         },
-        total_wallet_exposure_limit: extract_value(dict, "total_wallet_exposure_limit")?,
-        wallet_exposure_limit: extract_value(dict, "wallet_exposure_limit")?,
-        unstuck_close_pct: extract_value(dict, "unstuck_close_pct")?,
-        unstuck_ema_dist: extract_value(dict, "unstuck_ema_dist")?,
-        unstuck_loss_allowance_pct: extract_value(dict, "unstuck_loss_allowance_pct")?,
-        unstuck_threshold: extract_value(dict, "unstuck_threshold")?,
-    })
+        TrailingPriceBundle {
+            min_since_open: extract_value(dict, "min_since_open")
+                .unwrap_or(default_trailing.min_since_open),
+            max_since_min: extract_value(dict, "max_since_min")
+                .unwrap_or(default_trailing.max_since_min),
+            max_since_open: extract_value(dict, "max_since_open")
+                .unwrap_or(default_trailing.max_since_open),
+            min_since_max: extract_value(dict, "min_since_max")
+                .unwrap_or(default_trailing.min_since_max),
+        },
+    )
 }
 
-fn extract_value<'a, T: pyo3::FromPyObject<'a>>(dict: &'a PyDict, key: &str) -> PyResult<T> {
+fn extract_value<'py, T: pyo3::FromPyObject<'py>>(
+    dict: &Bound<'py, PyDict>,
+    key: &str,
+) -> PyResult<T> {
     dict.get_item(key)
         .map_err(|_| {
             PyErr::new::<pyo3::exceptions::PyKeyError, _>(format!("Key '{}' not found", key))
         })?
         .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>("Value is None"))
-        .and_then(pyo3::FromPyObject::extract)
+        .and_then(|value| value.extract())
 }
 
+// Legacy positional wrappers returning (qty, price, order_type name) tuples. Deprecated in favor
+// of the dict variants below, which return Order objects; to be removed in the next release.
+
 #[pyfunction]
+#[allow(clippy::too_many_arguments)]
 pub fn calc_grid_close_long_py(
     qty_step: f64,
     price_step: f64,
@@ -207,6 +603,41 @@ pub fn calc_grid_close_long_py(
     position_size: f64,
     position_price: f64,
     order_book_ask: f64,
+) -> PyResult<(f64, f64, String)> {
+    guard(|| {
+        calc_grid_close_long_py_impl(
+            qty_step,
+            price_step,
+            min_qty,
+            min_cost,
+            c_mult,
+            close_grid_markup_range,
+            close_grid_min_markup,
+            close_grid_qty_pct,
+            wallet_exposure_limit,
+            balance,
+            position_size,
+            position_price,
+            order_book_ask,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn calc_grid_close_long_py_impl(
+    qty_step: f64,
+    price_step: f64,
+    min_qty: f64,
+    min_cost: f64,
+    c_mult: f64,
+    close_grid_markup_range: f64,
+    close_grid_min_markup: f64,
+    close_grid_qty_pct: f64,
+    wallet_exposure_limit: f64,
+    balance: f64,
+    position_size: f64,
+    position_price: f64,
+    order_book_ask: f64,
 ) -> (f64, f64, String) {
     let exchange_params = ExchangeParams {
         qty_step,
@@ -214,6 +645,7 @@ pub fn calc_grid_close_long_py(
         min_qty,
         min_cost,
         c_mult,
+        ..Default::default()
     };
     let state_params = StateParams {
         balance,
@@ -240,6 +672,18 @@ pub fn calc_grid_close_long_py(
 }
 
 #[pyfunction]
+#[pyo3(signature = (
+    price_step,
+    order_book_ask,
+    max_since_open,
+    min_since_max,
+    close_trailing_threshold_pct,
+    close_trailing_retracement_pct,
+    position_size,
+    position_price,
+    close_trailing_min_profit_pct=0.0,
+))]
+#[allow(clippy::too_many_arguments)]
 pub fn calc_trailing_close_long_py(
     price_step: f64,
     order_book_ask: f64,
@@ -249,6 +693,34 @@ pub fn calc_trailing_close_long_py(
     close_trailing_retracement_pct: f64,
     position_size: f64,
     position_price: f64,
+    close_trailing_min_profit_pct: f64,
+) -> PyResult<(f64, f64, String)> {
+    guard(|| {
+        calc_trailing_close_long_py_impl(
+            price_step,
+            order_book_ask,
+            max_since_open,
+            min_since_max,
+            close_trailing_threshold_pct,
+            close_trailing_retracement_pct,
+            position_size,
+            position_price,
+            close_trailing_min_profit_pct,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn calc_trailing_close_long_py_impl(
+    price_step: f64,
+    order_book_ask: f64,
+    max_since_open: f64,
+    min_since_max: f64,
+    close_trailing_threshold_pct: f64,
+    close_trailing_retracement_pct: f64,
+    position_size: f64,
+    position_price: f64,
+    close_trailing_min_profit_pct: f64,
 ) -> (f64, f64, String) {
     let exchange_params = ExchangeParams {
         price_step,
@@ -264,6 +736,7 @@ pub fn calc_trailing_close_long_py(
     let bot_params = BotParams {
         close_trailing_retracement_pct: close_trailing_retracement_pct,
         close_trailing_threshold_pct: close_trailing_threshold_pct,
+        close_trailing_min_profit_pct,
         ..Default::default()
     };
     let position = Position {
@@ -286,6 +759,7 @@ pub fn calc_trailing_close_long_py(
 }
 
 #[pyfunction]
+#[allow(clippy::too_many_arguments)]
 pub fn calc_grid_entry_long_py(
     qty_step: f64,
     price_step: f64,
@@ -303,6 +777,47 @@ pub fn calc_grid_entry_long_py(
     wallet_exposure_limit: f64,
     position_size: f64,
     position_price: f64,
+) -> PyResult<(f64, f64, String)> {
+    guard(|| {
+        calc_grid_entry_long_py_impl(
+            qty_step,
+            price_step,
+            min_qty,
+            min_cost,
+            c_mult,
+            balance,
+            order_book_bid,
+            ema_bands_lower,
+            entry_grid_double_down_factor,
+            entry_grid_spacing_weight,
+            entry_grid_spacing_pct,
+            entry_initial_ema_dist,
+            entry_initial_qty_pct,
+            wallet_exposure_limit,
+            position_size,
+            position_price,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn calc_grid_entry_long_py_impl(
+    qty_step: f64,
+    price_step: f64,
+    min_qty: f64,
+    min_cost: f64,
+    c_mult: f64,
+    balance: f64,
+    order_book_bid: f64,
+    ema_bands_lower: f64,
+    entry_grid_double_down_factor: f64,
+    entry_grid_spacing_weight: f64,
+    entry_grid_spacing_pct: f64,
+    entry_initial_ema_dist: f64,
+    entry_initial_qty_pct: f64,
+    wallet_exposure_limit: f64,
+    position_size: f64,
+    position_price: f64,
 ) -> (f64, f64, String) {
     let exchange_params = ExchangeParams {
         qty_step,
@@ -310,6 +825,7 @@ pub fn calc_grid_entry_long_py(
         min_qty,
         min_cost,
         c_mult,
+        ..Default::default()
     };
     let state_params = StateParams {
         balance,
@@ -321,6 +837,7 @@ pub fn calc_grid_entry_long_py(
             lower: ema_bands_lower,
             ..Default::default()
         },
+        ..Default::default()
     };
     let bot_params = BotParams {
         entry_grid_double_down_factor,
@@ -341,6 +858,7 @@ pub fn calc_grid_entry_long_py(
 }
 
 #[pyfunction]
+#[allow(clippy::too_many_arguments)]
 pub fn calc_trailing_entry_long_py(
     qty_step: f64,
     price_step: f64,
@@ -358,6 +876,47 @@ pub fn calc_trailing_entry_long_py(
     max_since_min: f64,
     entry_trailing_threshold_pct: f64,
     entry_trailing_retracement_pct: f64,
+) -> PyResult<(f64, f64, String)> {
+    guard(|| {
+        calc_trailing_entry_long_py_impl(
+            qty_step,
+            price_step,
+            min_qty,
+            min_cost,
+            c_mult,
+            balance,
+            order_book_bid,
+            entry_grid_double_down_factor,
+            entry_initial_qty_pct,
+            wallet_exposure_limit,
+            position_size,
+            position_price,
+            min_since_open,
+            max_since_min,
+            entry_trailing_threshold_pct,
+            entry_trailing_retracement_pct,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn calc_trailing_entry_long_py_impl(
+    qty_step: f64,
+    price_step: f64,
+    min_qty: f64,
+    min_cost: f64,
+    c_mult: f64,
+    balance: f64,
+    order_book_bid: f64,
+    entry_grid_double_down_factor: f64,
+    entry_initial_qty_pct: f64,
+    wallet_exposure_limit: f64,
+    position_size: f64,
+    position_price: f64,
+    min_since_open: f64,
+    max_since_min: f64,
+    entry_trailing_threshold_pct: f64,
+    entry_trailing_retracement_pct: f64,
 ) -> (f64, f64, String) {
     let exchange_params = ExchangeParams {
         qty_step,
@@ -365,6 +924,7 @@ pub fn calc_trailing_entry_long_py(
         min_qty,
         min_cost,
         c_mult,
+        ..Default::default()
     };
     let state_params = StateParams {
         balance,
@@ -402,28 +962,148 @@ pub fn calc_trailing_entry_long_py(
 }
 
 #[pyfunction]
-pub fn calc_next_entry_long_py(
+pub fn calc_exposure_headroom_py(
+    c_mult: f64,
+    balance: f64,
+    position_size: f64,
+    position_price: f64,
+    wallet_exposure_limit: f64,
+) -> PyResult<f64> {
+    guard(|| {
+        calc_exposure_headroom_py_impl(
+            c_mult,
+            balance,
+            position_size,
+            position_price,
+            wallet_exposure_limit,
+        )
+    })
+}
+
+fn calc_exposure_headroom_py_impl(
+    c_mult: f64,
+    balance: f64,
+    position_size: f64,
+    position_price: f64,
+    wallet_exposure_limit: f64,
+) -> f64 {
+    let exchange_params = ExchangeParams {
+        c_mult,
+        ..Default::default()
+    };
+    let bot_params = BotParams {
+        wallet_exposure_limit,
+        ..Default::default()
+    };
+    let position = Position {
+        size: position_size,
+        price: position_price,
+    };
+    calc_exposure_headroom(&position, balance, &bot_params, &exchange_params)
+}
+
+#[pyfunction]
+pub fn calc_addable_qty_at_py(
+    qty_step: f64,
+    c_mult: f64,
+    price: f64,
+    balance: f64,
+    position_size: f64,
+    position_price: f64,
+    wallet_exposure_limit: f64,
+) -> PyResult<f64> {
+    guard(|| {
+        calc_addable_qty_at_py_impl(
+            qty_step,
+            c_mult,
+            price,
+            balance,
+            position_size,
+            position_price,
+            wallet_exposure_limit,
+        )
+    })
+}
+
+fn calc_addable_qty_at_py_impl(
+    qty_step: f64,
+    c_mult: f64,
+    price: f64,
+    balance: f64,
+    position_size: f64,
+    position_price: f64,
+    wallet_exposure_limit: f64,
+) -> f64 {
+    let exchange_params = ExchangeParams {
+        qty_step,
+        c_mult,
+        ..Default::default()
+    };
+    let bot_params = BotParams {
+        wallet_exposure_limit,
+        ..Default::default()
+    };
+    let position = Position {
+        size: position_size,
+        price: position_price,
+    };
+    calc_addable_qty_at(price, &position, balance, &bot_params, &exchange_params)
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+pub fn calc_initial_entry_long_with_timeout_py(
     qty_step: f64,
     price_step: f64,
     min_qty: f64,
     min_cost: f64,
     c_mult: f64,
-    entry_grid_double_down_factor: f64,
-    entry_grid_spacing_weight: f64,
-    entry_grid_spacing_pct: f64,
+    balance: f64,
+    order_book_bid: f64,
+    order_book_ask: f64,
+    ema_bands_lower: f64,
     entry_initial_ema_dist: f64,
     entry_initial_qty_pct: f64,
-    entry_trailing_grid_ratio: f64,
-    entry_trailing_retracement_pct: f64,
-    entry_trailing_threshold_pct: f64,
+    entry_initial_timeout_sec: f64,
     wallet_exposure_limit: f64,
+    seconds_pending: f64,
+) -> PyResult<(f64, f64, String)> {
+    guard(|| {
+        calc_initial_entry_long_with_timeout_py_impl(
+            qty_step,
+            price_step,
+            min_qty,
+            min_cost,
+            c_mult,
+            balance,
+            order_book_bid,
+            order_book_ask,
+            ema_bands_lower,
+            entry_initial_ema_dist,
+            entry_initial_qty_pct,
+            entry_initial_timeout_sec,
+            wallet_exposure_limit,
+            seconds_pending,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn calc_initial_entry_long_with_timeout_py_impl(
+    qty_step: f64,
+    price_step: f64,
+    min_qty: f64,
+    min_cost: f64,
+    c_mult: f64,
     balance: f64,
-    position_size: f64,
-    position_price: f64,
-    min_since_open: f64,
-    max_since_min: f64,
-    ema_bands_lower: f64,
     order_book_bid: f64,
+    order_book_ask: f64,
+    ema_bands_lower: f64,
+    entry_initial_ema_dist: f64,
+    entry_initial_qty_pct: f64,
+    entry_initial_timeout_sec: f64,
+    wallet_exposure_limit: f64,
+    seconds_pending: f64,
 ) -> (f64, f64, String) {
     let exchange_params = ExchangeParams {
         qty_step,
@@ -431,12 +1111,13 @@ pub fn calc_next_entry_long_py(
         min_qty,
         min_cost,
         c_mult,
+        ..Default::default()
     };
     let state_params = StateParams {
         balance,
         order_book: OrderBook {
             bid: order_book_bid,
-            ..Default::default()
+            ask: order_book_ask,
         },
         ema_bands: EMABands {
             lower: ema_bands_lower,
@@ -445,62 +1126,75 @@ pub fn calc_next_entry_long_py(
         ..Default::default()
     };
     let bot_params = BotParams {
-        entry_grid_double_down_factor,
-        entry_grid_spacing_weight,
-        entry_grid_spacing_pct,
         entry_initial_ema_dist,
         entry_initial_qty_pct,
-        entry_trailing_grid_ratio,
-        entry_trailing_retracement_pct,
-        entry_trailing_threshold_pct,
+        entry_initial_timeout_sec,
         wallet_exposure_limit,
         ..Default::default()
     };
-    let position = Position {
-        size: position_size,
-        price: position_price,
-    };
-    let trailing_price_bundle = TrailingPriceBundle {
-        min_since_open: min_since_open,
-        max_since_min: max_since_min,
-        ..Default::default()
-    };
-    let next_entry = calc_next_entry_long(
+    let order = calc_initial_entry_long_with_timeout(
         &exchange_params,
         &state_params,
         &bot_params,
-        &position,
-        &trailing_price_bundle,
+        seconds_pending,
     );
-
-    (
-        next_entry.qty,
-        next_entry.price,
-        next_entry.order_type.to_string(),
-    )
+    (order.qty, order.price, order.order_type.to_string())
 }
 
 #[pyfunction]
-pub fn calc_next_close_long_py(
+#[allow(clippy::too_many_arguments)]
+pub fn calc_initial_entry_short_with_timeout_py(
     qty_step: f64,
     price_step: f64,
     min_qty: f64,
     min_cost: f64,
     c_mult: f64,
-    close_grid_markup_range: f64,
-    close_grid_min_markup: f64,
-    close_grid_qty_pct: f64,
-    close_trailing_grid_ratio: f64,
-    close_trailing_qty_pct: f64,
-    close_trailing_retracement_pct: f64,
-    close_trailing_threshold_pct: f64,
+    balance: f64,
+    order_book_bid: f64,
+    order_book_ask: f64,
+    ema_bands_upper: f64,
+    entry_initial_ema_dist: f64,
+    entry_initial_qty_pct: f64,
+    entry_initial_timeout_sec: f64,
     wallet_exposure_limit: f64,
+    seconds_pending: f64,
+) -> PyResult<(f64, f64, String)> {
+    guard(|| {
+        calc_initial_entry_short_with_timeout_py_impl(
+            qty_step,
+            price_step,
+            min_qty,
+            min_cost,
+            c_mult,
+            balance,
+            order_book_bid,
+            order_book_ask,
+            ema_bands_upper,
+            entry_initial_ema_dist,
+            entry_initial_qty_pct,
+            entry_initial_timeout_sec,
+            wallet_exposure_limit,
+            seconds_pending,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn calc_initial_entry_short_with_timeout_py_impl(
+    qty_step: f64,
+    price_step: f64,
+    min_qty: f64,
+    min_cost: f64,
+    c_mult: f64,
     balance: f64,
-    position_size: f64,
-    position_price: f64,
-    max_since_open: f64,
-    min_since_max: f64,
+    order_book_bid: f64,
     order_book_ask: f64,
+    ema_bands_upper: f64,
+    entry_initial_ema_dist: f64,
+    entry_initial_qty_pct: f64,
+    entry_initial_timeout_sec: f64,
+    wallet_exposure_limit: f64,
+    seconds_pending: f64,
 ) -> (f64, f64, String) {
     let exchange_params = ExchangeParams {
         qty_step,
@@ -508,51 +1202,115 @@ pub fn calc_next_close_long_py(
         min_qty,
         min_cost,
         c_mult,
+        ..Default::default()
     };
     let state_params = StateParams {
         balance,
         order_book: OrderBook {
+            bid: order_book_bid,
             ask: order_book_ask,
+        },
+        ema_bands: EMABands {
+            upper: ema_bands_upper,
             ..Default::default()
         },
         ..Default::default()
     };
     let bot_params = BotParams {
-        close_grid_markup_range,
-        close_grid_min_markup,
-        close_grid_qty_pct,
-        close_trailing_grid_ratio,
-        close_trailing_qty_pct,
-        close_trailing_retracement_pct,
-        close_trailing_threshold_pct,
+        entry_initial_ema_dist,
+        entry_initial_qty_pct,
+        entry_initial_timeout_sec,
         wallet_exposure_limit,
         ..Default::default()
     };
-    let position = Position {
-        size: position_size,
-        price: position_price,
+    let order = calc_initial_entry_short_with_timeout(
+        &exchange_params,
+        &state_params,
+        &bot_params,
+        seconds_pending,
+    );
+    (order.qty, order.price, order.order_type.to_string())
+}
+
+#[pyfunction]
+pub fn calc_twap_entry_long_py(
+    qty_step: f64,
+    order_book_bid: f64,
+    total_qty: f64,
+    n_slices: usize,
+    slice_index: usize,
+) -> PyResult<(f64, f64, String)> {
+    guard(|| {
+        calc_twap_entry_long_py_impl(qty_step, order_book_bid, total_qty, n_slices, slice_index)
+    })
+}
+
+fn calc_twap_entry_long_py_impl(
+    qty_step: f64,
+    order_book_bid: f64,
+    total_qty: f64,
+    n_slices: usize,
+    slice_index: usize,
+) -> (f64, f64, String) {
+    let exchange_params = ExchangeParams {
+        qty_step,
+        ..Default::default()
     };
-    let trailing_price_bundle = TrailingPriceBundle {
-        max_since_open: max_since_open,
-        min_since_max: min_since_max,
+    let order_book = OrderBook {
+        bid: order_book_bid,
         ..Default::default()
     };
-    let next_entry = calc_next_close_long(
+    let order = calc_twap_entry_long(
+        total_qty,
+        n_slices,
+        slice_index,
+        &order_book,
         &exchange_params,
-        &state_params,
-        &bot_params,
-        &position,
-        &trailing_price_bundle,
     );
-    (
-        next_entry.qty,
-        next_entry.price,
-        next_entry.order_type.to_string(),
-    )
+    (order.qty, order.price, order.order_type.to_string())
 }
 
 #[pyfunction]
-pub fn calc_next_entry_short_py(
+pub fn calc_twap_entry_short_py(
+    qty_step: f64,
+    order_book_ask: f64,
+    total_qty: f64,
+    n_slices: usize,
+    slice_index: usize,
+) -> PyResult<(f64, f64, String)> {
+    guard(|| {
+        calc_twap_entry_short_py_impl(qty_step, order_book_ask, total_qty, n_slices, slice_index)
+    })
+}
+
+fn calc_twap_entry_short_py_impl(
+    qty_step: f64,
+    order_book_ask: f64,
+    total_qty: f64,
+    n_slices: usize,
+    slice_index: usize,
+) -> (f64, f64, String) {
+    let exchange_params = ExchangeParams {
+        qty_step,
+        ..Default::default()
+    };
+    let order_book = OrderBook {
+        ask: order_book_ask,
+        ..Default::default()
+    };
+    let order = calc_twap_entry_short(
+        total_qty,
+        n_slices,
+        slice_index,
+        &order_book,
+        &exchange_params,
+    );
+    (order.qty, order.price, order.order_type.to_string())
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+pub fn calc_next_entry_long_py(
     qty_step: f64,
     price_step: f64,
     min_qty: f64,
@@ -570,10 +1328,61 @@ pub fn calc_next_entry_short_py(
     balance: f64,
     position_size: f64,
     position_price: f64,
-    max_since_open: f64,
-    min_since_max: f64,
-    ema_bands_upper: f64,
-    order_book_ask: f64,
+    min_since_open: f64,
+    max_since_min: f64,
+    ema_bands_lower: f64,
+    order_book_bid: f64,
+) -> PyResult<(f64, f64, String)> {
+    guard(|| {
+        calc_next_entry_long_py_impl(
+            qty_step,
+            price_step,
+            min_qty,
+            min_cost,
+            c_mult,
+            entry_grid_double_down_factor,
+            entry_grid_spacing_weight,
+            entry_grid_spacing_pct,
+            entry_initial_ema_dist,
+            entry_initial_qty_pct,
+            entry_trailing_grid_ratio,
+            entry_trailing_retracement_pct,
+            entry_trailing_threshold_pct,
+            wallet_exposure_limit,
+            balance,
+            position_size,
+            position_price,
+            min_since_open,
+            max_since_min,
+            ema_bands_lower,
+            order_book_bid,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn calc_next_entry_long_py_impl(
+    qty_step: f64,
+    price_step: f64,
+    min_qty: f64,
+    min_cost: f64,
+    c_mult: f64,
+    entry_grid_double_down_factor: f64,
+    entry_grid_spacing_weight: f64,
+    entry_grid_spacing_pct: f64,
+    entry_initial_ema_dist: f64,
+    entry_initial_qty_pct: f64,
+    entry_trailing_grid_ratio: f64,
+    entry_trailing_retracement_pct: f64,
+    entry_trailing_threshold_pct: f64,
+    wallet_exposure_limit: f64,
+    balance: f64,
+    position_size: f64,
+    position_price: f64,
+    min_since_open: f64,
+    max_since_min: f64,
+    ema_bands_lower: f64,
+    order_book_bid: f64,
 ) -> (f64, f64, String) {
     let exchange_params = ExchangeParams {
         qty_step,
@@ -581,15 +1390,16 @@ pub fn calc_next_entry_short_py(
         min_qty,
         min_cost,
         c_mult,
+        ..Default::default()
     };
     let state_params = StateParams {
         balance,
         order_book: OrderBook {
-            ask: order_book_ask,
+            bid: order_book_bid,
             ..Default::default()
         },
         ema_bands: EMABands {
-            upper: ema_bands_upper,
+            lower: ema_bands_lower,
             ..Default::default()
         },
         ..Default::default()
@@ -611,11 +1421,11 @@ pub fn calc_next_entry_short_py(
         price: position_price,
     };
     let trailing_price_bundle = TrailingPriceBundle {
-        max_since_open: max_since_open,
-        min_since_max: min_since_max,
+        min_since_open: min_since_open,
+        max_since_min: max_since_min,
         ..Default::default()
     };
-    let next_entry = calc_next_entry_short(
+    let next_entry = calc_next_entry_long(
         &exchange_params,
         &state_params,
         &bot_params,
@@ -631,7 +1441,8 @@ pub fn calc_next_entry_short_py(
 }
 
 #[pyfunction]
-pub fn calc_next_close_short_py(
+#[allow(clippy::too_many_arguments)]
+pub fn calc_next_close_long_py(
     qty_step: f64,
     price_step: f64,
     min_qty: f64,
@@ -648,9 +1459,56 @@ pub fn calc_next_close_short_py(
     balance: f64,
     position_size: f64,
     position_price: f64,
-    min_since_open: f64,
-    max_since_min: f64,
-    order_book_bid: f64,
+    max_since_open: f64,
+    min_since_max: f64,
+    order_book_ask: f64,
+) -> PyResult<(f64, f64, String)> {
+    guard(|| {
+        calc_next_close_long_py_impl(
+            qty_step,
+            price_step,
+            min_qty,
+            min_cost,
+            c_mult,
+            close_grid_markup_range,
+            close_grid_min_markup,
+            close_grid_qty_pct,
+            close_trailing_grid_ratio,
+            close_trailing_qty_pct,
+            close_trailing_retracement_pct,
+            close_trailing_threshold_pct,
+            wallet_exposure_limit,
+            balance,
+            position_size,
+            position_price,
+            max_since_open,
+            min_since_max,
+            order_book_ask,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn calc_next_close_long_py_impl(
+    qty_step: f64,
+    price_step: f64,
+    min_qty: f64,
+    min_cost: f64,
+    c_mult: f64,
+    close_grid_markup_range: f64,
+    close_grid_min_markup: f64,
+    close_grid_qty_pct: f64,
+    close_trailing_grid_ratio: f64,
+    close_trailing_qty_pct: f64,
+    close_trailing_retracement_pct: f64,
+    close_trailing_threshold_pct: f64,
+    wallet_exposure_limit: f64,
+    balance: f64,
+    position_size: f64,
+    position_price: f64,
+    max_since_open: f64,
+    min_since_max: f64,
+    order_book_ask: f64,
 ) -> (f64, f64, String) {
     let exchange_params = ExchangeParams {
         qty_step,
@@ -658,11 +1516,12 @@ pub fn calc_next_close_short_py(
         min_qty,
         min_cost,
         c_mult,
+        ..Default::default()
     };
     let state_params = StateParams {
         balance,
         order_book: OrderBook {
-            bid: order_book_bid,
+            ask: order_book_ask,
             ..Default::default()
         },
         ..Default::default()
@@ -683,11 +1542,11 @@ pub fn calc_next_close_short_py(
         price: position_price,
     };
     let trailing_price_bundle = TrailingPriceBundle {
-        min_since_open: min_since_open,
-        max_since_min: max_since_min,
+        max_since_open: max_since_open,
+        min_since_max: min_since_max,
         ..Default::default()
     };
-    let next_entry = calc_next_close_short(
+    let next_entry = calc_next_close_long(
         &exchange_params,
         &state_params,
         &bot_params,
@@ -702,7 +1561,8 @@ pub fn calc_next_close_short_py(
 }
 
 #[pyfunction]
-pub fn calc_entries_long_py(
+#[allow(clippy::too_many_arguments)]
+pub fn calc_next_entry_short_py(
     qty_step: f64,
     price_step: f64,
     min_qty: f64,
@@ -720,71 +1580,40 @@ pub fn calc_entries_long_py(
     balance: f64,
     position_size: f64,
     position_price: f64,
-    min_since_open: f64,
-    max_since_min: f64,
-    ema_bands_lower: f64,
-    order_book_bid: f64,
-) -> Vec<(f64, f64, String)> {
-    let exchange_params = ExchangeParams {
-        qty_step,
-        price_step,
-        min_qty,
-        min_cost,
-        c_mult,
-    };
-
-    let state_params = StateParams {
-        balance,
-        order_book: OrderBook {
-            bid: order_book_bid,
-            ..Default::default()
-        },
-        ema_bands: EMABands {
-            lower: ema_bands_lower,
-            ..Default::default()
-        },
-        ..Default::default()
-    };
-
-    let bot_params = BotParams {
-        entry_grid_double_down_factor,
-        entry_grid_spacing_weight,
-        entry_grid_spacing_pct,
-        entry_initial_ema_dist,
-        entry_initial_qty_pct,
-        entry_trailing_grid_ratio,
-        entry_trailing_retracement_pct,
-        entry_trailing_threshold_pct,
-        wallet_exposure_limit,
-        ..Default::default()
-    };
-
-    let position = Position {
-        size: position_size,
-        price: position_price,
-    };
-    let trailing_price_bundle = TrailingPriceBundle {
-        min_since_open: min_since_open,
-        max_since_min: max_since_min,
-        ..Default::default()
-    };
-    let entries = calc_entries_long(
-        &exchange_params,
-        &state_params,
-        &bot_params,
-        &position,
-        &trailing_price_bundle,
-    );
-
-    // Convert entries to Python-compatible format
-    entries
-        .into_iter()
-        .map(|order| (order.qty, order.price, order.order_type.to_string()))
-        .collect()
+    max_since_open: f64,
+    min_since_max: f64,
+    ema_bands_upper: f64,
+    order_book_ask: f64,
+) -> PyResult<(f64, f64, String)> {
+    guard(|| {
+        calc_next_entry_short_py_impl(
+            qty_step,
+            price_step,
+            min_qty,
+            min_cost,
+            c_mult,
+            entry_grid_double_down_factor,
+            entry_grid_spacing_weight,
+            entry_grid_spacing_pct,
+            entry_initial_ema_dist,
+            entry_initial_qty_pct,
+            entry_trailing_grid_ratio,
+            entry_trailing_retracement_pct,
+            entry_trailing_threshold_pct,
+            wallet_exposure_limit,
+            balance,
+            position_size,
+            position_price,
+            max_since_open,
+            min_since_max,
+            ema_bands_upper,
+            order_book_ask,
+        )
+    })
 }
 
-#[pyfunction]
-pub fn calc_entries_short_py(
+#[allow(clippy::too_many_arguments)]
+fn calc_next_entry_short_py_impl(
     qty_step: f64,
     price_step: f64,
     min_qty: f64,
@@ -806,15 +1635,15 @@ pub fn calc_entries_short_py(
     min_since_max: f64,
     ema_bands_upper: f64,
     order_book_ask: f64,
-) -> Vec<(f64, f64, String)> {
+) -> (f64, f64, String) {
     let exchange_params = ExchangeParams {
         qty_step,
         price_step,
         min_qty,
         min_cost,
         c_mult,
+        ..Default::default()
     };
-
     let state_params = StateParams {
         balance,
         order_book: OrderBook {
@@ -827,7 +1656,6 @@ pub fn calc_entries_short_py(
         },
         ..Default::default()
     };
-
     let bot_params = BotParams {
         entry_grid_double_down_factor,
         entry_grid_spacing_weight,
@@ -840,7 +1668,6 @@ pub fn calc_entries_short_py(
         wallet_exposure_limit,
         ..Default::default()
     };
-
     let position = Position {
         size: position_size,
         price: position_price,
@@ -850,7 +1677,7 @@ pub fn calc_entries_short_py(
         min_since_max: min_since_max,
         ..Default::default()
     };
-    let entries = calc_entries_short(
+    let next_entry = calc_next_entry_short(
         &exchange_params,
         &state_params,
         &bot_params,
@@ -858,15 +1685,16 @@ pub fn calc_entries_short_py(
         &trailing_price_bundle,
     );
 
-    // Convert entries to Python-compatible format
-    entries
-        .into_iter()
-        .map(|order| (order.qty, order.price, order.order_type.to_string()))
-        .collect()
+    (
+        next_entry.qty,
+        next_entry.price,
+        next_entry.order_type.to_string(),
+    )
 }
 
 #[pyfunction]
-pub fn calc_closes_long_py(
+#[allow(clippy::too_many_arguments)]
+pub fn calc_next_close_short_py(
     qty_step: f64,
     price_step: f64,
     min_qty: f64,
@@ -883,27 +1711,73 @@ pub fn calc_closes_long_py(
     balance: f64,
     position_size: f64,
     position_price: f64,
-    max_since_open: f64,
-    min_since_max: f64,
-    order_book_ask: f64,
-) -> Vec<(f64, f64, String)> {
+    min_since_open: f64,
+    max_since_min: f64,
+    order_book_bid: f64,
+) -> PyResult<(f64, f64, String)> {
+    guard(|| {
+        calc_next_close_short_py_impl(
+            qty_step,
+            price_step,
+            min_qty,
+            min_cost,
+            c_mult,
+            close_grid_markup_range,
+            close_grid_min_markup,
+            close_grid_qty_pct,
+            close_trailing_grid_ratio,
+            close_trailing_qty_pct,
+            close_trailing_retracement_pct,
+            close_trailing_threshold_pct,
+            wallet_exposure_limit,
+            balance,
+            position_size,
+            position_price,
+            min_since_open,
+            max_since_min,
+            order_book_bid,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn calc_next_close_short_py_impl(
+    qty_step: f64,
+    price_step: f64,
+    min_qty: f64,
+    min_cost: f64,
+    c_mult: f64,
+    close_grid_markup_range: f64,
+    close_grid_min_markup: f64,
+    close_grid_qty_pct: f64,
+    close_trailing_grid_ratio: f64,
+    close_trailing_qty_pct: f64,
+    close_trailing_retracement_pct: f64,
+    close_trailing_threshold_pct: f64,
+    wallet_exposure_limit: f64,
+    balance: f64,
+    position_size: f64,
+    position_price: f64,
+    min_since_open: f64,
+    max_since_min: f64,
+    order_book_bid: f64,
+) -> (f64, f64, String) {
     let exchange_params = ExchangeParams {
         qty_step,
         price_step,
         min_qty,
         min_cost,
         c_mult,
+        ..Default::default()
     };
-
     let state_params = StateParams {
         balance,
         order_book: OrderBook {
-            ask: order_book_ask,
+            bid: order_book_bid,
             ..Default::default()
         },
         ..Default::default()
     };
-
     let bot_params = BotParams {
         close_grid_markup_range,
         close_grid_min_markup,
@@ -915,51 +1789,103 @@ pub fn calc_closes_long_py(
         wallet_exposure_limit,
         ..Default::default()
     };
-
     let position = Position {
         size: position_size,
         price: position_price,
     };
     let trailing_price_bundle = TrailingPriceBundle {
-        max_since_open: max_since_open,
-        min_since_max: min_since_max,
+        min_since_open: min_since_open,
+        max_since_min: max_since_min,
         ..Default::default()
     };
-    let closes = calc_closes_long(
+    let next_entry = calc_next_close_short(
         &exchange_params,
         &state_params,
         &bot_params,
         &position,
         &trailing_price_bundle,
     );
-
-    // Convert closes to Python-compatible format
-    closes
-        .into_iter()
-        .map(|order| (order.qty, order.price, order.order_type.to_string()))
-        .collect()
+    (
+        next_entry.qty,
+        next_entry.price,
+        next_entry.order_type.to_string(),
+    )
 }
 
 #[pyfunction]
-pub fn calc_closes_short_py(
+#[allow(clippy::too_many_arguments)]
+pub fn calc_entries_long_py(
     qty_step: f64,
     price_step: f64,
     min_qty: f64,
     min_cost: f64,
     c_mult: f64,
-    close_grid_markup_range: f64,
-    close_grid_min_markup: f64,
-    close_grid_qty_pct: f64,
-    close_trailing_grid_ratio: f64,
-    close_trailing_qty_pct: f64,
-    close_trailing_retracement_pct: f64,
-    close_trailing_threshold_pct: f64,
+    entry_grid_double_down_factor: f64,
+    entry_grid_spacing_weight: f64,
+    entry_grid_spacing_pct: f64,
+    entry_initial_ema_dist: f64,
+    entry_initial_qty_pct: f64,
+    entry_trailing_grid_ratio: f64,
+    entry_trailing_retracement_pct: f64,
+    entry_trailing_threshold_pct: f64,
+    wallet_exposure_limit: f64,
+    balance: f64,
+    position_size: f64,
+    position_price: f64,
+    min_since_open: f64,
+    max_since_min: f64,
+    ema_bands_lower: f64,
+    order_book_bid: f64,
+) -> PyResult<Vec<(f64, f64, String)>> {
+    guard(|| {
+        calc_entries_long_py_impl(
+            qty_step,
+            price_step,
+            min_qty,
+            min_cost,
+            c_mult,
+            entry_grid_double_down_factor,
+            entry_grid_spacing_weight,
+            entry_grid_spacing_pct,
+            entry_initial_ema_dist,
+            entry_initial_qty_pct,
+            entry_trailing_grid_ratio,
+            entry_trailing_retracement_pct,
+            entry_trailing_threshold_pct,
+            wallet_exposure_limit,
+            balance,
+            position_size,
+            position_price,
+            min_since_open,
+            max_since_min,
+            ema_bands_lower,
+            order_book_bid,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn calc_entries_long_py_impl(
+    qty_step: f64,
+    price_step: f64,
+    min_qty: f64,
+    min_cost: f64,
+    c_mult: f64,
+    entry_grid_double_down_factor: f64,
+    entry_grid_spacing_weight: f64,
+    entry_grid_spacing_pct: f64,
+    entry_initial_ema_dist: f64,
+    entry_initial_qty_pct: f64,
+    entry_trailing_grid_ratio: f64,
+    entry_trailing_retracement_pct: f64,
+    entry_trailing_threshold_pct: f64,
     wallet_exposure_limit: f64,
     balance: f64,
     position_size: f64,
     position_price: f64,
     min_since_open: f64,
     max_since_min: f64,
+    ema_bands_lower: f64,
     order_book_bid: f64,
 ) -> Vec<(f64, f64, String)> {
     let exchange_params = ExchangeParams {
@@ -968,6 +1894,7 @@ pub fn calc_closes_short_py(
         min_qty,
         min_cost,
         c_mult,
+        ..Default::default()
     };
 
     let state_params = StateParams {
@@ -976,20 +1903,26 @@ pub fn calc_closes_short_py(
             bid: order_book_bid,
             ..Default::default()
         },
+        ema_bands: EMABands {
+            lower: ema_bands_lower,
+            ..Default::default()
+        },
         ..Default::default()
     };
 
     let bot_params = BotParams {
-        close_grid_markup_range,
-        close_grid_min_markup,
-        close_grid_qty_pct,
-        close_trailing_grid_ratio,
-        close_trailing_qty_pct,
-        close_trailing_retracement_pct,
-        close_trailing_threshold_pct,
+        entry_grid_double_down_factor,
+        entry_grid_spacing_weight,
+        entry_grid_spacing_pct,
+        entry_initial_ema_dist,
+        entry_initial_qty_pct,
+        entry_trailing_grid_ratio,
+        entry_trailing_retracement_pct,
+        entry_trailing_threshold_pct,
         wallet_exposure_limit,
         ..Default::default()
     };
+
     let position = Position {
         size: position_size,
         price: position_price,
@@ -999,7 +1932,7 @@ pub fn calc_closes_short_py(
         max_since_min: max_since_min,
         ..Default::default()
     };
-    let closes = calc_closes_short(
+    let entries = calc_entries_long(
         &exchange_params,
         &state_params,
         &bot_params,
@@ -1007,9 +1940,1767 @@ pub fn calc_closes_short_py(
         &trailing_price_bundle,
     );
 
-    // Convert closes to Python-compatible format
-    closes
+    // Convert entries to Python-compatible format
+    entries
+        .into_iter()
+        .map(|order| (order.qty, order.price, order.order_type.to_string()))
+        .collect()
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+pub fn calc_entries_short_py(
+    qty_step: f64,
+    price_step: f64,
+    min_qty: f64,
+    min_cost: f64,
+    c_mult: f64,
+    entry_grid_double_down_factor: f64,
+    entry_grid_spacing_weight: f64,
+    entry_grid_spacing_pct: f64,
+    entry_initial_ema_dist: f64,
+    entry_initial_qty_pct: f64,
+    entry_trailing_grid_ratio: f64,
+    entry_trailing_retracement_pct: f64,
+    entry_trailing_threshold_pct: f64,
+    wallet_exposure_limit: f64,
+    balance: f64,
+    position_size: f64,
+    position_price: f64,
+    max_since_open: f64,
+    min_since_max: f64,
+    ema_bands_upper: f64,
+    order_book_ask: f64,
+) -> PyResult<Vec<(f64, f64, String)>> {
+    guard(|| {
+        calc_entries_short_py_impl(
+            qty_step,
+            price_step,
+            min_qty,
+            min_cost,
+            c_mult,
+            entry_grid_double_down_factor,
+            entry_grid_spacing_weight,
+            entry_grid_spacing_pct,
+            entry_initial_ema_dist,
+            entry_initial_qty_pct,
+            entry_trailing_grid_ratio,
+            entry_trailing_retracement_pct,
+            entry_trailing_threshold_pct,
+            wallet_exposure_limit,
+            balance,
+            position_size,
+            position_price,
+            max_since_open,
+            min_since_max,
+            ema_bands_upper,
+            order_book_ask,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn calc_entries_short_py_impl(
+    qty_step: f64,
+    price_step: f64,
+    min_qty: f64,
+    min_cost: f64,
+    c_mult: f64,
+    entry_grid_double_down_factor: f64,
+    entry_grid_spacing_weight: f64,
+    entry_grid_spacing_pct: f64,
+    entry_initial_ema_dist: f64,
+    entry_initial_qty_pct: f64,
+    entry_trailing_grid_ratio: f64,
+    entry_trailing_retracement_pct: f64,
+    entry_trailing_threshold_pct: f64,
+    wallet_exposure_limit: f64,
+    balance: f64,
+    position_size: f64,
+    position_price: f64,
+    max_since_open: f64,
+    min_since_max: f64,
+    ema_bands_upper: f64,
+    order_book_ask: f64,
+) -> Vec<(f64, f64, String)> {
+    let exchange_params = ExchangeParams {
+        qty_step,
+        price_step,
+        min_qty,
+        min_cost,
+        c_mult,
+        ..Default::default()
+    };
+
+    let state_params = StateParams {
+        balance,
+        order_book: OrderBook {
+            ask: order_book_ask,
+            ..Default::default()
+        },
+        ema_bands: EMABands {
+            upper: ema_bands_upper,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let bot_params = BotParams {
+        entry_grid_double_down_factor,
+        entry_grid_spacing_weight,
+        entry_grid_spacing_pct,
+        entry_initial_ema_dist,
+        entry_initial_qty_pct,
+        entry_trailing_grid_ratio,
+        entry_trailing_retracement_pct,
+        entry_trailing_threshold_pct,
+        wallet_exposure_limit,
+        ..Default::default()
+    };
+
+    let position = Position {
+        size: position_size,
+        price: position_price,
+    };
+    let trailing_price_bundle = TrailingPriceBundle {
+        max_since_open: max_since_open,
+        min_since_max: min_since_max,
+        ..Default::default()
+    };
+    let entries = calc_entries_short(
+        &exchange_params,
+        &state_params,
+        &bot_params,
+        &position,
+        &trailing_price_bundle,
+    );
+
+    // Convert entries to Python-compatible format
+    entries
         .into_iter()
         .map(|order| (order.qty, order.price, order.order_type.to_string()))
         .collect()
 }
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+pub fn calc_closes_long_py(
+    qty_step: f64,
+    price_step: f64,
+    min_qty: f64,
+    min_cost: f64,
+    c_mult: f64,
+    close_grid_markup_range: f64,
+    close_grid_min_markup: f64,
+    close_grid_qty_pct: f64,
+    close_trailing_grid_ratio: f64,
+    close_trailing_qty_pct: f64,
+    close_trailing_retracement_pct: f64,
+    close_trailing_threshold_pct: f64,
+    wallet_exposure_limit: f64,
+    balance: f64,
+    position_size: f64,
+    position_price: f64,
+    max_since_open: f64,
+    min_since_max: f64,
+    order_book_ask: f64,
+) -> PyResult<Vec<(f64, f64, String)>> {
+    guard(|| {
+        calc_closes_long_py_impl(
+            qty_step,
+            price_step,
+            min_qty,
+            min_cost,
+            c_mult,
+            close_grid_markup_range,
+            close_grid_min_markup,
+            close_grid_qty_pct,
+            close_trailing_grid_ratio,
+            close_trailing_qty_pct,
+            close_trailing_retracement_pct,
+            close_trailing_threshold_pct,
+            wallet_exposure_limit,
+            balance,
+            position_size,
+            position_price,
+            max_since_open,
+            min_since_max,
+            order_book_ask,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn calc_closes_long_py_impl(
+    qty_step: f64,
+    price_step: f64,
+    min_qty: f64,
+    min_cost: f64,
+    c_mult: f64,
+    close_grid_markup_range: f64,
+    close_grid_min_markup: f64,
+    close_grid_qty_pct: f64,
+    close_trailing_grid_ratio: f64,
+    close_trailing_qty_pct: f64,
+    close_trailing_retracement_pct: f64,
+    close_trailing_threshold_pct: f64,
+    wallet_exposure_limit: f64,
+    balance: f64,
+    position_size: f64,
+    position_price: f64,
+    max_since_open: f64,
+    min_since_max: f64,
+    order_book_ask: f64,
+) -> Vec<(f64, f64, String)> {
+    let exchange_params = ExchangeParams {
+        qty_step,
+        price_step,
+        min_qty,
+        min_cost,
+        c_mult,
+        ..Default::default()
+    };
+
+    let state_params = StateParams {
+        balance,
+        order_book: OrderBook {
+            ask: order_book_ask,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let bot_params = BotParams {
+        close_grid_markup_range,
+        close_grid_min_markup,
+        close_grid_qty_pct,
+        close_trailing_grid_ratio,
+        close_trailing_qty_pct,
+        close_trailing_retracement_pct,
+        close_trailing_threshold_pct,
+        wallet_exposure_limit,
+        ..Default::default()
+    };
+
+    let position = Position {
+        size: position_size,
+        price: position_price,
+    };
+    let trailing_price_bundle = TrailingPriceBundle {
+        max_since_open: max_since_open,
+        min_since_max: min_since_max,
+        ..Default::default()
+    };
+    let closes = calc_closes_long(
+        &exchange_params,
+        &state_params,
+        &bot_params,
+        &position,
+        &trailing_price_bundle,
+    );
+
+    // Convert closes to Python-compatible format
+    closes
+        .into_iter()
+        .map(|order| (order.qty, order.price, order.order_type.to_string()))
+        .collect()
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+pub fn calc_closes_short_py(
+    qty_step: f64,
+    price_step: f64,
+    min_qty: f64,
+    min_cost: f64,
+    c_mult: f64,
+    close_grid_markup_range: f64,
+    close_grid_min_markup: f64,
+    close_grid_qty_pct: f64,
+    close_trailing_grid_ratio: f64,
+    close_trailing_qty_pct: f64,
+    close_trailing_retracement_pct: f64,
+    close_trailing_threshold_pct: f64,
+    wallet_exposure_limit: f64,
+    balance: f64,
+    position_size: f64,
+    position_price: f64,
+    min_since_open: f64,
+    max_since_min: f64,
+    order_book_bid: f64,
+) -> PyResult<Vec<(f64, f64, String)>> {
+    guard(|| {
+        calc_closes_short_py_impl(
+            qty_step,
+            price_step,
+            min_qty,
+            min_cost,
+            c_mult,
+            close_grid_markup_range,
+            close_grid_min_markup,
+            close_grid_qty_pct,
+            close_trailing_grid_ratio,
+            close_trailing_qty_pct,
+            close_trailing_retracement_pct,
+            close_trailing_threshold_pct,
+            wallet_exposure_limit,
+            balance,
+            position_size,
+            position_price,
+            min_since_open,
+            max_since_min,
+            order_book_bid,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn calc_closes_short_py_impl(
+    qty_step: f64,
+    price_step: f64,
+    min_qty: f64,
+    min_cost: f64,
+    c_mult: f64,
+    close_grid_markup_range: f64,
+    close_grid_min_markup: f64,
+    close_grid_qty_pct: f64,
+    close_trailing_grid_ratio: f64,
+    close_trailing_qty_pct: f64,
+    close_trailing_retracement_pct: f64,
+    close_trailing_threshold_pct: f64,
+    wallet_exposure_limit: f64,
+    balance: f64,
+    position_size: f64,
+    position_price: f64,
+    min_since_open: f64,
+    max_since_min: f64,
+    order_book_bid: f64,
+) -> Vec<(f64, f64, String)> {
+    let exchange_params = ExchangeParams {
+        qty_step,
+        price_step,
+        min_qty,
+        min_cost,
+        c_mult,
+        ..Default::default()
+    };
+
+    let state_params = StateParams {
+        balance,
+        order_book: OrderBook {
+            bid: order_book_bid,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let bot_params = BotParams {
+        close_grid_markup_range,
+        close_grid_min_markup,
+        close_grid_qty_pct,
+        close_trailing_grid_ratio,
+        close_trailing_qty_pct,
+        close_trailing_retracement_pct,
+        close_trailing_threshold_pct,
+        wallet_exposure_limit,
+        ..Default::default()
+    };
+    let position = Position {
+        size: position_size,
+        price: position_price,
+    };
+    let trailing_price_bundle = TrailingPriceBundle {
+        min_since_open: min_since_open,
+        max_since_min: max_since_min,
+        ..Default::default()
+    };
+    let closes = calc_closes_short(
+        &exchange_params,
+        &state_params,
+        &bot_params,
+        &position,
+        &trailing_price_bundle,
+    );
+
+    // Convert closes to Python-compatible format
+    closes
+        .into_iter()
+        .map(|order| (order.qty, order.price, order.order_type.to_string()))
+        .collect()
+}
+
+#[pyfunction]
+pub fn calc_min_entry_qty_py(
+    entry_price: f64,
+    qty_step: f64,
+    min_qty: f64,
+    min_cost: f64,
+    c_mult: f64,
+) -> PyResult<f64> {
+    guard(|| calc_min_entry_qty_py_impl(entry_price, qty_step, min_qty, min_cost, c_mult))
+}
+
+fn calc_min_entry_qty_py_impl(
+    entry_price: f64,
+    qty_step: f64,
+    min_qty: f64,
+    min_cost: f64,
+    c_mult: f64,
+) -> f64 {
+    let exchange_params = ExchangeParams {
+        qty_step,
+        min_qty,
+        min_cost,
+        c_mult,
+        ..Default::default()
+    };
+    calc_min_entry_qty(entry_price, &exchange_params)
+}
+
+// Dict variants of the wrappers above, returning Order objects. exchange_params and bot_params
+// take the same keys as run_backtest; state takes the keys read by state_from_dict.
+
+#[pyfunction]
+pub fn calc_next_entry_long_dict_py(
+    exchange_params: &Bound<'_, PyDict>,
+    bot_params: &Bound<'_, PyDict>,
+    state: &Bound<'_, PyDict>,
+) -> PyResult<PyOrder> {
+    guard_result(|| calc_next_entry_long_dict_py_impl(exchange_params, bot_params, state))
+}
+
+fn calc_next_entry_long_dict_py_impl(
+    exchange_params: &Bound<'_, PyDict>,
+    bot_params: &Bound<'_, PyDict>,
+    state: &Bound<'_, PyDict>,
+) -> PyResult<PyOrder> {
+    let (state_params, position, trailing_price_bundle) = state_from_dict(state);
+    Ok(calc_next_entry_long(
+        &ExchangeParams::from_py_dict(exchange_params)?.0,
+        &state_params,
+        &BotParams::from_py_dict(bot_params)?.0,
+        &position,
+        &trailing_price_bundle,
+    )
+    .into())
+}
+
+/// Same as calc_next_entry_long_dict_py, also returning why the order is empty.
+#[pyfunction]
+pub fn calc_next_entry_long_explained_dict_py(
+    exchange_params: &Bound<'_, PyDict>,
+    bot_params: &Bound<'_, PyDict>,
+    state: &Bound<'_, PyDict>,
+) -> PyResult<(PyOrder, EmptyReason)> {
+    guard_result(|| calc_next_entry_long_explained_dict_py_impl(exchange_params, bot_params, state))
+}
+
+fn calc_next_entry_long_explained_dict_py_impl(
+    exchange_params: &Bound<'_, PyDict>,
+    bot_params: &Bound<'_, PyDict>,
+    state: &Bound<'_, PyDict>,
+) -> PyResult<(PyOrder, EmptyReason)> {
+    let (state_params, position, trailing_price_bundle) = state_from_dict(state);
+    let (order, reason) = calc_next_entry_long_explained(
+        &ExchangeParams::from_py_dict(exchange_params)?.0,
+        &state_params,
+        &BotParams::from_py_dict(bot_params)?.0,
+        &position,
+        &trailing_price_bundle,
+    );
+    Ok((order.into(), reason))
+}
+
+#[pyfunction]
+pub fn calc_next_entry_short_dict_py(
+    exchange_params: &Bound<'_, PyDict>,
+    bot_params: &Bound<'_, PyDict>,
+    state: &Bound<'_, PyDict>,
+) -> PyResult<PyOrder> {
+    guard_result(|| calc_next_entry_short_dict_py_impl(exchange_params, bot_params, state))
+}
+
+fn calc_next_entry_short_dict_py_impl(
+    exchange_params: &Bound<'_, PyDict>,
+    bot_params: &Bound<'_, PyDict>,
+    state: &Bound<'_, PyDict>,
+) -> PyResult<PyOrder> {
+    let (state_params, position, trailing_price_bundle) = state_from_dict(state);
+    Ok(calc_next_entry_short(
+        &ExchangeParams::from_py_dict(exchange_params)?.0,
+        &state_params,
+        &BotParams::from_py_dict(bot_params)?.0,
+        &position,
+        &trailing_price_bundle,
+    )
+    .into())
+}
+
+#[pyfunction]
+pub fn calc_blended_entries_long_dict_py(
+    exchange_params: &Bound<'_, PyDict>,
+    bot_params: &Bound<'_, PyDict>,
+    state: &Bound<'_, PyDict>,
+) -> PyResult<Vec<PyOrder>> {
+    guard_result(|| calc_blended_entries_long_dict_py_impl(exchange_params, bot_params, state))
+}
+
+fn calc_blended_entries_long_dict_py_impl(
+    exchange_params: &Bound<'_, PyDict>,
+    bot_params: &Bound<'_, PyDict>,
+    state: &Bound<'_, PyDict>,
+) -> PyResult<Vec<PyOrder>> {
+    let (state_params, position, trailing_price_bundle) = state_from_dict(state);
+    Ok(calc_blended_entries_long(
+        &ExchangeParams::from_py_dict(exchange_params)?.0,
+        &state_params,
+        &BotParams::from_py_dict(bot_params)?.0,
+        &position,
+        &trailing_price_bundle,
+    )
+    .into_iter()
+    .map(PyOrder::from)
+    .collect())
+}
+
+#[pyfunction]
+pub fn calc_blended_entries_short_dict_py(
+    exchange_params: &Bound<'_, PyDict>,
+    bot_params: &Bound<'_, PyDict>,
+    state: &Bound<'_, PyDict>,
+) -> PyResult<Vec<PyOrder>> {
+    guard_result(|| calc_blended_entries_short_dict_py_impl(exchange_params, bot_params, state))
+}
+
+fn calc_blended_entries_short_dict_py_impl(
+    exchange_params: &Bound<'_, PyDict>,
+    bot_params: &Bound<'_, PyDict>,
+    state: &Bound<'_, PyDict>,
+) -> PyResult<Vec<PyOrder>> {
+    let (state_params, position, trailing_price_bundle) = state_from_dict(state);
+    Ok(calc_blended_entries_short(
+        &ExchangeParams::from_py_dict(exchange_params)?.0,
+        &state_params,
+        &BotParams::from_py_dict(bot_params)?.0,
+        &position,
+        &trailing_price_bundle,
+    )
+    .into_iter()
+    .map(PyOrder::from)
+    .collect())
+}
+
+#[pyfunction]
+pub fn calc_closes_long_dict_py(
+    exchange_params: &Bound<'_, PyDict>,
+    bot_params: &Bound<'_, PyDict>,
+    state: &Bound<'_, PyDict>,
+) -> PyResult<Vec<PyOrder>> {
+    guard_result(|| calc_closes_long_dict_py_impl(exchange_params, bot_params, state))
+}
+
+fn calc_closes_long_dict_py_impl(
+    exchange_params: &Bound<'_, PyDict>,
+    bot_params: &Bound<'_, PyDict>,
+    state: &Bound<'_, PyDict>,
+) -> PyResult<Vec<PyOrder>> {
+    let (state_params, position, trailing_price_bundle) = state_from_dict(state);
+    Ok(calc_closes_long(
+        &ExchangeParams::from_py_dict(exchange_params)?.0,
+        &state_params,
+        &BotParams::from_py_dict(bot_params)?.0,
+        &position,
+        &trailing_price_bundle,
+    )
+    .into_iter()
+    .map(PyOrder::from)
+    .collect())
+}
+
+#[pyfunction]
+pub fn calc_closes_short_dict_py(
+    exchange_params: &Bound<'_, PyDict>,
+    bot_params: &Bound<'_, PyDict>,
+    state: &Bound<'_, PyDict>,
+) -> PyResult<Vec<PyOrder>> {
+    guard_result(|| calc_closes_short_dict_py_impl(exchange_params, bot_params, state))
+}
+
+fn calc_closes_short_dict_py_impl(
+    exchange_params: &Bound<'_, PyDict>,
+    bot_params: &Bound<'_, PyDict>,
+    state: &Bound<'_, PyDict>,
+) -> PyResult<Vec<PyOrder>> {
+    let (state_params, position, trailing_price_bundle) = state_from_dict(state);
+    Ok(calc_closes_short(
+        &ExchangeParams::from_py_dict(exchange_params)?.0,
+        &state_params,
+        &BotParams::from_py_dict(bot_params)?.0,
+        &position,
+        &trailing_price_bundle,
+    )
+    .into_iter()
+    .map(PyOrder::from)
+    .collect())
+}
+
+#[pyfunction]
+pub fn calc_next_grid_trigger_price_long_dict_py(
+    exchange_params: &Bound<'_, PyDict>,
+    bot_params: &Bound<'_, PyDict>,
+    state: &Bound<'_, PyDict>,
+) -> PyResult<Option<f64>> {
+    guard_result(|| {
+        calc_next_grid_trigger_price_long_dict_py_impl(exchange_params, bot_params, state)
+    })
+}
+
+fn calc_next_grid_trigger_price_long_dict_py_impl(
+    exchange_params: &Bound<'_, PyDict>,
+    bot_params: &Bound<'_, PyDict>,
+    state: &Bound<'_, PyDict>,
+) -> PyResult<Option<f64>> {
+    let (state_params, position, _) = state_from_dict(state);
+    Ok(calc_next_grid_trigger_price_long(
+        &position,
+        state_params.balance,
+        state_params.volatility,
+        &BotParams::from_py_dict(bot_params)?.0,
+        &ExchangeParams::from_py_dict(exchange_params)?.0,
+    ))
+}
+
+#[pyfunction]
+pub fn calc_next_grid_trigger_price_short_dict_py(
+    exchange_params: &Bound<'_, PyDict>,
+    bot_params: &Bound<'_, PyDict>,
+    state: &Bound<'_, PyDict>,
+) -> PyResult<Option<f64>> {
+    guard_result(|| {
+        calc_next_grid_trigger_price_short_dict_py_impl(exchange_params, bot_params, state)
+    })
+}
+
+fn calc_next_grid_trigger_price_short_dict_py_impl(
+    exchange_params: &Bound<'_, PyDict>,
+    bot_params: &Bound<'_, PyDict>,
+    state: &Bound<'_, PyDict>,
+) -> PyResult<Option<f64>> {
+    let (state_params, position, _) = state_from_dict(state);
+    Ok(calc_next_grid_trigger_price_short(
+        &position,
+        state_params.balance,
+        state_params.volatility,
+        &BotParams::from_py_dict(bot_params)?.0,
+        &ExchangeParams::from_py_dict(exchange_params)?.0,
+    ))
+}
+
+/// qtys, prices and order type codes, one per row.
+type BatchOrders = (Py<PyArray1<f64>>, Py<PyArray1<f64>>, Py<PyArray1<u8>>);
+
+// Batch variant for many symbols at once. Each argument maps keys to 1d float64 arrays with one
+// row per symbol; keys are those of the dict variants, missing keys take their defaults and
+// unknown keys are ignored. entry_grid_ema_floor is true if nonzero, a NaN equity or
+// entry_reentry_ema_dist is None, entry_trailing_mode is 0 threshold_drawdown or
+// 1 trailing_stop and entry_sizing_basis is 0 balance or 1 equity. Order types are returned
+// as codes, the values of the OrderType enum.
+
+#[pyfunction]
+pub fn calc_entries_batch(
+    py: Python,
+    exchange_params_arrays: &Bound<'_, PyDict>,
+    state_arrays: &Bound<'_, PyDict>,
+    bot_params_arrays: &Bound<'_, PyDict>,
+    pside: usize,
+) -> PyResult<BatchOrders> {
+    guard_result(|| {
+        calc_entries_batch_impl(
+            py,
+            exchange_params_arrays,
+            state_arrays,
+            bot_params_arrays,
+            pside,
+        )
+    })
+}
+
+fn calc_entries_batch_impl(
+    py: Python,
+    exchange_params_arrays: &Bound<'_, PyDict>,
+    state_arrays: &Bound<'_, PyDict>,
+    bot_params_arrays: &Bound<'_, PyDict>,
+    pside: usize,
+) -> PyResult<BatchOrders> {
+    let calc_next_entry = match pside {
+        LONG => calc_next_entry_long,
+        SHORT => calc_next_entry_short,
+        _ => return Err(PyValueError::new_err(format!("Invalid pside {}", pside))),
+    };
+    let exchange_params_columns = batch_columns(exchange_params_arrays)?;
+    let state_columns = batch_columns(state_arrays)?;
+    let bot_params_columns = batch_columns(bot_params_arrays)?;
+    let mut n_rows = None;
+    for (key, column) in exchange_params_columns
+        .iter()
+        .chain(&state_columns)
+        .chain(&bot_params_columns)
+    {
+        let len = column.as_slice()?.len();
+        match n_rows {
+            Some(n_rows) if n_rows != len => {
+                return Err(PyValueError::new_err(format!(
+                    "Array '{}' has {} rows, expected {}",
+                    key, len, n_rows
+                )))
+            }
+            _ => n_rows = Some(len),
+        }
+    }
+    let n_rows = n_rows.unwrap_or(0);
+    let exchange_params = batch_rows(&exchange_params_columns, n_rows, exchange_params_setter)?;
+    let states = batch_rows(&state_columns, n_rows, state_setter)?;
+    let bot_params = batch_rows(&bot_params_columns, n_rows, bot_params_setter)?;
+
+    let mut qtys = Array1::zeros(n_rows);
+    let mut prices = Array1::zeros(n_rows);
+    let mut type_codes = Array1::zeros(n_rows);
+    for i in 0..n_rows {
+        let (state_params, position, trailing_price_bundle) = &states[i];
+        let entry = calc_next_entry(
+            &exchange_params[i],
+            state_params,
+            &bot_params[i],
+            position,
+            trailing_price_bundle,
+        );
+        qtys[i] = entry.qty;
+        prices[i] = entry.price;
+        type_codes[i] = entry.order_type.code();
+    }
+    Ok((
+        qtys.into_pyarray_bound(py).unbind(),
+        prices.into_pyarray_bound(py).unbind(),
+        type_codes.into_pyarray_bound(py).unbind(),
+    ))
+}
+
+#[pymethods]
+impl OrderType {
+    /// snake_case name, as in fills and str().
+    #[getter]
+    fn name(&self) -> String {
+        self.to_string()
+    }
+
+    /// Integer code, as returned by the batch APIs.
+    #[getter]
+    fn value(&self) -> u8 {
+        self.code()
+    }
+
+    #[getter(is_entry)]
+    fn py_is_entry(&self) -> bool {
+        self.is_entry()
+    }
+
+    #[getter(is_close)]
+    fn py_is_close(&self) -> bool {
+        self.is_close()
+    }
+
+    #[getter]
+    fn is_long(&self) -> bool {
+        self.side() == Some(Side::Long)
+    }
+
+    #[getter]
+    fn is_short(&self) -> bool {
+        self.side() == Some(Side::Short)
+    }
+
+    /// None for Empty.
+    #[getter(side)]
+    fn py_side(&self) -> Option<Side> {
+        self.side()
+    }
+
+    #[staticmethod]
+    fn from_name(name: &str) -> PyResult<Self> {
+        name.parse().map_err(PyValueError::new_err)
+    }
+
+    #[staticmethod]
+    fn from_value(value: u8) -> PyResult<Self> {
+        OrderType::from_code(value)
+            .ok_or_else(|| PyValueError::new_err(format!("Invalid order type code {}", value)))
+    }
+
+    /// All members in value order.
+    #[staticmethod]
+    fn members() -> Vec<Self> {
+        ORDER_TYPES.to_vec()
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+
+    fn __hash__(&self) -> u64 {
+        self.code() as u64
+    }
+}
+
+#[pymethods]
+impl Side {
+    /// "long" or "short".
+    #[getter]
+    fn name(&self) -> String {
+        self.to_string()
+    }
+
+    /// LONG or SHORT, as taken by the pside arguments.
+    #[getter]
+    fn value(&self) -> usize {
+        self.index()
+    }
+
+    #[staticmethod]
+    fn from_name(name: &str) -> PyResult<Self> {
+        name.parse().map_err(PyValueError::new_err)
+    }
+
+    #[staticmethod]
+    fn from_value(value: usize) -> PyResult<Self> {
+        Side::from_index(value)
+            .ok_or_else(|| PyValueError::new_err(format!("Invalid side {}", value)))
+    }
+
+    #[staticmethod]
+    fn members() -> Vec<Self> {
+        Side::ALL.to_vec()
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+
+    fn __hash__(&self) -> u64 {
+        self.index() as u64
+    }
+}
+
+#[pymethods]
+impl EmptyReason {
+    /// Variant name, e.g. "ExposureLimitReached".
+    #[getter]
+    fn name(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    #[getter]
+    fn is_empty(&self) -> bool {
+        *self != EmptyReason::NotEmpty
+    }
+
+    #[staticmethod]
+    fn members() -> Vec<Self> {
+        EmptyReason::ALL.to_vec()
+    }
+
+    fn __str__(&self) -> String {
+        self.name()
+    }
+
+    fn __hash__(&self) -> u64 {
+        *self as u64
+    }
+}
+
+/// Order returned by the typed bindings.
+#[pyclass(name = "Order", frozen)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PyOrder {
+    #[pyo3(get)]
+    qty: f64, // negative for short entries and long closes
+    #[pyo3(get)]
+    price: f64,
+    #[pyo3(get)]
+    order_type: OrderType,
+}
+
+impl From<Order> for PyOrder {
+    fn from(order: Order) -> Self {
+        PyOrder {
+            qty: order.qty,
+            price: order.price,
+            order_type: order.order_type,
+        }
+    }
+}
+
+impl From<&PyOrder> for Order {
+    fn from(order: &PyOrder) -> Self {
+        Order::new(order.qty, order.price, order.order_type)
+    }
+}
+
+#[pymethods]
+impl PyOrder {
+    #[new]
+    fn new(qty: f64, price: f64, order_type: OrderType) -> Self {
+        PyOrder {
+            qty,
+            price,
+            order_type,
+        }
+    }
+
+    /// Deterministic client order id, see Order::custom_id.
+    fn custom_id(&self, symbol: &str, exchange_params: &Bound<'_, PyDict>) -> PyResult<String> {
+        Ok(Order::from(self).custom_id(symbol, &ExchangeParams::from_py_dict(exchange_params)?.0))
+    }
+
+    /// Order recovered from a custom id, or None if the id is not one of ours or belongs to
+    /// another symbol.
+    #[staticmethod]
+    fn from_custom_id(
+        id: &str,
+        symbol: &str,
+        exchange_params: &Bound<'_, PyDict>,
+    ) -> PyResult<Option<PyOrder>> {
+        let exchange_params = ExchangeParams::from_py_dict(exchange_params)?.0;
+        Ok(OrderIdInfo::parse(id)
+            .filter(|info| info.matches_symbol(symbol))
+            .map(|info| info.to_order(&exchange_params).into()))
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Order(qty={:?}, price={:?}, order_type={})",
+            self.qty, self.price, self.order_type
+        )
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    /// order_type as its name.
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new_bound(py);
+        dict.set_item("qty", self.qty)?;
+        dict.set_item("price", self.price)?;
+        dict.set_item("order_type", self.order_type.to_string())?;
+        Ok(dict)
+    }
+
+    /// Exchange neutral order request, see Order::to_order_request. None if empty.
+    #[pyo3(signature = (symbol, hedge_mode=true))]
+    fn to_order_request<'py>(
+        &self,
+        py: Python<'py>,
+        symbol: &str,
+        hedge_mode: bool,
+    ) -> PyResult<Option<Bound<'py, PyDict>>> {
+        let Some(request) = Order::from(self).to_order_request(symbol, position_mode(hedge_mode))
+        else {
+            return Ok(None);
+        };
+        let dict = PyDict::new_bound(py);
+        dict.set_item("symbol", request.symbol)?;
+        let side = match request.side {
+            OrderSide::Buy => "buy",
+            OrderSide::Sell => "sell",
+        };
+        dict.set_item("side", side)?;
+        dict.set_item(
+            "position_side",
+            request.position_side.map(|side| side.to_string()),
+        )?;
+        dict.set_item("order_type", request.order_type.to_string())?;
+        dict.set_item("price", request.price)?;
+        dict.set_item("qty", request.qty)?;
+        dict.set_item("reduce_only", request.reduce_only)?;
+        let time_in_force = match request.time_in_force {
+            TimeInForce::Gtc => "gtc",
+            TimeInForce::Ioc => "ioc",
+            TimeInForce::PostOnly => "post_only",
+        };
+        dict.set_item("time_in_force", time_in_force)?;
+        Ok(Some(dict))
+    }
+
+    /// Binance futures new order params, see to_binance_futures_params. None if empty.
+    #[pyo3(signature = (symbol, exchange_params, hedge_mode=true))]
+    fn to_binance_futures_params<'py>(
+        &self,
+        py: Python<'py>,
+        symbol: &str,
+        exchange_params: &Bound<'_, PyDict>,
+        hedge_mode: bool,
+    ) -> PyResult<Option<Bound<'py, PyDict>>> {
+        let exchange_params = ExchangeParams::from_py_dict(exchange_params)?.0;
+        let Some(request) = to_binance_futures_params(
+            &Order::from(self),
+            symbol,
+            &exchange_params,
+            position_mode(hedge_mode),
+        ) else {
+            return Ok(None);
+        };
+        let dict = PyDict::new_bound(py);
+        dict.set_item("symbol", request.symbol)?;
+        dict.set_item("side", request.side)?;
+        dict.set_item("positionSide", request.position_side)?;
+        dict.set_item("type", request.order_type)?;
+        dict.set_item("timeInForce", request.time_in_force)?;
+        dict.set_item("quantity", request.quantity)?;
+        dict.set_item("price", request.price)?;
+        if let Some(reduce_only) = request.reduce_only {
+            dict.set_item("reduceOnly", reduce_only)?;
+        }
+        dict.set_item("newClientOrderId", request.new_client_order_id)?;
+        Ok(Some(dict))
+    }
+
+    /// Bybit v5 create order params, see to_bybit_v5_params. None if empty.
+    #[pyo3(signature = (symbol, exchange_params, hedge_mode=true))]
+    fn to_bybit_v5_params<'py>(
+        &self,
+        py: Python<'py>,
+        symbol: &str,
+        exchange_params: &Bound<'_, PyDict>,
+        hedge_mode: bool,
+    ) -> PyResult<Option<Bound<'py, PyDict>>> {
+        let exchange_params = ExchangeParams::from_py_dict(exchange_params)?.0;
+        let Some(request) = to_bybit_v5_params(
+            &Order::from(self),
+            symbol,
+            &exchange_params,
+            position_mode(hedge_mode),
+        ) else {
+            return Ok(None);
+        };
+        let dict = PyDict::new_bound(py);
+        dict.set_item("category", request.category)?;
+        dict.set_item("symbol", request.symbol)?;
+        dict.set_item("side", request.side)?;
+        dict.set_item("orderType", request.order_type)?;
+        dict.set_item("qty", request.qty)?;
+        dict.set_item("price", request.price)?;
+        dict.set_item("timeInForce", request.time_in_force)?;
+        dict.set_item("positionIdx", request.position_idx)?;
+        dict.set_item("reduceOnly", request.reduce_only)?;
+        dict.set_item("orderLinkId", request.order_link_id)?;
+        Ok(Some(dict))
+    }
+}
+
+fn position_mode(hedge_mode: bool) -> PositionMode {
+    if hedge_mode {
+        PositionMode::Hedge
+    } else {
+        PositionMode::OneWay
+    }
+}
+
+#[pyclass(name = "Position", frozen)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PyPosition {
+    #[pyo3(get)]
+    size: f64, // negative for shorts
+    #[pyo3(get)]
+    price: f64,
+}
+
+impl From<Position> for PyPosition {
+    fn from(position: Position) -> Self {
+        PyPosition {
+            size: position.size,
+            price: position.price,
+        }
+    }
+}
+
+#[pymethods]
+impl PyPosition {
+    #[new]
+    #[pyo3(signature = (size=0.0, price=0.0))]
+    fn new(size: f64, price: f64) -> Self {
+        PyPosition { size, price }
+    }
+
+    /// Position after order fills in full, see Position::project_fill.
+    fn project_fill(&self, order: &PyOrder, qty_step: f64) -> PyPosition {
+        Position {
+            size: self.size,
+            price: self.price,
+        }
+        .project_fill(&order.into(), qty_step)
+        .into()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Position(size={:?}, price={:?})", self.size, self.price)
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new_bound(py);
+        dict.set_item("size", self.size)?;
+        dict.set_item("price", self.price)?;
+        Ok(dict)
+    }
+}
+
+#[pyclass(name = "Metrics", frozen)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PyMetrics {
+    metrics: Metrics,
+}
+
+#[pymethods]
+impl PyMetrics {
+    #[getter]
+    fn adg(&self) -> f64 {
+        self.metrics.adg
+    }
+
+    #[getter]
+    fn mdg(&self) -> f64 {
+        self.metrics.mdg
+    }
+
+    #[getter]
+    fn sharpe_ratio(&self) -> f64 {
+        self.metrics.sharpe_ratio
+    }
+
+    #[getter]
+    fn max_drawdown(&self) -> f64 {
+        self.metrics.max_drawdown
+    }
+
+    #[getter]
+    fn time_stuck_fraction(&self) -> f64 {
+        self.metrics.time_stuck_fraction
+    }
+
+    #[getter]
+    fn fills_per_day(&self) -> f64 {
+        self.metrics.fills_per_day
+    }
+
+    /// Run aborted early by a pruner; the other fields are meaningless.
+    #[getter]
+    fn pruned(&self) -> bool {
+        self.metrics.pruned
+    }
+
+    fn __repr__(&self) -> String {
+        let metrics = &self.metrics;
+        format!(
+            "Metrics(adg={:?}, mdg={:?}, sharpe_ratio={:?}, max_drawdown={:?}, \
+             time_stuck_fraction={:?}, fills_per_day={:?}, pruned={})",
+            metrics.adg,
+            metrics.mdg,
+            metrics.sharpe_ratio,
+            metrics.max_drawdown,
+            metrics.time_stuck_fraction,
+            metrics.fills_per_day,
+            if metrics.pruned { "True" } else { "False" }
+        )
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = metrics_to_dict(py, &self.metrics)?;
+        dict.set_item("pruned", self.metrics.pruned)?;
+        Ok(dict)
+    }
+}
+
+/// Result of run_backtest_py. fills and equities are converted to numpy arrays on access.
+#[pyclass(name = "BacktestResult", frozen)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PyBacktestResult {
+    fills: Vec<Fill>,
+    equities: Vec<f64>,
+    final_balance: f64,
+    n_trades: usize,
+    metrics: Metrics,
+}
+
+impl From<BacktestResult> for PyBacktestResult {
+    fn from(result: BacktestResult) -> Self {
+        PyBacktestResult {
+            metrics: Metrics::from_result(&result),
+            fills: result.fills,
+            equities: result.equities,
+            final_balance: result.final_balance,
+            n_trades: result.n_trades,
+        }
+    }
+}
+
+#[pymethods]
+impl PyBacktestResult {
+    /// Structured array with one record per fill, see fills_to_record_array.
+    #[getter]
+    fn fills(&self, py: Python) -> PyResult<PyObject> {
+        fills_to_record_array(py, &self.fills)
+    }
+
+    /// Equity after each candle.
+    #[getter]
+    fn equities(&self, py: Python) -> Py<PyArray1<f64>> {
+        Array1::from_vec(self.equities.clone())
+            .into_pyarray_bound(py)
+            .unbind()
+    }
+
+    #[getter]
+    fn final_balance(&self) -> f64 {
+        self.final_balance
+    }
+
+    #[getter]
+    fn n_trades(&self) -> usize {
+        self.n_trades
+    }
+
+    #[getter]
+    fn metrics(&self) -> PyMetrics {
+        PyMetrics {
+            metrics: self.metrics,
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "BacktestResult(n_fills={}, n_candles={}, final_balance={:?}, n_trades={})",
+            self.fills.len(),
+            self.equities.len(),
+            self.final_balance,
+            self.n_trades
+        )
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    /// Same layout as the dict run_backtest_py returned before: fills, equities and metrics,
+    /// with final_balance and n_trades among the metrics.
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let py_metrics = metrics_to_dict(py, &self.metrics)?;
+        py_metrics.set_item("final_balance", self.final_balance)?;
+        py_metrics.set_item("n_trades", self.n_trades)?;
+        let dict = PyDict::new_bound(py);
+        dict.set_item("fills", self.fills(py)?)?;
+        dict.set_item("equities", self.equities(py))?;
+        dict.set_item("metrics", py_metrics)?;
+        Ok(dict)
+    }
+}
+
+#[pyfunction]
+pub fn order_type_from_code(code: u8) -> PyResult<String> {
+    guard_result(|| order_type_from_code_impl(code))
+}
+
+fn order_type_from_code_impl(code: u8) -> PyResult<String> {
+    OrderType::from_code(code)
+        .map(|order_type| order_type.to_string())
+        .ok_or_else(|| PyValueError::new_err(format!("Invalid order type code {}", code)))
+}
+
+type BatchState = (StateParams, Position, TrailingPriceBundle);
+type Setter<T> = fn(&mut T, f64);
+
+fn batch_columns<'py>(
+    dict: &Bound<'py, PyDict>,
+) -> PyResult<Vec<(String, PyReadonlyArray1<'py, f64>)>> {
+    dict.iter()
+        .map(|(key, value)| Ok((key.extract()?, value.extract()?)))
+        .collect()
+}
+
+/// One T per row, with each known column written through its setter.
+fn batch_rows<T: Default + Clone>(
+    columns: &[(String, PyReadonlyArray1<'_, f64>)],
+    n_rows: usize,
+    setter: fn(&str) -> Option<Setter<T>>,
+) -> PyResult<Vec<T>> {
+    let mut rows = vec![T::default(); n_rows];
+    for (key, column) in columns {
+        if let Some(set) = setter(key) {
+            for (row, &value) in rows.iter_mut().zip(column.as_slice()?) {
+                set(row, value);
+            }
+        }
+    }
+    Ok(rows)
+}
+
+fn exchange_params_setter(key: &str) -> Option<Setter<ExchangeParams>> {
+    let setter: Setter<ExchangeParams> = match key {
+        "qty_step" => |p, v| p.qty_step = v,
+        "price_step" => |p, v| p.price_step = v,
+        "min_qty" => |p, v| p.min_qty = v,
+        "min_cost" => |p, v| p.min_cost = v,
+        "c_mult" => |p, v| p.c_mult = v,
+        "fee_rate" => |p, v| p.fee_rate = v,
+        "max_position_size" => |p, v| p.max_position_size = v,
+        "price_band_pct" => |p, v| p.price_band_pct = v,
+        _ => return None,
+    };
+    Some(setter)
+}
+
+fn state_setter(key: &str) -> Option<Setter<BatchState>> {
+    let setter: Setter<BatchState> = match key {
+        "balance" => |s, v| s.0.balance = v,
+        "equity" => |s, v| s.0.equity = (!v.is_nan()).then_some(v),
+        "order_book_bid" => |s, v| s.0.order_book.bid = v,
+        "order_book_ask" => |s, v| s.0.order_book.ask = v,
+        "ema_bands_upper" => |s, v| s.0.ema_bands.upper = v,
+        "ema_bands_lower" => |s, v| s.0.ema_bands.lower = v,
+        "volatility" => |s, v| s.0.volatility = v,
+        "position_size" => |s, v| s.1.size = v,
+        "position_price" => |s, v| s.1.price = v,
+        "min_since_open" => |s, v| s.2.min_since_open = v,
+        "max_since_min" => |s, v| s.2.max_since_min = v,
+        "max_since_open" => |s, v| s.2.max_since_open = v,
+        "min_since_max" => |s, v| s.2.min_since_max = v,
+        _ => return None,
+    };
+    Some(setter)
+}
+
+fn bot_params_setter(key: &str) -> Option<Setter<BotParams>> {
+    let setter: Setter<BotParams> = match key {
+        "balance_reserve_pct" => |p, v| p.balance_reserve_pct = v,
+        "entry_grid_double_down_factor" => |p, v| p.entry_grid_double_down_factor = v,
+        "entry_grid_ema_floor" => |p, v| p.entry_grid_ema_floor = v != 0.0,
+        "entry_grid_spacing_pct" => |p, v| p.entry_grid_spacing_pct = v,
+        "entry_grid_spacing_weight" => |p, v| p.entry_grid_spacing_weight = v,
+        "entry_grid_spacing_vol_weight" => |p, v| p.entry_grid_spacing_vol_weight = v,
+        "entry_initial_ema_dist" => |p, v| p.entry_initial_ema_dist = v,
+        "entry_initial_qty_pct" => |p, v| p.entry_initial_qty_pct = v,
+        "entry_initial_timeout_sec" => |p, v| p.entry_initial_timeout_sec = v,
+        "entry_reentry_ema_dist" => |p, v| p.entry_reentry_ema_dist = (!v.is_nan()).then_some(v),
+        "entry_sizing_basis" => |p, v| {
+            p.entry_sizing_basis = if v == 1.0 {
+                EntrySizingBasis::Equity
+            } else {
+                EntrySizingBasis::Balance
+            }
+        },
+        "entry_trailing_drawdown_pct" => |p, v| p.entry_trailing_drawdown_pct = v,
+        "entry_trailing_grid_ratio" => |p, v| p.entry_trailing_grid_ratio = v,
+        "entry_trailing_mode" => |p, v| {
+            p.entry_trailing_mode = if v == 1.0 {
+                EntryTrailingMode::TrailingStop
+            } else {
+                EntryTrailingMode::ThresholdDrawdown
+            }
+        },
+        "entry_trailing_retracement_pct" => |p, v| p.entry_trailing_retracement_pct = v,
+        "entry_trailing_threshold_pct" => |p, v| p.entry_trailing_threshold_pct = v,
+        "wallet_exposure_limit" => |p, v| p.wallet_exposure_limit = v,
+        _ => return None,
+    };
+    Some(setter)
+}
+
+/// Backtests one symbol on an (n, 4) float64 array of [timestamp, high, low, close] rows, or
+/// (n, 5) with volume appended. Candles are read in place and the backtest runs with the GIL
+/// released. Returns a BacktestResult with fills as a structured array, the equity curve and
+/// metrics. candles may also be a list of arrays, with exchange_params a list of dicts of the
+/// same length; each symbol is then backtested on its own, without shared balance, and a list of
+/// BacktestResults is returned. Use run_backtest for multi-symbol backtests sharing one balance.
+#[pyfunction]
+pub fn run_backtest_py(
+    py: Python,
+    candles: &Bound<'_, PyAny>,
+    bot_params_pair: &Bound<'_, PyDict>,
+    exchange_params: &Bound<'_, PyAny>,
+    backtest_params: &Bound<'_, PyDict>,
+) -> PyResult<PyObject> {
+    guard_result(|| {
+        run_backtest_py_impl(
+            py,
+            candles,
+            bot_params_pair,
+            exchange_params,
+            backtest_params,
+        )
+    })
+}
+
+fn run_backtest_py_impl(
+    py: Python,
+    candles: &Bound<'_, PyAny>,
+    bot_params_pair: &Bound<'_, PyDict>,
+    exchange_params: &Bound<'_, PyAny>,
+    backtest_params: &Bound<'_, PyDict>,
+) -> PyResult<PyObject> {
+    let bot_params_pair = BotParamsPair::from_py_dict(bot_params_pair)?.0;
+    let backtest_params = backtest_params_from_dict(backtest_params)?;
+    if let Ok(candles_list) = candles.downcast::<PyList>() {
+        let exchange_params_list = exchange_params.downcast::<PyList>().map_err(|_| {
+            PyValueError::new_err("exchange_params must be a list if candles is a list")
+        })?;
+        if exchange_params_list.len() != candles_list.len() {
+            return Err(PyValueError::new_err(format!(
+                "{} candle arrays but {} exchange_params",
+                candles_list.len(),
+                exchange_params_list.len()
+            )));
+        }
+        let results = PyList::empty_bound(py);
+        for (candles, exchange_params) in candles_list.iter().zip(exchange_params_list.iter()) {
+            results.append(Py::new(
+                py,
+                backtest_candle_array(
+                    py,
+                    candles.extract()?,
+                    &bot_params_pair,
+                    &ExchangeParams::from_py_dict(exchange_params.downcast()?)?.0,
+                    &backtest_params,
+                )?,
+            )?)?;
+        }
+        Ok(results.into())
+    } else {
+        Ok(backtest_candle_array(
+            py,
+            candles.extract()?,
+            &bot_params_pair,
+            &ExchangeParams::from_py_dict(exchange_params.downcast()?)?.0,
+            &backtest_params,
+        )?
+        .into_py(py))
+    }
+}
+
+fn backtest_candle_array(
+    py: Python,
+    candles: PyReadonlyArray2<f64>,
+    bot_params_pair: &BotParamsPair,
+    exchange_params: &ExchangeParams,
+    backtest_params: &BacktestParams,
+) -> PyResult<PyBacktestResult> {
+    let candles = candles.as_array();
+    let has_volume = candle_array_has_volume(&candles)?;
+    let result = py.allow_threads(|| {
+        backtest_from_fn(
+            candles.nrows(),
+            |k| candle_at(&candles, k, has_volume),
+            bot_params_pair,
+            exchange_params,
+            backtest_params,
+        )
+    });
+    Ok(result.into())
+}
+
+/// numpy record array with fields index, pnl, fee_paid, balance, fill_qty, fill_price,
+/// position_size, position_price and order_type, one record per fill.
+fn fills_to_record_array(py: Python, fills: &[Fill]) -> PyResult<PyObject> {
+    // one column per Fill field except symbol, combined into a structured array by numpy
+    let float_column = |field: fn(&Fill) -> f64| -> Py<PyArray1<f64>> {
+        Array1::from_iter(fills.iter().map(field))
+            .into_pyarray_bound(py)
+            .unbind()
+    };
+    let columns = (
+        Array1::from_iter(fills.iter().map(|fill| fill.index as i64))
+            .into_pyarray_bound(py)
+            .unbind(),
+        float_column(|fill| fill.pnl),
+        float_column(|fill| fill.fee_paid),
+        float_column(|fill| fill.balance),
+        float_column(|fill| fill.fill_qty),
+        float_column(|fill| fill.fill_price),
+        float_column(|fill| fill.position_size),
+        float_column(|fill| fill.position_price),
+        PyList::new_bound(py, fills.iter().map(|fill| fill.order_type.to_string())),
+    );
+    Ok(py
+        .import_bound("numpy")?
+        .getattr("rec")?
+        .call_method1(
+            "fromarrays",
+            (
+                columns,
+                "index,pnl,fee_paid,balance,fill_qty,fill_price,position_size,position_price,order_type",
+            ),
+        )?
+        .into())
+}
+
+/// Genetic optimization of bot params within bounds, each config scored by a backtest on candles,
+/// shaped as for run_backtest_py. bounds is in the flat format of config["optimize"]["bounds"];
+/// ga_config takes the GaConfig fields, with scorer a dict of Scorer fields. Runs with the GIL
+/// released, taking it back between generations to check for Ctrl-C and to call
+/// progress_callback(n_evaluations_done, n_evaluations_total, best_score) once at least
+/// progress_interval evaluations were done since the last call. KeyboardInterrupt, raised by the
+/// callback or by Ctrl-C, ends the run after the current generation and returns the population
+/// so far with cancelled set. Other exceptions raised by the callback are propagated.
+#[pyfunction]
+#[pyo3(signature = (
+    candles,
+    exchange_params,
+    backtest_params,
+    bounds,
+    ga_config,
+    progress_callback=None,
+    progress_interval=100,
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn run_optimize_py(
+    py: Python,
+    candles: PyReadonlyArray2<f64>,
+    exchange_params: &Bound<'_, PyDict>,
+    backtest_params: &Bound<'_, PyDict>,
+    bounds: &Bound<'_, PyDict>,
+    ga_config: &Bound<'_, PyDict>,
+    progress_callback: Option<PyObject>,
+    progress_interval: usize,
+) -> PyResult<PyObject> {
+    guard_result(|| {
+        run_optimize_py_impl(
+            py,
+            candles,
+            exchange_params,
+            backtest_params,
+            bounds,
+            ga_config,
+            progress_callback,
+            progress_interval,
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_optimize_py_impl(
+    py: Python,
+    candles: PyReadonlyArray2<f64>,
+    exchange_params: &Bound<'_, PyDict>,
+    backtest_params: &Bound<'_, PyDict>,
+    bounds: &Bound<'_, PyDict>,
+    ga_config: &Bound<'_, PyDict>,
+    progress_callback: Option<PyObject>,
+    progress_interval: usize,
+) -> PyResult<PyObject> {
+    let candles = candles.as_array();
+    let has_volume = candle_array_has_volume(&candles)?;
+    let exchange_params = ExchangeParams::from_py_dict(exchange_params)?.0;
+    let backtest_params = backtest_params_from_dict(backtest_params)?;
+    let bounds = param_bounds_from_dict(bounds)?;
+    let ga_config = ga_config_from_dict(ga_config)?;
+    let evaluator = |bot_params_pair: &BotParamsPair| {
+        Metrics::from_result(&backtest_from_fn(
+            candles.nrows(),
+            |k| candle_at(&candles, k, has_volume),
+            bot_params_pair,
+            &exchange_params,
+            &backtest_params,
+        ))
+    };
+    // the initial population is evaluated in full, later generations all but the elites
+    let population_size = ga_config.population_size.max(1);
+    let n_children = population_size - ga_config.n_elites.min(population_size);
+    let n_total = population_size + ga_config.n_generations * n_children;
+    let mut n_reported = 0;
+    let mut cancelled = false;
+    let mut callback_err = None;
+    let (n_generations, population) = py.allow_threads(|| {
+        let mut optimizer = GeneticOptimizer::new(&bounds, evaluator, &ga_config);
+        drive_while(
+            &mut optimizer,
+            ga_config.n_generations,
+            |generation, best| {
+                let n_done = population_size + generation * n_children;
+                Python::with_gil(|py| {
+                    let mut result = py.check_signals();
+                    if let (Ok(()), Some(callback)) = (&result, &progress_callback) {
+                        if n_done - n_reported >= progress_interval.max(1) || n_done == n_total {
+                            n_reported = n_done;
+                            result = callback
+                                .call1(py, (n_done, n_total, best.score))
+                                .map(|_| ());
+                        }
+                    }
+                    match result {
+                        Ok(()) => true,
+                        Err(err) if err.is_instance_of::<PyKeyboardInterrupt>(py) => {
+                            cancelled = true;
+                            false
+                        }
+                        Err(err) => {
+                            callback_err = Some(err);
+                            false
+                        }
+                    }
+                })
+            },
+        );
+        (optimizer.n_steps(), optimizer.into_population())
+    });
+    if let Some(err) = callback_err {
+        return Err(err);
+    }
+
+    let field_names = ParamBoundsPair::field_names();
+    let py_population = PyList::empty_bound(py);
+    for scored in &population {
+        let py_params = PyDict::new_bound(py);
+        for (name, value) in field_names
+            .iter()
+            .zip(bounds.to_vec(&scored.bot_params_pair))
+        {
+            py_params.set_item(name, value)?;
+        }
+        let py_config = PyDict::new_bound(py);
+        py_config.set_item("params", py_params)?;
+        py_config.set_item("score", scored.score)?;
+        py_config.set_item(
+            "metrics",
+            PyMetrics {
+                metrics: scored.metrics,
+            }
+            .into_py(py),
+        )?;
+        py_population.append(py_config)?;
+    }
+    let py_result = PyDict::new_bound(py);
+    py_result.set_item("population", py_population)?; // best first
+    py_result.set_item("n_generations", n_generations)?;
+    py_result.set_item("cancelled", cancelled)?;
+    Ok(py_result.into())
+}
+
+fn candle_array_has_volume(candles: &ArrayView2<f64>) -> PyResult<bool> {
+    match candles.ncols() {
+        4 => Ok(false),
+        5 => Ok(true),
+        n_cols => Err(PyValueError::new_err(format!(
+            "Candle array has {} columns, expected 4 or 5",
+            n_cols
+        ))),
+    }
+}
+
+fn candle_at(candles: &ArrayView2<f64>, k: usize, has_volume: bool) -> Candle {
+    Candle {
+        timestamp: candles[(k, 0)] as u64,
+        high: candles[(k, 1)],
+        low: candles[(k, 2)],
+        close: candles[(k, 3)],
+        volume: if has_volume { candles[(k, 4)] } else { 0.0 },
+    }
+}
+
+fn metrics_to_dict<'py>(py: Python<'py>, metrics: &Metrics) -> PyResult<Bound<'py, PyDict>> {
+    let py_metrics = PyDict::new_bound(py);
+    py_metrics.set_item("adg", metrics.adg)?;
+    py_metrics.set_item("mdg", metrics.mdg)?;
+    py_metrics.set_item("sharpe_ratio", metrics.sharpe_ratio)?;
+    py_metrics.set_item("max_drawdown", metrics.max_drawdown)?;
+    py_metrics.set_item("time_stuck_fraction", metrics.time_stuck_fraction)?;
+    py_metrics.set_item("fills_per_day", metrics.fills_per_day)?;
+    Ok(py_metrics)
+}
+
+/// Flat {"long_ema_span_0": [low, high], ...}. Unknown keys are ignored.
+fn param_bounds_from_dict(dict: &Bound<'_, PyDict>) -> PyResult<ParamBoundsPair> {
+    let mut flat = HashMap::new();
+    for (key, value) in dict.iter() {
+        let key: String = key.extract()?;
+        match value.extract::<Vec<f64>>().as_deref() {
+            Ok(&[low, high]) => flat.insert(key, (low, high)),
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "invalid bounds.{}: expected [low, high]",
+                    key
+                )))
+            }
+        };
+    }
+    ParamBoundsPair::try_from(flat).map_err(PyValueError::new_err)
+}
+
+/// Missing keys take their defaults. Raises on unknown keys.
+fn ga_config_from_dict(dict: &Bound<'_, PyDict>) -> PyResult<GaConfig> {
+    let mut config = ConfigDict::new(dict, "ga_config");
+    let default = GaConfig::default();
+    let scorer = match config.optional::<Bound<'_, PyDict>>("scorer")? {
+        Some(scorer) => scorer_from_config(ConfigDict::new(&scorer, &config.key_path("scorer")))?,
+        None => default.scorer,
+    };
+    let ga_config = GaConfig {
+        population_size: config
+            .optional("population_size")?
+            .unwrap_or(default.population_size),
+        n_generations: config
+            .optional("n_generations")?
+            .unwrap_or(default.n_generations),
+        n_elites: config.optional("n_elites")?.unwrap_or(default.n_elites),
+        tournament_size: config
+            .optional("tournament_size")?
+            .unwrap_or(default.tournament_size),
+        crossover_prob: config
+            .optional("crossover_prob")?
+            .unwrap_or(default.crossover_prob),
+        mutation_prob: config
+            .optional("mutation_prob")?
+            .unwrap_or(default.mutation_prob),
+        uniform_mutation_prob: config
+            .optional("uniform_mutation_prob")?
+            .unwrap_or(default.uniform_mutation_prob),
+        mutation_sigma: config
+            .optional("mutation_sigma")?
+            .unwrap_or(default.mutation_sigma),
+        seed: config.optional("seed")?.unwrap_or(default.seed),
+        cache_significant_digits: config
+            .optional("cache_significant_digits")?
+            .unwrap_or(default.cache_significant_digits),
+        scorer,
+    };
+    let unknown_keys = config.unknown_keys();
+    if !unknown_keys.is_empty() {
+        return Err(PyValueError::new_err(unknown_keys.join("; ")));
+    }
+    Ok(ga_config)
+}
+
+fn scorer_from_config(mut config: ConfigDict) -> PyResult<Scorer> {
+    let default = Scorer::default();
+    let scorer = Scorer {
+        adg_weight: config.optional("adg_weight")?.unwrap_or(default.adg_weight),
+        sharpe_weight: config
+            .optional("sharpe_weight")?
+            .unwrap_or(default.sharpe_weight),
+        max_drawdown: config.optional("max_drawdown")?,
+        max_time_stuck_fraction: config.optional("max_time_stuck_fraction")?,
+        min_fills_per_day: config.optional("min_fills_per_day")?,
+        penalty_weight: config
+            .optional("penalty_weight")?
+            .unwrap_or(default.penalty_weight),
+    };
+    let unknown_keys = config.unknown_keys();
+    if !unknown_keys.is_empty() {
+        return Err(PyValueError::new_err(unknown_keys.join("; ")));
+    }
+    Ok(scorer)
+}
@@ -0,0 +1,767 @@
+use crate::backtest::{calc_drawdowns, OpenOrderBundleNew};
+pub use crate::backtest::{calc_ema_alphas, EMAs, EmaAlphas};
+use crate::closes::{calc_closes_long, calc_closes_short};
+use crate::constants::{LONG, SHORT};
+use crate::data::CandleView;
+use crate::entries::{
+    calc_blended_entries_long, calc_blended_entries_short, calc_entries_long, calc_entries_short,
+};
+use crate::pruning::{InterimStats, Pruner};
+use crate::sync::{validate_order_set, OrderSetViolation};
+use crate::types::{
+    BacktestParams, BotParams, BotParamsPair, Candle, EntrySizingBasis, EntryTrailingGridMode,
+    ExchangeParams, Fill, LastFill, Order, OrderBook, OrderKind, OrderType, Position, StateParams,
+    TrailingPriceBundle,
+};
+use crate::utils::{calc_pnl_long, calc_pnl_short, qty_to_cost, round_, round_dn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+pub(crate) type OrderCalculator =
+    fn(&ExchangeParams, &StateParams, &BotParams, &Position, &TrailingPriceBundle) -> Vec<Order>;
+
+/// Entry and close calculators for a side, honoring entry_trailing_grid_mode.
+pub(crate) fn order_calculators(
+    pside: usize,
+    bot_params: &BotParams,
+) -> (OrderCalculator, OrderCalculator) {
+    let blended = bot_params.entry_trailing_grid_mode == EntryTrailingGridMode::Blended;
+    match pside {
+        LONG if blended => (calc_blended_entries_long, calc_closes_long),
+        LONG => (calc_entries_long, calc_closes_long),
+        _ if blended => (calc_blended_entries_short, calc_closes_short),
+        _ => (calc_entries_short, calc_closes_short),
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct BacktestResult {
+    pub fills: Vec<Fill>,
+    pub equities: Vec<f64>,
+    pub final_balance: f64,
+    pub max_drawdown: f64,
+    pub n_trades: usize,
+    pub pnl_by_order_type: HashMap<OrderType, PnlStats>,
+    pub order_set_violations: Vec<(usize, OrderSetViolation)>, // strict mode only; run halts at the first
+    // config echo
+    pub bot_params_pair: BotParamsPair,
+    pub exchange_params: ExchangeParams,
+    pub backtest_params: BacktestParams,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PnlStats {
+    pub count: usize,
+    pub gross_pnl: f64, // closes only
+    pub fees: f64,      // same sign convention as Fill::fee_paid
+}
+
+#[derive(Debug, Default)]
+struct SideState {
+    position: Position,
+    trailing_prices: TrailingPriceBundle,
+    open_orders: OpenOrderBundleNew,
+    pending_orders: VecDeque<(usize, OpenOrderBundleNew)>, // keyed by activation index
+    has_residual: bool,                                    // partially filled entries left to fill
+    last_entry_fill: Option<LastFill>,
+    trailing_add_count: usize,
+}
+
+/// Single symbol backtester fed one candle at a time.
+/// Orders computed at candle k may fill at candle k + 1 + latency_candles or later.
+pub struct Backtester<'a> {
+    bot_params_pair: &'a BotParamsPair,
+    exchange_params: &'a ExchangeParams,
+    backtest_params: &'a BacktestParams,
+    balance: f64,
+    ema_alphas: EmaAlphas,
+    emas: Option<EMAs>,
+    long: SideState,
+    short: SideState,
+    k: usize,
+    last_close: f64,
+    last_timestamp: u64,          // of the last candle with a close, ms
+    candle_ranges: VecDeque<f64>, // (high - low) / close of recent candles, for volatility
+    max_volatility_window: usize,
+    delisted: bool,
+    halted: bool,
+    fills: Vec<Fill>,
+    equities: Vec<f64>,
+    pnl_by_order_type: HashMap<OrderType, PnlStats>,
+    order_set_violations: Vec<(usize, OrderSetViolation)>,
+}
+
+impl<'a> Backtester<'a> {
+    pub fn new(
+        bot_params_pair: &'a BotParamsPair,
+        exchange_params: &'a ExchangeParams,
+        backtest_params: &'a BacktestParams,
+    ) -> Self {
+        Backtester {
+            bot_params_pair,
+            exchange_params,
+            backtest_params,
+            balance: backtest_params.starting_balance,
+            ema_alphas: calc_ema_alphas(bot_params_pair),
+            emas: None,
+            long: SideState::default(),
+            short: SideState::default(),
+            k: 0,
+            last_close: 0.0,
+            last_timestamp: 0,
+            candle_ranges: VecDeque::new(),
+            max_volatility_window: [&bot_params_pair.long, &bot_params_pair.short]
+                .iter()
+                .filter(|bot_params| bot_params.entry_grid_spacing_vol_weight != 0.0)
+                .map(|bot_params| bot_params.filter_rolling_window)
+                .max()
+                .unwrap_or(0),
+            delisted: false,
+            halted: false,
+            fills: Vec::new(),
+            equities: Vec::new(),
+            pnl_by_order_type: HashMap::new(),
+            order_set_violations: Vec::new(),
+        }
+    }
+
+    pub fn step(&mut self, candle: &Candle) {
+        let k = self.k;
+        self.k += 1;
+        if self.halted {
+            self.equities.push(self.calc_equity(self.last_close));
+            return;
+        }
+        if self.delisted || candle.close.is_nan() {
+            // market closed: no fills, no EMA update, orders stay as they are
+            self.equities.push(self.calc_equity(self.last_close));
+            return;
+        }
+        self.last_close = candle.close;
+        self.last_timestamp = candle.timestamp;
+        self.activate_pending_orders(k, LONG);
+        self.activate_pending_orders(k, SHORT);
+        let did_fill_long = self.check_for_fills(k, candle, LONG);
+        let did_fill_short = self.check_for_fills(k, candle, SHORT);
+        match self.emas.as_mut() {
+            Some(emas) => emas.update(candle.close, &self.ema_alphas),
+            None => {
+                self.emas = Some(EMAs {
+                    long: [candle.close; 3],
+                    short: [candle.close; 3],
+                })
+            }
+        }
+        if !did_fill_long && self.long.position.size != 0.0 {
+            self.long
+                .trailing_prices
+                .update(candle.high, candle.low, candle.close);
+        }
+        if !did_fill_short && self.short.position.size != 0.0 {
+            self.short
+                .trailing_prices
+                .update(candle.high, candle.low, candle.close);
+        }
+        self.update_open_orders(k, candle, LONG);
+        self.update_open_orders(k, candle, SHORT);
+        if self.max_volatility_window > 0 {
+            if self.candle_ranges.len() == self.max_volatility_window {
+                self.candle_ranges.pop_front();
+            }
+            self.candle_ranges
+                .push_back((candle.high - candle.low) / candle.close);
+        }
+        self.equities.push(self.calc_equity(candle.close));
+    }
+
+    /// Mean (high - low) / close over bot_params.filter_rolling_window candles before the
+    /// current one, as in the multi symbol backtest.
+    fn calc_volatility(&self, bot_params: &BotParams) -> f64 {
+        if bot_params.entry_grid_spacing_vol_weight == 0.0 {
+            return 0.0;
+        }
+        let window = bot_params
+            .filter_rolling_window
+            .min(self.candle_ranges.len());
+        if window == 0 {
+            return 0.0;
+        }
+        self.candle_ranges
+            .iter()
+            .skip(self.candle_ranges.len() - window)
+            .sum::<f64>()
+            / window as f64
+    }
+
+    /// step through candles in order, e.g. one chunk of a CandleStore symbol at a time.
+    pub fn step_view(&mut self, candles: &CandleView) {
+        for candle in candles.iter() {
+            self.step(&candle);
+        }
+    }
+
+    pub fn finish(self) -> BacktestResult {
+        let max_drawdown = calc_drawdowns(&self.equities)
+            .iter()
+            .fold(0.0, |a, &b| f64::max(a, b.abs()));
+        BacktestResult {
+            n_trades: self.fills.len(),
+            final_balance: self.balance,
+            max_drawdown,
+            fills: self.fills,
+            equities: self.equities,
+            pnl_by_order_type: self.pnl_by_order_type,
+            order_set_violations: self.order_set_violations,
+            bot_params_pair: self.bot_params_pair.clone(),
+            exchange_params: self.exchange_params.clone(),
+            backtest_params: self.backtest_params.clone(),
+        }
+    }
+
+    /// Symbol's data ended for good.
+    /// Force closes any open position at the last valid close and stops placing orders.
+    pub fn delist(&mut self) {
+        if self.delisted {
+            return;
+        }
+        self.delisted = true;
+        let k = self.k;
+        for pside in [LONG, SHORT] {
+            let side_state = self.side_state(pside);
+            side_state.open_orders = OpenOrderBundleNew::default();
+            side_state.pending_orders.clear();
+            let position_size = side_state.position.size;
+            if position_size != 0.0 {
+                let close = Order {
+                    qty: -position_size,
+                    price: self.last_close,
+                    order_type: match pside {
+                        LONG => OrderType::CloseUnstuckLong,
+                        _ => OrderType::CloseUnstuckShort,
+                    },
+                };
+                self.apply_fill(k, pside, &close);
+            }
+        }
+    }
+
+    fn side_state(&mut self, pside: usize) -> &mut SideState {
+        match pside {
+            LONG => &mut self.long,
+            SHORT => &mut self.short,
+            _ => panic!("Invalid pside"),
+        }
+    }
+
+    fn check_for_fills(&mut self, k: usize, candle: &Candle, pside: usize) -> bool {
+        let qty_step = self.exchange_params.qty_step;
+        // zero volume means the data has no volume, not that nothing traded
+        let max_fill_qty =
+            if self.backtest_params.max_fill_volume_fraction > 0.0 && candle.volume > 0.0 {
+                round_dn(
+                    candle.volume * self.backtest_params.max_fill_volume_fraction,
+                    qty_step,
+                )
+            } else {
+                f64::INFINITY
+            };
+        let side_state = self.side_state(pside);
+        let mut filled = Vec::<Order>::new();
+        let mut has_residual = false;
+        for (is_entry, orders) in [
+            (false, &mut side_state.open_orders.closes),
+            (true, &mut side_state.open_orders.entries),
+        ] {
+            orders.retain_mut(|order| {
+                if !order_filled(candle, order) {
+                    return true;
+                }
+                let fill_qty = order.qty.abs().min(max_fill_qty) * order.qty.signum();
+                if fill_qty != 0.0 {
+                    filled.push(order.with_qty(fill_qty));
+                }
+                if fill_qty == order.qty {
+                    return false;
+                }
+                // residual stays active while price remains beyond the order
+                order.qty = round_(order.qty - fill_qty, qty_step);
+                // residual closes are replaced by closes recomputed from the new position
+                has_residual |= is_entry;
+                true
+            });
+        }
+        side_state.has_residual = has_residual;
+        if filled.is_empty() {
+            return false;
+        }
+        // pending orders were computed from the old position
+        side_state.trailing_prices = TrailingPriceBundle::default();
+        side_state.pending_orders.clear();
+        for order in &filled {
+            self.apply_fill(k, pside, order);
+        }
+        true
+    }
+
+    fn apply_fill(&mut self, k: usize, pside: usize, order: &Order) {
+        let qty_step = self.exchange_params.qty_step;
+        let c_mult = self.exchange_params.c_mult;
+        let position = self.side_state(pside).position;
+        let is_close = match pside {
+            LONG => order.qty < 0.0,
+            _ => order.qty > 0.0,
+        };
+        let (fill_qty, pnl, new_position) = if is_close {
+            if position.size == 0.0 {
+                return;
+            }
+            // never close more than the position holds
+            let fill_qty = match pside {
+                LONG => order.qty.max(-position.size),
+                _ => order.qty.min(position.size.abs()),
+            };
+            let pnl = match pside {
+                LONG => calc_pnl_long(position.price, order.price, fill_qty, c_mult),
+                _ => calc_pnl_short(position.price, order.price, fill_qty, c_mult),
+            };
+            let new_position = position.project_fill(order, qty_step);
+            if new_position.size == 0.0 {
+                self.side_state(pside).trailing_add_count = 0;
+            }
+            (fill_qty, pnl, new_position)
+        } else {
+            let timestamp = self.last_timestamp;
+            let side_state = self.side_state(pside);
+            side_state.last_entry_fill = Some(LastFill {
+                price: order.price,
+                timestamp,
+            });
+            if order.order_type.kind() == OrderKind::EntryTrailing {
+                side_state.trailing_add_count += 1;
+            }
+            (order.qty, 0.0, position.project_fill(order, qty_step))
+        };
+        let fee_paid = -qty_to_cost(fill_qty, order.price, c_mult) * self.exchange_params.fee_rate;
+        self.balance += pnl + fee_paid;
+        self.side_state(pside).position = new_position;
+        let pnl_stats = self.pnl_by_order_type.entry(order.order_type).or_default();
+        pnl_stats.count += 1;
+        pnl_stats.gross_pnl += pnl;
+        pnl_stats.fees += fee_paid;
+        self.fills.push(Fill {
+            index: k,
+            symbol: String::new(),
+            pnl,
+            fee_paid,
+            balance: self.balance,
+            fill_qty,
+            fill_price: order.price,
+            position_size: new_position.size,
+            position_price: if new_position.size == 0.0 {
+                position.price
+            } else {
+                new_position.price
+            },
+            order_type: order.order_type,
+        });
+    }
+
+    fn activate_pending_orders(&mut self, k: usize, pside: usize) {
+        // latest recomputation due by now replaces whatever is active
+        let side_state = self.side_state(pside);
+        while side_state
+            .pending_orders
+            .front()
+            .is_some_and(|(activation_index, _)| *activation_index <= k)
+        {
+            side_state.open_orders = side_state.pending_orders.pop_front().unwrap().1;
+        }
+    }
+
+    fn update_open_orders(&mut self, k: usize, candle: &Candle, pside: usize) {
+        let bot_params_pair = self.bot_params_pair;
+        let bot_params = match pside {
+            LONG => &bot_params_pair.long,
+            _ => &bot_params_pair.short,
+        };
+        if bot_params.wallet_exposure_limit == 0.0 {
+            return;
+        }
+        let order_book = OrderBook {
+            bid: candle.close,
+            ask: candle.close,
+        };
+        let state_params = StateParams {
+            balance: self
+                .backtest_params
+                .balance_mode
+                .sizing_balance(self.balance, self.backtest_params.starting_balance),
+            // calc_equity walks both positions; only equity sizing reads it
+            equity: match bot_params.entry_sizing_basis {
+                EntrySizingBasis::Balance => None,
+                EntrySizingBasis::Equity => Some(self.backtest_params.balance_mode.sizing_balance(
+                    self.calc_equity(candle.close),
+                    self.backtest_params.starting_balance,
+                )),
+            },
+            order_book: order_book.clone(),
+            ema_bands: self.emas.as_ref().unwrap().compute_bands(pside),
+            timestamp: candle.timestamp,
+            last_entry_fill: self.side_state(pside).last_entry_fill,
+            trailing_add_count: self.side_state(pside).trailing_add_count,
+            volatility: self.calc_volatility(bot_params),
+        };
+        let exchange_params = self.exchange_params;
+        let activation_index = k + 1 + self.backtest_params.latency_candles;
+        let side_state = self.side_state(pside);
+        let (calc_entries, calc_closes) = order_calculators(pside, bot_params);
+        let entries = if side_state.has_residual {
+            // keep working the partially filled entries; closes follow the grown position
+            side_state.open_orders.entries.clone()
+        } else {
+            calc_entries(
+                exchange_params,
+                &state_params,
+                bot_params,
+                &side_state.position,
+                &side_state.trailing_prices,
+            )
+        };
+        let closes = calc_closes(
+            exchange_params,
+            &state_params,
+            bot_params,
+            &side_state.position,
+            &side_state.trailing_prices,
+        );
+        if self.backtest_params.strict_order_validation {
+            let orders: Vec<Order> = entries.iter().chain(closes.iter()).cloned().collect();
+            let violations = validate_order_set(
+                &orders,
+                &order_book,
+                &self.long.position,
+                &self.short.position,
+            );
+            if !violations.is_empty() {
+                // halt instead of simulating fills of an impossible order set
+                self.order_set_violations
+                    .extend(violations.into_iter().map(|violation| (k, violation)));
+                self.halted = true;
+                return;
+            }
+        }
+        self.side_state(pside)
+            .pending_orders
+            .push_back((activation_index, OpenOrderBundleNew { entries, closes }));
+    }
+
+    fn calc_equity(&self, close_price: f64) -> f64 {
+        self.balance
+            + calc_pnl_long(
+                self.long.position.price,
+                close_price,
+                self.long.position.size,
+                self.exchange_params.c_mult,
+            )
+            + calc_pnl_short(
+                self.short.position.price,
+                close_price,
+                self.short.position.size,
+                self.exchange_params.c_mult,
+            )
+    }
+}
+
+fn order_filled(candle: &Candle, order: &Order) -> bool {
+    if order.qty > 0.0 {
+        candle.low < order.price
+    } else if order.qty < 0.0 {
+        candle.high > order.price
+    } else {
+        false
+    }
+}
+
+pub fn backtest(
+    candles: &[Candle],
+    bot_params_pair: &BotParamsPair,
+    exchange_params: &ExchangeParams,
+    backtest_params: &BacktestParams,
+) -> BacktestResult {
+    backtest_from_fn(
+        candles.len(),
+        |k| candles[k],
+        bot_params_pair,
+        exchange_params,
+        backtest_params,
+    )
+}
+
+/// Same as backtest, with candle k built on demand, e.g. from a row of a borrowed array.
+pub fn backtest_from_fn<F>(
+    n_candles: usize,
+    candle_at: F,
+    bot_params_pair: &BotParamsPair,
+    exchange_params: &ExchangeParams,
+    backtest_params: &BacktestParams,
+) -> BacktestResult
+where
+    F: Fn(usize) -> Candle,
+{
+    let mut backtester = Backtester::new(bot_params_pair, exchange_params, backtest_params);
+    // trailing NaNs mean the symbol was delisted
+    let last_valid = (0..n_candles).rposition(|k| !candle_at(k).close.is_nan());
+    for k in 0..n_candles {
+        if last_valid.is_none_or(|last_valid| k > last_valid) {
+            backtester.delist();
+        }
+        backtester.step(&candle_at(k));
+    }
+    backtester.finish()
+}
+
+/// Same as backtest, with candles decoded from a CandleStore as they are needed.
+pub fn backtest_view(
+    candles: &CandleView,
+    bot_params_pair: &BotParamsPair,
+    exchange_params: &ExchangeParams,
+    backtest_params: &BacktestParams,
+) -> BacktestResult {
+    backtest_from_fn(
+        candles.len(),
+        |k| candles.get(k),
+        bot_params_pair,
+        exchange_params,
+        backtest_params,
+    )
+}
+
+/// Same as backtest, but aborts as soon as the pruner says so at one of its checkpoints.
+/// Returns None if pruned.
+pub fn backtest_with_pruner(
+    candles: &[Candle],
+    bot_params_pair: &BotParamsPair,
+    exchange_params: &ExchangeParams,
+    backtest_params: &BacktestParams,
+    pruner: &dyn Pruner,
+) -> Option<BacktestResult> {
+    let mut backtester = Backtester::new(bot_params_pair, exchange_params, backtest_params);
+    let last_valid = candles.iter().rposition(|candle| !candle.close.is_nan());
+    let interval = pruner.interval().max(1);
+    let mut checkpoints = Vec::new();
+    let mut equity_peak = backtest_params.starting_balance;
+    let mut max_drawdown: f64 = 0.0;
+    for (k, candle) in candles.iter().enumerate() {
+        if last_valid.is_none_or(|last_valid| k > last_valid) {
+            backtester.delist();
+        }
+        backtester.step(candle);
+        let equity = *backtester.equities.last().unwrap();
+        equity_peak = equity_peak.max(equity);
+        max_drawdown = max_drawdown.max(1.0 - equity / equity_peak);
+        if (k + 1) % interval == 0 {
+            let stats = InterimStats {
+                k: k + 1,
+                equity,
+                max_drawdown,
+            };
+            checkpoints.push(stats);
+            if pruner.should_prune(checkpoints.len(), &stats) {
+                return None;
+            }
+        }
+    }
+    pruner.report_completed(&checkpoints);
+    Some(backtester.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    /// Flat at 60000, with the lows of dip_indices 2% below, through the initial entry.
+    fn dip_candles(n_candles: usize, dip_indices: &[usize]) -> Vec<Candle> {
+        (0..n_candles)
+            .map(|k| Candle {
+                timestamp: k as u64 * 60_000,
+                high: 60006.0,
+                low: if dip_indices.contains(&k) {
+                    58800.0
+                } else {
+                    59994.0
+                },
+                close: 60000.0,
+                volume: 100.0,
+            })
+            .collect()
+    }
+
+    fn long_only() -> BotParamsPair {
+        let mut bot_params_pair = fixtures::bot_params_pair();
+        bot_params_pair.short.wallet_exposure_limit = 0.0;
+        bot_params_pair.long.entry_initial_ema_dist = 0.01;
+        bot_params_pair
+    }
+
+    fn first_fill_index(candles: &[Candle], backtest_params: &BacktestParams) -> Option<usize> {
+        backtest(
+            candles,
+            &long_only(),
+            &fixtures::exchange_params(),
+            backtest_params,
+        )
+        .fills
+        .first()
+        .map(|fill| fill.index)
+    }
+
+    #[test]
+    fn orders_activate_latency_candles_after_the_next_candle() {
+        // the first orders are computed at candle 0
+        let candles = dip_candles(10, &[2]);
+        let with_latency = |latency_candles| BacktestParams {
+            latency_candles,
+            ..fixtures::backtest_params()
+        };
+        assert_eq!(first_fill_index(&candles, &with_latency(0)), Some(2));
+        assert_eq!(first_fill_index(&candles, &with_latency(1)), Some(2));
+        assert_eq!(first_fill_index(&candles, &with_latency(2)), None);
+        // later dips fill on the candle itself whatever the latency, as orders are recomputed
+        // every candle
+        let candles = dip_candles(10, &[6]);
+        for latency_candles in 0..3 {
+            assert_eq!(
+                first_fill_index(&candles, &with_latency(latency_candles)),
+                Some(6)
+            );
+        }
+    }
+
+    #[test]
+    fn fills_capped_at_volume_share_leave_residual() {
+        // 10% of 0.05 per candle against an initial entry of 0.017
+        let mut candles = dip_candles(12, &[2, 3, 4, 5, 6]);
+        for candle in &mut candles {
+            candle.volume = 0.05;
+        }
+        let mut bot_params_pair = long_only();
+        bot_params_pair.long.wallet_exposure_limit = 1.0;
+        bot_params_pair.long.entry_initial_qty_pct = 0.1;
+        let backtest_params = BacktestParams {
+            max_fill_volume_fraction: 0.1,
+            ..fixtures::backtest_params()
+        };
+        let result = backtest(
+            &candles,
+            &bot_params_pair,
+            &fixtures::exchange_params(),
+            &backtest_params,
+        );
+        let fills: Vec<(usize, f64)> = result
+            .fills
+            .iter()
+            .map(|fill| (fill.index, fill.fill_qty))
+            .collect();
+        assert_eq!(fills, [(2, 0.005), (3, 0.005), (4, 0.005), (5, 0.002)]);
+        assert!(result
+            .fills
+            .iter()
+            .all(|fill| fill.order_type == OrderType::EntryInitialNormalLong
+                && fill.fill_price == 59400.0));
+        assert_eq!(result.fills[3].position_size, 0.017);
+    }
+
+    #[test]
+    fn pnl_attributed_per_order_type_adds_up() {
+        let result = backtest(
+            &fixtures::candles(20_000, 1),
+            &fixtures::bot_params_pair(),
+            &fixtures::exchange_params(),
+            &fixtures::backtest_params(),
+        );
+        assert!(result.pnl_by_order_type.len() > 2);
+        for (order_type, stats) in &result.pnl_by_order_type {
+            let fills: Vec<&Fill> = result
+                .fills
+                .iter()
+                .filter(|fill| fill.order_type == *order_type)
+                .collect();
+            assert_eq!(stats.count, fills.len());
+            let gross_pnl: f64 = fills.iter().map(|fill| fill.pnl).sum();
+            let fees: f64 = fills.iter().map(|fill| fill.fee_paid).sum();
+            assert!((stats.gross_pnl - gross_pnl).abs() < 1e-9);
+            assert!((stats.fees - fees).abs() < 1e-9);
+            if order_type.is_entry() {
+                assert_eq!(stats.gross_pnl, 0.0);
+            }
+            assert!(stats.fees < 0.0);
+        }
+        let total: f64 = result
+            .pnl_by_order_type
+            .values()
+            .map(|stats| stats.gross_pnl + stats.fees)
+            .sum();
+        assert!(
+            (fixtures::backtest_params().starting_balance + total - result.final_balance).abs()
+                < 1e-6
+        );
+    }
+
+    #[test]
+    fn strict_order_validation_passes_consistent_runs_unchanged() {
+        let candles = fixtures::candles(5000, 2);
+        let run = |strict_order_validation| {
+            backtest(
+                &candles,
+                &fixtures::bot_params_pair(),
+                &fixtures::exchange_params(),
+                &BacktestParams {
+                    strict_order_validation,
+                    ..fixtures::backtest_params()
+                },
+            )
+        };
+        let (lenient, strict) = (run(false), run(true));
+        assert!(strict.order_set_violations.is_empty());
+        assert!(!strict.fills.is_empty());
+        assert_eq!(strict.fills, lenient.fills);
+        assert_eq!(strict.equities.len(), candles.len());
+    }
+
+    #[test]
+    fn pruned_runs_return_none_and_median_runs_complete() {
+        use crate::pruning::MedianPruner;
+        let candles = fixtures::candles(5000, 3);
+        let run = |pruner: &MedianPruner| {
+            backtest_with_pruner(
+                &candles,
+                &fixtures::bot_params_pair(),
+                &fixtures::exchange_params(),
+                &fixtures::backtest_params(),
+                pruner,
+            )
+        };
+        let pruner = MedianPruner::new(500, 1, 0);
+        let unpruned = backtest(
+            &candles,
+            &fixtures::bot_params_pair(),
+            &fixtures::exchange_params(),
+            &fixtures::backtest_params(),
+        );
+        // identical runs sit at the median and are never pruned
+        for _ in 0..3 {
+            let result = run(&pruner).unwrap();
+            assert_eq!(result.equities, unpruned.equities);
+        }
+        let unreachable = MedianPruner::new(500, 1, 0);
+        unreachable.report_completed(&vec![
+            InterimStats {
+                equity: 1e12,
+                ..Default::default()
+            };
+            10
+        ]);
+        assert!(run(&unreachable).is_none());
+    }
+}
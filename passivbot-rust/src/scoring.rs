@@ -0,0 +1,129 @@
+use crate::analysis::Metrics;
+use serde::{Deserialize, Serialize};
+
+pub const PRUNED_SCORE: f64 = -1e9; // worse than any score of a completed run
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Scorer {
+    // objective = adg_weight * adg + sharpe_weight * sharpe_ratio
+    pub adg_weight: f64,
+    pub sharpe_weight: f64,
+    // constraints; None means unconstrained
+    pub max_drawdown: Option<f64>,
+    pub max_time_stuck_fraction: Option<f64>,
+    pub min_fills_per_day: Option<f64>,
+    // larger values punish violations harder
+    pub penalty_weight: f64,
+}
+
+impl Default for Scorer {
+    fn default() -> Self {
+        Scorer {
+            adg_weight: 1.0,
+            sharpe_weight: 0.0,
+            max_drawdown: None,
+            max_time_stuck_fraction: None,
+            min_fills_per_day: None,
+            penalty_weight: 10.0,
+        }
+    }
+}
+
+impl Scorer {
+    /// Higher is better.
+    /// Violated constraints scale the objective by 1 / (1 + penalty_weight * relative_violation)
+    /// instead of rejecting outright, so the score degrades smoothly the further off it is.
+    pub fn score(&self, metrics: &Metrics) -> f64 {
+        if metrics.pruned {
+            return PRUNED_SCORE;
+        }
+        let objective = self.adg_weight * metrics.adg + self.sharpe_weight * metrics.sharpe_ratio;
+        let multiplier = self.penalty_multiplier(metrics);
+        if objective >= 0.0 {
+            objective * multiplier
+        } else {
+            // keep penalties making negative scores worse, not closer to zero
+            objective / multiplier
+        }
+    }
+
+    pub fn penalty_multiplier(&self, metrics: &Metrics) -> f64 {
+        let violations = [
+            self.max_drawdown
+                .map(|limit| relative_violation(metrics.max_drawdown - limit, limit)),
+            self.max_time_stuck_fraction
+                .map(|limit| relative_violation(metrics.time_stuck_fraction - limit, limit)),
+            self.min_fills_per_day
+                .map(|limit| relative_violation(limit - metrics.fills_per_day, limit)),
+        ];
+        violations
+            .iter()
+            .flatten()
+            .map(|violation| 1.0 / (1.0 + self.penalty_weight * violation))
+            .product()
+    }
+}
+
+fn relative_violation(excess: f64, limit: f64) -> f64 {
+    // zero if within limit
+    f64::max(0.0, excess) / f64::max(limit.abs(), f64::EPSILON)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics() -> Metrics {
+        Metrics {
+            adg: 0.002,
+            sharpe_ratio: 0.5,
+            max_drawdown: 0.3,
+            time_stuck_fraction: 0.1,
+            fills_per_day: 4.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn unconstrained_score_is_the_weighted_objective() {
+        let scorer = Scorer {
+            adg_weight: 100.0,
+            sharpe_weight: 0.1,
+            ..Default::default()
+        };
+        assert!((scorer.score(&metrics()) - 0.25).abs() < 1e-12);
+        assert_eq!(scorer.score(&Metrics::pruned()), PRUNED_SCORE);
+    }
+
+    #[test]
+    fn constraint_violations_scale_the_score() {
+        let within = Scorer {
+            max_drawdown: Some(0.3),
+            max_time_stuck_fraction: Some(0.2),
+            min_fills_per_day: Some(1.0),
+            ..Default::default()
+        };
+        assert_eq!(within.penalty_multiplier(&metrics()), 1.0);
+        assert_eq!(within.score(&metrics()), 0.002);
+        // drawdown 50% over its limit: 1 / (1 + 10 * 0.5)
+        let tight = Scorer {
+            max_drawdown: Some(0.2),
+            ..Default::default()
+        };
+        assert!((tight.penalty_multiplier(&metrics()) - 1.0 / 6.0).abs() < 1e-12);
+        // violations multiply; fills per day 50% under its minimum
+        let tighter = Scorer {
+            min_fills_per_day: Some(8.0),
+            ..tight.clone()
+        };
+        assert!((tighter.penalty_multiplier(&metrics()) - 1.0 / 36.0).abs() < 1e-12);
+        // penalties push negative objectives further down
+        let losing = Metrics {
+            adg: -0.002,
+            ..metrics()
+        };
+        assert!((tight.score(&losing) - -0.012).abs() < 1e-12);
+        assert!(tight.score(&losing) > PRUNED_SCORE);
+    }
+}
@@ -0,0 +1,1193 @@
+use crate::constants::{LONG, SHORT};
+use crate::entries::calc_min_entry_qty;
+use crate::runner::order_calculators;
+use crate::types::{
+    BotParams, ExchangeParams, Order, OrderBook, OrderIdInfo, OrderType, Position, Side,
+    StateParams, TrailingPriceBundle,
+};
+use crate::utils::{
+    calc_new_psize_pprice, calc_pnl_long, calc_pnl_short, round_, round_dn, round_up,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+
+/// Merges consecutive orders on the same side whose prices are within one price step,
+/// summing their quantities. Price and order type are taken from the first order.
+pub fn coalesce_orders(orders: &[Order], exchange_params: &ExchangeParams) -> Vec<Order> {
+    let mut coalesced = Vec::<Order>::with_capacity(orders.len());
+    for order in orders {
+        if let Some(last) = coalesced.last_mut() {
+            // half a step tolerance so that adjacent ticks stay separate despite float error
+            if last.qty.signum() == order.qty.signum()
+                && (last.price - order.price).abs() < exchange_params.price_step * 0.5
+            {
+                last.qty = round_(last.qty + order.qty, exchange_params.qty_step);
+                continue;
+            }
+        }
+        coalesced.push(*order);
+    }
+    coalesced
+}
+
+/// Fits orders, nearest first, into the exchange's percent price band around mark, taken as
+/// the order book mid: mark * (1 - price_band_pct) to mark * (1 + price_band_pct). The
+/// nearest out of band entry is moved to the band edge, with qty cut to keep its cost when
+/// the move raises its price, and dropped if that leaves less than min qty. Further out of
+/// band entries and all out of band closes are dropped; they return as price approaches.
+/// No band if price_band_pct is zero.
+pub fn apply_price_band(
+    orders: &[Order],
+    order_book: &OrderBook,
+    exchange_params: &ExchangeParams,
+) -> Vec<Order> {
+    if exchange_params.price_band_pct <= 0.0 {
+        return orders.to_vec();
+    }
+    let mark_price = (order_book.bid + order_book.ask) / 2.0;
+    let floor = round_up(
+        mark_price * (1.0 - exchange_params.price_band_pct),
+        exchange_params.price_step,
+    );
+    let cap = round_dn(
+        mark_price * (1.0 + exchange_params.price_band_pct),
+        exchange_params.price_step,
+    );
+    let mut banded = Vec::with_capacity(orders.len());
+    let mut clamped = false;
+    for order in orders {
+        if order.price >= floor && order.price <= cap {
+            banded.push(*order);
+            continue;
+        }
+        if clamped || !order.order_type.is_entry() {
+            continue;
+        }
+        clamped = true;
+        let price = order.price.clamp(floor, cap);
+        let qty = if price > order.price {
+            round_dn(
+                order.qty.abs() * order.price / price,
+                exchange_params.qty_step,
+            )
+        } else {
+            order.qty.abs()
+        };
+        if qty >= calc_min_entry_qty(price, exchange_params) {
+            banded.push(Order::new(
+                qty * order.qty.signum(),
+                price,
+                order.order_type,
+            ));
+        }
+    }
+    banded
+}
+
+#[derive(Debug, Clone)]
+pub enum OrderSetViolation {
+    EntryCrossesClose {
+        entry: Order,
+        close: Order,
+    },
+    CrossesOrderBook {
+        order: Order,
+        bid: f64,
+        ask: f64,
+    },
+    DuplicatePrice {
+        first: Order,
+        second: Order,
+    },
+    ClosesExceedPosition {
+        pside: usize,
+        close_qty: f64,
+        position_size: f64,
+    },
+}
+
+impl fmt::Display for OrderSetViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OrderSetViolation::EntryCrossesClose { entry, close } => write!(
+                f,
+                "{} at {} crosses {} at {}",
+                entry.order_type, entry.price, close.order_type, close.price
+            ),
+            OrderSetViolation::CrossesOrderBook { order, bid, ask } => write!(
+                f,
+                "{} at {} crosses order book bid {} ask {}",
+                order.order_type, order.price, bid, ask
+            ),
+            OrderSetViolation::DuplicatePrice { first, second } => write!(
+                f,
+                "{} and {} share price {}",
+                first.order_type, second.order_type, first.price
+            ),
+            OrderSetViolation::ClosesExceedPosition {
+                pside,
+                close_qty,
+                position_size,
+            } => write!(
+                f,
+                "pside {}: closes total {} exceed position size {}",
+                pside, close_qty, position_size
+            ),
+        }
+    }
+}
+
+/// Checks that an ideal order set is internally consistent:
+/// - no entry priced through a close of the same side
+/// - no buy above ask or sell below bid (orders are meant to be post-only)
+/// - no two orders of the same side and direction at the same price
+/// - closes of a side do not sum to more than that side's position
+pub fn validate_order_set(
+    orders: &[Order],
+    order_book: &OrderBook,
+    position_long: &Position,
+    position_short: &Position,
+) -> Vec<OrderSetViolation> {
+    let mut violations = Vec::new();
+    for (i, order) in orders.iter().enumerate() {
+        if order.qty == 0.0 {
+            continue;
+        }
+        if (order.qty > 0.0 && order.price > order_book.ask)
+            || (order.qty < 0.0 && order.price < order_book.bid)
+        {
+            violations.push(OrderSetViolation::CrossesOrderBook {
+                order: *order,
+                bid: order_book.bid,
+                ask: order_book.ask,
+            });
+        }
+        for other in &orders[i + 1..] {
+            if other.qty != 0.0
+                && other.order_type.pside() == order.order_type.pside()
+                && other.qty.signum() == order.qty.signum()
+                && other.price == order.price
+            {
+                violations.push(OrderSetViolation::DuplicatePrice {
+                    first: *order,
+                    second: *other,
+                });
+            }
+        }
+        if !order.order_type.is_entry() {
+            continue;
+        }
+        // long entries must be below long closes, short entries above short closes
+        for close in orders.iter().filter(|close| {
+            close.order_type.is_close() && close.order_type.pside() == order.order_type.pside()
+        }) {
+            let crosses = match order.order_type.pside() {
+                LONG => order.price >= close.price,
+                _ => order.price <= close.price,
+            };
+            if crosses {
+                violations.push(OrderSetViolation::EntryCrossesClose {
+                    entry: *order,
+                    close: *close,
+                });
+            }
+        }
+    }
+    for (pside, position) in [(LONG, position_long), (SHORT, position_short)] {
+        let close_qty: f64 = orders
+            .iter()
+            .filter(|order| order.order_type.is_close() && order.order_type.pside() == pside)
+            .map(|order| order.qty.abs())
+            .sum();
+        // tolerate float error from summing rounded quantities
+        if close_qty > position.size.abs() * (1.0 + 1e-9) {
+            violations.push(OrderSetViolation::ClosesExceedPosition {
+                pside,
+                close_qty,
+                position_size: position.size,
+            });
+        }
+    }
+    violations
+}
+
+/// An order currently open on the exchange. qty is signed like Order: positive buys.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiveOrder {
+    pub id: String,
+    pub qty: f64,
+    pub price: f64,
+    pub side: Side,
+    pub reduce_only: bool,
+    pub created_ms: u64,
+}
+
+/// How far a live order may be from an ideal order and still be kept in its place, saving
+/// the rate limit weight of a replacement. Qty is within tolerance if it is within either
+/// qty_steps or qty_pct of the ideal qty. Orders older than max_age_ms are always replaced;
+/// zero disables the age limit. The default keeps only exact matches.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplacePolicy {
+    pub price_ticks: u32,
+    pub qty_steps: u32,
+    pub qty_pct: f64,
+    pub max_age_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OrderDiff {
+    pub cancel: Vec<LiveOrder>,
+    pub create: Vec<Order>,
+    pub keep: Vec<String>,
+}
+
+/// Whether live must be cancelled and ideal placed in its stead: true if they differ in side,
+/// direction or reduce_only, if price or qty is outside the policy's tolerance, or if live
+/// is older than max_age_ms at now.
+pub fn should_replace(
+    live: &LiveOrder,
+    ideal: &Order,
+    exchange_params: &ExchangeParams,
+    policy: &ReplacePolicy,
+    now_ms: u64,
+) -> bool {
+    if live.side.index() != ideal.order_type.pside()
+        || live.reduce_only != ideal.order_type.is_close()
+        || live.qty == 0.0
+        || live.qty.signum() != ideal.qty.signum()
+    {
+        return true;
+    }
+    if policy.max_age_ms > 0 && now_ms.saturating_sub(live.created_ms) > policy.max_age_ms {
+        return true;
+    }
+    // compare in whole ticks and steps so float error does not widen the tolerance
+    let price_ticks = ((live.price - ideal.price).abs() / exchange_params.price_step).round();
+    if price_ticks > policy.price_ticks as f64 {
+        return true;
+    }
+    let qty_steps = ((live.qty - ideal.qty).abs() / exchange_params.qty_step).round();
+    qty_steps > policy.qty_steps as f64
+        && qty_steps * exchange_params.qty_step > policy.qty_pct * ideal.qty.abs() * (1.0 + 1e-9)
+}
+
+/// Tries to match ideal order i, displacing earlier matches along an augmenting path.
+fn augment(
+    i: usize,
+    candidates: &[Vec<usize>],
+    live_match: &mut [Option<usize>],
+    visited: &mut [bool],
+) -> bool {
+    for &j in &candidates[i] {
+        if visited[j] {
+            continue;
+        }
+        visited[j] = true;
+        let free = match live_match[j] {
+            None => true,
+            Some(other) => augment(other, candidates, live_match, visited),
+        };
+        if free {
+            live_match[j] = Some(i);
+            return true;
+        }
+    }
+    false
+}
+
+/// Pairs live orders one to one with ideal orders they need not be replaced by, see
+/// should_replace, keeping as many live orders as possible and trying nearer prices first.
+/// The ideal index paired with each live order.
+fn match_live_orders(
+    ideal: &[&Order],
+    live: &[LiveOrder],
+    exchange_params: &ExchangeParams,
+    policy: &ReplacePolicy,
+    now_ms: u64,
+) -> Vec<Option<usize>> {
+    let candidates: Vec<Vec<usize>> = ideal
+        .iter()
+        .map(|order| {
+            let mut indices: Vec<usize> = (0..live.len())
+                .filter(|&j| !should_replace(&live[j], order, exchange_params, policy, now_ms))
+                .collect();
+            indices.sort_by(|&a, &b| {
+                (live[a].price - order.price)
+                    .abs()
+                    .total_cmp(&(live[b].price - order.price).abs())
+            });
+            indices
+        })
+        .collect();
+    let mut live_match: Vec<Option<usize>> = vec![None; live.len()];
+    for i in 0..ideal.len() {
+        let mut visited = vec![false; live.len()];
+        augment(i, &candidates, &mut live_match, &mut visited);
+    }
+    live_match
+}
+
+/// Reconciles the ideal order set against open exchange orders with minimal churn.
+/// Live orders paired with an ideal order by match_live_orders are kept, the rest cancelled,
+/// and unpaired ideal orders created. Zero qty ideal orders are ignored.
+pub fn diff_orders(
+    ideal: &[Order],
+    live: &[LiveOrder],
+    exchange_params: &ExchangeParams,
+    policy: &ReplacePolicy,
+    now_ms: u64,
+) -> OrderDiff {
+    let ideal: Vec<&Order> = ideal.iter().filter(|order| order.qty != 0.0).collect();
+    let live_match = match_live_orders(&ideal, live, exchange_params, policy, now_ms);
+    let mut ideal_matched = vec![false; ideal.len()];
+    let mut diff = OrderDiff::default();
+    for (live_order, matched) in live.iter().zip(&live_match) {
+        match matched {
+            Some(i) => {
+                ideal_matched[*i] = true;
+                diff.keep.push(live_order.id.clone());
+            }
+            None => diff.cancel.push(live_order.clone()),
+        }
+    }
+    for (order, matched) in ideal.iter().zip(&ideal_matched) {
+        if !matched {
+            diff.create.push(**order);
+        }
+    }
+    diff
+}
+
+/// Assigns the open orders of side found after a restart to the intents the bot would place
+/// now. An order whose id was made by Order::custom_id for symbol and side takes the order
+/// type in its id. The others are paired with the remaining ideal orders as in diff_orders,
+/// ignoring max_age_ms, and take the paired order's type; None marks an unrecognized order,
+/// to be cancelled. Trailing extrema are lost on restart, so the ideal orders are computed
+/// from a reset TrailingPriceBundle. Live orders of the other side are left out.
+#[allow(clippy::too_many_arguments)]
+pub fn classify_existing_orders(
+    symbol: &str,
+    live: &[LiveOrder],
+    side: Side,
+    exchange_params: &ExchangeParams,
+    state_params: &StateParams,
+    bot_params: &BotParams,
+    position: &Position,
+    policy: &ReplacePolicy,
+) -> Vec<(LiveOrder, Option<OrderType>)> {
+    let trailing_price_bundle = TrailingPriceBundle::default();
+    let (calc_entries, calc_closes) = order_calculators(side.index(), bot_params);
+    let mut ideal = calc_entries(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        &trailing_price_bundle,
+    );
+    ideal.extend(calc_closes(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        &trailing_price_bundle,
+    ));
+    let live: Vec<&LiveOrder> = live.iter().filter(|order| order.side == side).collect();
+    let tagged_types: Vec<Option<OrderType>> = live
+        .iter()
+        .map(|order| {
+            OrderIdInfo::parse(&order.id)
+                .filter(|info| {
+                    info.matches_symbol(symbol)
+                        && info.order_type.side() == Some(side)
+                        && info.order_type.is_close() == order.reduce_only
+                })
+                .map(|info| info.order_type)
+        })
+        .collect();
+    // intents already held by a tagged order are not up for price matching
+    let tagged_ids: HashSet<&str> = live
+        .iter()
+        .zip(&tagged_types)
+        .filter(|(_, order_type)| order_type.is_some())
+        .map(|(order, _)| order.id.as_str())
+        .collect();
+    let ideal: Vec<&Order> = ideal
+        .iter()
+        .filter(|order| {
+            order.qty != 0.0
+                && !tagged_ids.contains(order.custom_id(symbol, exchange_params).as_str())
+        })
+        .collect();
+    let untagged: Vec<LiveOrder> = live
+        .iter()
+        .zip(&tagged_types)
+        .filter(|(_, order_type)| order_type.is_none())
+        .map(|(order, _)| (*order).clone())
+        .collect();
+    let policy = ReplacePolicy {
+        max_age_ms: 0,
+        ..*policy
+    };
+    let mut untagged_types = match_live_orders(&ideal, &untagged, exchange_params, &policy, 0)
+        .into_iter()
+        .map(|matched| matched.map(|i| ideal[i].order_type));
+    live.iter()
+        .zip(tagged_types)
+        .map(|(order, tagged_type)| {
+            let order_type = match tagged_type {
+                Some(order_type) => Some(order_type),
+                None => untagged_types.next().flatten(),
+            };
+            ((*order).clone(), order_type)
+        })
+        .collect()
+}
+
+/// Side whose resting orders a position change in one-way mode invalidates: the previous
+/// position's side if the position flattened or flipped. Its closes now exceed the position
+/// and its entries were spaced from a position that is gone; cancel the live orders of that
+/// side before diffing the new ideal orders. None for a change that keeps the side, e.g. an
+/// add or a partial close, and for opening from flat.
+pub fn invalidated_side(prev_position: &Position, new_position: &Position) -> Option<Side> {
+    // signum of 0.0 is 1.0, so flat is checked apart
+    if prev_position.size == 0.0
+        || (new_position.size != 0.0 && new_position.size.signum() == prev_position.size.signum())
+    {
+        return None;
+    }
+    if prev_position.size > 0.0 {
+        Some(Side::Long)
+    } else {
+        Some(Side::Short)
+    }
+}
+
+/// Whether all resting orders are to be cancelled, i.e. the position flattened or flipped.
+/// See invalidated_side.
+pub fn should_cancel_all(prev_position: &Position, new_position: &Position) -> bool {
+    invalidated_side(prev_position, new_position).is_some()
+}
+
+/// One exchange request of a reconciliation cycle.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderAction {
+    Cancel { symbol: String, order: LiveOrder },
+    Create { symbol: String, order: Order },
+}
+
+impl OrderAction {
+    pub fn symbol(&self) -> &str {
+        match self {
+            OrderAction::Cancel { symbol, .. } | OrderAction::Create { symbol, .. } => symbol,
+        }
+    }
+}
+
+/// Priority tier and distance from mark as a fraction of mark; lower ranks first.
+fn action_rank(action: &OrderAction, order_book: &OrderBook) -> (u8, f64) {
+    let (qty, price, is_close) = match action {
+        OrderAction::Cancel { order, .. } => (order.qty, order.price, order.reduce_only),
+        OrderAction::Create { order, .. } => (order.qty, order.price, order.order_type.is_close()),
+    };
+    if let OrderAction::Cancel { .. } = action {
+        // resting orders the exchange should not hold, e.g. after a gap through the grid
+        if qty == 0.0
+            || price <= 0.0
+            || (qty > 0.0 && price > order_book.ask)
+            || (qty < 0.0 && price < order_book.bid)
+        {
+            return (0, 0.0);
+        }
+    }
+    let mark_price = (order_book.bid + order_book.ask) / 2.0;
+    let distance = if mark_price > 0.0 {
+        (price - mark_price).abs() / mark_price
+    } else {
+        f64::INFINITY
+    };
+    (if is_close { 1 } else { 2 }, distance)
+}
+
+/// Orders the actions of all symbols' diffs by importance and splits them at budget, the
+/// number of requests the rate limit allows this cycle: (sent now, deferred). Cancels of
+/// invalid or crossing orders rank first, then closes, then entries, each nearest to the
+/// market first, measured from the symbol's order book mid. At equal distance cancels go
+/// before creates, freeing margin and position for them; remaining ties keep input order.
+pub fn prioritize_actions(
+    diff_per_symbol: &[(String, OrderDiff, OrderBook)],
+    budget: usize,
+) -> (Vec<OrderAction>, Vec<OrderAction>) {
+    let mut ranked = Vec::new();
+    for (symbol, diff, order_book) in diff_per_symbol {
+        let cancels = diff.cancel.iter().map(|order| OrderAction::Cancel {
+            symbol: symbol.clone(),
+            order: order.clone(),
+        });
+        let creates = diff.create.iter().map(|order| OrderAction::Create {
+            symbol: symbol.clone(),
+            order: *order,
+        });
+        for action in cancels.chain(creates) {
+            ranked.push((action_rank(&action, order_book), action));
+        }
+    }
+    ranked.sort_by(|((tier_a, distance_a), a), ((tier_b, distance_b), b)| {
+        let is_create = |action: &OrderAction| matches!(action, OrderAction::Create { .. });
+        tier_a
+            .cmp(tier_b)
+            .then(distance_a.total_cmp(distance_b))
+            .then(is_create(a).cmp(&is_create(b)))
+    });
+    let mut actions: Vec<OrderAction> = ranked.into_iter().map(|(_, action)| action).collect();
+    let deferred = actions.split_off(budget.min(actions.len()));
+    (actions, deferred)
+}
+
+/// A fill as reported by the exchange. qty is signed: positive buys.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FillEvent {
+    pub id: Option<String>, // exchange fill id, for dropping fills received twice
+    pub qty: f64,
+    pub price: f64,
+    pub ts: u64,
+}
+
+/// Rebuilds a net position by applying fills to starting in timestamp order, e.g. after a
+/// reconnect. Returns the position and the realized pnl, fees excluded. Fills repeating an
+/// earlier id are dropped. A fill larger than the position closes it and opens the remainder
+/// on the opposite side at the fill price.
+pub fn reconcile_position(
+    starting: &Position,
+    fills: &[FillEvent],
+    exchange_params: &ExchangeParams,
+) -> (Position, f64) {
+    let qty_step = exchange_params.qty_step;
+    let mut sorted: Vec<&FillEvent> = fills.iter().collect();
+    sorted.sort_by_key(|fill| fill.ts);
+    let mut seen_ids = HashSet::new();
+    let mut position = *starting;
+    let mut realized_pnl = 0.0;
+    for fill in sorted {
+        if let Some(id) = &fill.id {
+            if !seen_ids.insert(id.as_str()) {
+                continue;
+            }
+        }
+        if fill.qty == 0.0 {
+            continue;
+        }
+        if position.size == 0.0 || position.size.signum() == fill.qty.signum() {
+            let (size, price) = calc_new_psize_pprice(
+                position.size,
+                position.price,
+                fill.qty,
+                fill.price,
+                qty_step,
+            );
+            position = Position { size, price };
+            continue;
+        }
+        let close_qty = if fill.qty.abs() > position.size.abs() {
+            -position.size
+        } else {
+            fill.qty
+        };
+        realized_pnl += if position.size > 0.0 {
+            calc_pnl_long(
+                position.price,
+                fill.price,
+                close_qty,
+                exchange_params.c_mult,
+            )
+        } else {
+            calc_pnl_short(
+                position.price,
+                fill.price,
+                close_qty,
+                exchange_params.c_mult,
+            )
+        };
+        let reopen_qty = round_(fill.qty - close_qty, qty_step);
+        let size = round_(position.size + close_qty, qty_step);
+        position = if reopen_qty != 0.0 {
+            Position {
+                size: reopen_qty,
+                price: fill.price,
+            }
+        } else if size == 0.0 {
+            Position::default()
+        } else {
+            Position {
+                size,
+                price: position.price,
+            }
+        };
+    }
+    (position, realized_pnl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn coalesce_merges_same_tick_orders_on_the_same_side() {
+        let exchange_params = fixtures::exchange_params();
+        let orders = [
+            Order::new(0.001, 60000.0, OrderType::EntryGridNormalLong),
+            Order::new(0.002, 60000.0 + 1e-9, OrderType::EntryGridCroppedLong),
+            // one tick away stays separate
+            Order::new(0.003, 59999.9, OrderType::EntryGridNormalLong),
+            // same price, other side
+            Order::new(-0.004, 59999.9, OrderType::CloseGridShort),
+            Order::new(-0.001, 59999.9, OrderType::CloseGridShort),
+        ];
+        assert_eq!(
+            coalesce_orders(&orders, &exchange_params),
+            vec![
+                Order::new(0.003, 60000.0, OrderType::EntryGridNormalLong),
+                Order::new(0.003, 59999.9, OrderType::EntryGridNormalLong),
+                Order::new(-0.005, 59999.9, OrderType::CloseGridShort),
+            ]
+        );
+        assert!(coalesce_orders(&[], &exchange_params).is_empty());
+    }
+
+    #[test]
+    fn order_set_violations() {
+        let order_book = OrderBook {
+            bid: 60000.0,
+            ask: 60000.1,
+        };
+        let long = Position {
+            size: 0.01,
+            price: 60000.0,
+        };
+        let short = Position::default();
+        let entry = Order::new(0.001, 59000.0, OrderType::EntryGridNormalLong);
+        let close = Order::new(-0.01, 61000.0, OrderType::CloseGridLong);
+        assert!(validate_order_set(&[entry, close], &order_book, &long, &short).is_empty());
+        // zero qty orders are ignored
+        let empty = Order::new(0.0, 70000.0, OrderType::EntryGridNormalLong);
+        assert!(validate_order_set(&[entry, close, empty], &order_book, &long, &short).is_empty());
+
+        let crossing_entry = Order::new(0.001, 61000.0, OrderType::EntryGridNormalLong);
+        let violations = validate_order_set(&[crossing_entry, close], &order_book, &long, &short);
+        assert!(matches!(
+            violations[..],
+            [
+                OrderSetViolation::CrossesOrderBook { .. },
+                OrderSetViolation::EntryCrossesClose { .. }
+            ]
+        ));
+        let duplicate = Order::new(0.002, 59000.0, OrderType::EntryGridCroppedLong);
+        assert!(matches!(
+            validate_order_set(&[entry, duplicate, close], &order_book, &long, &short)[..],
+            [OrderSetViolation::DuplicatePrice { first, second }] if first == entry && second == duplicate
+        ));
+        let second_close = Order::new(-0.001, 62000.0, OrderType::CloseGridLong);
+        assert!(matches!(
+            validate_order_set(&[close, second_close], &order_book, &long, &short)[..],
+            [OrderSetViolation::ClosesExceedPosition { pside: LONG, position_size, .. }] if position_size == 0.01
+        ));
+        // a short close buying above ask
+        let short_close = Order::new(0.001, 61000.0, OrderType::CloseGridShort);
+        let short = Position {
+            size: -0.001,
+            price: 60000.0,
+        };
+        assert!(matches!(
+            validate_order_set(&[short_close], &order_book, &long, &short)[..],
+            [OrderSetViolation::CrossesOrderBook { .. }]
+        ));
+    }
+
+    fn live_order(id: &str, order: &Order) -> LiveOrder {
+        LiveOrder {
+            id: id.to_string(),
+            qty: order.qty,
+            price: order.price,
+            side: if order.order_type.pside() == LONG {
+                Side::Long
+            } else {
+                Side::Short
+            },
+            reduce_only: order.order_type.is_close(),
+            created_ms: 0,
+        }
+    }
+
+    fn ids(diff: &OrderDiff) -> (Vec<&str>, Vec<&str>) {
+        (
+            diff.keep.iter().map(String::as_str).collect(),
+            diff.cancel.iter().map(|order| order.id.as_str()).collect(),
+        )
+    }
+
+    #[test]
+    fn diff_keeps_matches_cancels_and_creates_the_rest() {
+        let exchange_params = fixtures::exchange_params();
+        let entry = Order::new(0.01, 59000.0, OrderType::EntryGridNormalLong);
+        let close = Order::new(-0.01, 61000.0, OrderType::CloseGridLong);
+        let stale = Order::new(0.01, 58000.0, OrderType::EntryGridNormalLong);
+        let new = Order::new(-0.01, 62000.0, OrderType::CloseGridLong);
+        let live = [
+            live_order("entry", &entry),
+            live_order("close", &close),
+            live_order("stale", &stale),
+        ];
+        let diff = diff_orders(
+            &[entry, close, new, Order::default()],
+            &live,
+            &exchange_params,
+            &ReplacePolicy::default(),
+            0,
+        );
+        assert_eq!(ids(&diff), (vec!["entry", "close"], vec!["stale"]));
+        assert_eq!(diff.create, vec![new]);
+        // same price and qty but not reduce only, as for a short entry, is replaced
+        let short_entry = LiveOrder {
+            reduce_only: false,
+            side: Side::Short,
+            ..live_order("short", &close)
+        };
+        let diff = diff_orders(
+            &[close],
+            &[short_entry],
+            &exchange_params,
+            &ReplacePolicy::default(),
+            0,
+        );
+        assert_eq!(ids(&diff), (vec![], vec!["short"]));
+        assert_eq!(diff.create, vec![close]);
+    }
+
+    #[test]
+    fn diff_keeps_shifted_overlapping_grid() {
+        let exchange_params = fixtures::exchange_params();
+        let policy = ReplacePolicy {
+            price_ticks: 2,
+            ..Default::default()
+        };
+        // ideal grid moved one tick up from the live one: keep everything
+        let ideal: Vec<Order> = (0..10)
+            .map(|k| {
+                Order::new(
+                    0.01,
+                    59000.0 - 100.0 * k as f64,
+                    OrderType::EntryGridNormalLong,
+                )
+            })
+            .collect();
+        let live: Vec<LiveOrder> = ideal
+            .iter()
+            .enumerate()
+            .map(|(k, order)| LiveOrder {
+                price: order.price - 0.1,
+                ..live_order(&k.to_string(), order)
+            })
+            .collect();
+        let diff = diff_orders(&ideal, &live, &exchange_params, &policy, 0);
+        assert_eq!(
+            (diff.keep.len(), diff.cancel.len(), diff.create.len()),
+            (10, 0, 0)
+        );
+        // greedy nearest-first would give 100.1 to 100.2 and leave 100.0 without a match
+        let ideal = [
+            Order::new(0.01, 100.2, OrderType::EntryGridNormalLong),
+            Order::new(0.01, 100.0, OrderType::EntryGridNormalLong),
+        ];
+        let live = [
+            live_order("a", &ideal[1].with_price(100.1)),
+            live_order("b", &ideal[0].with_price(100.3)),
+        ];
+        let diff = diff_orders(&ideal, &live, &exchange_params, &policy, 0);
+        assert_eq!(ids(&diff), (vec!["a", "b"], vec![]));
+        assert!(diff.create.is_empty());
+    }
+
+    #[test]
+    fn diff_never_matches_two_live_orders_to_one_ideal() {
+        let exchange_params = fixtures::exchange_params();
+        let entry = Order::new(0.01, 59000.0, OrderType::EntryGridNormalLong);
+        let live = [live_order("first", &entry), live_order("second", &entry)];
+        let diff = diff_orders(
+            &[entry],
+            &live,
+            &exchange_params,
+            &ReplacePolicy::default(),
+            0,
+        );
+        assert_eq!(ids(&diff), (vec!["first"], vec!["second"]));
+        assert!(diff.create.is_empty());
+        // past max age, even an exact match is replaced
+        let policy = ReplacePolicy {
+            max_age_ms: 60_000,
+            ..Default::default()
+        };
+        let diff = diff_orders(&[entry], &live[..1], &exchange_params, &policy, 60_001);
+        assert_eq!(ids(&diff), (vec![], vec!["first"]));
+        assert_eq!(diff.create, vec![entry]);
+    }
+
+    fn fill(id: Option<&str>, qty: f64, price: f64, ts: u64) -> FillEvent {
+        FillEvent {
+            id: id.map(str::to_string),
+            qty,
+            price,
+            ts,
+        }
+    }
+
+    #[test]
+    fn reconcile_applies_fills_in_timestamp_order() {
+        let fills = [
+            fill(None, -0.001, 63000.0, 3000),
+            fill(None, 0.001, 60000.0, 1000),
+            fill(None, 0.001, 62000.0, 2000),
+        ];
+        let (position, realized_pnl) =
+            reconcile_position(&Position::default(), &fills, &fixtures::exchange_params());
+        assert_eq!((position.size, position.price), (0.001, 61000.0));
+        assert!((realized_pnl - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn reconcile_drops_repeated_fill_ids() {
+        let fills = [
+            fill(Some("a"), 0.001, 60000.0, 1000),
+            fill(Some("a"), 0.001, 60000.0, 1000),
+            fill(Some("b"), 0.001, 60000.0, 2000),
+            // fills without an id are never deduped
+            fill(None, 0.001, 60000.0, 3000),
+            fill(None, 0.001, 60000.0, 3000),
+        ];
+        let (position, realized_pnl) =
+            reconcile_position(&Position::default(), &fills, &fixtures::exchange_params());
+        assert_eq!((position.size, position.price), (0.004, 60000.0));
+        assert_eq!(realized_pnl, 0.0);
+    }
+
+    #[test]
+    fn reconcile_exactly_flat_resets_price() {
+        let starting = Position {
+            size: 0.002,
+            price: 60000.0,
+        };
+        let fills = [
+            fill(None, -0.001, 61000.0, 1000),
+            fill(None, -0.001, 62000.0, 2000),
+        ];
+        let (position, realized_pnl) =
+            reconcile_position(&starting, &fills, &fixtures::exchange_params());
+        assert_eq!((position.size, position.price), (0.0, 0.0));
+        assert!((realized_pnl - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn reconcile_flips_side_at_the_fill_price() {
+        let starting = Position {
+            size: 0.001,
+            price: 60000.0,
+        };
+        let (position, realized_pnl) = reconcile_position(
+            &starting,
+            &[fill(None, -0.003, 59000.0, 1000)],
+            &fixtures::exchange_params(),
+        );
+        assert_eq!((position.size, position.price), (-0.002, 59000.0));
+        assert!((realized_pnl + 1.0).abs() < 1e-9);
+        // and back: the short is closed in profit, the remainder opens long
+        let (position, realized_pnl) = reconcile_position(
+            &position,
+            &[fill(None, 0.003, 58000.0, 2000)],
+            &fixtures::exchange_params(),
+        );
+        assert_eq!((position.size, position.price), (0.001, 58000.0));
+        assert!((realized_pnl - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn should_replace_within_tolerances() {
+        let exchange_params = fixtures::exchange_params();
+        let ideal = Order::new(0.1, 60000.0, OrderType::EntryGridNormalLong);
+        let policy = ReplacePolicy {
+            price_ticks: 2,
+            qty_steps: 3,
+            qty_pct: 0.05,
+            max_age_ms: 60000,
+        };
+        let replace = |qty: f64, price: f64, policy: &ReplacePolicy, now_ms: u64| {
+            let live = LiveOrder {
+                qty,
+                price,
+                ..live_order("a", &ideal)
+            };
+            should_replace(&live, &ideal, &exchange_params, policy, now_ms)
+        };
+        assert!(!replace(0.1, 60000.0, &policy, 0));
+        assert!(!replace(0.1, 60000.0, &ReplacePolicy::default(), 0));
+        // price within 2 ticks either way
+        assert!(!replace(0.1, 60000.2, &policy, 0));
+        assert!(!replace(0.1, 59999.8, &policy, 0));
+        assert!(replace(0.1, 60000.3, &policy, 0));
+        assert!(replace(0.1, 60000.1, &ReplacePolicy::default(), 0));
+        // qty within 3 steps or 5%, whichever is wider
+        assert!(!replace(0.103, 60000.0, &policy, 0));
+        assert!(!replace(0.095, 60000.0, &policy, 0));
+        assert!(!replace(0.105, 60000.0, &policy, 0));
+        assert!(replace(0.106, 60000.0, &policy, 0));
+        let steps_only = ReplacePolicy {
+            qty_pct: 0.0,
+            ..policy
+        };
+        assert!(!replace(0.103, 60000.0, &steps_only, 0));
+        assert!(replace(0.104, 60000.0, &steps_only, 0));
+        // age limit, disabled at zero
+        assert!(!replace(0.1, 60000.0, &policy, 60000));
+        assert!(replace(0.1, 60000.0, &policy, 60001));
+        let no_age_limit = ReplacePolicy {
+            max_age_ms: 0,
+            ..policy
+        };
+        assert!(!replace(0.1, 60000.0, &no_age_limit, u64::MAX));
+        // never kept across side, direction or reduce_only
+        let live = live_order("a", &ideal);
+        for mismatched in [
+            LiveOrder {
+                side: Side::Short,
+                ..live.clone()
+            },
+            LiveOrder {
+                reduce_only: true,
+                ..live.clone()
+            },
+            LiveOrder {
+                qty: -0.1,
+                ..live.clone()
+            },
+            LiveOrder { qty: 0.0, ..live },
+        ] {
+            let wide = ReplacePolicy {
+                price_ticks: 1000,
+                qty_steps: 1000,
+                qty_pct: 10.0,
+                max_age_ms: 0,
+            };
+            assert!(should_replace(
+                &mismatched,
+                &ideal,
+                &exchange_params,
+                &wide,
+                0
+            ));
+        }
+    }
+
+    #[test]
+    fn price_band_clamps_nearest_entry_and_drops_the_rest() {
+        // mark 60000.0: band from 58800.0 to 61200.0
+        let exchange_params = ExchangeParams {
+            price_band_pct: 0.02,
+            ..fixtures::exchange_params()
+        };
+        let order_book = OrderBook {
+            bid: 59999.9,
+            ask: 60000.1,
+        };
+        let orders = [
+            Order::new(0.01, 59000.0, OrderType::EntryGridNormalLong),
+            Order::new(0.01, 58000.0, OrderType::EntryGridNormalLong),
+            Order::new(0.02, 57000.0, OrderType::EntryGridNormalLong),
+            Order::new(-0.01, 61000.0, OrderType::CloseGridLong),
+            Order::new(-0.01, 62000.0, OrderType::CloseGridLong),
+        ];
+        // raised to the floor at the same cost
+        assert_eq!(
+            apply_price_band(&orders, &order_book, &exchange_params),
+            [
+                orders[0],
+                Order::new(0.009, 58800.0, OrderType::EntryGridNormalLong),
+                orders[3],
+            ]
+        );
+        // lowered to the cap at the same qty
+        let short = [Order::new(-0.01, 62500.0, OrderType::EntryGridNormalShort)];
+        assert_eq!(
+            apply_price_band(&short, &order_book, &exchange_params),
+            [Order::new(-0.01, 61200.0, OrderType::EntryGridNormalShort)]
+        );
+        // dropped if the cut leaves less than min qty
+        let min_cost = ExchangeParams {
+            min_cost: 550.0,
+            ..exchange_params
+        };
+        assert_eq!(apply_price_band(&orders[1..2], &order_book, &min_cost), []);
+        let no_band = fixtures::exchange_params();
+        assert_eq!(apply_price_band(&orders, &order_book, &no_band), orders);
+    }
+
+    #[test]
+    fn prioritize_actions_ranks_and_splits_at_budget() {
+        let book = |price: f64| OrderBook {
+            bid: price,
+            ask: price,
+        };
+        let crossing = live_order(
+            "a0",
+            &Order::new(0.001, 60100.0, OrderType::EntryGridNormalLong),
+        );
+        let stale_entry = live_order(
+            "a1",
+            &Order::new(0.001, 59400.0, OrderType::EntryGridNormalLong),
+        );
+        let stale_close = live_order("b0", &Order::new(-1.0, 101.0, OrderType::CloseGridLong));
+        let close_a = Order::new(-0.001, 60300.0, OrderType::CloseGridLong);
+        let entry_a = Order::new(0.001, 59700.0, OrderType::EntryGridNormalLong);
+        let close_b = Order::new(-2.0, 101.0, OrderType::CloseGridLong);
+        let entry_b = Order::new(1.0, 99.9, OrderType::EntryGridNormalLong);
+        let diff_per_symbol = [
+            (
+                "A".to_string(),
+                OrderDiff {
+                    cancel: vec![stale_entry.clone(), crossing.clone()],
+                    create: vec![entry_a, close_a],
+                    keep: vec![],
+                },
+                book(60000.0),
+            ),
+            (
+                "B".to_string(),
+                OrderDiff {
+                    cancel: vec![stale_close.clone()],
+                    create: vec![close_b, entry_b],
+                    keep: vec![],
+                },
+                book(100.0),
+            ),
+        ];
+        let cancel = |symbol: &str, order: &LiveOrder| OrderAction::Cancel {
+            symbol: symbol.to_string(),
+            order: order.clone(),
+        };
+        let create = |symbol: &str, order: Order| OrderAction::Create {
+            symbol: symbol.to_string(),
+            order,
+        };
+        // crossing cancel, then closes, then entries, each nearest the mid first; at equal
+        // distance the cancel goes before the create
+        let ranked = vec![
+            cancel("A", &crossing),
+            create("A", close_a),
+            cancel("B", &stale_close),
+            create("B", close_b),
+            create("B", entry_b),
+            create("A", entry_a),
+            cancel("A", &stale_entry),
+        ];
+        let (sent, deferred) = prioritize_actions(&diff_per_symbol, 4);
+        assert_eq!(sent, ranked[..4]);
+        assert_eq!(deferred, ranked[4..]);
+        assert_eq!(
+            prioritize_actions(&diff_per_symbol, 0),
+            (vec![], ranked.clone())
+        );
+        assert_eq!(prioritize_actions(&diff_per_symbol, 100), (ranked, vec![]));
+        assert_eq!(prioritize_actions(&[], 4), (vec![], vec![]));
+    }
+
+    #[test]
+    fn classify_existing_orders_by_tag_then_by_price() {
+        let exchange_params = fixtures::exchange_params();
+        let state_params = fixtures::state_params(60000.0);
+        let bot_params = fixtures::bot_params();
+        let position = fixtures::position_long(60000.0);
+        let (calc_entries, calc_closes) = order_calculators(LONG, &bot_params);
+        let trailing_price_bundle = TrailingPriceBundle::default();
+        let entry = calc_entries(
+            &exchange_params,
+            &state_params,
+            &bot_params,
+            &position,
+            &trailing_price_bundle,
+        )[0];
+        let close = calc_closes(
+            &exchange_params,
+            &state_params,
+            &bot_params,
+            &position,
+            &trailing_price_bundle,
+        )[0];
+        let far = Order::new(0.005, 30000.0, entry.order_type);
+        // tagged for this symbol: adopted by its id even though its price moved
+        let tagged = LiveOrder {
+            id: entry.custom_id("BTCUSDT", &exchange_params),
+            price: entry.price - 500.0,
+            ..live_order("", &entry)
+        };
+        // untagged, matched by price and qty
+        let untagged = live_order("manual-1", &close);
+        // tagged for another symbol and nowhere near an ideal order
+        let foreign = live_order(&far.custom_id("ETHUSDT", &exchange_params), &far);
+        let short = live_order(
+            "manual-2",
+            &Order::new(-0.001, 61000.0, OrderType::EntryGridNormalShort),
+        );
+        let classified = classify_existing_orders(
+            "BTCUSDT",
+            &[tagged.clone(), short, untagged.clone(), foreign.clone()],
+            Side::Long,
+            &exchange_params,
+            &state_params,
+            &bot_params,
+            &position,
+            &ReplacePolicy::default(),
+        );
+        assert_eq!(
+            classified,
+            vec![
+                (tagged, Some(entry.order_type)),
+                (untagged, Some(close.order_type)),
+                (foreign, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn position_flip_and_flatten_invalidate_the_previous_side() {
+        let position = |size: f64| Position {
+            size,
+            price: 60000.0,
+        };
+        for (prev, new, expected) in [
+            // flips
+            (0.01, -0.005, Some(Side::Long)),
+            (-0.01, 0.02, Some(Side::Short)),
+            // flattens
+            (0.01, 0.0, Some(Side::Long)),
+            (-0.01, 0.0, Some(Side::Short)),
+            // adds and partial closes keep the side
+            (0.01, 0.02, None),
+            (-0.01, -0.005, None),
+            // opening from flat and staying flat
+            (0.0, 0.01, None),
+            (0.0, -0.01, None),
+            (0.0, 0.0, None),
+        ] {
+            assert_eq!(
+                invalidated_side(&position(prev), &position(new)),
+                expected,
+                "{} -> {}",
+                prev,
+                new
+            );
+            assert_eq!(
+                should_cancel_all(&position(prev), &position(new)),
+                expected.is_some()
+            );
+        }
+    }
+}
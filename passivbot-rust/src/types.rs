@@ -1,13 +1,23 @@
+use crate::constants::{LONG, NO_POS, SHORT};
+use crate::utils::{calc_new_psize_pprice, qty_to_cost, round_};
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
 use std::fmt;
+use std::str::FromStr;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ExchangeParams {
     pub qty_step: f64,
     pub price_step: f64,
     pub min_qty: f64,
     pub min_cost: f64,
     pub c_mult: f64,
+    pub fee_rate: f64,          // maker fee as a fraction of notional
+    pub max_position_size: f64, // cap on abs position size in qty units; 0.0 is unlimited
+    pub price_band_pct: f64,    // max order price distance from mark, see apply_price_band
 }
 
 impl Default for ExchangeParams {
@@ -18,36 +28,148 @@ impl Default for ExchangeParams {
             min_qty: 0.00001,
             min_cost: 1.0,
             c_mult: 1.0,
+            fee_rate: 0.0,
+            max_position_size: 0.0,
+            price_band_pct: 0.0,
         }
     }
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct BacktestParams {
     pub starting_balance: f64,
     pub maker_fee: f64,
     pub symbols: Vec<String>,
+    pub balance_mode: BalanceMode,
+    pub latency_candles: usize, // candles before recomputed orders become active; runner only
+    pub max_fill_volume_fraction: f64, // max share of candle volume an order may fill; 0.0 is unlimited; runner only
+    pub strict_order_validation: bool, // halt on inconsistent order sets; runner only
+    pub first_timestamp_ms: u64, // of the first hlcvs candle, 1m apart; multi symbol backtest only
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BalanceMode {
+    #[default]
+    Compounding,
+    Fixed,         // always size orders from starting balance
+    CappedAt(f64), // compound up to a maximum balance
+}
+
+impl BalanceMode {
+    /// Balance fed to order sizing. Realized PnL still accrues to the actual balance.
+    pub fn sizing_balance(&self, balance: f64, starting_balance: f64) -> f64 {
+        match self {
+            BalanceMode::Compounding => balance,
+            BalanceMode::Fixed => starting_balance,
+            BalanceMode::CappedAt(cap) => balance.min(*cap),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PositionMode {
+    #[default]
+    Hedge, // long and short held independently
+    OneWay, // at most one side holds a position at a time
 }
 
-#[derive(Default, Debug, Clone, Copy)]
+/// Operator override on which orders the bot may place.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TradingMode {
+    #[default]
+    Normal,
+    GracefulStop, // no new positions; existing ones keep re-entering and closing
+    Panic,        // close everything at market, see calc_panic_orders
+    Manual,       // bot places nothing
+    TpOnly,       // closes only
+}
+
+impl TradingMode {
+    /// Whether orders of this type may be placed. Panic allows only CloseStop orders.
+    pub fn allows(&self, order_type: OrderType) -> bool {
+        match self {
+            TradingMode::Normal => true,
+            TradingMode::GracefulStop => order_type.kind() != OrderKind::EntryInitial,
+            TradingMode::Panic => order_type.kind() == OrderKind::CloseStop,
+            TradingMode::Manual => false,
+            TradingMode::TpOnly => order_type.is_close(),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Position {
     pub size: f64,
     pub price: f64,
 }
 
+impl Position {
+    /// self if its size fits side: not negative for long, not positive for short. Side
+    /// specific calculators take positions in this form; a flat position fits either side.
+    pub fn for_side(&self, side: Side) -> Result<Position, SideError> {
+        let wrong_sign = match side {
+            Side::Long => self.size < 0.0,
+            Side::Short => self.size > 0.0,
+        };
+        if wrong_sign {
+            Err(SideError {
+                side,
+                size: self.size,
+            })
+        } else {
+            Ok(*self)
+        }
+    }
+
+    /// Hypothetical position after order fills in full, e.g. for computing orders against a
+    /// local fill not yet confirmed by the exchange. Entries average into the position price;
+    /// closes keep it and never close more than the position holds.
+    pub fn project_fill(&self, order: &Order, qty_step: f64) -> Position {
+        if order.order_type.is_close() {
+            if self.size == 0.0 {
+                return *self;
+            }
+            let fill_qty = if self.size > 0.0 {
+                order.qty.max(-self.size)
+            } else {
+                order.qty.min(-self.size)
+            };
+            let new_psize = round_(self.size + fill_qty, qty_step);
+            if new_psize == 0.0 {
+                Position::default()
+            } else {
+                Position {
+                    size: new_psize,
+                    price: self.price,
+                }
+            }
+        } else {
+            let (size, price) =
+                calc_new_psize_pprice(self.size, self.price, order.qty, order.price, qty_step);
+            Position { size, price }
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct Positions {
     pub long: HashMap<usize, Position>,
     pub short: HashMap<usize, Position>,
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct EMABands {
     pub upper: f64,
     pub lower: f64,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// An order with qty 0.0 must never be placed, whatever its type. The calculators return
+/// Order::default() (type Empty) when no order of that kind applies at all, e.g. flat position,
+/// zero balance or zero wallet_exposure_limit. A zero qty order with a specific type, e.g.
+/// EntryTrailingNormalLong or CloseTrailingLong, means that kind of order applies but has not
+/// triggered yet. Vec-returning calculators only contain orders with nonzero qty.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Order {
     pub qty: f64,
     pub price: f64,
@@ -62,6 +184,181 @@ impl Order {
             order_type,
         }
     }
+
+    #[must_use]
+    pub fn with_qty(self, qty: f64) -> Self {
+        Order { qty, ..self }
+    }
+
+    #[must_use]
+    pub fn with_price(self, price: f64) -> Self {
+        Order { price, ..self }
+    }
+
+    #[must_use]
+    pub fn with_type(self, order_type: OrderType) -> Self {
+        Order { order_type, ..self }
+    }
+
+    /// True if there is nothing to place, regardless of order_type.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.qty == 0.0
+    }
+
+    /// Order size in quote, always positive. Linear contracts only, like qty_to_cost.
+    #[must_use]
+    pub fn to_quote(self, c_mult: f64) -> f64 {
+        qty_to_cost(self.qty, self.price, c_mult)
+    }
+
+    /// Entries must add to the position and closes reduce it: positive qty for long entries
+    /// and short closes, negative for short entries and long closes. Zero qty and Empty pass.
+    pub fn validate_sign(&self) -> Result<(), SignError> {
+        if self.qty == 0.0 || self.order_type == OrderType::Empty {
+            return Ok(());
+        }
+        let expect_positive = (self.order_type.pside() == LONG) == self.order_type.is_entry();
+        if (self.qty > 0.0) == expect_positive {
+            Ok(())
+        } else {
+            Err(SignError { order: *self })
+        }
+    }
+
+    /// Deterministic client order id: "{short code}_{price in ticks}_{abs qty in steps}_{tag}",
+    /// e.g. "egnl_64123500_130_9f2c", where tag is a 16 bit hash of the symbol. The same order
+    /// always gets the same id, so a restarted bot can re-adopt its open orders. Fits
+    /// CUSTOM_ID_MAX_LEN as long as price ticks and qty steps have at most 25 digits together.
+    #[must_use]
+    pub fn custom_id(&self, symbol: &str, exchange_params: &ExchangeParams) -> String {
+        format!(
+            "{}_{}_{}_{:04x}",
+            self.order_type.short_code(),
+            (self.price / exchange_params.price_step).round() as u64,
+            (self.qty.abs() / exchange_params.qty_step).round() as u64,
+            symbol_tag(symbol)
+        )
+    }
+}
+
+/// Order sizes in quote, e.g. for display. See Order::to_quote.
+pub fn grid_to_quote(orders: &[Order], c_mult: f64) -> Vec<f64> {
+    orders.iter().map(|order| order.to_quote(c_mult)).collect()
+}
+
+/// Binance's client order id limit, the shortest among supported exchanges (Bybit allows 45).
+pub const CUSTOM_ID_MAX_LEN: usize = 36;
+
+/// FNV-1a hash of the symbol folded to 16 bits. Stable across processes, unlike DefaultHasher.
+fn symbol_tag(symbol: &str) -> u16 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in symbol.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    (hash ^ (hash >> 16) ^ (hash >> 32) ^ (hash >> 48)) as u16
+}
+
+/// Components of an id made by Order::custom_id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderIdInfo {
+    pub order_type: OrderType,
+    pub price_ticks: u64,
+    pub qty_steps: u64,
+    pub symbol_tag: u16,
+}
+
+impl OrderIdInfo {
+    /// None if the id was not made by Order::custom_id, e.g. a manually placed order.
+    pub fn parse(id: &str) -> Option<Self> {
+        let mut parts = id.split('_');
+        let info = OrderIdInfo {
+            order_type: OrderType::from_short_code(parts.next()?)?,
+            price_ticks: parts.next()?.parse().ok()?,
+            qty_steps: parts.next()?.parse().ok()?,
+            symbol_tag: u16::from_str_radix(parts.next()?, 16).ok()?,
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(info)
+    }
+
+    pub fn matches_symbol(&self, symbol: &str) -> bool {
+        self.symbol_tag == symbol_tag(symbol)
+    }
+
+    /// Rebuilds the order, with the qty sign implied by its order type.
+    pub fn to_order(self, exchange_params: &ExchangeParams) -> Order {
+        let order_type = self.order_type;
+        let qty = round_(
+            self.qty_steps as f64 * exchange_params.qty_step,
+            exchange_params.qty_step,
+        );
+        let sign = if order_type == OrderType::Empty
+            || (order_type.pside() == LONG) == order_type.is_entry()
+        {
+            1.0
+        } else {
+            -1.0
+        };
+        Order {
+            qty: qty * sign,
+            price: round_(
+                self.price_ticks as f64 * exchange_params.price_step,
+                exchange_params.price_step,
+            ),
+            order_type,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SignError {
+    pub order: Order,
+}
+
+impl fmt::Display for SignError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} order has qty {} with the wrong sign",
+            self.order.order_type, self.order.qty
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SideError {
+    pub side: Side,
+    pub size: f64,
+}
+
+impl fmt::Display for SideError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} position has size {} with the wrong sign",
+            self.side, self.size
+        )
+    }
+}
+
+/// Whether position fits side, see Position::for_side. Panics in debug builds if not;
+/// release builds get false, for the calculator to return no orders.
+pub(crate) fn check_side(position: &Position, side: Side) -> bool {
+    let result = position.for_side(side);
+    debug_assert!(result.is_ok(), "{}", result.unwrap_err());
+    result.is_ok()
+}
+
+/// Panics in debug builds if any order has a qty sign contradicting its order type.
+pub(crate) fn debug_assert_signs(orders: &[Order]) {
+    for order in orders {
+        let result = order.validate_sign();
+        debug_assert!(result.is_ok(), "{}", result.unwrap_err());
+    }
 }
 
 impl Default for Order {
@@ -74,46 +371,90 @@ impl Default for Order {
     }
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Candle {
+    pub timestamp: u64, // ms
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64, // base units
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct OrderBook {
     pub bid: f64,
     pub ask: f64,
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct StateParams {
     pub balance: f64,
+    pub equity: Option<f64>, // balance plus unrealized PnL; None falls back to balance
     pub order_book: OrderBook,
     pub ema_bands: EMABands,
+    pub timestamp: u64, // ms, of the current candle; compared with entry_blackout_windows
+    pub last_entry_fill: Option<LastFill>, // latest entry fill of this position side
+    pub trailing_add_count: usize, // trailing entries filled since the position opened
+    pub volatility: f64, // e.g. mean (high - low) / close, see entry_grid_spacing_vol_weight
 }
 
-#[derive(Clone, Default, Debug)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LastFill {
+    pub price: f64,
+    pub timestamp: u64,
+}
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct BotParamsPair {
     pub long: BotParams,
     pub short: BotParams,
 }
 
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct BotParams {
+    pub auto_adjust_markup: bool,
+    pub balance_reserve_pct: f64, // share of balance never used for entry sizing
+    pub bracket_stop_pct: f64,
+    pub close_grid_gap_pct: f64,
     pub close_grid_markup_range: f64,
     pub close_grid_min_markup: f64,
+    pub close_grid_max_markup: f64, // cap on the top of the close grid; 0.0 is no cap
+    pub close_grid_min_entry_distance_ratio: f64, // min markup as multiple of grid spacing; 0.0 is off
+    pub close_min_net_markup: f64,
     pub close_grid_qty_pct: f64,
     pub close_trailing_retracement_pct: f64,
+    pub close_trailing_drawdown_floor_pct: f64, // narrowest retracement at high profit; 0.0 is off
     pub close_trailing_grid_ratio: f64,
     pub close_trailing_qty_pct: f64,
     pub close_trailing_threshold_pct: f64,
+    pub close_trailing_min_profit_pct: f64, // extremum needed before trailing triggers count
+    pub coalesce_grid_orders: bool,
     pub entry_grid_double_down_factor: f64,
+    pub entry_grid_qty_schedule: Vec<f64>, // reentry qty multipliers per level; empty uses double down
     pub entry_grid_spacing_weight: f64,
     pub entry_grid_spacing_pct: f64,
+    pub entry_grid_spacing_vol_weight: f64, // widens spacing with StateParams::volatility; 0.0 is off
+    pub entry_grid_ema_floor: bool,
+    pub entry_blackout_windows: Vec<(u64, u64)>, // (start_ms, end_ms) without new entries
     pub entry_initial_ema_dist: f64,
     pub entry_initial_qty_pct: f64,
+    pub entry_initial_timeout_sec: f64,
+    pub entry_reentry_ema_dist: Option<f64>, // None falls back to entry_initial_ema_dist
+    pub entry_sizing_basis: EntrySizingBasis,
+    pub entry_trailing_drawdown_pct: f64,
+    pub entry_trailing_max_adds: usize, // 0 means unlimited
+    pub entry_trailing_mode: EntryTrailingMode,
     pub entry_trailing_retracement_pct: f64,
+    pub entry_trailing_grid_mode: EntryTrailingGridMode,
     pub entry_trailing_grid_ratio: f64,
     pub entry_trailing_threshold_pct: f64,
     pub filter_rolling_window: usize,
     pub filter_relative_volume_clip_pct: f64,
     pub ema_span_0: f64,
     pub ema_span_1: f64,
+    pub ema_warmup_behavior: EmaWarmupBehavior,
     pub n_positions: usize,
     pub total_wallet_exposure_limit: f64,
     pub wallet_exposure_limit: f64, // is total_wallet_exposure_limit / n_positions
@@ -123,7 +464,245 @@ pub struct BotParams {
     pub unstuck_threshold: f64,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum EntryTrailingMode {
+    #[default]
+    ThresholdDrawdown, // wait for threshold below pos price, then for retracement
+    TrailingStop, // enter on entry_trailing_drawdown_pct bounce off the extreme, no threshold
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum EntryTrailingGridMode {
+    #[default]
+    Switch, // trailing or grid entry depending on filled exposure
+    Blended, // for 0 < entry_trailing_grid_ratio < 1, trailing and grid entries side by side
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum EmaWarmupBehavior {
+    #[default]
+    SuppressEntries, // no initial entries while the EMA band is zero
+    FallbackToOrderBook, // initial entries at bid/ask until the EMA band is nonzero
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum EntrySizingBasis {
+    #[default]
+    Balance,
+    Equity, // balance plus unrealized PnL, e.g. of other positions on a cross margin account
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BotParamsError {
+    CloseGridInsideEntryGrid {
+        close_grid_min_markup: f64,
+        min_close_grid_min_markup: f64,
+    },
+    OutOfRange {
+        field: &'static str,
+        value: f64,
+        min: f64,
+        max: f64,
+    },
+}
+
+impl fmt::Display for BotParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BotParamsError::CloseGridInsideEntryGrid {
+                close_grid_min_markup,
+                min_close_grid_min_markup,
+            } => write!(
+                f,
+                "close_grid_min_markup {} is smaller than close_grid_min_entry_distance_ratio \
+                 times entry_grid_spacing_pct, {}",
+                close_grid_min_markup, min_close_grid_min_markup
+            ),
+            BotParamsError::OutOfRange {
+                field,
+                value,
+                min,
+                max,
+            } => write!(
+                f,
+                "{} is {}, expected within [{}, {}]",
+                field, value, min, max
+            ),
+        }
+    }
+}
+
+impl BotParams {
+    pub fn validate(&self) -> Result<(), Vec<BotParamsError>> {
+        let mut errors = Vec::new();
+        let mut check_range = |field: &'static str, value: f64, min: f64, max: f64| {
+            // NaN fails both comparisons
+            if !(value >= min && value <= max) {
+                errors.push(BotParamsError::OutOfRange {
+                    field,
+                    value,
+                    min,
+                    max,
+                });
+            }
+        };
+        let inf = f64::INFINITY;
+        check_range("balance_reserve_pct", self.balance_reserve_pct, 0.0, 1.0);
+        check_range("bracket_stop_pct", self.bracket_stop_pct, 0.0, 1.0);
+        check_range("close_grid_qty_pct", self.close_grid_qty_pct, 0.0, 1.0);
+        check_range(
+            "close_trailing_grid_ratio",
+            self.close_trailing_grid_ratio,
+            -1.0,
+            1.0,
+        );
+        check_range(
+            "close_trailing_min_profit_pct",
+            self.close_trailing_min_profit_pct,
+            0.0,
+            inf,
+        );
+        check_range(
+            "close_trailing_drawdown_floor_pct",
+            self.close_trailing_drawdown_floor_pct,
+            0.0,
+            inf,
+        );
+        check_range(
+            "close_grid_max_markup",
+            self.close_grid_max_markup,
+            0.0,
+            inf,
+        );
+        check_range(
+            "close_grid_min_entry_distance_ratio",
+            self.close_grid_min_entry_distance_ratio,
+            0.0,
+            inf,
+        );
+        check_range(
+            "close_trailing_qty_pct",
+            self.close_trailing_qty_pct,
+            0.0,
+            1.0,
+        );
+        check_range("ema_span_0", self.ema_span_0, 1.0, inf);
+        check_range("ema_span_1", self.ema_span_1, 1.0, inf);
+        check_range(
+            "entry_grid_double_down_factor",
+            self.entry_grid_double_down_factor,
+            0.0,
+            inf,
+        );
+        for &multiplier in &self.entry_grid_qty_schedule {
+            check_range("entry_grid_qty_schedule", multiplier, 0.0, inf);
+        }
+        check_range(
+            "entry_grid_spacing_pct",
+            self.entry_grid_spacing_pct,
+            0.0,
+            inf,
+        );
+        check_range(
+            "entry_grid_spacing_vol_weight",
+            self.entry_grid_spacing_vol_weight,
+            0.0,
+            inf,
+        );
+        check_range(
+            "entry_initial_qty_pct",
+            self.entry_initial_qty_pct,
+            0.0,
+            1.0,
+        );
+        check_range(
+            "entry_trailing_grid_ratio",
+            self.entry_trailing_grid_ratio,
+            -1.0,
+            1.0,
+        );
+        check_range(
+            "filter_relative_volume_clip_pct",
+            self.filter_relative_volume_clip_pct,
+            0.0,
+            1.0,
+        );
+        check_range(
+            "total_wallet_exposure_limit",
+            self.total_wallet_exposure_limit,
+            0.0,
+            inf,
+        );
+        check_range("unstuck_close_pct", self.unstuck_close_pct, 0.0, 1.0);
+        check_range(
+            "wallet_exposure_limit",
+            self.wallet_exposure_limit,
+            0.0,
+            inf,
+        );
+        // opt in: nearest close would sit inside the entry zone, causing churn
+        let min_close_grid_min_markup =
+            self.close_grid_min_entry_distance_ratio * self.entry_grid_spacing_pct;
+        if self.close_grid_min_entry_distance_ratio > 0.0
+            && self.close_grid_min_markup < min_close_grid_min_markup
+        {
+            errors.push(BotParamsError::CloseGridInsideEntryGrid {
+                close_grid_min_markup: self.close_grid_min_markup,
+                min_close_grid_min_markup,
+            });
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Whether timestamp falls in one of entry_blackout_windows, start inclusive, end exclusive,
+    /// so zero length windows never match.
+    pub fn in_entry_blackout(&self, timestamp: u64) -> bool {
+        self.entry_blackout_windows
+            .iter()
+            .any(|&(start, end)| start <= timestamp && timestamp < end)
+    }
+
+    /// Balance available to entry sizing and entry exposure checks.
+    pub fn usable_balance(&self, balance: f64) -> f64 {
+        balance * (1.0 - self.balance_reserve_pct)
+    }
+
+    /// usable_balance of the balance or, per entry_sizing_basis, the equity of state_params.
+    pub fn entry_sizing_balance(&self, state_params: &StateParams) -> f64 {
+        self.usable_balance(match self.entry_sizing_basis {
+            EntrySizingBasis::Balance => state_params.balance,
+            EntrySizingBasis::Equity => state_params.equity.unwrap_or(state_params.balance),
+        })
+    }
+
+    /// EMA distance of the re-entry floor.
+    pub fn reentry_ema_dist(&self) -> f64 {
+        self.entry_reentry_ema_dist
+            .unwrap_or(self.entry_initial_ema_dist)
+    }
+
+    /// Widens close_grid_min_markup, if auto_adjust_markup is set, to entry_grid_spacing_pct
+    /// times close_grid_min_entry_distance_ratio, or times 1.0 if the ratio is off.
+    pub fn adjust_markup(&mut self) {
+        if self.auto_adjust_markup {
+            let ratio = if self.close_grid_min_entry_distance_ratio > 0.0 {
+                self.close_grid_min_entry_distance_ratio
+            } else {
+                1.0
+            };
+            self.close_grid_min_markup = self
+                .close_grid_min_markup
+                .max(self.entry_grid_spacing_pct * ratio);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct TrailingPriceBundle {
     pub min_since_open: f64,
     pub max_since_min: f64,
@@ -141,7 +720,25 @@ impl Default for TrailingPriceBundle {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+impl TrailingPriceBundle {
+    pub fn update(&mut self, high: f64, low: f64, close: f64) {
+        if low < self.min_since_open {
+            self.min_since_open = low;
+            self.max_since_min = close;
+        } else {
+            self.max_since_min = self.max_since_min.max(high);
+        }
+        if high > self.max_since_open {
+            self.max_since_open = high;
+            self.min_since_max = close;
+        } else {
+            self.min_since_max = self.min_since_max.min(low);
+        }
+    }
+}
+
+#[cfg_attr(feature = "python", pyclass)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum OrderType {
     EntryInitialNormalLong,
     EntryInitialPartialLong,
@@ -154,6 +751,7 @@ pub enum OrderType {
     CloseGridLong,
     CloseTrailingLong,
     CloseUnstuckLong,
+    CloseStopLong,
 
     EntryInitialNormalShort,
     EntryInitialPartialShort,
@@ -166,10 +764,206 @@ pub enum OrderType {
     CloseGridShort,
     CloseTrailingShort,
     CloseUnstuckShort,
+    CloseStopShort,
 
     Empty,
 }
 
+#[cfg_attr(feature = "python", pyclass)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Long = 0,  // LONG
+    Short = 1, // SHORT
+}
+
+impl Side {
+    pub const ALL: [Side; 2] = [Side::Long, Side::Short];
+
+    /// LONG or SHORT, for indexing per side data.
+    pub fn index(&self) -> usize {
+        match self {
+            Side::Long => LONG,
+            Side::Short => SHORT,
+        }
+    }
+
+    pub fn from_index(pside: usize) -> Option<Self> {
+        match pside {
+            LONG => Some(Side::Long),
+            SHORT => Some(Side::Short),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Side::Long => write!(f, "long"),
+            Side::Short => write!(f, "short"),
+        }
+    }
+}
+
+impl FromStr for Side {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "long" => Ok(Side::Long),
+            "short" => Ok(Side::Short),
+            _ => Err(format!("unknown side '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum OrderKind {
+    EntryInitial,
+    EntryGrid,
+    EntryTrailing,
+    CloseGrid,
+    CloseTrailing,
+    CloseUnstuck,
+    CloseStop,
+    Empty,
+}
+
+/// All order types in declaration order; position is the integer code.
+pub const ORDER_TYPES: [OrderType; 23] = [
+    OrderType::EntryInitialNormalLong,
+    OrderType::EntryInitialPartialLong,
+    OrderType::EntryTrailingNormalLong,
+    OrderType::EntryTrailingCroppedLong,
+    OrderType::EntryGridNormalLong,
+    OrderType::EntryGridCroppedLong,
+    OrderType::EntryGridInflatedLong,
+    OrderType::CloseGridLong,
+    OrderType::CloseTrailingLong,
+    OrderType::CloseUnstuckLong,
+    OrderType::CloseStopLong,
+    OrderType::EntryInitialNormalShort,
+    OrderType::EntryInitialPartialShort,
+    OrderType::EntryTrailingNormalShort,
+    OrderType::EntryTrailingCroppedShort,
+    OrderType::EntryGridNormalShort,
+    OrderType::EntryGridCroppedShort,
+    OrderType::EntryGridInflatedShort,
+    OrderType::CloseGridShort,
+    OrderType::CloseTrailingShort,
+    OrderType::CloseUnstuckShort,
+    OrderType::CloseStopShort,
+    OrderType::Empty,
+];
+
+impl OrderType {
+    /// Short code used in custom order ids.
+    pub fn short_code(&self) -> &'static str {
+        match self {
+            OrderType::EntryInitialNormalLong => "einl",
+            OrderType::EntryInitialPartialLong => "eipl",
+            OrderType::EntryTrailingNormalLong => "etnl",
+            OrderType::EntryTrailingCroppedLong => "etcl",
+            OrderType::EntryGridNormalLong => "egnl",
+            OrderType::EntryGridCroppedLong => "egcl",
+            OrderType::EntryGridInflatedLong => "egil",
+            OrderType::CloseGridLong => "cgl",
+            OrderType::CloseTrailingLong => "ctl",
+            OrderType::CloseUnstuckLong => "cul",
+            OrderType::CloseStopLong => "csl",
+            OrderType::EntryInitialNormalShort => "eins",
+            OrderType::EntryInitialPartialShort => "eips",
+            OrderType::EntryTrailingNormalShort => "etns",
+            OrderType::EntryTrailingCroppedShort => "etcs",
+            OrderType::EntryGridNormalShort => "egns",
+            OrderType::EntryGridCroppedShort => "egcs",
+            OrderType::EntryGridInflatedShort => "egis",
+            OrderType::CloseGridShort => "cgs",
+            OrderType::CloseTrailingShort => "cts",
+            OrderType::CloseUnstuckShort => "cus",
+            OrderType::CloseStopShort => "css",
+            OrderType::Empty => "empty",
+        }
+    }
+
+    pub fn from_short_code(code: &str) -> Option<Self> {
+        ORDER_TYPES
+            .iter()
+            .find(|order_type| order_type.short_code() == code)
+            .copied()
+    }
+
+    /// Integer code for numpy output, the index into ORDER_TYPES.
+    pub fn code(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        ORDER_TYPES.get(code as usize).copied()
+    }
+
+    /// Returns (pside, kind). pside is NO_POS for Empty.
+    /// No wildcard arm: every new variant must be classified here.
+    pub fn classify(&self) -> (usize, OrderKind) {
+        match self {
+            OrderType::EntryInitialNormalLong => (LONG, OrderKind::EntryInitial),
+            OrderType::EntryInitialPartialLong => (LONG, OrderKind::EntryInitial),
+            OrderType::EntryTrailingNormalLong => (LONG, OrderKind::EntryTrailing),
+            OrderType::EntryTrailingCroppedLong => (LONG, OrderKind::EntryTrailing),
+            OrderType::EntryGridNormalLong => (LONG, OrderKind::EntryGrid),
+            OrderType::EntryGridCroppedLong => (LONG, OrderKind::EntryGrid),
+            OrderType::EntryGridInflatedLong => (LONG, OrderKind::EntryGrid),
+            OrderType::CloseGridLong => (LONG, OrderKind::CloseGrid),
+            OrderType::CloseTrailingLong => (LONG, OrderKind::CloseTrailing),
+            OrderType::CloseUnstuckLong => (LONG, OrderKind::CloseUnstuck),
+            OrderType::CloseStopLong => (LONG, OrderKind::CloseStop),
+            OrderType::EntryInitialNormalShort => (SHORT, OrderKind::EntryInitial),
+            OrderType::EntryInitialPartialShort => (SHORT, OrderKind::EntryInitial),
+            OrderType::EntryTrailingNormalShort => (SHORT, OrderKind::EntryTrailing),
+            OrderType::EntryTrailingCroppedShort => (SHORT, OrderKind::EntryTrailing),
+            OrderType::EntryGridNormalShort => (SHORT, OrderKind::EntryGrid),
+            OrderType::EntryGridCroppedShort => (SHORT, OrderKind::EntryGrid),
+            OrderType::EntryGridInflatedShort => (SHORT, OrderKind::EntryGrid),
+            OrderType::CloseGridShort => (SHORT, OrderKind::CloseGrid),
+            OrderType::CloseTrailingShort => (SHORT, OrderKind::CloseTrailing),
+            OrderType::CloseUnstuckShort => (SHORT, OrderKind::CloseUnstuck),
+            OrderType::CloseStopShort => (SHORT, OrderKind::CloseStop),
+            OrderType::Empty => (NO_POS, OrderKind::Empty),
+        }
+    }
+
+    pub fn pside(&self) -> usize {
+        self.classify().0
+    }
+
+    /// None for Empty.
+    pub fn side(&self) -> Option<Side> {
+        Side::from_index(self.pside())
+    }
+
+    pub fn kind(&self) -> OrderKind {
+        self.classify().1
+    }
+
+    pub fn is_entry(&self) -> bool {
+        matches!(
+            self.kind(),
+            OrderKind::EntryInitial | OrderKind::EntryGrid | OrderKind::EntryTrailing
+        )
+    }
+
+    pub fn is_close(&self) -> bool {
+        matches!(
+            self.kind(),
+            OrderKind::CloseGrid
+                | OrderKind::CloseTrailing
+                | OrderKind::CloseUnstuck
+                | OrderKind::CloseStop
+        )
+    }
+}
+
 impl fmt::Display for OrderType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -183,6 +977,7 @@ impl fmt::Display for OrderType {
             OrderType::CloseGridLong => write!(f, "close_grid_long"),
             OrderType::CloseTrailingLong => write!(f, "close_trailing_long"),
             OrderType::CloseUnstuckLong => write!(f, "close_unstuck_long"),
+            OrderType::CloseStopLong => write!(f, "close_stop_long"),
             OrderType::EntryInitialNormalShort => write!(f, "entry_initial_normal_short"),
             OrderType::EntryInitialPartialShort => write!(f, "entry_initial_partial_short"),
             OrderType::EntryTrailingNormalShort => write!(f, "entry_trailing_normal_short"),
@@ -193,12 +988,59 @@ impl fmt::Display for OrderType {
             OrderType::CloseGridShort => write!(f, "close_grid_short"),
             OrderType::CloseTrailingShort => write!(f, "close_trailing_short"),
             OrderType::CloseUnstuckShort => write!(f, "close_unstuck_short"),
+            OrderType::CloseStopShort => write!(f, "close_stop_short"),
             OrderType::Empty => write!(f, "empty"),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+impl FromStr for OrderType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "entry_initial_normal_long" => Ok(OrderType::EntryInitialNormalLong),
+            "entry_initial_partial_long" => Ok(OrderType::EntryInitialPartialLong),
+            "entry_trailing_normal_long" => Ok(OrderType::EntryTrailingNormalLong),
+            "entry_trailing_cropped_long" => Ok(OrderType::EntryTrailingCroppedLong),
+            "entry_grid_normal_long" => Ok(OrderType::EntryGridNormalLong),
+            "entry_grid_cropped_long" => Ok(OrderType::EntryGridCroppedLong),
+            "entry_grid_inflated_long" => Ok(OrderType::EntryGridInflatedLong),
+            "close_grid_long" => Ok(OrderType::CloseGridLong),
+            "close_trailing_long" => Ok(OrderType::CloseTrailingLong),
+            "close_unstuck_long" => Ok(OrderType::CloseUnstuckLong),
+            "close_stop_long" => Ok(OrderType::CloseStopLong),
+            "entry_initial_normal_short" => Ok(OrderType::EntryInitialNormalShort),
+            "entry_initial_partial_short" => Ok(OrderType::EntryInitialPartialShort),
+            "entry_trailing_normal_short" => Ok(OrderType::EntryTrailingNormalShort),
+            "entry_trailing_cropped_short" => Ok(OrderType::EntryTrailingCroppedShort),
+            "entry_grid_normal_short" => Ok(OrderType::EntryGridNormalShort),
+            "entry_grid_cropped_short" => Ok(OrderType::EntryGridCroppedShort),
+            "entry_grid_inflated_short" => Ok(OrderType::EntryGridInflatedShort),
+            "close_grid_short" => Ok(OrderType::CloseGridShort),
+            "close_trailing_short" => Ok(OrderType::CloseTrailingShort),
+            "close_unstuck_short" => Ok(OrderType::CloseUnstuckShort),
+            "close_stop_short" => Ok(OrderType::CloseStopShort),
+            "empty" => Ok(OrderType::Empty),
+            _ => Err(format!("unknown order type '{}'", s)),
+        }
+    }
+}
+
+impl Serialize for OrderType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for OrderType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Fill {
     pub index: usize,
     pub symbol: String,
@@ -236,3 +1078,319 @@ impl Default for Analysis {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn balance_mode_sizing_balance() {
+        let starting_balance = 1000.0;
+        for (balance, compounding, fixed, capped) in [
+            (500.0, 500.0, 1000.0, 500.0),
+            (1000.0, 1000.0, 1000.0, 1000.0),
+            (3000.0, 3000.0, 1000.0, 2000.0),
+        ] {
+            assert_eq!(
+                BalanceMode::Compounding.sizing_balance(balance, starting_balance),
+                compounding
+            );
+            assert_eq!(
+                BalanceMode::Fixed.sizing_balance(balance, starting_balance),
+                fixed
+            );
+            assert_eq!(
+                BalanceMode::CappedAt(2000.0).sizing_balance(balance, starting_balance),
+                capped
+            );
+        }
+    }
+
+    #[test]
+    fn order_types_classified_by_side_and_kind() {
+        use OrderKind::*;
+        for (code, order_type) in ORDER_TYPES.iter().enumerate() {
+            // no wildcard arm: a new variant fails to compile until it is listed here
+            let expected = match order_type {
+                OrderType::EntryInitialNormalLong | OrderType::EntryInitialPartialLong => {
+                    (Some(Side::Long), EntryInitial)
+                }
+                OrderType::EntryTrailingNormalLong | OrderType::EntryTrailingCroppedLong => {
+                    (Some(Side::Long), EntryTrailing)
+                }
+                OrderType::EntryGridNormalLong
+                | OrderType::EntryGridCroppedLong
+                | OrderType::EntryGridInflatedLong => (Some(Side::Long), EntryGrid),
+                OrderType::CloseGridLong => (Some(Side::Long), CloseGrid),
+                OrderType::CloseTrailingLong => (Some(Side::Long), CloseTrailing),
+                OrderType::CloseUnstuckLong => (Some(Side::Long), CloseUnstuck),
+                OrderType::CloseStopLong => (Some(Side::Long), CloseStop),
+                OrderType::EntryInitialNormalShort | OrderType::EntryInitialPartialShort => {
+                    (Some(Side::Short), EntryInitial)
+                }
+                OrderType::EntryTrailingNormalShort | OrderType::EntryTrailingCroppedShort => {
+                    (Some(Side::Short), EntryTrailing)
+                }
+                OrderType::EntryGridNormalShort
+                | OrderType::EntryGridCroppedShort
+                | OrderType::EntryGridInflatedShort => (Some(Side::Short), EntryGrid),
+                OrderType::CloseGridShort => (Some(Side::Short), CloseGrid),
+                OrderType::CloseTrailingShort => (Some(Side::Short), CloseTrailing),
+                OrderType::CloseUnstuckShort => (Some(Side::Short), CloseUnstuck),
+                OrderType::CloseStopShort => (Some(Side::Short), CloseStop),
+                OrderType::Empty => (None, Empty),
+            };
+            assert_eq!(
+                (order_type.side(), order_type.kind()),
+                expected,
+                "{}",
+                order_type
+            );
+            let (pside, kind) = order_type.classify();
+            assert_eq!(pside, expected.0.map_or(NO_POS, |side| side.index()));
+            assert_eq!(kind, expected.1);
+            assert_eq!(
+                order_type.is_entry(),
+                matches!(kind, EntryInitial | EntryGrid | EntryTrailing)
+            );
+            assert_eq!(
+                order_type.is_close(),
+                matches!(kind, CloseGrid | CloseTrailing | CloseUnstuck | CloseStop)
+            );
+            assert_eq!(order_type.code() as usize, code);
+            assert_eq!(OrderType::from_code(code as u8), Some(*order_type));
+            assert_eq!(order_type.to_string().parse::<OrderType>(), Ok(*order_type));
+        }
+    }
+
+    #[test]
+    fn close_grid_entry_distance_check_is_opt_in() {
+        // close_grid_min_markup 0.012842 is well inside entry_grid_spacing_pct 0.052341
+        let bot_params = crate::fixtures::bot_params();
+        assert_eq!(bot_params.validate(), Ok(()));
+        let with_ratio = |ratio| BotParams {
+            close_grid_min_entry_distance_ratio: ratio,
+            ..bot_params.clone()
+        };
+        assert_eq!(with_ratio(0.2).validate(), Ok(()));
+        assert_eq!(
+            with_ratio(1.0).validate(),
+            Err(vec![BotParamsError::CloseGridInsideEntryGrid {
+                close_grid_min_markup: 0.012842,
+                min_close_grid_min_markup: 0.052341,
+            }])
+        );
+        assert!(matches!(
+            with_ratio(-1.0).validate().unwrap_err()[..],
+            [BotParamsError::OutOfRange {
+                field: "close_grid_min_entry_distance_ratio",
+                ..
+            }]
+        ));
+        // auto_adjust_markup widens to the ratio, or to the plain spacing without one
+        let mut adjusted = BotParams {
+            auto_adjust_markup: true,
+            ..with_ratio(0.5)
+        };
+        adjusted.adjust_markup();
+        assert_eq!(adjusted.close_grid_min_markup, 0.0261705);
+        assert_eq!(adjusted.validate(), Ok(()));
+        let mut adjusted = BotParams {
+            auto_adjust_markup: true,
+            ..bot_params.clone()
+        };
+        adjusted.adjust_markup();
+        assert_eq!(adjusted.close_grid_min_markup, 0.052341);
+    }
+
+    #[test]
+    fn order_builders_replace_one_field_each() {
+        let order = Order::default()
+            .with_qty(0.01)
+            .with_price(60000.0)
+            .with_type(OrderType::EntryGridNormalLong);
+        assert_eq!(
+            order,
+            Order {
+                qty: 0.01,
+                price: 60000.0,
+                order_type: OrderType::EntryGridNormalLong,
+            }
+        );
+        assert_eq!(
+            order.with_price(59000.0),
+            Order {
+                price: 59000.0,
+                ..order
+            }
+        );
+    }
+
+    #[test]
+    fn validate_sign_catches_wrong_sign_orders() {
+        for order_type in ORDER_TYPES {
+            let (pside, kind) = order_type.classify();
+            if kind == OrderKind::Empty {
+                assert_eq!(Order::new(1.0, 100.0, order_type).validate_sign(), Ok(()));
+                continue;
+            }
+            // long entries and short closes buy
+            let buy = (pside == LONG) == order_type.is_entry();
+            let sign = if buy { 1.0 } else { -1.0 };
+            let right = Order::new(sign * 0.01, 100.0, order_type);
+            let wrong = Order::new(-sign * 0.01, 100.0, order_type);
+            assert_eq!(right.validate_sign(), Ok(()), "{:?}", order_type);
+            assert_eq!(wrong.validate_sign(), Err(SignError { order: wrong }));
+            assert_eq!(Order::new(0.0, 100.0, order_type).validate_sign(), Ok(()));
+        }
+        let ok = Order::new(0.01, 100.0, OrderType::EntryGridNormalLong);
+        debug_assert_signs(&[ok, ok.with_qty(-0.01).with_type(OrderType::CloseGridLong)]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "wrong sign")]
+    fn debug_assert_signs_panics_on_wrong_sign_in_debug_builds() {
+        debug_assert_signs(&[Order::new(0.01, 100.0, OrderType::CloseGridLong)]);
+    }
+
+    #[test]
+    fn projected_fill_matches_reconciled_fill() {
+        use crate::fixtures;
+        use crate::sync::{reconcile_position, FillEvent};
+        let parts = |position: Position| (position.size, position.price);
+        let exchange_params = fixtures::exchange_params();
+        let orders = [
+            Order::new(0.01, 57000.0, OrderType::EntryGridNormalLong),
+            Order::new(-0.005, 61000.0, OrderType::CloseGridLong),
+            Order::new(0.003, 55000.0, OrderType::EntryGridNormalLong),
+        ];
+        let shorts = orders.map(|order| Order {
+            qty: -order.qty,
+            order_type: if order.order_type.is_entry() {
+                OrderType::EntryGridNormalShort
+            } else {
+                OrderType::CloseGridShort
+            },
+            ..order
+        });
+        for (starting, orders) in [(0.01, orders), (-0.01, shorts)] {
+            let mut projected = Position {
+                size: starting,
+                price: 60000.0,
+            };
+            let mut confirmed = projected;
+            for (ts, order) in orders.iter().enumerate() {
+                projected = projected.project_fill(order, exchange_params.qty_step);
+                let fill = FillEvent {
+                    id: None,
+                    qty: order.qty,
+                    price: order.price,
+                    ts: ts as u64,
+                };
+                confirmed = reconcile_position(&confirmed, &[fill], &exchange_params).0;
+                assert_eq!(parts(projected), parts(confirmed));
+            }
+        }
+        // entries average in, closes keep the price
+        let position = Position {
+            size: 0.01,
+            price: 60000.0,
+        }
+        .project_fill(&orders[0], exchange_params.qty_step);
+        assert_eq!((position.size, position.price), (0.02, 58500.0));
+        // a close larger than the position only flattens it
+        let flat = position.project_fill(
+            &Order::new(-1.0, 61000.0, OrderType::CloseTrailingLong),
+            exchange_params.qty_step,
+        );
+        assert_eq!(parts(flat), (0.0, 0.0));
+        assert_eq!(
+            parts(flat.project_fill(&orders[1], exchange_params.qty_step)),
+            (0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn custom_ids_are_stable_and_round_trip_every_order_type() {
+        let exchange_params = ExchangeParams {
+            qty_step: 0.001,
+            price_step: 0.1,
+            ..Default::default()
+        };
+        // pinned: ids must survive restarts and upgrades
+        let order = Order::new(0.13, 6412350.0, OrderType::EntryGridNormalLong);
+        assert_eq!(
+            order.custom_id("BTCUSDT", &exchange_params),
+            "egnl_64123500_130_50d4"
+        );
+        let mut codes = HashSet::new();
+        for order_type in ORDER_TYPES {
+            assert!(codes.insert(order_type.short_code()));
+            let order = if order_type == OrderType::Empty {
+                Order::new(0.0, 0.0, order_type)
+            } else {
+                let buy = (order_type.pside() == LONG) == order_type.is_entry();
+                Order::new(if buy { 0.123 } else { -0.123 }, 60123.4, order_type)
+            };
+            let id = order.custom_id("ETHUSDT", &exchange_params);
+            assert!(id.len() <= CUSTOM_ID_MAX_LEN, "{}", id);
+            let info = OrderIdInfo::parse(&id).unwrap();
+            assert_eq!(info.order_type, order_type);
+            assert!(info.matches_symbol("ETHUSDT"));
+            assert!(!info.matches_symbol("BTCUSDT"));
+            assert_eq!(info.to_order(&exchange_params), order);
+        }
+    }
+
+    #[test]
+    fn custom_id_length_limits_and_foreign_ids() {
+        // 15 digits of price ticks and 10 of qty steps fill Binance's 36 characters
+        let exchange_params = ExchangeParams {
+            qty_step: 1.0,
+            price_step: 1e-8,
+            ..Default::default()
+        };
+        let id = Order::new(1e9, 1e6, OrderType::EntryGridNormalLong)
+            .custom_id("1000PEPEUSDT", &exchange_params);
+        assert_eq!(id.len(), CUSTOM_ID_MAX_LEN);
+        // and 19 of qty steps Bybit's 45
+        let id = Order::new(1e18, 1e6, OrderType::EntryGridNormalLong)
+            .custom_id("1000PEPEUSDT", &exchange_params);
+        assert_eq!(id.len(), 45);
+        assert_eq!(
+            OrderIdInfo::parse(&id).unwrap().qty_steps,
+            1_000_000_000_000_000_000
+        );
+        for foreign in [
+            "",
+            "web_123",
+            "egnl_1_2",
+            "egnl_1_2_zzzz",
+            "egnl_-1_2_50d4",
+            "xxxx_1_2_50d4",
+            "egnl_1_2_50d4_extra",
+        ] {
+            assert_eq!(OrderIdInfo::parse(foreign), None, "{}", foreign);
+        }
+    }
+
+    #[test]
+    fn quote_is_qty_times_price_times_c_mult() {
+        let orders = [
+            Order::new(0.002, 60000.0, OrderType::EntryGridNormalLong),
+            Order::new(-0.5, 3000.0, OrderType::CloseGridLong),
+            Order::new(-3.0, 0.5, OrderType::EntryGridNormalShort),
+        ];
+        assert_eq!(orders[0].to_quote(1.0), 0.002 * 60000.0);
+        // always positive, whatever the side
+        assert_eq!(orders[1].to_quote(1.0), 1500.0);
+        assert_eq!(orders[2].to_quote(1000.0), 1500.0);
+        assert_eq!(
+            grid_to_quote(&orders, 10.0),
+            [0.002 * 60000.0 * 10.0, 15000.0, 15.0]
+        );
+        assert!(grid_to_quote(&[], 1.0).is_empty());
+    }
+}
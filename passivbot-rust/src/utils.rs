@@ -1,5 +1,6 @@
 use crate::constants::{CLOSE, LONG, NO_POS, SHORT};
 use crate::types::ExchangeParams;
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 
 /// Rounds a number to the specified number of decimal places.
@@ -9,27 +10,27 @@ fn round_to_decimal_places(value: f64, decimal_places: usize) -> f64 {
 }
 
 /// Rounds up a number to the nearest multiple of the given step.
-#[pyfunction]
+#[cfg_attr(feature = "python", pyfunction)]
 pub fn round_up(n: f64, step: f64) -> f64 {
     let result = (n / step).ceil() * step;
     round_to_decimal_places(result, 10)
 }
 
 /// Rounds a number to the nearest multiple of the given step.
-#[pyfunction]
+#[cfg_attr(feature = "python", pyfunction)]
 pub fn round_(n: f64, step: f64) -> f64 {
     let result = (n / step).round() * step;
     round_to_decimal_places(result, 10)
 }
 
 /// Rounds down a number to the nearest multiple of the given step.
-#[pyfunction]
+#[cfg_attr(feature = "python", pyfunction)]
 pub fn round_dn(n: f64, step: f64) -> f64 {
     let result = (n / step).floor() * step;
     round_to_decimal_places(result, 10)
 }
 
-#[pyfunction]
+#[cfg_attr(feature = "python", pyfunction)]
 pub fn round_dynamic(n: f64, d: i32) -> f64 {
     if n == 0.0 {
         return n;
@@ -40,7 +41,7 @@ pub fn round_dynamic(n: f64, d: i32) -> f64 {
     round_to_decimal_places(result, 10)
 }
 
-#[pyfunction]
+#[cfg_attr(feature = "python", pyfunction)]
 pub fn round_dynamic_up(n: f64, d: i32) -> f64 {
     if n == 0.0 {
         return n;
@@ -51,7 +52,7 @@ pub fn round_dynamic_up(n: f64, d: i32) -> f64 {
     round_to_decimal_places(result, 10)
 }
 
-#[pyfunction]
+#[cfg_attr(feature = "python", pyfunction)]
 pub fn round_dynamic_dn(n: f64, d: i32) -> f64 {
     if n == 0.0 {
         return n;
@@ -62,7 +63,7 @@ pub fn round_dynamic_dn(n: f64, d: i32) -> f64 {
     round_to_decimal_places(result, 10)
 }
 
-#[pyfunction]
+#[cfg_attr(feature = "python", pyfunction)]
 pub fn calc_diff(x: f64, y: f64) -> f64 {
     if y == 0.0 {
         if x == 0.0 {
@@ -75,7 +76,7 @@ pub fn calc_diff(x: f64, y: f64) -> f64 {
     }
 }
 
-#[pyfunction]
+#[cfg_attr(feature = "python", pyfunction)]
 pub fn cost_to_qty(cost: f64, price: f64, c_mult: f64) -> f64 {
     if price > 0.0 {
         (cost.abs() / price) / c_mult
@@ -84,12 +85,12 @@ pub fn cost_to_qty(cost: f64, price: f64, c_mult: f64) -> f64 {
     }
 }
 
-#[pyfunction]
+#[cfg_attr(feature = "python", pyfunction)]
 pub fn qty_to_cost(qty: f64, price: f64, c_mult: f64) -> f64 {
     (qty.abs() * price) * c_mult
 }
 
-#[pyfunction]
+#[cfg_attr(feature = "python", pyfunction)]
 pub fn calc_wallet_exposure(
     c_mult: f64,
     balance: f64,
@@ -102,6 +103,13 @@ pub fn calc_wallet_exposure(
     qty_to_cost(position_size, position_price, c_mult) / balance
 }
 
+/// Whether wallet_exposure is at limit, counting anything within tolerance, a fraction of
+/// limit, below it as reached. Shared by all entry paths so that a position right at the
+/// threshold gets the same answer from each.
+pub fn exposure_limit_reached(wallet_exposure: f64, limit: f64, tolerance: f64) -> bool {
+    wallet_exposure >= limit * (1.0 - tolerance)
+}
+
 pub fn calc_wallet_exposure_if_filled(
     balance: f64,
     psize: f64,
@@ -117,7 +125,7 @@ pub fn calc_wallet_exposure_if_filled(
     calc_wallet_exposure(exchange_params.c_mult, balance, new_psize, new_pprice)
 }
 
-#[pyfunction]
+#[cfg_attr(feature = "python", pyfunction)]
 pub fn calc_new_psize_pprice(
     psize: f64,
     pprice: f64,
@@ -168,12 +176,12 @@ pub fn interpolate(x: f64, xs: &[f64], ys: &[f64]) -> f64 {
     result
 }
 
-#[pyfunction]
+#[cfg_attr(feature = "python", pyfunction)]
 pub fn calc_pnl_long(entry_price: f64, close_price: f64, qty: f64, c_mult: f64) -> f64 {
     qty.abs() * c_mult * (close_price - entry_price)
 }
 
-#[pyfunction]
+#[cfg_attr(feature = "python", pyfunction)]
 pub fn calc_pnl_short(entry_price: f64, close_price: f64, qty: f64, c_mult: f64) -> f64 {
     qty.abs() * c_mult * (entry_price - close_price)
 }
@@ -200,7 +208,7 @@ pub fn calc_pprice_diff_int(pside: usize, pprice: f64, price: f64) -> f64 {
     }
 }
 
-#[pyfunction]
+#[cfg_attr(feature = "python", pyfunction)]
 pub fn calc_auto_unstuck_allowance(
     balance: f64,
     loss_allowance_pct: f64,
@@ -213,27 +221,3 @@ pub fn calc_auto_unstuck_allowance(
     let drop_since_peak_pct = balance / balance_peak - 1.0;
     (balance_peak * (loss_allowance_pct + drop_since_peak_pct)).max(0.0)
 }
-
-pub fn calc_ema_price_bid(
-    price_step: f64,
-    order_book_bid: f64,
-    ema_bands_lower: f64,
-    ema_dist: f64,
-) -> f64 {
-    f64::min(
-        order_book_bid,
-        round_dn(ema_bands_lower * (1.0 - ema_dist), price_step),
-    )
-}
-
-pub fn calc_ema_price_ask(
-    price_step: f64,
-    order_book_ask: f64,
-    ema_bands_upper: f64,
-    ema_dist: f64,
-) -> f64 {
-    f64::max(
-        order_book_ask,
-        round_up(ema_bands_upper * (1.0 + ema_dist), price_step),
-    )
-}
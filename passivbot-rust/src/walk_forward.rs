@@ -0,0 +1,186 @@
+use crate::runner::{backtest, BacktestResult};
+use crate::types::{BacktestParams, BotParamsPair, Candle, ExchangeParams};
+use std::ops::Range;
+
+#[derive(Debug, Clone)]
+pub struct FoldResult {
+    pub train: Range<usize>,
+    pub test: Range<usize>,
+    pub best_index: usize, // index into candidate configs
+    pub train_score: f64,
+    pub test_result: BacktestResult,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct WalkForwardResult {
+    pub folds: Vec<FoldResult>,
+    pub oos_equities: Vec<f64>, // test windows stitched end to end
+}
+
+/// Splits candle indices into n_folds consecutive windows, each divided into a train range
+/// followed by a test range. Windows never overlap; leftover candles at the end are dropped.
+pub fn walk_forward_splits(
+    n_candles: usize,
+    n_folds: usize,
+    train_fraction: f64,
+) -> Vec<(Range<usize>, Range<usize>)> {
+    if n_folds == 0 || !(0.0..1.0).contains(&train_fraction) {
+        return Vec::new();
+    }
+    let fold_len = n_candles / n_folds;
+    let train_len = (fold_len as f64 * train_fraction).round() as usize;
+    if train_len == 0 || train_len >= fold_len {
+        return Vec::new();
+    }
+    (0..n_folds)
+        .map(|i| {
+            let start = i * fold_len;
+            (
+                start..start + train_len,
+                start + train_len..start + fold_len,
+            )
+        })
+        .collect()
+}
+
+/// For each fold, backtests every candidate on the train window, picks the highest scoring one
+/// and backtests it on the test window. Out of sample equity curves are stitched so that each
+/// test window continues from where the previous one ended.
+pub fn run_walk_forward<F>(
+    candles: &[Candle],
+    candidates: &[BotParamsPair],
+    score: F,
+    exchange_params: &ExchangeParams,
+    backtest_params: &BacktestParams,
+    n_folds: usize,
+    train_fraction: f64,
+) -> WalkForwardResult
+where
+    F: Fn(&BacktestResult) -> f64,
+{
+    let mut result = WalkForwardResult::default();
+    if candidates.is_empty() {
+        return result;
+    }
+    let mut equity_scale = 1.0;
+    for (train, test) in walk_forward_splits(candles.len(), n_folds, train_fraction) {
+        let (best_index, train_score) = candidates
+            .iter()
+            .enumerate()
+            .map(|(i, bot_params_pair)| {
+                let train_result = backtest(
+                    &candles[train.clone()],
+                    bot_params_pair,
+                    exchange_params,
+                    backtest_params,
+                );
+                (i, score(&train_result))
+            })
+            .fold((0, f64::NEG_INFINITY), |best, (i, s)| {
+                if s > best.1 {
+                    (i, s)
+                } else {
+                    best
+                }
+            });
+        let test_result = backtest(
+            &candles[test.clone()],
+            &candidates[best_index],
+            exchange_params,
+            backtest_params,
+        );
+        result.oos_equities.extend(
+            test_result
+                .equities
+                .iter()
+                .map(|equity| equity * equity_scale),
+        );
+        if let Some(&last) = result.oos_equities.last() {
+            equity_scale = last / backtest_params.starting_balance;
+        }
+        result.folds.push(FoldResult {
+            train,
+            test,
+            best_index,
+            train_score,
+            test_result,
+        });
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::types::BotParams;
+
+    #[test]
+    fn splits_are_consecutive_and_drop_leftovers() {
+        let splits = walk_forward_splits(1003, 4, 0.8);
+        assert_eq!(
+            splits,
+            vec![
+                (0..200, 200..250),
+                (250..450, 450..500),
+                (500..700, 700..750),
+                (750..950, 950..1000),
+            ]
+        );
+        assert!(walk_forward_splits(1000, 0, 0.8).is_empty());
+        assert!(walk_forward_splits(1000, 4, 1.0).is_empty());
+        assert!(walk_forward_splits(1000, 4, -0.1).is_empty());
+        // train or test window rounding to zero candles
+        assert!(walk_forward_splits(8, 4, 0.1).is_empty());
+        assert!(walk_forward_splits(8, 4, 0.9).is_empty());
+    }
+
+    #[test]
+    fn picks_best_train_candidate_and_stitches_test_windows() {
+        let candles = fixtures::candles(6000, 3);
+        let idle = BotParams {
+            wallet_exposure_limit: 0.0,
+            ..fixtures::bot_params()
+        };
+        let candidates = [
+            fixtures::bot_params_pair(),
+            BotParamsPair {
+                long: idle.clone(),
+                short: idle,
+            },
+        ];
+        let backtest_params = fixtures::backtest_params();
+        // fewest fills wins: the idle candidate
+        let result = run_walk_forward(
+            &candles,
+            &candidates,
+            |r| -(r.fills.len() as f64),
+            &fixtures::exchange_params(),
+            &backtest_params,
+            3,
+            0.5,
+        );
+        assert_eq!(result.folds.len(), 3);
+        for fold in &result.folds {
+            assert_eq!(fold.best_index, 1);
+            assert_eq!(fold.train_score, 0.0);
+            assert_eq!(fold.test_result.equities.len(), fold.test.len());
+        }
+        assert_eq!(result.oos_equities.len(), 3000);
+        assert!(result
+            .oos_equities
+            .iter()
+            .all(|&equity| equity == backtest_params.starting_balance));
+        assert!(run_walk_forward(
+            &candles,
+            &[],
+            |_| 0.0,
+            &fixtures::exchange_params(),
+            &backtest_params,
+            3,
+            0.5
+        )
+        .folds
+        .is_empty());
+    }
+}
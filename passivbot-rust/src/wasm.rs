@@ -0,0 +1,435 @@
+//! wasm-bindgen wrappers around the order calculators, built with the wasm feature.
+//! Build with: cargo build --target wasm32-unknown-unknown --no-default-features --features wasm
+//!
+//! Arguments are plain JS objects deserialized with serde-wasm-bindgen, using the same field
+//! names as the Rust structs; missing fields take their defaults. Orders come back as
+//! {qty, price, order_type} with order_type as its snake_case name. Invalid input throws.
+
+use crate::closes::{
+    calc_closes_long, calc_closes_short, calc_next_close_long, calc_next_close_short,
+};
+use crate::entries::{
+    calc_entries_long, calc_entries_short, calc_grid_entry_long, calc_grid_entry_short,
+    calc_next_entry_long, calc_next_entry_short,
+};
+use crate::types::{
+    BotParams, BotParamsError, ExchangeParams, Order, Position, StateParams, TrailingPriceBundle,
+};
+use serde_wasm_bindgen::{from_value, to_value};
+use wasm_bindgen::prelude::*;
+
+type Calculator =
+    fn(&ExchangeParams, &StateParams, &BotParams, &Position, &TrailingPriceBundle) -> Order;
+type GridCalculator = fn(&ExchangeParams, &StateParams, &BotParams, &Position) -> Order;
+type BatchCalculator =
+    fn(&ExchangeParams, &StateParams, &BotParams, &Position, &TrailingPriceBundle) -> Vec<Order>;
+
+/// Deserializes bot params and applies auto_adjust_markup. Out of range values throw,
+/// like in bot_params_from_config; other validation errors are left to the caller.
+fn bot_params_from_value(value: JsValue) -> Result<BotParams, JsError> {
+    let mut bot_params: BotParams = from_value(value)?;
+    bot_params.adjust_markup();
+    if let Err(errors) = bot_params.validate() {
+        let fatal: Vec<String> = errors
+            .iter()
+            .filter(|error| matches!(error, BotParamsError::OutOfRange { .. }))
+            .map(|error| error.to_string())
+            .collect();
+        if !fatal.is_empty() {
+            return Err(JsError::new(&fatal.join("; ")));
+        }
+    }
+    Ok(bot_params)
+}
+
+fn run_calculator(
+    exchange_params: JsValue,
+    state_params: JsValue,
+    bot_params: JsValue,
+    position: JsValue,
+    trailing_price_bundle: JsValue,
+    calculator: Calculator,
+) -> Result<JsValue, JsError> {
+    let order = calculator(
+        &from_value(exchange_params)?,
+        &from_value(state_params)?,
+        &bot_params_from_value(bot_params)?,
+        &from_value(position)?,
+        &from_value(trailing_price_bundle)?,
+    );
+    Ok(to_value(&order)?)
+}
+
+fn run_grid_calculator(
+    exchange_params: JsValue,
+    state_params: JsValue,
+    bot_params: JsValue,
+    position: JsValue,
+    calculator: GridCalculator,
+) -> Result<JsValue, JsError> {
+    let order = calculator(
+        &from_value(exchange_params)?,
+        &from_value(state_params)?,
+        &bot_params_from_value(bot_params)?,
+        &from_value(position)?,
+    );
+    Ok(to_value(&order)?)
+}
+
+fn run_batch_calculator(
+    exchange_params: JsValue,
+    state_params: JsValue,
+    bot_params: JsValue,
+    position: JsValue,
+    trailing_price_bundle: JsValue,
+    calculator: BatchCalculator,
+) -> Result<JsValue, JsError> {
+    let orders = calculator(
+        &from_value(exchange_params)?,
+        &from_value(state_params)?,
+        &bot_params_from_value(bot_params)?,
+        &from_value(position)?,
+        &from_value(trailing_price_bundle)?,
+    );
+    Ok(to_value(&orders)?)
+}
+
+#[wasm_bindgen(js_name = calc_grid_entry_long)]
+pub fn calc_grid_entry_long_js(
+    exchange_params: JsValue,
+    state_params: JsValue,
+    bot_params: JsValue,
+    position: JsValue,
+) -> Result<JsValue, JsError> {
+    run_grid_calculator(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        calc_grid_entry_long,
+    )
+}
+
+#[wasm_bindgen(js_name = calc_grid_entry_short)]
+pub fn calc_grid_entry_short_js(
+    exchange_params: JsValue,
+    state_params: JsValue,
+    bot_params: JsValue,
+    position: JsValue,
+) -> Result<JsValue, JsError> {
+    run_grid_calculator(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        calc_grid_entry_short,
+    )
+}
+
+#[wasm_bindgen(js_name = calc_next_entry_long)]
+pub fn calc_next_entry_long_js(
+    exchange_params: JsValue,
+    state_params: JsValue,
+    bot_params: JsValue,
+    position: JsValue,
+    trailing_price_bundle: JsValue,
+) -> Result<JsValue, JsError> {
+    run_calculator(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+        calc_next_entry_long,
+    )
+}
+
+#[wasm_bindgen(js_name = calc_next_entry_short)]
+pub fn calc_next_entry_short_js(
+    exchange_params: JsValue,
+    state_params: JsValue,
+    bot_params: JsValue,
+    position: JsValue,
+    trailing_price_bundle: JsValue,
+) -> Result<JsValue, JsError> {
+    run_calculator(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+        calc_next_entry_short,
+    )
+}
+
+#[wasm_bindgen(js_name = calc_next_close_long)]
+pub fn calc_next_close_long_js(
+    exchange_params: JsValue,
+    state_params: JsValue,
+    bot_params: JsValue,
+    position: JsValue,
+    trailing_price_bundle: JsValue,
+) -> Result<JsValue, JsError> {
+    run_calculator(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+        calc_next_close_long,
+    )
+}
+
+#[wasm_bindgen(js_name = calc_next_close_short)]
+pub fn calc_next_close_short_js(
+    exchange_params: JsValue,
+    state_params: JsValue,
+    bot_params: JsValue,
+    position: JsValue,
+    trailing_price_bundle: JsValue,
+) -> Result<JsValue, JsError> {
+    run_calculator(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+        calc_next_close_short,
+    )
+}
+
+/// Full entry grid, nearest first.
+#[wasm_bindgen(js_name = calc_entries_long)]
+pub fn calc_entries_long_js(
+    exchange_params: JsValue,
+    state_params: JsValue,
+    bot_params: JsValue,
+    position: JsValue,
+    trailing_price_bundle: JsValue,
+) -> Result<JsValue, JsError> {
+    run_batch_calculator(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+        calc_entries_long,
+    )
+}
+
+/// Full entry grid, nearest first.
+#[wasm_bindgen(js_name = calc_entries_short)]
+pub fn calc_entries_short_js(
+    exchange_params: JsValue,
+    state_params: JsValue,
+    bot_params: JsValue,
+    position: JsValue,
+    trailing_price_bundle: JsValue,
+) -> Result<JsValue, JsError> {
+    run_batch_calculator(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+        calc_entries_short,
+    )
+}
+
+/// Full close grid, nearest first.
+#[wasm_bindgen(js_name = calc_closes_long)]
+pub fn calc_closes_long_js(
+    exchange_params: JsValue,
+    state_params: JsValue,
+    bot_params: JsValue,
+    position: JsValue,
+    trailing_price_bundle: JsValue,
+) -> Result<JsValue, JsError> {
+    run_batch_calculator(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+        calc_closes_long,
+    )
+}
+
+/// Full close grid, nearest first.
+#[wasm_bindgen(js_name = calc_closes_short)]
+pub fn calc_closes_short_js(
+    exchange_params: JsValue,
+    state_params: JsValue,
+    bot_params: JsValue,
+    position: JsValue,
+    trailing_price_bundle: JsValue,
+) -> Result<JsValue, JsError> {
+    run_batch_calculator(
+        exchange_params,
+        state_params,
+        bot_params,
+        position,
+        trailing_price_bundle,
+        calc_closes_short,
+    )
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::types::{OrderBook, OrderType};
+    use serde::{Deserialize, Serialize};
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    type JsCalculator = fn(JsValue, JsValue, JsValue, JsValue, JsValue) -> Result<JsValue, JsError>;
+
+    fn bot_params() -> BotParams {
+        let mut bot_params = fixtures::bot_params();
+        bot_params.adjust_markup();
+        bot_params
+    }
+
+    /// Runs calculator on the fixtures, converted to JS objects and back.
+    fn run_js<T: for<'de> Deserialize<'de>>(
+        js_calculator: JsCalculator,
+        state_params: &StateParams,
+        bot_params: &BotParams,
+        position: &Position,
+    ) -> Result<T, JsError> {
+        let result = js_calculator(
+            to_value(&fixtures::exchange_params())?,
+            to_value(state_params)?,
+            to_value(bot_params)?,
+            to_value(position)?,
+            to_value(&TrailingPriceBundle::default())?,
+        )?;
+        Ok(from_value(result)?)
+    }
+
+    #[wasm_bindgen_test]
+    fn next_orders_match_rust() {
+        let state_params = fixtures::state_params(60000.0);
+        let long = fixtures::position_long(60000.0);
+        let cases: [(Position, JsCalculator, Calculator); 3] = [
+            (
+                Position::default(),
+                calc_next_entry_long_js,
+                calc_next_entry_long,
+            ),
+            (long, calc_next_entry_long_js, calc_next_entry_long),
+            (long, calc_next_close_long_js, calc_next_close_long),
+        ];
+        for (position, js_calculator, calculator) in cases {
+            let order: Order = run_js(
+                js_calculator,
+                &state_params,
+                &fixtures::bot_params(),
+                &position,
+            )
+            .unwrap();
+            let expected = calculator(
+                &fixtures::exchange_params(),
+                &state_params,
+                &bot_params(),
+                &position,
+                &TrailingPriceBundle::default(),
+            );
+            assert_eq!(order, expected);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn entry_grid_matches_rust() {
+        let state_params = fixtures::state_params(60000.0);
+        let long = fixtures::position_long(60000.0);
+        let entries: Vec<Order> = run_js(
+            calc_entries_long_js,
+            &state_params,
+            &fixtures::bot_params(),
+            &long,
+        )
+        .unwrap();
+        assert!(!entries.is_empty());
+        let expected = calc_entries_long(
+            &fixtures::exchange_params(),
+            &state_params,
+            &bot_params(),
+            &long,
+            &TrailingPriceBundle::default(),
+        );
+        assert_eq!(entries, expected);
+    }
+
+    #[wasm_bindgen_test]
+    fn order_type_as_snake_case_name() {
+        #[derive(Deserialize)]
+        struct JsOrder {
+            qty: f64,
+            order_type: String,
+        }
+        let order: JsOrder = run_js(
+            calc_next_entry_long_js,
+            &fixtures::state_params(60000.0),
+            &fixtures::bot_params(),
+            &Position::default(),
+        )
+        .unwrap();
+        assert!(order.qty > 0.0);
+        assert_eq!(
+            order.order_type,
+            OrderType::EntryInitialNormalLong.to_string()
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn missing_fields_take_defaults() {
+        #[derive(Serialize)]
+        struct PartialState {
+            balance: f64,
+            order_book: OrderBook,
+        }
+        let order_book = OrderBook {
+            bid: 60000.0,
+            ask: 60000.0,
+        };
+        let result = calc_grid_entry_long_js(
+            to_value(&fixtures::exchange_params()).unwrap(),
+            to_value(&PartialState {
+                balance: 10000.0,
+                order_book: order_book.clone(),
+            })
+            .unwrap(),
+            to_value(&fixtures::bot_params()).unwrap(),
+            to_value(&Position::default()).unwrap(),
+        )
+        .unwrap();
+        let state_params = StateParams {
+            balance: 10000.0,
+            order_book,
+            ..Default::default()
+        };
+        let expected = calc_grid_entry_long(
+            &fixtures::exchange_params(),
+            &state_params,
+            &bot_params(),
+            &Position::default(),
+        );
+        assert_eq!(from_value::<Order>(result).unwrap(), expected);
+    }
+
+    #[wasm_bindgen_test]
+    fn out_of_range_bot_params_throw() {
+        let bot_params = BotParams {
+            ema_span_0: -1.0,
+            ..fixtures::bot_params()
+        };
+        let result: Result<Order, _> = run_js(
+            calc_next_entry_long_js,
+            &fixtures::state_params(60000.0),
+            &bot_params,
+            &Position::default(),
+        );
+        assert!(result.is_err());
+    }
+}
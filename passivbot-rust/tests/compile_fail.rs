@@ -0,0 +1,6 @@
+/// Ignored calculator results must not compile with unused_must_use denied.
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,434 @@
+#![cfg(feature = "ffi")]
+
+use passivbot_rust::closes::{calc_next_close_long, calc_next_close_short};
+use passivbot_rust::entries::{calc_next_entry_long, calc_next_entry_short};
+use passivbot_rust::ffi::*;
+use passivbot_rust::fixtures;
+use passivbot_rust::types::{
+    BotParams, EmaWarmupBehavior, EntrySizingBasis, EntryTrailingGridMode, EntryTrailingMode,
+    ExchangeParams, Order, OrderType, Position, StateParams, TrailingPriceBundle,
+};
+
+fn exchange_params_c(exchange_params: &ExchangeParams) -> ExchangeParamsC {
+    ExchangeParamsC {
+        qty_step: exchange_params.qty_step,
+        price_step: exchange_params.price_step,
+        min_qty: exchange_params.min_qty,
+        min_cost: exchange_params.min_cost,
+        c_mult: exchange_params.c_mult,
+        fee_rate: exchange_params.fee_rate,
+        max_position_size: exchange_params.max_position_size,
+        price_band_pct: exchange_params.price_band_pct,
+    }
+}
+
+fn state_params_c(state_params: &StateParams) -> StateParamsC {
+    StateParamsC {
+        balance: state_params.balance,
+        bid: state_params.order_book.bid,
+        ask: state_params.order_book.ask,
+        ema_band_upper: state_params.ema_bands.upper,
+        ema_band_lower: state_params.ema_bands.lower,
+        timestamp: state_params.timestamp,
+        last_entry_fill_price: state_params
+            .last_entry_fill
+            .map_or(f64::NAN, |fill| fill.price),
+        last_entry_fill_timestamp: state_params
+            .last_entry_fill
+            .map_or(0, |fill| fill.timestamp),
+        trailing_add_count: state_params.trailing_add_count as u64,
+        equity: state_params.equity.unwrap_or(f64::NAN),
+        volatility: state_params.volatility,
+    }
+}
+
+fn bot_params_c(bot_params: &BotParams) -> BotParamsC {
+    let mut entry_grid_qty_schedule = [0.0; PB_MAX_QTY_SCHEDULE_LEN];
+    entry_grid_qty_schedule[..bot_params.entry_grid_qty_schedule.len()]
+        .copy_from_slice(&bot_params.entry_grid_qty_schedule);
+    let mut entry_blackout_windows = [[0; 2]; PB_MAX_BLACKOUT_WINDOWS];
+    for (window, &(start, end)) in entry_blackout_windows
+        .iter_mut()
+        .zip(&bot_params.entry_blackout_windows)
+    {
+        *window = [start, end];
+    }
+    BotParamsC {
+        auto_adjust_markup: bot_params.auto_adjust_markup as u8,
+        balance_reserve_pct: bot_params.balance_reserve_pct,
+        bracket_stop_pct: bot_params.bracket_stop_pct,
+        close_grid_gap_pct: bot_params.close_grid_gap_pct,
+        close_grid_markup_range: bot_params.close_grid_markup_range,
+        close_grid_min_markup: bot_params.close_grid_min_markup,
+        close_min_net_markup: bot_params.close_min_net_markup,
+        close_grid_qty_pct: bot_params.close_grid_qty_pct,
+        close_trailing_retracement_pct: bot_params.close_trailing_retracement_pct,
+        close_trailing_grid_ratio: bot_params.close_trailing_grid_ratio,
+        close_trailing_qty_pct: bot_params.close_trailing_qty_pct,
+        close_trailing_threshold_pct: bot_params.close_trailing_threshold_pct,
+        coalesce_grid_orders: bot_params.coalesce_grid_orders as u8,
+        entry_grid_double_down_factor: bot_params.entry_grid_double_down_factor,
+        entry_grid_spacing_weight: bot_params.entry_grid_spacing_weight,
+        entry_grid_spacing_pct: bot_params.entry_grid_spacing_pct,
+        entry_grid_ema_floor: bot_params.entry_grid_ema_floor as u8,
+        entry_initial_ema_dist: bot_params.entry_initial_ema_dist,
+        entry_initial_qty_pct: bot_params.entry_initial_qty_pct,
+        entry_initial_timeout_sec: bot_params.entry_initial_timeout_sec,
+        entry_reentry_ema_dist: bot_params.entry_reentry_ema_dist.unwrap_or(f64::NAN),
+        entry_trailing_drawdown_pct: bot_params.entry_trailing_drawdown_pct,
+        entry_trailing_mode: match bot_params.entry_trailing_mode {
+            EntryTrailingMode::ThresholdDrawdown => PB_ENTRY_TRAILING_MODE_THRESHOLD_DRAWDOWN,
+            EntryTrailingMode::TrailingStop => PB_ENTRY_TRAILING_MODE_TRAILING_STOP,
+        },
+        entry_trailing_retracement_pct: bot_params.entry_trailing_retracement_pct,
+        entry_trailing_grid_ratio: bot_params.entry_trailing_grid_ratio,
+        entry_trailing_threshold_pct: bot_params.entry_trailing_threshold_pct,
+        filter_rolling_window: bot_params.filter_rolling_window as u64,
+        filter_relative_volume_clip_pct: bot_params.filter_relative_volume_clip_pct,
+        ema_span_0: bot_params.ema_span_0,
+        ema_span_1: bot_params.ema_span_1,
+        n_positions: bot_params.n_positions as u64,
+        total_wallet_exposure_limit: bot_params.total_wallet_exposure_limit,
+        wallet_exposure_limit: bot_params.wallet_exposure_limit,
+        unstuck_close_pct: bot_params.unstuck_close_pct,
+        unstuck_ema_dist: bot_params.unstuck_ema_dist,
+        unstuck_loss_allowance_pct: bot_params.unstuck_loss_allowance_pct,
+        unstuck_threshold: bot_params.unstuck_threshold,
+        entry_trailing_max_adds: bot_params.entry_trailing_max_adds as u64,
+        entry_trailing_grid_mode: match bot_params.entry_trailing_grid_mode {
+            EntryTrailingGridMode::Switch => PB_ENTRY_TRAILING_GRID_MODE_SWITCH,
+            EntryTrailingGridMode::Blended => PB_ENTRY_TRAILING_GRID_MODE_BLENDED,
+        },
+        ema_warmup_behavior: match bot_params.ema_warmup_behavior {
+            EmaWarmupBehavior::SuppressEntries => PB_EMA_WARMUP_SUPPRESS_ENTRIES,
+            EmaWarmupBehavior::FallbackToOrderBook => PB_EMA_WARMUP_FALLBACK_TO_ORDER_BOOK,
+        },
+        close_trailing_min_profit_pct: bot_params.close_trailing_min_profit_pct,
+        entry_grid_qty_schedule,
+        entry_grid_qty_schedule_len: bot_params.entry_grid_qty_schedule.len() as u64,
+        entry_blackout_windows,
+        entry_blackout_windows_len: bot_params.entry_blackout_windows.len() as u64,
+        close_grid_max_markup: bot_params.close_grid_max_markup,
+        entry_sizing_basis: match bot_params.entry_sizing_basis {
+            EntrySizingBasis::Balance => PB_ENTRY_SIZING_BALANCE,
+            EntrySizingBasis::Equity => PB_ENTRY_SIZING_EQUITY,
+        },
+        close_trailing_drawdown_floor_pct: bot_params.close_trailing_drawdown_floor_pct,
+        entry_grid_spacing_vol_weight: bot_params.entry_grid_spacing_vol_weight,
+        close_grid_min_entry_distance_ratio: bot_params.close_grid_min_entry_distance_ratio,
+    }
+}
+
+type FfiCalculator = unsafe extern "C" fn(
+    *const ExchangeParamsC,
+    *const StateParamsC,
+    *const BotParamsC,
+    *const PositionC,
+    f64,
+    f64,
+    *mut OrderC,
+) -> i32;
+
+/// Calls calculator with the trailing prices passed to every ffi function, returning its code
+/// and the order written to out.
+fn call(
+    calculator: FfiCalculator,
+    state_params: &StateParams,
+    bot_params: &BotParamsC,
+    position: &Position,
+) -> (i32, OrderC) {
+    let mut out = OrderC {
+        qty: f64::NAN,
+        price: f64::NAN,
+        order_type: u8::MAX,
+    };
+    let code = unsafe {
+        calculator(
+            &exchange_params_c(&fixtures::exchange_params()),
+            &state_params_c(state_params),
+            bot_params,
+            &PositionC {
+                size: position.size,
+                price: position.price,
+            },
+            f64::MAX,
+            0.0,
+            &mut out,
+        )
+    };
+    (code, out)
+}
+
+fn assert_same_order(ffi_order: OrderC, order: Order) {
+    assert_eq!(ffi_order.qty, order.qty);
+    assert_eq!(ffi_order.price, order.price);
+    assert_eq!(
+        OrderType::from_code(ffi_order.order_type),
+        Some(order.order_type)
+    );
+}
+
+#[test]
+fn matches_rust_calculators() {
+    let bot_params = fixtures::bot_params();
+    let exchange_params = fixtures::exchange_params();
+    let long = fixtures::position_long(60000.0);
+    let short = Position {
+        size: -long.size,
+        price: 60000.0 * 0.97,
+    };
+    // the trailing prices of call
+    let long_trailing = TrailingPriceBundle {
+        min_since_open: f64::MAX,
+        max_since_min: 0.0,
+        ..Default::default()
+    };
+    let short_trailing = TrailingPriceBundle {
+        max_since_open: f64::MAX,
+        min_since_max: 0.0,
+        ..Default::default()
+    };
+    let close_long_trailing = TrailingPriceBundle {
+        max_since_open: f64::MAX,
+        min_since_max: 0.0,
+        ..Default::default()
+    };
+    let close_short_trailing = TrailingPriceBundle {
+        min_since_open: f64::MAX,
+        max_since_min: 0.0,
+        ..Default::default()
+    };
+    for price in [59000.0, 60000.0, 61000.0] {
+        let state_params = fixtures::state_params(price);
+        for position in [Position::default(), long] {
+            let (code, order) = call(
+                pb_calc_next_entry_long,
+                &state_params,
+                &bot_params_c(&bot_params),
+                &position,
+            );
+            assert_eq!(code, PB_OK);
+            assert_same_order(
+                order,
+                calc_next_entry_long(
+                    &exchange_params,
+                    &state_params,
+                    &bot_params,
+                    &position,
+                    &long_trailing,
+                ),
+            );
+        }
+        for position in [Position::default(), short] {
+            let (code, order) = call(
+                pb_calc_next_entry_short,
+                &state_params,
+                &bot_params_c(&bot_params),
+                &position,
+            );
+            assert_eq!(code, PB_OK);
+            assert_same_order(
+                order,
+                calc_next_entry_short(
+                    &exchange_params,
+                    &state_params,
+                    &bot_params,
+                    &position,
+                    &short_trailing,
+                ),
+            );
+        }
+        let (code, order) = call(
+            pb_calc_next_close_long,
+            &state_params,
+            &bot_params_c(&bot_params),
+            &long,
+        );
+        assert_eq!(code, PB_OK);
+        assert_same_order(
+            order,
+            calc_next_close_long(
+                &exchange_params,
+                &state_params,
+                &bot_params,
+                &long,
+                &close_long_trailing,
+            ),
+        );
+        let (code, order) = call(
+            pb_calc_next_close_short,
+            &state_params,
+            &bot_params_c(&bot_params),
+            &short,
+        );
+        assert_eq!(code, PB_OK);
+        assert_same_order(
+            order,
+            calc_next_close_short(
+                &exchange_params,
+                &state_params,
+                &bot_params,
+                &short,
+                &close_short_trailing,
+            ),
+        );
+    }
+}
+
+#[test]
+fn initial_entry_through_ffi() {
+    let (code, order) = call(
+        pb_calc_next_entry_long,
+        &fixtures::state_params(60000.0),
+        &bot_params_c(&fixtures::bot_params()),
+        &Position::default(),
+    );
+    assert_eq!(code, PB_OK);
+    assert!(order.qty > 0.0);
+    assert!(order.price > 0.0 && order.price <= 60000.0);
+    assert_eq!(
+        OrderType::from_code(order.order_type),
+        Some(OrderType::EntryInitialNormalLong)
+    );
+}
+
+#[test]
+fn null_pointers() {
+    let exchange_params = exchange_params_c(&fixtures::exchange_params());
+    let state_params = state_params_c(&fixtures::state_params(60000.0));
+    let bot_params = bot_params_c(&fixtures::bot_params());
+    let position = PositionC {
+        size: 0.0,
+        price: 0.0,
+    };
+    let mut out = OrderC {
+        qty: -1.0,
+        price: -1.0,
+        order_type: u8::MAX,
+    };
+    unsafe {
+        let code = pb_calc_next_entry_long(
+            std::ptr::null(),
+            &state_params,
+            &bot_params,
+            &position,
+            f64::MAX,
+            0.0,
+            &mut out,
+        );
+        assert_eq!(code, PB_ERR_NULL_POINTER);
+        let code = pb_calc_next_close_short(
+            &exchange_params,
+            &state_params,
+            &bot_params,
+            &position,
+            f64::MAX,
+            0.0,
+            std::ptr::null_mut(),
+        );
+        assert_eq!(code, PB_ERR_NULL_POINTER);
+    }
+    // out is only written on PB_OK
+    assert_eq!(out.qty, -1.0);
+    assert_eq!(out.order_type, u8::MAX);
+}
+
+#[test]
+fn invalid_params() {
+    let state_params = fixtures::state_params(60000.0);
+    let valid = bot_params_c(&fixtures::bot_params());
+    let invalid = [
+        BotParamsC {
+            entry_trailing_mode: 2,
+            ..valid
+        },
+        BotParamsC {
+            entry_trailing_grid_mode: -1,
+            ..valid
+        },
+        BotParamsC {
+            ema_warmup_behavior: 2,
+            ..valid
+        },
+        BotParamsC {
+            entry_sizing_basis: 2,
+            ..valid
+        },
+        BotParamsC {
+            entry_grid_qty_schedule_len: PB_MAX_QTY_SCHEDULE_LEN as u64 + 1,
+            ..valid
+        },
+        BotParamsC {
+            entry_blackout_windows_len: PB_MAX_BLACKOUT_WINDOWS as u64 + 1,
+            ..valid
+        },
+    ];
+    for bot_params in &invalid {
+        let (code, order) = call(
+            pb_calc_next_entry_long,
+            &state_params,
+            bot_params,
+            &Position::default(),
+        );
+        assert_eq!(code, PB_ERR_INVALID_PARAMS);
+        assert_eq!(order.order_type, u8::MAX);
+    }
+}
+
+#[test]
+fn optional_values_as_nan() {
+    // large enough for the initial entry to be above min_qty
+    let bot_params = BotParams {
+        wallet_exposure_limit: 2.0,
+        entry_initial_qty_pct: 0.1,
+        entry_reentry_ema_dist: Some(0.01),
+        entry_sizing_basis: EntrySizingBasis::Equity,
+        ..fixtures::bot_params()
+    };
+    let mut state_params = fixtures::state_params(60000.0);
+    state_params.equity = Some(5000.0);
+    let (code, order) = call(
+        pb_calc_next_entry_long,
+        &state_params,
+        &bot_params_c(&bot_params),
+        &Position::default(),
+    );
+    assert_eq!(code, PB_OK);
+    let long_trailing = TrailingPriceBundle {
+        min_since_open: f64::MAX,
+        max_since_min: 0.0,
+        ..Default::default()
+    };
+    let expected = calc_next_entry_long(
+        &fixtures::exchange_params(),
+        &state_params,
+        &bot_params,
+        &Position::default(),
+        &long_trailing,
+    );
+    assert_same_order(order, expected);
+    // sized off equity, so smaller than with the unknown equity falling back to balance
+    state_params.equity = None;
+    let (_, from_balance) = call(
+        pb_calc_next_entry_long,
+        &state_params,
+        &bot_params_c(&bot_params),
+        &Position::default(),
+    );
+    assert!(order.qty < from_balance.qty);
+}
+
+// check_side only panics on wrong side positions in debug builds
+#[cfg(debug_assertions)]
+#[test]
+fn panics_are_caught() {
+    let long = fixtures::position_long(60000.0);
+    let (code, order) = call(
+        pb_calc_next_entry_short,
+        &fixtures::state_params(60000.0),
+        &bot_params_c(&fixtures::bot_params()),
+        &long,
+    );
+    assert_eq!(code, PB_ERR_PANIC);
+    assert_eq!(order.order_type, u8::MAX);
+}
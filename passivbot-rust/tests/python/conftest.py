@@ -0,0 +1,52 @@
+"""Tests of the passivbot_rust extension module. From passivbot-rust: maturin develop && pytest"""
+
+import json
+import pathlib
+
+import pytest
+
+CONFIG_PATH = pathlib.Path(__file__).resolve().parents[3] / "configs" / "template.json"
+
+
+@pytest.fixture
+def config():
+    with open(CONFIG_PATH) as f:
+        return json.load(f)
+
+
+@pytest.fixture
+def exchange_params():
+    return {
+        "qty_step": 0.001,
+        "price_step": 0.1,
+        "min_qty": 0.001,
+        "min_cost": 5.0,
+        "c_mult": 1.0,
+    }
+
+
+@pytest.fixture
+def bot_params_pair(config):
+    # wallet_exposure_limit is derived the same way as in src/backtest.py
+    bot = config["bot"]
+    for pside in bot:
+        bot[pside]["wallet_exposure_limit"] = (
+            bot[pside]["total_wallet_exposure_limit"] / bot[pside]["n_positions"]
+        )
+    return bot
+
+
+@pytest.fixture
+def bot_params(bot_params_pair):
+    return bot_params_pair["long"]
+
+
+@pytest.fixture
+def state():
+    return {
+        "balance": 10000.0,
+        "order_book_bid": 60000.0,
+        "order_book_ask": 60000.0,
+        "ema_bands_upper": 60100.0,
+        "ema_bands_lower": 59900.0,
+    }
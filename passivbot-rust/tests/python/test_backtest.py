@@ -0,0 +1,120 @@
+import passivbot_rust as pbr
+import pytest
+
+np = pytest.importorskip("numpy")
+
+# sine_wave_smoke in tests/runner.rs runs the same backtest in Rust
+RUST_N_TRADES = 31
+RUST_FINAL_BALANCE = 10022.978574779987
+
+
+def sine_candles(n_candles, period):
+    """[timestamp, high, low, close] rows, as sine_candles in tests/runner.rs."""
+    phase = np.arange(n_candles) / period * (2.0 * np.pi)
+    close = np.floor(60000.0 * (1.0 + 0.05 * np.sin(phase)) * 10.0 + 0.5) / 10.0
+    timestamps = np.arange(n_candles, dtype=np.float64) * 60_000.0
+    rows = [timestamps, close * 1.0005, close * 0.9995, close]
+    return np.ascontiguousarray(np.column_stack(rows))
+
+
+@pytest.fixture
+def fixture_bot_params_pair(bot_params_pair):
+    # fixtures::bot_params with short disabled, as long_only in tests/runner.rs
+    long = {**bot_params_pair["long"], "n_positions": 10, "filter_rolling_window": 60}
+    long["wallet_exposure_limit"] = long["total_wallet_exposure_limit"] / 10
+    short = {**bot_params_pair["short"], "wallet_exposure_limit": 0.0}
+    return {"long": long, "short": short}
+
+
+@pytest.fixture
+def fixture_exchange_params(exchange_params):
+    return {**exchange_params, "fee_rate": 0.0002}
+
+
+@pytest.fixture
+def backtest_params():
+    return {"starting_balance": 10000.0, "maker_fee": 0.0002}
+
+
+def test_matches_rust_runner(fixture_bot_params_pair, fixture_exchange_params, backtest_params):
+    candles = sine_candles(20_000, 2_000)
+    result = pbr.run_backtest_py(
+        candles, fixture_bot_params_pair, fixture_exchange_params, backtest_params
+    )
+    assert result.n_trades == RUST_N_TRADES
+    assert result.final_balance == pytest.approx(RUST_FINAL_BALANCE, rel=1e-12)
+    fills = result.fills
+    assert len(fills) == RUST_N_TRADES
+    assert fills["balance"][-1] == pytest.approx(RUST_FINAL_BALANCE, rel=1e-12)
+    assert set(fills["order_type"]) >= {"entry_initial_normal_long"}
+    assert (np.diff(fills["index"]) >= 0).all()
+    equities = result.equities
+    assert equities.shape == (len(candles),)
+    assert result.to_dict()["metrics"]["n_trades"] == RUST_N_TRADES
+
+
+def test_volume_column_and_symbol_list(
+    fixture_bot_params_pair, fixture_exchange_params, backtest_params
+):
+    candles = sine_candles(4_000, 2_000)
+    with_volume = np.ascontiguousarray(np.column_stack([candles, np.full(len(candles), 100.0)]))
+    single = pbr.run_backtest_py(
+        candles, fixture_bot_params_pair, fixture_exchange_params, backtest_params
+    )
+    # volume only matters with max_fill_volume_fraction, which run_backtest_py leaves unlimited
+    assert (
+        pbr.run_backtest_py(
+            with_volume, fixture_bot_params_pair, fixture_exchange_params, backtest_params
+        )
+        == single
+    )
+    results = pbr.run_backtest_py(
+        [candles, candles],
+        fixture_bot_params_pair,
+        [fixture_exchange_params, fixture_exchange_params],
+        backtest_params,
+    )
+    assert results == [single, single]
+
+
+def test_symbol_list_length_mismatch(
+    fixture_bot_params_pair, fixture_exchange_params, backtest_params
+):
+    candles = sine_candles(100, 2_000)
+    with pytest.raises(ValueError, match="2 candle arrays but 1 exchange_params"):
+        pbr.run_backtest_py(
+            [candles, candles],
+            fixture_bot_params_pair,
+            [fixture_exchange_params],
+            backtest_params,
+        )
+
+
+def test_blackout_windows_need_first_timestamp(
+    fixture_bot_params_pair, fixture_exchange_params, backtest_params, tmp_path
+):
+    candles = sine_candles(100, 2_000)
+    hlcvs = np.column_stack([candles[:, 1:], np.full(len(candles), 100.0)])[:, None, :]
+    hlcvs_path = tmp_path / "hlcvs.bin"
+    np.ascontiguousarray(hlcvs).tofile(hlcvs_path)
+    bot_params_pair = {
+        **fixture_bot_params_pair,
+        "long": {**fixture_bot_params_pair["long"], "entry_blackout_windows": [[0, 60_000]]},
+    }
+    backtest_params = {**backtest_params, "symbols": ["BTCUSDT"]}
+
+    def run(backtest_params):
+        return pbr.run_backtest(
+            str(hlcvs_path),
+            hlcvs.shape,
+            hlcvs.dtype.str,
+            bot_params_pair,
+            [fixture_exchange_params],
+            backtest_params,
+        )
+
+    # hlcvs has no timestamps of its own, so the windows can't be placed without one
+    with pytest.raises(ValueError, match="first_timestamp_ms"):
+        run(backtest_params)
+    fills, equities, _ = run({**backtest_params, "first_timestamp_ms": 0})
+    assert equities.shape[0] == len(candles)
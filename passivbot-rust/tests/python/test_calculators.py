@@ -0,0 +1,182 @@
+import passivbot_rust as pbr
+import pytest
+
+EXCHANGE_KEYS = ["qty_step", "price_step", "min_qty", "min_cost", "c_mult"]
+ENTRY_KEYS = [
+    "entry_grid_double_down_factor",
+    "entry_grid_spacing_weight",
+    "entry_grid_spacing_pct",
+    "entry_initial_ema_dist",
+    "entry_initial_qty_pct",
+    "entry_trailing_grid_ratio",
+    "entry_trailing_retracement_pct",
+    "entry_trailing_threshold_pct",
+    "wallet_exposure_limit",
+]
+CLOSE_KEYS = [
+    "close_grid_markup_range",
+    "close_grid_min_markup",
+    "close_grid_qty_pct",
+    "close_trailing_grid_ratio",
+    "close_trailing_qty_pct",
+    "close_trailing_retracement_pct",
+    "close_trailing_threshold_pct",
+    "wallet_exposure_limit",
+]
+TRAILING_DEFAULTS = {
+    "min_since_open": 0.0,
+    "max_since_min": 0.0,
+    "max_since_open": 0.0,
+    "min_since_max": 0.0,
+}
+
+
+def flat_args(exchange_params, bot_params, bot_keys, state, state_keys):
+    """Positional arguments of the legacy wrappers, in numba signature order."""
+    state = {**TRAILING_DEFAULTS, **state}
+    return (
+        [exchange_params[key] for key in EXCHANGE_KEYS]
+        + [bot_params[key] for key in bot_keys]
+        + [state[key] for key in state_keys]
+    )
+
+
+def as_tuple(order):
+    return (order.qty, order.price, str(order.order_type))
+
+
+@pytest.fixture
+def long_position(state):
+    return {**state, "position_size": 0.01, "position_price": 62000.0}
+
+
+@pytest.fixture
+def short_position(state):
+    return {**state, "position_size": -0.01, "position_price": 58000.0}
+
+
+def test_min_entry_qty():
+    # min_cost 5.0 at price 1000.0 is 0.005, above min_qty
+    assert pbr.calc_min_entry_qty_py(1000.0, 0.001, 0.001, 5.0, 1.0) == pytest.approx(0.005)
+    assert pbr.calc_min_entry_qty_py(60000.0, 0.001, 0.001, 5.0, 1.0) == pytest.approx(0.001)
+    # c_mult scales cost per unit of qty
+    assert pbr.calc_min_entry_qty_py(1000.0, 0.001, 0.001, 5.0, 0.1) == pytest.approx(0.05)
+
+
+def test_initial_entry_order_type_strings(exchange_params, bot_params, state):
+    flat_long = {**state, "position_size": 0.0, "position_price": 0.0}
+    flat_short = {**state, "position_size": 0.0, "position_price": 0.0}
+    long_entry = pbr.calc_next_entry_long_dict_py(exchange_params, bot_params, flat_long)
+    short_entry = pbr.calc_next_entry_short_dict_py(exchange_params, bot_params, flat_short)
+    assert str(long_entry.order_type) == "entry_initial_normal_long"
+    assert long_entry.qty > 0.0
+    assert str(short_entry.order_type) == "entry_initial_normal_short"
+    assert short_entry.qty < 0.0
+
+
+@pytest.mark.parametrize("position_size", [0.0, 0.01])
+def test_next_entry_long_flat_matches_dict(exchange_params, bot_params, state, position_size):
+    state = {**state, "position_size": position_size, "position_price": 62000.0}
+    args = flat_args(
+        exchange_params,
+        bot_params,
+        ENTRY_KEYS,
+        state,
+        [
+            "balance",
+            "position_size",
+            "position_price",
+            "min_since_open",
+            "max_since_min",
+            "ema_bands_lower",
+            "order_book_bid",
+        ],
+    )
+    order = pbr.calc_next_entry_long_dict_py(exchange_params, bot_params, state)
+    assert pbr.calc_next_entry_long_py(*args) == as_tuple(order)
+    entries = pbr.calc_entries_long_py(*args)
+    assert entries and entries[0] == as_tuple(order)
+    assert all(qty > 0.0 for qty, _, _ in entries)
+
+
+@pytest.mark.parametrize("position_size", [0.0, -0.01])
+def test_next_entry_short_flat_matches_dict(exchange_params, bot_params, state, position_size):
+    state = {**state, "position_size": position_size, "position_price": 58000.0}
+    args = flat_args(
+        exchange_params,
+        bot_params,
+        ENTRY_KEYS,
+        state,
+        [
+            "balance",
+            "position_size",
+            "position_price",
+            "max_since_open",
+            "min_since_max",
+            "ema_bands_upper",
+            "order_book_ask",
+        ],
+    )
+    order = pbr.calc_next_entry_short_dict_py(exchange_params, bot_params, state)
+    assert pbr.calc_next_entry_short_py(*args) == as_tuple(order)
+    entries = pbr.calc_entries_short_py(*args)
+    assert entries and entries[0] == as_tuple(order)
+    assert all(qty < 0.0 for qty, _, _ in entries)
+
+
+def test_closes_long_flat_matches_dict(exchange_params, bot_params, long_position):
+    args = flat_args(
+        exchange_params,
+        bot_params,
+        CLOSE_KEYS,
+        long_position,
+        [
+            "balance",
+            "position_size",
+            "position_price",
+            "max_since_open",
+            "min_since_max",
+            "order_book_ask",
+        ],
+    )
+    closes = pbr.calc_closes_long_dict_py(exchange_params, bot_params, long_position)
+    assert closes
+    assert pbr.calc_closes_long_py(*args) == [as_tuple(order) for order in closes]
+    assert pbr.calc_next_close_long_py(*args) == as_tuple(closes[0])
+    assert all(str(order.order_type).startswith("close_") for order in closes)
+    assert sum(order.qty for order in closes) == pytest.approx(-long_position["position_size"])
+
+
+def test_closes_short_flat_matches_dict(exchange_params, bot_params, short_position):
+    args = flat_args(
+        exchange_params,
+        bot_params,
+        CLOSE_KEYS,
+        short_position,
+        [
+            "balance",
+            "position_size",
+            "position_price",
+            "min_since_open",
+            "max_since_min",
+            "order_book_bid",
+        ],
+    )
+    closes = pbr.calc_closes_short_dict_py(exchange_params, bot_params, short_position)
+    assert closes
+    assert pbr.calc_closes_short_py(*args) == [as_tuple(order) for order in closes]
+    assert pbr.calc_next_close_short_py(*args) == as_tuple(closes[0])
+    assert sum(order.qty for order in closes) == pytest.approx(-short_position["position_size"])
+
+
+def test_next_grid_trigger_price(exchange_params, bot_params, long_position, short_position):
+    trigger_long = pbr.calc_next_grid_trigger_price_long_dict_py(
+        exchange_params, bot_params, long_position
+    )
+    trigger_short = pbr.calc_next_grid_trigger_price_short_dict_py(
+        exchange_params, bot_params, short_position
+    )
+    assert trigger_long is not None and trigger_long < long_position["position_price"]
+    assert trigger_short is not None and trigger_short > short_position["position_price"]
+    flat = {**long_position, "position_size": 0.0, "position_price": 0.0}
+    assert pbr.calc_next_grid_trigger_price_long_dict_py(exchange_params, bot_params, flat) is None
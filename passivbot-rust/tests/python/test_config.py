@@ -0,0 +1,80 @@
+import copy
+
+import passivbot_rust as pbr
+import pytest
+
+
+@pytest.fixture
+def live_config(config, bot_params_pair):
+    # the whole template.json, with wallet_exposure_limit filled in by the bot_params_pair fixture
+    return {**config, "bot": bot_params_pair}
+
+
+def test_template_config(live_config):
+    assert pbr.check_bot_params_pair_py(live_config) == []
+    assert pbr.check_bot_params_pair_py(live_config["bot"]) == []
+
+
+def test_close_grid_entry_distance_check_is_opt_in(live_config):
+    # template.json has close_grid_min_markup below entry_grid_spacing_pct
+    live_config["bot"]["long"]["close_grid_min_entry_distance_ratio"] = 1.0
+    warnings = pbr.check_bot_params_pair_py(live_config)
+    assert len(warnings) == 1
+    assert warnings[0].startswith("bot.long.")
+    assert "close_grid_min_entry_distance_ratio" in warnings[0]
+
+
+def test_unknown_keys_are_warnings(live_config):
+    baseline = pbr.check_bot_params_pair_py(live_config)
+    live_config["bot"]["long"]["python_only_field"] = 1.0
+    live_config["bot"]["comment"] = "not a side"
+    warnings = pbr.check_bot_params_pair_py(live_config)
+    assert "unknown key bot.long.python_only_field" in warnings
+    assert "unknown key bot.comment" in warnings
+    assert len(warnings) == len(baseline) + 2
+
+
+def test_optional_keys_take_defaults(live_config):
+    live_config["bot"]["long"]["entry_trailing_mode"] = None
+    live_config["bot"]["long"]["entry_blackout_windows"] = [[1.7e12, 1.7e12 + 3.6e6]]
+    pbr.check_bot_params_pair_py(live_config)
+
+
+@pytest.mark.parametrize(
+    "side, key, value, message",
+    [
+        ("long", "ema_span_0", "465", "invalid bot.long.ema_span_0"),
+        ("long", "ema_span_0", -1.0, r"bot.long.ema_span_0 is -1, expected within \[1, inf\]"),
+        ("short", "n_positions", -2.0, "invalid bot.short.n_positions: expected a non-negative"),
+        ("short", "entry_trailing_mode", "sideways", "invalid bot.short.entry_trailing_mode"),
+        ("long", "entry_sizing_basis", "margin", "expected balance or equity, got 'margin'"),
+    ],
+)
+def test_malformed_values(live_config, side, key, value, message):
+    live_config["bot"][side][key] = value
+    with pytest.raises(ValueError, match=message):
+        pbr.check_bot_params_pair_py(live_config)
+
+
+def test_missing_required_key(live_config):
+    del live_config["bot"]["short"]["close_grid_qty_pct"]
+    with pytest.raises(ValueError, match="missing key bot.short.close_grid_qty_pct"):
+        pbr.check_bot_params_pair_py(live_config)
+
+
+def test_malformed_layout(live_config):
+    with pytest.raises(ValueError, match="invalid bot: expected a dict"):
+        pbr.check_bot_params_pair_py({**live_config, "bot": []})
+    without_short = copy.deepcopy(live_config)
+    del without_short["bot"]["short"]
+    with pytest.raises(ValueError, match="missing key bot.short"):
+        pbr.check_bot_params_pair_py(without_short)
+
+
+def test_exchange_params_errors(exchange_params, bot_params, state):
+    with pytest.raises(ValueError, match="invalid qty_step"):
+        pbr.calc_next_entry_long_dict_py(
+            {**exchange_params, "qty_step": "0.001"}, bot_params, state
+        )
+    # unknown exchange keys are ignored by the calculators
+    pbr.calc_next_entry_long_dict_py({**exchange_params, "symbol": "BTCUSDT"}, bot_params, state)
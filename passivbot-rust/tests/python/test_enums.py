@@ -0,0 +1,106 @@
+import passivbot_rust as pbr
+import pytest
+
+ORDER_TYPES = pbr.OrderType.members()
+
+
+def test_order_type_members():
+    assert len(ORDER_TYPES) == 23
+    assert [order_type.value for order_type in ORDER_TYPES] == list(range(23))
+    assert ORDER_TYPES[-1] == pbr.OrderType.Empty
+    assert len({order_type.name for order_type in ORDER_TYPES}) == 23
+
+
+@pytest.mark.parametrize("order_type", ORDER_TYPES, ids=str)
+def test_order_type_round_trips(order_type):
+    assert pbr.OrderType.from_name(order_type.name) == order_type
+    assert pbr.OrderType.from_value(order_type.value) == order_type
+    assert str(order_type) == order_type.name
+    assert int(order_type) == order_type.value
+    # the integer type codes of calc_entries_batch
+    assert pbr.order_type_from_code(order_type.value) == order_type.name
+    assert hash(order_type) == hash(pbr.OrderType.from_value(order_type.value))
+
+
+@pytest.mark.parametrize("order_type", ORDER_TYPES, ids=str)
+def test_order_type_predicates(order_type):
+    name = order_type.name
+    assert order_type.is_entry == name.startswith("entry_")
+    assert order_type.is_close == name.startswith("close_")
+    assert order_type.is_long == name.endswith("_long")
+    assert order_type.is_short == name.endswith("_short")
+    if order_type == pbr.OrderType.Empty:
+        assert order_type.side is None
+    else:
+        assert order_type.side == pbr.Side.from_name(name.rsplit("_", 1)[1])
+
+
+def test_order_type_invalid():
+    with pytest.raises(ValueError):
+        pbr.OrderType.from_name("EntryGridNormalLong")
+    with pytest.raises(ValueError, match="Invalid order type code 23"):
+        pbr.OrderType.from_value(23)
+    with pytest.raises(ValueError, match="Invalid order type code 255"):
+        pbr.order_type_from_code(255)
+
+
+def test_side():
+    assert pbr.Side.members() == [pbr.Side.Long, pbr.Side.Short]
+    for value, side in enumerate(pbr.Side.members()):
+        assert side.value == value
+        assert pbr.Side.from_value(value) == side
+        assert pbr.Side.from_name(side.name) == side
+        assert str(side) == side.name
+    assert [side.name for side in pbr.Side.members()] == ["long", "short"]
+    with pytest.raises(ValueError, match="Invalid side 2"):
+        pbr.Side.from_value(2)
+    with pytest.raises(ValueError):
+        pbr.Side.from_name("both")
+
+
+def test_usable_as_dict_keys():
+    counts = {order_type: 0 for order_type in ORDER_TYPES}
+    counts[pbr.OrderType.from_name("close_grid_long")] += 1
+    assert counts[pbr.OrderType.CloseGridLong] == 1
+    assert len({pbr.Side.Long, pbr.Side.from_value(0)}) == 1
+
+
+def test_empty_reason_members():
+    members = pbr.EmptyReason.members()
+    assert members[0] == pbr.EmptyReason.NotEmpty
+    assert [int(reason) for reason in members] == list(range(len(members)))
+    for reason in members:
+        assert getattr(pbr.EmptyReason, reason.name) == reason
+        assert str(reason) == reason.name
+        assert reason.is_empty == (reason != pbr.EmptyReason.NotEmpty)
+
+
+@pytest.fixture
+def explained(exchange_params, bot_params, state):
+    def explained(bot_updates={}, state_updates={}):
+        return pbr.calc_next_entry_long_explained_dict_py(
+            exchange_params, {**bot_params, **bot_updates}, {**state, **state_updates}
+        )
+
+    return explained
+
+
+def test_explained_matches_next_entry(exchange_params, bot_params, state, explained):
+    order, reason = explained()
+    assert reason == pbr.EmptyReason.NotEmpty
+    assert order == pbr.calc_next_entry_long_dict_py(exchange_params, bot_params, state)
+    assert order.order_type == pbr.OrderType.EntryInitialNormalLong
+
+
+@pytest.mark.parametrize(
+    "bot_updates, state_updates, expected",
+    [
+        ({"wallet_exposure_limit": 0.0}, {}, "ZeroExposureLimit"),
+        ({}, {"balance": 0.0}, "ZeroBalance"),
+        ({}, {"position_size": 100.0, "position_price": 60000.0}, "ExposureLimitReached"),
+    ],
+)
+def test_explained_reasons(explained, bot_updates, state_updates, expected):
+    order, reason = explained(bot_updates, state_updates)
+    assert reason == getattr(pbr.EmptyReason, expected)
+    assert order.qty == 0.0
@@ -0,0 +1,45 @@
+import struct
+
+import passivbot_rust as pbr
+import pytest
+
+
+@pytest.fixture
+def backtest_args(bot_params_pair, exchange_params):
+    # the args after shared_memory_file, hlcvs_shape and hlcvs_dtype
+    backtest_params = {"starting_balance": 10000.0, "maker_fee": 0.0002, "symbols": ["BTCUSDT"]}
+    return bot_params_pair, [exchange_params], backtest_params
+
+
+def write_hlcvs(path, n_timesteps, n_coins=1, n_columns=4):
+    n_values = n_timesteps * n_coins * n_columns
+    path.write_bytes(struct.pack("<%dd" % n_values, *[60000.0] * n_values))
+    return str(path)
+
+
+def test_missing_file_raises_os_error(tmp_path, backtest_args):
+    with pytest.raises(OSError, match="Unable to open shared memory file"):
+        pbr.run_backtest(str(tmp_path / "missing.bin"), (10, 1, 4), "<f8", *backtest_args)
+
+
+def test_invalid_input_raises_value_error(tmp_path, backtest_args):
+    path = write_hlcvs(tmp_path / "hlcvs.bin", 10)
+    with pytest.raises(ValueError, match="Unsupported dtype <f4"):
+        pbr.run_backtest(path, (10, 1, 4), "<f4", *backtest_args)
+    with pytest.raises(ValueError, match="does not fit shared memory file of 320 bytes"):
+        pbr.run_backtest(path, (11, 1, 4), "<f8", *backtest_args)
+    with pytest.raises(ValueError, match="needs at least one timestep and 4 columns"):
+        pbr.run_backtest(path, (20, 1, 2), "<f8", *backtest_args)
+    with pytest.raises(ValueError, match="missing key bot.long"):
+        pbr.run_backtest(path, (10, 1, 4), "<f8", {"bot": {}}, *backtest_args[1:])
+
+
+def test_panic_raises_runtime_error(tmp_path, backtest_args):
+    # analyze_backtest indexes the median of the daily returns, of which there are none in
+    # less than a day of candles
+    path = write_hlcvs(tmp_path / "hlcvs.bin", 100)
+    with pytest.raises(RuntimeError, match="internal error"):
+        pbr.run_backtest(path, (100, 1, 4), "<f8", *backtest_args)
+    # the module stays usable after the panic
+    assert pbr.round_(1.234, 0.01) == pytest.approx(1.23)
+
@@ -0,0 +1,109 @@
+import passivbot_rust as pbr
+import pytest
+
+np = pytest.importorskip("numpy")
+
+from test_backtest import sine_candles
+
+# 6 initial evaluations, then 4 children per generation as the 2 elites are kept
+GA_CONFIG = {"population_size": 6, "n_generations": 3, "n_elites": 2, "seed": 1}
+N_TOTAL = 6 + 3 * 4
+
+
+@pytest.fixture
+def optimize_args(config, exchange_params):
+    candles = sine_candles(2_000, 500)
+    backtest_params = {"starting_balance": 10000.0, "maker_fee": 0.0002}
+    return candles, exchange_params, backtest_params, config["optimize"]["bounds"]
+
+
+class Progress:
+    """Records the progress_callback calls, raising exception on call number raise_on."""
+
+    def __init__(self, exception=None, raise_on=None):
+        self.calls = []
+        self.exception = exception
+        self.raise_on = raise_on
+
+    def __call__(self, n_done, n_total, best_score):
+        self.calls.append((n_done, n_total))
+        assert isinstance(best_score, float)
+        if len(self.calls) == self.raise_on:
+            raise self.exception
+
+
+def test_population(optimize_args):
+    bounds = optimize_args[3]
+    result = pbr.run_optimize_py(*optimize_args, GA_CONFIG)
+    assert result["n_generations"] == 3
+    assert not result["cancelled"]
+    population = result["population"]
+    assert len(population) == 6
+    # best first
+    scores = [config["score"] for config in population]
+    assert scores == sorted(scores, reverse=True)
+    for config in population:
+        assert set(config["params"]) == set(bounds)
+        for key, value in config["params"].items():
+            low, high = bounds[key]
+            assert low <= value <= high
+        assert isinstance(config["metrics"], pbr.Metrics)
+    # seeded, so a second run gives the same population
+    rerun = pbr.run_optimize_py(*optimize_args, GA_CONFIG)["population"]
+    assert [(config["params"], config["score"]) for config in rerun] == [
+        (config["params"], config["score"]) for config in population
+    ]
+
+
+@pytest.mark.parametrize(
+    "progress_interval, expected",
+    [
+        (1, [6, 10, 14, 18]),
+        (5, [6, 14, 18]),
+        (100, [18]),
+    ],
+)
+def test_progress_callback(optimize_args, progress_interval, expected):
+    progress = Progress()
+    pbr.run_optimize_py(*optimize_args, GA_CONFIG, progress, progress_interval)
+    assert progress.calls == [(n_done, N_TOTAL) for n_done in expected]
+
+
+def test_keyboard_interrupt_cancels(optimize_args):
+    progress = Progress(KeyboardInterrupt, raise_on=2)
+    result = pbr.run_optimize_py(*optimize_args, GA_CONFIG, progress, 1)
+    # the second call comes after the first generation
+    assert len(progress.calls) == 2
+    assert result["cancelled"]
+    assert result["n_generations"] == 1
+    assert len(result["population"]) == 6
+
+
+def test_keyboard_interrupt_before_first_generation(optimize_args):
+    progress = Progress(KeyboardInterrupt, raise_on=1)
+    result = pbr.run_optimize_py(*optimize_args, GA_CONFIG, progress, 1)
+    assert result["cancelled"]
+    assert result["n_generations"] == 0
+    assert len(result["population"]) == 6
+
+
+def test_callback_exception_propagates(optimize_args):
+    progress = Progress(ValueError("stop"), raise_on=2)
+    with pytest.raises(ValueError, match="stop"):
+        pbr.run_optimize_py(*optimize_args, GA_CONFIG, progress, 1)
+    assert len(progress.calls) == 2
+
+
+def test_invalid_ga_config(optimize_args):
+    with pytest.raises(ValueError, match="unknown key ga_config.generations"):
+        pbr.run_optimize_py(*optimize_args, {"generations": 3})
+    with pytest.raises(ValueError, match="unknown key ga_config.scorer.adg"):
+        pbr.run_optimize_py(*optimize_args, {"scorer": {"adg": 1.0}})
+
+
+def test_invalid_bounds(optimize_args):
+    candles, exchange_params, backtest_params, bounds = optimize_args
+    bounds = {**bounds, "long_n_positions": [1.0]}
+    message = r"invalid bounds.long_n_positions: expected \[low, high\]"
+    with pytest.raises(ValueError, match=message):
+        pbr.run_optimize_py(candles, exchange_params, backtest_params, bounds, GA_CONFIG)
@@ -0,0 +1,136 @@
+import passivbot_rust as pbr
+import pytest
+
+
+@pytest.fixture
+def order():
+    return pbr.Order(0.01, 60000.0, pbr.OrderType.EntryGridNormalLong)
+
+
+@pytest.fixture
+def backtest_result(bot_params_pair, exchange_params):
+    pytest.importorskip("numpy")
+    from test_backtest import sine_candles
+
+    # same run as test_matches_rust_runner in test_backtest.py
+    long = {**bot_params_pair["long"], "n_positions": 10, "filter_rolling_window": 60}
+    long["wallet_exposure_limit"] = long["total_wallet_exposure_limit"] / 10
+    short = {**bot_params_pair["short"], "wallet_exposure_limit": 0.0}
+    return pbr.run_backtest_py(
+        sine_candles(20_000, 2_000),
+        {"long": long, "short": short},
+        {**exchange_params, "fee_rate": 0.0002},
+        {"starting_balance": 10000.0, "maker_fee": 0.0002},
+    )
+
+
+def test_order_attributes(order):
+    assert order.qty == 0.01
+    assert order.price == 60000.0
+    assert order.order_type == pbr.OrderType.EntryGridNormalLong
+
+
+def test_order_is_read_only(order):
+    with pytest.raises(AttributeError):
+        order.qty = 0.02
+    with pytest.raises(AttributeError):
+        order.order_type = pbr.OrderType.Empty
+
+
+def test_order_to_dict(order):
+    assert order.to_dict() == {
+        "qty": 0.01,
+        "price": 60000.0,
+        "order_type": "entry_grid_normal_long",
+    }
+
+
+def test_order_eq_and_repr(order):
+    assert order == pbr.Order(0.01, 60000.0, pbr.OrderType.EntryGridNormalLong)
+    assert order != pbr.Order(0.01, 60000.0, pbr.OrderType.EntryGridCroppedLong)
+    assert order != pbr.Order(0.02, 60000.0, pbr.OrderType.EntryGridNormalLong)
+    assert repr(order) == "Order(qty=0.01, price=60000.0, order_type=entry_grid_normal_long)"
+
+
+def test_calculators_return_orders(exchange_params, bot_params, state):
+    flat = {**state, "position_size": 0.0, "position_price": 0.0}
+    entry = pbr.calc_next_entry_long_dict_py(exchange_params, bot_params, flat)
+    assert isinstance(entry, pbr.Order)
+    assert entry.to_dict()["order_type"] == str(entry.order_type)
+
+
+def test_position_attributes_and_defaults():
+    position = pbr.Position(-0.5, 61000.0)
+    assert position.size == -0.5
+    assert position.price == 61000.0
+    assert pbr.Position() == pbr.Position(0.0, 0.0)
+    with pytest.raises(AttributeError):
+        position.size = 0.0
+
+
+def test_position_to_dict_and_repr():
+    position = pbr.Position(0.5, 61000.0)
+    assert position.to_dict() == {"size": 0.5, "price": 61000.0}
+    assert repr(position) == "Position(size=0.5, price=61000.0)"
+    assert position != pbr.Position(0.5, 61000.1)
+
+
+def test_position_project_fill(order):
+    filled = pbr.Position().project_fill(order, 0.001)
+    assert filled == pbr.Position(0.01, 60000.0)
+    # averaging in at a lower price
+    lower = pbr.Order(0.01, 58000.0, pbr.OrderType.EntryGridNormalLong)
+    assert filled.project_fill(lower, 0.001) == pbr.Position(0.02, 59000.0)
+
+
+def test_metrics_attributes(backtest_result):
+    metrics = backtest_result.metrics
+    assert isinstance(metrics, pbr.Metrics)
+    assert not metrics.pruned
+    assert metrics.fills_per_day > 0.0
+    assert 0.0 <= metrics.max_drawdown < 1.0
+    assert 0.0 <= metrics.time_stuck_fraction <= 1.0
+    with pytest.raises(AttributeError):
+        metrics.adg = 0.0
+
+
+def test_metrics_to_dict_and_eq(backtest_result):
+    metrics = backtest_result.metrics
+    assert metrics.to_dict() == {
+        "adg": metrics.adg,
+        "mdg": metrics.mdg,
+        "sharpe_ratio": metrics.sharpe_ratio,
+        "max_drawdown": metrics.max_drawdown,
+        "time_stuck_fraction": metrics.time_stuck_fraction,
+        "fills_per_day": metrics.fills_per_day,
+        "pruned": False,
+    }
+    assert metrics == backtest_result.metrics
+    assert repr(metrics).startswith("Metrics(adg=")
+    assert repr(metrics).endswith("pruned=False)")
+
+
+def test_backtest_result_attributes(backtest_result):
+    assert backtest_result.n_trades == len(backtest_result.fills) > 0
+    assert backtest_result.equities.shape == (20_000,)
+    assert backtest_result.fills["balance"][-1] == pytest.approx(backtest_result.final_balance)
+    with pytest.raises(AttributeError):
+        backtest_result.n_trades = 0
+    assert repr(backtest_result) == (
+        f"BacktestResult(n_fills={backtest_result.n_trades}, n_candles=20000, "
+        f"final_balance={backtest_result.final_balance!r}, n_trades={backtest_result.n_trades})"
+    )
+
+
+def test_backtest_result_to_dict(backtest_result):
+    result = backtest_result.to_dict()
+    assert set(result) == {"fills", "equities", "metrics"}
+    assert (result["equities"] == backtest_result.equities).all()
+    assert len(result["fills"]) == backtest_result.n_trades
+    metrics = backtest_result.metrics.to_dict()
+    del metrics["pruned"]
+    assert result["metrics"] == {
+        **metrics,
+        "final_balance": backtest_result.final_balance,
+        "n_trades": backtest_result.n_trades,
+    }
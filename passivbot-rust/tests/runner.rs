@@ -0,0 +1,357 @@
+use passivbot_rust::analysis::Metrics;
+use passivbot_rust::data::{validate_candles, Gap};
+use passivbot_rust::fixtures;
+use passivbot_rust::runner::{backtest, BacktestResult};
+use passivbot_rust::types::{
+    BacktestParams, BalanceMode, BotParamsPair, Candle, ExchangeParams, OrderType,
+};
+
+/// 1m candles oscillating +-5% around 60000 with a period of `period` candles.
+fn sine_candles(n_candles: usize, period: usize) -> Vec<Candle> {
+    (0..n_candles)
+        .map(|k| {
+            let phase = k as f64 / period as f64 * std::f64::consts::TAU;
+            let close = (60000.0 * (1.0 + 0.05 * phase.sin()) * 10.0).round() / 10.0;
+            Candle {
+                timestamp: k as u64 * 60_000,
+                high: close * 1.0005,
+                low: close * 0.9995,
+                close,
+                volume: 100.0,
+            }
+        })
+        .collect()
+}
+
+fn long_only() -> BotParamsPair {
+    let mut bot_params_pair = fixtures::bot_params_pair();
+    bot_params_pair.short.wallet_exposure_limit = 0.0;
+    bot_params_pair
+}
+
+#[test]
+fn sine_wave_smoke() {
+    let candles = sine_candles(20_000, 2_000);
+    let backtest_params = fixtures::backtest_params();
+    let result = backtest(
+        &candles,
+        &long_only(),
+        &fixtures::exchange_params(),
+        &backtest_params,
+    );
+    assert_eq!(result.equities.len(), candles.len());
+    assert!(result.n_trades > 0);
+    assert!(result.fills.iter().any(|fill| fill.fill_qty > 0.0));
+    assert!(result.fills.iter().any(|fill| fill.fill_qty < 0.0));
+    assert!(result.final_balance > backtest_params.starting_balance);
+    assert_eq!(result.final_balance, result.fills.last().unwrap().balance);
+    // same run as test_backtest.py in tests/python, through run_backtest_py
+    assert_eq!(result.n_trades, 31);
+    assert_eq!(result.final_balance, 10022.978574779987);
+}
+
+fn nan_out(candle: &mut Candle) {
+    candle.high = f64::NAN;
+    candle.low = f64::NAN;
+    candle.close = f64::NAN;
+}
+
+#[test]
+fn delisting_force_closes_at_last_valid_close() {
+    let mut candles = sine_candles(3_000, 2_000);
+    // delisted on the way down, while the long is still underwater
+    let last_valid = 1_199;
+    for candle in &mut candles[last_valid + 1..] {
+        nan_out(candle);
+    }
+    let result = backtest(
+        &candles,
+        &long_only(),
+        &fixtures::exchange_params(),
+        &fixtures::backtest_params(),
+    );
+    let last_fill = result.fills.last().unwrap();
+    assert_eq!(last_fill.order_type, OrderType::CloseUnstuckLong);
+    assert_eq!(last_fill.index, last_valid + 1);
+    assert_eq!(last_fill.fill_price, candles[last_valid].close);
+    assert_eq!(last_fill.position_size, 0.0);
+    assert_eq!(result.final_balance, last_fill.balance);
+    assert!(result.equities[last_valid + 1..]
+        .iter()
+        .all(|&equity| equity == result.final_balance));
+}
+
+#[test]
+fn three_hour_gap() {
+    let mut candles = sine_candles(3_000, 2_000);
+    for candle in &mut candles[1_000..1_180] {
+        nan_out(candle);
+    }
+    let stats = validate_candles(&candles).unwrap();
+    assert_eq!(
+        stats.gaps,
+        vec![Gap {
+            start_index: 1_000,
+            n_missing: 180
+        }]
+    );
+    assert_eq!(stats.trailing_nans, 0);
+
+    let result = backtest(
+        &candles,
+        &long_only(),
+        &fixtures::exchange_params(),
+        &fixtures::backtest_params(),
+    );
+    // market closed: no fills and equity frozen at the last close before the gap
+    assert!(!result
+        .fills
+        .iter()
+        .any(|fill| (1_000..1_180).contains(&fill.index)));
+    assert!(result.equities[1_000..1_180]
+        .iter()
+        .all(|&equity| equity == result.equities[999]));
+    assert!(result.fills.iter().any(|fill| fill.index >= 1_180));
+    assert!(!result
+        .fills
+        .iter()
+        .any(|fill| fill.order_type == OrderType::CloseUnstuckLong));
+
+    // same gap as a jump in timestamps
+    candles.drain(1_000..1_180);
+    let stats = validate_candles(&candles).unwrap();
+    assert_eq!(stats.interval_ms, 60_000);
+    assert_eq!(
+        stats.gaps,
+        vec![Gap {
+            start_index: 1_000,
+            n_missing: 180
+        }]
+    );
+}
+
+/// (index within period, qty) of the fills in each sine period.
+fn fills_by_period(result: &BacktestResult, period: usize) -> Vec<Vec<(usize, f64)>> {
+    let mut by_period = vec![Vec::new(); result.equities.len().div_ceil(period)];
+    for fill in &result.fills {
+        by_period[fill.index / period].push((fill.index % period, fill.fill_qty));
+    }
+    by_period
+}
+
+#[test]
+fn balance_mode_sizing() {
+    let period = 2_000;
+    let candles = sine_candles(21 * period, period);
+    let mut bot_params_pair = long_only();
+    bot_params_pair.long.wallet_exposure_limit = 1.0;
+    bot_params_pair.long.entry_initial_qty_pct = 0.1;
+    // fine enough for compounding to show in quantities
+    let exchange_params = ExchangeParams {
+        qty_step: 0.00001,
+        min_qty: 0.00001,
+        ..fixtures::exchange_params()
+    };
+    let run = |balance_mode| {
+        let backtest_params = BacktestParams {
+            balance_mode,
+            ..fixtures::backtest_params()
+        };
+        backtest(
+            &candles,
+            &bot_params_pair,
+            &exchange_params,
+            &backtest_params,
+        )
+    };
+    let compounding = run(BalanceMode::Compounding);
+    let fixed = run(BalanceMode::Fixed);
+    let capped = run(BalanceMode::CappedAt(10000.0));
+
+    // once past the first cycles the fills repeat every other period
+    let fixed_periods = fills_by_period(&fixed, period);
+    assert!(!fixed_periods[2].is_empty());
+    assert!((4..fixed_periods.len()).all(|k| fixed_periods[k] == fixed_periods[k - 2]));
+    let compounding_periods = fills_by_period(&compounding, period);
+    let first_entry = |fills: &[(usize, f64)]| fills.iter().find(|fill| fill.1 > 0.0).unwrap().1;
+    assert!(first_entry(&compounding_periods[20]) > first_entry(&compounding_periods[2]));
+
+    // balance above the cap sizes like fixed
+    assert_eq!(capped.fills, fixed.fills);
+
+    let adg_compounding = Metrics::from_result(&compounding).adg;
+    let adg_fixed = Metrics::from_result(&fixed).adg;
+    assert!(adg_compounding > adg_fixed);
+    assert!(fixed.final_balance > fixed.backtest_params.starting_balance);
+}
+
+fn flat_candle(k: usize, close: f64) -> Candle {
+    Candle {
+        timestamp: k as u64 * 60_000,
+        high: close * 1.0001,
+        low: close * 0.9999,
+        close,
+        volume: 100.0,
+    }
+}
+
+#[test]
+fn latency_misses_fast_wick() {
+    // first orders are computed at candle 0; candle 1 wicks through the initial entry 1% below
+    // the EMAs and recovers
+    let mut candles: Vec<Candle> = (0..100).map(|k| flat_candle(k, 60000.0)).collect();
+    candles[1].low = 59000.0;
+    let mut bot_params_pair = long_only();
+    bot_params_pair.long.entry_initial_ema_dist = 0.01;
+    let run = |latency_candles| {
+        let backtest_params = BacktestParams {
+            latency_candles,
+            ..fixtures::backtest_params()
+        };
+        backtest(
+            &candles,
+            &bot_params_pair,
+            &fixtures::exchange_params(),
+            &backtest_params,
+        )
+    };
+    let no_latency = run(0);
+    assert_eq!(no_latency.fills.len(), 1);
+    assert_eq!(no_latency.fills[0].index, 1);
+    assert_eq!(
+        no_latency.fills[0].order_type,
+        OrderType::EntryInitialNormalLong
+    );
+    assert!(run(1).fills.is_empty());
+}
+
+/// Flat at 60000, then on candles with `volume` through the initial entry at 59400 and later
+/// through the first grid re-entry.
+fn thin_drop_candles(volume: f64) -> Vec<Candle> {
+    let mut candles: Vec<Candle> = (0..10).map(|k| flat_candle(k, 60000.0)).collect();
+    for k in 10..120 {
+        let mut candle = flat_candle(k, if k < 60 { 59300.0 } else { 56000.0 });
+        candle.volume = volume;
+        candles.push(candle);
+    }
+    candles
+}
+
+fn volume_capped_run(candles: &[Candle]) -> BacktestResult {
+    let mut bot_params_pair = long_only();
+    bot_params_pair.long.entry_initial_ema_dist = 0.01;
+    bot_params_pair.long.wallet_exposure_limit = 1.0;
+    bot_params_pair.long.entry_initial_qty_pct = 0.1;
+    let backtest_params = BacktestParams {
+        max_fill_volume_fraction: 0.1,
+        ..fixtures::backtest_params()
+    };
+    backtest(
+        candles,
+        &bot_params_pair,
+        &fixtures::exchange_params(),
+        &backtest_params,
+    )
+}
+
+#[test]
+fn volume_capped_fills_spread_over_candles() {
+    // 10% of 0.01 per candle against an initial entry of 0.017
+    let result = volume_capped_run(&thin_drop_candles(0.01));
+    let initial: Vec<_> = result
+        .fills
+        .iter()
+        .filter(|fill| fill.order_type == OrderType::EntryInitialNormalLong)
+        .collect();
+    assert_eq!(initial.len(), 17);
+    assert!(initial
+        .iter()
+        .enumerate()
+        .all(|(i, fill)| fill.index == 10 + i
+            && fill.fill_qty == 0.001
+            && fill.fill_price == 59400.0));
+    let grid: Vec<_> = result
+        .fills
+        .iter()
+        .filter(|fill| fill.order_type == OrderType::EntryGridNormalLong)
+        .collect();
+    assert!(grid.len() > 1);
+    assert!(grid.iter().all(|fill| fill.fill_qty == 0.001));
+
+    // position price is the volume weighted blend of every partial fill
+    let last_fill = result.fills.last().unwrap();
+    let size: f64 = result.fills.iter().map(|fill| fill.fill_qty).sum();
+    let cost: f64 = result
+        .fills
+        .iter()
+        .map(|fill| fill.fill_qty * fill.fill_price)
+        .sum();
+    assert!((last_fill.position_size - size).abs() < 1e-9);
+    assert!((last_fill.position_price - cost / size).abs() < 1e-6);
+    assert!(last_fill.position_price < 59400.0 && last_fill.position_price > grid[0].fill_price);
+}
+
+#[test]
+fn volume_capped_closes_follow_partial_fills() {
+    // wicks up to the close grid while the initial entry is still being filled
+    let mut candles = thin_drop_candles(0.01);
+    for candle in &mut candles[10..60] {
+        candle.high = 61000.0;
+    }
+    let result = volume_capped_run(&candles);
+    let first_close = result
+        .fills
+        .iter()
+        .find(|fill| fill.order_type == OrderType::CloseGridLong)
+        .unwrap();
+    assert!(result.fills.iter().any(|fill| {
+        fill.order_type == OrderType::EntryInitialNormalLong && fill.index > first_close.index
+    }));
+}
+
+#[test]
+fn volume_capped_without_volume_data() {
+    // zero volume is missing data, not an illiquid market
+    let result = volume_capped_run(&thin_drop_candles(0.0));
+    assert_eq!(result.fills[0].index, 10);
+    assert_eq!(result.fills[0].fill_qty, 0.017);
+    assert_eq!(
+        result.fills[0].order_type,
+        OrderType::EntryInitialNormalLong
+    );
+}
+
+#[test]
+fn no_entries_in_blackout_window() {
+    // candles dated from a real epoch ms, so the window only matches candle timestamps
+    let base_ms = 1_700_000_000_000;
+    let candles: Vec<Candle> = sine_candles(20_000, 2_000)
+        .into_iter()
+        .map(|candle| Candle {
+            timestamp: base_ms + candle.timestamp,
+            ..candle
+        })
+        .collect();
+    let window = (base_ms + 6_000 * 60_000, base_ms + 12_000 * 60_000);
+    let entry_indices = |bot_params_pair: &BotParamsPair| -> Vec<usize> {
+        backtest(
+            &candles,
+            bot_params_pair,
+            &fixtures::exchange_params(),
+            &fixtures::backtest_params(),
+        )
+        .fills
+        .iter()
+        .filter(|fill| fill.order_type.is_entry())
+        .map(|fill| fill.index)
+        .collect()
+    };
+    // orders computed at candle k fill from candle k + 1 on
+    let in_window = |k: &usize| (6_001..=12_000).contains(k);
+    assert!(entry_indices(&long_only()).iter().any(in_window));
+    let mut bot_params_pair = long_only();
+    bot_params_pair.long.entry_blackout_windows = vec![window];
+    let blacked_out = entry_indices(&bot_params_pair);
+    assert!(!blacked_out.iter().any(in_window));
+    assert!(blacked_out.iter().any(|&k| k > 12_000));
+}
@@ -0,0 +1,84 @@
+//! Exact outputs of the long entry calculators over fixtures::long_entry_matrix, checked against
+//! tests/snapshots/entries_long.txt. Refactors must leave them unchanged.
+//! After an intended behavior change, regenerate with UPDATE_SNAPSHOTS=1 cargo test --test snapshots
+
+use passivbot_rust::entries::{calc_grid_entry_long, calc_next_entry_long};
+use passivbot_rust::fixtures;
+use passivbot_rust::types::{BotParams, Order, TrailingPriceBundle};
+use std::fs;
+
+const SNAPSHOT_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/snapshots/entries_long.txt"
+);
+
+fn format_order(case: usize, calculator: &str, order: &Order) -> String {
+    format!(
+        "{} {} {:?} {:?} {:?}",
+        case, calculator, order.qty, order.price, order.order_type
+    )
+}
+
+/// One line per (case, calculator): bot params without and with trailing entries, each with a
+/// fresh and a moved trailing price bundle.
+fn snapshot_lines() -> Vec<String> {
+    let exchange_params = fixtures::exchange_params();
+    let grid_only = BotParams {
+        entry_trailing_grid_ratio: 0.0,
+        ..fixtures::bot_params()
+    };
+    let bot_params_variants = [("grid", grid_only), ("blended", fixtures::bot_params())];
+    let mut lines = Vec::new();
+    for (case, (state_params, position)) in fixtures::long_entry_matrix().iter().enumerate() {
+        let price = state_params.order_book.bid;
+        let moved = || TrailingPriceBundle {
+            min_since_open: price * 0.94,
+            max_since_min: price * 0.95,
+            max_since_open: price * 1.01,
+            min_since_max: price * 0.94,
+        };
+        for (name, bot_params) in &bot_params_variants {
+            lines.push(format_order(
+                case,
+                &format!("grid_entry_{}", name),
+                &calc_grid_entry_long(&exchange_params, state_params, bot_params, position),
+            ));
+            for (bundle_name, trailing_price_bundle) in [
+                ("fresh", TrailingPriceBundle::default()),
+                ("moved", moved()),
+            ] {
+                lines.push(format_order(
+                    case,
+                    &format!("next_entry_{}_{}", name, bundle_name),
+                    &calc_next_entry_long(
+                        &exchange_params,
+                        state_params,
+                        bot_params,
+                        position,
+                        &trailing_price_bundle,
+                    ),
+                ));
+            }
+        }
+    }
+    lines
+}
+
+#[test]
+fn long_entries_match_snapshot() {
+    let actual = snapshot_lines();
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(SNAPSHOT_PATH, actual.join("\n") + "\n").unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(SNAPSHOT_PATH).unwrap();
+    let expected: Vec<&str> = expected.lines().collect();
+    assert_eq!(actual.len(), expected.len(), "number of snapshot cases");
+    let mismatches: Vec<String> = actual
+        .iter()
+        .zip(&expected)
+        .filter(|(actual, expected)| actual != expected)
+        .map(|(actual, expected)| format!("expected {}\n     got {}", expected, actual))
+        .collect();
+    assert!(mismatches.is_empty(), "\n{}", mismatches.join("\n"));
+}
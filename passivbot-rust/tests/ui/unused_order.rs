@@ -0,0 +1,18 @@
+#![deny(unused_must_use)]
+
+use passivbot_rust::entries::calc_next_entry_long;
+use passivbot_rust::fixtures;
+use passivbot_rust::types::{Order, OrderType, Position, TrailingPriceBundle};
+
+fn main() {
+    calc_next_entry_long(
+        &fixtures::exchange_params(),
+        &fixtures::state_params(60000.0),
+        &fixtures::bot_params(),
+        &Position::default(),
+        &TrailingPriceBundle::default(),
+    );
+    let order = Order::default();
+    order.with_type(OrderType::EntryGridNormalLong);
+    order.is_empty();
+}
@@ -154,6 +154,7 @@ def load_symbols_hlcvs_from_cache(config):
     cache_dir = Path("caches") / "hlcvs_data" / cache_hash[:16]
     if os.path.exists(cache_dir):
         symbols = json.load(open(cache_dir / "symbols.json"))
+        first_timestamp_ms = json.load(open(cache_dir / "first_timestamp_ms.json"))
         if config["backtest"]["compress_cache"]:
             fname = cache_dir / "hlcvs.npy.gz"
             logging.info(f"Attempting to load hlcvs data from cache {fname}...")
@@ -163,13 +164,15 @@ def load_symbols_hlcvs_from_cache(config):
             fname = cache_dir / "hlcvs.npy"
             logging.info(f"Attempting to load hlcvs data from cache {fname}...")
             hlcvs = np.load(fname)
-        return cache_dir, symbols, hlcvs
+        return cache_dir, symbols, first_timestamp_ms, hlcvs
 
 
-def save_symbols_hlcvs_to_cache(config, symbols, hlcvs):
+def save_symbols_hlcvs_to_cache(config, symbols, first_timestamp_ms, hlcvs):
     cache_hash = get_cache_hash(config)
     cache_dir = Path("caches") / "hlcvs_data" / cache_hash[:16]
     cache_dir.mkdir(parents=True, exist_ok=True)
+    # written before the early return so caches predating it get one too
+    json.dump(first_timestamp_ms, open(cache_dir / "first_timestamp_ms.json", "w"))
     if all([os.path.exists(cache_dir / x) for x in ["symbols.json", "hlcvs.npy"]]):
         return
     logging.info(f"Dumping cache...")
@@ -216,10 +219,10 @@ async def prepare_hlcvs_mss(config):
         result = load_symbols_hlcvs_from_cache(config)
         if result:
             logging.info(f"Seconds to load cache: {(utc_ms() - sts) / 1000:.4f}")
-            cache_dir, symbols, hlcvs = result
+            cache_dir, symbols, first_timestamp_ms, hlcvs = result
             mss = json.load(open(mss_path))
             logging.info(f"Successfully loaded hlcvs data from cache")
-            return symbols, hlcvs, mss, results_path, cache_dir
+            return symbols, first_timestamp_ms, hlcvs, mss, results_path, cache_dir
     except:
         logging.info(f"Unable to load hlcvs data from cache. Fetching...")
     try:
@@ -235,13 +238,14 @@ async def prepare_hlcvs_mss(config):
 
     symbols, timestamps, hlcvs = await prepare_hlcvs(config)
     logging.info(f"Finished preparing hlcvs data. Shape: {hlcvs.shape}")
+    first_timestamp_ms = int(timestamps[0])
     try:
-        cache_dir = save_symbols_hlcvs_to_cache(config, symbols, hlcvs)
+        cache_dir = save_symbols_hlcvs_to_cache(config, symbols, first_timestamp_ms, hlcvs)
     except Exception as e:
         logging.error(f"failed to save hlcvs to cache {e}")
         traceback.print_exc()
         cache_dir = ""
-    return symbols, hlcvs, mss, results_path, cache_dir
+    return symbols, first_timestamp_ms, hlcvs, mss, results_path, cache_dir
 
 
 def prep_backtest_args(config, mss, exchange_params=None, backtest_params=None):
@@ -263,6 +267,9 @@ def prep_backtest_args(config, mss, exchange_params=None, backtest_params=None):
             "starting_balance": config["backtest"]["starting_balance"],
             "maker_fee": mss[symbols[0]]["maker"],
             "symbols": symbols,
+            "balance_mode": config["backtest"].get("balance_mode", "compounding"),
+            "balance_cap": config["backtest"].get("balance_cap", 0.0),
+            "first_timestamp_ms": config["backtest"]["first_timestamp_ms"],
         }
     return bot_params, exchange_params, backtest_params
 
@@ -363,8 +370,11 @@ async def main():
     update_config_with_args(config, args)
     config = format_config(config)
     config["disable_plotting"] = args.disable_plotting
-    symbols, hlcvs, mss, results_path, cache_dir = await prepare_hlcvs_mss(config)
+    symbols, first_timestamp_ms, hlcvs, mss, results_path, cache_dir = await prepare_hlcvs_mss(
+        config
+    )
     config["backtest"]["symbols"] = symbols
+    config["backtest"]["first_timestamp_ms"] = first_timestamp_ms
     config["backtest"]["cache_dir"] = str(cache_dir)
     fills, equities, analysis = run_backtest(hlcvs, mss, config)
     post_process(config, hlcvs, fills, equities, analysis, results_path)